@@ -1,3 +1,7 @@
+use std::env;
+use std::fs;
+use std::path::Path;
+
 fn main() {
     // forward git repo hashes we build at
     println!(
@@ -8,4 +12,35 @@ fn main() {
         "cargo:rustc-env=COMMIT_DATE={}",
         ckb_build_info::get_commit_date().unwrap_or_default()
     );
+
+    // blake2b of the Cargo.lock we build with (empty when it is missing), for
+    // `util attest-binary`. Cargo.lock is ignored by git, so it is watched
+    // explicitly, which also requires watching the sources of the crate.
+    let cargo_lock = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("Cargo.lock");
+    let cargo_lock_blake2b = fs::read(&cargo_lock)
+        .map(|content| {
+            format!(
+                "0x{}",
+                faster_hex::hex_string(&ckb_hash::blake2b_256(&content)).unwrap()
+            )
+        })
+        .unwrap_or_default();
+    println!("cargo:rustc-env=CARGO_LOCK_BLAKE2B={}", cargo_lock_blake2b);
+    for path in &["Cargo.lock", "Cargo.toml", "build.rs", "src"] {
+        println!("cargo:rerun-if-changed={}", path);
+    }
+    // COMMIT_DESCRIBE changes with the checked out commit: HEAD and the branch
+    // it points to (a missing path would rerun the script on every build)
+    let mut git_paths = vec![".git/HEAD".to_string()];
+    if let Ok(head) = fs::read_to_string(".git/HEAD") {
+        if head.starts_with("ref: ") {
+            git_paths.push(format!(".git/{}", head[5..].trim()));
+        }
+        git_paths.push(".git/packed-refs".to_string());
+    }
+    for path in git_paths {
+        if Path::new(&path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
 }
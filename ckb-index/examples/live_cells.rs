@@ -0,0 +1,52 @@
+//! List the live cells of a lock hash from the index database built by
+//! `ckb-cli` (`~/.ckb-cli/index-v2`), the node gives the genesis block and the
+//! network:
+//!
+//! ```text
+//! cargo run -p ckb-index --example live_cells -- <lock-hash> ~/.ckb-cli/index-v2 http://127.0.0.1:8114
+//! ```
+
+use std::env;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use ckb_index::{with_index_db, IndexDatabase, LiveCellQuery};
+use ckb_sdk::{GenesisInfo, HttpRpcClient, NetworkType};
+use ckb_types::{core::BlockView, prelude::*, H256};
+
+fn main() -> Result<(), String> {
+    let mut args = env::args().skip(1);
+    let usage = "usage: live_cells <lock-hash> <index-dir> [url]";
+    let lock_hash = args.next().ok_or(usage)?;
+    let lock_hash =
+        H256::from_str(lock_hash.trim_start_matches("0x")).map_err(|err| err.to_string())?;
+    let index_dir = args.next().map(PathBuf::from).ok_or(usage)?;
+    let url = args
+        .next()
+        .unwrap_or_else(|| "http://127.0.0.1:8114".to_string());
+
+    let mut rpc_client = HttpRpcClient::new(url);
+    let chain = rpc_client.get_blockchain_info()?.chain;
+    let network = NetworkType::from_raw_str(&chain)
+        .ok_or_else(|| format!("Unexpected network type: {}", chain))?;
+    let genesis_block: BlockView = rpc_client
+        .get_block_by_number(0)?
+        .ok_or("Can not get genesis block")?
+        .into();
+    let genesis_info = GenesisInfo::from_block(&genesis_block)?;
+    let genesis_hash: H256 = genesis_block.hash().unpack();
+
+    // The database is opened exclusively, stop ckb-cli or keep it short
+    let cells = with_index_db(&index_dir, genesis_hash, |backend, cf| {
+        let db = IndexDatabase::from_db(backend, cf, network, genesis_info, false)?;
+        Ok(db.query_live_cells(LiveCellQuery::Lock(lock_hash.pack()), None, 0, 20))
+    })
+    .map_err(|err| err.to_string())?;
+    for cell in cells {
+        println!(
+            "{:#x}:{} {} shannons (block #{})",
+            cell.tx_hash, cell.tx_index, cell.capacity, cell.number
+        );
+    }
+    Ok(())
+}
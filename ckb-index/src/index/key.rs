@@ -18,6 +18,9 @@ pub enum KeyType {
     LastHeader = 2,
     // key => value: {type} => u128
     TotalCapacity = 3,
+    // >> The transactions of each lock are recorded without a gap since this block
+    // key => value: {type} => {block-number}
+    TxHistoryFrom = 4,
 
     // >> hash-type: block, transaction, lock, data
     // key => value: {type}:{hash} => {hash-type}
@@ -67,6 +70,7 @@ impl KeyType {
             1 => KeyType::Network,
             2 => KeyType::LastHeader,
             3 => KeyType::TotalCapacity,
+            4 => KeyType::TxHistoryFrom,
 
             100 => KeyType::GlobalHash,
             101 => KeyType::TxMap,
@@ -96,6 +100,7 @@ pub enum Key {
     Network,
     LastHeader,
     TotalCapacity,
+    TxHistoryFrom,
 
     GlobalHash(H256),
     TxMap(H256),
@@ -125,6 +130,7 @@ impl Key {
             Key::Network => KeyType::Network.to_bytes(),
             Key::LastHeader => KeyType::LastHeader.to_bytes(),
             Key::TotalCapacity => KeyType::TotalCapacity.to_bytes(),
+            Key::TxHistoryFrom => KeyType::TxHistoryFrom.to_bytes(),
             Key::GlobalHash(hash) => {
                 let mut bytes = KeyType::GlobalHash.to_bytes();
                 bytes.extend(hash.as_bytes().to_vec());
@@ -245,6 +251,7 @@ impl Key {
             KeyType::Network => Key::Network,
             KeyType::LastHeader => Key::LastHeader,
             KeyType::TotalCapacity => Key::TotalCapacity,
+            KeyType::TxHistoryFrom => Key::TxHistoryFrom,
             KeyType::GlobalHash => {
                 let hash = H256::from_slice(args_bytes).unwrap();
                 Key::GlobalHash(hash)
@@ -349,6 +356,7 @@ impl Key {
             Key::Network => KeyType::Network,
             Key::LastHeader => KeyType::LastHeader,
             Key::TotalCapacity => KeyType::TotalCapacity,
+            Key::TxHistoryFrom => KeyType::TxHistoryFrom,
             Key::GlobalHash(..) => KeyType::GlobalHash,
             Key::TxMap(..) => KeyType::TxMap,
             Key::RecentHeader(..) => KeyType::RecentHeader,
@@ -386,6 +394,9 @@ impl Key {
     pub(crate) fn pair_total_capacity(value: &u128) -> (Vec<u8>, Vec<u8>) {
         (Key::TotalCapacity.to_bytes(), value.to_le_bytes().to_vec())
     }
+    pub(crate) fn pair_tx_history_from(number: u64) -> (Vec<u8>, Vec<u8>) {
+        (Key::TxHistoryFrom.to_bytes(), number.to_le_bytes().to_vec())
+    }
 
     pub(crate) fn pair_global_hash(hash: H256, value: HashType) -> (Vec<u8>, Vec<u8>) {
         (Key::GlobalHash(hash).to_bytes(), vec![value as u8])
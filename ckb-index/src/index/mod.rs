@@ -2,6 +2,7 @@ mod key;
 mod types;
 
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::io;
 
@@ -10,6 +11,7 @@ use ckb_types::{
     core::{BlockView, HeaderView},
     packed::{Byte32, Header, OutPoint, Script},
     prelude::*,
+    H256,
 };
 use rocksdb::{ColumnFamily, DB};
 
@@ -110,24 +112,7 @@ impl<'a> IndexDatabase<'a> {
                 }
 
                 log::warn!("Rollback because of block: {:#x}", block_hash);
-                self.init_block_buf.clear();
-                // Reload last header
-                let last_block_delta: BlockDeltaInfo = {
-                    let reader = RocksReader::new(self.db, self.cf);
-                    let last_header: HeaderView = reader
-                        .get(&Key::LastHeader.to_bytes())
-                        .map(|bytes| Header::new_unchecked(bytes.into()).into_view())
-                        .unwrap();
-                    reader
-                        .get(&Key::BlockDelta(last_header.number()).to_bytes())
-                        .map(|bytes| bincode::deserialize(&bytes).unwrap())
-                        .unwrap()
-                };
-                let mut txn = RocksTxn::new(self.db, self.cf);
-                last_block_delta.rollback(&mut txn);
-                txn.commit();
-                self.last_header = last_block_delta.parent_header();
-                return Ok(());
+                return self.rollback_last_block();
             }
             if number > self.tip_header.number() {
                 return Err(IndexError::BlockImmature(number));
@@ -154,6 +139,35 @@ impl<'a> IndexDatabase<'a> {
         self.tip_header = header
     }
 
+    /// Roll back the last persisted block (e.g. it is not in the main chain
+    /// any more), the blocks buffered during initial sync are dropped. Only
+    /// the recent blocks can be rolled back.
+    pub fn rollback_last_block(&mut self) -> Result<(), IndexError> {
+        self.init_block_buf.clear();
+        let reader = RocksReader::new(self.db, self.cf);
+        let last_header: HeaderView = reader
+            .get(&Key::LastHeader.to_bytes())
+            .map(|bytes| Header::new_unchecked(bytes.into()).into_view())
+            .ok_or(IndexError::NotInit)?;
+        let last_block_delta: BlockDeltaInfo = reader
+            .get(&Key::BlockDelta(last_header.number()).to_bytes())
+            .ok_or_else(|| {
+                IndexError::Inconsistent(format!(
+                    "block {} is too old to roll back",
+                    last_header.number()
+                ))
+            })
+            .and_then(|bytes| {
+                bincode::deserialize(&bytes)
+                    .map_err(|err| IndexError::Inconsistent(err.to_string()))
+            })?;
+        let mut txn = RocksTxn::new(self.db, self.cf);
+        last_block_delta.rollback(&mut txn);
+        txn.commit();
+        self.last_header = last_block_delta.parent_header();
+        Ok(())
+    }
+
     pub fn last_header(&self) -> Option<&HeaderView> {
         self.last_header.as_ref()
     }
@@ -169,7 +183,7 @@ impl<'a> IndexDatabase<'a> {
     fn get_address_inner(&self, reader: &RocksReader, lock_hash: Byte32) -> Option<AddressPayload> {
         reader
             .get(&Key::LockScript(lock_hash.unpack()).to_bytes())
-            .map(|bytes| AddressPayload::from(Script::new_unchecked(bytes.into())))
+            .and_then(|bytes| AddressPayload::try_from(Script::new_unchecked(bytes.into())).ok())
     }
 
     pub fn get_capacity(&self, lock_hash: Byte32) -> Option<u64> {
@@ -183,6 +197,18 @@ impl<'a> IndexDatabase<'a> {
             })
     }
 
+    /// The block number from which the transactions of each lock are recorded
+    /// (by `enable_explorer`) without a gap, `Some(0)` for the whole history,
+    /// `None` when the last block is applied without them.
+    pub fn tx_history_from(&self) -> Option<u64> {
+        let reader = RocksReader::new(self.db, self.cf);
+        reader.get(&Key::TxHistoryFrom.to_bytes()).map(|bytes| {
+            let mut data = [0u8; 8];
+            data.copy_from_slice(&bytes[..8]);
+            u64::from_le_bytes(data)
+        })
+    }
+
     pub fn get_lock_script_by_hash(&self, lock_hash: Byte32) -> Option<Script> {
         let reader = RocksReader::new(self.db, self.cf);
         reader
@@ -223,6 +249,93 @@ impl<'a> IndexDatabase<'a> {
         self.get_live_cell_infos(key_prefix, key_start, terminator)
     }
 
+    /// Query live cells with pagination, `skip` cells are skipped and at most
+    /// `limit` cells are returned.
+    pub fn query_live_cells(
+        &self,
+        query: LiveCellQuery,
+        from_number: Option<u64>,
+        skip: usize,
+        limit: usize,
+    ) -> Vec<LiveCellInfo> {
+        if limit == 0 {
+            return Vec::new();
+        }
+        let terminator = |idx: usize, _info: &LiveCellInfo| (idx + 1 >= skip + limit, idx >= skip);
+        match query {
+            LiveCellQuery::Lock(hash) => self.get_live_cells_by_lock(hash, from_number, terminator),
+            LiveCellQuery::Type(hash) => self.get_live_cells_by_type(hash, from_number, terminator),
+            LiveCellQuery::Code(hash) => self.get_live_cells_by_code(hash, from_number, terminator),
+        }
+    }
+
+    /// Get transactions related to a lock script (ordered by block number),
+    /// only available when the database is opened with `enable_explorer`.
+    pub fn get_txs_by_lock(
+        &self,
+        lock_hash: Byte32,
+        from_number: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<TxInfo>, IndexError> {
+        let reader = RocksReader::new(self.db, self.cf);
+        let mut key_prefix = KeyType::LockTx.to_bytes();
+        key_prefix.extend(lock_hash.as_slice().to_vec());
+        let mut key_start = key_prefix.clone();
+        key_start.extend(from_number.unwrap_or(0).to_be_bytes().to_vec());
+
+        let mut infos = Vec::new();
+        for (key_bytes, value_bytes) in reader.iter_from(&key_start) {
+            if infos.len() >= limit || key_bytes[..key_prefix.len()] != key_prefix[..] {
+                break;
+            }
+            if let Some(info) = get_tx_info(&reader, &value_bytes)? {
+                infos.push(info);
+            }
+        }
+        Ok(infos)
+    }
+
+    /// Get the newest transactions related to a lock script in blocks before
+    /// `to_number` (newest first), only available when the database is opened
+    /// with `enable_explorer`. The transactions of the last block are never
+    /// cut by `limit`, so the next page is before the last block number.
+    pub fn get_recent_txs_by_lock(
+        &self,
+        lock_hash: Byte32,
+        to_number: Option<u64>,
+        limit: usize,
+    ) -> Result<Vec<TxInfo>, IndexError> {
+        let reader = RocksReader::new(self.db, self.cf);
+        let mut key_prefix = KeyType::LockTx.to_bytes();
+        key_prefix.extend(lock_hash.as_slice().to_vec());
+        let mut key_start = key_prefix.clone();
+        match to_number {
+            Some(0) => return Ok(Vec::new()),
+            Some(number) => {
+                key_start.extend((number - 1).to_be_bytes().to_vec());
+                key_start.extend(u32::max_value().to_be_bytes().to_vec());
+            }
+            None => key_start.extend(vec![0xff; 12]),
+        }
+
+        let mut infos: Vec<TxInfo> = Vec::new();
+        for (key_bytes, value_bytes) in reader.iter_rev_from(&key_start) {
+            if !key_bytes.starts_with(&key_prefix) {
+                break;
+            }
+            let mut number_bytes = [0u8; 8];
+            number_bytes.copy_from_slice(&key_bytes[key_prefix.len()..key_prefix.len() + 8]);
+            let number = u64::from_be_bytes(number_bytes);
+            if infos.len() >= limit && infos.last().map(|info| info.block_number) != Some(number) {
+                break;
+            }
+            if let Some(info) = get_tx_info(&reader, &value_bytes)? {
+                infos.push(info);
+            }
+        }
+        Ok(infos)
+    }
+
     pub fn get_live_cell_infos<F: FnMut(usize, &LiveCellInfo) -> (bool, bool)>(
         &self,
         key_prefix: Key,
@@ -301,7 +414,19 @@ impl<'a> IndexDatabase<'a> {
             blocks.push(block);
             blocks
         };
+        self.apply_blocks(blocks);
+    }
 
+    /// Write the blocks buffered during initial sync into database, after this
+    /// `last_header()` is persisted.
+    pub fn flush(&mut self) {
+        if !self.init_block_buf.is_empty() {
+            let blocks = self.init_block_buf.split_off(0);
+            self.apply_blocks(blocks);
+        }
+    }
+
+    fn apply_blocks(&mut self, blocks: Vec<BlockView>) {
         let mut txn = RocksTxn::new(self.db, self.cf);
         let blocks_len = blocks.len();
         for (idx, block) in blocks.into_iter().enumerate() {
@@ -324,6 +449,67 @@ impl<'a> IndexDatabase<'a> {
         txn.commit();
     }
 
+    /// Check the live cells, the total capacity and the capacity of each lock
+    /// agree with each other (used after rebuild the database).
+    pub fn verify_consistency(&self) -> Result<(), IndexError> {
+        let reader = RocksReader::new(self.db, self.cf);
+        let chain_capacity = reader
+            .get(&Key::TotalCapacity.to_bytes())
+            .map(|bytes| {
+                let mut data = [0u8; 16];
+                data.copy_from_slice(&bytes[..16]);
+                u128::from_le_bytes(data)
+            })
+            .unwrap_or(0);
+
+        let mut live_cells = 0;
+        let mut live_cells_capacity: u128 = 0;
+        let key_prefix = KeyType::LiveCellMap.to_bytes();
+        for (key_bytes, value_bytes) in reader.iter_from(&key_prefix) {
+            if key_bytes[..key_prefix.len()] != key_prefix[..] {
+                break;
+            }
+            let info: LiveCellInfo = bincode::deserialize(&value_bytes)
+                .map_err(|err| IndexError::Inconsistent(err.to_string()))?;
+            live_cells += 1;
+            live_cells_capacity += u128::from(info.capacity);
+        }
+
+        let mut live_cell_indexes = 0;
+        let key_prefix = KeyType::LiveCellIndex.to_bytes();
+        for (key_bytes, _) in reader.iter_from(&key_prefix) {
+            if key_bytes[..key_prefix.len()] != key_prefix[..] {
+                break;
+            }
+            live_cell_indexes += 1;
+        }
+
+        let mut locks_capacity: u128 = 0;
+        let key_prefix = KeyType::LockTotalCapacity.to_bytes();
+        for (key_bytes, value_bytes) in reader.iter_from(&key_prefix) {
+            if key_bytes[..key_prefix.len()] != key_prefix[..] {
+                break;
+            }
+            let mut data = [0u8; 8];
+            data.copy_from_slice(&value_bytes[..8]);
+            locks_capacity += u128::from(u64::from_le_bytes(data));
+        }
+
+        if live_cells != live_cell_indexes {
+            return Err(IndexError::Inconsistent(format!(
+                "live cells: {}, live cell indexes: {}",
+                live_cells, live_cell_indexes
+            )));
+        }
+        if live_cells_capacity != chain_capacity || locks_capacity != chain_capacity {
+            return Err(IndexError::Inconsistent(format!(
+                "chain capacity: {}, live cells capacity: {}, locks capacity: {}",
+                chain_capacity, live_cells_capacity, locks_capacity
+            )));
+        }
+        Ok(())
+    }
+
     pub fn get_metrics(&self, key_type_opt: Option<KeyType>) -> BTreeMap<KeyType, KeyMetrics> {
         let mut key_types = BTreeMap::default();
         if let Some(key_type) = key_type_opt {
@@ -346,7 +532,12 @@ impl<'a> IndexDatabase<'a> {
                 KeyType::CodeLiveCellIndex,
             ];
             if self.enable_explorer {
-                types.extend(vec![KeyType::TxMap, KeyType::LockTx, KeyType::GlobalHash]);
+                types.extend(vec![
+                    KeyType::TxHistoryFrom,
+                    KeyType::TxMap,
+                    KeyType::LockTx,
+                    KeyType::GlobalHash,
+                ]);
             }
             for key_type in types {
                 key_types.insert(key_type, KeyMetrics::default());
@@ -367,6 +558,31 @@ impl<'a> IndexDatabase<'a> {
     }
 }
 
+// The transaction of a `LockTx` value (the tx hash), a missing one is skipped
+fn get_tx_info(reader: &RocksReader, value_bytes: &[u8]) -> Result<Option<TxInfo>, IndexError> {
+    let tx_hash = H256::from_slice(value_bytes)
+        .map_err(|err| IndexError::Inconsistent(format!("Invalid tx hash in LockTx: {}", err)))?;
+    reader
+        .get(&Key::TxMap(tx_hash.clone()).to_bytes())
+        .map(|bytes| {
+            bincode::deserialize(&bytes).map_err(|err| {
+                IndexError::Inconsistent(format!("Invalid TxInfo of {:#x}: {}", tx_hash, err))
+            })
+        })
+        .transpose()
+}
+
+/// The index used by `IndexDatabase::query_live_cells`
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LiveCellQuery {
+    // Lock script hash
+    Lock(Byte32),
+    // Type script hash
+    Type(Byte32),
+    // Type script code hash
+    Code(Byte32),
+}
+
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub enum IndexError {
     BlockImmature(u64),
@@ -377,6 +593,7 @@ pub enum IndexError {
     IoError(String),
     InvalidGenesis(String),
     InvalidNetworkType(String),
+    Inconsistent(String),
 }
 
 impl From<io::Error> for IndexError {
@@ -291,9 +291,10 @@ impl BlockDeltaInfo {
             } in &tx.inputs
             {
                 let out_point = OutPoint::new(tx_hash.clone().pack(), *tx_index);
+                // Keyed by the spending transaction, not where the cell is created
                 if enable_explorer {
                     txn.put_pair(Key::pair_lock_tx(
-                        (lock_hash.clone(), *number, index.tx_index),
+                        (lock_hash.clone(), tx.block_number, tx.tx_index),
                         &tx.tx_hash,
                     ));
                 }
@@ -413,6 +414,14 @@ impl BlockDeltaInfo {
         txn.put_pair(Key::pair_last_header(&Header::new_unchecked(
             self.header_info.header.clone(),
         )));
+        // A block applied without the transactions breaks the history
+        if enable_explorer {
+            if txn.get(&Key::TxHistoryFrom.to_bytes()).is_none() {
+                txn.put_pair(Key::pair_tx_history_from(current_number));
+            }
+        } else {
+            txn.remove_ok(Key::TxHistoryFrom.to_bytes());
+        }
 
         self.header_info.clone().into()
     }
@@ -434,7 +443,7 @@ impl BlockDeltaInfo {
                     ..
                 } = live_cell_info;
                 let out_point = OutPoint::new(tx_hash.clone().pack(), *tx_index);
-                delete_lock_txs.insert((lock_hash.clone(), *number, index.tx_index));
+                delete_lock_txs.insert((lock_hash.clone(), tx.block_number, tx.tx_index));
                 txn.put_pair(Key::pair_live_cell_map(out_point.clone(), live_cell_info));
                 txn.put_pair(Key::pair_live_cell_index((*number, *index), &out_point));
                 txn.put_pair(Key::pair_lock_live_cell_index(
@@ -527,6 +536,15 @@ impl BlockDeltaInfo {
         txn.remove(Key::RecentHeader(self.number()).to_bytes());
         // Remove recent block
         txn.remove(Key::BlockDelta(self.number()).to_bytes());
+        // No transactions recorded when the history starts from this block
+        let tx_history_from = txn.get(&Key::TxHistoryFrom.to_bytes()).map(|bytes| {
+            let mut data = [0u8; 8];
+            data.copy_from_slice(&bytes[..8]);
+            u64::from_le_bytes(data)
+        });
+        if tx_history_from == Some(self.number()) {
+            txn.remove(Key::TxHistoryFrom.to_bytes());
+        }
         // Update last header
         txn.put_pair(Key::pair_last_header(&Header::new_unchecked(
             self.parent_header.clone().unwrap(),
@@ -721,7 +739,7 @@ impl TxInfo {
             .collect::<Vec<_>>()
     }
     pub fn outputs(&self) -> Vec<OutPoint> {
-        self.inputs
+        self.outputs
             .iter()
             .map(|data| OutPoint::new_unchecked(data.clone()))
             .collect::<Vec<_>>()
@@ -16,6 +16,16 @@ impl<'a> RocksReader<'a> {
     pub fn new(db: &'a DB, cf: &'a ColumnFamily) -> RocksReader<'a> {
         RocksReader { db, cf }
     }
+
+    /// Iterate backward from the last key <= `key_start`
+    pub fn iter_rev_from(&'a self, key_start: &[u8]) -> ReaderIter<'a> {
+        let mode = IteratorMode::From(key_start, Direction::Reverse);
+        let iter = self
+            .db
+            .iterator_cf(self.cf, mode)
+            .expect("RocksReader iterator_cf failed");
+        ReaderIter { iter }
+    }
 }
 
 impl<'a> KVReader<'a> for RocksReader<'a> {
@@ -1,3 +1,25 @@
+//! Local index database of live cells, maintained by `ckb-cli`.
+//!
+//! The query API of `IndexDatabase` (`query_live_cells`, `get_live_cells_by_*`,
+//! `get_txs_by_lock`, `get_capacity`) and `LiveCellInfo`/`TxInfo` are kept
+//! stable across patch versions, the database layout is versioned by `VERSION`
+//! (`ckb-cli` stores it in `~/.ckb-cli/index-v{VERSION}`).
+//!
+//! ```ignore
+//! use ckb_index::{with_index_db, IndexDatabase, LiveCellQuery};
+//!
+//! let cells = with_index_db(&index_dir, genesis_hash, |backend, cf| {
+//!     let db = IndexDatabase::from_db(backend, cf, network_type, genesis_info, false)?;
+//!     // The first page (20 cells) of live cells owned by `lock_hash`
+//!     Ok(db.query_live_cells(LiveCellQuery::Lock(lock_hash), None, 0, 20))
+//! })?;
+//! ```
+//!
+//! NOTE: The database is opened exclusively, readers should keep the closure
+//! short so that the sync thread of `ckb-cli` can continue.
+//!
+//! `examples/live_cells.rs` is the complete program (built by `cargo test`).
+
 mod error;
 mod index;
 mod kvdb;
@@ -6,10 +28,13 @@ mod util;
 pub use error::Error;
 pub use index::{
     CellIndex, HashType, IndexDatabase, IndexError, Key as IndexKey, KeyMetrics as IndexKeyMetrics,
-    KeyType as IndexKeyType, LiveCellInfo, TxInfo,
+    KeyType as IndexKeyType, LiveCellInfo, LiveCellQuery, TxInfo,
 };
 pub use kvdb::{KVReader, KVTxn, RocksReader, RocksTxn};
 pub use util::{with_index_db, with_rocksdb};
 
 pub const ROCKSDB_COL_INDEX_DB: &str = "index-db";
-pub const VERSION: usize = 1;
+/// Bumped when the key layout changes, the index of a new version is built
+/// from the genesis block (2: the spent cells of a lock are keyed by the
+/// spending transaction)
+pub const VERSION: usize = 2;
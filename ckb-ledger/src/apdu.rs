@@ -11,6 +11,19 @@ pub fn app_version() -> ledger::ApduCommand {
     }
 }
 
+/// The name and version of the running app, answered by the device OS for
+/// any app
+pub fn get_app_version() -> ledger::ApduCommand {
+    ApduCommand {
+        cla: 0xb0,
+        ins: 0x01,
+        p1: 0x00,
+        p2: 0x00,
+        length: 0,
+        data: Vec::new(),
+    }
+}
+
 pub fn app_git_hash() -> ledger::ApduCommand {
     ApduCommand {
         cla: 0x80,
@@ -33,6 +46,32 @@ pub fn extend_public_key(data: Vec<u8>) -> ledger::ApduCommand {
     }
 }
 
+/// Same as `extend_public_key`, but the app displays the derived address on
+/// the device screen and only responds after the user confirmed it
+pub fn prompt_public_key(data: Vec<u8>) -> ledger::ApduCommand {
+    ApduCommand {
+        cla: 0x80,
+        ins: 0x02,
+        p1: 0x01,
+        p2: 0x00,
+        length: data.len() as u8,
+        data,
+    }
+}
+
+/// The public key and the chain code of the path (for deriving the non
+/// hardened children without the device)
+pub fn get_extended_public_key(data: Vec<u8>) -> ledger::ApduCommand {
+    ApduCommand {
+        cla: 0x80,
+        ins: 0x04,
+        p1: 0x00,
+        p2: 0x00,
+        length: data.len() as u8,
+        data,
+    }
+}
+
 pub fn get_wallet_id() -> ledger::ApduCommand {
     ApduCommand {
         cla: 0x80,
@@ -0,0 +1,291 @@
+#[cfg(target_os = "linux")]
+use std::{fs, io, path::Path};
+
+use ledger::LedgerApp as RawLedgerApp;
+
+use super::{apdu, LedgerAppInfo, LedgerTransport};
+
+/// The USB vendor id of Ledger devices
+pub const LEDGER_VENDOR_ID: u16 = 0x2c97;
+
+#[cfg(target_os = "linux")]
+const UDEV_RULE: &str = r#"SUBSYSTEMS=="usb", ATTRS{idVendor}=="2c97", MODE="0660", GROUP="plugdev", TAG+="uaccess", TAG+="udev-acl""#;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    Ok,
+    Failed,
+    Skipped,
+}
+
+impl CheckStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Failed => "failed",
+            CheckStatus::Skipped => "skipped",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub message: String,
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, message: String) -> Check {
+        Check {
+            name,
+            status,
+            message,
+        }
+    }
+}
+
+/// Run all the checks between the USB bus and the Nervos app, a check is
+/// skipped when an earlier one failed.
+pub fn run_checks() -> Vec<Check> {
+    let mut checks = usb_checks();
+    if checks
+        .iter()
+        .any(|check| check.status == CheckStatus::Failed)
+    {
+        checks.push(Check::new(
+            "nervos-app",
+            CheckStatus::Skipped,
+            "The device is not accessible".to_string(),
+        ));
+    } else {
+        checks.push(check_app());
+    }
+    checks
+}
+
+/// The guidance when a Ledger device is plugged in but can not be used,
+/// `None` when there is no Ledger device at all or no problem is found.
+pub fn troubleshoot() -> Option<String> {
+    let checks = usb_checks();
+    if checks
+        .iter()
+        .any(|check| check.name == "usb-device" && check.status != CheckStatus::Ok)
+    {
+        return None;
+    }
+    let failed = checks
+        .into_iter()
+        .filter(|check| check.status == CheckStatus::Failed)
+        .map(|check| check.message)
+        .collect::<Vec<_>>();
+    if failed.is_empty() {
+        None
+    } else {
+        Some(format!(
+            "A Ledger device is connected but can not be used:\n  {}\nRun `account ledger-doctor` for details",
+            failed.join("\n  ")
+        ))
+    }
+}
+
+fn check_app() -> Check {
+    let result = RawLedgerApp::new()
+        .map_err(|err| format!("Open the device failed: {}", err))
+        .and_then(|app| {
+            LedgerTransport::exchange(&app, apdu::get_wallet_id())
+                .map_err(|err| format!("The Nervos app is not responding ({}), unlock the device and open the Nervos app", err))?;
+            LedgerTransport::exchange(&app, apdu::get_app_version())
+                .and_then(|response| LedgerAppInfo::from_response(&response.data))
+                .map_err(|err| format!("Get the app version failed: {}", err))
+        });
+    match result {
+        Ok(app_info) => Check::new(
+            "nervos-app",
+            CheckStatus::Ok,
+            format!("The Nervos app is open: {}", app_info),
+        ),
+        Err(message) => Check::new("nervos-app", CheckStatus::Failed, message),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn usb_checks() -> Vec<Check> {
+    vec![Check::new(
+        "usb-device",
+        CheckStatus::Skipped,
+        "USB level probing is only supported on Linux".to_string(),
+    )]
+}
+
+#[cfg(target_os = "linux")]
+fn usb_checks() -> Vec<Check> {
+    let devices = read_dir_names("/sys/bus/usb/devices")
+        .into_iter()
+        .filter(|name| {
+            let path = Path::new("/sys/bus/usb/devices")
+                .join(name)
+                .join("idVendor");
+            fs::read_to_string(path)
+                .map(|vendor| parse_vendor_id(&vendor) == Some(LEDGER_VENDOR_ID))
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+    if devices.is_empty() {
+        return vec![Check::new(
+            "usb-device",
+            CheckStatus::Failed,
+            "No Ledger device on USB, connect the device with a data cable and unlock it"
+                .to_string(),
+        )];
+    }
+    let mut checks = vec![Check::new(
+        "usb-device",
+        CheckStatus::Ok,
+        format!("Ledger device found on USB: {}", devices.join(", ")),
+    )];
+
+    let rule_dirs = [
+        "/etc/udev/rules.d",
+        "/lib/udev/rules.d",
+        "/usr/lib/udev/rules.d",
+    ];
+    let rule_files = rule_dirs
+        .iter()
+        .flat_map(|dir| {
+            read_dir_names(dir)
+                .into_iter()
+                .map(move |name| Path::new(dir).join(name))
+        })
+        .filter(|path| {
+            fs::read_to_string(path)
+                .map(|content| content.to_lowercase().contains("2c97"))
+                .unwrap_or(false)
+        })
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>();
+    if rule_files.is_empty() {
+        checks.push(Check::new(
+            "udev-rule",
+            CheckStatus::Failed,
+            format!(
+                "No udev rule for Ledger devices, add to /etc/udev/rules.d/20-hw1.rules:\n    {}\n  then run `sudo udevadm control --reload-rules && sudo udevadm trigger` and reconnect the device",
+                UDEV_RULE
+            ),
+        ));
+    } else {
+        checks.push(Check::new(
+            "udev-rule",
+            CheckStatus::Ok,
+            format!("udev rule found: {}", rule_files.join(", ")),
+        ));
+    }
+
+    let hidraw_nodes = read_dir_names("/sys/class/hidraw")
+        .into_iter()
+        .filter(|name| {
+            let path = Path::new("/sys/class/hidraw")
+                .join(name)
+                .join("device")
+                .join("uevent");
+            fs::read_to_string(path)
+                .map(|uevent| is_ledger_uevent(&uevent))
+                .unwrap_or(false)
+        })
+        .collect::<Vec<_>>();
+    if hidraw_nodes.is_empty() {
+        checks.push(Check::new(
+            "hid-access",
+            CheckStatus::Failed,
+            "The Ledger device has no HID interface, unlock the device (it is not usable in bootloader mode)".to_string(),
+        ));
+        return checks;
+    }
+    for name in hidraw_nodes {
+        let path = Path::new("/dev").join(&name);
+        let check = match fs::OpenOptions::new().read(true).write(true).open(&path) {
+            Ok(_) => Check::new(
+                "hid-access",
+                CheckStatus::Ok,
+                format!("{} is accessible", path.display()),
+            ),
+            Err(ref err) if err.kind() == io::ErrorKind::PermissionDenied => Check::new(
+                "hid-access",
+                CheckStatus::Failed,
+                format!(
+                    "Permission denied to open {}, check the udev rule and that your user is in the `plugdev` group (re-login after joining it)",
+                    path.display()
+                ),
+            ),
+            // EBUSY
+            Err(ref err) if err.raw_os_error() == Some(16) => Check::new(
+                "hid-access",
+                CheckStatus::Failed,
+                format!(
+                    "{} is claimed by another process, close Ledger Live or other wallets using the device",
+                    path.display()
+                ),
+            ),
+            Err(err) => Check::new(
+                "hid-access",
+                CheckStatus::Failed,
+                format!("Open {} failed: {}", path.display(), err),
+            ),
+        };
+        checks.push(check);
+    }
+    checks
+}
+
+#[cfg(target_os = "linux")]
+fn read_dir_names<P: AsRef<Path>>(dir: P) -> Vec<String> {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(target_os = "linux")]
+fn parse_vendor_id(input: &str) -> Option<u16> {
+    u16::from_str_radix(input.trim(), 16).ok()
+}
+
+#[cfg(target_os = "linux")]
+// The `HID_ID` line of a HID device uevent: `HID_ID=<bus>:<vendor>:<product>`
+fn is_ledger_uevent(uevent: &str) -> bool {
+    uevent
+        .lines()
+        .filter_map(|line| {
+            if line.starts_with("HID_ID=") {
+                Some(&line["HID_ID=".len()..])
+            } else {
+                None
+            }
+        })
+        .any(|hid_id| {
+            hid_id
+                .split(':')
+                .nth(1)
+                .and_then(|vendor| u32::from_str_radix(vendor, 16).ok())
+                == Some(u32::from(LEDGER_VENDOR_ID))
+        })
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_device_ids() {
+        assert_eq!(parse_vendor_id("2c97\n"), Some(LEDGER_VENDOR_ID));
+        assert_eq!(parse_vendor_id("046d"), Some(0x046d));
+        assert!(is_ledger_uevent(
+            "DRIVER=hid-generic\nHID_ID=0003:00002C97:00001011\nHID_NAME=Ledger Nano S\n"
+        ));
+        assert!(!is_ledger_uevent("HID_ID=0003:0000046D:0000C52B\n"));
+    }
+}
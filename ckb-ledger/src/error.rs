@@ -6,7 +6,7 @@ use failure::Fail;
 
 use ledger::LedgerError as RawLedgerError;
 
-use super::LedgerId;
+use super::{AppVersion, LedgerAppInfo, LedgerId};
 
 #[derive(Debug, Fail)]
 pub enum Error {
@@ -33,6 +33,72 @@ pub enum Error {
         _0
     )]
     InvalidDerivationPath { path: DerivationPath },
+    #[fail(
+        display = "The change path {} does not derive the address of any output, refuse to sign",
+        _0
+    )]
+    ChangePathMismatch(DerivationPath),
+    #[fail(display = "Invalid ledger app version: {}", _0)]
+    InvalidAppVersion(String),
+    #[fail(
+        display = "The ledger app {} is older than the minimum supported version {}, please upgrade it by Ledger Live",
+        installed, required
+    )]
+    AppVersionTooOld {
+        installed: LedgerAppInfo,
+        required: AppVersion,
+    },
+    #[fail(display = "Ledger emulator error: {}", _0)]
+    EmulatorError(String),
+    #[fail(display = "Declined on the Ledger device")]
+    UserRejected,
+    #[fail(
+        display = "The Ledger device is locked (status word {:#06x}), unlock it by the PIN",
+        _0
+    )]
+    DeviceLocked(u16),
+    #[fail(
+        display = "The Nervos app is not open (status word {:#06x}), open the Nervos app on the Ledger device",
+        _0
+    )]
+    WrongApp(u16),
+    #[fail(
+        display = "The Nervos app rejected the data (status word 0x6a80), the transaction may not be supported by this app version"
+    )]
+    InvalidData,
+    #[fail(display = "The Ledger device responded with status word {:#06x}", _0)]
+    StatusWord(u16),
+    #[fail(
+        display = "Ledger with id {:?} is not connected, only its cached public keys are available",
+        id
+    )]
+    NotConnected { id: LedgerId },
+    #[fail(display = "Ledger extended public key cache error: {}", _0)]
+    XpubCacheError(String),
+    #[fail(
+        display = "The message to sign is not a valid annotated transaction: {}",
+        _0
+    )]
+    InvalidSignMessage(String),
+}
+
+/// The status word of a successful command
+pub const SW_OK: u16 = 0x9000;
+
+impl Error {
+    /// The error of a failed command by its status word, `None` for success
+    pub fn from_status_word(status_word: u16) -> Option<Error> {
+        match status_word {
+            SW_OK => None,
+            0x6985 => Some(Error::UserRejected),
+            // PIN not validated / security status not satisfied
+            0x5515 | 0x6982 => Some(Error::DeviceLocked(status_word)),
+            // INS or CLA not supported: the dashboard or another app is open
+            0x6d00 | 0x6e00 | 0x6e01 => Some(Error::WrongApp(status_word)),
+            0x6a80 => Some(Error::InvalidData),
+            _ => Some(Error::StatusWord(status_word)),
+        }
+    }
 }
 
 impl From<RawLedgerError> for Error {
@@ -52,3 +118,25 @@ impl From<secp256k1::Error> for Error {
         Error::Secp256k1Error(err)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_status_word() {
+        assert!(Error::from_status_word(SW_OK).is_none());
+        match Error::from_status_word(0x6985) {
+            Some(Error::UserRejected) => {}
+            other => panic!("unexpected: {:?}", other),
+        }
+        match Error::from_status_word(0x6e00) {
+            Some(Error::WrongApp(0x6e00)) => {}
+            other => panic!("unexpected: {:?}", other),
+        }
+        match Error::from_status_word(0x6f00) {
+            Some(Error::StatusWord(0x6f00)) => {}
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
+}
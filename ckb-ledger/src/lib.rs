@@ -1,44 +1,419 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::Arc;
 
 use bitflags;
-use byteorder::{BigEndian, WriteBytesExt};
-use log::debug;
-use secp256k1::{key::PublicKey, recovery::RecoverableSignature, recovery::RecoveryId, Signature};
+use log::{debug, warn};
+use secp256k1::{key::PublicKey, recovery::RecoverableSignature, Signature};
 
 use ckb_sdk::wallet::{
-    is_valid_derivation_path, AbstractKeyStore, AbstractMasterPrivKey, AbstractPrivKey,
-    ChildNumber, DerivationPath, ScryptType,
+    is_valid_derivation_path, AbstractKeyStore, AbstractMasterPrivKey, AbstractPrivKey, ChainCode,
+    ChildNumber, DerivationPath, ExtendedPubKey, Fingerprint, ScryptType,
 };
-use ckb_sdk::SignEntireHelper;
+use ckb_sdk::{AddressPayload, SignerSingleShot, SECP256K1};
 use ckb_types::H256;
 
-use ledger::ApduCommand;
 use ledger::LedgerApp as RawLedgerApp;
+use ledger::{ApduAnswer, ApduCommand};
 
 pub mod apdu;
+pub mod doctor;
 mod error;
 pub mod parse;
+mod progress;
+pub mod transport;
+mod version;
+pub mod xpub_cache;
 
 pub use error::Error as LedgerKeyStoreError;
+pub use progress::{SignProgress, SignProgressFn};
+pub use transport::{LedgerTransport, SpeculosTransport};
+pub use version::{AppVersion, LedgerAppInfo, DEFAULT_MIN_APP_VERSION};
+pub use xpub_cache::XpubCache;
 
 use ckb_types::{
-    packed::{AnnotatedTransaction, Bip32, Uint32},
+    packed::{AnnotatedTransaction, Bip32, Script, Uint32},
     prelude::*,
 };
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use crate::transport::MockTransport;
+    use ckb_sdk::wallet::ExtendedPrivKey;
+    use ckb_sdk::SignerSingleShot;
+    use ckb_types::{
+        bytes::Bytes,
+        packed::{BytesVec, RawTransaction},
+    };
+    use secp256k1::{Message, SecretKey};
+    use std::sync::Mutex;
+
+    const WALLET_ID: [u8; 32] = [0x42; 32];
+
     #[test]
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    fn wallet_id_response() -> Vec<u8> {
+        let mut data = WALLET_ID.to_vec();
+        data.extend_from_slice(&[0x24; 32]);
+        data
+    }
+
+    fn app_version_response(version: &str) -> Vec<u8> {
+        let mut data = vec![0x01, 6];
+        data.extend_from_slice(b"Nervos");
+        data.push(version.len() as u8);
+        data.extend_from_slice(version.as_bytes());
+        data.extend_from_slice(&[1, 0x00]);
+        data
+    }
+
+    fn discovery(transport: MockTransport) -> MockTransport {
+        transport
+            .expect(apdu::get_app_version(), app_version_response("0.5.2"))
+            .expect(apdu::get_wallet_id(), wallet_id_response())
+    }
+
+    // The exchanges of `script` are expected after the discovery
+    fn ledger_cap<F>(script: F) -> (Arc<MockTransport>, LedgerCap)
+    where
+        F: FnOnce(MockTransport) -> MockTransport,
+    {
+        let transport = Arc::new(script(discovery(MockTransport::new())));
+        let master = LedgerMasterCap::from_transport(transport.clone()).unwrap();
+        let path: DerivationPath = "m/44'/309'/0'/0/0".parse().unwrap();
+        let cap = master.extended_privkey(path.as_ref()).unwrap();
+        (transport, cap)
+    }
+
+    fn raw_path() -> Vec<u8> {
+        let mut data = vec![5];
+        for child_num in &[0x8000_002c_u32, 0x8000_0135, 0x8000_0000, 0, 0] {
+            data.extend_from_slice(&child_num.to_be_bytes());
+        }
+        data
+    }
+
+    fn sign_command(p1: SignP1, data: &[u8]) -> ApduCommand {
+        ApduCommand {
+            cla: 0x80,
+            ins: 0x03,
+            p1: p1.bits,
+            p2: 0,
+            length: data.len() as u8,
+            data: data.to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_wallet_id() {
+        let transport = Arc::new(discovery(MockTransport::new()));
+        let master = LedgerMasterCap::from_transport(transport.clone()).unwrap();
+        assert_eq!(master.id, LedgerId(H256::from_slice(&WALLET_ID).unwrap()));
+        assert_eq!(
+            master.app_info(),
+            &LedgerAppInfo {
+                name: "Nervos".to_string(),
+                version: AppVersion(0, 5, 2),
+            }
+        );
+        assert_eq!(transport.remaining(), 0);
+
+        // The app responds with 64 bytes exactly
+        for len in &[63, 65] {
+            let mut data = wallet_id_response();
+            data.resize(*len, 0);
+            let transport = Arc::new(
+                MockTransport::new()
+                    .expect(apdu::get_app_version(), app_version_response("0.5.2"))
+                    .expect(apdu::get_wallet_id(), data),
+            );
+            assert!(LedgerMasterCap::from_transport(transport).is_err());
+        }
+    }
+
+    #[test]
+    fn test_min_app_version() {
+        let transport = Arc::new(
+            MockTransport::new()
+                .expect(apdu::get_app_version(), app_version_response("0.4.0"))
+                .expect(apdu::get_wallet_id(), wallet_id_response()),
+        );
+        let master = LedgerMasterCap::from_transport(transport.clone()).unwrap();
+        // Discovery and public keys still work with an old app
+        assert!(master.check_app_version().is_err());
+        let master = master.with_min_app_version(AppVersion(0, 4, 0));
+        assert!(master.check_app_version().is_ok());
+
+        let (_, cap) = ledger_cap(|transport| transport);
+        let cap = LedgerCap {
+            master: cap.master.with_min_app_version(AppVersion(1, 0, 0)),
+            path: cap.path,
+        };
+        assert!(cap.sign_hash(&H256::default()).is_err());
+    }
+
+    #[test]
+    fn test_sign_rejected() {
+        let (transport, cap) = ledger_cap(|transport| {
+            transport
+                .expect(sign_command(SignP1::FIRST, &raw_path()), Vec::new())
+                .expect_failure(
+                    sign_command(SignP1::HASH_ONLY_NEXT | SignP1::LAST_MARKER, &[0; 32]),
+                    0x6985,
+                )
+        });
+        match cap.sign_hash(&H256::default()) {
+            Err(LedgerKeyStoreError::UserRejected) => {}
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert_eq!(transport.remaining(), 0);
+    }
+
+    #[test]
+    fn test_sign_invalid_message() {
+        let (transport, cap) = ledger_cap(|transport| transport);
+        let mut signer = cap.begin_sign_recoverable();
+        signer.append(&[0x01, 0x02, 0x03]);
+        match Box::new(signer).finalize() {
+            Err(LedgerKeyStoreError::InvalidSignMessage(_)) => {}
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert_eq!(transport.remaining(), 0);
+
+        let too_deep: DerivationPath = "m/44'/309'/0'/0/0/0/0/0/0/0/0".parse().unwrap();
+        assert!(cap.master.extended_privkey(too_deep.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_sign_digest() {
+        // A 32 bytes message is still parsed as a transaction
+        let (transport, cap) = ledger_cap(|transport| transport);
+        let mut signer = cap.begin_sign_recoverable();
+        signer.append(&[0; 32]);
+        match Box::new(signer).finalize() {
+            Err(LedgerKeyStoreError::InvalidSignMessage(_)) => {}
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert_eq!(transport.remaining(), 0);
+
+        // Only signed as a hash when asked
+        let (transport, cap) = ledger_cap(|transport| {
+            transport
+                .expect(sign_command(SignP1::FIRST, &raw_path()), Vec::new())
+                .expect_failure(
+                    sign_command(SignP1::HASH_ONLY_NEXT | SignP1::LAST_MARKER, &[0; 32]),
+                    0x6985,
+                )
+        });
+        let mut signer = cap.begin_sign_recoverable();
+        assert!(signer.sign_digest());
+        signer.append(&[0; 32]);
+        match Box::new(signer).finalize() {
+            Err(LedgerKeyStoreError::UserRejected) => {}
+            other => panic!("unexpected: {:?}", other),
+        }
+        assert_eq!(transport.remaining(), 0);
+
+        let mut signer = cap.begin_sign_recoverable();
+        assert!(signer.sign_digest());
+        signer.append(&[0; 33]);
+        assert!(Box::new(signer).finalize().is_err());
+    }
+
+    #[test]
+    fn test_public_key() {
+        let privkey = SecretKey::from_slice(&[0x01; 32]).unwrap();
+        let pubkey = PublicKey::from_secret_key(&SECP256K1, &privkey);
+        let mut data = vec![33];
+        data.extend_from_slice(&pubkey.serialize());
+        let (transport, cap) =
+            ledger_cap(|transport| transport.expect(apdu::extend_public_key(raw_path()), data));
+        assert_eq!(cap.public_key().unwrap(), pubkey);
+        assert_eq!(transport.remaining(), 0);
+    }
+
+    #[test]
+    fn test_sign_chunks() {
+        let privkey = SecretKey::from_slice(&[0x01; 32]).unwrap();
+        let signature =
+            SECP256K1.sign_recoverable(&Message::from_slice(&[0x02; 32]).unwrap(), &privkey);
+        let (recovery_id, compact) = signature.serialize_compact();
+        let mut signature_data = compact.to_vec();
+        signature_data.push(recovery_id.to_i32() as u8);
+
+        // A single chunk and several chunks
+        for (data_len, chunk_count) in &[(0, 1), (2 * MAX_APDU_SIZE, 3)] {
+            let raw_tx = RawTransaction::new_builder()
+                .outputs_data(
+                    BytesVec::new_builder()
+                        .push(Bytes::from(vec![0xab; *data_len]).pack())
+                        .build(),
+                )
+                .build();
+            let message = AnnotatedTransaction::new_builder().raw(raw_tx).build();
+
+            let (_, cap) = ledger_cap(|transport| transport);
+            let sign_path = {
+                let mut bytes = Vec::new();
+                for &child_num in cap.path.as_ref().iter() {
+                    let raw_child_num: u32 = child_num.into();
+                    bytes.push(raw_child_num.pack());
+                }
+                Bip32::new_builder().set(bytes).build()
+            };
+            let expected_message = message
+                .clone()
+                .as_builder()
+                .sign_path(sign_path.clone())
+                .change_path(sign_path)
+                .build();
+            let chunks = expected_message
+                .as_slice()
+                .chunks(MAX_APDU_SIZE)
+                .collect::<Vec<_>>();
+            assert_eq!(chunks.len(), *chunk_count);
+
+            let (transport, cap) = ledger_cap(|mut transport| {
+                for (idx, chunk) in chunks.iter().enumerate() {
+                    let mut p1 = if idx == 0 {
+                        SignP1::FIRST
+                    } else {
+                        SignP1::NEXT
+                    };
+                    let response = if idx + 1 == chunks.len() {
+                        p1 |= SignP1::LAST_MARKER;
+                        signature_data.clone()
+                    } else {
+                        Vec::new()
+                    };
+                    transport = transport.expect(sign_command(p1, chunk), response);
+                }
+                transport
+            });
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let recorder = events.clone();
+            let sign_progress: SignProgressFn =
+                Arc::new(move |progress| recorder.lock().unwrap().push(progress));
+            let cap = LedgerCap {
+                master: cap.master.with_sign_progress(Some(sign_progress)),
+                path: cap.path,
+            };
+            let mut signer = cap.begin_sign_recoverable();
+            signer.append(message.as_slice());
+            assert_eq!(Box::new(signer).finalize().unwrap(), signature);
+            assert_eq!(transport.remaining(), 0);
+
+            let mut expected = (0..*chunk_count)
+                .map(|sent| SignProgress::Sending {
+                    sent,
+                    total: *chunk_count,
+                })
+                .collect::<Vec<_>>();
+            expected.push(SignProgress::AwaitingConfirmation);
+            expected.push(SignProgress::Finished);
+            assert_eq!(*events.lock().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_cached_account_xpub() {
+        let master_key = ExtendedPrivKey::new_master(&[0x01; 32]).unwrap();
+        let account = master_key
+            .derive_priv(&SECP256K1, &xpub_cache::account_path())
+            .unwrap();
+        let account_xpub = ExtendedPubKey::from_private(&SECP256K1, &account);
+        let mut data = vec![33];
+        data.extend_from_slice(&account_xpub.public_key.serialize());
+        data.push(32);
+        data.extend_from_slice(&account_xpub.chain_code[..]);
+        let mut account_raw_path = vec![3];
+        for child_num in &[0x8000_002c_u32, 0x8000_0135, 0x8000_0000] {
+            account_raw_path.extend_from_slice(&child_num.to_be_bytes());
+        }
+        let transport = Arc::new(discovery(MockTransport::new()).expect(
+            apdu::get_extended_public_key(account_raw_path.clone()),
+            data.clone(),
+        ));
+        let master = LedgerMasterCap::from_transport(transport.clone()).unwrap();
+        let fetched = master.fetch_account_xpub().unwrap();
+        assert_eq!(fetched.public_key, account_xpub.public_key);
+        assert_eq!(fetched.chain_code, account_xpub.chain_code);
+        assert_eq!(transport.remaining(), 0);
+
+        // A tampered cache is replaced by the key of the connected device
+        let dir = std::env::temp_dir().join(format!("ckb-ledger-xpub-{}", std::process::id()));
+        let xpub_cache = XpubCache::new(dir.clone());
+        let other_key = ExtendedPrivKey::new_master(&[0x02; 32]).unwrap();
+        let tampered = ExtendedPubKey::from_private(
+            &SECP256K1,
+            &other_key
+                .derive_priv(&SECP256K1, &xpub_cache::account_path())
+                .unwrap(),
+        );
+        xpub_cache.store(&master.id, &tampered).unwrap();
+        let key_store = LedgerKeyStore::new(Some(xpub_cache));
+        let transport = Arc::new(
+            discovery(MockTransport::new())
+                .expect(apdu::get_extended_public_key(account_raw_path), data),
+        );
+        let connected = key_store
+            .with_cached_xpub(LedgerMasterCap::from_transport(transport.clone()).unwrap())
+            .unwrap();
+        assert_eq!(
+            connected.account_xpub.as_ref().map(|xpub| xpub.public_key),
+            Some(account_xpub.public_key)
+        );
+        let cached = XpubCache::new(dir.clone())
+            .load(&master.id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(cached.public_key, account_xpub.public_key);
+        assert_eq!(transport.remaining(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+
+        // The receiving and change addresses are derived without the device
+        let offline = LedgerMasterCap::from_cache(master.id.clone(), fetched);
+        let path: DerivationPath = "m/44'/309'/0'/1/7".parse().unwrap();
+        let expected = PublicKey::from_secret_key(
+            &SECP256K1,
+            &master_key
+                .derive_priv(&SECP256K1, &path)
+                .unwrap()
+                .private_key,
+        );
+        let cap = offline.extended_privkey(path.as_ref()).unwrap();
+        assert_eq!(cap.public_key().unwrap(), expected);
+        match cap.sign_hash(&H256::default()) {
+            Err(LedgerKeyStoreError::NotConnected { .. }) => {}
+            other => panic!("unexpected: {:?}", other),
+        }
+        let hardened: DerivationPath = "m/44'/309'/1'/0/0".parse().unwrap();
+        match offline
+            .extended_privkey(hardened.as_ref())
+            .unwrap()
+            .public_key()
+        {
+            Err(LedgerKeyStoreError::NotConnected { .. }) => {}
+            other => panic!("unexpected: {:?}", other),
+        }
+    }
 }
 
 pub struct LedgerKeyStore {
     discovered_devices: HashMap<LedgerId, LedgerMasterCap>,
+    // Use the Speculos emulator listening on this address instead of devices
+    emulator: Option<SocketAddr>,
+    // Refuse to sign by an older app
+    min_app_version: AppVersion,
+    sign_progress: Option<SignProgressFn>,
+    // The account extended public keys of the devices seen before
+    xpub_cache: Option<XpubCache>,
 }
 
 #[derive(Clone, Default, PartialEq, Eq, Hash, Debug)]
@@ -46,22 +421,111 @@ pub struct LedgerKeyStore {
 pub struct LedgerId(pub H256);
 
 impl LedgerKeyStore {
-    fn new() -> Self {
+    fn new(xpub_cache: Option<XpubCache>) -> Self {
         LedgerKeyStore {
             discovered_devices: HashMap::new(),
+            emulator: None,
+            min_app_version: DEFAULT_MIN_APP_VERSION,
+            sign_progress: None,
+            xpub_cache,
         }
     }
 
+    /// Talk to the Speculos emulator (APDU port) instead of the USB devices
+    pub fn set_emulator(&mut self, emulator: Option<SocketAddr>) {
+        self.emulator = emulator;
+    }
+
+    /// The oldest app version allowed to sign, `DEFAULT_MIN_APP_VERSION` by
+    /// default
+    pub fn set_min_app_version(&mut self, version: AppVersion) {
+        self.min_app_version = version;
+    }
+
+    /// Report the progress of signing by the devices (e.g. to show a status
+    /// line, signing a large transaction takes a while)
+    pub fn set_sign_progress(&mut self, sign_progress: Option<SignProgressFn>) {
+        self.sign_progress = sign_progress;
+    }
+
     fn refresh(&mut self) -> Result<(), LedgerKeyStoreError> {
         self.discovered_devices.clear();
+        if let Some(addr) = self.emulator {
+            let transport = SpeculosTransport::connect(addr)?;
+            let ledger_app = LedgerMasterCap::from_transport(Arc::new(transport))?
+                .with_min_app_version(self.min_app_version)
+                .with_sign_progress(self.sign_progress.clone());
+            self.discovered_devices
+                .insert(ledger_app.id.clone(), ledger_app);
+            return Ok(());
+        }
         // TODO fix ledger library so can put in all ledgers
         if let Ok(raw_ledger_app) = RawLedgerApp::new() {
-            let ledger_app = LedgerMasterCap::from_ledger(raw_ledger_app)?;
+            let ledger_app = LedgerMasterCap::from_transport(Arc::new(raw_ledger_app))?
+                .with_min_app_version(self.min_app_version)
+                .with_sign_progress(self.sign_progress.clone());
+            let ledger_app = self.with_cached_xpub(ledger_app)?;
             self.discovered_devices
                 .insert(ledger_app.id.clone(), ledger_app);
         }
+        // The devices not connected are still usable for deriving addresses
+        if let Some(ref xpub_cache) = self.xpub_cache {
+            for id in xpub_cache.cached_ids() {
+                if self.discovered_devices.contains_key(&id) {
+                    continue;
+                }
+                match xpub_cache.load(&id) {
+                    Ok(Some(xpub)) => {
+                        let ledger_app = LedgerMasterCap::from_cache(id.clone(), xpub);
+                        self.discovered_devices.insert(id, ledger_app);
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!("Skip the cached xpub: {}", err),
+                }
+            }
+        }
         Ok(())
     }
+
+    // The account extended public key of a connected device is always asked
+    // from the device, the cache is only trusted for the devices not
+    // connected. A cached key differing from the device (tampered, or the
+    // device was reset with the same wallet id) is replaced.
+    fn with_cached_xpub(
+        &self,
+        ledger_app: LedgerMasterCap,
+    ) -> Result<LedgerMasterCap, LedgerKeyStoreError> {
+        let xpub_cache = match self.xpub_cache {
+            Some(ref xpub_cache) => xpub_cache,
+            None => return Ok(ledger_app),
+        };
+        // The cache is refreshed from the device, a broken one is not an error
+        let cached = xpub_cache.load(&ledger_app.id).unwrap_or_else(|err| {
+            warn!("Ignore the cached xpub: {}", err);
+            None
+        });
+        match ledger_app.fetch_account_xpub() {
+            Ok(xpub) => {
+                let matched = cached
+                    .map(|cached| {
+                        cached.public_key == xpub.public_key && cached.chain_code == xpub.chain_code
+                    })
+                    .unwrap_or(false);
+                if !matched {
+                    xpub_cache.store(&ledger_app.id, &xpub)?;
+                }
+                Ok(ledger_app.with_account_xpub(Some(xpub)))
+            }
+            Err(err) => {
+                // Older apps don't give the chain code, keep asking the device
+                debug!("Fetch the account extended public key failed: {}", err);
+                if cached.is_some() {
+                    xpub_cache.remove(&ledger_app.id)?;
+                }
+                Ok(ledger_app)
+            }
+        }
+    }
 }
 
 impl AbstractKeyStore for LedgerKeyStore {
@@ -79,9 +543,9 @@ impl AbstractKeyStore for LedgerKeyStore {
         Ok(Box::new(key_copies.into_iter()))
     }
 
-    fn from_dir(_dir: PathBuf, _scrypt_type: ScryptType) -> Result<Self, LedgerKeyStoreError> {
+    fn from_dir(dir: PathBuf, _scrypt_type: ScryptType) -> Result<Self, LedgerKeyStoreError> {
         // TODO maybe force the initialization of the HidAPI "lazy static"?
-        Ok(LedgerKeyStore::new())
+        Ok(LedgerKeyStore::new(Some(XpubCache::new(dir))))
     }
 
     fn borrow_account<'a, 'b>(
@@ -101,14 +565,35 @@ impl AbstractKeyStore for LedgerKeyStore {
 #[derive(Clone)]
 pub struct LedgerMasterCap {
     id: LedgerId,
+    app_info: LedgerAppInfo,
+    min_app_version: AppVersion,
+    sign_progress: Option<SignProgressFn>,
+    // The extended public key of `xpub_cache::ACCOUNT_PATH`
+    account_xpub: Option<ExtendedPubKey>,
+    connected: bool,
     // TODO no Arc once we have "generic associated types" and can just borrow the device.
-    ledger_app: Arc<RawLedgerApp>,
+    ledger_app: Arc<dyn LedgerTransport>,
+}
+
+// The device of a cached wallet id which is not connected
+struct NotConnected(LedgerId);
+
+impl LedgerTransport for NotConnected {
+    fn exchange(&self, _command: ApduCommand) -> Result<ApduAnswer, LedgerKeyStoreError> {
+        Err(LedgerKeyStoreError::NotConnected { id: self.0.clone() })
+    }
 }
 
 impl LedgerMasterCap {
-    /// Create from a ledger device, checking that a proper version of the
-    /// Nervos app is installed.
-    fn from_ledger(ledger_app: RawLedgerApp) -> Result<Self, LedgerKeyStoreError> {
+    /// Create from a ledger device (or any other transport), checking that a
+    /// proper version of the Nervos app is installed.
+    pub fn from_transport(
+        ledger_app: Arc<dyn LedgerTransport>,
+    ) -> Result<Self, LedgerKeyStoreError> {
+        let response = ledger_app.exchange(apdu::get_app_version())?;
+        let app_info = LedgerAppInfo::from_response(&response.data)?;
+        debug!("Ledger app: {}", app_info);
+
         let command = apdu::get_wallet_id();
         let response = ledger_app.exchange(command)?;
         debug!("Nervos CKB Ledger app wallet id: {:02x?}", response);
@@ -117,18 +602,130 @@ impl LedgerMasterCap {
         // TODO: The ledger app gives us 64 bytes but we only use 32
         // bytes. We should either limit how many the ledger app
         // gives, or take all 64 bytes here.
-        let raw_wallet_id = parse::split_off_at(&mut resp, 32)?;
+        let wallet_id = parse::split_off_h256(&mut resp)?;
         let _ = parse::split_off_at(&mut resp, 32)?;
         parse::assert_nothing_left(resp)?;
 
         Ok(LedgerMasterCap {
-            id: LedgerId(H256::from_slice(raw_wallet_id).unwrap()),
-            ledger_app: Arc::new(ledger_app),
+            id: LedgerId(wallet_id),
+            app_info,
+            min_app_version: DEFAULT_MIN_APP_VERSION,
+            sign_progress: None,
+            account_xpub: None,
+            connected: true,
+            ledger_app,
         })
     }
-}
 
-const WRITE_ERR_MSG: &'static str = "IO error not possible when writing to Vec last I checked";
+    /// A device not connected, only the public keys derived from the cached
+    /// account extended public key are available.
+    pub fn from_cache(id: LedgerId, account_xpub: ExtendedPubKey) -> Self {
+        LedgerMasterCap {
+            id: id.clone(),
+            app_info: LedgerAppInfo {
+                name: "Nervos".to_string(),
+                version: AppVersion::default(),
+            },
+            min_app_version: DEFAULT_MIN_APP_VERSION,
+            sign_progress: None,
+            account_xpub: Some(account_xpub),
+            connected: false,
+            ledger_app: Arc::new(NotConnected(id)),
+        }
+    }
+
+    pub fn with_min_app_version(mut self, version: AppVersion) -> Self {
+        self.min_app_version = version;
+        self
+    }
+
+    pub fn with_sign_progress(mut self, sign_progress: Option<SignProgressFn>) -> Self {
+        self.sign_progress = sign_progress;
+        self
+    }
+
+    pub fn with_account_xpub(mut self, account_xpub: Option<ExtendedPubKey>) -> Self {
+        self.account_xpub = account_xpub;
+        self
+    }
+
+    /// Ask the device for the public key and the chain code of
+    /// `xpub_cache::ACCOUNT_PATH`
+    pub fn fetch_account_xpub(&self) -> Result<ExtendedPubKey, LedgerKeyStoreError> {
+        let cap = self.extended_privkey(xpub_cache::account_path().as_ref())?;
+        let response = self
+            .ledger_app
+            .exchange(apdu::get_extended_public_key(cap.raw_path()))?;
+        let mut resp = &response.data[..];
+        let len = parse::split_first(&mut resp)? as usize;
+        let raw_public_key = parse::split_off_at(&mut resp, len)?;
+        // The chain code is always 32 bytes
+        let _ = parse::split_first(&mut resp)?;
+        let raw_chain_code = parse::split_off_at(&mut resp, 32)?;
+        parse::assert_nothing_left(resp)?;
+        Ok(ExtendedPubKey {
+            depth: cap.path.as_ref().len() as u8,
+            parent_fingerprint: Fingerprint::default(),
+            child_number: ChildNumber::Hardened { index: 0 },
+            public_key: PublicKey::from_slice(raw_public_key)?,
+            chain_code: ChainCode::from(raw_chain_code),
+        })
+    }
+
+    // The public key of the path derived from the account extended public key
+    // (without the device), `None` when the path is not under the account or
+    // has hardened children.
+    fn cached_public_key(
+        &self,
+        path: &[ChildNumber],
+    ) -> Option<Result<PublicKey, LedgerKeyStoreError>> {
+        let account_xpub = self.account_xpub.as_ref()?;
+        let account_path = xpub_cache::account_path();
+        let account_path = account_path.as_ref();
+        if path.len() < account_path.len() || &path[..account_path.len()] != account_path {
+            return None;
+        }
+        let children = &path[account_path.len()..];
+        if children.iter().any(|child_num| child_num.is_hardened()) {
+            return None;
+        }
+        Some(
+            account_xpub
+                .derive_pub(&SECP256K1, children)
+                .map(|xpub| xpub.public_key)
+                .map_err(LedgerKeyStoreError::from),
+        )
+    }
+
+    fn report(&self, progress: SignProgress) {
+        debug!("Ledger signing progress: {:?}", progress);
+        if let Some(ref sign_progress) = self.sign_progress {
+            sign_progress(progress);
+        }
+    }
+
+    /// False when only known by the cached account extended public key
+    pub fn is_connected(&self) -> bool {
+        self.connected
+    }
+
+    /// The name and version of the app found at discovery
+    pub fn app_info(&self) -> &LedgerAppInfo {
+        &self.app_info
+    }
+
+    /// Signing is refused when the app is older than the minimum version
+    pub fn check_app_version(&self) -> Result<(), LedgerKeyStoreError> {
+        if self.app_info.version < self.min_app_version {
+            Err(LedgerKeyStoreError::AppVersionTooOld {
+                installed: self.app_info.clone(),
+                required: self.min_app_version,
+            })
+        } else {
+            Ok(())
+        }
+    }
+}
 
 impl AbstractMasterPrivKey for LedgerMasterCap {
     type Err = LedgerKeyStoreError;
@@ -136,7 +733,7 @@ impl AbstractMasterPrivKey for LedgerMasterCap {
     type Privkey = LedgerCap;
 
     fn extended_privkey(&self, path: &[ChildNumber]) -> Result<LedgerCap, Self::Err> {
-        if !is_valid_derivation_path(path.as_ref()) {
+        if !is_valid_derivation_path(path.as_ref()) || path.len() > MAX_PATH_DEPTH {
             return Err(LedgerKeyStoreError::InvalidDerivationPath {
                 path: path.as_ref().iter().cloned().collect(),
             });
@@ -156,18 +753,19 @@ pub struct LedgerCap {
     pub path: DerivationPath,
 }
 
-// Only not using impl trait because unstable
-type LedgerClosure = Box<dyn FnOnce(Vec<u8>) -> Result<RecoverableSignature, LedgerKeyStoreError>>;
-
 const MAX_APDU_SIZE: usize = 230;
 
+// The deepest path accepted by the app
+const MAX_PATH_DEPTH: usize = 10;
+
 bitflags::bitflags! {
     struct SignP1: u8 {
         // for the path
         const FIRST = 0b_0000_0000;
         // for the tx
         const NEXT  = 0b_0000_0001;
-        //const HASH_ONLY_NEXT  = 0b_000_0010 | Self::NEXT.bits; // You only need it once
+        // for the 32 bytes hash, instead of the tx
+        const HASH_ONLY_NEXT  = 0b_0000_0010 | Self::NEXT.bits; // You only need it once
         const CHANGE_PATH = 0b_0001_0000;
         const IS_CONTEXT = 0b_0010_0000;
         const NO_FALLBACK = 0b_0100_0000;
@@ -176,20 +774,84 @@ bitflags::bitflags! {
     }
 }
 
-impl AbstractPrivKey for LedgerCap {
-    type Err = LedgerKeyStoreError;
+impl LedgerCap {
+    /// Ask the Nervos app to display the address of this path on the device
+    /// screen, returns the public key after the user confirmed it. An error is
+    /// returned when the user rejected it.
+    pub fn verify_address(&self) -> Result<secp256k1::PublicKey, LedgerKeyStoreError> {
+        self.exchange_public_key(apdu::prompt_public_key(self.raw_path()))
+    }
 
-    type SignerSingleShot = SignEntireHelper<LedgerClosure>;
+    /// The device will treat the outputs locked by the address of change path
+    /// as change, so the change path must derive (by this device) the address
+    /// of an output, otherwise the change may be silently redirected.
+    fn check_change_path(&self, message: &AnnotatedTransaction) -> Result<(), LedgerKeyStoreError> {
+        let change_path = message
+            .change_path()
+            .into_iter()
+            .map(|child_num| {
+                let raw_child_num: u32 = child_num.unpack();
+                ChildNumber::from(raw_child_num)
+            })
+            .collect::<Vec<_>>();
+        let change_public_key = self.master.extended_privkey(&change_path)?.public_key()?;
+        let change_lock = Script::from(&AddressPayload::from_pubkey(&change_public_key));
+        if message
+            .raw()
+            .outputs()
+            .into_iter()
+            .any(|output| output.lock().as_slice() == change_lock.as_slice())
+        {
+            Ok(())
+        } else {
+            Err(LedgerKeyStoreError::ChangePathMismatch(change_path.into()))
+        }
+    }
 
-    fn public_key(&self) -> Result<secp256k1::PublicKey, Self::Err> {
-        let mut data = Vec::new();
-        data.write_u8(self.path.as_ref().len() as u8)
-            .expect(WRITE_ERR_MSG);
+    /// Sign a 32 bytes hash directly (without parsing a transaction), the
+    /// device shows the hash for the user to confirm.
+    pub fn sign_hash(&self, message: &H256) -> Result<RecoverableSignature, LedgerKeyStoreError> {
+        self.master.check_app_version()?;
+        let raw_path = self.raw_path();
+        self.master.ledger_app.exchange(ApduCommand {
+            cla: 0x80,
+            ins: 0x03,
+            p1: SignP1::FIRST.bits,
+            p2: 0,
+            length: raw_path.len() as u8,
+            data: raw_path,
+        })?;
+        self.master.report(SignProgress::AwaitingConfirmation);
+        let response = self.master.ledger_app.exchange(ApduCommand {
+            cla: 0x80,
+            ins: 0x03,
+            p1: (SignP1::HASH_ONLY_NEXT | SignP1::LAST_MARKER).bits,
+            p2: 0,
+            length: message.as_bytes().len() as u8,
+            data: message.as_bytes().to_vec(),
+        });
+        self.master.report(SignProgress::Finished);
+        let response = response?;
+        debug!(
+            "Received Nervos CKB Ledger hash signature of {:02x?} for path {:?}",
+            response.data, &self.path
+        );
+        parse::recoverable_signature(&response.data)
+    }
+
+    fn raw_path(&self) -> Vec<u8> {
+        // The depth is limited by `MAX_PATH_DEPTH` in `extended_privkey`
+        let mut data = vec![self.path.as_ref().len() as u8];
         for &child_num in self.path.as_ref().iter() {
-            data.write_u32::<BigEndian>(From::from(child_num))
-                .expect(WRITE_ERR_MSG);
+            data.extend_from_slice(&u32::from(child_num).to_be_bytes());
         }
-        let command = apdu::extend_public_key(data);
+        data
+    }
+
+    fn exchange_public_key(
+        &self,
+        command: ApduCommand,
+    ) -> Result<secp256k1::PublicKey, LedgerKeyStoreError> {
         let response = self.master.ledger_app.exchange(command)?;
         debug!(
             "Nervos CBK Ledger app extended pub key raw public key {:02x?} for path {:?}",
@@ -201,102 +863,174 @@ impl AbstractPrivKey for LedgerCap {
         parse::assert_nothing_left(resp)?;
         Ok(PublicKey::from_slice(&raw_public_key)?)
     }
+}
+
+impl AbstractPrivKey for LedgerCap {
+    type Err = LedgerKeyStoreError;
 
-    fn sign(&self, _message: &H256) -> Result<Signature, Self::Err> {
-        unimplemented!("Need to generalize method to not take hash")
-        //let signature = self.sign_recoverable(message)?;
-        //Ok(RecoverableSignature::to_standard(&signature))
+    type SignerSingleShot = LedgerSigner;
+
+    fn public_key(&self) -> Result<secp256k1::PublicKey, Self::Err> {
+        if let Some(public_key) = self.master.cached_public_key(self.path.as_ref()) {
+            return public_key;
+        }
+        self.exchange_public_key(apdu::extend_public_key(self.raw_path()))
+    }
+
+    fn sign(&self, message: &H256) -> Result<Signature, Self::Err> {
+        let signature = self.sign_hash(message)?;
+        Ok(RecoverableSignature::to_standard(&signature))
     }
 
     fn begin_sign_recoverable(&self) -> Self::SignerSingleShot {
-        let my_self = self.clone();
+        LedgerSigner {
+            cap: self.clone(),
+            buffer: Vec::new(),
+            digest: false,
+        }
+    }
+}
 
-        SignEntireHelper::new(Box::new(move |message: Vec<u8>| {
-            debug!(
-                "Sending Nervos CKB Ledger app message of {:02x?} with length {:?}",
-                message,
-                message.len()
-            );
+/// The signer of a ledger path, the message is an `AnnotatedTransaction` the
+/// app parses and shows, or a 32 bytes digest when `sign_digest` was called
+pub struct LedgerSigner {
+    cap: LedgerCap,
+    buffer: Vec<u8>,
+    digest: bool,
+}
 
-            // Need to fill in missing “path” from signer.
-            let mut raw_path = Vec::<Uint32>::new();
-            for &child_num in my_self.path.as_ref().iter() {
-                let raw_child_num: u32 = child_num.into();
-                let raw_path_bytes = raw_child_num.to_le_bytes();
-                raw_path.push(
-                    Uint32::new_builder()
-                        .nth0(raw_path_bytes[0].into())
-                        .nth1(raw_path_bytes[1].into())
-                        .nth2(raw_path_bytes[2].into())
-                        .nth3(raw_path_bytes[3].into())
-                        .build(),
-                )
-            }
+impl SignerSingleShot for LedgerSigner {
+    type Err = LedgerKeyStoreError;
 
-            let message_with_sign_path = AnnotatedTransaction::from_slice(&message).unwrap();
-            let sign_path = Bip32::new_builder().set(raw_path).build();
-            let change_path = if message_with_sign_path.change_path().len() == 0 {
-                sign_path.clone()
-            } else {
-                message_with_sign_path.change_path()
-            };
+    fn append(&mut self, message_fragment: &[u8]) {
+        self.buffer.extend_from_slice(message_fragment)
+    }
 
-            let raw_message = message_with_sign_path
-                .as_builder()
-                .sign_path(sign_path)
-                .change_path(change_path)
-                .build();
+    fn sign_digest(&mut self) -> bool {
+        self.digest = true;
+        true
+    }
 
-            debug!(
-                "Modified Nervos CKB Ledger app message of {:02x?} with length {:?}",
-                raw_message.as_slice(),
-                raw_message.as_slice().len()
-            );
+    fn finalize(self: Box<Self>) -> Result<RecoverableSignature, Self::Err> {
+        let LedgerSigner {
+            cap,
+            buffer,
+            digest,
+        } = *self;
+        if digest {
+            let mut message = &buffer[..];
+            let digest = parse::split_off_h256(&mut message)
+                .map_err(|err| LedgerKeyStoreError::InvalidSignMessage(err.to_string()))?;
+            parse::assert_nothing_left(message)
+                .map_err(|err| LedgerKeyStoreError::InvalidSignMessage(err.to_string()))?;
+            cap.sign_hash(&digest)
+        } else {
+            cap.sign_transaction(buffer)
+        }
+    }
+}
 
-            let chunk = |mut message: &[u8]| -> Result<_, Self::Err> {
-                assert!(message.len() > 0, "initial message must be non-empty");
-                let mut base = SignP1::FIRST;
-                loop {
-                    let length = ::std::cmp::min(message.len(), MAX_APDU_SIZE);
-                    let chunk = parse::split_off_at(&mut message, length)?;
-                    let rest_length = message.len();
-                    let response = my_self.master.ledger_app.exchange(ApduCommand {
-                        cla: 0x80,
-                        ins: 0x03,
-                        p1: (if rest_length > 0 {
-                            base
-                        } else {
-                            base | SignP1::LAST_MARKER
-                        })
-                        .bits,
-                        p2: 0,
-                        length: chunk.len() as u8,
-                        data: chunk.to_vec(),
-                    })?;
-                    if rest_length == 0 {
-                        return Ok(response);
-                    }
-                    base = SignP1::NEXT;
-                }
-            };
+impl LedgerCap {
+    /// Sign an `AnnotatedTransaction` message, the device parses the
+    /// transaction and shows its outputs for the user to confirm.
+    fn sign_transaction(
+        &self,
+        message: Vec<u8>,
+    ) -> Result<RecoverableSignature, LedgerKeyStoreError> {
+        debug!(
+            "Sending Nervos CKB Ledger app message of {:02x?} with length {:?}",
+            message,
+            message.len()
+        );
 
-            let response = chunk(raw_message.as_slice().as_ref())?;
+        self.master.check_app_version()?;
 
-            debug!(
-                "Received Nervos CKB Ledger result of {:02x?} with length {:?}",
-                response.data,
-                response.data.len()
-            );
+        // Need to fill in missing “path” from signer.
+        let mut raw_path = Vec::<Uint32>::new();
+        for &child_num in self.path.as_ref().iter() {
+            let raw_child_num: u32 = child_num.into();
+            let raw_path_bytes = raw_child_num.to_le_bytes();
+            raw_path.push(
+                Uint32::new_builder()
+                    .nth0(raw_path_bytes[0].into())
+                    .nth1(raw_path_bytes[1].into())
+                    .nth2(raw_path_bytes[2].into())
+                    .nth3(raw_path_bytes[3].into())
+                    .build(),
+            )
+        }
 
-            let raw_signature = response.data.clone();
-            let mut resp = &raw_signature[..];
+        let message_with_sign_path = parse::annotated_transaction(&message)?;
+        let sign_path = Bip32::new_builder().set(raw_path).build();
+        let change_path = if message_with_sign_path.change_path().len() == 0 {
+            sign_path.clone()
+        } else {
+            self.check_change_path(&message_with_sign_path)?;
+            message_with_sign_path.change_path()
+        };
 
-            let data = parse::split_off_at(&mut resp, 64)?;
-            let recovery_id = RecoveryId::from_i32(parse::split_first(&mut resp)? as i32)?;
-            debug!("Recovery id is {:?}", recovery_id);
-            parse::assert_nothing_left(resp)?;
+        let raw_message = message_with_sign_path
+            .as_builder()
+            .sign_path(sign_path)
+            .change_path(change_path)
+            .build();
+
+        debug!(
+            "Modified Nervos CKB Ledger app message of {:02x?} with length {:?}",
+            raw_message.as_slice(),
+            raw_message.as_slice().len()
+        );
+
+        let chunk = |mut message: &[u8]| -> Result<_, LedgerKeyStoreError> {
+            if message.is_empty() {
+                return Err(LedgerKeyStoreError::InvalidSignMessage(
+                    "empty message".to_string(),
+                ));
+            }
+            let total = (message.len() + MAX_APDU_SIZE - 1) / MAX_APDU_SIZE;
+            self.master.report(SignProgress::Sending { sent: 0, total });
+            let mut base = SignP1::FIRST;
+            let mut sent = 0;
+            loop {
+                let length = ::std::cmp::min(message.len(), MAX_APDU_SIZE);
+                let chunk = parse::split_off_at(&mut message, length)?;
+                let rest_length = message.len();
+                // The device asks the user after receiving the last chunk
+                if rest_length == 0 {
+                    self.master.report(SignProgress::AwaitingConfirmation);
+                }
+                let response = self.master.ledger_app.exchange(ApduCommand {
+                    cla: 0x80,
+                    ins: 0x03,
+                    p1: (if rest_length > 0 {
+                        base
+                    } else {
+                        base | SignP1::LAST_MARKER
+                    })
+                    .bits,
+                    p2: 0,
+                    length: chunk.len() as u8,
+                    data: chunk.to_vec(),
+                });
+                if rest_length == 0 {
+                    self.master.report(SignProgress::Finished);
+                    return response;
+                }
+                response?;
+                sent += 1;
+                self.master.report(SignProgress::Sending { sent, total });
+                base = SignP1::NEXT;
+            }
+        };
+
+        let response = chunk(raw_message.as_slice().as_ref())?;
+
+        debug!(
+            "Received Nervos CKB Ledger result of {:02x?} with length {:?}",
+            response.data,
+            response.data.len()
+        );
 
-            Ok(RecoverableSignature::from_compact(data, recovery_id)?)
-        }))
+        parse::recoverable_signature(&response.data)
     }
 }
@@ -1,3 +1,7 @@
+use ckb_types::{packed::AnnotatedTransaction, prelude::*, H256};
+use log::debug;
+use secp256k1::recovery::{RecoverableSignature, RecoveryId};
+
 pub use super::error::Error;
 
 pub fn split_off_at<'inner, 'outer>(
@@ -27,6 +31,16 @@ pub fn split_first<'inner, 'outer>(response: &'outer mut &'inner [u8]) -> Result
     })
 }
 
+/// A 32 bytes hash (e.g. the wallet id) from the response, or a digest given
+/// to the signer
+pub fn split_off_h256<'inner, 'outer>(response: &'outer mut &'inner [u8]) -> Result<H256, Error> {
+    let raw = split_off_at(response, 32)?;
+    H256::from_slice(raw).map_err(|_| Error::RestOfResponseTooShort {
+        expected: 32,
+        tail: raw.to_vec(),
+    })
+}
+
 pub fn assert_nothing_left(response: &[u8]) -> Result<(), Error> {
     if response.len() != 0 {
         Err(Error::TrailingExtraReponse {
@@ -36,3 +50,21 @@ pub fn assert_nothing_left(response: &[u8]) -> Result<(), Error> {
         Ok(())
     }
 }
+
+/// The signature responded by the device: 64 bytes compact signature and 1
+/// byte recovery id
+pub fn recoverable_signature(raw_signature: &[u8]) -> Result<RecoverableSignature, Error> {
+    let mut resp = raw_signature;
+    let data = split_off_at(&mut resp, 64)?;
+    let recovery_id = RecoveryId::from_i32(split_first(&mut resp)? as i32)?;
+    debug!("Recovery id is {:?}", recovery_id);
+    assert_nothing_left(resp)?;
+    Ok(RecoverableSignature::from_compact(data, recovery_id)?)
+}
+
+/// The message given to the signer, it is built from the transaction file of
+/// the user so it is verified instead of trusted
+pub fn annotated_transaction(message: &[u8]) -> Result<AnnotatedTransaction, Error> {
+    AnnotatedTransaction::from_slice(message)
+        .map_err(|err| Error::InvalidSignMessage(err.to_string()))
+}
@@ -0,0 +1,30 @@
+use std::fmt;
+use std::sync::Arc;
+
+/// The progress of a signing request to the device
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignProgress {
+    /// `sent` of the `total` chunks of the message are sent to the device
+    Sending { sent: usize, total: usize },
+    /// The whole message is sent, the device waits for the user to confirm it
+    AwaitingConfirmation,
+    /// The device responded (signed or rejected)
+    Finished,
+}
+
+impl fmt::Display for SignProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SignProgress::Sending { sent, total } => {
+                write!(f, "Sending to the Ledger device: {}/{} chunks", sent, total)
+            }
+            SignProgress::AwaitingConfirmation => {
+                write!(f, "Confirm the signing request on the Ledger device")
+            }
+            SignProgress::Finished => write!(f, "The Ledger device responded"),
+        }
+    }
+}
+
+/// Called on every progress of signing, from the signing thread
+pub type SignProgressFn = Arc<dyn Fn(SignProgress) + Send + Sync>;
@@ -0,0 +1,175 @@
+#[cfg(test)]
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Mutex;
+
+use ledger::LedgerApp as RawLedgerApp;
+use ledger::{ApduAnswer, ApduCommand};
+
+#[cfg(test)]
+use super::error::SW_OK;
+use super::LedgerKeyStoreError;
+
+/// The channel APDU commands are exchanged with the Nervos app through.
+pub trait LedgerTransport {
+    fn exchange(&self, command: ApduCommand) -> Result<ApduAnswer, LedgerKeyStoreError>;
+}
+
+/// A real device connected over USB HID.
+impl LedgerTransport for RawLedgerApp {
+    fn exchange(&self, command: ApduCommand) -> Result<ApduAnswer, LedgerKeyStoreError> {
+        check_status_word(RawLedgerApp::exchange(self, command)?)
+    }
+}
+
+// The failed command is an error by its status word
+fn check_status_word(answer: ApduAnswer) -> Result<ApduAnswer, LedgerKeyStoreError> {
+    match LedgerKeyStoreError::from_status_word(answer.retcode) {
+        Some(err) => Err(err),
+        None => Ok(answer),
+    }
+}
+
+/// The APDU socket of the Speculos emulator: the command is prefixed by its
+/// length (4 bytes big endian), the response is the length of data (4 bytes
+/// big endian), the data, then the status word (2 bytes).
+pub struct SpeculosTransport {
+    stream: Mutex<TcpStream>,
+}
+
+impl SpeculosTransport {
+    pub fn connect(addr: SocketAddr) -> Result<SpeculosTransport, LedgerKeyStoreError> {
+        let stream = TcpStream::connect(addr).map_err(|err| {
+            LedgerKeyStoreError::EmulatorError(format!("connect to {} failed: {}", addr, err))
+        })?;
+        Ok(SpeculosTransport {
+            stream: Mutex::new(stream),
+        })
+    }
+}
+
+impl LedgerTransport for SpeculosTransport {
+    fn exchange(&self, command: ApduCommand) -> Result<ApduAnswer, LedgerKeyStoreError> {
+        let io_error = |err: std::io::Error| LedgerKeyStoreError::EmulatorError(err.to_string());
+        let raw = raw_command(&command);
+        let mut stream = self.stream.lock().map_err(|_| {
+            LedgerKeyStoreError::EmulatorError("emulator connection poisoned".to_string())
+        })?;
+        stream
+            .write_all(&(raw.len() as u32).to_be_bytes())
+            .and_then(|_| stream.write_all(&raw))
+            .map_err(io_error)?;
+
+        let mut length = [0u8; 4];
+        stream.read_exact(&mut length).map_err(io_error)?;
+        let mut data = vec![0u8; u32::from_be_bytes(length) as usize];
+        stream.read_exact(&mut data).map_err(io_error)?;
+        let mut status = [0u8; 2];
+        stream.read_exact(&mut status).map_err(io_error)?;
+        let retcode = u16::from_be_bytes(status);
+        check_status_word(ApduAnswer { data, retcode })
+    }
+}
+
+/// Replays canned responses, checking every command against the script, so
+/// the APDU flow can be tested without a device.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MockTransport {
+    script: Mutex<VecDeque<(Vec<u8>, ApduAnswer)>>,
+}
+
+#[cfg(test)]
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        MockTransport::default()
+    }
+
+    /// Expect `command` as the next exchange and respond with `data`
+    pub fn expect(self, command: ApduCommand, data: Vec<u8>) -> MockTransport {
+        self.respond(command, data, SW_OK)
+    }
+
+    /// Expect `command` as the next exchange and fail it with `status_word`
+    pub fn expect_failure(self, command: ApduCommand, status_word: u16) -> MockTransport {
+        self.respond(command, Vec::new(), status_word)
+    }
+
+    fn respond(self, command: ApduCommand, data: Vec<u8>, retcode: u16) -> MockTransport {
+        self.script
+            .lock()
+            .unwrap()
+            .push_back((raw_command(&command), ApduAnswer { data, retcode }));
+        self
+    }
+
+    /// The number of scripted exchanges not happened yet
+    pub fn remaining(&self) -> usize {
+        self.script.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+impl LedgerTransport for MockTransport {
+    fn exchange(&self, command: ApduCommand) -> Result<ApduAnswer, LedgerKeyStoreError> {
+        let (expected, answer) =
+            self.script.lock().unwrap().pop_front().unwrap_or_else(|| {
+                panic!("Unexpected APDU command: {:02x?}", raw_command(&command))
+            });
+        assert_eq!(
+            raw_command(&command),
+            expected,
+            "APDU command mismatch (left: actual, right: expected)"
+        );
+        check_status_word(answer)
+    }
+}
+
+// The command as sent over the wire: header followed by data
+fn raw_command(command: &ApduCommand) -> Vec<u8> {
+    let mut raw = vec![
+        command.cla,
+        command.ins,
+        command.p1,
+        command.p2,
+        command.length,
+    ];
+    raw.extend_from_slice(&command.data);
+    raw
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    fn test_speculos_transport() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let emulator = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            for response in &[&[0x01, 0x02, 0x90, 0x00][..], &[0x69, 0x85][..]] {
+                let mut length = [0u8; 4];
+                stream.read_exact(&mut length).unwrap();
+                let mut command = vec![0u8; u32::from_be_bytes(length) as usize];
+                stream.read_exact(&mut command).unwrap();
+                assert_eq!(command, vec![0x80, 0x01, 0x00, 0x00, 0x00]);
+                let data_length = (response.len() - 2) as u32;
+                stream.write_all(&data_length.to_be_bytes()).unwrap();
+                stream.write_all(response).unwrap();
+            }
+        });
+
+        let transport = SpeculosTransport::connect(addr).unwrap();
+        let answer = transport.exchange(crate::apdu::get_wallet_id()).unwrap();
+        assert_eq!(answer.data, vec![0x01, 0x02]);
+        match transport.exchange(crate::apdu::get_wallet_id()) {
+            Err(LedgerKeyStoreError::UserRejected) => {}
+            other => panic!("unexpected: {:?}", other.map(|answer| answer.data)),
+        }
+        emulator.join().unwrap();
+    }
+}
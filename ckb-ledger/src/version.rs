@@ -0,0 +1,97 @@
+use std::fmt;
+
+use super::{parse, LedgerKeyStoreError};
+
+/// The oldest Nervos app version accepted for signing by default
+pub const DEFAULT_MIN_APP_VERSION: AppVersion = AppVersion(0, 5, 0);
+
+/// `major.minor.patch` of an app
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AppVersion(pub u8, pub u8, pub u8);
+
+impl AppVersion {
+    /// Parse `major.minor.patch`, a pre-release suffix (eg: `-rc1`) is ignored
+    pub fn parse(input: &str) -> Result<AppVersion, String> {
+        let invalid = || {
+            format!(
+                "Invalid app version: {:?}, expected major.minor.patch",
+                input
+            )
+        };
+        let numbers = input
+            .split('-')
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse::<u8>().map_err(|_| invalid()))
+            .collect::<Result<Vec<_>, String>>()?;
+        match numbers.as_slice() {
+            [major, minor, patch] => Ok(AppVersion(*major, *minor, *patch)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for AppVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.0, self.1, self.2)
+    }
+}
+
+/// The app running on the device, reported by `apdu::get_app_version`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LedgerAppInfo {
+    pub name: String,
+    pub version: AppVersion,
+}
+
+impl LedgerAppInfo {
+    /// Parse the response: format (1 byte), the name and the version string
+    /// (both prefixed by the length in 1 byte), then the flags.
+    pub fn from_response(data: &[u8]) -> Result<LedgerAppInfo, LedgerKeyStoreError> {
+        let mut resp = data;
+        let _format = parse::split_first(&mut resp)?;
+        let name_len = parse::split_first(&mut resp)? as usize;
+        let name = String::from_utf8_lossy(parse::split_off_at(&mut resp, name_len)?).to_string();
+        let version_len = parse::split_first(&mut resp)? as usize;
+        let version = String::from_utf8_lossy(parse::split_off_at(&mut resp, version_len)?);
+        let version =
+            AppVersion::parse(&version).map_err(LedgerKeyStoreError::InvalidAppVersion)?;
+        Ok(LedgerAppInfo { name, version })
+    }
+}
+
+impl fmt::Display for LedgerAppInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.name, self.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_app_version() {
+        assert_eq!(AppVersion::parse("0.5.2"), Ok(AppVersion(0, 5, 2)));
+        assert_eq!(AppVersion::parse("1.0.0-rc1"), Ok(AppVersion(1, 0, 0)));
+        assert!(AppVersion::parse("1.0").is_err());
+        assert!(AppVersion::parse("1.x.0").is_err());
+        assert!(AppVersion(0, 4, 9) < DEFAULT_MIN_APP_VERSION);
+        assert!(AppVersion(0, 10, 0) > DEFAULT_MIN_APP_VERSION);
+
+        let mut data = vec![0x01, 6];
+        data.extend_from_slice(b"Nervos");
+        data.push(5);
+        data.extend_from_slice(b"0.5.2");
+        data.extend_from_slice(&[1, 0x02]);
+        assert_eq!(
+            LedgerAppInfo::from_response(&data).unwrap(),
+            LedgerAppInfo {
+                name: "Nervos".to_string(),
+                version: AppVersion(0, 5, 2),
+            }
+        );
+        assert!(LedgerAppInfo::from_response(&data[0..5]).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use ckb_sdk::wallet::{
+    write_file_atomic, ChainCode, ChildNumber, DerivationPath, ExtendedPubKey, Fingerprint,
+};
+use ckb_types::H256;
+use faster_hex::{hex_decode, hex_string};
+use log::warn;
+use secp256k1::key::PublicKey;
+
+use super::{LedgerId, LedgerKeyStoreError};
+
+/// The path of the cached extended public key, the receiving and change
+/// addresses are derived from it without the device
+pub const ACCOUNT_PATH: &str = "m/44'/309'/0'";
+
+pub fn account_path() -> DerivationPath {
+    DerivationPath::from_str(ACCOUNT_PATH).unwrap()
+}
+
+/// The account extended public keys of the devices seen before, one
+/// `<wallet-id>.json` file of each device.
+pub struct XpubCache {
+    dir: PathBuf,
+}
+
+impl XpubCache {
+    pub fn new(dir: PathBuf) -> XpubCache {
+        XpubCache { dir }
+    }
+
+    fn file_path(&self, id: &LedgerId) -> PathBuf {
+        self.dir.join(format!("{:x}.json", id.0))
+    }
+
+    /// The wallet ids of the cache files
+    pub fn cached_ids(&self) -> Vec<LedgerId> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        let mut ids = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                    return None;
+                }
+                let stem = path.file_stem()?.to_str()?;
+                H256::from_str(stem).ok().map(LedgerId)
+            })
+            .collect::<Vec<_>>();
+        ids.sort_by(|a, b| a.0.cmp(&b.0));
+        ids
+    }
+
+    /// The cached key of the device, a file recorded for another wallet id or
+    /// path is stale (e.g. renamed or the device was reset), it is removed. An
+    /// unparsable file is a cache miss, it is removed with a warning.
+    pub fn load(&self, id: &LedgerId) -> Result<Option<ExtendedPubKey>, LedgerKeyStoreError> {
+        let path = self.file_path(id);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read(&path).map_err(|err| {
+            LedgerKeyStoreError::XpubCacheError(format!("{}: {}", path.display(), err))
+        })?;
+        match parse(id, &content) {
+            Ok(xpub_opt) => {
+                if xpub_opt.is_none() {
+                    fs::remove_file(&path).map_err(|err| {
+                        LedgerKeyStoreError::XpubCacheError(format!("{}: {}", path.display(), err))
+                    })?;
+                }
+                Ok(xpub_opt)
+            }
+            Err(reason) => {
+                warn!(
+                    "Ignore the invalid xpub cache file {}: {}",
+                    path.display(),
+                    reason
+                );
+                if let Err(err) = fs::remove_file(&path) {
+                    warn!("Remove {} failed: {}", path.display(), err);
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    pub fn store(&self, id: &LedgerId, xpub: &ExtendedPubKey) -> Result<(), LedgerKeyStoreError> {
+        let path = self.file_path(id);
+        let content = serde_json::json!({
+            "wallet_id": format!("{:#x}", id.0),
+            "path": ACCOUNT_PATH,
+            "public_key": format!("0x{}", hex_string(&xpub.public_key.serialize()).unwrap()),
+            "chain_code": format!("0x{}", hex_string(&xpub.chain_code[..]).unwrap()),
+        });
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| {
+                write_file_atomic(
+                    &path,
+                    serde_json::to_string_pretty(&content).unwrap().as_bytes(),
+                )
+            })
+            .map_err(|err| {
+                LedgerKeyStoreError::XpubCacheError(format!("{}: {}", path.display(), err))
+            })
+    }
+
+    /// Remove the cached key of the device, e.g. it mismatches the device
+    pub fn remove(&self, id: &LedgerId) -> Result<(), LedgerKeyStoreError> {
+        let path = self.file_path(id);
+        if !path.exists() {
+            return Ok(());
+        }
+        fs::remove_file(&path).map_err(|err| {
+            LedgerKeyStoreError::XpubCacheError(format!("{}: {}", path.display(), err))
+        })
+    }
+}
+
+// The key of a cache file, `None` when it is recorded for another wallet id or
+// path
+fn parse(id: &LedgerId, content: &[u8]) -> Result<Option<ExtendedPubKey>, String> {
+    let value: serde_json::Value =
+        serde_json::from_slice(content).map_err(|err| err.to_string())?;
+    let field = |name: &str| {
+        value[name]
+            .as_str()
+            .map(|value| value.trim_start_matches("0x"))
+            .ok_or_else(|| format!("missing {}", name))
+    };
+    if field("wallet_id")? != format!("{:x}", id.0) || field("path")? != ACCOUNT_PATH {
+        return Ok(None);
+    }
+    let public_key = from_hex(field("public_key")?)
+        .and_then(|bytes| PublicKey::from_slice(&bytes).ok())
+        .ok_or_else(|| "invalid public_key".to_string())?;
+    let chain_code = from_hex(field("chain_code")?)
+        .filter(|bytes| bytes.len() == 32)
+        .ok_or_else(|| "invalid chain_code".to_string())?;
+    Ok(Some(ExtendedPubKey {
+        depth: account_path().as_ref().len() as u8,
+        parent_fingerprint: Fingerprint::default(),
+        child_number: ChildNumber::Hardened { index: 0 },
+        public_key,
+        chain_code: ChainCode::from(&chain_code[..]),
+    }))
+}
+
+fn from_hex(input: &str) -> Option<Vec<u8>> {
+    let mut bytes = vec![0u8; input.len() / 2];
+    hex_decode(input.as_bytes(), &mut bytes).ok()?;
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_sdk::wallet::ExtendedPrivKey;
+    use ckb_sdk::SECP256K1;
+
+    #[test]
+    fn test_store_and_load() {
+        let dir =
+            std::env::temp_dir().join(format!("ckb-ledger-xpub-cache-{}", std::process::id()));
+        let cache = XpubCache::new(dir.clone());
+        let id = LedgerId(H256::from_slice(&[0x42; 32]).unwrap());
+        let master = ExtendedPrivKey::new_master(&[0x01; 32]).unwrap();
+        let account = master.derive_priv(&SECP256K1, &account_path()).unwrap();
+        let xpub = ExtendedPubKey::from_private(&SECP256K1, &account);
+
+        assert_eq!(cache.load(&id).unwrap(), None);
+        cache.store(&id, &xpub).unwrap();
+        assert_eq!(cache.cached_ids(), vec![id.clone()]);
+        let loaded = cache.load(&id).unwrap().unwrap();
+        assert_eq!(loaded.public_key, xpub.public_key);
+        assert_eq!(loaded.chain_code, xpub.chain_code);
+
+        // The file of another wallet id is stale
+        let other_id = LedgerId(H256::from_slice(&[0x24; 32]).unwrap());
+        fs::rename(cache.file_path(&id), cache.file_path(&other_id)).unwrap();
+        assert_eq!(cache.load(&other_id).unwrap(), None);
+        assert!(cache.cached_ids().is_empty());
+
+        // An unparsable file is a cache miss, it is removed
+        fs::write(cache.file_path(&id), "{\"wallet_id\":").unwrap();
+        assert_eq!(cache.load(&id).unwrap(), None);
+        assert!(cache.cached_ids().is_empty());
+
+        cache.store(&id, &xpub).unwrap();
+        cache.remove(&id).unwrap();
+        assert_eq!(cache.load(&id).unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,26 @@
+//! Derive the first receiving and change addresses (BIP-44 account
+//! `m/44'/309'/0'`) of a master key, the same as `ckb-cli account
+//! bip44-addresses`:
+//!
+//! ```text
+//! cargo run -p ckb-sdk --example derive_addresses
+//! ```
+
+use std::str::FromStr;
+
+use ckb_sdk::wallet::{AbstractMasterPrivKey, DerivationPath, KeyChain, MasterPrivKey};
+use ckb_sdk::{Address, AddressPayload, NetworkType};
+
+fn main() {
+    // A fixed seed for the example, use the seed of a BIP-39 mnemonic instead
+    let master = MasterPrivKey::from_seed(&[0x01; 32]).expect("valid seed");
+    for key_chain in &[KeyChain::External, KeyChain::Change] {
+        for index in 0..3 {
+            let path = format!("m/44'/309'/0'/{}/{}", *key_chain as u8, index);
+            let path = DerivationPath::from_str(&path).unwrap();
+            let xpub = master.extended_pubkey(path.as_ref()).unwrap();
+            let payload = AddressPayload::from_pubkey(&xpub.public_key);
+            println!("{} {}", path, Address::new(NetworkType::Testnet, payload));
+        }
+    }
+}
@@ -0,0 +1,39 @@
+//! Sign a message by the `AbstractPrivKey` signer interface (implemented by
+//! software keys and ledger devices), then recover the public key from the
+//! signature:
+//!
+//! ```text
+//! cargo run -p ckb-sdk --example sign_message -- "hello"
+//! ```
+
+use std::env;
+use std::str::FromStr;
+
+use ckb_hash::blake2b_256;
+use ckb_sdk::wallet::{AbstractMasterPrivKey, AbstractPrivKey, DerivationPath, MasterPrivKey};
+use ckb_sdk::{SignerSingleShot, SECP256K1};
+
+fn main() {
+    let message = env::args().nth(1).unwrap_or_else(|| "hello".to_string());
+    let master = MasterPrivKey::from_seed(&[0x01; 32]).expect("valid seed");
+    let path = DerivationPath::from_str("m/44'/309'/0'/0/0").unwrap();
+    let privkey = master.extended_privkey(path.as_ref()).unwrap();
+
+    // The message is hashed by blake2b (ckb-default-hash) before signing
+    let mut signer = privkey.begin_sign_recoverable();
+    signer.append(message.as_bytes());
+    let signature = Box::new(signer).finalize().unwrap();
+
+    let digest = secp256k1::Message::from_slice(&blake2b_256(message.as_bytes())).unwrap();
+    let recovered = SECP256K1.recover(&digest, &signature).unwrap();
+    assert_eq!(recovered, privkey.public_key().unwrap());
+    let (recovery_id, compact) = signature.serialize_compact();
+    println!(
+        "signature: 0x{}{:02x}",
+        compact
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<String>(),
+        recovery_id.to_i32()
+    );
+}
@@ -0,0 +1,19 @@
+//! Print the tip header of a node by `HttpRpcClient`:
+//!
+//! ```text
+//! cargo run -p ckb-sdk --example tip_header -- http://127.0.0.1:8114
+//! ```
+
+use std::env;
+
+use ckb_sdk::HttpRpcClient;
+
+fn main() -> Result<(), String> {
+    let url = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "http://127.0.0.1:8114".to_string());
+    let mut rpc_client = HttpRpcClient::new(url);
+    let header = rpc_client.get_tip_header()?;
+    println!("tip: #{} {:#x}", header.inner.number, header.hash);
+    Ok(())
+}
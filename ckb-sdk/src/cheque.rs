@@ -0,0 +1,109 @@
+use ckb_types::{
+    bytes::Bytes,
+    core::{DepType, ScriptHashType},
+    packed::{Byte32, CellDep, OutPoint, Script},
+    prelude::*,
+    H160, H256,
+};
+
+use crate::types::{Since, SinceType};
+
+// receiver lock hash[0..20] + sender lock hash[0..20]
+pub const CHEQUE_ARGS_SIZE: usize = 40;
+// The sender can withdraw the cheque after 6 epochs since it is committed
+pub const CHEQUE_WITHDRAW_EPOCHS: u64 = 6;
+
+/// The deployment of cheque lock, it is not in the genesis block so the type
+/// hash and the cell dep must be given
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChequeConfig {
+    type_hash: H256,
+    cell_dep: CellDep,
+}
+
+impl ChequeConfig {
+    pub fn new(type_hash: H256, out_point: OutPoint) -> ChequeConfig {
+        let cell_dep = CellDep::new_builder()
+            .out_point(out_point)
+            .dep_type(DepType::Code.into())
+            .build();
+        ChequeConfig {
+            type_hash,
+            cell_dep,
+        }
+    }
+
+    pub fn type_hash(&self) -> &H256 {
+        &self.type_hash
+    }
+    pub fn cell_dep(&self) -> &CellDep {
+        &self.cell_dep
+    }
+
+    pub fn is_cheque(&self, lock: &Script) -> bool {
+        lock.code_hash() == self.type_hash.pack() && lock.hash_type() == ScriptHashType::Type.into()
+    }
+
+    /// The cheque lock script from the sender to the receiver
+    pub fn lock_script(&self, receiver_lock_hash: &Byte32, sender_lock_hash: &Byte32) -> Script {
+        let mut args = Vec::with_capacity(CHEQUE_ARGS_SIZE);
+        args.extend_from_slice(&receiver_lock_hash.as_slice()[0..20]);
+        args.extend_from_slice(&sender_lock_hash.as_slice()[0..20]);
+        Script::new_builder()
+            .code_hash(self.type_hash.pack())
+            .hash_type(ScriptHashType::Type.into())
+            .args(Bytes::from(args).pack())
+            .build()
+    }
+}
+
+/// Parse the cheque lock args, return the lock hash prefixes of the receiver
+/// and the sender
+pub fn parse_cheque_args(args: &[u8]) -> Result<(H160, H160), String> {
+    if args.len() != CHEQUE_ARGS_SIZE {
+        return Err(format!("Invalid cheque args length: {}", args.len()));
+    }
+    Ok((
+        H160::from_slice(&args[0..20]).unwrap(),
+        H160::from_slice(&args[20..40]).unwrap(),
+    ))
+}
+
+/// The lock hash prefix stored in cheque lock args
+pub fn cheque_lock_hash_prefix(lock_hash: &Byte32) -> H160 {
+    H160::from_slice(&lock_hash.as_slice()[0..20]).unwrap()
+}
+
+/// The since of the cheque input withdrawn by the sender, the cheque lock
+/// requires exactly this value (relative 6 epochs without the fraction part)
+pub fn cheque_withdraw_since() -> u64 {
+    Since::new(
+        SinceType::EpochNumberWithFraction,
+        CHEQUE_WITHDRAW_EPOCHS,
+        true,
+    )
+    .value()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_cheque_args() {
+        let config = ChequeConfig::new(H256::default(), OutPoint::default());
+        let receiver = Byte32::from_slice(&[1u8; 32]).unwrap();
+        let sender = Byte32::from_slice(&[2u8; 32]).unwrap();
+        let lock = config.lock_script(&receiver, &sender);
+        assert!(config.is_cheque(&lock));
+        assert_eq!(
+            parse_cheque_args(&lock.args().raw_data()),
+            Ok((
+                cheque_lock_hash_prefix(&receiver),
+                cheque_lock_hash_prefix(&sender)
+            ))
+        );
+        assert!(parse_cheque_args(&[0u8; 20]).is_err());
+        assert_eq!(cheque_withdraw_since(), 0xa000_0000_0000_0006);
+    }
+}
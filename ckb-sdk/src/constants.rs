@@ -34,6 +34,14 @@ pub const MULTISIG_TYPE_HASH: H256 =
     h256!("0x5c5069eb0857efc65e1bca0c07df34c31663b3622fd3876c876320fc9634e2a8");
 pub const DAO_TYPE_HASH: H256 =
     h256!("0x82d76d1b75fe2fd9a27dfbaa65a039221a380d76c926f378d3f81cf3e7e13f2e");
+// The anyone-can-pay lock (RFC 0026), it's deployed separately on each chain
+pub const ACP_TYPE_HASH_MAINNET: H256 =
+    h256!("0xd369597ff47f29fbc0d47d2e3775370d1250b85140c670e4718af712983a2354");
+pub const ACP_TYPE_HASH_TESTNET: H256 =
+    h256!("0x3419a1c09eb2567f6552ee7a8ecffd64155cffe0f1796e6e61ec088d740c1356");
+// The built-in Type ID script, "TYPE_ID" in ascii
+pub const TYPE_ID_CODE_HASH: H256 =
+    h256!("0x00000000000000000000000000000000000000000000000000545950455f4944");
 
 #[cfg(test)]
 mod test {
@@ -1,8 +1,39 @@
+//! Building, signing and sending CKB transactions, used by `ckb-cli`.
+//!
+//! The items re-exported at the crate root and by `wallet` and `rpc` are the
+//! public surface, downstream projects should use them instead of the modules
+//! of `ckb-cli` by path:
+//!
+//! - signers: `wallet::{AbstractKeyStore, AbstractMasterPrivKey,
+//!   AbstractPrivKey}`, `SignerSingleShot` and the helpers `SignEntireHelper`,
+//!   `SignPrehashedHelper`, `SignerFnTrait`, the remote `wallet::RemoteSigner`
+//! - key stores: `wallet::{KeyStore, MasterPrivKey, DerivationPath}`
+//! - RPC: `HttpRpcClient` (`rpc::types` are the JSON types it returns)
+//! - transactions: `TxHelper`, `TransactionBuilder`, `MockTransaction`,
+//!   `Address`, `HumanCapacity`, `GenesisInfo`
+//!
+//! They follow semver: a patch version only adds items, a removed or changed
+//! item (including a new required method of a trait) bumps the minor version
+//! (the major version is 0) and is noted in `CHANGELOG.md`. The examples in
+//! `examples/` are built by `cargo test`, so they always compile against the
+//! current surface:
+//!
+//! ```text
+//! cargo run -p ckb-sdk --example derive_addresses
+//! cargo run -p ckb-sdk --example sign_message -- "hello"
+//! cargo run -p ckb-sdk --example tip_header -- http://127.0.0.1:8114
+//! ```
+
 mod chain;
+mod cheque;
 mod error;
+mod omnilock;
+mod placeholder;
 mod signing;
 mod transaction;
+mod tx_builder;
 mod tx_helper;
+mod type_id;
 mod types;
 
 pub mod constants;
@@ -10,21 +41,36 @@ pub mod rpc;
 pub mod wallet;
 
 pub use chain::{calc_max_mature_number, GenesisInfo};
+pub use cheque::{
+    cheque_lock_hash_prefix, cheque_withdraw_since, parse_cheque_args, ChequeConfig,
+    CHEQUE_WITHDRAW_EPOCHS,
+};
 pub use error::Error;
-pub use rpc::HttpRpcClient;
+pub use omnilock::{
+    omnilock_signing_message, omnilock_witness_lock, parse_omnilock_args, OmniLockConfig,
+    OMNILOCK_AUTH_SECP256K1_BLAKE160, OMNILOCK_WITNESS_LOCK_SIZE,
+};
+pub use placeholder::{register_witness_lock_size, witness_lock_placeholder};
+pub use rpc::{AsyncRpcClient, HttpRpcClient};
 pub use signing::{
-    FullyAbstractSingleShotSigner, SignEntireHelper, SignPrehashedHelper, SignerSingleShot,
+    serialize_signature, FullyAbstractSingleShotSigner, SignEntireHelper, SignPrehashedHelper,
+    SignerSingleShot,
 };
 pub use transaction::{
     MockCellDep, MockInfo, MockInput, MockResourceLoader, MockTransaction, MockTransactionHelper,
     ReprMockCellDep, ReprMockInfo, ReprMockInput, ReprMockTransaction,
 };
+pub use tx_builder::{
+    calculate_fee, child_pays_for_parent_fee, estimate_tx_size, split_change, tx_size_in_block,
+    CellCollector, CollectedCell, FeePolicy, TransactionBuilder, MAX_FEE_ITERATIONS,
+};
 pub use tx_helper::{
     build_signature, BoxedSignerFn, MultisigConfig, SignerClosureHelper, SignerFnTrait, TxHelper,
 };
+pub use type_id::{is_type_id, type_id_args, type_id_script};
 pub use types::{
-    Address, AddressPayload, AddressType, CodeHashIndex, HumanCapacity, NetworkType, OldAddress,
-    OldAddressFormat, Since, SinceType,
+    Address, AddressHashType, AddressPayload, AddressType, CodeHashIndex, HumanCapacity,
+    NetworkType, OldAddress, OldAddressFormat, Since, SinceType,
 };
 
 pub use ckb_crypto::secp::SECP256K1;
@@ -0,0 +1,188 @@
+use ckb_types::{
+    bytes::Bytes,
+    core::{DepType, ScriptHashType},
+    packed::{self, Byte32, CellDep, OutPoint, Script, WitnessArgs},
+    prelude::*,
+    H160, H256,
+};
+
+use crate::constants::SECP_SIGNATURE_SIZE;
+
+// The auth flag of omnilock args: secp256k1 + blake160 (the same as sighash)
+pub const OMNILOCK_AUTH_SECP256K1_BLAKE160: u8 = 0x00;
+// auth(21 bytes) + omnilock flags(1 byte)
+pub const OMNILOCK_ARGS_SIZE: usize = 22;
+// The size of `OmniLockWitnessLock` with only the signature field
+pub const OMNILOCK_WITNESS_LOCK_SIZE: usize = 16 + 4 + SECP_SIGNATURE_SIZE;
+
+/// The deployment of omnilock, it is not in the genesis block so the type hash
+/// and the cell dep must be given
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OmniLockConfig {
+    type_hash: H256,
+    cell_dep: CellDep,
+}
+
+impl OmniLockConfig {
+    pub fn new(type_hash: H256, out_point: OutPoint) -> OmniLockConfig {
+        let cell_dep = CellDep::new_builder()
+            .out_point(out_point)
+            .dep_type(DepType::Code.into())
+            .build();
+        OmniLockConfig {
+            type_hash,
+            cell_dep,
+        }
+    }
+
+    pub fn type_hash(&self) -> &H256 {
+        &self.type_hash
+    }
+    pub fn cell_dep(&self) -> &CellDep {
+        &self.cell_dep
+    }
+
+    pub fn is_omnilock(&self, lock: &Script) -> bool {
+        lock.code_hash() == self.type_hash.pack() && lock.hash_type() == ScriptHashType::Type.into()
+    }
+
+    /// The omnilock script of a secp256k1 key (without any omnilock mode)
+    pub fn lock_script(&self, pubkey_hash: &H160) -> Script {
+        let mut args = vec![OMNILOCK_AUTH_SECP256K1_BLAKE160];
+        args.extend_from_slice(pubkey_hash.as_bytes());
+        args.push(0);
+        Script::new_builder()
+            .code_hash(self.type_hash.pack())
+            .hash_type(ScriptHashType::Type.into())
+            .args(Bytes::from(args).pack())
+            .build()
+    }
+}
+
+/// Parse the omnilock args, return the secp256k1 pubkey hash. Only the
+/// secp256k1 auth without any omnilock mode (administrator, anyone-can-pay,
+/// time lock, supply) is supported.
+pub fn parse_omnilock_args(args: &[u8]) -> Result<H160, String> {
+    if args.len() != OMNILOCK_ARGS_SIZE {
+        return Err(format!("Invalid omnilock args length: {}", args.len()));
+    }
+    if args[0] != OMNILOCK_AUTH_SECP256K1_BLAKE160 {
+        return Err(format!("Unsupported omnilock auth flag: {:#04x}", args[0]));
+    }
+    if args[21] != 0 {
+        return Err(format!("Unsupported omnilock flags: {:#04x}", args[21]));
+    }
+    Ok(H160::from_slice(&args[1..21]).unwrap())
+}
+
+/// Serialize `OmniLockWitnessLock` (molecule table of `signature`,
+/// `omni_identity` and `preimage`) with only the signature field.
+pub fn omnilock_witness_lock(signature: &[u8]) -> Bytes {
+    const HEADER_SIZE: usize = 4 * 4;
+    let signature_size = 4 + signature.len();
+    let total_size = HEADER_SIZE + signature_size;
+    let mut data = Vec::with_capacity(total_size);
+    data.extend_from_slice(&(total_size as u32).to_le_bytes());
+    data.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+    data.extend_from_slice(&(total_size as u32).to_le_bytes());
+    data.extend_from_slice(&(total_size as u32).to_le_bytes());
+    data.extend_from_slice(&(signature.len() as u32).to_le_bytes());
+    data.extend_from_slice(signature);
+    Bytes::from(data)
+}
+
+/// The message (before hashing) to be signed by an omnilock input group, the
+/// witness lock is zero filled with the size of the final witness lock.
+pub fn omnilock_signing_message(
+    tx_hash: &Byte32,
+    input_group_idxs: &[usize],
+    witnesses: &[packed::Bytes],
+) -> Result<Vec<u8>, String> {
+    let witness = |idx: usize| {
+        witnesses
+            .get(idx)
+            .ok_or_else(|| format!("Missing the witness of input(no.{})", idx + 1))
+    };
+    let init_witness_idx = *input_group_idxs
+        .first()
+        .ok_or_else(|| "Empty omnilock input group".to_string())?;
+    let init_witness = if witness(init_witness_idx)?.raw_data().is_empty() {
+        WitnessArgs::default()
+    } else {
+        WitnessArgs::from_slice(witness(init_witness_idx)?.raw_data().as_ref())
+            .map_err(|err| err.to_string())?
+    };
+    let init_witness = init_witness
+        .as_builder()
+        .lock(Some(Bytes::from(vec![0u8; OMNILOCK_WITNESS_LOCK_SIZE])).pack())
+        .build();
+
+    let mut message = Vec::new();
+    message.extend_from_slice(tx_hash.as_slice());
+    message.extend_from_slice(&(init_witness.as_bytes().len() as u64).to_le_bytes());
+    message.extend_from_slice(&init_witness.as_bytes());
+    for idx in input_group_idxs.iter().skip(1).cloned() {
+        let other_witness: &packed::Bytes = witness(idx)?;
+        message.extend_from_slice(&(other_witness.len() as u64).to_le_bytes());
+        message.extend_from_slice(&other_witness.raw_data());
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ckb_types::h160;
+
+    #[test]
+    fn test_omnilock_witness_lock() {
+        let signature = [1u8; SECP_SIGNATURE_SIZE];
+        let data = omnilock_witness_lock(&signature);
+        assert_eq!(data.len(), OMNILOCK_WITNESS_LOCK_SIZE);
+        assert_eq!(&data[0..4], &(85u32).to_le_bytes());
+        assert_eq!(&data[4..8], &(16u32).to_le_bytes());
+        assert_eq!(&data[8..12], &(85u32).to_le_bytes());
+        assert_eq!(&data[12..16], &(85u32).to_le_bytes());
+        assert_eq!(&data[16..20], &(65u32).to_le_bytes());
+        assert_eq!(&data[20..], &signature[..]);
+    }
+
+    #[test]
+    fn test_parse_omnilock_args() {
+        let hash = h160!("0xb39bbc0b3673c7d36450bc14cfcdad2d559c6c64");
+        let config = OmniLockConfig::new(H256::default(), OutPoint::default());
+        let lock = config.lock_script(&hash);
+        assert!(config.is_omnilock(&lock));
+        assert_eq!(parse_omnilock_args(&lock.args().raw_data()), Ok(hash));
+
+        let mut args = lock.args().raw_data().to_vec();
+        args[21] = 0x01;
+        assert!(parse_omnilock_args(&args).is_err());
+        args[0] = 0x01;
+        args[21] = 0;
+        assert!(parse_omnilock_args(&args).is_err());
+        args[0] = 0;
+        args.push(0);
+        assert_eq!(
+            parse_omnilock_args(&args),
+            Err("Invalid omnilock args length: 23".to_string())
+        );
+    }
+
+    #[test]
+    fn test_omnilock_signing_message() {
+        let tx_hash = Byte32::default();
+        let witnesses = vec![Bytes::new().pack(), Bytes::from(vec![1u8; 3]).pack()];
+        let message = omnilock_signing_message(&tx_hash, &[0, 1], &witnesses).unwrap();
+        let init_witness = WitnessArgs::new_builder()
+            .lock(Some(Bytes::from(vec![0u8; OMNILOCK_WITNESS_LOCK_SIZE])).pack())
+            .build();
+        assert_eq!(
+            message.len(),
+            32 + 8 + init_witness.as_bytes().len() + 8 + 3
+        );
+
+        assert!(omnilock_signing_message(&tx_hash, &[], &witnesses).is_err());
+        assert!(omnilock_signing_message(&tx_hash, &[0, 2], &witnesses).is_err());
+    }
+}
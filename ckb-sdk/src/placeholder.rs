@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use ckb_types::{bytes::Bytes, H256};
+use lazy_static::lazy_static;
+
+use crate::constants::{
+    ACP_TYPE_HASH_MAINNET, ACP_TYPE_HASH_TESTNET, MULTISIG_TYPE_HASH, SECP_SIGNATURE_SIZE,
+    SIGHASH_TYPE_HASH,
+};
+use crate::omnilock::OMNILOCK_WITNESS_LOCK_SIZE;
+use crate::tx_helper::MultisigConfig;
+
+lazy_static! {
+    static ref WITNESS_LOCK_SIZES: Mutex<HashMap<H256, usize>> = Mutex::new(HashMap::new());
+}
+
+/// Register the witness lock size of a lock script by its code hash (e.g. a
+/// custom lock of the `witness_placeholders` config), it takes precedence
+/// over the built-in sizes. A size smaller than the built-in one would
+/// undershoot the fee and is rejected, the multisig lock is always sized by
+/// its config.
+pub fn register_witness_lock_size(code_hash: H256, size: usize) -> Result<(), String> {
+    if code_hash == MULTISIG_TYPE_HASH {
+        return Err(
+            "The witness lock size of multisig lock is given by its config, it can not be overridden"
+                .to_string(),
+        );
+    }
+    if let Some(builtin_size) = builtin_witness_lock_size(&code_hash) {
+        if size < builtin_size {
+            return Err(format!(
+                "The witness lock size {} of lock code hash {:#x} is smaller than the built-in size {}",
+                size, code_hash, builtin_size
+            ));
+        }
+    }
+    WITNESS_LOCK_SIZES
+        .lock()
+        .expect("witness lock sizes lock")
+        .insert(code_hash, size);
+    Ok(())
+}
+
+fn builtin_witness_lock_size(code_hash: &H256) -> Option<usize> {
+    if code_hash == &SIGHASH_TYPE_HASH
+        || code_hash == &ACP_TYPE_HASH_MAINNET
+        || code_hash == &ACP_TYPE_HASH_TESTNET
+    {
+        Some(SECP_SIGNATURE_SIZE)
+    } else {
+        None
+    }
+}
+
+/// The zero filled witness lock of an input group, it has the size of the
+/// signed witness lock so the size of the signed transaction (and the fee by
+/// fee rate) can be estimated before signing:
+///
+/// - sighash and anyone-can-pay: a signature
+/// - multisig: the multisig script of `multisig_config` and `threshold`
+///   signatures
+/// - omnilock (`is_omnilock`): the omnilock witness lock of a signature
+///
+/// Return `None` for a lock of unknown witness size, or a multisig lock
+/// without its config.
+pub fn witness_lock_placeholder(
+    code_hash: &H256,
+    multisig_config: Option<&MultisigConfig>,
+    is_omnilock: bool,
+) -> Option<Bytes> {
+    let registered = WITNESS_LOCK_SIZES
+        .lock()
+        .expect("witness lock sizes lock")
+        .get(code_hash)
+        .cloned();
+    if let Some(size) = registered {
+        return Some(Bytes::from(vec![0u8; size]));
+    }
+    if code_hash == &MULTISIG_TYPE_HASH {
+        return multisig_config.map(|config| {
+            let mut lock = config.to_witness_data().to_vec();
+            lock.extend(vec![
+                0u8;
+                usize::from(config.threshold()) * SECP_SIGNATURE_SIZE
+            ]);
+            Bytes::from(lock)
+        });
+    }
+    let size = if is_omnilock {
+        OMNILOCK_WITNESS_LOCK_SIZE
+    } else {
+        builtin_witness_lock_size(code_hash)?
+    };
+    Some(Bytes::from(vec![0u8; size]))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::AddressPayload;
+    use ckb_types::H160;
+
+    #[test]
+    fn test_witness_lock_placeholder() {
+        assert_eq!(
+            witness_lock_placeholder(&SIGHASH_TYPE_HASH, None, false).map(|lock| lock.len()),
+            Some(SECP_SIGNATURE_SIZE)
+        );
+        let members = (0..3u8)
+            .map(|i| AddressPayload::from_pubkey_hash(H160::from([i; 20])))
+            .collect::<Vec<_>>();
+        let config = MultisigConfig::new_with(members, 0, 2).unwrap();
+        // 4-byte header, 3 members and 2 signatures
+        assert_eq!(
+            witness_lock_placeholder(&MULTISIG_TYPE_HASH, Some(&config), false)
+                .map(|lock| lock.len()),
+            Some(4 + 3 * 20 + 2 * SECP_SIGNATURE_SIZE)
+        );
+        assert_eq!(
+            witness_lock_placeholder(&MULTISIG_TYPE_HASH, None, false),
+            None
+        );
+
+        let custom = H256::from([7u8; 32]);
+        assert_eq!(witness_lock_placeholder(&custom, None, false), None);
+        register_witness_lock_size(custom.clone(), 100).unwrap();
+        assert_eq!(
+            witness_lock_placeholder(&custom, None, false).map(|lock| lock.len()),
+            Some(100)
+        );
+
+        // The built-in sizes can only be enlarged
+        assert!(register_witness_lock_size(SIGHASH_TYPE_HASH.clone(), 64).is_err());
+        assert!(register_witness_lock_size(MULTISIG_TYPE_HASH.clone(), 200).is_err());
+        assert_eq!(
+            witness_lock_placeholder(&MULTISIG_TYPE_HASH, Some(&config), false)
+                .map(|lock| lock.len()),
+            Some(4 + 3 * 20 + 2 * SECP_SIGNATURE_SIZE)
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use ckb_jsonrpc_types::BlockNumber;
+use futures::{future, Future};
+use hyper::header::{Authorization, Bearer};
+use jsonrpc_client_core::Transport;
+use serde_json::Value;
+
+use super::{
+    client::{decode_opt, RawRpcClient},
+    journal::JournaledHandle,
+    socks::new_transport_for,
+    types,
+};
+
+pub type RpcFuture<T> = Box<dyn Future<Item = T, Error = String> + Send>;
+
+/// Non-blocking RPC client, every call returns a future instead of waiting for
+/// the response. The requests are driven by the event loop thread of the HTTP
+/// transport, all clones share the same hyper client so the connections are
+/// pooled and kept alive.
+#[derive(Clone)]
+pub struct AsyncRpcClient {
+    url: String,
+    handle: JournaledHandle,
+}
+
+impl AsyncRpcClient {
+    pub fn new(url: &str) -> Result<AsyncRpcClient, String> {
+        let handle = new_transport_for(url)?
+            .handle(url)
+            .map_err(|err| err.to_string())?;
+        let handle = JournaledHandle::new(url, handle);
+        Ok(AsyncRpcClient {
+            url: url.to_owned(),
+            handle,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        self.url.as_str()
+    }
+
+    /// Send `Authorization: Bearer <token>` with every request
+    pub fn set_bearer_token(&mut self, token: &str) {
+        self.handle.inner_mut().set_header(Authorization(Bearer {
+            token: token.to_owned(),
+        }));
+    }
+
+    /// The typed client sharing the connections of this client
+    pub fn raw_client(&self) -> RawRpcClient<JournaledHandle> {
+        RawRpcClient::new(self.handle.clone())
+    }
+
+    /// Send a JSON-RPC request (or batch) without decoding the response
+    pub fn send_raw(&self, request: &Value) -> RpcFuture<Value> {
+        let body = match serde_json::to_vec(request) {
+            Ok(body) => body,
+            Err(err) => return Box::new(future::err(err.to_string())),
+        };
+        Box::new(
+            self.handle
+                .send(body)
+                .map_err(|err| err.to_string())
+                .and_then(|response| {
+                    serde_json::from_slice(&response)
+                        .map_err(|err| format!("Invalid response: {}", err))
+                }),
+        )
+    }
+
+    /// See `HttpRpcClient::call`
+    pub fn call(&self, method: &str, params: Vec<Value>) -> RpcFuture<Value> {
+        let request = serde_json::json!({
+            "id": 0,
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        });
+        let method = method.to_owned();
+        Box::new(self.send_raw(&request).and_then(move |mut response| {
+            if let Some(error) = response.get("error") {
+                return Err(format!("RPC {} error: {}", method, error));
+            }
+            Ok(response["result"].take())
+        }))
+    }
+
+    /// See `HttpRpcClient::batch`
+    pub fn batch(&self, requests: Vec<Value>) -> RpcFuture<Vec<Value>> {
+        if requests.is_empty() {
+            return Box::new(future::err("Empty batch".to_string()));
+        }
+        let batch = match requests
+            .into_iter()
+            .enumerate()
+            .map(|(id, request)| {
+                let method = request
+                    .get("method")
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| format!("Missing method in request #{}: {}", id, request))?;
+                Ok(serde_json::json!({
+                    "id": id,
+                    "jsonrpc": "2.0",
+                    "method": method,
+                    "params": request.get("params").cloned().unwrap_or_else(|| Value::Array(Vec::new())),
+                }))
+            })
+            .collect::<Result<Vec<_>, String>>()
+        {
+            Ok(batch) => batch,
+            Err(err) => return Box::new(future::err(err)),
+        };
+        let batch_len = batch.len();
+        Box::new(
+            self.send_raw(&Value::Array(batch))
+                .and_then(move |response| {
+                    let responses: Vec<Value> = serde_json::from_value(response)
+                        .map_err(|err| format!("Invalid batch response: {}", err))?;
+
+                    // The responses may be in any order
+                    let mut aligned = vec![Value::Null; batch_len];
+                    for response in responses {
+                        let id = response
+                            .get("id")
+                            .and_then(Value::as_u64)
+                            .filter(|id| (*id as usize) < aligned.len())
+                            .ok_or_else(|| format!("Unexpected batch response: {}", response))?;
+                        aligned[id as usize] = response;
+                    }
+                    Ok(aligned)
+                }),
+        )
+    }
+
+    /// Also decodes the blocks of CKB2021, see `BlockView::from_json`
+    pub fn get_block_by_number(&self, number: u64) -> RpcFuture<Option<types::BlockView>> {
+        let params = vec![serde_json::json!(BlockNumber::from(number))];
+        Box::new(
+            self.call("get_block_by_number", params)
+                .and_then(|block| decode_opt(block, types::BlockView::from_json)),
+        )
+    }
+
+    pub fn get_tip_block_number(&self) -> RpcFuture<u64> {
+        Box::new(
+            self.raw_client()
+                .get_tip_block_number()
+                .map(Into::into)
+                .map_err(|err| err.to_string()),
+        )
+    }
+
+    pub fn get_tip_header(&self) -> RpcFuture<types::HeaderView> {
+        Box::new(
+            self.raw_client()
+                .get_tip_header()
+                .map(Into::into)
+                .map_err(|err| err.to_string()),
+        )
+    }
+}
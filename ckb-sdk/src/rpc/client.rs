@@ -1,13 +1,18 @@
+use std::fmt;
+
 use ckb_jsonrpc_types::{
     BannedAddr, Block, BlockNumber, BlockReward, BlockTemplate, BlockView, CellOutputWithOutPoint,
     CellTransaction, CellWithStatus, ChainInfo, EpochNumber, EpochView, HeaderView, LiveCell,
     LockHashIndexState, Node, OutPoint, PeerState, Timestamp, Transaction, TransactionWithStatus,
     TxPoolInfo, Uint64, Version,
 };
+use futures::Future;
 use jsonrpc_client_core::{expand_params, jsonrpc_client};
-use jsonrpc_client_http::{HttpHandle, HttpTransport};
+use serde_json::Value;
 
-use super::types;
+use super::{
+    async_client::AsyncRpcClient, journal::JournaledHandle, socks::new_transport_for, types,
+};
 use ckb_types::{packed, H256};
 
 jsonrpc_client!(pub struct RawRpcClient {
@@ -79,25 +84,77 @@ jsonrpc_client!(pub struct RawRpcClient {
     pub fn submit_block(&mut self, _work_id: String, _data: Block) -> RpcRequest<H256>;
 });
 
-impl RawRpcClient<HttpHandle> {
-    pub fn from_uri(server: &str) -> RawRpcClient<HttpHandle> {
-        let transport = HttpTransport::new().standalone().unwrap();
-        let transport_handle = transport.handle(server).unwrap();
-        RawRpcClient::new(transport_handle)
+impl RawRpcClient<JournaledHandle> {
+    /// Panics when the transport can not be created, see `try_from_uri`
+    pub fn from_uri(server: &str) -> RawRpcClient<JournaledHandle> {
+        Self::try_from_uri(server).unwrap()
+    }
+
+    /// Create the client, an https url through the SOCKS5 proxy (eg: `--tor`)
+    /// is an error
+    pub fn try_from_uri(server: &str) -> Result<RawRpcClient<JournaledHandle>, String> {
+        let transport_handle = new_transport_for(server)?
+            .handle(server)
+            .map_err(|err| err.to_string())?;
+        Ok(RawRpcClient::new(JournaledHandle::new(
+            server,
+            transport_handle,
+        )))
+    }
+}
+
+pub type RawHttpRpcClient = RawRpcClient<JournaledHandle>;
+
+/// A failed RPC call, `code` is the JSON-RPC error code of the response
+/// (`None` when there is no error response, e.g. the connection failed)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+    pub code: Option<i64>,
+    pub message: String,
+}
+
+impl From<jsonrpc_client_core::Error> for RpcError {
+    fn from(err: jsonrpc_client_core::Error) -> RpcError {
+        let code = match err.kind() {
+            jsonrpc_client_core::ErrorKind::JsonRpcError(error) => Some(error.code.code()),
+            _ => None,
+        };
+        RpcError {
+            code,
+            message: err.to_string(),
+        }
     }
 }
 
-pub type RawHttpRpcClient = RawRpcClient<HttpHandle>;
+impl fmt::Display for RpcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
 
+/// The blocking facade of `AsyncRpcClient` used by the CLI commands
 pub struct HttpRpcClient {
     url: String,
     client: RawHttpRpcClient,
+    async_client: AsyncRpcClient,
 }
 
 impl HttpRpcClient {
+    /// Panics when the transport can not be created, see `try_new`
     pub fn new(url: String) -> HttpRpcClient {
-        let client = RawHttpRpcClient::from_uri(url.as_str());
-        HttpRpcClient { url, client }
+        Self::try_new(url).unwrap()
+    }
+
+    /// Create the client, an https url through the SOCKS5 proxy (eg: `--tor`)
+    /// is an error
+    pub fn try_new(url: String) -> Result<HttpRpcClient, String> {
+        let async_client = AsyncRpcClient::new(url.as_str())?;
+        let client = async_client.raw_client();
+        Ok(HttpRpcClient {
+            url,
+            client,
+            async_client,
+        })
     }
 
     pub fn url(&self) -> &str {
@@ -106,23 +163,48 @@ impl HttpRpcClient {
     pub fn client(&mut self) -> &mut RawHttpRpcClient {
         &mut self.client
     }
+    /// The non-blocking client sharing the connections of this client
+    pub fn async_client(&self) -> &AsyncRpcClient {
+        &self.async_client
+    }
+
+    /// Send the requests (`{"method": .., "params": [..]}`) as one JSON-RPC
+    /// batch in a single HTTP round trip, the responses are aligned with the
+    /// requests.
+    pub fn batch(&mut self, requests: Vec<Value>) -> Result<Vec<Value>, String> {
+        self.async_client.batch(requests).wait()
+    }
+
+    /// Call a method without decoding the result, for the methods/data not
+    /// known by the typed client (eg: `get_consensus` and the blocks of
+    /// CKB2021 hardfork)
+    pub fn call(&mut self, method: &str, params: Vec<Value>) -> Result<Value, String> {
+        self.async_client.call(method, params).wait()
+    }
 }
 
+/// Decode a nullable result of the node by `decode`
+pub(crate) fn decode_opt<T, F>(value: Value, decode: F) -> Result<Option<T>, String>
+where
+    F: FnOnce(Value) -> Result<T, String>,
+{
+    if value.is_null() {
+        Ok(None)
+    } else {
+        decode(value).map(Some)
+    }
+}
+
+// The blocks and the transactions are decoded from the untyped results, the
+// typed client does not know the data of CKB2021 hardfork
 impl HttpRpcClient {
     // Chain
     pub fn get_block(&mut self, hash: H256) -> Result<Option<types::BlockView>, String> {
-        self.client
-            .get_block(hash)
-            .call()
-            .map(|opt| opt.map(Into::into))
-            .map_err(|err| err.to_string())
+        let block = self.call("get_block", vec![serde_json::json!(hash)])?;
+        decode_opt(block, types::BlockView::from_json)
     }
     pub fn get_block_by_number(&mut self, number: u64) -> Result<Option<types::BlockView>, String> {
-        self.client
-            .get_block_by_number(BlockNumber::from(number))
-            .call()
-            .map(|opt| opt.map(Into::into))
-            .map_err(|err| err.to_string())
+        self.async_client.get_block_by_number(number).wait()
     }
     pub fn get_block_hash(&mut self, number: u64) -> Result<Option<H256>, String> {
         self.client
@@ -213,11 +295,8 @@ impl HttpRpcClient {
         &mut self,
         hash: H256,
     ) -> Result<Option<types::TransactionWithStatus>, String> {
-        self.client
-            .get_transaction(hash)
-            .call()
-            .map(|opt| opt.map(Into::into))
-            .map_err(|err| err.to_string())
+        let tx = self.call("get_transaction", vec![serde_json::json!(hash)])?;
+        decode_opt(tx, types::TransactionWithStatus::from_json)
     }
 
     // Indexer
@@ -318,6 +397,17 @@ impl HttpRpcClient {
             .call()
             .map_err(|err| err.to_string())
     }
+    /// Same as `send_transaction`, the error keeps the JSON-RPC error code
+    /// (e.g. to tell a duplicated transaction from a rejected one)
+    pub fn send_transaction_with_code(
+        &mut self,
+        tx: packed::Transaction,
+    ) -> Result<H256, RpcError> {
+        self.client
+            .send_transaction(tx.into())
+            .call()
+            .map_err(RpcError::from)
+    }
     pub fn tx_pool_info(&mut self) -> Result<types::TxPoolInfo, String> {
         self.client
             .tx_pool_info()
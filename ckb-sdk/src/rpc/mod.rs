@@ -1,9 +1,19 @@
+mod async_client;
 mod client;
+mod journal;
 mod primitive;
+mod socks;
+mod subscription;
 mod types;
 
-pub use client::{HttpRpcClient, RawHttpRpcClient, RawRpcClient};
+pub use async_client::{AsyncRpcClient, RpcFuture};
+pub use client::{HttpRpcClient, RawHttpRpcClient, RawRpcClient, RpcError};
+pub use journal::{last_rpc_journal_id, set_rpc_journal, JournaledHandle};
 pub use primitive::{Capacity, EpochNumberWithFraction, Since, Timestamp};
+pub use socks::{
+    http_get, is_onion_url, new_http_transport, new_transport_for, set_socks_proxy, socks_proxy,
+};
+pub use subscription::{Subscription, SUBSCRIPTION_TOPICS};
 pub use types::{
     Alert, AlertMessage, BannedAddr, Block, BlockReward, BlockView, Byte32, CellDep, CellInput,
     CellOutput, CellOutputWithOutPoint, CellTransaction, ChainInfo, DepType, EpochView, Header,
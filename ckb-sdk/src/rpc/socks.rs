@@ -0,0 +1,300 @@
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream as StdTcpStream};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use futures::future::{self, Either, FutureResult};
+use futures::{Future, Stream};
+use hyper::{client::Service, Body, Client, Uri};
+use jsonrpc_client_http::{ClientCreator, DefaultClient, DefaultTlsClient, HttpTransport};
+use lazy_static::lazy_static;
+use tokio_core::{
+    net::TcpStream,
+    reactor::{Core, Handle, Timeout},
+};
+
+// Tor may take a while to build a circuit to an onion service
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(60);
+// A server accepting the connection but never answering must not hang the
+// caller
+const HTTP_GET_TIMEOUT: Duration = Duration::from_secs(30);
+
+lazy_static! {
+    static ref SOCKS_PROXY: RwLock<Option<SocketAddr>> = RwLock::new(None);
+}
+
+/// Route all following RPC connections through a SOCKS5 proxy (eg: Tor), the
+/// host name is resolved by the proxy so `.onion` urls work.
+pub fn set_socks_proxy(proxy: Option<SocketAddr>) {
+    *SOCKS_PROXY.write().expect("socks proxy lock") = proxy;
+}
+
+pub fn socks_proxy() -> Option<SocketAddr> {
+    *SOCKS_PROXY.read().expect("socks proxy lock")
+}
+
+/// Create a standalone http transport, through the SOCKS5 proxy if it is set
+pub fn new_http_transport() -> Result<HttpTransport, String> {
+    if let Some(proxy) = socks_proxy() {
+        HttpTransport::with_client_creator(SocksClientCreator { proxy })
+            .standalone()
+            .map_err(|err| err.to_string())
+    } else {
+        HttpTransport::new()
+            .standalone()
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Create a standalone transport for the url, https urls are served by TLS
+/// which is not supported through the SOCKS5 proxy
+pub fn new_transport_for(url: &str) -> Result<HttpTransport, String> {
+    if !url.starts_with("https://") {
+        return new_http_transport();
+    }
+    if socks_proxy().is_some() {
+        return Err(format!(
+            "https url is not supported by SOCKS proxy: {}",
+            url
+        ));
+    }
+    HttpTransport::with_client_creator(DefaultTlsClient)
+        .standalone()
+        .map_err(|err| err.to_string())
+}
+
+/// Fetch the body of the url by a GET request, through the SOCKS5 proxy if it
+/// is set (http url only, as `new_transport_for`). The request fails after
+/// 30 seconds.
+pub fn http_get(url: &str) -> Result<Vec<u8>, String> {
+    let uri = url
+        .parse::<Uri>()
+        .map_err(|err| format!("Invalid url {}: {}", url, err))?;
+    if !url.starts_with("https://") {
+        match socks_proxy() {
+            Some(proxy) => get_by(&SocksClientCreator { proxy }, uri),
+            None => get_by(&DefaultClient, uri),
+        }
+    } else if socks_proxy().is_some() {
+        Err(format!(
+            "https url is not supported by SOCKS proxy: {}",
+            url
+        ))
+    } else {
+        get_by(&DefaultTlsClient, uri)
+    }
+}
+
+fn get_by<C: ClientCreator>(creator: &C, uri: Uri) -> Result<Vec<u8>, String> {
+    let mut core = Core::new().map_err(|err| err.to_string())?;
+    let client = creator
+        .create(&core.handle())
+        .map_err(|err| err.to_string())?;
+    let work = client
+        .get(uri)
+        .and_then(|response| {
+            let status = response.status();
+            response.body().concat2().map(move |body| (status, body))
+        })
+        .map_err(|err| err.to_string());
+    let timeout = Timeout::new(HTTP_GET_TIMEOUT, &core.handle())
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string());
+    let work = work.select2(timeout).then(|result| match result {
+        Ok(Either::A((response, _))) => Ok(response),
+        Ok(Either::B(_)) => Err(format!(
+            "Timeout after {} seconds",
+            HTTP_GET_TIMEOUT.as_secs()
+        )),
+        Err(Either::A((err, _))) | Err(Either::B((err, _))) => Err(err),
+    });
+    let (status, body) = core.run(work)?;
+    if !status.is_success() {
+        return Err(format!("HTTP status {}", status));
+    }
+    Ok(body.to_vec())
+}
+
+/// Connect to `host:port`, through the SOCKS5 proxy if it is set
+pub(crate) fn connect_tcp(host: &str, port: u16) -> io::Result<StdTcpStream> {
+    match socks_proxy() {
+        Some(proxy) => {
+            let mut stream = StdTcpStream::connect(&proxy)?;
+            stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+            stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+            socks5_handshake(&mut stream, host, port)?;
+            stream.set_read_timeout(None)?;
+            stream.set_write_timeout(None)?;
+            Ok(stream)
+        }
+        None => StdTcpStream::connect((host, port)),
+    }
+}
+
+pub fn is_onion_url(url: &str) -> bool {
+    url.parse::<Uri>()
+        .ok()
+        .and_then(|uri| uri.host().map(|host| host.ends_with(".onion")))
+        .unwrap_or(false)
+}
+
+struct SocksClientCreator {
+    proxy: SocketAddr,
+}
+
+impl ClientCreator for SocksClientCreator {
+    type Connect = SocksConnector;
+    type Error = io::Error;
+
+    fn create(&self, handle: &Handle) -> Result<Client<SocksConnector, Body>, io::Error> {
+        let connector = SocksConnector {
+            proxy: self.proxy,
+            handle: handle.clone(),
+        };
+        Ok(Client::configure().connector(connector).build(handle))
+    }
+}
+
+struct SocksConnector {
+    proxy: SocketAddr,
+    handle: Handle,
+}
+
+impl SocksConnector {
+    fn connect(&self, uri: &Uri) -> io::Result<TcpStream> {
+        if uri.scheme() != Some("http") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "only http url is supported by SOCKS proxy",
+            ));
+        }
+        let host = uri
+            .host()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "missing host in url"))?;
+        let port = uri.port().unwrap_or(80);
+        let mut stream = StdTcpStream::connect(&self.proxy)?;
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        stream.set_write_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        socks5_handshake(&mut stream, host, port)?;
+        stream.set_read_timeout(None)?;
+        stream.set_write_timeout(None)?;
+        TcpStream::from_stream(stream, &self.handle)
+    }
+}
+
+impl Service for SocksConnector {
+    type Request = Uri;
+    type Response = TcpStream;
+    type Error = io::Error;
+    type Future = FutureResult<TcpStream, io::Error>;
+
+    fn call(&self, uri: Uri) -> Self::Future {
+        future::result(self.connect(&uri))
+    }
+}
+
+/// SOCKS5 CONNECT without authentication (RFC 1928), the target is sent as a
+/// domain name
+fn socks5_handshake<S: Read + Write>(stream: &mut S, host: &str, port: u16) -> io::Result<()> {
+    let socks_error = |message: String| io::Error::new(io::ErrorKind::Other, message);
+    if host.len() > 255 {
+        return Err(socks_error(format!("host name too long: {}", host)));
+    }
+
+    stream.write_all(&[0x05, 0x01, 0x00])?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    if reply != [0x05, 0x00] {
+        return Err(socks_error(
+            "SOCKS5 proxy requires authentication or is not a SOCKS5 proxy".to_string(),
+        ));
+    }
+
+    let mut request = vec![0x05, 0x01, 0x00, 0x03, host.len() as u8];
+    request.extend_from_slice(host.as_bytes());
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request)?;
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply)?;
+    if reply[1] != 0x00 {
+        let reason = match reply[1] {
+            0x01 => "general failure",
+            0x02 => "connection not allowed by ruleset",
+            0x03 => "network unreachable",
+            0x04 => "host unreachable",
+            0x05 => "connection refused",
+            0x06 => "TTL expired",
+            0x07 => "command not supported",
+            0x08 => "address type not supported",
+            _ => "unknown error",
+        };
+        return Err(socks_error(format!(
+            "SOCKS5 proxy connect to {}:{} failed: {}",
+            host, port, reason
+        )));
+    }
+    // Skip the bound address
+    let addr_len = match reply[3] {
+        0x01 => 4,
+        0x04 => 16,
+        0x03 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        ty => return Err(socks_error(format!("invalid SOCKS5 address type: {}", ty))),
+    };
+    let mut bound_addr = vec![0u8; addr_len + 2];
+    stream.read_exact(&mut bound_addr)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct MockStream {
+        input: io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_socks5_handshake() {
+        let mut stream = MockStream {
+            input: io::Cursor::new(vec![5, 0, 5, 0, 0, 1, 0, 0, 0, 0, 0, 0]),
+            output: Vec::new(),
+        };
+        socks5_handshake(&mut stream, "abc.onion", 8114).unwrap();
+        let mut expected = vec![5, 1, 0, 5, 1, 0, 3, 9];
+        expected.extend_from_slice(b"abc.onion");
+        expected.extend_from_slice(&[0x1f, 0xb2]);
+        assert_eq!(stream.output, expected);
+
+        let mut stream = MockStream {
+            input: io::Cursor::new(vec![5, 0, 5, 4, 0, 1, 0, 0, 0, 0, 0, 0]),
+            output: Vec::new(),
+        };
+        assert!(socks5_handshake(&mut stream, "abc.onion", 8114).is_err());
+    }
+
+    #[test]
+    fn test_is_onion_url() {
+        assert!(is_onion_url("http://abcdefghijklmnop.onion:8114"));
+        assert!(!is_onion_url("http://127.0.0.1:8114"));
+    }
+}
@@ -0,0 +1,88 @@
+use std::net::TcpStream;
+
+use hyper::Uri;
+use serde_json::Value;
+use tungstenite::{Message, WebSocket};
+
+use super::socks::connect_tcp;
+
+/// The topics of node's subscription RPC
+pub const SUBSCRIPTION_TOPICS: [&str; 3] = ["new_tip_header", "new_tip_block", "new_transaction"];
+
+/// A subscription through the WebSocket RPC of node (`ws_listen_address` in
+/// ckb.toml), only `ws://` url is supported.
+pub struct Subscription {
+    socket: WebSocket<TcpStream>,
+}
+
+impl Subscription {
+    pub fn subscribe(url: &str, topic: &str) -> Result<Subscription, String> {
+        let uri: Uri = url
+            .parse()
+            .map_err(|err| format!("Invalid url {}: {}", url, err))?;
+        if uri.scheme() != Some("ws") {
+            return Err(format!("Only ws:// url is supported: {}", url));
+        }
+        let host = uri
+            .host()
+            .ok_or_else(|| format!("Missing host in url: {}", url))?;
+        let port = uri.port().unwrap_or(80);
+        let stream =
+            connect_tcp(host, port).map_err(|err| format!("Connect {} failed: {}", url, err))?;
+        let (socket, _) = tungstenite::client(url, stream)
+            .map_err(|err| format!("WebSocket handshake failed: {}", err))?;
+        let mut subscription = Subscription { socket };
+
+        let request = serde_json::json!({
+            "id": 0,
+            "jsonrpc": "2.0",
+            "method": "subscribe",
+            "params": [topic],
+        });
+        subscription
+            .socket
+            .write_message(Message::Text(request.to_string()))
+            .map_err(|err| err.to_string())?;
+        let response: Value = match subscription.read_text()? {
+            Some(text) => serde_json::from_str(&text).map_err(|err| err.to_string())?,
+            None => return Err("Connection closed by node".to_string()),
+        };
+        if let Some(error) = response.get("error") {
+            return Err(format!("Subscribe {} failed: {}", topic, error));
+        }
+        Ok(subscription)
+    }
+
+    /// Wait for the next event (eg: a `HeaderView` of `new_tip_header`),
+    /// return `None` when the connection is closed by node.
+    pub fn next_event(&mut self) -> Result<Option<Value>, String> {
+        while let Some(text) = self.read_text()? {
+            let notification: Value = serde_json::from_str(&text).map_err(|err| err.to_string())?;
+            if notification["method"] != "subscribe" {
+                continue;
+            }
+            // The event is serialized as a JSON string
+            let result = notification["params"]["result"]
+                .as_str()
+                .ok_or_else(|| format!("Invalid notification: {}", text))?;
+            return serde_json::from_str(result)
+                .map(Some)
+                .map_err(|err| err.to_string());
+        }
+        Ok(None)
+    }
+
+    fn read_text(&mut self) -> Result<Option<String>, String> {
+        loop {
+            match self.socket.read_message() {
+                Ok(Message::Text(text)) => return Ok(Some(text)),
+                Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => {
+                    return Ok(None)
+                }
+                // Ping is replied by tungstenite
+                Ok(_) => {}
+                Err(err) => return Err(err.to_string()),
+            }
+        }
+    }
+}
@@ -1,8 +1,8 @@
-use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use serde::{de::DeserializeOwned, ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use serde_json::Value;
 
 pub use ckb_jsonrpc_types::{
-    self as rpc_types, Byte32, DepType, JsonBytes, ProposalShortId, ScriptHashType, TxStatus,
-    Uint128,
+    self as rpc_types, Byte32, DepType, JsonBytes, ProposalShortId, TxStatus, Uint128,
 };
 use ckb_types::{core, packed, prelude::*, H256, U256};
 
@@ -20,6 +20,63 @@ type Uint64 = u64;
 // ===============
 //  blockchain.rs
 // ===============
+/// The hash type of a script, `ScriptHashType` of ckb-jsonrpc-types does not
+/// know `data1` (the scripts run by CKB-VM version 1 since CKB2021)
+#[derive(Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptHashType {
+    Data,
+    Type,
+    Data1,
+}
+impl Default for ScriptHashType {
+    fn default() -> Self {
+        ScriptHashType::Data
+    }
+}
+impl From<rpc_types::ScriptHashType> for ScriptHashType {
+    fn from(json: rpc_types::ScriptHashType) -> ScriptHashType {
+        match json {
+            rpc_types::ScriptHashType::Data => ScriptHashType::Data,
+            rpc_types::ScriptHashType::Type => ScriptHashType::Type,
+        }
+    }
+}
+impl From<ScriptHashType> for packed::Byte {
+    fn from(hash_type: ScriptHashType) -> packed::Byte {
+        let value = match hash_type {
+            ScriptHashType::Data => 0x00,
+            ScriptHashType::Type => 0x01,
+            ScriptHashType::Data1 => 0x02,
+        };
+        packed::Byte::new(value)
+    }
+}
+
+// ckb-jsonrpc-types does not know the `data1` hash type, the JSON of the node
+// is decoded by it with `data1` replaced by `data`, then the hash types are
+// restored by the JSON (see `Transaction::restore_data1`)
+fn decode_hardfork_json<T: DeserializeOwned>(mut json: Value) -> Result<T, String> {
+    fn replace_data1(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                if map.get("hash_type").and_then(Value::as_str) == Some("data1") {
+                    map.insert("hash_type".to_string(), Value::from("data"));
+                }
+                map.values_mut().for_each(replace_data1);
+            }
+            Value::Array(values) => values.iter_mut().for_each(replace_data1),
+            _ => {}
+        }
+    }
+    replace_data1(&mut json);
+    serde_json::from_value(json).map_err(|err| err.to_string())
+}
+
+fn is_data1(script: &Value) -> bool {
+    script["hash_type"].as_str() == Some("data1")
+}
+
 #[derive(Clone, Default, Deserialize, PartialEq, Eq, Hash, Debug)]
 #[serde(deny_unknown_fields)]
 pub struct Script {
@@ -31,7 +88,7 @@ impl From<rpc_types::Script> for Script {
     fn from(json: rpc_types::Script) -> Script {
         Script {
             code_hash: json.code_hash,
-            hash_type: json.hash_type,
+            hash_type: json.hash_type.into(),
             args: json.args,
         }
     }
@@ -43,7 +100,6 @@ impl From<Script> for packed::Script {
             code_hash,
             hash_type,
         } = json;
-        let hash_type: core::ScriptHashType = hash_type.into();
         packed::Script::new_builder()
             .args(args.into_bytes().pack())
             .code_hash(code_hash.pack())
@@ -219,6 +275,27 @@ impl From<rpc_types::Transaction> for Transaction {
         }
     }
 }
+impl Transaction {
+    // Restore the `data1` hash types of the outputs by the JSON of the node,
+    // see `decode_hardfork_json`
+    fn restore_data1(&mut self, json: &Value) {
+        let outputs_json = json["outputs"].as_array().map(Vec::as_slice);
+        for (output, output_json) in self
+            .outputs
+            .iter_mut()
+            .zip(outputs_json.unwrap_or_default())
+        {
+            if is_data1(&output_json["lock"]) {
+                output.lock.hash_type = ScriptHashType::Data1;
+            }
+            if let Some(type_) = output.type_.as_mut() {
+                if is_data1(&output_json["type"]) {
+                    type_.hash_type = ScriptHashType::Data1;
+                }
+            }
+        }
+    }
+}
 impl From<Transaction> for packed::Transaction {
     fn from(json: Transaction) -> Self {
         let Transaction {
@@ -266,6 +343,16 @@ pub struct TransactionWithStatus {
     /// Indicate the Transaction status
     pub tx_status: TxStatus,
 }
+impl TransactionWithStatus {
+    /// Decode the result of `get_transaction`, including the `data1` hash
+    /// type of CKB2021
+    pub fn from_json(json: Value) -> Result<TransactionWithStatus, String> {
+        let raw: rpc_types::TransactionWithStatus = decode_hardfork_json(json.clone())?;
+        let mut tx = TransactionWithStatus::from(raw);
+        tx.transaction.inner.restore_data1(&json["transaction"]);
+        Ok(tx)
+    }
+}
 impl From<rpc_types::TransactionWithStatus> for TransactionWithStatus {
     fn from(json: rpc_types::TransactionWithStatus) -> TransactionWithStatus {
         TransactionWithStatus {
@@ -441,6 +528,34 @@ pub struct BlockView {
     pub uncles: Vec<UncleBlockView>,
     pub transactions: Vec<TransactionView>,
     pub proposals: Vec<ProposalShortId>,
+    /// The extension of the blocks since CKB2021
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extension: Option<JsonBytes>,
+}
+impl BlockView {
+    /// Decode the result of `get_block`, including the `extension` and the
+    /// `data1` hash type of CKB2021
+    pub fn from_json(mut json: Value) -> Result<BlockView, String> {
+        let extension = match json.as_object_mut().and_then(|map| map.remove("extension")) {
+            None | Some(Value::Null) => None,
+            Some(extension) => Some(
+                serde_json::from_value::<JsonBytes>(extension)
+                    .map_err(|err| format!("Invalid block extension: {}", err))?,
+            ),
+        };
+        let raw: rpc_types::BlockView = decode_hardfork_json(json.clone())?;
+        let mut block = BlockView::from(raw);
+        let transactions_json = json["transactions"].as_array().map(Vec::as_slice);
+        for (tx, tx_json) in block
+            .transactions
+            .iter_mut()
+            .zip(transactions_json.unwrap_or_default())
+        {
+            tx.inner.restore_data1(tx_json);
+        }
+        block.extension = extension;
+        Ok(block)
+    }
 }
 impl From<rpc_types::BlockView> for BlockView {
     fn from(json: rpc_types::BlockView) -> BlockView {
@@ -449,9 +564,12 @@ impl From<rpc_types::BlockView> for BlockView {
             uncles: json.uncles.into_iter().map(Into::into).collect(),
             transactions: json.transactions.into_iter().map(Into::into).collect(),
             proposals: json.proposals,
+            extension: None,
         }
     }
 }
+// The extension is dropped, the blocks of ckb-types have no extension (it is
+// committed by the header, so the block hash is kept)
 impl From<BlockView> for core::BlockView {
     fn from(input: BlockView) -> Self {
         let BlockView {
@@ -459,6 +577,7 @@ impl From<BlockView> for core::BlockView {
             uncles,
             transactions,
             proposals,
+            ..
         } = input;
         let block = Block {
             header: header.inner,
@@ -861,3 +980,79 @@ impl From<rpc_types::TxPoolInfo> for TxPoolInfo {
 //         }
 //     }
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A block of CKB2021: the block has `extension`, the lock of output 0 and
+    // the type of output 1 have the `data1` hash type
+    const HARDFORK_BLOCK_JSON: &str = include_str!("../test-data/hardfork_block.json");
+
+    #[test]
+    fn test_hardfork_block() {
+        let json: Value = serde_json::from_str(HARDFORK_BLOCK_JSON).unwrap();
+        let block = BlockView::from_json(json.clone()).unwrap();
+        assert_eq!(
+            block.extension,
+            Some(serde_json::from_value(json["extension"].clone()).unwrap())
+        );
+        let outputs = &block.transactions[0].inner.outputs;
+        assert_eq!(outputs[0].lock.hash_type, ScriptHashType::Data1);
+        assert_eq!(outputs[1].lock.hash_type, ScriptHashType::Type);
+        assert_eq!(
+            outputs[1].type_.as_ref().unwrap().hash_type,
+            ScriptHashType::Data1
+        );
+
+        // The hashes of the packed block match the hashes given by the node
+        let tx_hash = block.transactions[0].hash.clone();
+        let block_hash = block.header.hash.clone();
+        let block_view: core::BlockView = block.into();
+        let packed_block_hash: H256 = block_view.hash().unpack();
+        assert_eq!(packed_block_hash, block_hash);
+        let tx = &block_view.transactions()[0];
+        let packed_tx_hash: H256 = tx.hash().unpack();
+        assert_eq!(packed_tx_hash, tx_hash);
+        assert_eq!(
+            tx.outputs().get(0).unwrap().lock().hash_type(),
+            packed::Byte::new(0x02)
+        );
+
+        let tx_json = serde_json::json!({
+            "transaction": json["transactions"][0],
+            "tx_status": {"status": "committed", "block_hash": block_hash},
+        });
+        let tx = TransactionWithStatus::from_json(tx_json).unwrap();
+        let packed_tx: packed::Transaction = tx.transaction.inner.into();
+        let packed_tx_hash: H256 = packed_tx.into_view().hash().unpack();
+        assert_eq!(packed_tx_hash, tx_hash);
+    }
+
+    #[test]
+    fn test_block_before_hardfork() {
+        let json: Value =
+            serde_json::from_str(include_str!("../test-data/genesis_block.json")).unwrap();
+        let block = BlockView::from_json(json.clone()).unwrap();
+        assert_eq!(block.extension, None);
+        let raw: rpc_types::BlockView = serde_json::from_value(json).unwrap();
+        assert_eq!(block, BlockView::from(raw));
+    }
+
+    #[test]
+    fn test_script_hash_type_json() {
+        for (hash_type, name) in &[
+            (ScriptHashType::Data, "data"),
+            (ScriptHashType::Type, "type"),
+            (ScriptHashType::Data1, "data1"),
+        ] {
+            let json = serde_json::to_value(hash_type).unwrap();
+            assert_eq!(json, Value::from(*name));
+            assert_eq!(
+                serde_json::from_value::<ScriptHashType>(json).unwrap(),
+                *hash_type
+            );
+        }
+        assert!(serde_json::from_value::<ScriptHashType>(Value::from("data2")).is_err());
+    }
+}
@@ -7,12 +7,28 @@ pub trait SignerSingleShot {
     type Err;
 
     fn append(&mut self, message_fragment: &[u8]);
+    /// Sign the appended message as a 32 bytes digest computed by the caller,
+    /// for the signers which parse the message (ledger) and can't parse the
+    /// one of some locks (e.g. omnilock). Returns false when not supported.
+    fn sign_digest(&mut self) -> bool {
+        false
+    }
     // Work around needing #![feature(unsized_locals)]
     fn finalize(self: Box<Self>) -> Result<RecoverableSignature, Self::Err>;
 }
 
 pub type FullyAbstractSingleShotSigner<'a> = Box<dyn SignerSingleShot<Err = String> + 'a>;
 
+/// The 65 bytes of a recoverable signature in witness: the compact signature
+/// and the recovery id
+pub fn serialize_signature(signature: &RecoverableSignature) -> [u8; 65] {
+    let (recov_id, data) = signature.serialize_compact();
+    let mut signature_bytes = [0u8; 65];
+    signature_bytes[0..64].copy_from_slice(&data[0..64]);
+    signature_bytes[64] = recov_id.to_i32() as u8;
+    signature_bytes
+}
+
 impl<T> SignerSingleShot for Box<T>
 where
     T: ?Sized + SignerSingleShot,
@@ -22,6 +38,9 @@ where
     fn append(&mut self, message_fragment: &[u8]) {
         (&mut **self).append(message_fragment)
     }
+    fn sign_digest(&mut self) -> bool {
+        (&mut **self).sign_digest()
+    }
     fn finalize(self: Box<Self>) -> Result<RecoverableSignature, Self::Err> {
         (*self).finalize()
     }
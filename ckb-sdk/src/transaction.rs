@@ -3,7 +3,7 @@ use ckb_script::TransactionScriptsVerifier;
 use ckb_types::{
     bytes::Bytes,
     core::{cell::resolve_transaction, Capacity, Cycle, ScriptHashType},
-    packed::{Byte32, CellInput, CellOutput, OutPoint, Script, WitnessArgs},
+    packed::{Byte, Byte32, CellInput, CellOutput, OutPoint, Script, WitnessArgs},
     prelude::*,
     H160, H256,
 };
@@ -20,6 +20,16 @@ pub use ckb_sdk_types::transaction::{
     ReprMockInfo, ReprMockInput, ReprMockTransaction, Resource,
 };
 
+// The code of a `data1` script (CKB-VM version 1) is also found by the data hash
+fn dep_hash_type(hash_type: Byte) -> Result<ScriptHashType, String> {
+    if hash_type.as_slice()[0] == 0x02 {
+        return Ok(ScriptHashType::Data);
+    }
+    hash_type
+        .try_into()
+        .map_err(|err: FailureError| err.to_string())
+}
+
 pub struct MockTransactionHelper<'a> {
     pub mock_tx: &'a mut MockTransaction,
     live_cell_cache: HashMap<OutPoint, (CellOutput, Bytes)>,
@@ -176,18 +186,12 @@ impl<'a> MockTransactionHelper<'a> {
         };
         for input in tx.inputs().into_iter() {
             let lock = self.get_input_cell(&input, &mut live_cell_getter)?.0.lock();
-            let hash_type = lock
-                .hash_type()
-                .try_into()
-                .map_err(|err: FailureError| err.to_string())?;
+            let hash_type = dep_hash_type(lock.hash_type())?;
             insert_dep(hash_type, &lock.code_hash())?;
         }
         for output in tx.outputs().into_iter() {
             if let Some(script) = output.type_().to_opt() {
-                let hash_type = script
-                    .hash_type()
-                    .try_into()
-                    .map_err(|err: FailureError| err.to_string())?;
+                let hash_type = dep_hash_type(script.hash_type())?;
                 insert_dep(hash_type, &script.code_hash())?;
             }
         }
@@ -0,0 +1,757 @@
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, TransactionView},
+    packed::{CellDep, CellOutput, OutPoint, WitnessArgs},
+    prelude::*,
+    H160, H256,
+};
+
+use crate::cheque::ChequeConfig;
+use crate::constants::{MIN_SECP_CELL_CAPACITY, MULTISIG_TYPE_HASH, ONE_CKB};
+use crate::placeholder::witness_lock_placeholder;
+use crate::rpc::Transaction;
+use crate::signing::serialize_signature;
+use crate::tx_helper::{MultisigConfig, SignerFnTrait, TxHelper};
+use crate::wallet::DerivationPath;
+use crate::{AddressPayload, GenesisInfo};
+
+/// A live cell which can be used as transaction input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectedCell {
+    pub out_point: OutPoint,
+    pub capacity: u64,
+}
+
+impl CollectedCell {
+    pub fn new(out_point: OutPoint, capacity: u64) -> CollectedCell {
+        CollectedCell {
+            out_point,
+            capacity,
+        }
+    }
+}
+
+/// Cell collection strategy used by `TransactionBuilder`.
+///
+/// The collector is asked for cells covering at least `required` shannons, it
+/// may return less than required (the builder will report capacity not
+/// enough), the cells must be unlockable by the signer.
+pub trait CellCollector {
+    fn collect_cells(&mut self, required: u64) -> Result<Vec<CollectedCell>, String>;
+}
+
+impl<F> CellCollector for F
+where
+    F: FnMut(u64) -> Result<Vec<CollectedCell>, String>,
+{
+    fn collect_cells(&mut self, required: u64) -> Result<Vec<CollectedCell>, String> {
+        self(required)
+    }
+}
+
+/// Give up when the fee calculated by fee rate still not converged
+pub const MAX_FEE_ITERATIONS: usize = 16;
+
+/// How the transaction fee is decided
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeePolicy {
+    /// Pay a fixed fee (in shannons)
+    Fixed(u64),
+    /// Pay by the serialized size of the signed transaction (in shannons/KB)
+    Rate(u64),
+}
+
+impl FeePolicy {
+    /// The fixed fee, 0 for `Rate` whose fee depends on the transaction size
+    #[deprecated(
+        note = "the fee of FeePolicy::Rate depends on the transaction size, use calculate_fee"
+    )]
+    pub fn fee(&self) -> u64 {
+        match self {
+            FeePolicy::Fixed(fee) => *fee,
+            FeePolicy::Rate(_) => 0,
+        }
+    }
+}
+
+/// Calculate the fee of a `tx_size` bytes transaction by fee rate
+/// (shannons/KB), rounded up
+pub fn calculate_fee(fee_rate: u64, tx_size: usize) -> u64 {
+    let fee = u128::from(fee_rate) * tx_size as u128;
+    ((fee + 999) / 1000) as u64
+}
+
+/// The fee of a child transaction which pays for its parent (CPFP): the parent
+/// and the child together pay `fee_rate` (shannons/KB) for their total size,
+/// and the child alone pays at least `fee_rate` for its own size.
+pub fn child_pays_for_parent_fee(
+    fee_rate: u64,
+    parent_size: usize,
+    parent_fee: u64,
+    child_size: usize,
+) -> u64 {
+    let package_fee = calculate_fee(fee_rate, parent_size + child_size);
+    cmp::max(
+        package_fee.saturating_sub(parent_fee),
+        calculate_fee(fee_rate, child_size),
+    )
+}
+
+/// The size of transaction when it is serialized in a block (with the 4 bytes
+/// offset of transactions vector)
+pub fn tx_size_in_block(tx: &TransactionView) -> usize {
+    tx.data().as_slice().len() + 4
+}
+
+/// Split the change capacity into at most `cells` cells, so the following
+/// transactions can spend them in parallel. Every cell can hold a secp256k1
+/// cell and is rounded down to whole CKB, the last one takes the remainder.
+/// The capacity less than `cells * 61 CKB` is split into fewer cells.
+pub fn split_change(capacity: u64, cells: usize) -> Vec<u64> {
+    let cells = cmp::max(cmp::min(cells as u64, capacity / MIN_SECP_CELL_CAPACITY), 1);
+    let denomination = capacity / cells / ONE_CKB * ONE_CKB;
+    let mut values = vec![denomination; cells as usize - 1];
+    values.push(capacity - denomination * (cells - 1));
+    values
+}
+
+impl Default for FeePolicy {
+    fn default() -> FeePolicy {
+        FeePolicy::Fixed(0)
+    }
+}
+
+/// Build and sign a transfer transaction with secp256k1(sighash/multisig) locked inputs.
+///
+/// This is the construction logic used by `ckb-cli wallet transfer`:
+///  1. Collect input cells by `CellCollector` until outputs + fee is covered
+///  2. Put the rest capacity to change address (or pay it as fee if it can not
+///     hold a change cell)
+///  3. Sign the inputs by `SignerFnTrait` and fill the witnesses
+///
+/// ```ignore
+/// let mut builder = TransactionBuilder::default();
+/// builder
+///     .output(output, Bytes::new())
+///     .change_address(change_payload)
+///     .fee_policy(FeePolicy::Fixed(1000));
+/// let tx = builder.build(&mut collector, &mut get_live_cell, &genesis_info, signer)?;
+/// ```
+#[derive(Clone)]
+pub struct TransactionBuilder {
+    outputs: Vec<(CellOutput, Bytes)>,
+    cell_deps: Vec<CellDep>,
+    change_address: Option<AddressPayload>,
+    change_cells: usize,
+    fee_policy: FeePolicy,
+    max_fee: u64,
+    tip: u64,
+    multisig_configs: Vec<MultisigConfig>,
+    cheque: Option<ChequeConfig>,
+    input_since: HashMap<OutPoint, u64>,
+    is_ledger: bool,
+    change_path: DerivationPath,
+}
+
+impl Default for TransactionBuilder {
+    fn default() -> TransactionBuilder {
+        TransactionBuilder {
+            outputs: Vec::new(),
+            cell_deps: Vec::new(),
+            change_address: None,
+            change_cells: 1,
+            fee_policy: FeePolicy::default(),
+            max_fee: ONE_CKB,
+            tip: 0,
+            multisig_configs: Vec::new(),
+            cheque: None,
+            input_since: HashMap::new(),
+            is_ledger: false,
+            change_path: DerivationPath::empty(),
+        }
+    }
+}
+
+impl TransactionBuilder {
+    pub fn output(&mut self, output: CellOutput, data: Bytes) -> &mut Self {
+        self.outputs.push((output, data));
+        self
+    }
+
+    /// Add an output send `capacity` shannons to `payload`
+    pub fn output_to(&mut self, payload: &AddressPayload, capacity: u64, data: Bytes) -> &mut Self {
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(payload.into())
+            .build();
+        self.output(output, data)
+    }
+
+    /// Extra cell dep required by the type script of inputs/outputs (eg: sUDT)
+    pub fn cell_dep(&mut self, cell_dep: CellDep) -> &mut Self {
+        self.cell_deps.push(cell_dep);
+        self
+    }
+
+    pub fn change_address(&mut self, payload: AddressPayload) -> &mut Self {
+        self.change_address = Some(payload);
+        self
+    }
+
+    /// Split the change into at most `cells` cells (see `split_change`),
+    /// default is 1
+    pub fn change_cells(&mut self, cells: usize) -> &mut Self {
+        self.change_cells = cells;
+        self
+    }
+
+    pub fn fee_policy(&mut self, fee_policy: FeePolicy) -> &mut Self {
+        self.fee_policy = fee_policy;
+        self
+    }
+
+    /// The upper bound of the fee (including the rest capacity can not put
+    /// into a change cell), default is 1.0 CKB
+    pub fn max_fee(&mut self, max_fee: u64) -> &mut Self {
+        self.max_fee = max_fee;
+        self
+    }
+
+    /// Extra fee paid on top of the fee policy for faster inclusion, not
+    /// limited by `max_fee`
+    pub fn tip(&mut self, tip: u64) -> &mut Self {
+        self.tip = tip;
+        self
+    }
+
+    pub fn multisig_config(&mut self, config: MultisigConfig) -> &mut Self {
+        self.multisig_configs.push(config);
+        self
+    }
+
+    /// The cheque lock deployment, required when the collector returns cheque
+    /// cells
+    pub fn cheque(&mut self, config: ChequeConfig) -> &mut Self {
+        self.cheque = Some(config);
+        self
+    }
+
+    /// The since of a collected input required by its lock script, the since
+    /// of other inputs is decided by `TxHelper::add_input`
+    pub fn input_since(&mut self, out_point: OutPoint, since: u64) -> &mut Self {
+        self.input_since.insert(out_point, since);
+        self
+    }
+
+    /// Signing by hardware wallet, `change_path` is the derivation path of
+    /// the change address
+    pub fn ledger(&mut self, change_path: DerivationPath) -> &mut Self {
+        self.is_ledger = true;
+        self.change_path = change_path;
+        self
+    }
+
+    pub fn outputs(&self) -> &[(CellOutput, Bytes)] {
+        &self.outputs
+    }
+
+    pub fn outputs_capacity(&self) -> u64 {
+        self.outputs
+            .iter()
+            .map(|(output, _)| {
+                let capacity: u64 = output.capacity().unpack();
+                capacity
+            })
+            .sum()
+    }
+
+    /// Collect inputs and build an unsigned `TxHelper`
+    pub fn build_unsigned<C, F>(
+        &self,
+        collector: &mut C,
+        get_live_cell: &mut F,
+        genesis_info: &GenesisInfo,
+    ) -> Result<TxHelper, String>
+    where
+        C: CellCollector,
+        F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+    {
+        let fee_rate = match self.fee_policy {
+            FeePolicy::Fixed(fee) => {
+                return self.build_unsigned_with_fee(fee, collector, get_live_cell, genesis_info);
+            }
+            FeePolicy::Rate(fee_rate) => fee_rate,
+        };
+        let mut fee = 0;
+        for _ in 0..MAX_FEE_ITERATIONS {
+            let helper =
+                self.build_unsigned_with_fee(fee, collector, get_live_cell, genesis_info)?;
+            let tx_size = estimate_tx_size(&helper, get_live_cell)?;
+            let required_fee = calculate_fee(fee_rate, tx_size);
+            if required_fee <= fee {
+                return Ok(helper);
+            }
+            fee = required_fee;
+        }
+        Err(format!(
+            "Transaction fee not converged by fee rate {} shannons/KB",
+            fee_rate
+        ))
+    }
+
+    fn build_unsigned_with_fee<C, F>(
+        &self,
+        fee: u64,
+        collector: &mut C,
+        get_live_cell: &mut F,
+        genesis_info: &GenesisInfo,
+    ) -> Result<TxHelper, String>
+    where
+        C: CellCollector,
+        F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+    {
+        if fee > self.max_fee {
+            return Err(format!(
+                "Transaction fee can not be more than {} shannons",
+                self.max_fee
+            ));
+        }
+        let to_capacity = self.outputs_capacity();
+        let required = to_capacity
+            .checked_add(fee)
+            .and_then(|value| value.checked_add(self.tip))
+            .ok_or_else(|| "Capacity overflow".to_string())?;
+
+        let mut out_points: HashSet<OutPoint> = HashSet::default();
+        let mut cells = Vec::new();
+        let mut from_capacity: u64 = 0;
+        for cell in collector.collect_cells(required)? {
+            if out_points.insert(cell.out_point.clone()) {
+                from_capacity += cell.capacity;
+                cells.push(cell);
+            }
+        }
+        if required > from_capacity {
+            return Err(format!(
+                "Capacity(mature) not enough: required {}, found {}",
+                required, from_capacity,
+            ));
+        }
+
+        let rest_capacity = from_capacity - required;
+        let has_change = rest_capacity >= MIN_SECP_CELL_CAPACITY && self.change_address.is_some();
+        if !has_change && fee + rest_capacity > self.max_fee {
+            return Err(format!(
+                "Transaction fee can not be more than {} shannons, please change outputs capacity to adjust",
+                self.max_fee
+            ));
+        }
+
+        let mut helper = TxHelper::default();
+        helper.set_cheque(self.cheque.clone());
+        for config in &self.multisig_configs {
+            helper.add_multisig_config(config.clone());
+        }
+        for cell in &cells {
+            if let Some(since) = self.input_since.get(&cell.out_point) {
+                helper.add_input_with_since(
+                    cell.out_point.clone(),
+                    *since,
+                    get_live_cell,
+                    genesis_info,
+                )?;
+            } else {
+                helper.add_input(cell.out_point.clone(), None, get_live_cell, genesis_info)?;
+            }
+        }
+        for cell_dep in &self.cell_deps {
+            helper.add_cell_dep(cell_dep.clone());
+        }
+        for (output, data) in &self.outputs {
+            helper.add_output(output.clone(), data.clone());
+        }
+        if has_change {
+            for capacity in split_change(rest_capacity, self.change_cells) {
+                let change_output = CellOutput::new_builder()
+                    .capacity(Capacity::shannons(capacity).pack())
+                    .lock(self.change_address.as_ref().unwrap().into())
+                    .build();
+                helper.add_output(change_output, Bytes::default());
+            }
+        }
+        Ok(helper)
+    }
+
+    /// Collect inputs, sign them by `signer` and return the final transaction
+    pub fn build<C, F, S>(
+        &self,
+        collector: &mut C,
+        get_live_cell: &mut F,
+        genesis_info: &GenesisInfo,
+        signer: S,
+    ) -> Result<TransactionView, String>
+    where
+        C: CellCollector,
+        F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+        S: SignerFnTrait,
+    {
+        let helper = self.build_unsigned(collector, get_live_cell, genesis_info)?;
+        self.sign(helper, get_live_cell, signer)
+    }
+
+    /// Sign the inputs of `helper` (built by `build_unsigned`) by `signer` and
+    /// return the final transaction
+    pub fn sign<F, S>(
+        &self,
+        mut helper: TxHelper,
+        get_live_cell: &mut F,
+        signer: S,
+    ) -> Result<TransactionView, String>
+    where
+        F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+        S: SignerFnTrait,
+    {
+        // The hardware wallet refuses a change path which derives none of the
+        // outputs, so do not send it when there is no change output
+        let has_change = helper.transaction().outputs().len() > self.outputs.len();
+        let change_path = if has_change {
+            self.change_path.clone()
+        } else {
+            DerivationPath::empty()
+        };
+        for (lock_arg, signature) in
+            helper.sign_inputs(signer, get_live_cell, self.is_ledger, &change_path)?
+        {
+            helper.add_signature(lock_arg, Bytes::from(&serialize_signature(&signature)[..]))?;
+        }
+        helper.build_tx(get_live_cell)
+    }
+}
+
+/// Estimate the size of signed transaction, the witness lock of every input
+/// group is filled with a placeholder of the signed size (see
+/// `witness_lock_placeholder`)
+pub fn estimate_tx_size<F>(helper: &TxHelper, get_live_cell: &mut F) -> Result<usize, String>
+where
+    F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+{
+    let mut witnesses = helper.init_witnesses();
+    for ((code_hash, lock_arg), first_idx) in estimation_input_group(helper, get_live_cell)? {
+        let multisig_config = if code_hash == MULTISIG_TYPE_HASH && lock_arg.len() >= 20 {
+            helper
+                .multisig_configs()
+                .get(&H160::from_slice(&lock_arg[..20]).unwrap())
+        } else {
+            None
+        };
+        if code_hash == MULTISIG_TYPE_HASH && multisig_config.is_none() {
+            return Err(format!(
+                "No multisig config found for input(no.{})",
+                first_idx + 1
+            ));
+        }
+        let is_omnilock = helper
+            .omnilock()
+            .map(|config| config.type_hash() == &code_hash)
+            .unwrap_or(false);
+        let lock = witness_lock_placeholder(&code_hash, multisig_config, is_omnilock)
+            .ok_or_else(|| format!("Unknown witness size of lock code hash {:#x}", code_hash))?;
+        let witness = &witnesses[first_idx];
+        let witness_args = if witness.raw_data().is_empty() {
+            WitnessArgs::default()
+        } else {
+            WitnessArgs::from_slice(&witness.raw_data()).map_err(|err| err.to_string())?
+        };
+        witnesses[first_idx] = witness_args
+            .as_builder()
+            .lock(Some(lock).pack())
+            .build()
+            .as_bytes()
+            .pack();
+    }
+    let transaction = helper
+        .transaction()
+        .as_advanced_builder()
+        .set_witnesses(witnesses)
+        .build();
+    Ok(tx_size_in_block(&transaction))
+}
+
+// The first input of each lock script, unlike `TxHelper::input_group` the
+// locks not signed by the helper (anyone-can-pay and the locks of the
+// registered witness sizes) are also grouped, the cheque inputs have no
+// witness.
+fn estimation_input_group<F>(
+    helper: &TxHelper,
+    get_live_cell: &mut F,
+) -> Result<Vec<((H256, Bytes), usize)>, String>
+where
+    F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+{
+    let mut groups: Vec<((H256, Bytes), usize)> = Vec::new();
+    for (idx, input) in helper.transaction().inputs().into_iter().enumerate() {
+        let (cell_output, _) = get_live_cell(input.previous_output(), false)?;
+        let lock = cell_output.lock();
+        if helper
+            .cheque()
+            .map(|config| config.is_cheque(&lock))
+            .unwrap_or(false)
+        {
+            continue;
+        }
+        let key: (H256, Bytes) = (lock.code_hash().unpack(), lock.args().raw_data());
+        if groups.iter().all(|(group_key, _)| group_key != &key) {
+            groups.push((key, idx));
+        }
+    }
+    Ok(groups)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_calculate_fee() {
+        assert_eq!(calculate_fee(1000, 0), 0);
+        assert_eq!(calculate_fee(1000, 597), 597);
+        assert_eq!(calculate_fee(1, 597), 1);
+        assert_eq!(calculate_fee(1500, 1001), 1502);
+    }
+
+    #[test]
+    fn test_child_pays_for_parent_fee() {
+        // The parent pays 100 of the 500 required by its size
+        assert_eq!(child_pays_for_parent_fee(1000, 500, 100, 300), 700);
+        // The parent already pays enough
+        assert_eq!(child_pays_for_parent_fee(1000, 500, 10_000, 300), 300);
+    }
+
+    #[test]
+    fn test_split_change() {
+        assert_eq!(split_change(100 * ONE_CKB, 1), vec![100 * ONE_CKB]);
+        assert_eq!(split_change(100 * ONE_CKB, 4), vec![100 * ONE_CKB]);
+        assert_eq!(split_change(10 * ONE_CKB, 4), vec![10 * ONE_CKB]);
+        assert_eq!(
+            split_change(200 * ONE_CKB + 5, 3),
+            vec![66 * ONE_CKB, 66 * ONE_CKB, 68 * ONE_CKB + 5]
+        );
+        for cells in 1..10 {
+            let values = split_change(1000 * ONE_CKB + 123, cells);
+            assert_eq!(values.len(), cells);
+            assert_eq!(values.iter().sum::<u64>(), 1000 * ONE_CKB + 123);
+            assert!(values.iter().all(|value| *value >= MIN_SECP_CELL_CAPACITY));
+        }
+    }
+
+    #[test]
+    fn test_estimate_tx_size() {
+        use crate::constants::{ACP_TYPE_HASH_TESTNET, SECP_SIGNATURE_SIZE};
+        use ckb_types::{
+            core::ScriptHashType,
+            packed::{CellInput, Script},
+        };
+
+        let lock_of = |code_hash: &H256| {
+            Script::new_builder()
+                .code_hash(code_hash.pack())
+                .hash_type(ScriptHashType::Type.into())
+                .args(Bytes::from(vec![1u8; 20]).pack())
+                .build()
+        };
+        let input = |index: u32| {
+            CellInput::new_builder()
+                .previous_output(OutPoint::new(Default::default(), index))
+                .build()
+        };
+        let transaction = TransactionView::new_advanced_builder()
+            .inputs(vec![input(0), input(1)])
+            .output(CellOutput::new_builder().build())
+            .output_data(Bytes::new().pack())
+            .build();
+        let helper = TxHelper::new(transaction.clone());
+        let estimate = |lock: Script| {
+            estimate_tx_size(&helper, &mut |_: OutPoint, _: bool| {
+                let output = CellOutput::new_builder().lock(lock.clone()).build();
+                Ok((output, ckb_types::packed::Transaction::default().into()))
+            })
+        };
+
+        // The anyone-can-pay inputs (not signed by the helper) are one group
+        let witness = WitnessArgs::new_builder()
+            .lock(Some(Bytes::from(vec![0u8; SECP_SIGNATURE_SIZE])).pack())
+            .build();
+        let signed = transaction
+            .as_advanced_builder()
+            .set_witnesses(vec![witness.as_bytes().pack(), Bytes::new().pack()])
+            .build();
+        assert_eq!(
+            estimate(lock_of(&ACP_TYPE_HASH_TESTNET)),
+            Ok(tx_size_in_block(&signed))
+        );
+
+        // A lock of unknown witness size, and a multisig lock without config
+        assert!(estimate(lock_of(&H256::from([9u8; 32]))).is_err());
+        assert!(estimate(lock_of(&MULTISIG_TYPE_HASH)).is_err());
+    }
+
+    #[test]
+    fn test_multisig_partial_sign() {
+        use crate::constants::SECP_SIGNATURE_SIZE;
+        use crate::signing::SignPrehashedHelper;
+        use crate::tx_helper::SignerClosureHelper;
+        use ckb_crypto::secp::SECP256K1;
+        use ckb_hash::blake2b_256;
+        use ckb_jsonrpc_types as json_types;
+        use ckb_types::{
+            core::BlockView,
+            packed::{Script, Transaction as PackedTransaction},
+        };
+
+        const GENESIS_JSON: &str = include_str!("test-data/genesis_block.json");
+        let genesis_block: json_types::BlockView = serde_json::from_str(GENESIS_JSON).unwrap();
+        let genesis_info = GenesisInfo::from_block(&BlockView::from(genesis_block)).unwrap();
+
+        let privkeys = (1..=4u8)
+            .map(|byte| secp256k1::SecretKey::from_slice(&[byte; 32]).unwrap())
+            .collect::<Vec<_>>();
+        let lock_arg_of = |privkey: &secp256k1::SecretKey| {
+            let pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, privkey);
+            H160::from_slice(&blake2b_256(&pubkey.serialize()[..])[0..20]).unwrap()
+        };
+        let signer = |privkey: secp256k1::SecretKey| {
+            let lock_arg = lock_arg_of(&privkey);
+            SignerClosureHelper(move |lock_args: &HashSet<H160>| {
+                Ok::<_, String>(if lock_args.contains(&lock_arg) {
+                    Some(SignPrehashedHelper::new(move |digest: H256| {
+                        let message = secp256k1::Message::from_slice(digest.as_bytes()).unwrap();
+                        Ok::<_, String>(SECP256K1.sign_recoverable(&message, &privkey))
+                    }))
+                } else {
+                    None
+                })
+            })
+        };
+
+        // A 2 of 3 multisig, the 4th key is not a member
+        let config = MultisigConfig::new_with(
+            privkeys[0..3]
+                .iter()
+                .map(|privkey| AddressPayload::from_pubkey_hash(lock_arg_of(privkey)))
+                .collect(),
+            0,
+            2,
+        )
+        .unwrap();
+        let multisig_payload = config.to_address_payload(None);
+        let multisig_lock = Script::from(&multisig_payload);
+        let multisig_lock_arg = multisig_lock.args().raw_data();
+        let out_points = (0..2u32)
+            .map(|index| OutPoint::new(H256::from_slice(&[9u8; 32]).unwrap().pack(), index))
+            .collect::<Vec<_>>();
+        let mut get_live_cell = |_: OutPoint, _: bool| -> Result<_, String> {
+            let output = CellOutput::new_builder()
+                .capacity(Capacity::shannons(100 * ONE_CKB).pack())
+                .lock(multisig_lock.clone())
+                .build();
+            Ok((output, PackedTransaction::default().into()))
+        };
+        let mut collector = |_: u64| -> Result<Vec<CollectedCell>, String> {
+            Ok(out_points
+                .iter()
+                .map(|out_point| CollectedCell::new(out_point.clone(), 100 * ONE_CKB))
+                .collect())
+        };
+        let receiver = AddressPayload::from_pubkey_hash(H160::from_slice(&[7u8; 20]).unwrap());
+
+        let mut builder = TransactionBuilder::default();
+        builder
+            .output_to(&receiver, 100 * ONE_CKB, Bytes::new())
+            .change_address(multisig_payload.clone())
+            .fee_policy(FeePolicy::Fixed(1000))
+            .multisig_config(config.clone());
+        let mut helper = builder
+            .build_unsigned(&mut collector, &mut get_live_cell, &genesis_info)
+            .unwrap();
+        assert_eq!(helper.transaction().inputs().len(), 2);
+        assert_eq!(helper.transaction().outputs().len(), 2);
+        assert!(helper
+            .transaction()
+            .cell_deps()
+            .into_iter()
+            .any(|cell_dep| cell_dep == genesis_info.multisig_dep()));
+        let digest = helper
+            .signing_digests(&mut get_live_cell)
+            .unwrap()
+            .remove(&multisig_lock_arg)
+            .unwrap();
+
+        // Sign by the key of a member (or not) and collect its signature
+        let mut sign = {
+            let multisig_lock_arg = multisig_lock_arg.clone();
+            let mut get_live_cell = get_live_cell;
+            move |helper: &mut TxHelper, privkey: &secp256k1::SecretKey| {
+                let signatures = helper
+                    .sign_inputs(
+                        signer(*privkey),
+                        &mut get_live_cell,
+                        false,
+                        &DerivationPath::empty(),
+                    )
+                    .unwrap();
+                for (lock_arg, signature) in signatures {
+                    assert_eq!(lock_arg, multisig_lock_arg);
+                    // Signed the digest of the whole input group by this member
+                    let message = secp256k1::Message::from_slice(digest.as_bytes()).unwrap();
+                    let pubkey = SECP256K1.recover(&message, &signature).unwrap();
+                    assert_eq!(
+                        &blake2b_256(&pubkey.serialize()[..])[0..20],
+                        lock_arg_of(privkey).as_bytes()
+                    );
+                    helper
+                        .add_signature(lock_arg, Bytes::from(&serialize_signature(&signature)[..]))
+                        .unwrap();
+                }
+            }
+        };
+
+        // Not a member: nothing signed
+        sign(&mut helper, &privkeys[3]);
+        assert!(helper.signatures().is_empty());
+
+        // Partially signed: one signature of the threshold 2, the same
+        // signature added again is not counted
+        sign(&mut helper, &privkeys[0]);
+        sign(&mut helper, &privkeys[0]);
+        assert_eq!(helper.signatures()[&multisig_lock_arg].len(), 1);
+        let err = helper.build_tx(&mut get_live_cell).unwrap_err();
+        assert!(err.contains("got: 1, expected: 2"), "{}", err);
+
+        sign(&mut helper, &privkeys[2]);
+        let tx = helper.build_tx(&mut get_live_cell).unwrap();
+        let witnesses = tx.witnesses();
+        assert_eq!(witnesses.len(), 2);
+        // The inputs of the same lock are one group, only the first witness
+        // has the lock: the multisig script and the threshold signatures
+        let witness = WitnessArgs::from_slice(&witnesses.get(0).unwrap().raw_data()).unwrap();
+        let lock = witness.lock().to_opt().unwrap().raw_data();
+        let script_data = config.to_witness_data();
+        assert_eq!(&script_data[0..4], &[0u8, 0, 2, 3][..]);
+        assert_eq!(lock.len(), 4 + 20 * 3 + SECP_SIGNATURE_SIZE * 2);
+        assert_eq!(&lock[0..script_data.len()], &script_data[..]);
+        for signature in helper.signatures()[&multisig_lock_arg].iter() {
+            assert!(lock[script_data.len()..]
+                .chunks(SECP_SIGNATURE_SIZE)
+                .any(|chunk| chunk == signature.as_ref()));
+        }
+        assert!(witnesses.get(1).unwrap().raw_data().is_empty());
+
+        // More signatures than the threshold
+        sign(&mut helper, &privkeys[1]);
+        let err = helper.build_tx(&mut get_live_cell).unwrap_err();
+        assert!(err.contains("got: 3, expected: 2"), "{}", err);
+    }
+}
@@ -15,22 +15,30 @@ use ckb_types::{
 
 use crate::wallet::DerivationPath;
 
+use crate::cheque::{parse_cheque_args, ChequeConfig};
 use crate::constants::{MULTISIG_TYPE_HASH, SECP_SIGNATURE_SIZE, SIGHASH_TYPE_HASH};
+use crate::omnilock::{
+    omnilock_signing_message, omnilock_witness_lock, parse_omnilock_args, OmniLockConfig,
+};
 use crate::rpc::Transaction;
 use crate::signing::{FullyAbstractSingleShotSigner, SignerSingleShot};
 use crate::{AddressPayload, AddressType, CodeHashIndex, GenesisInfo, Since};
 
 // TODO: Add dao support
 
-/// A transaction helper handle input/output with secp256k1(sighash/multisg) lock
+/// A transaction helper handle input/output with secp256k1(sighash/multisg)
+/// or omnilock (secp256k1 auth) lock, the cheque inputs are unlocked by the
+/// other inputs of the receiver/sender so they are not signed
 ///  1. Sign transaction
 ///  2. Inspect transaction information
 #[derive(Clone)]
 pub struct TxHelper {
     transaction: TransactionView,
     multisig_configs: HashMap<H160, MultisigConfig>,
-    // Only support sighash/multisig signatures
+    // Only support sighash/multisig/omnilock signatures
     signatures: HashMap<Bytes, HashSet<Bytes>>,
+    omnilock: Option<OmniLockConfig>,
+    cheque: Option<ChequeConfig>,
 }
 
 impl Default for TxHelper {
@@ -39,6 +47,8 @@ impl Default for TxHelper {
             transaction: TransactionBuilder::default().build(),
             multisig_configs: HashMap::default(),
             signatures: HashMap::default(),
+            omnilock: None,
+            cheque: None,
         }
     }
 }
@@ -49,6 +59,8 @@ impl TxHelper {
             transaction,
             multisig_configs: HashMap::default(),
             signatures: HashMap::default(),
+            omnilock: None,
+            cheque: None,
         }
     }
 
@@ -61,6 +73,50 @@ impl TxHelper {
     pub fn signatures(&self) -> &HashMap<Bytes, HashSet<Bytes>> {
         &self.signatures
     }
+    pub fn omnilock(&self) -> Option<&OmniLockConfig> {
+        self.omnilock.as_ref()
+    }
+    pub fn set_omnilock(&mut self, config: Option<OmniLockConfig>) {
+        self.omnilock = config;
+    }
+    pub fn cheque(&self) -> Option<&ChequeConfig> {
+        self.cheque.as_ref()
+    }
+    pub fn set_cheque(&mut self, config: Option<ChequeConfig>) {
+        self.cheque = config;
+    }
+
+    fn is_omnilock(&self, lock: &Script) -> bool {
+        self.omnilock
+            .as_ref()
+            .map(|config| config.is_omnilock(lock))
+            .unwrap_or(false)
+    }
+
+    fn is_cheque(&self, lock: &Script) -> bool {
+        self.cheque
+            .as_ref()
+            .map(|config| config.is_cheque(lock))
+            .unwrap_or(false)
+    }
+
+    // The input groups are keyed by code hash (the hash type is always type)
+    fn is_omnilock_code_hash(&self, code_hash: &Byte32) -> bool {
+        self.omnilock
+            .as_ref()
+            .map(|config| &config.type_hash().pack() == code_hash)
+            .unwrap_or(false)
+    }
+
+    fn check_lock(&self, lock: &Script) -> Result<(), String> {
+        if self.is_omnilock(lock) {
+            parse_omnilock_args(&lock.args().raw_data()).map(|_| ())
+        } else if self.is_cheque(lock) {
+            parse_cheque_args(&lock.args().raw_data()).map(|_| ())
+        } else {
+            check_lock_script(lock)
+        }
+    }
 
     pub fn clear_inputs(&mut self) {
         self.transaction = self
@@ -93,8 +149,6 @@ impl TxHelper {
     ) -> Result<(), String> {
         let (cell_output, _) = get_live_cell(out_point.clone(), false)?;
         let lock = cell_output.lock();
-        check_lock_script(&lock)?;
-
         let since = if let Some(number) = since_absolute_epoch_opt {
             Since::new_absolute_epoch(number).value()
         } else {
@@ -107,6 +161,23 @@ impl TxHelper {
                 0
             }
         };
+        self.add_input_with_since(out_point, since, get_live_cell, genesis_info)
+    }
+
+    /// Add an input with the since required by its lock script (eg: the cheque
+    /// withdrawn by the sender)
+    pub fn add_input_with_since<
+        F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+    >(
+        &mut self,
+        out_point: OutPoint,
+        since: u64,
+        get_live_cell: &mut F,
+        genesis_info: &GenesisInfo,
+    ) -> Result<(), String> {
+        let (cell_output, _) = get_live_cell(out_point.clone(), false)?;
+        let lock = cell_output.lock();
+        self.check_lock(&lock)?;
 
         let input = CellInput::new_builder()
             .previous_output(out_point)
@@ -114,6 +185,17 @@ impl TxHelper {
             .build();
 
         self.transaction = self.transaction.as_advanced_builder().input(input).build();
+        // Keep the extra cell deps added by `add_cell_dep`
+        let mut lock_deps = vec![genesis_info.sighash_dep(), genesis_info.multisig_dep()];
+        if let Some(config) = self.omnilock.as_ref() {
+            lock_deps.push(config.cell_dep().clone());
+        }
+        let mut extra_cell_deps: Vec<CellDep> = self
+            .transaction
+            .cell_deps()
+            .into_iter()
+            .filter(|cell_dep| !lock_deps.contains(cell_dep))
+            .collect();
         let mut cell_deps: HashSet<CellDep> = HashSet::default();
         for ((code_hash, _), _) in self.input_group(get_live_cell)?.into_iter() {
             let code_hash: H256 = code_hash.unpack();
@@ -121,18 +203,49 @@ impl TxHelper {
                 cell_deps.insert(genesis_info.sighash_dep());
             } else if code_hash == MULTISIG_TYPE_HASH {
                 cell_deps.insert(genesis_info.multisig_dep());
+            } else if let Some(config) = self
+                .omnilock
+                .as_ref()
+                .filter(|config| config.type_hash() == &code_hash)
+            {
+                cell_deps.insert(config.cell_dep().clone());
+                // The secp256k1 data cell is in the sighash dep group
+                cell_deps.insert(genesis_info.sighash_dep());
             } else {
                 panic!("Unexpected input code_hash: {:#x}", code_hash);
             }
         }
+        extra_cell_deps.extend(cell_deps.into_iter());
         self.transaction = self
             .transaction
             .as_advanced_builder()
-            .set_cell_deps(cell_deps.into_iter().collect())
+            .set_cell_deps(extra_cell_deps)
             .build();
+        if self.is_cheque(&lock) {
+            let cell_dep = self.cheque.as_ref().unwrap().cell_dep().clone();
+            self.add_cell_dep(cell_dep);
+        }
         Ok(())
     }
 
+    /// Add a cell dep required by the type script of inputs/outputs (the
+    /// cell deps of sighash/multisig lock are added by `add_input`)
+    pub fn add_cell_dep(&mut self, cell_dep: CellDep) {
+        if self
+            .transaction
+            .cell_deps()
+            .into_iter()
+            .any(|dep| dep == cell_dep)
+        {
+            return;
+        }
+        self.transaction = self
+            .transaction
+            .as_advanced_builder()
+            .cell_dep(cell_dep)
+            .build();
+    }
+
     pub fn add_output(&mut self, output: CellOutput, data: Bytes) {
         // TODO: Check output(lock-script/type-script)
         self.transaction = self
@@ -144,7 +257,8 @@ impl TxHelper {
     }
 
     pub fn add_signature(&mut self, lock_arg: Bytes, signature: Bytes) -> Result<bool, String> {
-        if lock_arg.len() != 20 && lock_arg.len() != 28 {
+        // sighash/short multisig: 20, omnilock: 22, multisig with since: 28
+        if lock_arg.len() != 20 && lock_arg.len() != 22 && lock_arg.len() != 28 {
             return Err(format!(
                 "Invalid lock_arg(0x{}) length({}) with signature(0x{})",
                 hex_string(lock_arg.as_ref()).unwrap(),
@@ -192,7 +306,12 @@ impl TxHelper {
         for (idx, input) in self.transaction.inputs().into_iter().enumerate() {
             let (cell_output, _cell_transaction) = get_live_cell(input.previous_output(), false)?;
             let lock = cell_output.lock();
-            check_lock_script(&lock).map_err(|err| format!("Input(no.{}) {}", idx + 1, err))?;
+            self.check_lock(&lock)
+                .map_err(|err| format!("Input(no.{}) {}", idx + 1, err))?;
+            // Cheque inputs have no witness
+            if self.is_cheque(&lock) {
+                continue;
+            }
 
             let lock_arg = lock.args().raw_data();
             let code_hash = lock.code_hash();
@@ -311,6 +430,26 @@ impl TxHelper {
             };
         for ((code_hash, lock_arg), idxs) in input_cells.into_iter() {
             let multisig_hash160 = H160::from_slice(&lock_arg[..20]).unwrap();
+            if self.is_omnilock_code_hash(&code_hash) {
+                let mut lock_args = HashSet::default();
+                lock_args.insert(parse_omnilock_args(&lock_arg)?);
+                if let Some(mut builder) = signer.new_signature_builder(&lock_args)? {
+                    let message =
+                        omnilock_signing_message(&self.transaction.hash(), &idxs, &witnesses)?;
+                    // The ledger app only knows the sighash witness layout,
+                    // sign the digest directly
+                    if is_ledger {
+                        if !builder.sign_digest() {
+                            return Err("The signer can't sign a digest".to_string());
+                        }
+                        builder.append(&blake2b_256(&message));
+                    } else {
+                        builder.append(&message);
+                    }
+                    signatures.insert(lock_arg, Box::new(builder).finalize()?);
+                }
+                continue;
+            }
             let lock_args = if code_hash == MULTISIG_TYPE_HASH.pack() {
                 all_sighash_lock_args
                     .get(&multisig_hash160)
@@ -342,6 +481,33 @@ impl TxHelper {
         Ok(signatures)
     }
 
+    /// The blake2b hash of the message to be signed by every input group
+    /// (keyed by lock args), used to verify what is signed by an offline signer
+    pub fn signing_digests<
+        F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+    >(
+        &self,
+        get_live_cell: &mut F,
+    ) -> Result<HashMap<Bytes, H256>, String> {
+        let witnesses = self.init_witnesses();
+        let mut digests = HashMap::default();
+        for ((code_hash, lock_arg), idxs) in self.input_group(get_live_cell)?.into_iter() {
+            let multisig_hash160 = H160::from_slice(&lock_arg[..20]).unwrap();
+            let message = if self.is_omnilock_code_hash(&code_hash) {
+                omnilock_signing_message(&self.transaction.hash(), &idxs, &witnesses)?
+            } else {
+                signing_message(
+                    &self.transaction.hash(),
+                    &idxs,
+                    &witnesses,
+                    self.multisig_configs.get(&multisig_hash160),
+                )?
+            };
+            digests.insert(lock_arg, H256::from(blake2b_256(&message)));
+        }
+        Ok(digests)
+    }
+
     pub fn build_tx<F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>>(
         &self,
         get_live_cell: &mut F,
@@ -376,6 +542,15 @@ impl TxHelper {
                     data.extend_from_slice(signature.as_ref());
                 }
                 data
+            } else if self.is_omnilock_code_hash(&code_hash) {
+                if signatures.len() != 1 {
+                    return Err(format!(
+                        "Invalid omnilock signature length for lock_arg: 0x{}, got: {}, expected: 1",
+                        hex_string(&lock_arg).unwrap(),
+                        signatures.len(),
+                    ));
+                }
+                omnilock_witness_lock(signatures.iter().last().unwrap())
             } else {
                 if signatures.len() != 1 {
                     return Err(format!(
@@ -627,6 +802,22 @@ pub fn build_signature<S: SignerSingleShot<Err = String>>(
     multisig_config_opt: Option<&MultisigConfig>,
     mut signer: S,
 ) -> Result<RecoverableSignature, String> {
+    signer.append(&signing_message(
+        tx_hash,
+        input_group_idxs,
+        witnesses,
+        multisig_config_opt,
+    )?);
+    Box::new(signer).finalize()
+}
+
+/// The message (before hashing) to be signed by an input group
+pub fn signing_message(
+    tx_hash: &Byte32,
+    input_group_idxs: &[usize],
+    witnesses: &[packed::Bytes],
+    multisig_config_opt: Option<&MultisigConfig>,
+) -> Result<Vec<u8>, String> {
     let init_witness_idx = input_group_idxs[0];
     let init_witness = if witnesses[init_witness_idx].raw_data().is_empty() {
         WitnessArgs::default()
@@ -653,13 +844,76 @@ pub fn build_signature<S: SignerSingleShot<Err = String>>(
             .build()
     };
 
-    signer.append(tx_hash.as_slice());
-    signer.append(&(init_witness.as_bytes().len() as u64).to_le_bytes());
-    signer.append(&init_witness.as_bytes());
+    let mut message = Vec::new();
+    message.extend_from_slice(tx_hash.as_slice());
+    message.extend_from_slice(&(init_witness.as_bytes().len() as u64).to_le_bytes());
+    message.extend_from_slice(&init_witness.as_bytes());
     for idx in input_group_idxs.iter().skip(1).cloned() {
         let other_witness: &packed::Bytes = &witnesses[idx];
-        signer.append(&(other_witness.len() as u64).to_le_bytes());
-        signer.append(&other_witness.raw_data());
+        message.extend_from_slice(&(other_witness.len() as u64).to_le_bytes());
+        message.extend_from_slice(&other_witness.raw_data());
+    }
+    Ok(message)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sighash_address(byte: u8) -> AddressPayload {
+        AddressPayload::from_pubkey_hash(H160::from_slice(&[byte; 20]).unwrap())
+    }
+
+    #[test]
+    fn test_multisig_config() {
+        let addresses = (1..=3).map(sighash_address).collect::<Vec<_>>();
+        let config = MultisigConfig::new_with(addresses.clone(), 1, 2).unwrap();
+        let witness_data = config.to_witness_data();
+        assert_eq!(&witness_data[0..4], &[0u8, 1, 2, 3][..]);
+        assert_eq!(witness_data.len(), 4 + 20 * 3);
+        assert_eq!(&witness_data[4..24], &[1u8; 20][..]);
+        assert_eq!(
+            config.hash160().as_bytes(),
+            &blake2b_256(&witness_data)[0..20]
+        );
+        assert_eq!(
+            config.to_address_payload(None).args().as_ref(),
+            config.hash160().as_bytes()
+        );
+        assert_eq!(config.to_address_payload(Some(5)).args().len(), 28);
+
+        // The threshold can't be more than the members, and the required
+        // first n members can't be more than the threshold
+        assert!(MultisigConfig::new_with(addresses.clone(), 0, 3).is_ok());
+        assert!(MultisigConfig::new_with(addresses.clone(), 0, 4).is_err());
+        assert!(MultisigConfig::new_with(addresses.clone(), 3, 2).is_err());
+        let mut duplicated = addresses.clone();
+        duplicated.push(sighash_address(1));
+        assert!(MultisigConfig::new_with(duplicated, 0, 2).is_err());
+        let mut multisig_member = addresses;
+        multisig_member.push(config.to_address_payload(None));
+        assert!(MultisigConfig::new_with(multisig_member, 0, 2).is_err());
+    }
+
+    #[test]
+    fn test_add_signature() {
+        let mut helper = TxHelper::default();
+        let signature = Bytes::from(vec![1u8; SECP_SIGNATURE_SIZE]);
+        let lock_arg = Bytes::from(vec![2u8; 20]);
+        assert_eq!(
+            helper.add_signature(lock_arg.clone(), signature.clone()),
+            Ok(true)
+        );
+        assert_eq!(
+            helper.add_signature(lock_arg.clone(), signature.clone()),
+            Ok(false)
+        );
+        assert_eq!(helper.signatures()[&lock_arg].len(), 1);
+        assert!(helper
+            .add_signature(Bytes::from(vec![2u8; 21]), signature)
+            .is_err());
+        assert!(helper
+            .add_signature(lock_arg, Bytes::from(vec![1u8; 64]))
+            .is_err());
     }
-    Box::new(signer).finalize()
 }
@@ -0,0 +1,49 @@
+use ckb_hash::new_blake2b;
+use ckb_types::{
+    bytes::Bytes,
+    core::ScriptHashType,
+    packed::{CellInput, Script},
+    prelude::*,
+};
+
+use crate::constants::TYPE_ID_CODE_HASH;
+
+/// The Type ID args of a new cell: blake2b(first input || output index)
+pub fn type_id_args(first_input: &CellInput, output_index: u64) -> Bytes {
+    let mut blake2b = new_blake2b();
+    blake2b.update(first_input.as_slice());
+    blake2b.update(&output_index.to_le_bytes());
+    let mut args = [0u8; 32];
+    blake2b.finalize(&mut args);
+    Bytes::from(args.to_vec())
+}
+
+pub fn type_id_script(args: Bytes) -> Script {
+    Script::new_builder()
+        .code_hash(TYPE_ID_CODE_HASH.pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(args.pack())
+        .build()
+}
+
+pub fn is_type_id(script: &Script) -> bool {
+    script.code_hash() == TYPE_ID_CODE_HASH.pack()
+        && script.hash_type() == ScriptHashType::Type.into()
+        && script.args().raw_data().len() == 32
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ckb_types::packed::OutPoint;
+
+    #[test]
+    fn test_type_id_args() {
+        let input = CellInput::new(OutPoint::default(), 0);
+        let args = type_id_args(&input, 0);
+        assert_eq!(args.len(), 32);
+        assert_ne!(args, type_id_args(&input, 1));
+        assert!(is_type_id(&type_id_script(args)));
+        assert!(!is_type_id(&type_id_script(Bytes::from(vec![0u8; 20]))));
+    }
+}
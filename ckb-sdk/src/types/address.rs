@@ -1,4 +1,4 @@
-use std::convert::TryInto;
+use std::convert::TryFrom;
 use std::fmt;
 use std::str::FromStr;
 
@@ -7,7 +7,7 @@ use ckb_hash::blake2b_256;
 use ckb_types::{
     bytes::Bytes,
     core::ScriptHashType,
-    packed::{Byte32, Script},
+    packed::{Byte, Byte32, Script},
     prelude::*,
     H160, H256,
 };
@@ -21,14 +21,20 @@ pub use old_addr::{Address as OldAddress, AddressFormat as OldAddressFormat};
 #[derive(Hash, Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum AddressType {
+    // The full format of CKB2021 (bech32m): code_hash, hash_type and args
+    Full = 0x00,
+    // Deprecated since CKB2021 (bech32)
     Short = 0x01,
+    // Deprecated since CKB2021 (bech32)
     FullData = 0x02,
+    // Deprecated since CKB2021 (bech32)
     FullType = 0x04,
 }
 
 impl AddressType {
     pub fn from_u8(value: u8) -> Result<AddressType, String> {
         match value {
+            0x00 => Ok(AddressType::Full),
             0x01 => Ok(AddressType::Short),
             0x02 => Ok(AddressType::FullData),
             0x04 => Ok(AddressType::FullType),
@@ -56,6 +62,47 @@ impl CodeHashIndex {
     }
 }
 
+/// The hash type of a full address, `ScriptHashType` of ckb-types does not
+/// know `data1` (the scripts run by CKB-VM version 1 since CKB2021)
+#[derive(Hash, Eq, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum AddressHashType {
+    Data = 0x00,
+    Type = 0x01,
+    Data1 = 0x02,
+}
+
+impl AddressHashType {
+    pub fn from_u8(value: u8) -> Result<AddressHashType, String> {
+        match value {
+            0x00 => Ok(AddressHashType::Data),
+            0x01 => Ok(AddressHashType::Type),
+            0x02 => Ok(AddressHashType::Data1),
+            _ => Err(format!("Invalid hash_type: {}", value)),
+        }
+    }
+}
+
+impl From<ScriptHashType> for AddressHashType {
+    fn from(hash_type: ScriptHashType) -> AddressHashType {
+        match hash_type {
+            ScriptHashType::Data => AddressHashType::Data,
+            ScriptHashType::Type => AddressHashType::Type,
+        }
+    }
+}
+
+impl fmt::Display for AddressHashType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = match self {
+            AddressHashType::Data => "data",
+            AddressHashType::Type => "type",
+            AddressHashType::Data1 => "data1",
+        };
+        write!(f, "{}", value)
+    }
+}
+
 #[derive(Hash, Eq, PartialEq, Clone)]
 pub enum AddressPayload {
     Short {
@@ -63,7 +110,7 @@ pub enum AddressPayload {
         hash: H160,
     },
     Full {
-        hash_type: ScriptHashType,
+        hash_type: AddressHashType,
         code_hash: Byte32,
         args: Bytes,
     },
@@ -76,7 +123,7 @@ impl AddressPayload {
 
     pub fn new_full(hash_type: ScriptHashType, code_hash: Byte32, args: Bytes) -> AddressPayload {
         AddressPayload::Full {
-            hash_type,
+            hash_type: hash_type.into(),
             code_hash,
             args,
         }
@@ -87,20 +134,31 @@ impl AddressPayload {
     pub fn new_full_type(code_hash: Byte32, args: Bytes) -> AddressPayload {
         Self::new_full(ScriptHashType::Type, code_hash, args)
     }
+    /// Only the full format of CKB2021 can encode it
+    pub fn new_full_data1(code_hash: Byte32, args: Bytes) -> AddressPayload {
+        AddressPayload::Full {
+            hash_type: AddressHashType::Data1,
+            code_hash,
+            args,
+        }
+    }
 
+    /// The address type of the deprecated formats, `Full` for the `data1`
+    /// hash type which has no deprecated format
     pub fn ty(&self) -> AddressType {
         match self {
             AddressPayload::Short { .. } => AddressType::Short,
             AddressPayload::Full { hash_type, .. } => match hash_type {
-                ScriptHashType::Data => AddressType::FullData,
-                ScriptHashType::Type => AddressType::FullType,
+                AddressHashType::Data => AddressType::FullData,
+                AddressHashType::Type => AddressType::FullType,
+                AddressHashType::Data1 => AddressType::Full,
             },
         }
     }
 
-    pub fn hash_type(&self) -> ScriptHashType {
+    pub fn hash_type(&self) -> AddressHashType {
         match self {
-            AddressPayload::Short { .. } => ScriptHashType::Type,
+            AddressPayload::Short { .. } => AddressHashType::Type,
             AddressPayload::Full { hash_type, .. } => *hash_type,
         }
     }
@@ -156,13 +214,8 @@ impl AddressPayload {
 
 impl fmt::Debug for AddressPayload {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let hash_type = if self.hash_type() == ScriptHashType::Type {
-            "type"
-        } else {
-            "data"
-        };
         f.debug_struct("AddressPayload")
-            .field("hash_type", &hash_type)
+            .field("hash_type", &self.hash_type().to_string())
             .field("code_hash", &self.code_hash())
             .field("args", &self.args())
             .finish()
@@ -172,39 +225,43 @@ impl fmt::Debug for AddressPayload {
 impl From<&AddressPayload> for Script {
     fn from(payload: &AddressPayload) -> Script {
         Script::new_builder()
-            .hash_type(payload.hash_type().into())
+            .hash_type(Byte::new(payload.hash_type() as u8))
             .code_hash(payload.code_hash())
             .args(payload.args().pack())
             .build()
     }
 }
 
-impl From<Script> for AddressPayload {
-    fn from(lock: Script) -> AddressPayload {
-        let hash_type: ScriptHashType = lock.hash_type().try_into().expect("Invalid hash_type");
+/// An unknown `hash_type` byte is an error, the script may be untrusted (eg:
+/// from a transaction file)
+impl TryFrom<Script> for AddressPayload {
+    type Error = String;
+
+    fn try_from(lock: Script) -> Result<AddressPayload, String> {
+        let hash_type = AddressHashType::from_u8(lock.hash_type().into())?;
         let code_hash = lock.code_hash();
         let code_hash_h256: H256 = code_hash.unpack();
         let args = lock.args().raw_data();
-        if hash_type == ScriptHashType::Type
+        if hash_type == AddressHashType::Type
             && code_hash_h256 == SIGHASH_TYPE_HASH
             && args.len() == 20
         {
             let index = CodeHashIndex::Sighash;
             let hash = H160::from_slice(args.as_ref()).unwrap();
-            AddressPayload::Short { index, hash }
-        } else if hash_type == ScriptHashType::Type
+            Ok(AddressPayload::Short { index, hash })
+        } else if hash_type == AddressHashType::Type
             && code_hash_h256 == MULTISIG_TYPE_HASH
             && args.len() == 20
         {
             let index = CodeHashIndex::Multisig;
             let hash = H160::from_slice(args.as_ref()).unwrap();
-            AddressPayload::Short { index, hash }
+            Ok(AddressPayload::Short { index, hash })
         } else {
-            AddressPayload::Full {
+            Ok(AddressPayload::Full {
                 hash_type,
                 code_hash,
                 args,
-            }
+            })
         }
     }
 }
@@ -243,6 +300,8 @@ impl Address {
                 data[1..].copy_from_slice(payload_data.as_slice());
                 data
             }
+            // The data1 hash type has no deprecated format
+            AddressType::Full => return self.display_full_with_network(network),
         };
         let base32 = data.to_base32();
         debug!("ascii 32 {}", {
@@ -257,52 +316,186 @@ impl Address {
             .unwrap_or_else(|_| panic!("Encode address failed: payload={:?}", self.payload));
         format!("{}", value)
     }
-}
 
-impl fmt::Display for Address {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        write!(f, "{}", self.display_with_network(self.network))
+    /// Encode in the full format of CKB2021 (bech32m), every lock script
+    /// (including the ones have short addresses) can be encoded.
+    pub fn display_full_with_network(&self, network: NetworkType) -> String {
+        let mut data = vec![AddressType::Full as u8];
+        data.extend_from_slice(self.payload.code_hash().as_slice());
+        data.push(self.payload.hash_type() as u8);
+        data.extend_from_slice(self.payload.args().as_ref());
+        bech32m::encode(network.to_prefix(), &data)
     }
-}
 
-impl FromStr for Address {
-    type Err = String;
+    pub fn to_full_string(&self) -> String {
+        self.display_full_with_network(self.network)
+    }
 
-    fn from_str(input: &str) -> Result<Self, Self::Err> {
-        let value = Bech32::from_str(input).map_err(|err| err.to_string())?;
-        let network = NetworkType::from_prefix(value.hrp())
-            .ok_or_else(|| format!("Invalid hrp: {}", value.hrp()))?;
-        let data = convert_bits(value.data(), 5, 8, false).unwrap();
+    /// Parse the address and return the type of its format, the deprecated
+    /// formats (short, full data and full type) are encoded by bech32 while
+    /// the full format is encoded by bech32m.
+    pub fn from_str_with_type(input: &str) -> Result<(Address, AddressType), String> {
+        let (hrp, data) = match Bech32::from_str(input) {
+            Ok(value) => {
+                let data = convert_bits(value.data(), 5, 8, false).unwrap();
+                if data.first() == Some(&(AddressType::Full as u8)) {
+                    return Err("The full address must be encoded by bech32m".to_string());
+                }
+                (value.hrp().to_string(), data)
+            }
+            Err(err) => {
+                let (hrp, data) = bech32m::decode(input).map_err(|_| err.to_string())?;
+                if data.first() != Some(&(AddressType::Full as u8)) {
+                    return Err("Only the full address can be encoded by bech32m".to_string());
+                }
+                (hrp, data)
+            }
+        };
+        let network =
+            NetworkType::from_prefix(&hrp).ok_or_else(|| format!("Invalid hrp: {}", hrp))?;
+        if data.is_empty() {
+            return Err("Empty address data".to_string());
+        }
         let ty = AddressType::from_u8(data[0])?;
-        match ty {
+        let payload = match ty {
+            AddressType::Full => {
+                if data.len() < 34 {
+                    return Err(format!("Insufficient data length: {}", data.len()));
+                }
+                let code_hash = Byte32::from_slice(&data[1..33]).unwrap();
+                let hash_type = AddressHashType::from_u8(data[33])?;
+                let script = Script::new_builder()
+                    .code_hash(code_hash)
+                    .hash_type(Byte::new(hash_type as u8))
+                    .args(Bytes::from(&data[34..]).pack())
+                    .build();
+                // Same payload as the short address for the short scripts
+                AddressPayload::try_from(script)?
+            }
             AddressType::Short => {
                 if data.len() != 22 {
                     return Err(format!("Invalid input data length {}", data.len()));
                 }
                 let index = CodeHashIndex::from_u8(data[1])?;
                 let hash = H160::from_slice(&data[2..22]).unwrap();
-                let payload = AddressPayload::Short { index, hash };
-                Ok(Address { network, payload })
+                AddressPayload::Short { index, hash }
             }
             AddressType::FullData | AddressType::FullType => {
-                if data.len() < 32 {
+                if data.len() < 33 {
                     return Err(format!("Insufficient data length: {}", data.len()));
                 }
                 let hash_type = if ty == AddressType::FullData {
-                    ScriptHashType::Data
+                    AddressHashType::Data
                 } else {
-                    ScriptHashType::Type
+                    AddressHashType::Type
                 };
                 let code_hash = Byte32::from_slice(&data[1..33]).unwrap();
                 let args = Bytes::from(&data[33..]);
-                let payload = AddressPayload::Full {
+                AddressPayload::Full {
                     hash_type,
                     code_hash,
                     args,
-                };
-                Ok(Address { network, payload })
+                }
+            }
+        };
+        Ok((Address { network, payload }, ty))
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.display_with_network(self.network))
+    }
+}
+
+impl FromStr for Address {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Address::from_str_with_type(input).map(|(address, _)| address)
+    }
+}
+
+// The bech32 crate in use predates BIP-350, only the checksum differs from bech32.
+mod bech32m {
+    use super::convert_bits;
+
+    const CHARSET: &[u8] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+    const BECH32M_CONST: u32 = 0x2bc8_30a3;
+    const GEN: [u32; 5] = [
+        0x3b6a_57b2,
+        0x2650_8e6d,
+        0x1ea1_19fa,
+        0x3d42_33dd,
+        0x2a14_62b3,
+    ];
+
+    fn polymod(values: &[u8]) -> u32 {
+        let mut chk: u32 = 1;
+        for value in values {
+            let top = chk >> 25;
+            chk = ((chk & 0x01ff_ffff) << 5) ^ u32::from(*value);
+            for (i, gen) in GEN.iter().enumerate() {
+                if (top >> i) & 1 == 1 {
+                    chk ^= gen;
+                }
             }
         }
+        chk
+    }
+
+    fn hrp_expand(hrp: &[u8]) -> Vec<u8> {
+        let mut values: Vec<u8> = hrp.iter().map(|c| c >> 5).collect();
+        values.push(0);
+        values.extend(hrp.iter().map(|c| c & 0x1f));
+        values
+    }
+
+    pub fn encode(hrp: &str, data: &[u8]) -> String {
+        let mut values = convert_bits(data, 8, 5, true).expect("convert bits");
+        let mut checked = hrp_expand(hrp.as_bytes());
+        checked.extend_from_slice(&values);
+        checked.extend_from_slice(&[0; 6]);
+        let checksum = polymod(&checked) ^ BECH32M_CONST;
+        values.extend((0..6).map(|i| ((checksum >> (5 * (5 - i))) & 0x1f) as u8));
+        let mut output = format!("{}1", hrp);
+        output.extend(values.iter().map(|v| CHARSET[*v as usize] as char));
+        output
+    }
+
+    pub fn decode(input: &str) -> Result<(String, Vec<u8>), String> {
+        let lowercase = input.to_lowercase();
+        if lowercase != input && input.to_uppercase() != input {
+            return Err("Mixed case".to_string());
+        }
+        let pos = lowercase
+            .rfind('1')
+            .ok_or_else(|| "Missing separator".to_string())?;
+        let (hrp, data_part) = (&lowercase[..pos], &lowercase[pos + 1..]);
+        if hrp.is_empty() || data_part.len() < 6 {
+            return Err("Invalid length".to_string());
+        }
+        if hrp.bytes().any(|c| c < 33 || c > 126) {
+            return Err("Invalid character in hrp".to_string());
+        }
+        let values = data_part
+            .bytes()
+            .map(|c| {
+                CHARSET
+                    .iter()
+                    .position(|v| *v == c)
+                    .map(|v| v as u8)
+                    .ok_or_else(|| format!("Invalid character: {}", c as char))
+            })
+            .collect::<Result<Vec<u8>, String>>()?;
+        let mut checked = hrp_expand(hrp.as_bytes());
+        checked.extend_from_slice(&values);
+        if polymod(&checked) != BECH32M_CONST {
+            return Err("Invalid checksum".to_string());
+        }
+        let data = convert_bits(&values[..values.len() - 6], 5, 8, false)
+            .map_err(|err| err.to_string())?;
+        Ok((hrp.to_string(), data))
     }
 }
 
@@ -479,4 +672,55 @@ mod test {
         assert_eq!(address.to_string(), "ckb1qjda0cr08m85hc8jlnfp3zer7xulejywt49kt2rr0vthywaa50xw3vumhs9nvu786dj9p0q5elx66t24n3kxgj53qks");
         assert_eq!(address, Address::from_str("ckb1qjda0cr08m85hc8jlnfp3zer7xulejywt49kt2rr0vthywaa50xw3vumhs9nvu786dj9p0q5elx66t24n3kxgj53qks").unwrap());
     }
+
+    #[test]
+    fn test_full_format_address() {
+        let payload =
+            AddressPayload::from_pubkey_hash(h160!("0xb39bbc0b3673c7d36450bc14cfcdad2d559c6c64"));
+        let address = Address::new(NetworkType::Mainnet, payload);
+        let full = "ckb1qzda0cr08m85hc8jlnfp3zer7xulejywt49kt2rr0vthywaa50xwsqdnnw7qkdnnclfkg59uzn8umtfd2kwxceqxwquc4";
+        assert_eq!(address.to_full_string(), full);
+        assert_eq!(
+            Address::from_str_with_type(full).unwrap(),
+            (address.clone(), AddressType::Full)
+        );
+        assert_eq!(
+            Address::from_str_with_type("ckb1qyqt8xaupvm8837nv3gtc9x0ekkj64vud3jqfwyw5v").unwrap(),
+            (address, AddressType::Short)
+        );
+        // Invalid checksum
+        let bad = "ckb1qzda0cr08m85hc8jlnfp3zer7xulejywt49kt2rr0vthywaa50xwsqdnnw7qkdnnclfkg59uzn8umtfd2kwxceqxwquc5";
+        assert!(Address::from_str(bad).is_err());
+    }
+
+    #[test]
+    fn test_data1_address() {
+        let code_hash = Byte32::from_slice(
+            h256!("0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8").as_bytes(),
+        )
+        .unwrap();
+        let args = Bytes::from(h160!("0xb39bbc0b3673c7d36450bc14cfcdad2d559c6c64").as_bytes());
+        let script = Script::new_builder()
+            .code_hash(code_hash.clone())
+            .hash_type(Byte::new(0x02))
+            .args(args.pack())
+            .build();
+        let payload = AddressPayload::try_from(script.clone()).unwrap();
+        assert_eq!(payload, AddressPayload::new_full_data1(code_hash, args));
+        assert_eq!(payload.hash_type(), AddressHashType::Data1);
+        assert_eq!(payload.ty(), AddressType::Full);
+        assert_eq!(Script::from(&payload).as_slice(), script.as_slice());
+
+        // Only encoded in the full format
+        let address = Address::new(NetworkType::Mainnet, payload);
+        let full = address.to_full_string();
+        assert_eq!(address.to_string(), full);
+        assert_eq!(
+            Address::from_str_with_type(&full).unwrap(),
+            (address, AddressType::Full)
+        );
+
+        let invalid = script.as_builder().hash_type(Byte::new(0x03)).build();
+        assert!(AddressPayload::try_from(invalid).is_err());
+    }
 }
@@ -7,7 +7,8 @@ use serde_derive::{Deserialize, Serialize};
 use std::fmt;
 
 pub use address::{
-    Address, AddressPayload, AddressType, CodeHashIndex, OldAddress, OldAddressFormat,
+    Address, AddressHashType, AddressPayload, AddressType, CodeHashIndex, OldAddress,
+    OldAddressFormat,
 };
 pub use human_capacity::HumanCapacity;
 pub use since::{Since, SinceType};
@@ -33,6 +33,9 @@ pub enum Error {
     #[fail(display = "Unsupported kdf: {}", _0)]
     UnsupportedKdf(String),
 
+    #[fail(display = "Invalid kdf params: {}", _0)]
+    InvalidKdfParams(String),
+
     #[fail(display = "Generate secp256k1 secret failed, tried: {}", _0)]
     GenSecpFailed(u16),
 
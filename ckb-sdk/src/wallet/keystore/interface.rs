@@ -285,3 +285,13 @@ pub fn is_valid_derivation_path(path: &[ChildNumber]) -> bool {
         .zip(MANDATORY_PREFIX.iter())
         .all(|(x, y)| x == Some(y))
 }
+
+/// Account level of BIP-44 path: m / purpose' / coin_type' / account'
+pub const ACCOUNT_LEVEL_DEPTH: usize = 3;
+
+/// Check if all the account level children of the path are hardened.
+pub fn is_hardened_account_path(path: &[ChildNumber]) -> bool {
+    path.iter()
+        .take(ACCOUNT_LEVEL_DEPTH)
+        .all(|child| child.is_hardened())
+}
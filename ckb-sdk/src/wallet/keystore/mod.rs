@@ -31,8 +31,12 @@ use uuid::Uuid;
 use void::*;
 
 pub use error::Error;
-pub use passphrase::{CipherParams, Crypto, KdfParams, ScryptParams, ScryptType};
-pub use util::{zeroize_privkey, zeroize_slice};
+pub use passphrase::{
+    Argon2Params, CipherParams, Crypto, KdfParams, ScryptParams, ScryptType,
+    ARGON2_DEFAULT_ITERATIONS, ARGON2_DEFAULT_MEMORY, ARGON2_MAX_ITERATIONS, ARGON2_MAX_MEMORY,
+    ARGON2_MAX_PARALLELISM,
+};
+pub use util::{write_file_atomic, zeroize_privkey, zeroize_slice};
 
 const KEYSTORE_VERSION: u32 = 3;
 const KEYSTORE_ORIGIN: &str = "ckb-cli";
@@ -105,9 +109,21 @@ impl AbstractKeyStore for KeyStore {
 
 impl KeyStore {
     pub fn new_account(&mut self, password: &[u8]) -> Result<H160, Error> {
+        let kdfparams = self.storage.default_kdfparams();
+        self.new_account_with_kdf(password, kdfparams)
+    }
+    /// Create an account encrypted by the KDF instead of the scrypt type of
+    /// the key store (e.g. argon2id)
+    pub fn new_account_with_kdf(
+        &mut self,
+        password: &[u8],
+        kdfparams: KdfParams,
+    ) -> Result<H160, Error> {
         let privkey = MasterPrivKey::try_new(1024)?;
         let key = Key::new(privkey);
-        let abs_path = self.storage.store_key(key.filename(), &key, password)?;
+        let abs_path =
+            self.storage
+                .store_key_with_kdf(key.filename(), &key, password, kdfparams)?;
         let hash160 = key.hash160().clone();
         self.files.insert(hash160.clone(), abs_path);
         Ok(hash160)
@@ -135,10 +151,54 @@ impl KeyStore {
         self.refresh_dir()?;
         let filepath = self.get_filepath(hash160)?;
         let key = self.storage.get_key(hash160, &filepath, password)?;
+        // Keep the KDF of the file, an argon2id file is not downgraded
+        let kdfparams = self.storage.get_kdfparams(&filepath)?.with_new_salt();
         self.storage
-            .store_key(&filepath, &key, new_password)
+            .store_key_with_kdf(&filepath, &key, new_password, kdfparams)
             .map(|_| ())
     }
+    /// The KDF of the new key files: scrypt of the scrypt type of the keystore
+    pub fn default_kdfparams(&self) -> KdfParams {
+        self.storage.default_kdfparams()
+    }
+    /// Re-encrypt the key by the new password (and KDF, the one of the file is
+    /// kept by default). The new file is checked before it atomically replaces
+    /// the old one, which is kept as `<file>.<timestamp>.bak` (its path is
+    /// returned).
+    pub fn change_password(
+        &mut self,
+        hash160: &H160,
+        password: &[u8],
+        new_password: &[u8],
+        kdfparams: Option<KdfParams>,
+    ) -> Result<PathBuf, Error> {
+        self.refresh_dir()?;
+        let filepath = self.get_filepath(hash160)?;
+        let key = self.storage.get_key(hash160, &filepath, password)?;
+        let kdfparams = match kdfparams {
+            Some(kdfparams) => kdfparams,
+            None => self.storage.get_kdfparams(&filepath)?.with_new_salt(),
+        };
+        let json_value = key.to_json_with_kdf(new_password, kdfparams)?;
+        if Key::from_json(&json_value, new_password)?.hash160() != hash160 {
+            return Err(Error::CheckPasswordFailed);
+        }
+
+        let tmp_path = path_with_suffix(&filepath, ".tmp");
+        {
+            let mut file = fs::File::create(&tmp_path)?;
+            serde_json::to_writer(&mut file, &json_value)
+                .map_err(|err| Error::Io(err.to_string()))?;
+            file.sync_all()?;
+        }
+        let backup_path = path_with_suffix(
+            &filepath,
+            &format!(".{}.bak", Utc::now().format("%Y%m%dT%H%M%S")),
+        );
+        fs::copy(&filepath, &backup_path)?;
+        fs::rename(&tmp_path, &filepath)?;
+        Ok(backup_path)
+    }
     pub fn delete(&mut self, hash160: &H160, password: &[u8]) -> Result<(), Error> {
         self.refresh_dir()?;
         let filepath = self.get_filepath(hash160)?;
@@ -181,6 +241,39 @@ impl KeyStore {
             Ok(key.hash160().clone())
         }
     }
+    /// Copy a key file of another key store as it is (still encrypted by its
+    /// own password). Only the format is checked, the key is not decrypted.
+    pub fn import_file(&mut self, path: &Path) -> Result<H160, Error> {
+        self.refresh_dir()?;
+        let data = self.storage.read_json(path)?;
+        let hash160 = util::get_str(&data, "hash160").and_then(|hash160_hex| {
+            let mut hash160_bin = [0u8; 20];
+            hex_decode(hash160_hex.as_bytes(), &mut hash160_bin)
+                .ok()
+                .and_then(|_| H160::from_slice(&hash160_bin).ok())
+                .ok_or_else(|| Error::ParseJsonFailed(format!("Invalid hash160: {}", hash160_hex)))
+        })?;
+        util::get_value(&data, "crypto").and_then(Crypto::from_json)?;
+        if self.files.contains_key(&hash160) {
+            return Err(Error::KeyExists(hash160));
+        }
+        // The key files are found by the hash160 suffix of the name
+        let filename = path
+            .file_name()
+            .filter(|name| {
+                name.to_str()
+                    .map(|name| name.ends_with(&format!("--{:x}", hash160)))
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| Error::Io(format!("Invalid key file name: {}", path.display())))?;
+        let filepath = self.keys_dir.join(filename);
+        if filepath.exists() {
+            return Err(Error::Io(format!("File exists: {}", filepath.display())));
+        }
+        fs::copy(path, &filepath)?;
+        self.files.insert(hash160.clone(), filepath);
+        Ok(hash160)
+    }
     pub fn import_secp_key(
         &mut self,
         key: &secp256k1::SecretKey,
@@ -385,6 +478,13 @@ impl KeyStore {
     }
 }
 
+// `<path><suffix>`, the key files are ignored by `refresh_dir` once suffixed
+fn path_with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
 /// KeyStore protected by password
 #[derive(Clone)]
 struct PassphraseKeyStore {
@@ -393,6 +493,16 @@ struct PassphraseKeyStore {
 }
 
 impl PassphraseKeyStore {
+    fn default_kdfparams(&self) -> KdfParams {
+        KdfParams::Scrypt(ScryptParams::new(self.scrypt_type))
+    }
+
+    fn read_json<P: AsRef<Path>>(&self, filename: P) -> Result<serde_json::Value, Error> {
+        let filepath = self.join_path(filename);
+        let mut file = fs::File::open(&filepath)?;
+        serde_json::from_reader(&mut file).map_err(|err| Error::ParseJsonFailed(err.to_string()))
+    }
+
     // Loads and decrypts the key from disk.
     fn get_key<P: AsRef<Path>>(
         &self,
@@ -400,10 +510,7 @@ impl PassphraseKeyStore {
         filename: P,
         password: &[u8],
     ) -> Result<Key, Error> {
-        let filepath = self.join_path(filename);
-        let mut file = fs::File::open(&filepath)?;
-        let data = serde_json::from_reader(&mut file)
-            .map_err(|err| Error::ParseJsonFailed(err.to_string()))?;
+        let data = self.read_json(filename)?;
         let key = Key::from_json(&data, password)?;
         if key.hash160() != hash160 {
             return Err(Error::KeyMismatch {
@@ -414,15 +521,33 @@ impl PassphraseKeyStore {
         Ok(key)
     }
 
+    // The KDF of the key on disk, without decrypting it.
+    fn get_kdfparams<P: AsRef<Path>>(&self, filename: P) -> Result<KdfParams, Error> {
+        let data = self.read_json(filename)?;
+        util::get_value(&data, "crypto")
+            .and_then(Crypto::from_json)
+            .map(|crypto| crypto.kdfparams().clone())
+    }
+
     // Writes and encrypts the key.
     fn store_key<P: AsRef<Path>>(
         &self,
         filename: P,
         key: &Key,
         password: &[u8],
+    ) -> Result<PathBuf, Error> {
+        self.store_key_with_kdf(filename, key, password, self.default_kdfparams())
+    }
+
+    fn store_key_with_kdf<P: AsRef<Path>>(
+        &self,
+        filename: P,
+        key: &Key,
+        password: &[u8],
+        kdfparams: KdfParams,
     ) -> Result<PathBuf, Error> {
         let filepath = self.join_path(filename);
-        let json_value = key.to_json(password, self.scrypt_type);
+        let json_value = key.to_json_with_kdf(password, kdfparams)?;
         let mut file = fs::File::create(&filepath)?;
         serde_json::to_writer(&mut file, &json_value).map_err(|err| Error::Io(err.to_string()))?;
         Ok(filepath)
@@ -631,18 +756,33 @@ impl Key {
     }
 
     pub fn to_json(&self, password: &[u8], scrypt_type: ScryptType) -> serde_json::Value {
+        let kdfparams = KdfParams::Scrypt(ScryptParams::new(scrypt_type));
+        self.to_json_with_kdf(password, kdfparams)
+            .expect("encrypt key scrypt failed")
+    }
+
+    pub fn to_json_with_kdf(
+        &self,
+        password: &[u8],
+        kdfparams: KdfParams,
+    ) -> Result<serde_json::Value, Error> {
         let mut buf = Uuid::encode_buffer();
         let id_str = self.id.to_hyphenated().encode_lower(&mut buf);
         let hash160_hex = format!("{:x}", self.hash160);
         let master_privkey = self.master_privkey.to_bytes();
-        let crypto = Crypto::encrypt_key_scrypt(&master_privkey, password, scrypt_type);
-        serde_json::json!({
+        let crypto = Crypto::encrypt_key(
+            &master_privkey,
+            password,
+            kdfparams,
+            CipherParams::default(),
+        )?;
+        Ok(serde_json::json!({
             "origin": KEYSTORE_ORIGIN,
             "id": id_str,
             "version": KEYSTORE_VERSION,
             "hash160": hash160_hex,
             "crypto": crypto.to_json(),
-        })
+        }))
     }
 }
 
@@ -692,6 +832,16 @@ impl MasterPrivKey {
         }
     }
 
+    /// Generate the master key from a seed (BIP-32), e.g. the seed of a BIP-39
+    /// mnemonic, the same as other HD wallets (Neuron)
+    pub fn from_seed(seed: &[u8]) -> Result<MasterPrivKey, Error> {
+        let master = ExtendedPrivKey::new_master(seed).map_err(|_| Error::InvalidSecpSecret)?;
+        Ok(MasterPrivKey {
+            secp_secret_key: master.private_key,
+            chain_code: master.chain_code.0,
+        })
+    }
+
     pub fn from_bytes(bytes: [u8; 64]) -> Result<MasterPrivKey, Error> {
         let secp_secret_key = secp256k1::SecretKey::from_slice(&bytes[0..32])
             .map_err(|_| Error::InvalidSecpSecret)?;
@@ -744,3 +894,83 @@ impl Drop for MasterPrivKey {
         zeroize_slice(&mut self.chain_code);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_change_password() {
+        let dir = std::env::temp_dir().join(format!("ckb-sdk-keystore-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let scrypt_type = ScryptType::Custom { log_n: 4, p: 1 };
+        let mut key_store = KeyStore::from_dir(dir.clone(), scrypt_type).unwrap();
+        let hash160 = key_store.new_account(b"old").unwrap();
+        let filepath = key_store.get_filepath(&hash160).unwrap();
+
+        // Wrong old password, the file is not touched
+        let content = fs::read(&filepath).unwrap();
+        assert!(key_store
+            .change_password(&hash160, b"wrong", b"new", None)
+            .is_err());
+        assert_eq!(fs::read(&filepath).unwrap(), content);
+
+        let backup_path = key_store
+            .change_password(&hash160, b"old", b"new", None)
+            .unwrap();
+        assert!(key_store.get_key(&hash160, b"old").is_err());
+        assert!(key_store.get_key(&hash160, b"new").is_ok());
+        // The backup is the old file, encrypted by the old password
+        assert_eq!(fs::read(&backup_path).unwrap(), content);
+        // The scrypt parameters of the file are kept
+        let data: serde_json::Value =
+            serde_json::from_slice(&fs::read(&filepath).unwrap()).unwrap();
+        assert_eq!(data["crypto"]["kdfparams"]["n"], serde_json::json!(16));
+        assert_eq!(
+            key_store.default_kdfparams().to_json()["n"],
+            serde_json::json!(16)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_import_file() {
+        let dir = std::env::temp_dir().join(format!("ckb-sdk-import-file-{}", std::process::id()));
+        fs::create_dir_all(dir.join("source")).unwrap();
+        fs::create_dir_all(dir.join("keystore")).unwrap();
+        let scrypt_type = ScryptType::Custom { log_n: 4, p: 1 };
+        let mut source = KeyStore::from_dir(dir.join("source"), scrypt_type).unwrap();
+        let mut key_store = KeyStore::from_dir(dir.join("keystore"), scrypt_type).unwrap();
+        let hash160 = source.new_account(b"source").unwrap();
+        let source_path = source.get_filepath(&hash160).unwrap();
+
+        // Copied as it is, still encrypted by the source password
+        assert_eq!(key_store.import_file(&source_path), Ok(hash160.clone()));
+        let filepath = key_store.get_filepath(&hash160).unwrap();
+        assert_eq!(
+            filepath.parent(),
+            Some(dir.join("keystore").canonicalize().unwrap().as_path())
+        );
+        assert_eq!(
+            fs::read(&filepath).unwrap(),
+            fs::read(&source_path).unwrap()
+        );
+        assert!(key_store.get_key(&hash160, b"source").is_ok());
+        assert_eq!(
+            key_store.import_file(&source_path),
+            Err(Error::KeyExists(hash160))
+        );
+
+        // The name must end with the hash160 of the content
+        let other = source.new_account(b"source").unwrap();
+        let renamed = dir.join("renamed.json");
+        fs::copy(source.get_filepath(&other).unwrap(), &renamed).unwrap();
+        assert!(key_store.import_file(&renamed).is_err());
+        // Not a key file
+        let invalid = dir.join(format!("invalid--{:x}", other));
+        fs::write(&invalid, b"{}").unwrap();
+        assert!(key_store.import_file(&invalid).is_err());
+        assert!(!key_store.has_account(&other));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,6 +1,8 @@
 //! Web3 Secret Storage
 //! https://github.com/ethereum/wiki/wiki/Web3-Secret-Storage-Definition
 
+use std::convert::TryFrom;
+
 use aes_ctr::stream_cipher::generic_array::GenericArray;
 use aes_ctr::stream_cipher::{NewStreamCipher, SyncStreamCipher};
 use aes_ctr::Aes128Ctr;
@@ -10,7 +12,8 @@ use rand::Rng;
 use super::error::Error;
 use super::util;
 
-const SUPPORT_KDF_TYPE: &str = "scrypt";
+const SCRYPT_KDF_TYPE: &str = "scrypt";
+const ARGON2ID_KDF_TYPE: &str = "argon2id";
 const SUPPORT_CIPHER_TYPE: &str = "aes-128-ctr";
 // const SUPPORT_PBKDF2_PRF: &str = "hmac-sha256";
 
@@ -21,6 +24,17 @@ const SCRYPT_DK_LIGHT_P: u32 = 6;
 const SCRYPT_DK_R: u32 = 8;
 const SCRYPT_DK_LEN: u32 = 32;
 
+/// Memory cost in KiB
+pub const ARGON2_DEFAULT_MEMORY: u32 = 64 * 1024;
+pub const ARGON2_DEFAULT_ITERATIONS: u32 = 3;
+/// A key file asking for more memory (4 GiB) is refused
+pub const ARGON2_MAX_MEMORY: u32 = 4 * 1024 * 1024;
+/// A key file asking for more passes or lanes is refused too
+pub const ARGON2_MAX_ITERATIONS: u32 = 64;
+pub const ARGON2_MAX_PARALLELISM: u32 = 16;
+const ARGON2_PARALLELISM: u32 = 1;
+const ARGON2_DK_LEN: u32 = 32;
+
 // Example:
 // ========
 // {
@@ -154,6 +168,118 @@ impl Default for ScryptType {
     }
 }
 
+// Example:
+// ========
+// {
+//     "kdf": "argon2id",
+//     "kdfparams": {
+//         "salt": "d27657804be7ef5b9c4d0ac513aa210eeb1ec64ad8f23d639aa579aebfc76832",
+//         "memory": 65536,
+//         "iterations": 3,
+//         "parallelism": 1,
+//         "dklen": 32
+//     },
+//     ...
+// }
+#[derive(Debug, Clone)]
+pub struct Argon2Params {
+    salt: [u8; 32],
+    /// KiB
+    memory: u32,
+    iterations: u32,
+    parallelism: u32,
+    dklen: u32,
+}
+
+impl Default for Argon2Params {
+    fn default() -> Argon2Params {
+        Argon2Params::new(ARGON2_DEFAULT_MEMORY, ARGON2_DEFAULT_ITERATIONS)
+    }
+}
+
+impl Argon2Params {
+    pub fn new(memory: u32, iterations: u32) -> Argon2Params {
+        Self::new_with_salt(rand::thread_rng().gen(), memory, iterations)
+    }
+
+    pub fn new_with_salt(salt: [u8; 32], memory: u32, iterations: u32) -> Argon2Params {
+        Argon2Params {
+            salt,
+            memory,
+            iterations,
+            parallelism: ARGON2_PARALLELISM,
+            dklen: ARGON2_DK_LEN,
+        }
+    }
+
+    fn kdf_key(&self, password: &[u8]) -> Result<[u8; 32], Error> {
+        let config = argon2::Config {
+            variant: argon2::Variant::Argon2id,
+            version: argon2::Version::Version13,
+            mem_cost: self.memory,
+            time_cost: self.iterations,
+            lanes: self.parallelism,
+            hash_length: ARGON2_DK_LEN,
+            ..argon2::Config::default()
+        };
+        let hash = argon2::hash_raw(password, &self.salt, &config)
+            .map_err(|err| Error::InvalidKdfParams(format!("argon2id: {}", err)))?;
+        let mut output = [0u8; 32];
+        output.copy_from_slice(&hash);
+        Ok(output)
+    }
+
+    pub fn from_json(value: &serde_json::Value) -> Result<Argon2Params, Error> {
+        let salt_bin = util::get_hex_bin(value, "salt")?;
+        if salt_bin.len() != 32 {
+            return Err(Error::ParseJsonFailed("Invalid argon2id salt".to_owned()));
+        }
+        let mut salt = [0u8; 32];
+        salt[..].copy_from_slice(&salt_bin[..]);
+
+        let memory = argon2_param(value, "memory", ARGON2_MAX_MEMORY)?;
+        let iterations = argon2_param(value, "iterations", ARGON2_MAX_ITERATIONS)?;
+        let parallelism = argon2_param(value, "parallelism", ARGON2_MAX_PARALLELISM)?;
+        let dklen = util::get_u64(value, "dklen")?;
+        if dklen != u64::from(ARGON2_DK_LEN) {
+            return Err(Error::InvalidKdfParams(format!(
+                "argon2id dklen must be {}: {}",
+                ARGON2_DK_LEN, dklen
+            )));
+        }
+        Ok(Argon2Params {
+            salt,
+            memory,
+            iterations,
+            parallelism,
+            dklen: ARGON2_DK_LEN,
+        })
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let salt_hex = hex_string(&self.salt).unwrap();
+        serde_json::json!({
+            "salt": salt_hex,
+            "memory": self.memory,
+            "iterations": self.iterations,
+            "parallelism": self.parallelism,
+            "dklen": self.dklen,
+        })
+    }
+}
+
+// An argon2id cost of the key file, between 1 and the limit
+fn argon2_param(value: &serde_json::Value, key: &str, max: u32) -> Result<u32, Error> {
+    let param = util::get_u64(value, key)?;
+    match u32::try_from(param) {
+        Ok(param) if param >= 1 && param <= max => Ok(param),
+        _ => Err(Error::InvalidKdfParams(format!(
+            "argon2id {} must be between 1 and {}: {}",
+            key, max, param
+        ))),
+    }
+}
+
 // Example:
 // ========
 // {
@@ -208,6 +334,7 @@ pub enum KdfParams {
     Scrypt(ScryptParams),
     #[allow(dead_code)]
     Pbkdf2(Pbkdf2Params),
+    Argon2id(Argon2Params),
 }
 
 impl Default for KdfParams {
@@ -221,20 +348,51 @@ impl KdfParams {
         match self {
             KdfParams::Scrypt(params) => params.to_json(),
             KdfParams::Pbkdf2(params) => params.to_json(),
+            KdfParams::Argon2id(params) => params.to_json(),
         }
     }
 
     pub fn from_json(value: &serde_json::Value, kdf: &str) -> Result<KdfParams, Error> {
         match kdf {
-            "scrypt" => ScryptParams::from_json(value).map(KdfParams::Scrypt),
+            SCRYPT_KDF_TYPE => ScryptParams::from_json(value).map(KdfParams::Scrypt),
+            ARGON2ID_KDF_TYPE => Argon2Params::from_json(value).map(KdfParams::Argon2id),
             _ => Err(Error::UnsupportedKdf(kdf.to_owned())),
         }
     }
 
+    /// The `kdf` field of the key file
+    pub fn name(&self) -> &'static str {
+        match self {
+            KdfParams::Scrypt(_) => SCRYPT_KDF_TYPE,
+            KdfParams::Pbkdf2(_) => "pbkdf2",
+            KdfParams::Argon2id(_) => ARGON2ID_KDF_TYPE,
+        }
+    }
+
+    /// The same KDF and cost with a new salt, for re-encrypting a key
+    pub fn with_new_salt(&self) -> KdfParams {
+        let salt = rand::thread_rng().gen();
+        match self {
+            KdfParams::Scrypt(params) => KdfParams::Scrypt(ScryptParams {
+                salt,
+                ..params.clone()
+            }),
+            KdfParams::Pbkdf2(params) => KdfParams::Pbkdf2(Pbkdf2Params {
+                salt,
+                ..params.clone()
+            }),
+            KdfParams::Argon2id(params) => KdfParams::Argon2id(Argon2Params {
+                salt,
+                ..params.clone()
+            }),
+        }
+    }
+
     fn kdf_key(&self, password: &[u8]) -> Result<[u8; 32], Error> {
         match self {
             KdfParams::Scrypt(params) => Ok(params.kdf_key(password)),
             KdfParams::Pbkdf2(_) => Err(Error::UnsupportedKdf("pbkdf2".to_owned())),
+            KdfParams::Argon2id(params) => params.kdf_key(password),
         }
     }
 }
@@ -309,7 +467,7 @@ impl Crypto {
             cipher: SUPPORT_CIPHER_TYPE,
             cipherparams,
             ciphertext,
-            kdf: SUPPORT_KDF_TYPE,
+            kdf: kdfparams.name(),
             kdfparams,
             mac,
         })
@@ -357,15 +515,10 @@ impl Crypto {
         let cipherparams_data = util::get_value(data, "cipherparams")?;
         let cipherparams = CipherParams::from_json(cipherparams_data)?;
 
-        let kdf = util::get_str(data, "kdf").and_then(|kdf_str| {
-            if kdf_str == SUPPORT_KDF_TYPE {
-                Ok(SUPPORT_KDF_TYPE)
-            } else {
-                Err(Error::UnsupportedKdf(kdf_str.to_owned()))
-            }
-        })?;
+        let kdf_str = util::get_str(data, "kdf")?;
         let kdfparams_data = util::get_value(data, "kdfparams")?;
-        let kdfparams = KdfParams::from_json(kdfparams_data, kdf)?;
+        let kdfparams = KdfParams::from_json(kdfparams_data, kdf_str)?;
+        let kdf = kdfparams.name();
 
         let mac_bin = util::get_hex_bin(data, "mac")?;
         if mac_bin.len() != 32 {
@@ -389,6 +542,10 @@ impl Crypto {
         &self.ciphertext
     }
 
+    pub fn kdfparams(&self) -> &KdfParams {
+        &self.kdfparams
+    }
+
     pub fn to_json(&self) -> serde_json::Value {
         let mac_hex = hex_string(&self.mac).unwrap();
         let ciphertext_hex = hex_string(&self.ciphertext).unwrap();
@@ -485,6 +642,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_argon2id() {
+        let data = test_data();
+        // The minimum cost, the default one is too slow for a test
+        let kdfparams = KdfParams::Argon2id(Argon2Params::new(8, 1));
+        let crypto = Crypto::encrypt_key(
+            &data.secret_key,
+            &data.password,
+            kdfparams,
+            CipherParams::default(),
+        )
+        .unwrap();
+        let json_data = crypto.to_json();
+        assert_eq!(json_data["kdf"], "argon2id");
+        let crypto = Crypto::from_json(&json_data).unwrap();
+        assert_eq!(
+            crypto.decrypt(&data.password).unwrap(),
+            data.secret_key.to_vec()
+        );
+        assert_eq!(
+            crypto.decrypt(b"this is a wrong password"),
+            Err(Error::CheckPasswordFailed),
+        );
+    }
+
+    #[test]
+    fn test_argon2id_params_limit() {
+        let json_data = Argon2Params::new(8, 1).to_json();
+        assert!(Argon2Params::from_json(&json_data).is_ok());
+        for (key, value) in &[
+            ("memory", u64::from(ARGON2_MAX_MEMORY) + 1),
+            ("iterations", 0),
+            ("iterations", u64::from(ARGON2_MAX_ITERATIONS) + 1),
+            ("iterations", u64::from(u32::max_value()) + 1),
+            ("parallelism", 0),
+            ("parallelism", u64::from(ARGON2_MAX_PARALLELISM) + 1),
+            ("parallelism", u64::from(u32::max_value()) + 3),
+        ] {
+            let mut json_data = json_data.clone();
+            json_data[*key] = serde_json::json!(value);
+            match Argon2Params::from_json(&json_data) {
+                Err(Error::InvalidKdfParams(_)) => {}
+                result => panic!("{} = {}: {:?}", key, value, result),
+            }
+        }
+    }
+
     #[test]
     fn check_password() {
         let data = test_data();
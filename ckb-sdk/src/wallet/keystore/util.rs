@@ -1,4 +1,8 @@
 use faster_hex::hex_decode;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process;
 use std::{ptr, sync::atomic};
 
 use super::error::Error;
@@ -58,3 +62,24 @@ pub fn zeroize_slice(data: &mut [u8]) {
         atomic::compiler_fence(atomic::Ordering::SeqCst);
     }
 }
+
+/// Write the file atomically: the content is written to a temporary file in
+/// the same directory then renamed over `path`, so a crash never leaves a
+/// half written file behind.
+pub fn write_file_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let file_name = path.file_name().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid file path: {}", path.to_string_lossy()),
+        )
+    })?;
+    let tmp_name = format!(".{}.{}.tmp", file_name.to_string_lossy(), process::id());
+    let tmp_path = path.with_file_name(tmp_name);
+    let result = fs::File::create(&tmp_path)
+        .and_then(|mut file| file.write_all(content).and_then(|_| file.sync_all()))
+        .and_then(|_| fs::rename(&tmp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
@@ -1,6 +1,7 @@
 mod bip32;
 mod error;
 mod keystore;
+mod remote_signer;
 
 pub use bip32::{
     ChainCode, ChildNumber, DerivationPath, Error as Bip32Error, ExtendedPrivKey, ExtendedPubKey,
@@ -9,10 +10,13 @@ pub use bip32::{
 pub use error::Error as WalletError;
 pub use keystore::{
     interface::{
-        is_valid_derivation_path, AbstractKeyStore, AbstractMasterPrivKey, AbstractPrivKey,
-        DerivedKeySet, FullyBoxedAbstractMasterPrivkey, FullyBoxedAbstractPrivkey, KeyChain,
-        MANDATORY_PREFIX,
+        is_hardened_account_path, is_valid_derivation_path, AbstractKeyStore,
+        AbstractMasterPrivKey, AbstractPrivKey, DerivedKeySet, FullyBoxedAbstractMasterPrivkey,
+        FullyBoxedAbstractPrivkey, KeyChain, ACCOUNT_LEVEL_DEPTH, MANDATORY_PREFIX,
     },
-    zeroize_privkey, zeroize_slice, CipherParams, Crypto, Error as KeyStoreError, KdfParams, Key,
-    KeyStore, KeyTimeout, MasterPrivKey, ScryptParams, ScryptType,
+    write_file_atomic, zeroize_privkey, zeroize_slice, Argon2Params, CipherParams, Crypto,
+    Error as KeyStoreError, KdfParams, Key, KeyStore, KeyTimeout, MasterPrivKey, ScryptParams,
+    ScryptType, ARGON2_DEFAULT_ITERATIONS, ARGON2_DEFAULT_MEMORY, ARGON2_MAX_ITERATIONS,
+    ARGON2_MAX_MEMORY, ARGON2_MAX_PARALLELISM,
 };
+pub use remote_signer::RemoteSigner;
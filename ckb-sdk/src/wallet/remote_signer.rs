@@ -0,0 +1,261 @@
+//! A signing key held by a remote service (eg: a HSM behind an institutional
+//! signing service), spoken to by JSON-RPC 2.0 over HTTPS with an optional
+//! `Authorization: Bearer <token>` header:
+//!
+//! - `get_public_key()` returns the 33 bytes compressed public key as hex
+//! - `sign(public_key, message, digest)` returns the 65 bytes recoverable
+//!   signature (`r || s || recovery_id`) of the 32 bytes `digest` as hex.
+//!   `message` is the hex of the bytes hashed by blake2b-256 into `digest`
+//!   (the signing message of the transaction) so the service can inspect
+//!   what it signs, it is `null` when only the digest is known.
+//!
+//! The returned signature is checked against the public key.
+
+use ckb_crypto::secp::SECP256K1;
+use ckb_hash::blake2b_256;
+use ckb_types::H256;
+use faster_hex::hex_string;
+use futures::Future;
+use secp256k1::recovery::{RecoverableSignature, RecoveryId};
+use serde_json::Value;
+
+use super::keystore::interface::AbstractPrivKey;
+use crate::rpc::AsyncRpcClient;
+use crate::signing::SignEntireHelper;
+
+// Only not using impl trait because unstable
+type RemoteSignerSignClosure = Box<dyn FnOnce(Vec<u8>) -> Result<RecoverableSignature, String>>;
+
+#[derive(Clone)]
+pub struct RemoteSigner {
+    client: AsyncRpcClient,
+    public_key: secp256k1::PublicKey,
+}
+
+impl RemoteSigner {
+    /// Connect to the service and fetch its public key, plain http is only
+    /// allowed for a service on the same host.
+    pub fn connect(url: &str, token: Option<&str>) -> Result<RemoteSigner, String> {
+        check_url(url)?;
+        let mut client = AsyncRpcClient::new(url)?;
+        if let Some(token) = token {
+            client.set_bearer_token(token);
+        }
+        let result = client.call("get_public_key", Vec::new()).wait()?;
+        let public_key = decode_hex(&result, 33)
+            .and_then(|bytes| {
+                secp256k1::PublicKey::from_slice(&bytes).map_err(|err| err.to_string())
+            })
+            .map_err(|err| format!("Invalid public key from remote signer: {}", err))?;
+        Ok(RemoteSigner { client, public_key })
+    }
+
+    pub fn url(&self) -> &str {
+        self.client.url()
+    }
+
+    /// Sign the digest, the message is its blake2b-256 preimage if known
+    pub fn sign_digest(
+        &self,
+        digest: &H256,
+        message: Option<&[u8]>,
+    ) -> Result<RecoverableSignature, String> {
+        let params = vec![
+            Value::String(format!(
+                "0x{}",
+                hex_string(&self.public_key.serialize()).unwrap()
+            )),
+            message
+                .map(|message| Value::String(format!("0x{}", hex_string(message).unwrap())))
+                .unwrap_or(Value::Null),
+            Value::String(format!("{:#x}", digest)),
+        ];
+        let result = self.client.call("sign", params).wait()?;
+        let bytes = decode_hex(&result, 65)
+            .map_err(|err| format!("Invalid signature from remote signer: {}", err))?;
+        let recov_id = RecoveryId::from_i32(i32::from(bytes[64]))
+            .map_err(|err| format!("Invalid signature from remote signer: {}", err))?;
+        let signature = RecoverableSignature::from_compact(&bytes[0..64], recov_id)
+            .map_err(|err| format!("Invalid signature from remote signer: {}", err))?;
+        let message =
+            secp256k1::Message::from_slice(digest.as_bytes()).expect("Convert to message failed");
+        match SECP256K1.recover(&message, &signature) {
+            Ok(ref public_key) if public_key == &self.public_key => Ok(signature),
+            _ => Err(format!(
+                "Remote signer {} returned a signature not made by its public key",
+                self.url()
+            )),
+        }
+    }
+}
+
+impl AbstractPrivKey for RemoteSigner {
+    type Err = String;
+
+    type SignerSingleShot = SignEntireHelper<RemoteSignerSignClosure>;
+
+    fn public_key(&self) -> Result<secp256k1::PublicKey, Self::Err> {
+        Ok(self.public_key)
+    }
+
+    fn sign(&self, message: &H256) -> Result<secp256k1::Signature, Self::Err> {
+        self.sign_digest(message, None)
+            .map(|signature| signature.to_standard())
+    }
+
+    fn begin_sign_recoverable(&self) -> Self::SignerSingleShot {
+        let signer = self.clone();
+        SignEntireHelper::new(Box::new(move |message: Vec<u8>| {
+            let digest = H256::from(blake2b_256(&message));
+            signer.sign_digest(&digest, Some(&message))
+        }))
+    }
+}
+
+fn check_url(url: &str) -> Result<(), String> {
+    let local_prefixes = ["http://127.0.0.1", "http://localhost", "http://[::1]"];
+    if url.starts_with("https://")
+        || local_prefixes.iter().any(|prefix| {
+            url.starts_with(prefix) && {
+                let rest = &url[prefix.len()..];
+                rest.is_empty() || rest.starts_with(':') || rest.starts_with('/')
+            }
+        })
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "Remote signer url must be https (or http on localhost): {}",
+            url
+        ))
+    }
+}
+
+fn decode_hex(value: &Value, len: usize) -> Result<Vec<u8>, String> {
+    let input = value
+        .as_str()
+        .ok_or_else(|| format!("expected a hex string, got {}", value))?;
+    let input = input.trim_start_matches("0x");
+    if input.len() != len * 2 {
+        return Err(format!("expected {} bytes, got {:?}", len, input));
+    }
+    let mut bytes = vec![0u8; len];
+    faster_hex::hex_decode(input.as_bytes(), &mut bytes).map_err(|err| err.to_string())?;
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_crypto::secp::{Message, Privkey};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    // A signing service on localhost, the public key is of `key_seed` while
+    // the signatures are made by `signing_seed`
+    fn serve(key_seed: [u8; 32], signing_seed: [u8; 32]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                respond(stream.unwrap(), &key_seed, &signing_seed);
+            }
+        });
+        url
+    }
+
+    fn respond(stream: TcpStream, key_seed: &[u8; 32], signing_seed: &[u8; 32]) {
+        let mut reader = BufReader::new(stream);
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end().to_lowercase();
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("content-length:") {
+                content_length = line["content-length:".len()..].trim().parse().unwrap();
+            }
+        }
+        let mut body = vec![0u8; content_length];
+        reader.read_exact(&mut body).unwrap();
+        let request: Value = serde_json::from_slice(&body).unwrap();
+        let result = match request["method"].as_str().unwrap() {
+            "get_public_key" => {
+                let pubkey = Privkey::from_slice(key_seed).pubkey().unwrap();
+                format!("0x{}", hex_string(&pubkey.serialize()).unwrap())
+            }
+            "sign" => {
+                let digest = decode_hex(&request["params"][2], 32).unwrap();
+                let message = Message::from_slice(&digest).unwrap();
+                let signature = Privkey::from_slice(signing_seed)
+                    .sign_recoverable(&message)
+                    .unwrap();
+                format!("0x{}", hex_string(&signature.serialize()).unwrap())
+            }
+            method => panic!("unexpected method: {}", method),
+        };
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": request["id"],
+            "result": result,
+        })
+        .to_string();
+        let mut stream = reader.into_inner();
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            response.len(),
+            response
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_remote_signer() {
+        let key_seed = [1u8; 32];
+        let pubkey = Privkey::from_slice(&key_seed).pubkey().unwrap();
+        let message = b"signing message";
+        let digest = H256::from(blake2b_256(&message[..]));
+
+        let signer = RemoteSigner::connect(&serve(key_seed, key_seed), Some("token")).unwrap();
+        assert_eq!(
+            signer.public_key().unwrap().serialize()[..],
+            pubkey.serialize()[..]
+        );
+        let signature = signer.sign_digest(&digest, Some(&message[..])).unwrap();
+        let recovered = SECP256K1
+            .recover(
+                &secp256k1::Message::from_slice(digest.as_bytes()).unwrap(),
+                &signature,
+            )
+            .unwrap();
+        assert_eq!(recovered, signer.public_key().unwrap());
+
+        // A signature not made by the public key of the service is rejected
+        let signer = RemoteSigner::connect(&serve(key_seed, [2u8; 32]), None).unwrap();
+        assert!(signer.sign_digest(&digest, None).is_err());
+    }
+
+    #[test]
+    fn test_decode_hex() {
+        assert_eq!(
+            decode_hex(&Value::String("0x0102".to_string()), 2),
+            Ok(vec![1, 2])
+        );
+        assert!(decode_hex(&Value::String("0x0102".to_string()), 3).is_err());
+        assert!(decode_hex(&Value::String("0xzz".to_string()), 1).is_err());
+        assert!(decode_hex(&Value::Null, 2).is_err());
+    }
+
+    #[test]
+    fn test_check_url() {
+        assert!(check_url("https://signer.example.com/ckb").is_ok());
+        assert!(check_url("http://127.0.0.1:8000").is_ok());
+        assert!(check_url("http://localhost/sign").is_ok());
+        assert!(check_url("http://localhost.example.com").is_err());
+        assert!(check_url("http://signer.example.com").is_err());
+    }
+}
@@ -0,0 +1,18 @@
+//! The message given to the ledger signer (built from the transaction file
+//! of the user)
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use ckb_ledger::parse;
+use ckb_types::prelude::*;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(message) = parse::annotated_transaction(data) {
+        let raw = message.raw();
+        for output in raw.outputs().into_iter() {
+            let _ = output.lock().calc_script_hash();
+        }
+        let _ = message.change_path().len();
+        let _ = message.sign_path().len();
+    }
+});
@@ -0,0 +1,19 @@
+//! The parsers of the responses of the Nervos app
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use ckb_ledger::{parse, LedgerAppInfo};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = LedgerAppInfo::from_response(data);
+    let _ = parse::recoverable_signature(data);
+
+    // Length prefixed fields until the data runs out
+    let mut resp = data;
+    while let Ok(len) = parse::split_first(&mut resp) {
+        if parse::split_off_at(&mut resp, len as usize).is_err() {
+            break;
+        }
+    }
+    let _ = parse::assert_nothing_left(resp);
+});
@@ -0,0 +1,49 @@
+//! A device answering every command by the fuzz data: discovery, public keys
+//! and signing must fail by errors
+#![no_main]
+use std::sync::{Arc, Mutex};
+
+use libfuzzer_sys::fuzz_target;
+
+use ckb_ledger::{LedgerKeyStoreError, LedgerMasterCap, LedgerTransport};
+use ckb_sdk::wallet::{AbstractMasterPrivKey, AbstractPrivKey, DerivationPath};
+use ckb_types::H256;
+use ledger::{ApduAnswer, ApduCommand};
+
+// The responses are length prefixed (1 byte) in the data, then empty
+struct FuzzDevice {
+    data: Mutex<Vec<u8>>,
+}
+
+impl LedgerTransport for FuzzDevice {
+    fn exchange(&self, _command: ApduCommand) -> Result<ApduAnswer, LedgerKeyStoreError> {
+        let mut data = self.data.lock().unwrap();
+        let len = data.first().map(|len| *len as usize).unwrap_or(0);
+        let len = len.min(data.len().saturating_sub(1));
+        let response = data.iter().skip(1).take(len).cloned().collect::<Vec<_>>();
+        let rest = data.split_off((len + 1).min(data.len()));
+        *data = rest;
+        Ok(ApduAnswer {
+            data: response,
+            retcode: 0x9000,
+        })
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let device = Arc::new(FuzzDevice {
+        data: Mutex::new(data.to_vec()),
+    });
+    let master = match LedgerMasterCap::from_transport(device) {
+        Ok(master) => master,
+        Err(_) => return,
+    };
+    let _ = master.fetch_account_xpub();
+    let path: DerivationPath = "m/44'/309'/0'/0/0".parse().unwrap();
+    let cap = match master.extended_privkey(path.as_ref()) {
+        Ok(cap) => cap,
+        Err(_) => return,
+    };
+    let _ = cap.public_key();
+    let _ = cap.sign_hash(&H256::default());
+});
@@ -0,0 +1,41 @@
+//! The decoders of `molecule decode`, the first byte selects the type
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use ckb_cli::fuzzing::{decode_molecule, OutputFormat};
+
+const TYPE_NAMES: &[&str] = &[
+    "Uint32",
+    "Uint64",
+    "Uint128",
+    "Bytes",
+    "BytesVec",
+    "Byte32Vec",
+    "UncleBlockVec",
+    "TransactionVec",
+    "ProposalShortIdVec",
+    "CellDepVec",
+    "CellInputVec",
+    "CellOutputVec",
+    "Script",
+    "OutPoint",
+    "CellInput",
+    "CellOutput",
+    "CellDep",
+    "RawTransaction",
+    "Transaction",
+    "RawHeader",
+    "Header",
+    "UncleBlock",
+    "Block",
+    "CellbaseWitness",
+    "WitnessArgs",
+    "OutPointVec",
+];
+
+fuzz_target!(|data: &[u8]| {
+    if let Some((selector, binary)) = data.split_first() {
+        let type_name = TYPE_NAMES[*selector as usize % TYPE_NAMES.len()];
+        let _ = decode_molecule(type_name, binary, OutputFormat::Json, false);
+    }
+});
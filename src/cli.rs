@@ -0,0 +1,490 @@
+//! The command line entry of the `ckb-cli` binary
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::iter::FromIterator;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+
+use crate::interactive::InteractiveEnv;
+use crate::server::Server;
+#[cfg(unix)]
+use crate::subcommands::TuiSubCommand;
+use crate::subcommands::{
+    start_index_thread, AccountSubCommand, CliSubCommand, DAOSubCommand, DeploySubCommand,
+    IndexSubCommand, MockTxSubCommand, MoleculeSubCommand, NodeSubCommand, RpcSubCommand,
+    SudtSubCommand, TxSubCommand, UtilSubCommand, WalletSubCommand,
+};
+use crate::utils::{
+    alert::AlertConfig,
+    arg_parser::{ArgParser, FromStrParser},
+    broadcast::{broadcast_urls_from_config, SendTargets},
+    config::GlobalConfig,
+    index::IndexThreadState,
+    interrupt::set_interrupt_handler,
+    other::{
+        check_alerts, check_vault_name, get_all_key_stores, get_ckb_cli_dir, get_genesis_info,
+        get_key_store, get_network_type, index_dirname, ledger_emulator, ledger_min_app_version,
+        password_from_matches, set_assume_yes, set_password_source, sync_to_tip,
+        witness_placeholders_from_config, PasswordSource, LEDGER_EMULATOR_ENV_NAME,
+        LEDGER_MIN_APP_VERSION_ENV_NAME, PASSWORD_ENV_NAME, VAULT_ENV_NAME,
+    },
+    output::write_output,
+    policy::SigningPolicy,
+    printer::{ColorWhen, OutputFormat},
+    remote_config::apply_remote_config,
+    schema::schema_request,
+    tor::{socks_proxy_from_matches, SOCKS_PROXY_ENV_NAME},
+};
+use crate::{build_cli, get_version};
+use ckb_sdk::{
+    register_witness_lock_size,
+    rpc::{
+        is_onion_url, last_rpc_journal_id, set_rpc_journal, set_socks_proxy, socks_proxy,
+        RawHttpRpcClient,
+    },
+    HttpRpcClient,
+};
+use ckb_util::RwLock;
+
+const RPC_JOURNAL_FILE: &str = "rpc-journal.jsonl";
+const DEFAULT_RPC_JOURNAL_MAX_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Run the command line of the arguments of the process, the binary is a
+/// wrapper of it
+pub fn run() -> Result<(), io::Error> {
+    env_logger::init();
+
+    #[cfg(unix)]
+    let ansi_support = true;
+    #[cfg(not(unix))]
+    let ansi_support = ansi_term::enable_ansi_support().is_ok();
+
+    let version = get_version();
+    let version_short = version.short();
+    let version_long = version.long();
+    let matches = build_cli(&version_short, &version_long).get_matches();
+    match schema_request(&matches) {
+        Some(Ok(schema)) => {
+            print!("{}", schema);
+            return Ok(());
+        }
+        Some(Err(err)) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        None => {}
+    }
+
+    let mut env_map: HashMap<String, String> = HashMap::from_iter(env::vars());
+    let api_uri_opt = matches
+        .value_of("url")
+        .map(ToOwned::to_owned)
+        .or_else(|| env_map.remove("API_URL"));
+    if let Some(vault) = matches.value_of("vault") {
+        env::set_var(VAULT_ENV_NAME, vault);
+    } else if let Some(Err(err)) = env_map
+        .get(VAULT_ENV_NAME)
+        .filter(|name| !name.is_empty())
+        .map(|name| check_vault_name(name))
+    {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+
+    if let Some(emulator) = matches.value_of("ledger-emulator") {
+        env::set_var(LEDGER_EMULATOR_ENV_NAME, emulator);
+    } else if let Err(err) = ledger_emulator() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+    if let Some(version) = matches.value_of("ledger-min-app-version") {
+        env::set_var(LEDGER_MIN_APP_VERSION_ENV_NAME, version);
+    } else if let Err(err) = ledger_min_app_version() {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+
+    set_assume_yes(matches.is_present("yes"));
+    // Not inherited by the child processes (eg. notify hooks)
+    env::remove_var(PASSWORD_ENV_NAME);
+    match password_from_matches(&matches, env_map.remove(PASSWORD_ENV_NAME)) {
+        Ok(Some(password)) => set_password_source(PasswordSource::Preset(Some(password))),
+        Ok(None) => {}
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+
+    match socks_proxy_from_matches(&matches, env_map.remove(SOCKS_PROXY_ENV_NAME)) {
+        Ok(proxy_opt) => set_socks_proxy(proxy_opt),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+
+    let ckb_cli_dir = get_ckb_cli_dir();
+    let mut resource_dir = ckb_cli_dir.clone();
+    resource_dir.push("resource");
+    let mut index_dir = ckb_cli_dir.clone();
+    index_dir.push(index_dirname());
+    let index_state = Arc::new(RwLock::new(IndexThreadState::default()));
+
+    let mut config = GlobalConfig::new(api_uri_opt.clone(), Arc::clone(&index_state));
+    let mut config_file = ckb_cli_dir.clone();
+    config_file.push("config");
+
+    let mut output_format = OutputFormat::Yaml;
+    let mut broadcast_urls = Vec::new();
+    let mut rpc_journal = matches.is_present("rpc-journal");
+    let mut rpc_journal_max_size = DEFAULT_RPC_JOURNAL_MAX_SIZE;
+    if config_file.as_path().exists() {
+        let mut file = fs::File::open(&config_file)?;
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        let mut configs: serde_json::Value = serde_json::from_str(content.as_str()).unwrap();
+        if let Err(err) = apply_remote_config(&mut configs, &ckb_cli_dir) {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+        if api_uri_opt.is_none() {
+            if let Some(value) = configs["url"].as_str() {
+                config.set_url(value.to_string());
+            }
+        }
+        config.set_debug(configs["debug"].as_bool().unwrap_or(false));
+        config.set_color(ansi_support && configs["color"].as_bool().unwrap_or(true));
+        output_format =
+            OutputFormat::from_str(&configs["output_format"].as_str().unwrap_or("yaml"))
+                .unwrap_or(OutputFormat::Yaml);
+        config.set_output_format(output_format);
+        config.set_completion_style(configs["completion_style"].as_bool().unwrap_or(true));
+        config.set_edit_style(configs["edit_style"].as_bool().unwrap_or(true));
+        match SigningPolicy::from_config(&configs) {
+            Ok(signing_policy) => config.set_signing_policy(signing_policy),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+        match AlertConfig::from_config(&configs) {
+            Ok(alert_config) => config.set_alert_config(alert_config),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+        match broadcast_urls_from_config(&configs) {
+            Ok(urls) => broadcast_urls = urls,
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+        match witness_placeholders_from_config(&configs) {
+            Ok(sizes) => {
+                for (code_hash, size) in sizes {
+                    if let Err(err) = register_witness_lock_size(code_hash, size) {
+                        eprintln!("Invalid witness_placeholders config: {}", err);
+                        process::exit(1);
+                    }
+                }
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+        rpc_journal |= configs["rpc_journal"].as_bool().unwrap_or(false);
+        if let Some(size) = configs["rpc_journal_max_size"].as_u64() {
+            rpc_journal_max_size = size;
+        }
+    }
+    if rpc_journal {
+        set_rpc_journal(
+            Some(ckb_cli_dir.join(RPC_JOURNAL_FILE)),
+            rpc_journal_max_size,
+        );
+    }
+
+    let mut signing_policy = config.signing_policy();
+    if let Err(err) = signing_policy.load_manifest(&ckb_cli_dir) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+    config.set_signing_policy(signing_policy);
+
+    let api_uri = config.get_url().to_string();
+    if is_onion_url(&api_uri) && socks_proxy().is_none() {
+        eprintln!("Onion url {} requires --socks-proxy or --tor", api_uri);
+        process::exit(1);
+    }
+    if matches.is_present("broadcast-all-nodes") {
+        broadcast_urls.retain(|url| url != &api_uri);
+        if broadcast_urls.is_empty() {
+            eprintln!("--broadcast-all-nodes requires other urls in broadcast_urls config");
+            process::exit(1);
+        }
+        config.set_send_targets(SendTargets {
+            broadcast_urls,
+            ..SendTargets::default()
+        });
+    }
+    // Eg: an https url is not supported through the SOCKS proxy
+    let clients = HttpRpcClient::try_new(api_uri.clone()).and_then(|rpc_client| {
+        RawHttpRpcClient::try_from_uri(api_uri.as_str())
+            .map(|raw_rpc_client| (rpc_client, raw_rpc_client))
+    });
+    let (mut rpc_client, mut raw_rpc_client) = match clients {
+        Ok(clients) => clients,
+        Err(err) => {
+            eprintln!("Connect to {} failed: {}", api_uri, err);
+            process::exit(1);
+        }
+    };
+    let fetch_concurrency = FromStrParser::<usize>::default()
+        .from_matches(&matches, "index-fetch-concurrency")
+        .unwrap();
+    let index_controller = start_index_thread(
+        api_uri.as_str(),
+        index_dir.clone(),
+        index_state,
+        fetch_concurrency,
+        matches.is_present("index-tx-history"),
+    );
+    if let Err(err) = set_interrupt_handler(index_controller.clone()) {
+        eprintln!("{}", err);
+    }
+    check_alerts(&mut rpc_client);
+    config.set_network(get_network_type(&mut rpc_client).ok());
+
+    let signing_policy = config.signing_policy();
+    let send_targets = config.send_targets();
+    let output_to = matches.value_of("output-to").map(PathBuf::from);
+    let color = ColorWhen::new(!matches.is_present("no-color") && output_to.is_none()).color();
+    let debug = matches.is_present("debug");
+
+    // When flag `--wait-for-sync` given, we have to ensure that the index-store synchronizes
+    // to the tip before executing the command.
+    let wait_for_sync = matches.is_present("wait-for-sync");
+    if wait_for_sync {
+        if let Err(err) = sync_to_tip(&index_controller) {
+            eprintln!("Synchronize error: {}", err);
+            process::exit(1);
+        }
+    }
+
+    if let Some(format) = matches.value_of("output-format") {
+        output_format = OutputFormat::from_str(format).unwrap();
+    }
+    if let Some(path) = matches.value_of("batch-file") {
+        if let Some(name) = matches.subcommand_name() {
+            eprintln!("--batch-file can not be given with subcommand {}", name);
+            process::exit(1);
+        }
+        let continue_on_error = matches.is_present("continue-on-error");
+        if matches.is_present("no-color") {
+            config.set_color(false);
+        }
+        if matches.occurrences_of("output-format") > 0 {
+            config.set_output_format(output_format);
+        }
+        let result = InteractiveEnv::from_config(ckb_cli_dir, config, index_controller.clone())
+            .and_then(|mut env| {
+                env.start_index();
+                env.run_script(Path::new(path), continue_on_error)
+            });
+        index_controller.shutdown();
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            if let Some(id) = last_rpc_journal_id() {
+                eprintln!("Last RPC exchange in {}: {}", RPC_JOURNAL_FILE, id);
+            }
+            process::exit(1);
+        }
+        process::exit(0)
+    }
+    let result = match matches.subcommand() {
+        #[cfg(unix)]
+        ("tui", _) => TuiSubCommand::new(
+            api_uri.to_string(),
+            index_dir.clone(),
+            index_controller.clone(),
+        )
+        .start(),
+        ("rpc", Some(sub_matches)) => RpcSubCommand::new(&mut rpc_client, &mut raw_rpc_client)
+            .process(&sub_matches, output_format, color, debug),
+        ("node", Some(sub_matches)) => {
+            NodeSubCommand::new(&mut rpc_client).process(&sub_matches, output_format, color, debug)
+        }
+        ("account", Some(sub_matches)) => {
+            get_all_key_stores(&ckb_cli_dir).and_then(|(mut key_store, mut ledger_key_store)| {
+                AccountSubCommand::new(
+                    &mut rpc_client,
+                    &mut key_store,
+                    &mut ledger_key_store,
+                    None,
+                    index_dir.clone(),
+                    index_controller.clone(),
+                    signing_policy,
+                )
+                .process(&sub_matches, output_format, color, debug)
+            })
+        }
+        ("mock-tx", Some(sub_matches)) => {
+            get_key_store(&ckb_cli_dir).and_then(|mut key_store| {
+                MockTxSubCommand::new(&mut rpc_client, &mut key_store, None, signing_policy)
+                    .process(&sub_matches, output_format, color, debug)
+            })
+        }
+        ("tx", Some(sub_matches)) => {
+            get_all_key_stores(&ckb_cli_dir).and_then(|(mut key_store, mut ledger_key_store)| {
+                TxSubCommand::new(
+                    &mut rpc_client,
+                    &mut key_store,
+                    &mut ledger_key_store,
+                    None,
+                    index_dir.clone(),
+                    index_controller.clone(),
+                    signing_policy,
+                    send_targets,
+                )
+                .process(&sub_matches, output_format, color, debug)
+            })
+        }
+        ("util", Some(sub_matches)) => {
+            get_all_key_stores(&ckb_cli_dir).and_then(|(mut key_store, mut ledger_key_store)| {
+                UtilSubCommand::new(
+                    &mut rpc_client,
+                    &mut key_store,
+                    &mut ledger_key_store,
+                    config.alert_config().clone(),
+                )
+                .process(&sub_matches, output_format, color, debug)
+            })
+        }
+        ("molecule", Some(sub_matches)) => {
+            MoleculeSubCommand::new().process(&sub_matches, output_format, color, debug)
+        }
+        ("wallet", Some(sub_matches)) => {
+            get_all_key_stores(&ckb_cli_dir).and_then(|(mut key_store, mut ledger_key_store)| {
+                WalletSubCommand::new(
+                    &mut rpc_client,
+                    &mut key_store,
+                    &mut ledger_key_store,
+                    None,
+                    index_dir.clone(),
+                    index_controller.clone(),
+                    signing_policy,
+                    send_targets,
+                )
+                .process(&sub_matches, output_format, color, debug)
+            })
+        }
+        ("sudt", Some(sub_matches)) => get_key_store(&ckb_cli_dir).and_then(|mut key_store| {
+            SudtSubCommand::new(
+                &mut rpc_client,
+                &mut key_store,
+                None,
+                index_dir.clone(),
+                index_controller.clone(),
+                signing_policy,
+                send_targets,
+            )
+            .process(&sub_matches, output_format, color, debug)
+        }),
+        ("deploy", Some(sub_matches)) => get_key_store(&ckb_cli_dir).and_then(|mut key_store| {
+            DeploySubCommand::new(
+                &mut rpc_client,
+                &mut key_store,
+                None,
+                index_dir.clone(),
+                index_controller.clone(),
+                signing_policy,
+                send_targets,
+            )
+            .process(&sub_matches, output_format, color, debug)
+        }),
+        ("index", Some(sub_matches)) => IndexSubCommand::new(
+            &mut rpc_client,
+            index_dir.clone(),
+            index_controller.record_txs(),
+        )
+        .process(&sub_matches, output_format, color, debug),
+        ("dao", Some(sub_matches)) => {
+            get_genesis_info(&None, &mut rpc_client).and_then(|genesis_info| {
+                get_all_key_stores(&ckb_cli_dir).and_then(
+                    |(mut key_store, mut ledger_key_store)| {
+                        DAOSubCommand::new(
+                            &mut rpc_client,
+                            &mut key_store,
+                            &mut ledger_key_store,
+                            genesis_info,
+                            index_dir.clone(),
+                            index_controller.clone(),
+                            signing_policy,
+                            send_targets,
+                        )
+                        .process(&sub_matches, output_format, color, debug)
+                    },
+                )
+            })
+        }
+        ("server", Some(sub_matches)) => FromStrParser::<SocketAddr>::new()
+            .from_matches(sub_matches, "listen")
+            .and_then(|listen| {
+                let enable_signing = sub_matches.is_present("enable-signing");
+                let auth_token = Server::auth_token_from_matches(sub_matches)?;
+                let watcher = Server::watcher_from_matches(
+                    sub_matches,
+                    api_uri.as_str(),
+                    index_dir.clone(),
+                    index_controller.clone(),
+                )?;
+                InteractiveEnv::from_config(ckb_cli_dir, config, index_controller.clone()).and_then(
+                    |env| Server::new(env, enable_signing, auth_token, watcher).start(listen),
+                )
+            })
+            .map(|_| String::new()),
+        _ => {
+            if let Err(err) =
+                InteractiveEnv::from_config(ckb_cli_dir, config, index_controller.clone())
+                    .and_then(|mut env| env.start())
+            {
+                eprintln!("Process error: {}", err);
+                index_controller.shutdown();
+                process::exit(1);
+            }
+            index_controller.shutdown();
+            process::exit(0)
+        }
+    };
+
+    let result = result.and_then(|message| match output_to {
+        Some(path) => write_output(&path, &message).map(|_| None),
+        None => Ok(Some(message)),
+    });
+    match result {
+        Ok(message) => {
+            if let Some(message) = message {
+                println!("{}", message);
+            }
+            index_controller.shutdown();
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            if let Some(id) = last_rpc_journal_id() {
+                eprintln!("Last RPC exchange in {}: {}", RPC_JOURNAL_FILE, id);
+            }
+            index_controller.shutdown();
+            process::exit(1);
+        }
+    }
+    Ok(())
+}
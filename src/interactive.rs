@@ -1,10 +1,11 @@
 use std::fs;
-use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use ansi_term::Colour::Green;
 use ckb_types::{core::service::Request, core::BlockView};
+use clap::ArgMatches;
 use regex::Regex;
 use rustyline::config::Configurer;
 use rustyline::error::ReadlineError;
@@ -12,20 +13,50 @@ use rustyline::{Cmd, CompletionType, Config, EditMode, Editor, KeyPress};
 use serde_json::json;
 
 use crate::subcommands::{
-    AccountSubCommand, CliSubCommand, DAOSubCommand, MockTxSubCommand, MoleculeSubCommand,
-    RpcSubCommand, TxSubCommand, UtilSubCommand, WalletSubCommand,
+    start_index_thread, AccountSubCommand, CliSubCommand, DAOSubCommand, DeploySubCommand,
+    MockTxSubCommand, MoleculeSubCommand, NodeSubCommand, RpcSubCommand, SudtSubCommand,
+    TxSubCommand, UtilSubCommand, WalletSubCommand,
 };
 use crate::utils::{
+    alert::AlertConfig,
     completer::CkbCompleter,
-    config::GlobalConfig,
-    index::{IndexController, IndexRequest},
+    config::{select_field, GlobalConfig},
+    index::{IndexController, IndexRequest, IndexThreadState},
     other::{check_alerts, get_key_store, get_ledger_key_store, get_network_type, index_dirname},
-    printer::{ColorWhen, OutputFormat, Printable},
+    output::{write_file_atomic, write_output},
+    printer::{capture_json, ColorWhen, OutputFormat, Printable},
+    schema::schema_request,
 };
 use ckb_ledger::LedgerKeyStore;
-use ckb_sdk::{rpc::RawHttpRpcClient, wallet::KeyStore, GenesisInfo, HttpRpcClient};
+use ckb_sdk::{
+    rpc::{last_rpc_journal_id, RawHttpRpcClient},
+    wallet::KeyStore,
+    GenesisInfo, HttpRpcClient,
+};
+use ckb_util::RwLock;
+
+pub(crate) const ENV_PATTERN: &str = r"\$\{\s*(?P<key>\S+)\s*\}";
+// Scripts sourcing each other are stopped at this depth
+const MAX_SOURCE_DEPTH: usize = 16;
+// The default of `--index-fetch-concurrency`
+const DEFAULT_INDEX_FETCH_CONCURRENCY: usize = 8;
 
-const ENV_PATTERN: &str = r"\$\{\s*(?P<key>\S+)\s*\}";
+/// The result of a command line
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandOutput {
+    /// No output (`set` or an empty line)
+    Empty,
+    /// The rendered output of a subcommand, `get` or a schema request
+    Text(String),
+    /// The value of the output, a string when the command does not output a
+    /// value (eg: a message or a schema) (returned by `InteractiveEnv::evaluate`
+    /// instead of `Text`)
+    Value(serde_json::Value),
+    /// The global config entries after `config` or `info`
+    Config(serde_json::Value),
+    /// `exit` or `quit`
+    Exit,
+}
 
 /// Interactive command line
 pub struct InteractiveEnv {
@@ -40,16 +71,34 @@ pub struct InteractiveEnv {
     raw_rpc_client: RawHttpRpcClient,
     index_controller: IndexController,
     genesis_info: Option<GenesisInfo>,
+    env_regex: Regex,
+    source_depth: usize,
 }
 
 impl InteractiveEnv {
-    pub fn from_config(
+    /// An environment of the node at `url` with the key stores and the index
+    /// database of `ckb_cli_dir`, the index database is synchronized after
+    /// `start_index`
+    pub fn new(ckb_cli_dir: PathBuf, url: &str) -> Result<InteractiveEnv, String> {
+        let index_state = Arc::new(RwLock::new(IndexThreadState::default()));
+        let config = GlobalConfig::new(Some(url.to_string()), Arc::clone(&index_state));
+        let index_controller = start_index_thread(
+            url,
+            ckb_cli_dir.join(index_dirname()),
+            index_state,
+            DEFAULT_INDEX_FETCH_CONCURRENCY,
+            false,
+        );
+        InteractiveEnv::from_config(ckb_cli_dir, config, index_controller)
+    }
+
+    pub(crate) fn from_config(
         ckb_cli_dir: PathBuf,
         mut config: GlobalConfig,
         index_controller: IndexController,
     ) -> Result<InteractiveEnv, String> {
         if !ckb_cli_dir.as_path().exists() {
-            fs::create_dir(&ckb_cli_dir).map_err(|err| err.to_string())?;
+            fs::create_dir_all(&ckb_cli_dir).map_err(|err| err.to_string())?;
         }
         let mut history_file = ckb_cli_dir.clone();
         history_file.push("history");
@@ -70,8 +119,8 @@ impl InteractiveEnv {
         }
 
         let parser = crate::build_interactive();
-        let rpc_client = HttpRpcClient::new(config.get_url().to_string());
-        let raw_rpc_client = RawHttpRpcClient::from_uri(config.get_url());
+        let rpc_client = HttpRpcClient::try_new(config.get_url().to_string())?;
+        let raw_rpc_client = RawHttpRpcClient::try_from_uri(config.get_url())?;
         let key_store = get_key_store(&ckb_cli_dir)?;
         let ledger_key_store = get_ledger_key_store(&ckb_cli_dir)?;
         Ok(InteractiveEnv {
@@ -86,6 +135,8 @@ impl InteractiveEnv {
             ledger_key_store,
             index_controller,
             genesis_info: None,
+            env_regex: Regex::new(ENV_PATTERN).unwrap(),
+            source_depth: 0,
         })
     }
 
@@ -93,7 +144,6 @@ impl InteractiveEnv {
         self.print_logo();
         self.config.print();
 
-        let env_regex = Regex::new(ENV_PATTERN).unwrap();
         let prompt = {
             #[cfg(unix)]
             {
@@ -134,10 +184,7 @@ impl InteractiveEnv {
             eprintln!("No previous history.");
         }
 
-        Request::call(
-            self.index_controller.sender(),
-            IndexRequest::UpdateUrl(self.config.get_url().to_string()),
-        );
+        self.start_index();
         let mut last_save_history = Instant::now();
         loop {
             rl_mode(
@@ -147,14 +194,15 @@ impl InteractiveEnv {
             );
             match rl.readline(&prompt) {
                 Ok(line) => {
-                    match self.handle_command(line.as_str(), &env_regex) {
-                        Ok(true) => {
+                    let format = self.config.output_format();
+                    let color = ColorWhen::new(self.config.color()).color();
+                    let last_journal_id = last_rpc_journal_id();
+                    match self.handle_command(line.as_str(), format, color) {
+                        Ok(CommandOutput::Exit) => {
                             break;
                         }
-                        Ok(false) => {}
-                        Err(err) => {
-                            eprintln!("{}", err);
-                        }
+                        Ok(output) => self.print_output(output, format, color),
+                        Err(err) => print_error(&err, last_journal_id),
                     }
                     rl.add_history_entry(line.as_str());
                 }
@@ -185,6 +233,115 @@ impl InteractiveEnv {
         Ok(())
     }
 
+    /// Execute the command lines of a script file (`--batch-file` or `source`)
+    /// and print the outputs as the interactive mode. Empty lines and lines
+    /// starting with `#` are skipped, `exit` stops the script. The script
+    /// stops at the first failed command unless `continue_on_error`, an error
+    /// is returned when any command failed. A line with an undefined `${var}`
+    /// fails.
+    pub fn run_script(&mut self, path: &Path, continue_on_error: bool) -> Result<(), String> {
+        if self.source_depth >= MAX_SOURCE_DEPTH {
+            return Err(format!(
+                "Too many nested scripts (at most {})",
+                MAX_SOURCE_DEPTH
+            ));
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Read script {} failed: {}", path.display(), err))?;
+        self.source_depth += 1;
+        let mut failed = 0;
+        for (idx, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let format = self.config.output_format();
+            let color = ColorWhen::new(self.config.color()).color();
+            let last_journal_id = last_rpc_journal_id();
+            // An undefined variable would shift or empty the arguments
+            let undefined = self.config.undefined_vars(&self.env_regex, line);
+            let result = if undefined.is_empty() {
+                self.handle_command(line, format, color)
+            } else {
+                Err(format!("Undefined variable: {}", undefined.join(", ")))
+            };
+            match result {
+                Ok(CommandOutput::Exit) => break,
+                Ok(output) => self.print_output(output, format, color),
+                Err(err) => {
+                    let err = format!("{}:{}: {}", path.display(), idx + 1, err);
+                    if !continue_on_error {
+                        self.source_depth -= 1;
+                        return Err(err);
+                    }
+                    print_error(&err, last_journal_id);
+                    failed += 1;
+                }
+            }
+        }
+        self.source_depth -= 1;
+        if failed > 0 {
+            Err(format!(
+                "{} command(s) of {} failed",
+                failed,
+                path.display()
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn print_output(&self, output: CommandOutput, format: OutputFormat, color: bool) {
+        match output {
+            CommandOutput::Text(output) => println!("{}", output),
+            CommandOutput::Value(value) => println!("{}", value.render(format, color)),
+            CommandOutput::Config(_) => self.config.print(),
+            CommandOutput::Empty | CommandOutput::Exit => {}
+        }
+    }
+
+    /// Start (or restart) syncing the index database from the configured url
+    pub fn start_index(&self) {
+        Request::call(
+            self.index_controller.sender(),
+            IndexRequest::UpdateUrl(self.config.get_url().to_string()),
+        );
+    }
+
+    /// Evaluate a command line the same way as the interactive mode (include
+    /// `config`, `set`, `get` and the `${var}` substitution) without printing,
+    /// the output is the json value of the command.
+    pub fn evaluate(&mut self, line: &str) -> Result<CommandOutput, String> {
+        let (output, captured) =
+            capture_json(|| self.handle_command(line, OutputFormat::Json, false));
+        Ok(match output? {
+            CommandOutput::Text(output) => CommandOutput::Value(captured.into_value(output)),
+            output => output,
+        })
+    }
+
+    /// Parse and process a subcommand without printing, the output is in json
+    /// (used by server mode, the interactive only commands are not accepted)
+    pub fn execute(&mut self, args: Vec<String>) -> Result<serde_json::Value, String> {
+        let matches = self
+            .parser
+            .clone()
+            .get_matches_from_safe(args)
+            .map_err(|err| err.message)?;
+        if matches.is_present("output-to") {
+            return Err("--output-to is not supported here".to_string());
+        }
+        match matches.subcommand() {
+            (name, Some(sub_matches)) => {
+                let (output, captured) = capture_json(|| {
+                    self.process_subcommand(name, sub_matches, OutputFormat::Json, false, false)
+                });
+                output.map(|output| captured.into_value(output))
+            }
+            _ => Err(matches.usage().to_owned()),
+        }
+    }
+
     fn print_logo(&mut self) {
         println!(
             "{}",
@@ -219,166 +376,418 @@ impl InteractiveEnv {
         Ok(self.genesis_info.clone().unwrap())
     }
 
-    fn handle_command(&mut self, line: &str, env_regex: &Regex) -> Result<bool, String> {
-        let args = match shell_words::split(self.config.replace_cmd(&env_regex, line).as_str()) {
+    fn handle_command(
+        &mut self,
+        line: &str,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<CommandOutput, String> {
+        if let Some(result) = parse_let(line) {
+            let (name, field, command) = result?;
+            return self.capture_output(name, field, command);
+        }
+        let args = match shell_words::split(self.config.replace_cmd(&self.env_regex, line).as_str())
+        {
             Ok(args) => args,
             Err(e) => return Err(e.to_string()),
         };
 
-        let format = self.config.output_format();
-        let color = ColorWhen::new(self.config.color()).color();
         let debug = self.config.debug();
         match self.parser.clone().get_matches_from_safe(args) {
-            Ok(matches) => match matches.subcommand() {
-                ("config", Some(m)) => {
-                    m.value_of("url").and_then(|url| {
-                        let index_sender = self.index_controller.sender();
-                        Request::call(index_sender, IndexRequest::UpdateUrl(url.to_string()));
-                        self.config.set_url(url.to_string());
-                        self.rpc_client = HttpRpcClient::new(self.config.get_url().to_string());
-                        self.raw_rpc_client = RawHttpRpcClient::from_uri(self.config.get_url());
-                        self.config
-                            .set_network(get_network_type(&mut self.rpc_client).ok());
-                        self.genesis_info = None;
-                        Some(())
-                    });
-                    if m.is_present("color") {
-                        self.config.switch_color();
-                    }
+            Ok(matches) => {
+                if let Some(result) = schema_request(&matches) {
+                    return result.map(|schema| CommandOutput::Text(schema.trim_end().to_string()));
+                }
+                match matches.subcommand() {
+                    ("config", Some(m)) => {
+                        if let Some(url) = m.value_of("url") {
+                            // An url not usable (eg: https with `--tor`) keeps the current node
+                            let rpc_client = HttpRpcClient::try_new(url.to_string())?;
+                            let raw_rpc_client = RawHttpRpcClient::try_from_uri(url)?;
+                            let index_sender = self.index_controller.sender();
+                            Request::call(index_sender, IndexRequest::UpdateUrl(url.to_string()));
+                            self.config.set_url(url.to_string());
+                            self.rpc_client = rpc_client;
+                            self.raw_rpc_client = raw_rpc_client;
+                            self.config
+                                .set_network(get_network_type(&mut self.rpc_client).ok());
+                            self.genesis_info = None;
+                        }
+                        if m.is_present("color") {
+                            self.config.switch_color();
+                        }
 
-                    if let Some(format) = m.value_of("output-format") {
-                        let output_format =
-                            OutputFormat::from_str(format).unwrap_or(OutputFormat::Yaml);
-                        self.config.set_output_format(output_format);
-                    }
+                        if let Some(format) = m.value_of("output-format") {
+                            let output_format =
+                                OutputFormat::from_str(format).unwrap_or(OutputFormat::Yaml);
+                            self.config.set_output_format(output_format);
+                        }
 
-                    if m.is_present("debug") {
-                        self.config.switch_debug();
-                    }
+                        if m.is_present("debug") {
+                            self.config.switch_debug();
+                        }
 
-                    if m.is_present("edit_style") {
-                        self.config.switch_edit_style();
-                    }
+                        if m.is_present("edit_style") {
+                            self.config.switch_edit_style();
+                        }
 
-                    if m.is_present("completion_style") {
-                        self.config.switch_completion_style();
-                    }
+                        if m.is_present("completion_style") {
+                            self.config.switch_completion_style();
+                        }
 
-                    self.config.print();
-                    let mut file = fs::File::create(self.config_file.as_path())
-                        .map_err(|err| format!("open config error: {:?}", err))?;
-                    let content = serde_json::to_string_pretty(&json!({
-                        "url": self.config.get_url().to_string(),
-                        "color": self.config.color(),
-                        "debug": self.config.debug(),
-                        "output_format": self.config.output_format().to_string(),
-                        "completion_style": self.config.completion_style(),
-                        "edit_style": self.config.edit_style(),
-                    }))
-                    .unwrap();
-                    file.write_all(content.as_bytes())
-                        .map_err(|err| format!("save config error: {:?}", err))?;
-                    Ok(())
-                }
-                ("set", Some(m)) => {
-                    let key = m.value_of("key").unwrap().to_owned();
-                    let value = m.value_of("value").unwrap().to_owned();
-                    self.config.set(key, serde_json::Value::String(value));
-                    Ok(())
-                }
-                ("get", Some(m)) => {
-                    let key = m.value_of("key");
-                    println!("{}", self.config.get(key).render(format, color));
-                    Ok(())
-                }
-                ("info", _) => {
-                    self.config.print();
-                    Ok(())
-                }
-                ("rpc", Some(sub_matches)) => {
-                    check_alerts(&mut self.rpc_client);
-                    let output = RpcSubCommand::new(&mut self.rpc_client, &mut self.raw_rpc_client)
-                        .process(&sub_matches, format, color, debug)?;
-                    println!("{}", output);
-                    Ok(())
-                }
-                ("account", Some(sub_matches)) => {
-                    let output =
-                        AccountSubCommand::new(&mut self.key_store, &mut self.ledger_key_store)
-                            .process(&sub_matches, format, color, debug)?;
-                    println!("{}", output);
-                    Ok(())
-                }
-                ("mock-tx", Some(sub_matches)) => {
-                    let genesis_info = self.genesis_info().ok();
-                    let output = MockTxSubCommand::new(
-                        &mut self.rpc_client,
-                        &mut self.key_store,
-                        genesis_info,
-                    )
-                    .process(&sub_matches, format, color, debug)?;
-                    println!("{}", output);
-                    Ok(())
-                }
-                ("tx", Some(sub_matches)) => {
-                    let genesis_info = self.genesis_info().ok();
-                    let output = TxSubCommand::new(
-                        &mut self.rpc_client,
-                        &mut self.key_store,
-                        &mut self.ledger_key_store,
-                        genesis_info,
-                    )
-                    .process(&sub_matches, format, color, debug)?;
-                    println!("{}", output);
-                    Ok(())
-                }
-                ("util", Some(sub_matches)) => {
-                    let output = UtilSubCommand::new(&mut self.rpc_client, &mut self.key_store)
-                        .process(&sub_matches, format, color, debug)?;
-                    println!("{}", output);
-                    Ok(())
-                }
-                ("molecule", Some(sub_matches)) => {
-                    let output =
-                        MoleculeSubCommand::new().process(&sub_matches, format, color, debug)?;
-                    println!("{}", output);
-                    Ok(())
-                }
-                ("wallet", Some(sub_matches)) => {
-                    let genesis_info = self.genesis_info()?;
-                    let output = WalletSubCommand::new(
-                        &mut self.rpc_client,
-                        &mut self.key_store,
-                        &mut self.ledger_key_store,
-                        Some(genesis_info),
-                        self.index_dir.clone(),
-                        self.index_controller.clone(),
-                    )
-                    .process(&sub_matches, format, color, debug)?;
-                    println!("{}", output);
-                    Ok(())
-                }
-                ("dao", Some(sub_matches)) => {
-                    let genesis_info = self.genesis_info()?;
-                    let output = DAOSubCommand::new(
-                        &mut self.rpc_client,
-                        &mut self.key_store,
-                        &mut self.ledger_key_store,
-                        genesis_info,
-                        self.index_dir.clone(),
-                        self.index_controller.clone(),
-                    )
-                    .process(&sub_matches, format, color, debug)?;
-                    println!("{}", output);
-                    Ok(())
-                }
-                ("exit", _) => {
-                    return Ok(true);
+                        if m.is_present("hardened_only") {
+                            self.config.switch_hardened_only();
+                        }
+
+                        // Keep the entries not set here (eg: remote_config). The
+                        // entries the remote config may override (`url`,
+                        // `hardened_only`, ...) are only saved when changed by this
+                        // command, otherwise the remote values would persist locally
+                        let mut content = fs::read_to_string(self.config_file.as_path())
+                            .ok()
+                            .and_then(|content| serde_json::from_str(&content).ok())
+                            .filter(serde_json::Value::is_object)
+                            .unwrap_or_else(|| json!({}));
+                        for (key, value) in json!({
+                            "color": self.config.color(),
+                            "debug": self.config.debug(),
+                            "output_format": self.config.output_format().to_string(),
+                            "completion_style": self.config.completion_style(),
+                            "edit_style": self.config.edit_style(),
+                        })
+                        .as_object()
+                        .unwrap()
+                        {
+                            content[key.as_str()] = value.clone();
+                        }
+                        if m.value_of("url").is_some() {
+                            content["url"] = json!(self.config.get_url());
+                        }
+                        if m.is_present("hardened_only") {
+                            content["hardened_only"] =
+                                json!(self.config.signing_policy().hardened_only);
+                        }
+                        if self.config.alert_config() != &AlertConfig::default() {
+                            content["alert_signature"] = json!(self.config.alert_config());
+                        }
+                        let content = serde_json::to_string_pretty(&content).unwrap();
+                        write_file_atomic(self.config_file.as_path(), content.as_bytes())
+                            .map_err(|err| format!("save config error: {}", err))?;
+                        Ok(CommandOutput::Config(self.config_entries()))
+                    }
+                    ("set", Some(m)) => {
+                        let key = m.value_of("key").unwrap().to_owned();
+                        let value = m.value_of("value").unwrap().to_owned();
+                        self.config.set(key, serde_json::Value::String(value));
+                        Ok(CommandOutput::Empty)
+                    }
+                    ("get", Some(m)) => {
+                        let key = m.value_of("key");
+                        Ok(CommandOutput::Text(
+                            self.config.get(key).render(format, color),
+                        ))
+                    }
+                    ("info", _) => Ok(CommandOutput::Config(self.config_entries())),
+                    ("source", Some(m)) => {
+                        let path = m.value_of("file").unwrap();
+                        self.run_script(Path::new(path), m.is_present("continue-on-error"))?;
+                        Ok(CommandOutput::Empty)
+                    }
+                    ("exit", _) => Ok(CommandOutput::Exit),
+                    (name, Some(sub_matches)) => match matches.value_of("output-to") {
+                        Some(path) => {
+                            let output =
+                                self.process_subcommand(name, sub_matches, format, false, debug)?;
+                            write_output(Path::new(path), &output)?;
+                            Ok(CommandOutput::Empty)
+                        }
+                        None => self
+                            .process_subcommand(name, sub_matches, format, color, debug)
+                            .map(CommandOutput::Text),
+                    },
+                    _ => Ok(CommandOutput::Empty),
                 }
-                _ => Ok(()),
-            },
+            }
             Err(err) => Err(err.to_string()),
         }
-        .map(|_| false)
+    }
+
+    // Store the json output of the command (or a field of it) in the variable
+    fn capture_output(
+        &mut self,
+        name: String,
+        field: Option<String>,
+        command: &str,
+    ) -> Result<CommandOutput, String> {
+        let output = match self.evaluate(command)? {
+            CommandOutput::Value(value) | CommandOutput::Config(value) => value,
+            _ => return Err("The command has no output to store".to_string()),
+        };
+        let value = match field {
+            Some(selector) => select_field(&output, &selector)?,
+            None => output,
+        };
+        self.config.set(name, value.clone());
+        Ok(CommandOutput::Value(value))
+    }
+
+    fn config_entries(&self) -> serde_json::Value {
+        serde_json::Value::Object(
+            self.config
+                .entries()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), serde_json::Value::String(value)))
+                .collect(),
+        )
+    }
+
+    /// Process the subcommands (except the interactive only ones: config, set,
+    /// get, info and exit) and return the output
+    pub(crate) fn process_subcommand(
+        &mut self,
+        name: &str,
+        sub_matches: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        match name {
+            "rpc" => {
+                check_alerts(&mut self.rpc_client);
+                RpcSubCommand::new(&mut self.rpc_client, &mut self.raw_rpc_client).process(
+                    sub_matches,
+                    format,
+                    color,
+                    debug,
+                )
+            }
+            "node" => {
+                NodeSubCommand::new(&mut self.rpc_client).process(sub_matches, format, color, debug)
+            }
+            "account" => AccountSubCommand::new(
+                &mut self.rpc_client,
+                &mut self.key_store,
+                &mut self.ledger_key_store,
+                self.genesis_info.clone(),
+                self.index_dir.clone(),
+                self.index_controller.clone(),
+                self.config.signing_policy(),
+            )
+            .process(sub_matches, format, color, debug),
+            "mock-tx" => {
+                let genesis_info = self.genesis_info().ok();
+                MockTxSubCommand::new(
+                    &mut self.rpc_client,
+                    &mut self.key_store,
+                    genesis_info,
+                    self.config.signing_policy(),
+                )
+                .process(sub_matches, format, color, debug)
+            }
+            "tx" => {
+                let genesis_info = self.genesis_info().ok();
+                TxSubCommand::new(
+                    &mut self.rpc_client,
+                    &mut self.key_store,
+                    &mut self.ledger_key_store,
+                    genesis_info,
+                    self.index_dir.clone(),
+                    self.index_controller.clone(),
+                    self.config.signing_policy(),
+                    self.config.send_targets(),
+                )
+                .process(sub_matches, format, color, debug)
+            }
+            "util" => UtilSubCommand::new(
+                &mut self.rpc_client,
+                &mut self.key_store,
+                &mut self.ledger_key_store,
+                self.config.alert_config().clone(),
+            )
+            .process(sub_matches, format, color, debug),
+            "molecule" => MoleculeSubCommand::new().process(sub_matches, format, color, debug),
+            "wallet" => {
+                let genesis_info = self.genesis_info()?;
+                WalletSubCommand::new(
+                    &mut self.rpc_client,
+                    &mut self.key_store,
+                    &mut self.ledger_key_store,
+                    Some(genesis_info),
+                    self.index_dir.clone(),
+                    self.index_controller.clone(),
+                    self.config.signing_policy(),
+                    self.config.send_targets(),
+                )
+                .process(sub_matches, format, color, debug)
+            }
+            "dao" => {
+                let genesis_info = self.genesis_info()?;
+                DAOSubCommand::new(
+                    &mut self.rpc_client,
+                    &mut self.key_store,
+                    &mut self.ledger_key_store,
+                    genesis_info,
+                    self.index_dir.clone(),
+                    self.index_controller.clone(),
+                    self.config.signing_policy(),
+                    self.config.send_targets(),
+                )
+                .process(sub_matches, format, color, debug)
+            }
+            "sudt" => {
+                let genesis_info = self.genesis_info()?;
+                SudtSubCommand::new(
+                    &mut self.rpc_client,
+                    &mut self.key_store,
+                    Some(genesis_info),
+                    self.index_dir.clone(),
+                    self.index_controller.clone(),
+                    self.config.signing_policy(),
+                    self.config.send_targets(),
+                )
+                .process(sub_matches, format, color, debug)
+            }
+            "deploy" => {
+                let genesis_info = self.genesis_info()?;
+                DeploySubCommand::new(
+                    &mut self.rpc_client,
+                    &mut self.key_store,
+                    Some(genesis_info),
+                    self.index_dir.clone(),
+                    self.index_controller.clone(),
+                    self.config.signing_policy(),
+                    self.config.send_targets(),
+                )
+                .process(sub_matches, format, color, debug)
+            }
+            _ => Err(format!("Unknown subcommand: {}", name)),
+        }
+    }
+}
+
+// Parse `let <name> [--field <selector>] = <command>` into the variable name,
+// the field selector and the command, `None` if it is not a let line
+fn parse_let(line: &str) -> Option<Result<(String, Option<String>, &str), String>> {
+    let line = line.trim_start();
+    if !line.starts_with("let ") {
+        return None;
+    }
+    let usage = "Usage: let <name> [--field <selector>] = <command>".to_string();
+    let pos = match line.find('=') {
+        Some(pos) => pos,
+        None => return Some(Err(usage)),
+    };
+    let words = shell_words::split(&line[4..pos]).unwrap_or_default();
+    let (name, field) = match words.as_slice() {
+        [name] => (name.clone(), None),
+        [name, flag, selector] if flag == "--field" => (name.clone(), Some(selector.clone())),
+        _ => return Some(Err(usage)),
+    };
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Some(Err(format!(
+            "Invalid variable name {}: only letters, digits, `_` and `-` are allowed",
+            name
+        )));
+    }
+    Some(Ok((name, field, line[pos + 1..].trim())))
+}
+
+// Print the error with the id of the last RPC exchange when the command made
+// any (the RPC journal is enabled)
+fn print_error(err: &str, last_journal_id: Option<String>) {
+    eprintln!("{}", err);
+    let journal_id = last_rpc_journal_id();
+    if journal_id != last_journal_id {
+        if let Some(id) = journal_id {
+            eprintln!("Last RPC exchange in the RPC journal: {}", id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_script() {
+        let dir = std::env::temp_dir().join(format!("ckb-cli-run-script-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let url = "http://127.0.0.1:8114";
+        let index_state = Arc::new(RwLock::new(IndexThreadState::WaitToStart));
+        let config = GlobalConfig::new(Some(url.to_string()), Arc::clone(&index_state));
+        let index_controller = start_index_thread(url, dir.join("index"), index_state, 1, false);
+        let mut env =
+            InteractiveEnv::from_config(dir.join("ckb-cli"), config, index_controller).unwrap();
+        let value = |env: &InteractiveEnv, key: &str| env.config.get(Some(key)).next().cloned();
+
+        let script = dir.join("script");
+        fs::write(
+            &script,
+            "# transfer\nset amount 100\n\nset capacity ${amount}\nset to ${to}\nset fee 1\n",
+        )
+        .unwrap();
+        // Stops at the line with the undefined variable
+        let err = env.run_script(&script, false).unwrap_err();
+        assert!(err.contains(":5: Undefined variable: to"), "{}", err);
+        assert_eq!(value(&env, "capacity"), Some(json!("100")));
+        assert_eq!(value(&env, "to"), None);
+        assert_eq!(value(&env, "fee"), None);
+
+        // Continues after the failed line, the failure is still reported
+        let err = env.run_script(&script, true).unwrap_err();
+        assert!(err.starts_with("1 command(s)"), "{}", err);
+        assert_eq!(value(&env, "fee"), Some(json!("1")));
+
+        env.config.set("to".to_string(), json!("ckt1qyq"));
+        env.run_script(&script, false).unwrap();
+        assert_eq!(value(&env, "to"), Some(json!("ckt1qyq")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_evaluate() {
+        let dir = std::env::temp_dir().join(format!("ckb-cli-evaluate-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut env = InteractiveEnv::new(dir.join("ckb-cli"), "http://127.0.0.1:8114").unwrap();
+
+        assert_eq!(env.evaluate("set amount 100"), Ok(CommandOutput::Empty));
+        assert_eq!(
+            env.evaluate("get amount"),
+            Ok(CommandOutput::Value(json!("100")))
+        );
+
+        // The value the output is rendered from
+        let out_point = json!({
+            "tx_hash": format!("0x{}", "0".repeat(64)),
+            "index": "0x0",
+        });
+        assert_eq!(
+            env.evaluate("molecule default --type OutPoint"),
+            Ok(CommandOutput::Value(out_point))
+        );
+        // A message is a string
+        let json_path = dir.join("out-point.json");
+        assert_eq!(
+            env.evaluate(&format!(
+                "molecule default --type OutPoint --json-path {}",
+                json_path.display()
+            )),
+            Ok(CommandOutput::Value(json!("DONE")))
+        );
+
+        assert_eq!(
+            env.evaluate("let index --field index = molecule default --type OutPoint"),
+            Ok(CommandOutput::Value(json!("0x0")))
+        );
+        assert_eq!(
+            env.evaluate("get index"),
+            Ok(CommandOutput::Value(json!("0x0")))
+        );
+        assert!(env.evaluate("molecule default --type Unknown").is_err());
+        assert_eq!(env.evaluate("exit"), Ok(CommandOutput::Exit));
+        fs::remove_dir_all(dir).unwrap();
     }
 }
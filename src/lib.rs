@@ -0,0 +1,352 @@
+//! The command surface of ckb-cli as a library: the binary is a thin wrapper
+//! around `run`, test harnesses and GUIs can drive the same commands in-process
+//! through `InteractiveEnv::evaluate`. The modules are not part of the API.
+
+use std::net::SocketAddr;
+
+use ckb_build_info::Version;
+use ckb_ledger::AppVersion;
+use clap::crate_version;
+use clap::{App, AppSettings, Arg, SubCommand};
+
+use server::Server;
+use subcommands::{
+    AccountSubCommand, DAOSubCommand, DeploySubCommand, IndexSubCommand, MockTxSubCommand,
+    MoleculeSubCommand, NodeSubCommand, RpcSubCommand, SudtSubCommand, TxSubCommand,
+    UtilSubCommand, WalletSubCommand,
+};
+use utils::{
+    arg_parser::{ArgParser, FilePathParser, FromStrParser, UrlParser},
+    other::{check_vault_name, parse_ledger_emulator},
+};
+
+mod cli;
+mod interactive;
+mod server;
+mod subcommands;
+mod utils;
+
+pub use cli::run;
+pub use interactive::{CommandOutput, InteractiveEnv};
+
+/// The decoders of `molecule decode`, used by the fuzz targets
+#[doc(hidden)]
+pub mod fuzzing {
+    pub use crate::subcommands::molecule::decode_molecule;
+    pub use crate::utils::printer::OutputFormat;
+}
+
+pub(crate) fn get_version() -> Version {
+    let major = env!("CARGO_PKG_VERSION_MAJOR")
+        .parse::<u8>()
+        .expect("CARGO_PKG_VERSION_MAJOR parse success");
+    let minor = env!("CARGO_PKG_VERSION_MINOR")
+        .parse::<u8>()
+        .expect("CARGO_PKG_VERSION_MINOR parse success");
+    let patch = env!("CARGO_PKG_VERSION_PATCH")
+        .parse::<u16>()
+        .expect("CARGO_PKG_VERSION_PATCH parse success");
+    let dash_pre = {
+        let pre = env!("CARGO_PKG_VERSION_PRE");
+        if pre == "" {
+            pre.to_string()
+        } else {
+            "-".to_string() + pre
+        }
+    };
+
+    let commit_describe = option_env!("COMMIT_DESCRIBE").map(ToString::to_string);
+    #[cfg(docker)]
+    let commit_describe = commit_describe.map(|s| s.replace("-dirty", ""));
+    let commit_date = option_env!("COMMIT_DATE").map(ToString::to_string);
+    Version {
+        code_name: None,
+        major,
+        minor,
+        patch,
+        dash_pre,
+        commit_describe,
+        commit_date,
+    }
+}
+
+pub(crate) fn build_cli<'a>(version_short: &'a str, version_long: &'a str) -> App<'a, 'a> {
+    let app = App::new("ckb-cli")
+        .version(version_short)
+        .long_version(version_long)
+        .global_setting(AppSettings::ColoredHelp)
+        .global_setting(AppSettings::DeriveDisplayOrder)
+        .subcommand(RpcSubCommand::subcommand())
+        .subcommand(NodeSubCommand::subcommand())
+        .subcommand(AccountSubCommand::subcommand("account"))
+        .subcommand(MockTxSubCommand::subcommand("mock-tx"))
+        .subcommand(TxSubCommand::subcommand("tx"))
+        .subcommand(UtilSubCommand::subcommand("util"))
+        .subcommand(MoleculeSubCommand::subcommand("molecule"))
+        .subcommand(WalletSubCommand::subcommand())
+        .subcommand(DAOSubCommand::subcommand())
+        .subcommand(SudtSubCommand::subcommand())
+        .subcommand(DeploySubCommand::subcommand())
+        // Not available in interactive mode, the index thread is running there
+        .subcommand(IndexSubCommand::subcommand())
+        .subcommand(Server::subcommand())
+        .arg(
+            Arg::with_name("url")
+                .long("url")
+                .takes_value(true)
+                .validator(|input| UrlParser.validate(input))
+                .help("RPC API server url"),
+        )
+        .arg(
+            Arg::with_name("vault")
+                .long("vault")
+                .takes_value(true)
+                .validator(|input| check_vault_name(&input))
+                .help("Use an isolated keystore, config, index and local stores under ~/.ckb-cli/vaults/<name> (env: CKB_CLI_VAULT)"),
+        )
+        .arg(
+            Arg::with_name("ledger-emulator")
+                .long("ledger-emulator")
+                .takes_value(true)
+                .validator(|input| parse_ledger_emulator(&input).map(|_| ()))
+                .help("Use the Speculos emulator listening on this APDU address (host:port) instead of ledger devices (env: CKB_CLI_LEDGER_EMULATOR)"),
+        )
+        .arg(
+            Arg::with_name("ledger-min-app-version")
+                .long("ledger-min-app-version")
+                .takes_value(true)
+                .validator(|input| AppVersion::parse(&input).map(|_| ()))
+                .help("Refuse to sign by a ledger app older than this version (major.minor.patch, default: 0.5.0) (env: CKB_CLI_LEDGER_MIN_APP_VERSION)"),
+        )
+        .arg(
+            Arg::with_name("password-file")
+                .long("password-file")
+                .takes_value(true)
+                .validator(|input| FilePathParser::new(true).validate(input))
+                .help("Read the keystore password from this file instead of prompting, every password prompt is answered with it. Keep the file readable only by you (chmod 600)"),
+        )
+        .arg(
+            Arg::with_name("password-fd")
+                .long("password-fd")
+                .takes_value(true)
+                .conflicts_with("password-file")
+                .validator(|input| FromStrParser::<i32>::new().validate(input))
+                .help("Read the keystore password from this file descriptor (unix only, 3 or greater, it is closed after reading), eg. `--password-fd 3 3<<<\"$PASS\"`. The environment variable CKB_CLI_PASSWORD is used when neither is given, it is less safe since the environment is visible to other processes of the user"),
+        )
+        .arg(
+            Arg::with_name("socks-proxy")
+                .long("socks-proxy")
+                .takes_value(true)
+                .validator(|input| FromStrParser::<SocketAddr>::new().validate(input))
+                .help("Connect the RPC servers through this SOCKS5 proxy, required by `.onion` url (env: CKB_CLI_SOCKS_PROXY)"),
+        )
+        .arg(
+            Arg::with_name("broadcast-all-nodes")
+                .long("broadcast-all-nodes")
+                .help("Send the transactions to the nodes of `broadcast_urls` config at the same time as the --url node, the transaction is sent when any node accepted it"),
+        )
+        .arg(
+            Arg::with_name("rpc-journal")
+                .long("rpc-journal")
+                .help("Record the RPC requests and responses in ~/.ckb-cli/rpc-journal.jsonl (bounded by `rpc_journal_max_size` config, 4 MiB by default), the id of the last exchange is shown with the errors (config: `rpc_journal`)"),
+        )
+        .arg(
+            Arg::with_name("tor")
+                .long("tor")
+                .conflicts_with("socks-proxy")
+                .help("Connect the RPC servers through local Tor (127.0.0.1:9050 or 9150), a `tor` process is spawned if it is not running"),
+        )
+        .arg(
+            Arg::with_name("output-format")
+                .long("output-format")
+                .takes_value(true)
+                .possible_values(&["yaml", "json", "csv"])
+                .default_value("yaml")
+                .global(true)
+                .help("Select output format"),
+        )
+        .arg(
+            Arg::with_name("no-color")
+                .long("no-color")
+                .global(true)
+                .help("Do not highlight(color) output json"),
+        )
+        .arg(
+            Arg::with_name("debug")
+                .long("debug")
+                .global(true)
+                .help("Display request parameters"),
+        )
+        .arg(arg_schema())
+        .arg(arg_output_to())
+        .arg(
+            Arg::with_name("index-fetch-concurrency")
+                .long("index-fetch-concurrency")
+                .takes_value(true)
+                .default_value("8")
+                .validator(|input| match FromStrParser::<usize>::default().parse(&input) {
+                    Ok(0) => Err("Must be at least 1".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(err),
+                })
+                .help("The number of concurrent block requests when the index database synchronizes"),
+        )
+        .arg(
+            Arg::with_name("yes")
+                .long("yes")
+                .global(true)
+                .help("Answer yes to the confirmation prompts (eg: the address resolved from an alias, which is still printed), for scripts without a terminal"),
+        )
+        .arg(
+            Arg::with_name("index-tx-history")
+                .long("index-tx-history")
+                .help("Record the transactions of each lock in the index database (used by `wallet tx-history` and the duplicate payment detection), takes more disk space"),
+        )
+        .arg(
+            Arg::with_name("batch-file")
+                .long("batch-file")
+                .takes_value(true)
+                .validator(|input| FilePathParser::new(true).validate(input))
+                .help("Execute the commands of this file (one command per line, as the interactive mode) and exit, the exit code is non-zero when a command failed"),
+        )
+        .arg(
+            Arg::with_name("continue-on-error")
+                .long("continue-on-error")
+                .requires("batch-file")
+                .help("Execute the remaining commands of --batch-file after a command failed"),
+        )
+        .arg(
+            Arg::with_name("wait-for-sync")
+                .long("wait-for-sync")
+                .global(true)
+                .help(
+                    "Ensure the index-store synchronizes completely before command being executed",
+                ),
+        );
+
+    #[cfg(unix)]
+    let app = app.subcommand(SubCommand::with_name("tui").about("Enter TUI mode"));
+
+    app
+}
+
+// Handled by `schema_request`, the arguments required by the commands having
+// an output schema are `required_unless` it
+fn arg_schema<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("schema")
+        .long("schema")
+        .global(true)
+        .help("Print the JSON Schema of the command's output")
+}
+
+fn arg_output_to<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("output-to")
+        .long("output-to")
+        .takes_value(true)
+        .global(true)
+        .validator(|input| FilePathParser::new(false).validate(input))
+        .help("Write the output to this file instead of stdout, atomically (gzip compressed when the file name ends with .gz)")
+}
+
+pub(crate) fn build_interactive() -> App<'static, 'static> {
+    App::new("interactive")
+        .version(crate_version!())
+        .global_setting(AppSettings::NoBinaryName)
+        .global_setting(AppSettings::ColoredHelp)
+        .global_setting(AppSettings::DeriveDisplayOrder)
+        .global_setting(AppSettings::DisableVersion)
+        .arg(arg_schema())
+        .arg(arg_output_to())
+        .subcommand(
+            SubCommand::with_name("config")
+                .about("Config environment")
+                .arg(
+                    Arg::with_name("url")
+                        .long("url")
+                        .validator(|input| UrlParser.validate(input))
+                        .takes_value(true)
+                        .help("Config RPC API url"),
+                )
+                .arg(
+                    Arg::with_name("color")
+                        .long("color")
+                        .help("Switch color for rpc interface"),
+                )
+                .arg(
+                    Arg::with_name("debug")
+                        .long("debug")
+                        .help("Switch debug mode"),
+                )
+                .arg(
+                    Arg::with_name("output-format")
+                        .long("output-format")
+                        .takes_value(true)
+                        .possible_values(&["yaml", "json", "csv"])
+                        .default_value("yaml")
+                        .help("Select output format"),
+                )
+                .arg(
+                    Arg::with_name("completion_style")
+                        .long("completion_style")
+                        .help("Switch completion style"),
+                )
+                .arg(
+                    Arg::with_name("edit_style")
+                        .long("edit_style")
+                        .help("Switch edit style"),
+                )
+                .arg(
+                    Arg::with_name("hardened_only")
+                        .long("hardened_only")
+                        .help("Switch hardened only signing policy (refuse non-hardened account level derivation path on hardware wallet)"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("set")
+                .about("Set a variable, used by `${key}` in the command lines (`let <key> [--field <selector>] = <command>` stores the output of a command)")
+                .arg(Arg::with_name("key").required(true).index(1).help("Key"))
+                .arg(Arg::with_name("value").required(true).index(2).help("Value")),
+        )
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("Get a variable, or list the variables")
+                .arg(
+                    Arg::with_name("key")
+                        .index(1)
+                        .help("Key, the field of a json value is given by dots (eg: tx.transaction.hash)"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("info").about("Display global variables"))
+        .subcommand(
+            SubCommand::with_name("source")
+                .about("Execute the commands of a file, one command per line")
+                .arg(
+                    Arg::with_name("file")
+                        .required(true)
+                        .index(1)
+                        .validator(|input| FilePathParser::new(true).validate(input))
+                        .help("The script file, empty lines and lines starting with `#` are skipped"),
+                )
+                .arg(
+                    Arg::with_name("continue-on-error")
+                        .long("continue-on-error")
+                        .help("Execute the remaining commands after a command failed"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("exit")
+                .visible_alias("quit")
+                .about("Exit the interactive interface"),
+        )
+        .subcommand(RpcSubCommand::subcommand())
+        .subcommand(NodeSubCommand::subcommand())
+        .subcommand(AccountSubCommand::subcommand("account"))
+        .subcommand(MockTxSubCommand::subcommand("mock-tx"))
+        .subcommand(TxSubCommand::subcommand("tx"))
+        .subcommand(UtilSubCommand::subcommand("util"))
+        .subcommand(MoleculeSubCommand::subcommand("molecule"))
+        .subcommand(WalletSubCommand::subcommand())
+        .subcommand(DAOSubCommand::subcommand())
+        .subcommand(SudtSubCommand::subcommand())
+        .subcommand(DeploySubCommand::subcommand())
+}
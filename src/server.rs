@@ -0,0 +1,691 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_json::{Map, Value};
+
+use crate::interactive::InteractiveEnv;
+use crate::utils::{
+    arg_parser::{ArgParser, DurationParser, FilePathParser, FromStrParser},
+    index::IndexController,
+    notify::{load_rules, Watcher},
+    other::{with_password_source, PasswordSource},
+};
+
+// Larger request body is refused
+const MAX_REQUEST_SIZE: usize = 1024 * 1024;
+// The connections are handled one at a time, an idle or slow client holds
+// the others for at most this long on each read or write
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_HEADERS: usize = 64;
+// Longer request line or header line is refused (with the line break)
+const MAX_LINE_LEN: u64 = 8 * 1024;
+const MIN_AUTH_TOKEN_LEN: usize = 16;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const COMMAND_FAILED: i64 = -32000;
+const SIGNING_DISABLED: i64 = -32001;
+
+// The params accepted by every query method of the local index
+const INDEX_QUERY_PARAMS: &[&str] = &["lock-hash", "address"];
+
+// The JSON-RPC methods: (method, command, accepted params, requires
+// `--enable-signing`). The params object is passed as the arguments of the
+// command, eg: `{"address": "ckt1...", "limit": 10}` =>
+// `--address=ckt1... --limit=10`. Only the listed params are accepted, the
+// callers must not read or write files of the server host, nor point the
+// server to their own endpoints (eg. --signer-url gets the signer token of
+// the server).
+const METHODS: &[(&str, &[&str], &[&str], bool)] = &[
+    (
+        "get_capacity",
+        &["wallet", "get-capacity"],
+        &[
+            "lock-hash",
+            "address",
+            "pubkey",
+            "lock-arg",
+            "derive-receiving-address-length",
+            "derive-change-address-length",
+            "derived",
+            "read-through",
+        ],
+        false,
+    ),
+    (
+        "get_live_cells",
+        &["wallet", "get-live-cells"],
+        &[
+            "lock-hash",
+            "type-hash",
+            "code-hash",
+            "address",
+            "limit",
+            "from",
+            "to",
+            "read-through",
+        ],
+        false,
+    ),
+    (
+        "get_tx_history",
+        &["wallet", "tx-history"],
+        &["address", "limit", "before"],
+        false,
+    ),
+    ("get_tx_status", &["tx", "status"], &["tx-hash"], false),
+    (
+        "get_dao_deposited_cells",
+        &["dao", "query-deposited-cells"],
+        INDEX_QUERY_PARAMS,
+        false,
+    ),
+    (
+        "get_dao_prepared_cells",
+        &["dao", "query-prepared-cells"],
+        INDEX_QUERY_PARAMS,
+        false,
+    ),
+    (
+        "get_dao_compensation",
+        &["dao", "query-compensation"],
+        &["out-point"],
+        false,
+    ),
+    (
+        "transfer",
+        &["wallet", "transfer"],
+        &[
+            "from-account",
+            "from-locked-address",
+            "to-address",
+            "to-data",
+            "capacity",
+            "to",
+            "tx-fee",
+            "fee-rate",
+            "allow-high-fee",
+            "wait-for-committed",
+            "confirmations",
+            "timeout",
+            "change-cells",
+            "max-inputs",
+            "use-out-point",
+            "exclude-out-point",
+            "tip",
+            "tip-address",
+            "derive-receiving-address-length",
+            "derive-change-address-length",
+            "derive-change-address",
+            "memo",
+            "duplicate-window",
+            "allow-duplicate",
+        ],
+        true,
+    ),
+];
+
+/// Daemon mode, the index database keeps syncing in background and the
+/// wallet operations are served over JSON-RPC (HTTP POST), so callers do not
+/// repay the startup cost of the CLI for every call.
+pub struct Server {
+    env: InteractiveEnv,
+    enable_signing: bool,
+    auth_token: String,
+    watcher: Option<Watcher>,
+}
+
+impl Server {
+    pub fn new(
+        env: InteractiveEnv,
+        enable_signing: bool,
+        auth_token: String,
+        watcher: Option<Watcher>,
+    ) -> Server {
+        Server {
+            env,
+            enable_signing,
+            auth_token,
+            watcher,
+        }
+    }
+
+    pub fn subcommand() -> App<'static, 'static> {
+        SubCommand::with_name("server")
+            .about("Keep the index synced and serve wallet operations over JSON-RPC")
+            .arg(
+                Arg::with_name("listen")
+                    .long("listen")
+                    .takes_value(true)
+                    .default_value("127.0.0.1:8120")
+                    .validator(|input| FromStrParser::<SocketAddr>::new().validate(input))
+                    .help("The address JSON-RPC server listens on"),
+            )
+            .arg(
+                Arg::with_name("enable-signing")
+                    .long("enable-signing")
+                    .help("Serve the signing method (transfer), the keystore password is given by `password` param of every request. Only allowed on a loopback listen address"),
+            )
+            .arg(
+                Arg::with_name("auth-token-file")
+                    .long("auth-token-file")
+                    .takes_value(true)
+                    .required(true)
+                    .validator(|input| FilePathParser::new(true).validate(input))
+                    .help("The file of the secret token, every request must have the header `Authorization: Bearer <token>` (at least 16 characters)"),
+            )
+            .arg(
+                Arg::with_name("notify-rules")
+                    .long("notify-rules")
+                    .takes_value(true)
+                    .validator(|input| FilePathParser::new(true).validate(input))
+                    .help("Watch the rules in this file (eg. balance of an address drops below a threshold, a NervosDAO cell becomes withdrawable) and run the webhook/command hooks when they trigger"),
+            )
+            .arg(
+                Arg::with_name("notify-interval")
+                    .long("notify-interval")
+                    .takes_value(true)
+                    .default_value("60s")
+                    .validator(|input| DurationParser.validate(input))
+                    .help("How often the notify rules are checked"),
+            )
+    }
+
+    /// The token of `--auth-token-file`
+    pub fn auth_token_from_matches(m: &ArgMatches) -> Result<String, String> {
+        let path: PathBuf = FilePathParser::new(true).from_matches(m, "auth-token-file")?;
+        let token = fs::read_to_string(&path)
+            .map_err(|err| format!("Read {} failed: {}", path.display(), err))?
+            .trim()
+            .to_string();
+        if token.len() < MIN_AUTH_TOKEN_LEN {
+            return Err(format!(
+                "The auth token in {} is shorter than {} characters",
+                path.display(),
+                MIN_AUTH_TOKEN_LEN
+            ));
+        }
+        Ok(token)
+    }
+
+    /// The watcher of `--notify-rules`
+    pub fn watcher_from_matches(
+        m: &ArgMatches,
+        url: &str,
+        index_dir: PathBuf,
+        index_controller: IndexController,
+    ) -> Result<Option<Watcher>, String> {
+        let path: Option<PathBuf> =
+            FilePathParser::new(true).from_matches_opt(m, "notify-rules", false)?;
+        let path = match path {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let rules = load_rules(&path)?;
+        let interval: Duration = DurationParser.from_matches(m, "notify-interval")?;
+        if interval.as_secs() == 0 {
+            return Err("--notify-interval must be at least 1s".to_string());
+        }
+        let mut watcher = Watcher::new(
+            rules,
+            interval,
+            path.with_extension("state"),
+            url,
+            index_dir,
+            index_controller,
+        );
+        watcher.check_rules()?;
+        Ok(Some(watcher))
+    }
+
+    pub fn start(&mut self, listen: SocketAddr) -> Result<(), String> {
+        if self.enable_signing && !listen.ip().is_loopback() {
+            return Err(format!(
+                "--enable-signing requires a loopback listen address, got {}",
+                listen
+            ));
+        }
+        let listener = TcpListener::bind(listen)
+            .map_err(|err| format!("Listen on {} failed: {}", listen, err))?;
+        self.env.start_index();
+        if let Some(watcher) = self.watcher.take() {
+            eprintln!("Watching {} notify rules", watcher.rules_count());
+            watcher.start();
+        }
+        eprintln!(
+            "JSON-RPC server listening on {} (signing {})",
+            listen,
+            if self.enable_signing {
+                "enabled"
+            } else {
+                "disabled"
+            }
+        );
+        for stream in listener.incoming() {
+            let result = stream
+                .map_err(|err| err.to_string())
+                .and_then(|stream| self.handle_connection(stream));
+            if let Err(err) = result {
+                log::warn!("Handle connection failed: {}", err);
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_connection(&mut self, mut stream: TcpStream) -> Result<(), String> {
+        stream
+            .set_read_timeout(Some(IO_TIMEOUT))
+            .and_then(|_| stream.set_write_timeout(Some(IO_TIMEOUT)))
+            .map_err(|err| err.to_string())?;
+        let local_addr = stream.local_addr().map_err(|err| err.to_string())?;
+        let request = match read_http_request(&mut stream) {
+            Ok(request) => request,
+            Err((status, err)) => {
+                return write_http_response(&mut stream, status, err.as_bytes());
+            }
+        };
+        if let Err((status, err)) = check_headers(&request.headers, &self.auth_token, local_addr) {
+            return write_http_response(&mut stream, status, err.as_bytes());
+        }
+        let body = request.body;
+        let response = match serde_json::from_slice::<Value>(&body) {
+            Ok(request) => self.handle_request(request),
+            Err(err) => error_response(Value::Null, PARSE_ERROR, err.to_string()),
+        };
+        write_http_response(&mut stream, "200 OK", response.to_string().as_bytes())
+    }
+
+    fn handle_request(&mut self, request: Value) -> Value {
+        let id = request["id"].clone();
+        match self.call(&request["method"], request["params"].clone()) {
+            Ok(result) => serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": result,
+            }),
+            Err((code, message)) => error_response(id, code, message),
+        }
+    }
+
+    fn call(&mut self, method: &Value, params: Value) -> Result<Value, (i64, String)> {
+        let method = method
+            .as_str()
+            .ok_or_else(|| (INVALID_REQUEST, "Invalid method".to_string()))?;
+        let (command, allowed_params, signing) = METHODS
+            .iter()
+            .find(|(name, _, _, _)| *name == method)
+            .map(|(_, command, allowed_params, signing)| (*command, *allowed_params, *signing))
+            .ok_or_else(|| (METHOD_NOT_FOUND, format!("Method not found: {}", method)))?;
+        if signing && !self.enable_signing {
+            return Err((
+                SIGNING_DISABLED,
+                "Signing is disabled, start the server with --enable-signing".to_string(),
+            ));
+        }
+        let mut params = match params {
+            Value::Object(params) => params,
+            Value::Null => Map::new(),
+            _ => {
+                return Err((INVALID_PARAMS, "Params must be an object".to_string()));
+            }
+        };
+        let password = match params.remove("password") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(ref password)) if signing => Some(password.clone()),
+            Some(_) => {
+                return Err((
+                    INVALID_PARAMS,
+                    "Invalid password, only accepted by signing methods".to_string(),
+                ));
+            }
+        };
+        let args =
+            command_args(command, allowed_params, &params).map_err(|err| (INVALID_PARAMS, err))?;
+        let env = &mut self.env;
+        with_password_source(PasswordSource::Preset(password), || env.execute(args))
+            .map_err(|err| (COMMAND_FAILED, err))
+    }
+}
+
+fn error_response(id: Value, code: i64, message: String) -> Value {
+    serde_json::json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": {
+            "code": code,
+            "message": message,
+        },
+    })
+}
+
+// Convert params to arguments of command, `true` is a flag, an array is a
+// repeated argument, the params not in `allowed_params` are refused
+fn command_args(
+    command: &[&str],
+    allowed_params: &[&str],
+    params: &Map<String, Value>,
+) -> Result<Vec<String>, String> {
+    let mut args = command
+        .iter()
+        .map(|name| (*name).to_string())
+        .collect::<Vec<_>>();
+    for (key, value) in params {
+        let name = key.replace('_', "-");
+        if !allowed_params.contains(&name.as_str()) {
+            return Err(format!("Param is not accepted: {}", key));
+        }
+        let values = match value {
+            Value::Array(values) => values.iter().collect::<Vec<_>>(),
+            value => vec![value],
+        };
+        for value in values {
+            match value {
+                Value::Bool(true) => args.push(format!("--{}", name)),
+                Value::Bool(false) | Value::Null => {}
+                Value::String(value) => args.push(format!("--{}={}", name, value)),
+                Value::Number(value) => args.push(format!("--{}={}", name, value)),
+                _ => return Err(format!("Invalid value of param {}", key)),
+            }
+        }
+    }
+    Ok(args)
+}
+
+struct HttpRequest {
+    // The header names are lowercase
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+// Check the bearer token, and refuse the requests of browsers: a cross-origin
+// request has `Origin`, a DNS rebinding request has a foreign `Host`, and a
+// form post can not have the json `Content-Type`
+fn check_headers(
+    headers: &[(String, String)],
+    auth_token: &str,
+    local_addr: SocketAddr,
+) -> Result<(), (&'static str, String)> {
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let token = header("authorization")
+        .filter(|value| value.starts_with("Bearer "))
+        .map(|value| value["Bearer ".len()..].trim());
+    if !token
+        .map(|token| constant_time_eq(token.as_bytes(), auth_token.as_bytes()))
+        .unwrap_or(false)
+    {
+        return Err((
+            "401 Unauthorized",
+            "Invalid or missing bearer token".to_string(),
+        ));
+    }
+    if header("origin").is_some() {
+        return Err((
+            "403 Forbidden",
+            "Requests from browsers are refused".to_string(),
+        ));
+    }
+    let host_allowed = header("host")
+        .map(|host| {
+            let port = local_addr.port();
+            host == local_addr.to_string()
+                || host == format!("localhost:{}", port)
+                || host == format!("127.0.0.1:{}", port)
+                || host == format!("[::1]:{}", port)
+        })
+        .unwrap_or(false);
+    if !host_allowed {
+        return Err(("403 Forbidden", "Invalid Host header".to_string()));
+    }
+    let is_json = header("content-type")
+        .map(|value| {
+            value
+                .split(';')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .eq_ignore_ascii_case("application/json")
+        })
+        .unwrap_or(false);
+    if !is_json {
+        return Err((
+            "415 Unsupported Media Type",
+            "Content-Type must be application/json".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn read_http_request<R: Read>(stream: R) -> Result<HttpRequest, (&'static str, String)> {
+    let bad_request = |err: String| ("400 Bad Request", err);
+    let mut reader = BufReader::new(stream);
+    let request_line = read_line(&mut reader, "400 Bad Request")?;
+    if !request_line.starts_with("POST ") {
+        return Err(bad_request("Only POST method is supported".to_string()));
+    }
+    let mut headers = Vec::new();
+    loop {
+        let line = read_line(&mut reader, "431 Request Header Fields Too Large")?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if headers.len() >= MAX_HEADERS {
+            return Err((
+                "431 Request Header Fields Too Large",
+                "Too many headers".to_string(),
+            ));
+        }
+        let mut parts = line.splitn(2, ':');
+        let name = parts.next().unwrap_or_default().trim().to_ascii_lowercase();
+        let value = parts.next().unwrap_or_default().trim().to_string();
+        headers.push((name, value));
+    }
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .ok_or_else(|| bad_request("Missing Content-Length".to_string()))?
+        .1
+        .parse::<usize>()
+        .map_err(|err| bad_request(format!("Invalid Content-Length: {}", err)))?;
+    if content_length > MAX_REQUEST_SIZE {
+        return Err(bad_request(format!(
+            "Request is too large: {} > {}",
+            content_length, MAX_REQUEST_SIZE
+        )));
+    }
+    let mut body = vec![0u8; content_length];
+    reader
+        .read_exact(&mut body)
+        .map_err(|err| bad_request(err.to_string()))?;
+    Ok(HttpRequest { headers, body })
+}
+
+// A line of at most `MAX_LINE_LEN` bytes, a longer one is answered by `status`
+fn read_line<R: BufRead>(
+    reader: &mut R,
+    status: &'static str,
+) -> Result<String, (&'static str, String)> {
+    let mut line = String::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_LEN)
+        .read_line(&mut line)
+        .map_err(|err| ("400 Bad Request", err.to_string()))?;
+    if line.len() as u64 >= MAX_LINE_LEN && !line.ends_with('\n') {
+        return Err((
+            status,
+            format!("Line is longer than {} bytes", MAX_LINE_LEN),
+        ));
+    }
+    Ok(line)
+}
+
+fn write_http_response(stream: &mut TcpStream, status: &str, body: &[u8]) -> Result<(), String> {
+    let header = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    stream
+        .write_all(header.as_bytes())
+        .and_then(|_| stream.write_all(body))
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_args() {
+        let allowed = &["address", "limit", "read-through", "derived", "to"];
+        let params = serde_json::json!({
+            "address": "ckt1qyqt8xaupvm8837nv3gtc9x0ekkj64vud3jqfwyw5v",
+            "limit": 10,
+            "read_through": true,
+            "derived": false,
+            "to": ["ckt1a:100", "ckt1b:200"],
+        });
+        let args = command_args(
+            &["wallet", "transfer"],
+            allowed,
+            params.as_object().unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            args,
+            vec![
+                "wallet",
+                "transfer",
+                "--address=ckt1qyqt8xaupvm8837nv3gtc9x0ekkj64vud3jqfwyw5v",
+                "--limit=10",
+                "--read-through",
+                "--to=ckt1a:100",
+                "--to=ckt1b:200",
+            ]
+        );
+        let params = serde_json::json!({ "--url": "http://evil" });
+        assert!(command_args(&["wallet"], allowed, params.as_object().unwrap()).is_err());
+        let params = serde_json::json!({ "address": { "nested": 1 } });
+        assert!(command_args(&["wallet"], allowed, params.as_object().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_methods_refuse_host_params() {
+        let refused = &[
+            "tx_file",
+            "to_file",
+            "to_data_path",
+            "privkey_path",
+            "on_failure",
+            "signer_url",
+            "indexer_url",
+            "light_client_url",
+            "index_backend",
+            "addresses_file",
+            "output_to",
+        ];
+        for (method, command, allowed_params, _) in METHODS {
+            for name in refused {
+                let mut params = Map::new();
+                params.insert(name.to_string(), Value::from("/tmp/x"));
+                assert!(
+                    command_args(command, allowed_params, &params).is_err(),
+                    "{} accepts {}",
+                    method,
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_check_headers() {
+        let local_addr: SocketAddr = "127.0.0.1:8120".parse().unwrap();
+        let token = "0123456789abcdef";
+        let headers = |extra: &[(&str, &str)]| {
+            let mut headers = vec![
+                ("authorization", "Bearer 0123456789abcdef"),
+                ("host", "127.0.0.1:8120"),
+                ("content-type", "application/json; charset=utf-8"),
+            ];
+            for (name, value) in extra {
+                headers.retain(|(key, _)| key != name);
+                headers.push((*name, *value));
+            }
+            headers
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert!(check_headers(&headers(&[]), token, local_addr).is_ok());
+        assert!(check_headers(&headers(&[("host", "localhost:8120")]), token, local_addr).is_ok());
+        let status = |extra: &[(&str, &str)]| {
+            check_headers(&headers(extra), token, local_addr)
+                .unwrap_err()
+                .0
+        };
+        assert_eq!(
+            status(&[("authorization", "Bearer wrong")]),
+            "401 Unauthorized"
+        );
+        assert_eq!(status(&[("origin", "http://evil.com")]), "403 Forbidden");
+        assert_eq!(status(&[("host", "evil.com:8120")]), "403 Forbidden");
+        assert_eq!(
+            status(&[("content-type", "text/plain")]),
+            "415 Unsupported Media Type"
+        );
+    }
+
+    #[test]
+    fn test_read_http_request() {
+        let request = |head: &str| {
+            let mut request = head.as_bytes().to_vec();
+            request.extend_from_slice(b"\r\n{}");
+            request
+        };
+        let head = "POST / HTTP/1.1\r\nContent-Type: application/json\r\nContent-Length: 2\r\n";
+        let parsed = read_http_request(&request(head)[..])
+            .map_err(|err| err.1)
+            .unwrap();
+        assert_eq!(parsed.body, b"{}".to_vec());
+        assert!(parsed
+            .headers
+            .contains(&("content-length".to_string(), "2".to_string())));
+
+        let status = |request: Vec<u8>| read_http_request(&request[..]).map(|_| ()).unwrap_err().0;
+        let long = "a".repeat(MAX_LINE_LEN as usize);
+        assert_eq!(
+            status(format!("POST /{}", long).into_bytes()),
+            "400 Bad Request"
+        );
+        assert_eq!(
+            status(request(&format!("{}X-Long: {}\r\n", head, long))),
+            "431 Request Header Fields Too Large"
+        );
+        let many = "X-Name: value\r\n".repeat(MAX_HEADERS);
+        assert_eq!(
+            status(request(&format!("{}{}", head, many))),
+            "431 Request Header Fields Too Large"
+        );
+        assert_eq!(
+            status(request("GET / HTTP/1.1\r\nContent-Length: 2\r\n")),
+            "400 Bad Request"
+        );
+    }
+}
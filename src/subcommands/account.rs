@@ -1,30 +1,57 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::time::Duration;
 
-use ckb_ledger::{LedgerId, LedgerKeyStore};
+use chrono::{TimeZone, Utc};
+use ckb_jsonrpc_types::Status;
+use ckb_ledger::{doctor, LedgerId, LedgerKeyStore};
 use ckb_sdk::{
+    constants::MULTISIG_TYPE_HASH,
     wallet::{
-        AbstractKeyStore, AbstractMasterPrivKey, DerivationPath, Key, KeyStore, MasterPrivKey,
+        AbstractKeyStore, AbstractMasterPrivKey, AbstractPrivKey, Argon2Params, DerivationPath,
+        KdfParams, Key, KeyChain, KeyStore, MasterPrivKey, ScryptType, ARGON2_MAX_ITERATIONS,
+        ARGON2_MAX_MEMORY,
     },
-    Address, AddressPayload, NetworkType,
+    Address, AddressPayload, GenesisInfo, HttpRpcClient, HumanCapacity, MultisigConfig,
+    NetworkType, Since, SinceType,
 };
-use ckb_types::{packed::Script, prelude::*, H160, H256};
+use ckb_types::{core::EpochNumberWithFraction, packed::Script, prelude::*, H160, H256};
 use clap::{App, Arg, ArgMatches, SubCommand};
+use faster_hex::hex_string;
 
-use super::CliSubCommand;
+use super::{CliSubCommand, DAOSubCommand, WalletSubCommand};
 use crate::utils::{
+    alias::AliasResolver,
     arg,
     arg_parser::{
-        AccountIdParser, ArgParser, DerivationPathParser, DurationParser,
-        ExtendedPrivkeyPathParser, FilePathParser, FixedHashParser, FromStrParser,
+        AccountIdParser, AddressParser, ArgParser, DerivationPathParser, DirPathParser,
+        DurationParser, ExtendedPrivkeyPathParser, FilePathParser, FixedHashParser, FromStrParser,
         PrivkeyPathParser, PrivkeyWrapper,
     },
-    other::read_password,
+    broadcast::SendTargets,
+    descriptor::Descriptor,
+    index::{with_chain_index_db, IndexController},
+    ledger_labels::{check_label, LedgerLabelStore},
+    mnemonic::{
+        change_mnemonic_password, load_mnemonic, mnemonic_master_key, new_mnemonic, parse_mnemonic,
+        save_mnemonic,
+    },
+    multisig::MultisigConfigStore,
+    other::{get_genesis_info, get_network_type, read_confirmed_password, read_password},
+    policy::SigningPolicy,
     printer::{OutputFormat, Printable},
+    tx_record::TxRecordStore,
 };
 
+// The BIP-44 account of CKB, the receiving and change addresses are derived from it
+const DEFAULT_ACCOUNT_PATH: &str = "m/44'/309'/0'";
+// The multisig addresses saved by `account multisig-create` or `account
+// descriptor import`, no key of them is stored locally
+const WATCH_ONLY_SOURCE_NAME: &str = "multisig config (watch-only)";
+
 #[derive(Debug, Clone)]
 pub enum AccountId {
     SoftwareMasterKey(H160),
@@ -32,15 +59,33 @@ pub enum AccountId {
 }
 
 pub struct AccountSubCommand<'a> {
+    rpc_client: &'a mut HttpRpcClient,
     key_store: &'a mut KeyStore,
     ledger_key_store: &'a mut LedgerKeyStore,
+    genesis_info: Option<GenesisInfo>,
+    index_dir: PathBuf,
+    index_controller: IndexController,
+    signing_policy: SigningPolicy,
 }
 
 impl<'a> AccountSubCommand<'a> {
-    pub fn new(key_store: &'a mut KeyStore, ledger_key_store: &'a mut LedgerKeyStore) -> Self {
+    pub fn new(
+        rpc_client: &'a mut HttpRpcClient,
+        key_store: &'a mut KeyStore,
+        ledger_key_store: &'a mut LedgerKeyStore,
+        genesis_info: Option<GenesisInfo>,
+        index_dir: PathBuf,
+        index_controller: IndexController,
+        signing_policy: SigningPolicy,
+    ) -> Self {
         AccountSubCommand {
+            rpc_client,
             key_store,
             ledger_key_store,
+            genesis_info,
+            index_dir,
+            index_controller,
+            signing_policy,
         }
     }
 
@@ -52,12 +97,44 @@ impl<'a> AccountSubCommand<'a> {
             .long("extended-privkey-path")
             .takes_value(true)
             .help("Extended private key path (include master private key and chain code)");
+        let arg_with_passphrase = Arg::with_name("with-passphrase")
+            .long("with-passphrase")
+            .help(
+            "The mnemonic is protected by a BIP-39 passphrase (the \"25th word\", it is not saved)",
+        );
+        let arg_kdf = Arg::with_name("kdf")
+            .long("kdf")
+            .takes_value(true)
+            .possible_values(&["scrypt", "argon2id"])
+            .help("The key derivation function encrypting the key file, argon2id is harder to brute force (for the key files synced to cloud backups)");
+        let arg_kdf_memory = Arg::with_name("kdf-memory")
+            .long("kdf-memory")
+            .takes_value(true)
+            .default_value("64")
+            .validator(|input| FromStrParser::<u32>::default().validate(input))
+            .help("The memory cost of argon2id in MiB");
+        let arg_kdf_iterations = Arg::with_name("kdf-iterations")
+            .long("kdf-iterations")
+            .takes_value(true)
+            .default_value("3")
+            .validator(|input| FromStrParser::<u32>::default().validate(input))
+            .help("The iterations of argon2id");
 
         SubCommand::with_name(name)
             .about("Manage accounts")
             .subcommands(vec![
-                SubCommand::with_name("list").about("List all accounts"),
-                SubCommand::with_name("new").about("Create a new account and print related information."),
+                SubCommand::with_name("list")
+                    .about("List all accounts")
+                    .arg(
+                        Arg::with_name("verbose")
+                            .long("verbose")
+                            .help("Also show the app name and version of ledger devices"),
+                    ),
+                SubCommand::with_name("new")
+                    .about("Create a new account and print related information.")
+                    .arg(arg_kdf.clone().default_value("scrypt"))
+                    .arg(arg_kdf_memory.clone())
+                    .arg(arg_kdf_iterations.clone()),
                 SubCommand::with_name("import")
                     .about("Import an unencrypted private key from <privkey-path> and create a new account.")
                     .arg(
@@ -72,6 +149,23 @@ impl<'a> AccountSubCommand<'a> {
                          .required_unless("privkey-path")
                          .validator(|input| ExtendedPrivkeyPathParser.validate(input))
                     ),
+                SubCommand::with_name("mnemonic-new")
+                    .about("Create a new account from a new BIP-39 mnemonic (compatible with Neuron), the mnemonic is printed only once, please back it up")
+                    .arg(
+                        Arg::with_name("words")
+                            .long("words")
+                            .takes_value(true)
+                            .possible_values(&["12", "24"])
+                            .default_value("12")
+                            .help("Words count of the mnemonic")
+                    )
+                    .arg(arg_with_passphrase.clone()),
+                SubCommand::with_name("import-mnemonic")
+                    .about("Import an account from a 12/24 words BIP-39 mnemonic (read from stdin without echo)")
+                    .arg(arg_with_passphrase.clone()),
+                SubCommand::with_name("export-mnemonic")
+                    .about("Export the mnemonic of an account created by `mnemonic-new` or `import-mnemonic` (USE WITH YOUR OWN RISK)")
+                    .arg(arg::lock_arg().required(true)),
                 SubCommand::with_name("import-keystore")
                     .about("Import key from encrypted keystore json file and create a new account.")
                     .arg(arg::derivation_path().required(true)),
@@ -89,6 +183,33 @@ impl<'a> AccountSubCommand<'a> {
                 SubCommand::with_name("update")
                     .about("Update password of an account")
                     .arg(arg::lock_arg().required(true)),
+                SubCommand::with_name("change-password")
+                    .about("Re-encrypt the key file of an account by a new password (and KDF), the file is replaced atomically and the old one is kept as a backup")
+                    .arg(arg::lock_arg().required(true))
+                    .arg(arg_kdf.help("The new key derivation function, the one of the key file is kept by default"))
+                    .arg(arg_kdf_memory)
+                    .arg(arg_kdf_iterations),
+                SubCommand::with_name("merge-keystores")
+                    .about("Merge the accounts of other keystore directories (e.g. backups of migrated machines) into the keystore, the accounts already in the keystore are skipped")
+                    .arg(
+                        Arg::with_name("from-dir")
+                            .long("from-dir")
+                            .takes_value(true)
+                            .multiple(true)
+                            .required(true)
+                            .validator(|input| DirPathParser::new(true).validate(input))
+                            .help("The keystore directory to merge from")
+                    )
+                    .arg(
+                        Arg::with_name("reencrypt")
+                            .long("reencrypt")
+                            .help("Re-encrypt the merged keys by one new password (the password of each key is asked), the key files are copied as they are by default")
+                    )
+                    .arg(
+                        Arg::with_name("dry-run")
+                            .long("dry-run")
+                            .help("Only report what would be merged")
+                    ),
                 SubCommand::with_name("export")
                     .about("Export master private key and chain code as hex plain text (USE WITH YOUR OWN RISK)")
                     .arg(arg::lock_arg().required(true))
@@ -143,6 +264,142 @@ impl<'a> AccountSubCommand<'a> {
                             .validator(|input| FromStrParser::<DerivationPath>::new().validate(input))
                             .help("The address path")
                     ),
+                SubCommand::with_name("verify-address")
+                    .about("Display the address of a ledger account on the device screen to verify it")
+                    .arg(arg::account_id().required(true))
+                    .arg(
+                        Arg::with_name("path")
+                            .long("path")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FromStrParser::<DerivationPath>::new().validate(input))
+                            .help("The address path")
+                    ),
+                SubCommand::with_name("ledger-doctor")
+                    .about("Check why a ledger device can not be found (USB device, udev rule, permissions, the Nervos app)"),
+                SubCommand::with_name("ledger-label")
+                    .about("Name a ledger account, the label is shown by `account list` and accepted as the account argument of any command")
+                    .arg(arg::account_id().required(true))
+                    .arg(
+                        Arg::with_name("label")
+                            .long("label")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| check_label(&input))
+                            .help("The name of the ledger account (without spaces, not starting with 0x, ckb1 or ckt1)")
+                    )
+                    .arg(
+                        Arg::with_name("derivation-root")
+                            .long("derivation-root")
+                            .takes_value(true)
+                            .validator(|input| FromStrParser::<DerivationPath>::new().validate(input))
+                            .help("The preferred account path of the device [default: m/44'/309'/0']")
+                    ),
+                SubCommand::with_name("report")
+                    .about("Show everything about an account in one report: capacity, NervosDAO positions, recent and pending transactions, aliases (with local index)")
+                    .arg(arg::lock_arg().required_unless("account-id"))
+                    .arg(
+                        arg::account_id()
+                            .conflicts_with("lock-arg")
+                            .help("The account (a ledger id or label), the address of --path is reported")
+                    )
+                    .arg(
+                        arg::derivation_path()
+                            .requires("account-id")
+                            .help("The address path of the ledger account (default: m)")
+                    )
+                    .arg(
+                        Arg::with_name("tx-limit")
+                            .long("tx-limit")
+                            .takes_value(true)
+                            .default_value("10")
+                            .validator(|input| FromStrParser::<usize>::default().validate(input))
+                            .help("The number of recent transactions")
+                    )
+                    .arg(
+                        arg::derived()
+                            .help("Also report the usage of derived receiving and change addresses (keystore account only, the password is required)")
+                    )
+                    .arg(arg::derive_receiving_address_length())
+                    .arg(arg::derive_change_address_length()),
+                SubCommand::with_name("multisig-create")
+                    .about("Create a secp256k1-multisig (R-of-N) lock, the config is saved locally for `wallet transfer-multisig`")
+                    .arg(
+                        Arg::with_name("sighash-address")
+                            .long("sighash-address")
+                            .takes_value(true)
+                            .multiple(true)
+                            .required(true)
+                            .validator(|input| AddressParser::new_sighash().validate(input))
+                            .help("Normal sighash address of the member")
+                    )
+                    .arg(
+                        Arg::with_name("require-first-n")
+                            .long("require-first-n")
+                            .takes_value(true)
+                            .default_value("0")
+                            .validator(|input| FromStrParser::<u8>::default().validate(input))
+                            .help("Require first n signatures of corresponding pubkey")
+                    )
+                    .arg(
+                        Arg::with_name("threshold")
+                            .long("threshold")
+                            .takes_value(true)
+                            .default_value("1")
+                            .validator(|input| FromStrParser::<u8>::default().validate(input))
+                            .help("Multisig threshold")
+                    )
+                    .arg(
+                        Arg::with_name("since-absolute-epoch")
+                            .long("since-absolute-epoch")
+                            .takes_value(true)
+                            .validator(|input| FromStrParser::<u64>::default().validate(input))
+                            .help("Since absolute epoch number (cells can only be spent after this epoch)")
+                    ),
+                SubCommand::with_name("descriptor")
+                    .about("Export/import account descriptors (lock type, extended public key and derivation path, multisig composition) to share accounts with other tools")
+                    .subcommands(vec![
+                        SubCommand::with_name("export")
+                            .about("Export the descriptor of a BIP-44 account (`sighash([m/44'/309'/0']0x<pubkey><chain-code>)`) or a multisig address (`multisig(<require-first-n>,<threshold>,<lock-arg>,...)`)")
+                            .arg(
+                                arg::account_id()
+                                    .required_unless("multisig-address")
+                                    .conflicts_with("multisig-address")
+                            )
+                            .arg(
+                                Arg::with_name("multisig-address")
+                                    .long("multisig-address")
+                                    .takes_value(true)
+                                    .validator(|input| AddressParser::default().validate(input))
+                                    .help("The multisig address created by `account multisig-create`")
+                            ),
+                        SubCommand::with_name("import")
+                            .about("Reconstruct the addresses of a descriptor, the multisig config is saved locally for `wallet transfer-multisig`")
+                            .arg(
+                                Arg::with_name("descriptor")
+                                    .long("descriptor")
+                                    .takes_value(true)
+                                    .required(true)
+                                    .validator(|input| FromStrParser::<Descriptor>::new().validate(input))
+                                    .help("The descriptor string (the `#<checksum>` suffix is verified if given)")
+                            )
+                            .arg(
+                                Arg::with_name("receiving-length")
+                                    .long("receiving-length")
+                                    .takes_value(true)
+                                    .default_value("20")
+                                    .validator(|input| FromStrParser::<u32>::default().validate(input))
+                                    .help("Receiving addresses length (account descriptor only)")
+                            )
+                            .arg(
+                                Arg::with_name("change-length")
+                                    .long("change-length")
+                                    .takes_value(true)
+                                    .default_value("10")
+                                    .validator(|input| FromStrParser::<u32>::default().validate(input))
+                                    .help("Change addresses length (account descriptor only)")
+                            ),
+                    ]),
             ])
     }
 }
@@ -156,67 +413,24 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
         _debug: bool,
     ) -> Result<String, String> {
         match matches.subcommand() {
-            ("list", _) => {
-                fn list_accounts_with_source<KS>(
-                    ks: &mut KS,
-                ) -> Result<impl Iterator<Item = (KS::AccountId, &'static str)>, String>
-                where
-                    KS::Err: std::string::ToString,
-                    KS: AbstractKeyStore,
-                {
-                    Ok(ks
-                        .list_accounts()
-                        .map_err(|err| err.to_string())?
-                        .map(|lock_arg| (lock_arg, KS::SOURCE_NAME)))
-                }
-                let resp = list_accounts_with_source(self.key_store)?
-                    .map(|(lock_arg, source)| {
-                        let address_payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
-                        let lock_hash: H256 = Script::from(&address_payload)
-                            .calc_script_hash()
-                            .unpack();
-                        let v = serde_json::json!({
-                            "lock_arg": format!("{:#x}", lock_arg),
-                            "lock_hash": format!("{:#x}", lock_hash),
-                            "address": {
-                                "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
-                                "testnet": Address::new(NetworkType::Testnet, address_payload.clone()).to_string(),
-                            },
-                            "account_source": source,
-                        });
-                        match v {
-                            serde_json::Value::Object(m) => m,
-                            _ => panic!("We should have written a panic above."),
-                        }
-                    })
-                    .chain(list_accounts_with_source(self.ledger_key_store)?
-                           .map(|(LedgerId(ledger_id), source)| {
-                               let v = serde_json::json!({
-                                   "ledger_id": ledger_id,
-                                   "account_source": source,
-                               });
-                               match v {
-                                   serde_json::Value::Object(m) => m,
-                                   _ => panic!("We should have written a panic above."),
-                               }
-                           }))
-                    .enumerate()
-                    .map(|(idx, mut rest_of_map)| {
-                        // unwrap is gross, but also what the macro does?!?
-                        rest_of_map.insert("#".to_string(), serde_json::to_value(idx).unwrap());
-                        serde_json::Value::Object(rest_of_map)
-                    })
-                    .collect::<Vec<_>>();
-                Ok(serde_json::json!(resp).render(format, color))
-            }
-            ("new", _) => {
+            ("list", Some(m)) => self.list_accounts(m.is_present("verbose"), format, color),
+            ("new", Some(m)) => {
+                let kdfparams_opt = kdfparams_from_matches(m, self.key_store)?;
+                let kdf = kdfparams_opt
+                    .as_ref()
+                    .map(KdfParams::name)
+                    .unwrap_or("scrypt");
+
                 println!("Your new account is locked with a password. Please give a password. Do not forget this password.");
 
-                let pass = read_password(true, None)?;
-                let lock_arg = self
-                    .key_store
-                    .new_account(pass.as_bytes())
-                    .map_err(|err| err.to_string())?;
+                let pass = read_confirmed_password(None)?;
+                let lock_arg = match kdfparams_opt {
+                    Some(kdfparams) => self
+                        .key_store
+                        .new_account_with_kdf(pass.as_bytes(), kdfparams),
+                    None => self.key_store.new_account(pass.as_bytes()),
+                }
+                .map_err(|err| err.to_string())?;
                 let address_payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
                 let lock_hash: H256 = Script::from(&address_payload).calc_script_hash().unpack();
                 let resp = serde_json::json!({
@@ -226,13 +440,14 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                         "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
                         "testnet": Address::new(NetworkType::Testnet, address_payload.clone()).to_string(),
                     },
+                    "kdf": kdf,
                 });
                 Ok(resp.render(format, color))
             }
             ("import", Some(m)) => {
                 let secp_key: Option<PrivkeyWrapper> =
                     PrivkeyPathParser.from_matches_opt(m, "privkey-path", false)?;
-                let password = read_password(true, None)?;
+                let password = read_confirmed_password(None)?;
                 let lock_arg = if let Some(secp_key) = secp_key {
                     self.key_store
                         .import_secp_key(&secp_key, password.as_bytes())
@@ -255,6 +470,79 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                 });
                 Ok(resp.render(format, color))
             }
+            ("mnemonic-new", Some(m)) => {
+                let words: usize = FromStrParser::<usize>::default().from_matches(m, "words")?;
+                let passphrase = if m.is_present("with-passphrase") {
+                    read_password(true, Some("Mnemonic passphrase"))?
+                } else {
+                    String::new()
+                };
+                println!("Your new account is locked with a password. Please give a password. Do not forget this password.");
+                let password = read_confirmed_password(None)?;
+                let mnemonic = new_mnemonic(words)?;
+                let key = Key::new(mnemonic_master_key(&mnemonic, &passphrase)?);
+                let lock_arg = self
+                    .key_store
+                    .import_key(&key, password.as_bytes())
+                    .map_err(|err| err.to_string())?;
+                save_mnemonic(&lock_arg, mnemonic.phrase(), password.as_bytes())?;
+                let address_payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
+                let resp = serde_json::json!({
+                    "mnemonic": mnemonic.phrase(),
+                    "lock_arg": format!("{:#x}", lock_arg),
+                    "address": {
+                        "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
+                        "testnet": Address::new(NetworkType::Testnet, address_payload.clone()).to_string(),
+                    },
+                });
+                Ok(resp.render(format, color))
+            }
+            ("import-mnemonic", Some(m)) => {
+                let phrase = read_password(false, Some("Mnemonic"))?;
+                let mnemonic = parse_mnemonic(&phrase)?;
+                let passphrase = if m.is_present("with-passphrase") {
+                    read_password(false, Some("Mnemonic passphrase"))?
+                } else {
+                    String::new()
+                };
+                let password = read_confirmed_password(None)?;
+                let key = Key::new(mnemonic_master_key(&mnemonic, &passphrase)?);
+                let lock_arg = self
+                    .key_store
+                    .import_key(&key, password.as_bytes())
+                    .map_err(|err| err.to_string())?;
+                save_mnemonic(&lock_arg, mnemonic.phrase(), password.as_bytes())?;
+                let address_payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
+                let resp = serde_json::json!({
+                    "lock_arg": format!("{:#x}", lock_arg),
+                    "address": {
+                        "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
+                        "testnet": Address::new(NetworkType::Testnet, address_payload.clone()).to_string(),
+                    },
+                });
+                Ok(resp.render(format, color))
+            }
+            ("export-mnemonic", Some(m)) => {
+                let lock_arg: H160 =
+                    FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
+                // Confirm the password, the mnemonic is as sensitive as the private key
+                let password = read_confirmed_password(None)?;
+                // Check the password by keystore first
+                self.key_store
+                    .get_key(&lock_arg, password.as_bytes())
+                    .map_err(|err| err.to_string())?;
+                let phrase = load_mnemonic(&lock_arg, password.as_bytes())?.ok_or_else(|| {
+                    format!(
+                        "Account {:#x} is not created from a mnemonic, please use `account export`",
+                        lock_arg
+                    )
+                })?;
+                let resp = serde_json::json!({
+                    "lock_arg": format!("{:#x}", lock_arg),
+                    "mnemonic": phrase,
+                });
+                Ok(resp.render(format, color))
+            }
             ("import-keystore", Some(m)) => {
                 let path: PathBuf = FilePathParser::new(true).from_matches(m, "path")?;
 
@@ -297,11 +585,124 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                     FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
                 let old_password = read_password(false, Some("Old password"))?;
                 let new_passsword = read_password(true, Some("New password"))?;
-                self.key_store
-                    .update(&lock_arg, old_password.as_bytes(), new_passsword.as_bytes())
-                    .map_err(|err| err.to_string())?;
+                let key_store = &mut self.key_store;
+                // The mnemonic is encrypted by the same password
+                change_mnemonic_password(
+                    &lock_arg,
+                    old_password.as_bytes(),
+                    new_passsword.as_bytes(),
+                    || {
+                        key_store
+                            .update(&lock_arg, old_password.as_bytes(), new_passsword.as_bytes())
+                            .map_err(|err| err.to_string())
+                    },
+                )?;
                 Ok("success".to_owned())
             }
+            ("change-password", Some(m)) => {
+                let lock_arg: H160 =
+                    FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
+                let kdfparams_opt = kdfparams_from_matches(m, self.key_store)?;
+                // A preset password would answer both prompts, it is refused
+                // by the confirmation of the new password
+                let old_password = read_password(false, Some("Old password"))?;
+                let new_password = read_password(true, Some("New password"))?;
+                // Nothing would change, unless only the KDF is changed
+                if new_password == old_password && kdfparams_opt.is_none() {
+                    return Err("The new password is the same as the old password".to_string());
+                }
+                let key_store = &mut self.key_store;
+                // The mnemonic is encrypted by the same password
+                let backup_path = change_mnemonic_password(
+                    &lock_arg,
+                    old_password.as_bytes(),
+                    new_password.as_bytes(),
+                    || {
+                        key_store
+                            .change_password(
+                                &lock_arg,
+                                old_password.as_bytes(),
+                                new_password.as_bytes(),
+                                kdfparams_opt,
+                            )
+                            .map_err(|err| err.to_string())
+                    },
+                )?;
+                let resp = serde_json::json!({
+                    "lock_arg": format!("{:#x}", lock_arg),
+                    "backup": backup_path.to_string_lossy(),
+                    "note": "The backup is still encrypted by the old password, delete it once the new password is verified",
+                });
+                Ok(resp.render(format, color))
+            }
+            ("merge-keystores", Some(m)) => {
+                let dirs: Vec<PathBuf> =
+                    DirPathParser::new(true).from_matches_vec(m, "from-dir")?;
+                let reencrypt = m.is_present("reencrypt");
+                let dry_run = m.is_present("dry-run");
+
+                let mut sources = Vec::new();
+                for dir in dirs {
+                    let source =
+                        KeyStore::from_dir(dir.clone(), ScryptType::default()).map_err(|err| {
+                            format!("Open keystore {} failed: {}", dir.display(), err)
+                        })?;
+                    sources.push(source);
+                }
+                let source_accounts = sources
+                    .iter_mut()
+                    .map(|source| source.get_accounts().clone())
+                    .collect::<Vec<_>>();
+                let (to_merge, duplicates) =
+                    plan_merge(self.key_store.get_accounts(), &source_accounts);
+
+                let mut merged = Vec::new();
+                if !dry_run && !to_merge.is_empty() {
+                    let new_password = if reencrypt {
+                        Some(read_password(true, Some("New password"))?)
+                    } else {
+                        None
+                    };
+                    for (source_idx, lock_arg, path) in &to_merge {
+                        let result = if let Some(new_password) = new_password.as_ref() {
+                            let password = read_password(
+                                false,
+                                Some(format!("Password of {:#x}", lock_arg).as_str()),
+                            )?;
+                            sources[*source_idx]
+                                .get_key(lock_arg, password.as_bytes())
+                                .and_then(|key| {
+                                    self.key_store.import_key(&key, new_password.as_bytes())
+                                })
+                        } else {
+                            self.key_store.import_file(path)
+                        };
+                        result.map_err(|err| {
+                            format!(
+                                "Merge {} failed: {} (merged before: {})",
+                                path.display(),
+                                err,
+                                merged.len()
+                            )
+                        })?;
+                        merged.push(format!("{:#x}", lock_arg));
+                    }
+                }
+                let resp = serde_json::json!({
+                    "dry_run": dry_run,
+                    "to_merge": to_merge
+                        .iter()
+                        .map(|(_, lock_arg, path)| serde_json::json!({
+                            "lock_arg": format!("{:#x}", lock_arg),
+                            "source": path.to_string_lossy(),
+                        }))
+                        .collect::<Vec<_>>(),
+                    "merged": merged,
+                    "duplicates": duplicates,
+                    "reencrypted": reencrypt && !dry_run,
+                });
+                Ok(resp.render(format, color))
+            }
             ("export", Some(m)) => {
                 let lock_arg: H160 =
                     FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
@@ -372,6 +773,11 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
             ("extended-address", Some(m)) => {
                 let account_id = AccountIdParser::default().from_matches(m, "account-id")?;
                 let path: DerivationPath = DerivationPathParser.from_matches(m, "path")?;
+                let is_ledger = match account_id {
+                    AccountId::SoftwareMasterKey(_) => false,
+                    AccountId::LedgerId(_) => true,
+                };
+                self.signing_policy.check_path(&path, is_ledger)?;
                 let (extended_pubkey, account_source) = match account_id {
                     AccountId::SoftwareMasterKey(lock_arg) => (
                         {
@@ -399,11 +805,699 @@ impl<'a> CliSubCommand for AccountSubCommand<'a> {
                 let resp = address_resp(account_source, &address_payload);
                 Ok(resp.render(format, color))
             }
+            ("verify-address", Some(m)) => {
+                let account_id = AccountIdParser::default().from_matches(m, "account-id")?;
+                let path: DerivationPath = DerivationPathParser.from_matches(m, "path")?;
+                let ledger_id = match account_id {
+                    AccountId::SoftwareMasterKey(_) => {
+                        return Err("Only ledger account can verify address on device".to_string());
+                    }
+                    AccountId::LedgerId(ledger_id) => ledger_id,
+                };
+                self.signing_policy.check_path(&path, true)?;
+                let ledger_cap = self
+                    .ledger_key_store
+                    .borrow_account(&ledger_id)
+                    .map_err(|err| err.to_string())?
+                    .extended_privkey(path.as_ref())
+                    .map_err(|err| err.to_string())?;
+                let expected = ledger_cap.public_key().map_err(|err| err.to_string())?;
+                let address_payload = AddressPayload::from_pubkey(&expected);
+                // The device shows the address of the network set in the app
+                let address = match get_network_type(self.rpc_client) {
+                    Ok(network) => Address::new(network, address_payload.clone()).to_string(),
+                    Err(_) => format!(
+                        "{} (mainnet) or {} (testnet)",
+                        Address::new(NetworkType::Mainnet, address_payload.clone()),
+                        Address::new(NetworkType::Testnet, address_payload.clone())
+                    ),
+                };
+                eprintln!(
+                    "Please verify the address on the ledger device: {}",
+                    address
+                );
+                let confirmed = ledger_cap
+                    .verify_address()
+                    .map_err(|err| format!("Address not confirmed on device: {}", err))?;
+                if confirmed != expected {
+                    return Err(
+                        "Public key confirmed on device is different from the derived one"
+                            .to_string(),
+                    );
+                }
+                let mut resp = address_resp(LedgerKeyStore::SOURCE_NAME, &address_payload);
+                resp["path"] = serde_json::json!(path.to_string());
+                resp["verified"] = serde_json::json!(true);
+                Ok(resp.render(format, color))
+            }
+            ("ledger-label", Some(m)) => {
+                let account_id = AccountIdParser::default().from_matches(m, "account-id")?;
+                let ledger_id = match account_id {
+                    AccountId::SoftwareMasterKey(_) => {
+                        return Err("Only ledger account can be labeled".to_string());
+                    }
+                    AccountId::LedgerId(ledger_id) => ledger_id,
+                };
+                let label = m.value_of("label").unwrap();
+                let derivation_root: Option<DerivationPath> = FromStrParser::<DerivationPath>::new(
+                )
+                .from_matches_opt(m, "derivation-root", false)?;
+                let value =
+                    LedgerLabelStore::load()?.set(&ledger_id, label, derivation_root.as_ref())?;
+                let resp = serde_json::json!({
+                    "ledger_id": ledger_id.0,
+                    "label": value.label,
+                    "created_at": value.created_at,
+                    "derivation_root": value.derivation_root,
+                });
+                Ok(resp.render(format, color))
+            }
+            ("report", Some(m)) => self.report(m, format, color),
+            ("ledger-doctor", _) => {
+                let checks = doctor::run_checks();
+                let healthy = checks
+                    .iter()
+                    .all(|check| check.status != doctor::CheckStatus::Failed);
+                let resp = serde_json::json!({
+                    "healthy": healthy,
+                    "checks": checks
+                        .into_iter()
+                        .map(|check| serde_json::json!({
+                            "check": check.name,
+                            "status": check.status.as_str(),
+                            "message": check.message,
+                        }))
+                        .collect::<Vec<_>>(),
+                });
+                Ok(resp.render(format, color))
+            }
+            ("multisig-create", Some(m)) => {
+                let sighash_addresses: Vec<Address> =
+                    AddressParser::new_sighash().from_matches_vec(m, "sighash-address")?;
+                let require_first_n: u8 =
+                    FromStrParser::<u8>::default().from_matches(m, "require-first-n")?;
+                let threshold: u8 = FromStrParser::<u8>::default().from_matches(m, "threshold")?;
+                let since_absolute_epoch_opt: Option<u64> = FromStrParser::<u64>::default()
+                    .from_matches_opt(m, "since-absolute-epoch", false)?;
+
+                let sighash_addresses = sighash_addresses
+                    .into_iter()
+                    .map(|address| address.payload().clone())
+                    .collect::<Vec<_>>();
+                let config =
+                    MultisigConfig::new_with(sighash_addresses, require_first_n, threshold)?;
+                MultisigConfigStore::load()?.insert(&config)?;
+                let address_payload = config.to_address_payload(since_absolute_epoch_opt);
+                let lock_hash: H256 = Script::from(&address_payload).calc_script_hash().unpack();
+                let resp = serde_json::json!({
+                    "multisig_hash160": format!("{:#x}", config.hash160()),
+                    "lock_arg": format!("0x{}", hex_string(address_payload.args().as_ref()).unwrap()),
+                    "lock_hash": lock_hash,
+                    "threshold": format!("{}-of-{}", threshold, config.sighash_addresses().len()),
+                    "address": {
+                        "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
+                        "testnet": Address::new(NetworkType::Testnet, address_payload).to_string(),
+                    },
+                });
+                Ok(resp.render(format, color))
+            }
+            ("descriptor", Some(m)) => {
+                match m.subcommand() {
+                    ("export", Some(m)) => {
+                        let descriptor = if let Some(input) = m.value_of("multisig-address") {
+                            let address: Address = AddressParser::default().parse(input)?;
+                            multisig_descriptor(address.payload())?
+                        } else {
+                            let account_id =
+                                AccountIdParser::default().from_matches(m, "account-id")?;
+                            let path = DerivationPath::from_str(DEFAULT_ACCOUNT_PATH).unwrap();
+                            let extended_pubkey = match account_id {
+                                AccountId::SoftwareMasterKey(lock_arg) => {
+                                    let password = read_password(false, None)?;
+                                    self.key_store
+                                        .extended_pubkey_with_password(
+                                            &lock_arg,
+                                            path.as_ref(),
+                                            password.as_bytes(),
+                                        )
+                                        .map_err(|err| err.to_string())?
+                                }
+                                AccountId::LedgerId(ledger_id) => self
+                                    .ledger_key_store
+                                    .borrow_account(&ledger_id)
+                                    .map_err(|err| err.to_string())?
+                                    .extended_pubkey(path.as_ref())
+                                    .map_err(|err| err.to_string())?,
+                            };
+                            Descriptor::SighashAccount {
+                                path,
+                                extended_pubkey,
+                            }
+                        };
+                        let resp = serde_json::json!({
+                            "lock_type": descriptor.lock_type(),
+                            "descriptor": descriptor.to_string(),
+                        });
+                        Ok(resp.render(format, color))
+                    }
+                    ("import", Some(m)) => {
+                        let descriptor: Descriptor =
+                            FromStrParser::<Descriptor>::new().from_matches(m, "descriptor")?;
+                        let receiving_length: u32 =
+                            FromStrParser::<u32>::default().from_matches(m, "receiving-length")?;
+                        let change_length: u32 =
+                            FromStrParser::<u32>::default().from_matches(m, "change-length")?;
+
+                        let mut resp = serde_json::json!({
+                            "lock_type": descriptor.lock_type(),
+                            "descriptor": descriptor.to_string(),
+                        });
+                        if let Descriptor::Multisig { ref config, .. } = descriptor {
+                            MultisigConfigStore::load()?.insert(config)?;
+                        }
+                        if let Some(address_payload) = descriptor.address_payload() {
+                            let lock_hash: H256 =
+                                Script::from(&address_payload).calc_script_hash().unpack();
+                            resp["lock_hash"] = serde_json::json!(lock_hash);
+                            resp["address"] = serde_json::json!({
+                                "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
+                                "testnet": Address::new(NetworkType::Testnet, address_payload).to_string(),
+                            });
+                        } else {
+                            // Both networks when the node is not reachable
+                            let network_opt = get_network_type(self.rpc_client).ok();
+                            let get_addresses = |set: Vec<(DerivationPath, AddressPayload)>| {
+                                set.into_iter()
+                                .map(|(path, payload)| {
+                                    let address = match network_opt {
+                                        Some(network) => serde_json::json!(Address::new(network, payload).to_string()),
+                                        None => serde_json::json!({
+                                            "mainnet": Address::new(NetworkType::Mainnet, payload.clone()).to_string(),
+                                            "testnet": Address::new(NetworkType::Testnet, payload).to_string(),
+                                        }),
+                                    };
+                                    serde_json::json!({
+                                        "path": path.to_string(),
+                                        "address": address,
+                                    })
+                                })
+                                .collect::<Vec<_>>()
+                            };
+                            resp["receiving"] = serde_json::json!(get_addresses(
+                                descriptor.derive(KeyChain::External, 0, receiving_length)?
+                            ));
+                            resp["change"] = serde_json::json!(get_addresses(descriptor.derive(
+                                KeyChain::Change,
+                                0,
+                                change_length
+                            )?));
+                        }
+                        Ok(resp.render(format, color))
+                    }
+                    _ => Err(m.usage().to_owned()),
+                }
+            }
             _ => Err(matches.usage().to_owned()),
         }
     }
 }
 
+impl<'a> AccountSubCommand<'a> {
+    // The output is documented by `schemas/account-list.json`, only add fields
+    fn list_accounts(
+        &mut self,
+        verbose: bool,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let default_path = DerivationPath::from_str(DEFAULT_ACCOUNT_PATH).unwrap();
+        let mut accounts = Vec::new();
+        let lock_args = self
+            .key_store
+            .list_accounts()
+            .map_err(|err| err.to_string())?
+            .collect::<Vec<_>>();
+        for lock_arg in lock_args {
+            let address_payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
+            let mut account = address_resp(KeyStore::SOURCE_NAME, &address_payload);
+            // The extended public key is encrypted, only known when unlocked
+            let fingerprint = self
+                .key_store
+                .extended_pubkey(&lock_arg, default_path.as_ref())
+                .ok()
+                .map(|xpub| hex_string(&xpub.fingerprint().0).unwrap());
+            account["source"] = serde_json::json!("keystore");
+            account["xpub_fingerprint"] = serde_json::json!(fingerprint);
+            account["derivation_path"] = serde_json::json!(DEFAULT_ACCOUNT_PATH);
+            accounts.push(account);
+        }
+
+        // An account failing to load is listed with its error, the others are
+        // still listed
+        let ledger_ids = match self.ledger_key_store.list_accounts() {
+            Ok(ledger_ids) => ledger_ids.collect::<Vec<_>>(),
+            Err(err) => {
+                accounts.push(error_account(
+                    "ledger",
+                    LedgerKeyStore::SOURCE_NAME,
+                    err.to_string(),
+                ));
+                Vec::new()
+            }
+        };
+        if ledger_ids.is_empty() {
+            if let Some(guidance) = doctor::troubleshoot() {
+                eprintln!("{}", guidance);
+            }
+        }
+        let ledger_labels =
+            LedgerLabelStore::load().map_err(|err| format!("Load ledger labels failed: {}", err));
+        for ledger_id in ledger_ids {
+            let master = match self.ledger_key_store.borrow_account(&ledger_id) {
+                Ok(master) => master,
+                Err(err) => {
+                    let mut account =
+                        error_account("ledger", LedgerKeyStore::SOURCE_NAME, err.to_string());
+                    account["ledger_id"] = serde_json::json!(ledger_id.0);
+                    accounts.push(account);
+                    continue;
+                }
+            };
+            let derivation_root = match ledger_labels {
+                Ok(ref ledger_labels) => ledger_labels.derivation_root(&ledger_id),
+                Err(_) => default_path.clone(),
+            };
+            // A not connected device only knows the default account path
+            let (fingerprint, error) = match master.extended_pubkey(derivation_root.as_ref()) {
+                Ok(xpub) => (Some(hex_string(&xpub.fingerprint().0).unwrap()), None),
+                Err(err) => (None, Some(err.to_string())),
+            };
+            let app_info = master.app_info().clone();
+            // The addresses of a ledger account are always derived
+            let mut account = serde_json::json!({
+                "ledger_id": ledger_id.0,
+                "label": ledger_labels
+                    .as_ref()
+                    .ok()
+                    .and_then(|ledger_labels| ledger_labels.get(&ledger_id))
+                    .map(|value| value.label.clone()),
+                "lock_arg": null,
+                "lock_hash": null,
+                "address": null,
+                "account_source": LedgerKeyStore::SOURCE_NAME,
+                "source": "ledger",
+                "xpub_fingerprint": fingerprint,
+                "derivation_path": derivation_root.to_string(),
+                "connected": master.is_connected(),
+            });
+            if verbose && master.is_connected() {
+                account["app_name"] = serde_json::json!(app_info.name);
+                account["app_version"] = serde_json::json!(app_info.version.to_string());
+            }
+            if let Some(error) = ledger_labels.as_ref().err().cloned().or(error) {
+                account["error"] = serde_json::json!(error);
+            }
+            accounts.push(account);
+        }
+
+        match MultisigConfigStore::load().and_then(|store| store.list()) {
+            Ok(configs) => {
+                for config in configs {
+                    let address_payload = config.to_address_payload(None);
+                    let mut account = address_resp(WATCH_ONLY_SOURCE_NAME, &address_payload);
+                    account["source"] = serde_json::json!("watch-only");
+                    account["xpub_fingerprint"] = serde_json::Value::Null;
+                    account["derivation_path"] = serde_json::Value::Null;
+                    accounts.push(account);
+                }
+            }
+            Err(err) => accounts.push(error_account(
+                "watch-only",
+                WATCH_ONLY_SOURCE_NAME,
+                format!("Load multisig configs failed: {}", err),
+            )),
+        }
+
+        for (idx, account) in accounts.iter_mut().enumerate() {
+            account["#"] = serde_json::json!(idx);
+        }
+        Ok(serde_json::json!(accounts).render(format, color))
+    }
+
+    fn report(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let (lock_arg, account_source, path_opt) = match m.value_of("account-id") {
+            Some(_) => match AccountIdParser::default().from_matches(m, "account-id")? {
+                AccountId::SoftwareMasterKey(lock_arg) => {
+                    let account_source = self.account_source(&lock_arg);
+                    (lock_arg, account_source, None)
+                }
+                AccountId::LedgerId(ledger_id) => {
+                    let path: DerivationPath = DerivationPathParser.from_matches(m, "path")?;
+                    let extended_pubkey = self
+                        .ledger_key_store
+                        .borrow_account(&ledger_id)
+                        .map_err(|err| err.to_string())?
+                        .extended_pubkey(path.as_ref())
+                        .map_err(|err| err.to_string())?;
+                    let payload = AddressPayload::from_pubkey(&extended_pubkey.public_key);
+                    let lock_arg = H160::from_slice(&payload.args()).unwrap();
+                    (lock_arg, Some(LedgerKeyStore::SOURCE_NAME), Some(path))
+                }
+            },
+            None => {
+                let lock_arg: H160 =
+                    FixedHashParser::<H160>::default().from_matches(m, "lock-arg")?;
+                let account_source = self.account_source(&lock_arg);
+                (lock_arg, account_source, None)
+            }
+        };
+        let tx_limit: usize = FromStrParser::<usize>::default().from_matches(m, "tx-limit")?;
+        let network_type = get_network_type(self.rpc_client)?;
+        let genesis_info = get_genesis_info(&self.genesis_info, self.rpc_client)?;
+        let payload = AddressPayload::from_pubkey_hash(lock_arg.clone());
+        let address = Address::new(network_type, payload.clone()).to_string();
+        let lock_script = Script::from(&payload);
+        let lock_hash: H256 = lock_script.calc_script_hash().unpack();
+
+        let lock_arg_hex = format!("{:#x}", lock_arg);
+        let limit = tx_limit.to_string();
+        let mut wallet = WalletSubCommand::new(
+            self.rpc_client,
+            self.key_store,
+            self.ledger_key_store,
+            Some(genesis_info.clone()),
+            self.index_dir.clone(),
+            self.index_controller.clone(),
+            self.signing_policy.clone(),
+            SendTargets::default(),
+        );
+        let capacity = report_section(
+            &mut wallet,
+            WalletSubCommand::subcommand(),
+            &["wallet", "get-capacity", "--lock-arg", &lock_arg_hex],
+        );
+        let recent_transactions = report_section(
+            &mut wallet,
+            WalletSubCommand::subcommand(),
+            &[
+                "wallet",
+                "tx-history",
+                "--address",
+                &address,
+                "--limit",
+                &limit,
+            ],
+        );
+        let mut dao = DAOSubCommand::new(
+            self.rpc_client,
+            self.key_store,
+            self.ledger_key_store,
+            genesis_info.clone(),
+            self.index_dir.clone(),
+            self.index_controller.clone(),
+            self.signing_policy.clone(),
+            SendTargets::default(),
+        );
+        let deposited = report_section(
+            &mut dao,
+            DAOSubCommand::subcommand(),
+            &["dao", "query-deposited-cells", "--address", &address],
+        );
+        let prepared = report_section(
+            &mut dao,
+            DAOSubCommand::subcommand(),
+            &["dao", "query-prepared-cells", "--address", &address],
+        );
+        let pending_transactions = pending_transactions(self.rpc_client, &lock_script)
+            .unwrap_or_else(|err| serde_json::json!({ "error": err }));
+        let aliases = aliases_section(AliasResolver::load(), &payload);
+
+        let mut resp = serde_json::json!({
+            "lock_arg": lock_arg_hex,
+            "address": address,
+            "lock_hash": lock_hash,
+            "account_source": account_source,
+            "aliases": aliases,
+            "capacity": capacity,
+            "dao": {
+                "deposited": deposited,
+                "prepared": prepared,
+            },
+            "recent_transactions": recent_transactions,
+            "pending_transactions": pending_transactions,
+        });
+        if let Some(path) = path_opt {
+            resp["path"] = serde_json::json!(path.to_string());
+        }
+        if m.is_present("derived") {
+            resp["derived_addresses"] = self
+                .derived_usage(m, &lock_arg, network_type, genesis_info)
+                .unwrap_or_else(|err| serde_json::json!({ "error": err }));
+        }
+        Ok(resp.render(format, color))
+    }
+
+    fn account_source(&mut self, lock_arg: &H160) -> Option<&'static str> {
+        if self.key_store.has_account(lock_arg) {
+            Some(KeyStore::SOURCE_NAME)
+        } else {
+            None
+        }
+    }
+
+    // The derived addresses holding live cells, from the local index
+    fn derived_usage(
+        &mut self,
+        m: &ArgMatches,
+        lock_arg: &H160,
+        network_type: NetworkType,
+        genesis_info: GenesisInfo,
+    ) -> Result<serde_json::Value, String> {
+        if !self.key_store.has_account(lock_arg) {
+            return Err("Derived addresses are only reported for keystore accounts".to_string());
+        }
+        let receiving_length: u32 =
+            FromStrParser::<u32>::default().from_matches(m, "derive-receiving-address-length")?;
+        let change_length: u32 =
+            FromStrParser::<u32>::default().from_matches(m, "derive-change-address-length")?;
+        let password = read_password(false, None)?;
+        let key_set = self
+            .key_store
+            .derived_key_set_by_index_with_password(
+                lock_arg,
+                password.as_bytes(),
+                0,
+                receiving_length,
+                0,
+                change_length,
+            )
+            .map_err(|err| err.to_string())?;
+
+        with_chain_index_db(
+            &self.index_dir,
+            &self.index_controller,
+            network_type,
+            genesis_info,
+            |db| {
+                let usage = |keys: &[(DerivationPath, H160)]| {
+                    keys.iter()
+                        .filter_map(|(path, hash160)| {
+                            let payload = AddressPayload::from_pubkey_hash(hash160.clone());
+                            let lock_hash = Script::from(&payload).calc_script_hash();
+                            db.get_capacity(lock_hash)
+                                .filter(|capacity| *capacity > 0)
+                                .map(|capacity| {
+                                    serde_json::json!({
+                                        "path": path.to_string(),
+                                        "address": Address::new(network_type, payload).to_string(),
+                                        "capacity": format!("{:#}", HumanCapacity::from(capacity)),
+                                    })
+                                })
+                        })
+                        .collect::<Vec<_>>()
+                };
+                serde_json::json!({
+                    "receiving": usage(&key_set.external),
+                    "change": usage(&key_set.change),
+                })
+            },
+        )
+    }
+}
+
+/// The KDF of `--kdf`, `None` when not given. scrypt is of the scrypt type of
+/// the keystore.
+fn kdfparams_from_matches(
+    m: &ArgMatches,
+    key_store: &KeyStore,
+) -> Result<Option<KdfParams>, String> {
+    match m.value_of("kdf") {
+        Some("argon2id") => {
+            let memory_mib: u32 = FromStrParser::<u32>::default().from_matches(m, "kdf-memory")?;
+            let iterations: u32 =
+                FromStrParser::<u32>::default().from_matches(m, "kdf-iterations")?;
+            let memory = memory_mib.saturating_mul(1024);
+            if memory_mib == 0 || memory > ARGON2_MAX_MEMORY {
+                return Err(format!(
+                    "--kdf-memory must be between 1 and {} MiB",
+                    ARGON2_MAX_MEMORY / 1024
+                ));
+            }
+            if iterations == 0 || iterations > ARGON2_MAX_ITERATIONS {
+                return Err(format!(
+                    "--kdf-iterations must be between 1 and {}",
+                    ARGON2_MAX_ITERATIONS
+                ));
+            }
+            Ok(Some(KdfParams::Argon2id(Argon2Params::new(
+                memory, iterations,
+            ))))
+        }
+        Some("scrypt") => Ok(Some(key_store.default_kdfparams())),
+        Some(kdf) => Err(format!("Unsupported kdf: {}", kdf)),
+        None => Ok(None),
+    }
+}
+
+// Run a subcommand and parse its JSON output, the error is reported in the
+// section instead of failing the whole report (eg: the index is not synced)
+fn report_section<C: CliSubCommand>(
+    command: &mut C,
+    app: App<'static, 'static>,
+    args: &[&str],
+) -> serde_json::Value {
+    app.get_matches_from_safe(args)
+        .map_err(|err| err.message)
+        .and_then(|matches| command.process(&matches, OutputFormat::Json, false, false))
+        .and_then(|output| serde_json::from_str(&output).map_err(|err| err.to_string()))
+        .unwrap_or_else(|err| serde_json::json!({ "error": err }))
+}
+
+// An entry of `account list` which failed to load
+fn error_account(source: &str, account_source: &str, error: String) -> serde_json::Value {
+    serde_json::json!({
+        "source": source,
+        "account_source": account_source,
+        "lock_arg": null,
+        "lock_hash": null,
+        "address": null,
+        "xpub_fingerprint": null,
+        "derivation_path": null,
+        "error": error,
+    })
+}
+
+// The local aliases of the address, a bad aliases file is reported in the
+// section
+fn aliases_section(
+    resolver: Result<AliasResolver, String>,
+    payload: &AddressPayload,
+) -> serde_json::Value {
+    match resolver {
+        Ok(resolver) => serde_json::json!(resolver.local_aliases_of(payload)),
+        Err(err) => serde_json::json!({ "error": err }),
+    }
+}
+
+// The max number of newest local tx records checked for pending transactions
+const PENDING_CHECK_LIMIT: usize = 50;
+
+// The transactions in the local tracking store not committed yet, which
+// spend or create cells of the lock
+fn pending_transactions(
+    rpc_client: &mut HttpRpcClient,
+    lock_script: &Script,
+) -> Result<serde_json::Value, String> {
+    let store = TxRecordStore::load()?;
+    let mut pending = Vec::new();
+    for record in store.records().iter().rev().take(PENDING_CHECK_LIMIT) {
+        let tx_with_status = match rpc_client.get_transaction(record.tx_hash.clone())? {
+            Some(tx_with_status) => tx_with_status,
+            None => continue,
+        };
+        let status = match tx_with_status.tx_status.status {
+            Status::Pending => "pending",
+            Status::Proposed => "proposed",
+            Status::Committed => continue,
+        };
+        let tx = tx_with_status.transaction.inner;
+        let mut touched = tx
+            .outputs
+            .iter()
+            .any(|output| Script::from(output.lock.clone()).as_slice() == lock_script.as_slice());
+        for input in &tx.inputs {
+            if touched {
+                break;
+            }
+            let prev = &input.previous_output;
+            if let Some(prev_tx) = rpc_client.get_transaction(prev.tx_hash.clone())? {
+                touched = prev_tx
+                    .transaction
+                    .inner
+                    .outputs
+                    .get(prev.index.value() as usize)
+                    .map(|output| {
+                        Script::from(output.lock.clone()).as_slice() == lock_script.as_slice()
+                    })
+                    .unwrap_or(false);
+            }
+        }
+        if touched {
+            pending.push(serde_json::json!({
+                "tx_hash": record.tx_hash,
+                "status": status,
+                "memo": record.memo,
+                "sent_at": Utc.timestamp_millis(record.sent_at as i64).to_rfc3339(),
+            }));
+        }
+    }
+    Ok(serde_json::json!(pending))
+}
+
+/// The descriptor of a multisig address, the config must be saved by
+/// `account multisig-create` or `account descriptor import`
+fn multisig_descriptor(payload: &AddressPayload) -> Result<Descriptor, String> {
+    let args = payload.args();
+    if payload.code_hash() != MULTISIG_TYPE_HASH.pack() || (args.len() != 20 && args.len() != 28) {
+        return Err("Invalid multisig address".to_string());
+    }
+    let multisig_hash160 = H160::from_slice(&args[0..20]).unwrap();
+    let config = MultisigConfigStore::load()?
+        .get(&multisig_hash160)?
+        .ok_or_else(|| {
+            format!(
+                "Multisig config of {:#x} not found, please create it by `account multisig-create`",
+                multisig_hash160
+            )
+        })?;
+    let since_absolute_epoch = if args.len() == 28 {
+        let mut since_bytes = [0u8; 8];
+        since_bytes.copy_from_slice(&args[20..28]);
+        match Since::from_raw_value(u64::from_le_bytes(since_bytes)).extract_metric() {
+            Some((SinceType::EpochNumberWithFraction, value)) => {
+                Some(EpochNumberWithFraction::from_full_value(value).number())
+            }
+            _ => None,
+        }
+    } else {
+        None
+    };
+    let descriptor = Descriptor::Multisig {
+        config,
+        since_absolute_epoch,
+    };
+    // Only absolute epoch number since is supported by descriptor
+    if descriptor.address_payload().as_ref() != Some(payload) {
+        return Err("Only multisig address with absolute epoch since is supported".to_string());
+    }
+    Ok(descriptor)
+}
+
 fn address_resp(
     account_source: &'static str,
     address_payload: &AddressPayload,
@@ -419,3 +1513,157 @@ fn address_resp(
         },
     })
 }
+
+/// The key files of the source keystores to merge (with the index of the
+/// source), and the duplicates of the accounts existing or merged from an
+/// earlier source
+fn plan_merge(
+    existing: &HashMap<H160, PathBuf>,
+    sources: &[HashMap<H160, PathBuf>],
+) -> (Vec<(usize, H160, PathBuf)>, Vec<serde_json::Value>) {
+    let mut existing = existing.clone();
+    let mut to_merge = Vec::new();
+    let mut duplicates = Vec::new();
+    for (source_idx, accounts) in sources.iter().enumerate() {
+        let mut accounts = accounts.iter().collect::<Vec<_>>();
+        accounts.sort_by(|a, b| a.1.cmp(b.1));
+        for (lock_arg, path) in accounts {
+            if let Some(existing_path) = existing.get(lock_arg) {
+                duplicates.push(serde_json::json!({
+                    "lock_arg": format!("{:#x}", lock_arg),
+                    "source": path.to_string_lossy(),
+                    "existing": existing_path.to_string_lossy(),
+                }));
+            } else {
+                existing.insert(lock_arg.clone(), path.clone());
+                to_merge.push((source_idx, lock_arg.clone(), path.clone()));
+            }
+        }
+    }
+    (to_merge, duplicates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_merge() {
+        let lock_arg = |byte: u8| H160::from_slice(&[byte; 20]).unwrap();
+        let path =
+            |dir: &str, byte: u8| PathBuf::from(format!("{}/key--{:x}", dir, lock_arg(byte)));
+        let existing: HashMap<_, _> = vec![(lock_arg(1), path("keystore", 1))]
+            .into_iter()
+            .collect();
+        let sources: Vec<HashMap<_, _>> = vec![
+            vec![(lock_arg(1), path("a", 1)), (lock_arg(2), path("a", 2))]
+                .into_iter()
+                .collect(),
+            vec![(lock_arg(3), path("b", 3)), (lock_arg(2), path("b", 2))]
+                .into_iter()
+                .collect(),
+        ];
+        let (to_merge, duplicates) = plan_merge(&existing, &sources);
+        assert_eq!(
+            to_merge,
+            vec![
+                (0, lock_arg(2), path("a", 2)),
+                (1, lock_arg(3), path("b", 3)),
+            ]
+        );
+        // Already in the keystore, or merged from the first source
+        assert_eq!(duplicates.len(), 2);
+        assert_eq!(
+            duplicates[0]["existing"],
+            serde_json::json!(path("keystore", 1).to_string_lossy())
+        );
+        assert_eq!(
+            duplicates[1]["existing"],
+            serde_json::json!(path("a", 2).to_string_lossy())
+        );
+        assert_eq!(plan_merge(&existing, &[]), (Vec::new(), Vec::new()));
+    }
+
+    // Echo the `--output` argument as the command output
+    struct EchoCommand;
+
+    impl CliSubCommand for EchoCommand {
+        fn process(
+            &mut self,
+            matches: &ArgMatches,
+            _format: OutputFormat,
+            _color: bool,
+            _debug: bool,
+        ) -> Result<String, String> {
+            match matches.value_of("output") {
+                Some("fail") => Err("index database is not synced".to_string()),
+                Some(output) => Ok(output.to_string()),
+                None => Err(matches.usage().to_owned()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_report_section() {
+        let app = || {
+            App::new("echo").arg(
+                Arg::with_name("output")
+                    .long("output")
+                    .takes_value(true)
+                    .required(true),
+            )
+        };
+        let section = |args: &[&str]| report_section(&mut EchoCommand, app(), args);
+        assert_eq!(
+            section(&["echo", "--output", r#"{"total": "100.0"}"#]),
+            serde_json::json!({"total": "100.0"})
+        );
+        // The errors of the command, the arguments and the output are
+        // reported in the section
+        assert_eq!(
+            section(&["echo", "--output", "fail"]),
+            serde_json::json!({"error": "index database is not synced"})
+        );
+        assert!(section(&["echo"])["error"].is_string());
+        assert!(section(&["echo", "--output", "total: 100"])["error"].is_string());
+    }
+
+    #[test]
+    fn test_aliases_section() {
+        let payload = AddressPayload::from_pubkey_hash(H160::from_slice(&[1u8; 20]).unwrap());
+        let address = Address::new(NetworkType::Testnet, payload.clone()).to_string();
+        let resolver: AliasResolver = serde_json::from_value(serde_json::json!({
+            "aliases": {"alice.bit": address, "bob.bit": "ckt1qyqt8xaupvm8837nv3gtc9x0ekkj64vud3jqfwyw5v"},
+        }))
+        .unwrap();
+        assert_eq!(
+            aliases_section(Ok(resolver), &payload),
+            serde_json::json!(["alice.bit"])
+        );
+        // A bad aliases file does not fail the report
+        assert_eq!(
+            aliases_section(
+                Err("Parse address-aliases.json failed".to_string()),
+                &payload
+            ),
+            serde_json::json!({"error": "Parse address-aliases.json failed"})
+        );
+    }
+
+    #[test]
+    fn test_error_account() {
+        let schema: serde_json::Value =
+            serde_json::from_str(include_str!("../../schemas/account-list.json")).unwrap();
+        let account = error_account(
+            "watch-only",
+            WATCH_ONLY_SOURCE_NAME,
+            "Load multisig configs failed".to_string(),
+        );
+        // Listed with the index
+        for field in schema["items"]["required"].as_array().unwrap() {
+            let field = field.as_str().unwrap();
+            assert!(field == "#" || account.get(field).is_some(), "{}", field);
+        }
+        assert_eq!(account["error"], "Load multisig configs failed");
+    }
+}
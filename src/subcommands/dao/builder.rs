@@ -1,7 +1,7 @@
 use super::util::minimal_unlock_point;
 use crate::subcommands::dao::util::calculate_dao_maximum_withdraw4;
 use ckb_index::LiveCellInfo;
-use ckb_sdk::{GenesisInfo, HttpRpcClient, Since, SinceType};
+use ckb_sdk::{split_change, GenesisInfo, HttpRpcClient, Since, SinceType};
 use ckb_types::core::Capacity;
 use ckb_types::{
     bytes::Bytes,
@@ -11,28 +11,54 @@ use ckb_types::{
 };
 use std::collections::HashSet;
 
-// NOTE: We assume all inputs are from same account
+// The data of a DAO cell: 0 for deposit, the deposit block number for prepare
+pub(crate) const DAO_OUTPUT_DATA_LEN: usize = 8;
+
 #[derive(Debug)]
 pub(crate) struct DAOBuilder {
     genesis_info: GenesisInfo,
     tx_fee: u64,
+    change_cells: usize,
     live_cells: Vec<LiveCellInfo>,
+    // The lock of change outputs (the withdrawn outputs of withdraw)
+    change_lock: Script,
 }
 
 impl DAOBuilder {
     pub(crate) fn new(
         genesis_info: GenesisInfo,
         tx_fee: u64,
+        change_cells: usize,
         live_cells: Vec<LiveCellInfo>,
+        change_lock: Script,
     ) -> Self {
         Self {
             genesis_info,
             tx_fee,
+            change_cells,
             live_cells,
+            change_lock,
         }
     }
 
-    pub(crate) fn deposit(&self, deposit_capacity: u64) -> Result<TransactionView, String> {
+    fn change_outputs(&self, capacity: u64) -> (Vec<CellOutput>, Vec<packed::Bytes>) {
+        split_change(capacity, self.change_cells)
+            .into_iter()
+            .map(|capacity| {
+                let output = CellOutput::new_builder()
+                    .capacity(capacity.pack())
+                    .lock(self.change_lock.clone())
+                    .build();
+                (output, Default::default())
+            })
+            .unzip()
+    }
+
+    pub(crate) fn deposit(
+        &self,
+        deposit_capacities: &[u64],
+        deposit_lock: Script,
+    ) -> Result<TransactionView, String> {
         let genesis_info = &self.genesis_info;
         let inputs = self
             .live_cells
@@ -43,30 +69,33 @@ impl DAOBuilder {
             .iter()
             .map(|_| Default::default())
             .collect::<Vec<_>>();
-        let (output, output_data) = {
-            // NOTE: Here give null lock script to the output. It's caller's duty to fill the lock
-            let output = CellOutput::new_builder()
-                .capacity(deposit_capacity.pack())
-                .type_(Some(dao_type_script(&self.genesis_info)?).pack())
-                .build();
-            let output_data = Bytes::from(&[0u8; 8][..]).pack();
-            (output, output_data)
-        };
+        let dao_type_script = dao_type_script(&self.genesis_info)?;
+        let (outputs, outputs_data): (Vec<_>, Vec<_>) = deposit_capacities
+            .iter()
+            .map(|capacity| {
+                let output = CellOutput::new_builder()
+                    .capacity(capacity.pack())
+                    .lock(deposit_lock.clone())
+                    .type_(Some(dao_type_script.clone()).pack())
+                    .build();
+                let output_data = Bytes::from(&[0u8; 8][..]).pack();
+                (output, output_data)
+            })
+            .unzip();
         let cell_deps = vec![genesis_info.dao_dep()];
         let tx = TransactionBuilder::default()
             .inputs(inputs)
-            .output(output)
-            .output_data(output_data)
+            .outputs(outputs)
+            .outputs_data(outputs_data)
             .cell_deps(cell_deps)
             .witnesses(witnesses);
 
         let input_capacity = self.live_cells.iter().map(|txo| txo.capacity).sum::<u64>();
+        let deposit_capacity = deposit_capacities.iter().sum::<u64>();
         let change_capacity = input_capacity - deposit_capacity - self.tx_fee;
         if change_capacity > 0 {
-            let change = CellOutput::new_builder()
-                .capacity(change_capacity.pack())
-                .build();
-            Ok(tx.output(change).output_data(Default::default()).build())
+            let (changes, changes_data) = self.change_outputs(change_capacity);
+            Ok(tx.outputs(changes).outputs_data(changes_data).build())
         } else {
             Ok(tx.build())
         }
@@ -134,10 +163,8 @@ impl DAOBuilder {
 
         let change_capacity =
             change_cells.iter().map(|txo| txo.capacity).sum::<u64>() - self.tx_fee;
-        let change = CellOutput::new_builder()
-            .capacity(change_capacity.pack())
-            .build();
-        Ok(tx.output(change).output_data(Default::default()).build())
+        let (changes, changes_data) = self.change_outputs(change_capacity);
+        Ok(tx.outputs(changes).outputs_data(changes_data).build())
     }
 
     pub(crate) fn withdraw(
@@ -190,7 +217,6 @@ impl DAOBuilder {
             .iter()
             .zip(prepare_txo_headers.iter())
             .map(|((_, output, deposit_header), (_, _, prepare_header))| {
-                const DAO_OUTPUT_DATA_LEN: usize = 8;
                 let occupied_capacity = output
                     .occupied_capacity(Capacity::bytes(DAO_OUTPUT_DATA_LEN).unwrap())
                     .unwrap();
@@ -203,9 +229,7 @@ impl DAOBuilder {
             })
             .sum::<u64>();
         let output_capacity = total_capacity - self.tx_fee;
-        let output = CellOutput::new_builder()
-            .capacity(output_capacity.pack())
-            .build();
+        let (outputs, outputs_data) = self.change_outputs(output_capacity);
         let cell_deps = vec![genesis_info.dao_dep()];
         let header_deps = deposit_txo_headers
             .iter()
@@ -230,11 +254,11 @@ impl DAOBuilder {
             .collect::<Vec<_>>();
         Ok(TransactionBuilder::default()
             .inputs(inputs)
-            .output(output)
+            .outputs(outputs)
             .cell_deps(cell_deps)
             .header_deps(header_deps)
             .witnesses(witnesses)
-            .output_data(Default::default())
+            .outputs_data(outputs_data)
             .build())
     }
 
@@ -272,7 +296,7 @@ impl DAOBuilder {
     }
 }
 
-fn dao_type_script(genesis_info: &GenesisInfo) -> Result<Script, String> {
+pub(crate) fn dao_type_script(genesis_info: &GenesisInfo) -> Result<Script, String> {
     Ok(Script::new_builder()
         .hash_type(ScriptHashType::Type.into())
         .code_hash(genesis_info.dao_type_hash().clone())
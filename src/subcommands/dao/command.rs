@@ -1,27 +1,51 @@
 use clap::{App, Arg, ArgMatches, SubCommand};
 use either::Either;
 use std::collections::HashSet;
+use std::path::PathBuf;
 
 use crate::subcommands::{
     account::AccountId,
-    dao::util::{calculate_dao_maximum_withdraw, send_transaction},
+    dao::util::{
+        calculate_dao_compensation, calculate_dao_maximum_withdraw, dao_unlock_point,
+        send_transaction, send_transactions, split_deposit, DaoCompensation, DepositSplit,
+        SendArgs, UnlockEstimator, MAX_DEPOSIT_CELLS,
+    },
+    tx::write_tx_file,
     CliSubCommand, DAOSubCommand,
 };
 use crate::utils::{
+    alias::{is_alias_name, resolve_address},
     arg,
     arg_parser::{
-        ArgParser, CapacityParser, DerivationPathParser, FixedHashParser, OutPointParser,
+        AddressParser, ArgParser, CapacityParser, DerivationPathParser, FilePathParser,
+        FixedHashParser, FromAccountParser, FromStrParser, OutPointParser, PrivkeyPathParser,
         PrivkeyWrapper,
     },
-    other::{get_address, get_network_type, privkey_or_from_account},
-    printer::{OutputFormat, Printable},
+    broadcast::SendTargets,
+    coin_control::{display_out_point, CoinControl},
+    index_backend::{light_client_url, CellQuery},
+    multisig::MultisigConfigStore,
+    on_failure::OnFailure,
+    other::{
+        get_address, get_fee_policy, get_network_type, privkey_or_from_account,
+        remote_signer_from_matches,
+    },
+    printer::{render_list, OutputFormat, Printable},
+    tx_status::WaitForCommitted,
 };
 
-use ckb_sdk::{wallet::DerivationPath, NetworkType};
+use chrono::{TimeZone, Utc};
+use ckb_index::LiveCellQuery;
+use ckb_sdk::{
+    constants::MULTISIG_TYPE_HASH,
+    wallet::{DerivationPath, RemoteSigner},
+    Address, AddressPayload, FeePolicy, HumanCapacity, MultisigConfig, NetworkType, TxHelper,
+};
 use ckb_types::{
-    packed::{Byte32, Script},
+    core::{EpochNumberWithFraction, HeaderView, TransactionView},
+    packed::{OutPoint, Script},
     prelude::*,
-    H256,
+    H160, H256,
 };
 
 impl<'a> CliSubCommand for DAOSubCommand<'a> {
@@ -33,66 +57,267 @@ impl<'a> CliSubCommand for DAOSubCommand<'a> {
         debug: bool,
     ) -> Result<String, String> {
         let network_type = get_network_type(&mut self.rpc_client)?;
+        if let (_, Some(m)) = matches.subcommand() {
+            self.select_index_backend(m, network_type)?;
+            self.signing_policy.allow_high_fee = m.is_present("allow-high-fee");
+            self.send_targets.light_client_url = light_client_url(m);
+        }
         match matches.subcommand() {
             ("deposit", Some(m)) => {
                 let capacity: u64 = CapacityParser.from_matches(m, "capacity")?;
+                let split_count: Option<usize> =
+                    FromStrParser::<usize>::default().from_matches_opt(m, "split-count", false)?;
+                let cell_capacity: Option<u64> =
+                    CapacityParser.from_matches_opt(m, "cell-capacity", false)?;
+                let split = split_count
+                    .map(DepositSplit::Count)
+                    .or_else(|| cell_capacity.map(DepositSplit::CellCapacity));
+                let capacities = split_deposit(capacity, split)?;
+                let transact_args =
+                    TransactArgs::from_matches(m, network_type, &self.send_targets)?;
+                let send_args = transact_args.send_args.clone();
+                let multisig = transact_args.funding.multisig().cloned();
                 let transaction = self
-                    .with_transact_args(TransactArgs::from_matches(m, network_type)?)?
-                    .deposit(capacity)?;
-                send_transaction(self.rpc_client(), transaction, format, color, debug)
+                    .with_transact_args(transact_args)?
+                    .deposit(capacities)?;
+                self.send_or_save(transaction, multisig, send_args, format, color, debug)
             }
             ("prepare", Some(m)) => {
+                let transact_args =
+                    TransactArgs::from_matches(m, network_type, &self.send_targets)?;
+                let send_args = transact_args.send_args.clone();
+                if m.is_present("all") {
+                    let max_cells: Option<usize> = FromStrParser::<usize>::default()
+                        .from_matches_opt(m, "max-cells", false)?;
+                    let transactions = self
+                        .with_transact_args(transact_args)?
+                        .prepare_all(max_cells)?;
+                    return send_transactions(
+                        self.rpc_client(),
+                        transactions,
+                        send_args,
+                        format,
+                        color,
+                        debug,
+                    );
+                }
                 let out_points = OutPointParser.from_matches_vec(m, "out-point")?;
                 if out_points.len() != out_points.iter().collect::<HashSet<_>>().len() {
                     return Err("Duplicated out-points".to_string());
                 }
+                let multisig = transact_args.funding.multisig().cloned();
                 let transaction = self
-                    .with_transact_args(TransactArgs::from_matches(m, network_type)?)?
+                    .with_transact_args(transact_args)?
                     .prepare(out_points)?;
-                send_transaction(self.rpc_client(), transaction, format, color, debug)
+                self.send_or_save(transaction, multisig, send_args, format, color, debug)
             }
             ("withdraw", Some(m)) => {
+                let transact_args =
+                    TransactArgs::from_matches(m, network_type, &self.send_targets)?;
+                let send_args = transact_args.send_args.clone();
+                if m.is_present("all") {
+                    let max_cells: Option<usize> = FromStrParser::<usize>::default()
+                        .from_matches_opt(m, "max-cells", false)?;
+                    let transactions = self
+                        .with_withdraw_args(transact_args)?
+                        .withdraw_all(max_cells)?;
+                    return send_transactions(
+                        self.rpc_client(),
+                        transactions,
+                        send_args,
+                        format,
+                        color,
+                        debug,
+                    );
+                }
                 let out_points = OutPointParser.from_matches_vec(m, "out-point")?;
                 if out_points.len() != out_points.iter().collect::<HashSet<_>>().len() {
                     return Err("Duplicated out-points".to_string());
                 }
+                let multisig = transact_args.funding.multisig().cloned();
                 let transaction = self
-                    .with_transact_args(TransactArgs::from_matches(m, network_type)?)?
+                    .with_withdraw_args(transact_args)?
                     .withdraw(out_points)?;
-                send_transaction(self.rpc_client(), transaction, format, color, debug)
+                self.send_or_save(transaction, multisig, send_args, format, color, debug)
+            }
+            ("sign", Some(m)) => {
+                let tx_file: PathBuf = FilePathParser::new(true).from_matches(m, "tx-file")?;
+                let account = privkey_or_from_account(m)?;
+                let path = match account {
+                    Either::Left(_) => DerivationPath::empty(),
+                    _ => DerivationPathParser.from_matches(m, "path")?,
+                };
+                let (signatures, threshold, transaction_opt) =
+                    self.sign_tx_file(&tx_file, &account, &path, network_type)?;
+                if !m.is_present("send") {
+                    let resp = serde_json::json!({
+                        "tx_file": tx_file.to_string_lossy(),
+                        "signatures": signatures,
+                        "threshold": threshold,
+                    });
+                    return Ok(resp.render(format, color));
+                }
+                let transaction = transaction_opt.ok_or_else(|| {
+                    format!(
+                        "Not enough signatures ({}/{}), sign by more members of the multisig before --send",
+                        signatures, threshold
+                    )
+                })?;
+                let send_args = SendArgs {
+                    memo: m.value_of("memo").map(ToOwned::to_owned),
+                    wait_for_committed: WaitForCommitted::from_matches(m, "wait-for-committed")?,
+                    on_failure: OnFailure::from_matches(m)?,
+                    targets: self.send_targets.clone(),
+                };
+                send_transaction(
+                    self.rpc_client(),
+                    transaction,
+                    send_args,
+                    format,
+                    color,
+                    debug,
+                )
             }
             ("query-deposited-cells", Some(m)) => {
                 let query_args = QueryArgs::from_matches(m, network_type)?;
-                let lock_hash = query_args.lock_hash;
-                let cells = self.query_deposit_cells(lock_hash)?;
+                let cells = self.query_deposit_cells(query_args.query)?;
                 let total_capacity = cells.iter().map(|live| live.capacity).sum::<u64>();
+                let dao_type_hash = self.dao_type_hash().clone();
+                let tip_header: HeaderView = self.rpc_client().get_tip_header()?.into();
+                let compensations = cells
+                    .iter()
+                    .map(|cell| {
+                        calculate_dao_compensation(
+                            self.rpc_client(),
+                            &cell.out_point(),
+                            &dao_type_hash,
+                            &tip_header,
+                        )
+                    })
+                    .collect::<Result<Vec<_>, String>>()?;
+                let total_compensation = compensations
+                    .iter()
+                    .map(DaoCompensation::compensation)
+                    .sum::<u64>();
                 let resp = serde_json::json!({
-                    "live_cells": cells.into_iter().map(|info| {
-                        serde_json::to_value(&info).unwrap()
+                    "live_cells": cells.into_iter().zip(compensations.iter()).map(|(info, compensation)| {
+                        let mut value = serde_json::to_value(&info).unwrap();
+                        let obj = value.as_object_mut().unwrap();
+                        obj.insert("compensation".to_owned(), serde_json::json!(compensation.compensation()));
+                        obj.insert("maximum_withdraw".to_owned(), serde_json::json!(compensation.maximum_withdraw));
+                        value
                     }).collect::<Vec<_>>(),
                     "total_capacity": total_capacity,
+                    "total_compensation": total_compensation,
+                    "tip_block_number": tip_header.number(),
+                });
+                Ok(render_list(&resp, "live_cells", format, color))
+            }
+            ("query-compensation", Some(m)) => {
+                let out_point: OutPoint = OutPointParser.from_matches(m, "out-point")?;
+                let cell = self.rpc_client().get_live_cell(out_point.clone(), false)?;
+                if cell.status != "live" {
+                    return Err(format!(
+                        "Invalid cell status: {}, out_point: {}",
+                        cell.status, out_point
+                    ));
+                }
+                let dao_type_hash = self.dao_type_hash().clone();
+                let tip_header: HeaderView = self.rpc_client().get_tip_header()?.into();
+                let compensation = calculate_dao_compensation(
+                    self.rpc_client(),
+                    &out_point,
+                    &dao_type_hash,
+                    &tip_header,
+                )?;
+                let resp = serde_json::json!({
+                    "status": if compensation.prepared { "prepared" } else { "deposited" },
+                    "deposit_block_number": compensation.deposit_block_number,
+                    "compensation_until_block_number": compensation.end_block_number,
+                    "deposited_capacity": format!("{:#}", HumanCapacity::from(compensation.capacity)),
+                    "compensation": format!("{:#}", HumanCapacity::from(compensation.compensation())),
+                    "maximum_withdraw": format!("{:#}", HumanCapacity::from(compensation.maximum_withdraw)),
                 });
                 Ok(resp.render(format, color))
             }
+            ("export-positions", Some(m)) => {
+                let query_args = QueryArgs::from_matches(m, network_type)?;
+                let mut cells = self.query_deposit_cells(query_args.query.clone())?;
+                cells.extend(self.query_prepare_cells(query_args.query)?);
+                cells.sort_by_key(|cell| (cell.number, cell.tx_index, cell.index.output_index));
+                let dao_type_hash = self.dao_type_hash().clone();
+                let tip_header: HeaderView = self.rpc_client().get_tip_header()?.into();
+                let mut positions = Vec::with_capacity(cells.len());
+                let mut total_capacity = 0;
+                let mut total_compensation = 0;
+                for cell in cells {
+                    let compensation = calculate_dao_compensation(
+                        self.rpc_client(),
+                        &cell.out_point(),
+                        &dao_type_hash,
+                        &tip_header,
+                    )?;
+                    total_capacity += compensation.capacity;
+                    total_compensation += compensation.compensation();
+                    positions.push(serde_json::json!({
+                        "out_point": display_out_point(&cell.out_point()),
+                        "status": if compensation.prepared { "prepared" } else { "deposited" },
+                        "deposit_block_number": compensation.deposit_block_number,
+                        "prepare_block_number": if compensation.prepared {
+                            Some(compensation.end_block_number)
+                        } else {
+                            None
+                        },
+                        "capacity": compensation.capacity,
+                        "compensation": compensation.compensation(),
+                        "maximum_withdraw": compensation.maximum_withdraw,
+                        "unlock_epoch": epoch_json(compensation.unlock_point),
+                    }));
+                }
+                let resp = serde_json::json!({
+                    "positions": positions,
+                    "total_capacity": total_capacity,
+                    "total_compensation": total_compensation,
+                    "tip_block_number": tip_header.number(),
+                    "tip_epoch": epoch_json(tip_header.epoch()),
+                });
+                Ok(render_list(&resp, "positions", format, color))
+            }
             ("query-prepared-cells", Some(m)) => {
                 let query_args = QueryArgs::from_matches(m, network_type)?;
-                let lock_hash = query_args.lock_hash;
-                let cells = self.query_prepare_cells(lock_hash)?;
+                let cells = self.query_prepare_cells(query_args.query)?;
                 let maximum_withdraws: Vec<_> = cells
                     .iter()
                     .map(|cell| calculate_dao_maximum_withdraw(self.rpc_client(), cell))
                     .collect::<Result<Vec<u64>, String>>()?;
                 let total_maximum_withdraw = maximum_withdraws.iter().sum::<u64>();
+                let tip_header: HeaderView = self.rpc_client().get_tip_header()?.into();
+                let estimator = UnlockEstimator::new(self.rpc_client(), tip_header)?;
+                let mut unlocks = Vec::with_capacity(cells.len());
+                for cell in &cells {
+                    let unlock_point = dao_unlock_point(self.rpc_client(), &cell.out_point())?
+                        .ok_or_else(|| "invalid out_point, not a prepared cell".to_string())?;
+                    let unlock_time = estimator.unlock_time(self.rpc_client(), unlock_point)?;
+                    unlocks.push((unlock_point, unlock_time));
+                }
                 let resp = serde_json::json!({
                     "live_cells": (0..cells.len()).map(|i| {
+                        let (unlock_point, unlock_time) = unlocks[i];
                         let mut value = serde_json::to_value(&cells[i]).unwrap();
                         let obj = value.as_object_mut().unwrap();
                         obj.insert("maximum_withdraw".to_owned(), serde_json::json!(maximum_withdraws[i]));
+                        obj.insert("unlock_epoch".to_owned(), epoch_json(unlock_point));
+                        obj.insert(
+                            "estimated_unlock_time".to_owned(),
+                            serde_json::json!(Utc.timestamp_millis(unlock_time as i64).to_rfc3339()),
+                        );
+                        obj.insert("withdrawable_now".to_owned(), serde_json::json!(estimator.is_unlocked(unlock_point)));
                         value
                     }).collect::<Vec<_>>(),
                     "total_maximum_withdraw": total_maximum_withdraw,
+                    "median_block_time": format!("{:.2}s", estimator.block_time as f64 / 1000.0),
                 });
-                Ok(resp.render(format, color))
+                Ok(render_list(&resp, "live_cells", format, color))
             }
             _ => Err(matches.usage().to_owned()),
         }
@@ -100,6 +325,41 @@ impl<'a> CliSubCommand for DAOSubCommand<'a> {
 }
 
 impl<'a> DAOSubCommand<'a> {
+    // Send the signed transaction, or save the unsigned transaction of a
+    // multisig lock to collect the signatures by `dao sign`
+    fn send_or_save(
+        &mut self,
+        transaction: TransactionView,
+        multisig: Option<MultisigFunding>,
+        send_args: SendArgs,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let multisig = match multisig {
+            Some(multisig) => multisig,
+            None => {
+                return send_transaction(
+                    self.rpc_client(),
+                    transaction,
+                    send_args,
+                    format,
+                    color,
+                    debug,
+                )
+            }
+        };
+        let mut helper = TxHelper::new(transaction);
+        helper.add_multisig_config(multisig.config.clone());
+        write_tx_file(&multisig.tx_file, helper, multisig.network_type)?;
+        let resp = serde_json::json!({
+            "tx_file": multisig.tx_file.to_string_lossy(),
+            "signatures": 0,
+            "threshold": multisig.config.threshold(),
+        });
+        Ok(resp.render(format, color))
+    }
+
     pub fn subcommand() -> App<'static, 'static> {
         SubCommand::with_name("dao")
             .about("Deposit / prepare / withdraw / query NervosDAO balance (with local index) / key utils")
@@ -107,79 +367,374 @@ impl<'a> DAOSubCommand<'a> {
                 SubCommand::with_name("deposit")
                     .about("Deposit capacity into NervosDAO")
                     .args(&TransactArgs::args())
-                    .arg(arg::capacity().required(true)),
+                    .arg(
+                        Arg::with_name("deposit-address")
+                            .long("deposit-address")
+                            .takes_value(true)
+                            .validator(|input| {
+                                if is_alias_name(&input) {
+                                    Ok(())
+                                } else {
+                                    AddressParser::default().validate(input)
+                                }
+                            })
+                            .help("The owner of the deposited cell (eg: a cold key), default: the funding account"),
+                    )
+                    .arg(arg::capacity().required(true))
+                    .arg(
+                        Arg::with_name("split-count")
+                            .long("split-count")
+                            .takes_value(true)
+                            .validator(|input| {
+                                let count = FromStrParser::<usize>::default().parse(&input)?;
+                                if count == 0 || count > MAX_DEPOSIT_CELLS {
+                                    return Err(format!(
+                                        "split-count must be in range [1, {}]",
+                                        MAX_DEPOSIT_CELLS
+                                    ));
+                                }
+                                Ok(())
+                            })
+                            .help("Divide the deposit into this number of DAO cells (whole CKB each, the last one takes the remainder), so they can be withdrawn separately"),
+                    )
+                    .arg(
+                        Arg::with_name("cell-capacity")
+                            .long("cell-capacity")
+                            .takes_value(true)
+                            .conflicts_with("split-count")
+                            .validator(|input| CapacityParser.validate(input))
+                            .help("Divide the deposit into DAO cells of this capacity (the last one takes the remainder), so they can be withdrawn separately"),
+                    )
+                    .arg(arg::use_out_point())
+                    .arg(arg::exclude_out_point()),
                 SubCommand::with_name("prepare")
-                    .about("Prepare specified cells from NervosDAO")
+                    .about("Prepare specified cells from NervosDAO (the fee is paid by the funding account)")
                     .args(&TransactArgs::args())
-                    .arg(arg::out_point().required(true).multiple(true)),
+                    .args(&TransactArgs::owner_args())
+                    .arg(arg::use_out_point())
+                    .arg(arg::exclude_out_point())
+                    .arg(arg::out_point().required_unless("all").multiple(true))
+                    .args(&all_cells_args("Prepare every deposited cell of the owner, split into multiple transactions when too large")),
                 SubCommand::with_name("withdraw")
-                    .about("Withdraw specified cells from NervosDAO")
+                    .about("Withdraw specified cells from NervosDAO (the fee is paid by the withdrawn capacity)")
                     .args(&TransactArgs::args())
-                    .arg(arg::out_point().required(true).multiple(true)),
+                    .args(&TransactArgs::owner_args())
+                    .arg(
+                        Arg::with_name("withdraw-address")
+                            .long("withdraw-address")
+                            .takes_value(true)
+                            .validator(|input| {
+                                if is_alias_name(&input) {
+                                    Ok(())
+                                } else {
+                                    AddressParser::default().validate(input)
+                                }
+                            })
+                            .help("The destination of the withdrawn capacity, default: the funding account"),
+                    )
+                    .arg(arg::out_point().required_unless("all").multiple(true))
+                    .args(&all_cells_args("Withdraw every prepared cell of the owner which can be withdrawn now, split into multiple transactions when too large")),
+                SubCommand::with_name("sign")
+                    .about("Sign the multisig transaction saved by deposit / prepare / withdraw with --from-multisig-address, the signature is added to the file (run once by every signing member)")
+                    .arg(
+                        Arg::with_name("tx-file")
+                            .long("tx-file")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FilePathParser::new(true).validate(input))
+                            .help("The multisig transaction file"),
+                    )
+                    .arg(arg::privkey_path().required_unless(arg::from_account().b.name))
+                    .arg(
+                        arg::from_account()
+                            .required_unless(arg::privkey_path().b.name)
+                            .conflicts_with(arg::privkey_path().b.name),
+                    )
+                    .arg(arg::derivation_path().conflicts_with(arg::privkey_path().b.name))
+                    .arg(arg::allow_high_fee())
+                    .arg(
+                        Arg::with_name("send")
+                            .long("send")
+                            .help("Send the transaction when the signatures reach the threshold"),
+                    )
+                    .arg(arg::memo())
+                    .arg(arg::on_failure())
+                    .args(&arg::wait_for_committed_args()),
                 SubCommand::with_name("query-deposited-cells")
                     .about("Query NervosDAO deposited capacity by lock script hash or address")
                     .args(&QueryArgs::args()),
+                SubCommand::with_name("query-compensation")
+                    .about("Query the compensation accrued by a NervosDAO deposited (until the tip block) or prepared (until the prepare block) cell")
+                    .arg(arg::out_point().required_unless("schema")),
+                SubCommand::with_name("export-positions")
+                    .about("Export the NervosDAO positions (deposited and prepared cells) of a lock script hash or address for portfolio tools, see `dao export-positions --schema`")
+                    .args(&QueryArgs::args()),
                 SubCommand::with_name("query-prepared-cells")
-                    .about("Query NervosDAO prepared capacity by lock script hash or address")
+                    .about("Query NervosDAO prepared capacity by lock script hash or address, with the epoch and the estimated time since which each cell can be withdrawn")
                     .args(&QueryArgs::args())
             ])
     }
 }
 
+fn epoch_json(epoch: EpochNumberWithFraction) -> serde_json::Value {
+    serde_json::json!({
+        "number": epoch.number(),
+        "index": epoch.index(),
+        "length": epoch.length(),
+    })
+}
+
+// The `--all` and `--max-cells` arguments of prepare / withdraw
+fn all_cells_args(help: &'static str) -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("all")
+            .long("all")
+            .conflicts_with_all(&["out-point", "from-multisig-address"])
+            .help(help),
+        Arg::with_name("max-cells")
+            .long("max-cells")
+            .takes_value(true)
+            .requires("all")
+            .validator(|input| {
+                let max_cells = FromStrParser::<usize>::default().parse(&input)?;
+                if max_cells == 0 {
+                    return Err("max-cells must be greater than 0".to_string());
+                }
+                Ok(())
+            })
+            .help("Select at most this number of cells with --all"),
+    ]
+}
+
 pub(crate) struct QueryArgs {
-    pub(crate) lock_hash: Byte32,
+    pub(crate) query: CellQuery,
+}
+
+/// The secp256k1-multisig lock paying and owning the DAO cells, the unsigned
+/// transaction is saved to `tx_file`
+#[derive(Clone)]
+pub(crate) struct MultisigFunding {
+    pub(crate) payload: AddressPayload,
+    pub(crate) config: MultisigConfig,
+    pub(crate) tx_file: PathBuf,
+    pub(crate) network_type: NetworkType,
+}
+
+pub(crate) enum Funding {
+    // The sighash key signing the transaction
+    Key(Either<PrivkeyWrapper, AccountId>, DerivationPath),
+    // The key held by the remote signing service of `--signer-url`
+    Remote(RemoteSigner),
+    Multisig(MultisigFunding),
+}
+
+impl Funding {
+    pub(crate) fn multisig(&self) -> Option<&MultisigFunding> {
+        match self {
+            Funding::Key(..) | Funding::Remote(_) => None,
+            Funding::Multisig(multisig) => Some(multisig),
+        }
+    }
 }
 
 pub(crate) struct TransactArgs {
-    pub(crate) account: Either<PrivkeyWrapper, AccountId>,
-    pub(crate) path: DerivationPath,
-    pub(crate) tx_fee: u64,
+    pub(crate) funding: Funding,
+    pub(crate) fee_policy: FeePolicy,
+    pub(crate) change_cells: usize,
     pub(crate) network_type: NetworkType,
+    pub(crate) send_args: SendArgs,
+    // The key owning the DAO cells to prepare or withdraw, default: the
+    // funding account
+    pub(crate) owner: Option<(Either<PrivkeyWrapper, AccountId>, DerivationPath)>,
+    // The lock of the deposited cell, default: the funding account
+    pub(crate) deposit_lock: Option<Script>,
+    // The lock of the withdrawn outputs, default: the funding account
+    pub(crate) withdraw_lock: Option<Script>,
+    // The cells of the funding account spent for the capacity and fee
+    pub(crate) coin_control: CoinControl,
 }
 
 impl QueryArgs {
     fn from_matches(m: &ArgMatches, network_type: NetworkType) -> Result<Self, String> {
         let lock_hash_opt: Option<H256> =
             FixedHashParser::<H256>::default().from_matches_opt(m, "lock-hash", false)?;
-        let lock_hash = if let Some(lock_hash) = lock_hash_opt {
-            lock_hash.pack()
+        let query = if let Some(lock_hash) = lock_hash_opt {
+            CellQuery::Hash(LiveCellQuery::Lock(lock_hash.pack()))
         } else {
             let address = get_address(Some(network_type), m)?;
-            Script::from(&address).calc_script_hash()
+            CellQuery::Lock(Script::from(&address))
         };
 
-        Ok(Self { lock_hash })
+        Ok(Self { query })
     }
 
     fn args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
-        vec![arg::lock_hash(), arg::address()]
+        vec![
+            arg::lock_hash(),
+            arg::address(),
+            arg::index_backend(),
+            arg::indexer_url(),
+            arg::light_client_url(),
+        ]
     }
 }
 
 impl TransactArgs {
-    fn from_matches(m: &ArgMatches, network_type: NetworkType) -> Result<Self, String> {
-        let account = privkey_or_from_account(m)?;
-        let path = match account {
-            Either::Left(_) => DerivationPath::empty(),
-            _ => DerivationPathParser.from_matches(m, "path")?,
+    fn from_matches(
+        m: &ArgMatches,
+        network_type: NetworkType,
+        targets: &SendTargets,
+    ) -> Result<Self, String> {
+        let funding = if let Some(remote_signer) = remote_signer_from_matches(m)? {
+            Funding::Remote(remote_signer)
+        } else if m.is_present("from-multisig-address") {
+            let address: Address = AddressParser::default()
+                .set_network(network_type)
+                .from_matches(m, "from-multisig-address")?;
+            let payload = address.payload().clone();
+            // The lock with since can not be spent by the DAO transactions
+            if payload.code_hash() != MULTISIG_TYPE_HASH.pack() || payload.args().len() != 20 {
+                return Err(format!(
+                    "Invalid multisig address (short format without since): {}",
+                    address
+                ));
+            }
+            let config = MultisigConfigStore::load()?
+                .get(&H160::from_slice(payload.args().as_ref()).unwrap())?
+                .ok_or_else(|| {
+                    format!(
+                        "Multisig config of {} not found, please create it by `account multisig-create`",
+                        address
+                    )
+                })?;
+            Funding::Multisig(MultisigFunding {
+                payload,
+                config,
+                tx_file: FilePathParser::new(false).from_matches(m, "tx-file")?,
+                network_type,
+            })
+        } else {
+            let account = privkey_or_from_account(m)?;
+            let path = match account {
+                Either::Left(_) => DerivationPath::empty(),
+                _ => DerivationPathParser.from_matches(m, "path")?,
+            };
+            Funding::Key(account, path)
         };
-        let tx_fee: u64 = CapacityParser.from_matches(m, "tx-fee")?;
+        let fee_policy = get_fee_policy(m)?;
+        let change_cells: usize =
+            FromStrParser::<usize>::default().from_matches(m, "change-cells")?;
+        let send_args = SendArgs {
+            memo: m.value_of("memo").map(ToOwned::to_owned),
+            wait_for_committed: WaitForCommitted::from_matches(m, "wait-for-committed")?,
+            on_failure: OnFailure::from_matches(m)?,
+            targets: targets.clone(),
+        };
+        let owner_privkey: Option<PrivkeyWrapper> =
+            PrivkeyPathParser.from_matches_opt(m, "owner-privkey-path", false)?;
+        let owner_account: Option<AccountId> =
+            FromAccountParser.from_matches_opt(m, "owner-account", false)?;
+        let owner = match (owner_privkey, owner_account) {
+            (Some(privkey), _) => Some((Either::Left(privkey), DerivationPath::empty())),
+            (None, Some(account)) => Some((
+                Either::Right(account),
+                DerivationPathParser.from_matches(m, "owner-path")?,
+            )),
+            (None, None) => None,
+        };
+        let lock_of = |name: &str| -> Result<Option<Script>, String> {
+            m.value_of(name)
+                .map(|input| {
+                    resolve_address(input, network_type)
+                        .map(|address| Script::from(address.payload()))
+                })
+                .transpose()
+        };
+        let deposit_lock = lock_of("deposit-address")?;
+        let withdraw_lock = lock_of("withdraw-address")?;
         Ok(Self {
-            account,
-            path,
-            tx_fee,
+            funding,
+            fee_policy,
+            change_cells,
             network_type,
+            send_args,
+            owner,
+            deposit_lock,
+            withdraw_lock,
+            coin_control: CoinControl::from_matches(m)?,
         })
     }
 
     fn args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
-        vec![
-            arg::privkey_path().required_unless(arg::from_account().b.name),
+        let mut args = vec![
+            arg::privkey_path().required_unless_one(&[
+                arg::from_account().b.name,
+                "from-multisig-address",
+                "signer-url",
+            ]),
             arg::from_account()
-                .required_unless(arg::privkey_path().b.name)
+                .required_unless_one(&[
+                    arg::privkey_path().b.name,
+                    "from-multisig-address",
+                    "signer-url",
+                ])
                 .conflicts_with(arg::privkey_path().b.name),
             arg::derivation_path().conflicts_with(arg::privkey_path().b.name),
-            arg::tx_fee().required(true),
+            arg::signer_url().conflicts_with_all(&[
+                arg::privkey_path().b.name,
+                arg::from_account().b.name,
+                arg::derivation_path().b.name,
+                "from-multisig-address",
+            ]),
+            Arg::with_name("from-multisig-address")
+                .long("from-multisig-address")
+                .takes_value(true)
+                .conflicts_with_all(&[arg::privkey_path().b.name, arg::from_account().b.name])
+                .requires("tx-file")
+                .validator(|input| AddressParser::default().validate(input))
+                .help("Use the cells of this multisig address (created by `account multisig-create`, without since), the unsigned transaction is saved to --tx-file to collect the signatures by `dao sign`"),
+            Arg::with_name("tx-file")
+                .long("tx-file")
+                .takes_value(true)
+                .requires("from-multisig-address")
+                .validator(|input| FilePathParser::new(false).validate(input))
+                .help("Save the unsigned multisig transaction to this file"),
+            arg::tx_fee().required_unless(arg::fee_rate().b.name),
+            arg::fee_rate().conflicts_with(arg::tx_fee().b.name),
+            arg::allow_high_fee(),
+            arg::on_failure(),
+            arg::change_cells(),
+            arg::memo(),
+            arg::index_backend(),
+            arg::indexer_url(),
+            arg::light_client_url(),
+        ];
+        args.extend(arg::wait_for_committed_args());
+        args
+    }
+
+    // The owner key of the DAO cells when it is not the funding account, eg: a
+    // cold key holding the principal while the fee is paid by a hot wallet
+    fn owner_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+        vec![
+            Arg::with_name("owner-privkey-path")
+                .long("owner-privkey-path")
+                .takes_value(true)
+                .conflicts_with("from-multisig-address")
+                .validator(|input| PrivkeyPathParser.validate(input))
+                .help("Private key file path of the DAO cells owner (only read first line), default: the funding account"),
+            Arg::with_name("owner-account")
+                .long("owner-account")
+                .takes_value(true)
+                .conflicts_with_all(&["owner-privkey-path", "from-multisig-address"])
+                .validator(|input| FromAccountParser.validate(input))
+                .help("The account owning the DAO cells (software key or ledger), default: the funding account"),
+            Arg::with_name("owner-path")
+                .long("owner-path")
+                .takes_value(true)
+                .requires("owner-account")
+                .validator(|input| DerivationPathParser.validate(input))
+                .help("The address path of --owner-account"),
         ]
     }
 }
@@ -2,40 +2,62 @@ use byteorder::{ByteOrder, LittleEndian};
 use either::Either;
 use itertools::Itertools;
 use std::collections::HashSet;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use self::builder::DAOBuilder;
-use self::command::TransactArgs;
-use crate::subcommands::account::AccountId;
+use self::builder::{dao_type_script, DAOBuilder, DAO_OUTPUT_DATA_LEN};
+use self::command::{Funding, MultisigFunding, TransactArgs};
+use crate::subcommands::{
+    account::AccountId,
+    tx::{read_tx_file, write_tx_file},
+};
+use crate::utils::arg_parser::PrivkeyWrapper;
+use crate::utils::broadcast::SendTargets;
 use crate::utils::index::IndexController;
+use crate::utils::index_backend::{index_backend_from_matches, CellQuery, IndexBackend};
 use crate::utils::key_adapter::KeyAdapter;
 use crate::utils::other::{
-    get_live_cell, get_max_mature_number, get_network_type, get_privkey_signer, is_mature,
-    read_password, serialize_signature_bytes,
+    get_live_cell, get_max_mature_number, get_privkey_signer, is_mature, read_password,
+    serialize_signature_bytes,
 };
+use crate::utils::policy::SigningPolicy;
 
 use ckb_crypto::secp::SECP256K1;
-use ckb_index::{with_index_db, IndexDatabase, LiveCellInfo};
+use ckb_index::LiveCellInfo;
 use ckb_jsonrpc_types::JsonBytes;
 use ckb_ledger::LedgerKeyStore;
 use ckb_sdk::{
-    constants::{MIN_SECP_CELL_CAPACITY, SIGHASH_TYPE_HASH},
+    build_signature, calculate_fee,
+    constants::{MIN_SECP_CELL_CAPACITY, MULTISIG_TYPE_HASH, SIGHASH_TYPE_HASH},
     rpc::Transaction,
-    wallet::{AbstractKeyStore, AbstractMasterPrivKey, FullyBoxedAbstractPrivkey, KeyStore},
-    Address, AddressPayload, BoxedSignerFn, GenesisInfo, HttpRpcClient,
+    tx_size_in_block,
+    wallet::{
+        AbstractKeyStore, AbstractMasterPrivKey, AbstractPrivKey, DerivationPath,
+        FullyBoxedAbstractPrivkey, KeyStore,
+    },
+    witness_lock_placeholder, Address, AddressPayload, BoxedSignerFn, FeePolicy, GenesisInfo,
+    HttpRpcClient, HumanCapacity, NetworkType, TxHelper, MAX_FEE_ITERATIONS,
 };
 use ckb_types::{
     bytes::Bytes,
-    core::{ScriptHashType, TransactionView},
-    packed::{self, Byte32, CellInput, CellOutput, OutPoint, Script, WitnessArgs},
+    core::{Capacity, HeaderView, ScriptHashType, TransactionView},
+    packed::{self, Byte32, CellOutput, OutPoint, Script, WitnessArgs},
     prelude::*,
     {H160, H256},
 };
+use clap::ArgMatches;
 
 mod builder;
 mod command;
 mod util;
 
+use util::split_by_size;
+pub(crate) use util::{dao_unlock_point, epoch_reached, withdraw_input_capacity};
+
+// The transactions of `--all` are split under this size, the block size limit
+// is 597,000 bytes
+const MAX_TX_SIZE: usize = 500_000;
+
 // Should CLI handle "immature header problem"?
 pub struct DAOSubCommand<'a> {
     rpc_client: &'a mut HttpRpcClient,
@@ -44,6 +66,9 @@ pub struct DAOSubCommand<'a> {
     genesis_info: GenesisInfo,
     index_dir: PathBuf,
     index_controller: IndexController,
+    index_backend: Option<Box<dyn IndexBackend>>,
+    signing_policy: SigningPolicy,
+    send_targets: SendTargets,
 }
 
 impl<'a> DAOSubCommand<'a> {
@@ -54,6 +79,8 @@ impl<'a> DAOSubCommand<'a> {
         genesis_info: GenesisInfo,
         index_dir: PathBuf,
         index_controller: IndexController,
+        signing_policy: SigningPolicy,
+        send_targets: SendTargets,
     ) -> Self {
         Self {
             rpc_client,
@@ -62,11 +89,14 @@ impl<'a> DAOSubCommand<'a> {
             genesis_info,
             index_dir,
             index_controller,
+            index_backend: None,
+            signing_policy,
+            send_targets,
         }
     }
 
-    pub fn query_deposit_cells(&mut self, lock_hash: Byte32) -> Result<Vec<LiveCellInfo>, String> {
-        let dao_cells = self.collect_dao_cells(lock_hash)?;
+    pub fn query_deposit_cells(&mut self, query: CellQuery) -> Result<Vec<LiveCellInfo>, String> {
+        let dao_cells = self.collect_dao_cells(query)?;
         assert!(dao_cells.iter().all(|cell| cell.data_bytes == 8));
         let mut ret = Vec::with_capacity(dao_cells.len());
         for cell in dao_cells {
@@ -77,8 +107,8 @@ impl<'a> DAOSubCommand<'a> {
         Ok(ret)
     }
 
-    pub fn query_prepare_cells(&mut self, lock_hash: Byte32) -> Result<Vec<LiveCellInfo>, String> {
-        let dao_cells = self.collect_dao_cells(lock_hash)?;
+    pub fn query_prepare_cells(&mut self, query: CellQuery) -> Result<Vec<LiveCellInfo>, String> {
+        let dao_cells = self.collect_dao_cells(query)?;
         assert!(dao_cells.iter().all(|cell| cell.data_bytes == 8));
         let mut ret = Vec::with_capacity(dao_cells.len());
         for cell in dao_cells {
@@ -89,46 +119,49 @@ impl<'a> DAOSubCommand<'a> {
         Ok(ret)
     }
 
-    fn collect_dao_cells(&mut self, lock_hash: Byte32) -> Result<Vec<LiveCellInfo>, String> {
-        let dao_type_hash = self.dao_type_hash().clone();
-        self.with_db(|db, _| {
-            let cells_by_lock = db
-                .get_live_cells_by_lock(lock_hash, Some(0), |_, _| (false, true))
-                .into_iter()
-                .collect::<HashSet<_>>();
-            let cells_by_code = db
-                .get_live_cells_by_code(dao_type_hash.clone(), Some(0), |_, _| (false, true))
-                .into_iter()
-                .collect::<HashSet<_>>();
-            cells_by_lock
-                .intersection(&cells_by_code)
-                .sorted_by_key(|live| (live.number, live.tx_index, live.index.output_index))
-                .cloned()
-                .collect::<Vec<_>>()
-        })
+    fn collect_dao_cells(&mut self, query: CellQuery) -> Result<Vec<LiveCellInfo>, String> {
+        let dao_type_hash: H256 = self.dao_type_hash().unpack();
+        let mut terminator = |_, cell: &LiveCellInfo| {
+            let is_dao = cell
+                .type_hashes
+                .as_ref()
+                .filter(|(code_hash, _)| code_hash == &dao_type_hash)
+                .is_some();
+            (false, is_dao)
+        };
+        let dao_cells = self
+            .index_backend()?
+            .get_live_cells(&query, Some(0), &mut terminator)?;
+        Ok(dao_cells
+            .into_iter()
+            .sorted_by_key(|live| (live.number, live.tx_index, live.index.output_index))
+            .collect())
     }
 
     fn check_db_ready(&mut self) -> Result<(), String> {
-        self.with_db(|_, _| ())
+        self.index_backend()?.last_number().map(|_| ())
     }
 
-    fn with_db<F, T>(&mut self, func: F) -> Result<T, String>
-    where
-        F: FnOnce(IndexDatabase, &mut HttpRpcClient) -> T,
-    {
-        let network_type = get_network_type(self.rpc_client)?;
-        let genesis_info = self.genesis_info.clone();
-        let genesis_hash: H256 = genesis_info.header().hash().unpack();
-        with_index_db(&self.index_dir.clone(), genesis_hash, |backend, cf| {
-            let db = IndexDatabase::from_db(backend, cf, network_type, genesis_info, false)?;
-            Ok(func(db, self.rpc_client()))
-        })
-        .map_err(|_err| {
-            format!(
-                "Index database may not ready, sync process: {}",
-                self.index_controller.state().read().to_string()
-            )
-        })
+    /// Select the index backend by `--index-backend` of the subcommand
+    pub(crate) fn select_index_backend(
+        &mut self,
+        m: &ArgMatches,
+        network_type: NetworkType,
+    ) -> Result<(), String> {
+        self.index_backend = Some(index_backend_from_matches(
+            m,
+            self.index_dir.clone(),
+            self.genesis_info.clone(),
+            network_type,
+            Arc::clone(self.index_controller.state()),
+        )?);
+        Ok(())
+    }
+
+    fn index_backend(&mut self) -> Result<&mut Box<dyn IndexBackend>, String> {
+        self.index_backend
+            .as_mut()
+            .ok_or_else(|| "Index backend is not selected".to_string())
     }
 
     fn dao_type_hash(&self) -> &Byte32 {
@@ -139,129 +172,429 @@ impl<'a> DAOSubCommand<'a> {
         &mut self.rpc_client
     }
 
+    /// Add the signature of the key to the multisig transaction file saved by
+    /// `--from-multisig-address`, return the count of signatures, the
+    /// threshold, and the transaction when the signatures are enough.
+    pub(crate) fn sign_tx_file(
+        &mut self,
+        tx_file: &Path,
+        account: &Either<PrivkeyWrapper, AccountId>,
+        path: &DerivationPath,
+        network_type: NetworkType,
+    ) -> Result<(usize, usize, Option<TransactionView>), String> {
+        let key = load_sighash_key(self, account, path, None)?;
+        if key.is_ledger {
+            return Err("Only keystore account can sign multisig transaction".to_string());
+        }
+        let tx_file = tx_file.to_path_buf();
+        let mut helper = read_tx_file(&tx_file)?;
+        let rpc_client = &mut *self.rpc_client;
+        let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
+            get_live_cell(rpc_client, out_point, with_data).map(|(output, _)| output)
+        };
+        self.signing_policy.check_transaction(
+            helper.transaction(),
+            network_type,
+            &mut get_live_cell_fn,
+        )?;
+        let lock_arg = H160::from_slice(key.address_payload.args().as_ref()).unwrap();
+        let signer: BoxedSignerFn = Box::new(KeyAdapter(get_privkey_signer(key.key_cap)?));
+        let result =
+            add_multisig_signatures(&mut helper, signer, &lock_arg, &mut get_live_cell_fn)?;
+        write_tx_file(&tx_file, helper, network_type)?;
+        Ok(result)
+    }
+
     fn with_transact_args<'b>(
         &'b mut self,
         transact_args: TransactArgs,
     ) -> Result<WithTransactArgs<'a, 'b>, String> {
-        WithTransactArgs::from_subcommand(self, transact_args)
+        WithTransactArgs::from_subcommand(self, transact_args, true)
+    }
+
+    /// A withdraw by the owner key spends no cell of the funding account (the
+    /// fee is paid by the withdrawn capacity), the funding key is not unlocked
+    fn with_withdraw_args<'b>(
+        &'b mut self,
+        transact_args: TransactArgs,
+    ) -> Result<WithTransactArgs<'a, 'b>, String> {
+        let funding_signs = transact_args.owner.is_none();
+        WithTransactArgs::from_subcommand(self, transact_args, funding_signs)
+    }
+}
+
+/// Add the signatures of the member key (of `lock_arg`) to the multisig
+/// transaction, return the count of signatures, the threshold, and the
+/// transaction when the signatures are enough.
+fn add_multisig_signatures<C>(
+    helper: &mut TxHelper,
+    signer: BoxedSignerFn,
+    lock_arg: &H160,
+    get_live_cell: &mut C,
+) -> Result<(usize, usize, Option<TransactionView>), String>
+where
+    C: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+{
+    let configs = helper
+        .multisig_configs()
+        .values()
+        .cloned()
+        .collect::<Vec<_>>();
+    if configs.len() != 1 {
+        return Err("Expect one multisig config in the transaction file".to_string());
+    }
+    let config = configs[0].clone();
+    if !config.sighash_lock_args().contains(lock_arg) {
+        return Err(format!(
+            "Key {:#x} is not a member of the multisig",
+            lock_arg
+        ));
+    }
+
+    let signatures = helper.sign_inputs(signer, get_live_cell, false, &DerivationPath::empty())?;
+    if signatures.is_empty() {
+        return Err("No input of the transaction is signed by the key".to_string());
+    }
+    for (multisig_lock_arg, signature) in signatures {
+        helper.add_signature(multisig_lock_arg, serialize_signature_bytes(&signature))?;
     }
+
+    let signatures_count = helper
+        .signatures()
+        .get(&Bytes::from(config.hash160().as_bytes().to_vec()))
+        .map(HashSet::len)
+        .unwrap_or(0);
+    let threshold = usize::from(config.threshold());
+    let transaction_opt = if signatures_count >= threshold {
+        Some(helper.build_tx(get_live_cell)?)
+    } else {
+        None
+    };
+    Ok((signatures_count, threshold, transaction_opt))
+}
+
+// A key signing the inputs locked by its sighash lock
+struct SighashKey {
+    address_payload: AddressPayload,
+    key_cap: FullyBoxedAbstractPrivkey<'static>,
+    is_ledger: bool,
 }
 
 struct WithTransactArgs<'a, 'b> {
     dao: &'b mut DAOSubCommand<'a>,
     transact_args: TransactArgs,
-    address_payload: AddressPayload,
-    key_cap: FullyBoxedAbstractPrivkey<'static>,
+    // The lock of the funding account (the multisig lock or the sighash lock
+    // of the funding key)
+    funding_payload: AddressPayload,
+    // The funding key when it signs, then the owner key of the DAO cells when
+    // it is given and different from the funding key, empty when funded by a
+    // multisig lock
+    keys: Vec<SighashKey>,
 }
 
 impl<'a, 'b> WithTransactArgs<'a, 'b> {
     fn from_subcommand(
         dao: &'b mut DAOSubCommand<'a>,
         transact_args: TransactArgs,
+        funding_signs: bool,
     ) -> Result<Self, String> {
-        let (address_payload, key_cap): (AddressPayload, FullyBoxedAbstractPrivkey) =
-            match transact_args.account {
-                Either::Left(ref from_privkey) => {
-                    let from_pubkey =
-                        secp256k1::PublicKey::from_secret_key(&SECP256K1, from_privkey);
-                    (
-                        AddressPayload::from_pubkey(&from_pubkey),
-                        Box::new(KeyAdapter(from_privkey.clone())),
-                    )
-                }
-                Either::Right(AccountId::SoftwareMasterKey(ref hash160)) => {
-                    let password = read_password(false, None)?;
-                    (
-                        AddressPayload::from_pubkey_hash(hash160.clone()),
-                        KeyAdapter(
-                            dao.key_store
-                                .get_key(&hash160, password.as_bytes())
-                                .map_err(|e| e.to_string())?,
-                        )
-                        .extended_privkey(transact_args.path.as_ref())?,
-                    )
-                }
-                Either::Right(AccountId::LedgerId(ref ledger_id)) => {
-                    let master = dao
-                        .ledger_key_store
-                        .borrow_account(&ledger_id)
-                        .map_err(|e| e.to_string())?
-                        .clone();
-                    let derived_priv = master
-                        .extended_privkey(transact_args.path.as_ref())
-                        .map_err(|e| e.to_string())?;
-                    let derived_pub = master
-                        .extended_pubkey(transact_args.path.as_ref())
-                        .map_err(|e| e.to_string())?;
-                    (
-                        AddressPayload::from_pubkey(&derived_pub.public_key),
-                        Box::new(KeyAdapter(derived_priv)),
-                    )
+        let mut keys = Vec::new();
+        let funding_payload = match transact_args.funding {
+            Funding::Key(ref account, ref path) if funding_signs => {
+                let key = load_sighash_key(dao, account, path, None)?;
+                let payload = key.address_payload.clone();
+                keys.push(key);
+                payload
+            }
+            Funding::Key(ref account, ref path) => {
+                sighash_address_payload(dao.ledger_key_store, account, path)?
+            }
+            Funding::Remote(ref remote_signer) => {
+                let payload = AddressPayload::from_pubkey(&remote_signer.public_key()?);
+                if funding_signs {
+                    keys.push(SighashKey {
+                        address_payload: payload.clone(),
+                        key_cap: Box::new(KeyAdapter(remote_signer.clone())),
+                        is_ledger: false,
+                    });
                 }
-            };
-        assert_eq!(address_payload.code_hash(), SIGHASH_TYPE_HASH.pack());
+                payload
+            }
+            Funding::Multisig(ref multisig) => multisig.payload.clone(),
+        };
+        if let Some((ref account, ref path)) = transact_args.owner {
+            let owner = load_sighash_key(dao, account, path, Some("Password of owner account"))?;
+            if keys
+                .first()
+                .map(|funding| funding.address_payload != owner.address_payload)
+                .unwrap_or(true)
+            {
+                keys.push(owner);
+            }
+        }
         Ok(Self {
             dao,
             transact_args,
-            address_payload,
-            key_cap,
+            funding_payload,
+            keys,
         })
     }
 
-    pub(crate) fn sighash_args(&self) -> H160 {
-        H160::from_slice(self.address_payload.args().as_ref()).unwrap()
+    fn multisig(&self) -> Option<&MultisigFunding> {
+        self.transact_args.funding.multisig()
     }
 
-    pub(crate) fn lock_hash(&self) -> Byte32 {
-        Script::from(&self.address_payload).calc_script_hash()
+    fn funding_payload(&self) -> &AddressPayload {
+        &self.funding_payload
+    }
+
+    // The lock owning the DAO cells to prepare or withdraw
+    fn owner_payload(&self) -> &AddressPayload {
+        match self.multisig() {
+            Some(multisig) => &multisig.payload,
+            None => {
+                &self
+                    .keys
+                    .last()
+                    .expect("funding key always exists")
+                    .address_payload
+            }
+        }
     }
 
-    pub fn deposit(&mut self, capacity: u64) -> Result<TransactionView, String> {
+    fn funding_lock(&self) -> Script {
+        match self.multisig() {
+            Some(multisig) => Script::from(&multisig.payload),
+            None => self.sighash_lock(self.funding_payload()),
+        }
+    }
+
+    pub(crate) fn lock_query(&self) -> CellQuery {
+        CellQuery::Lock(Script::from(self.funding_payload()))
+    }
+
+    pub(crate) fn owner_lock_query(&self) -> CellQuery {
+        CellQuery::Lock(Script::from(self.owner_payload()))
+    }
+
+    fn sighash_lock(&self, payload: &AddressPayload) -> Script {
+        Script::new_builder()
+            .hash_type(ScriptHashType::Type.into())
+            .code_hash(self.dao.genesis_info.sighash_type_hash().clone())
+            .args(payload.args().pack())
+            .build()
+    }
+
+    /// Deposit the capacities, one DAO cell for each
+    pub fn deposit(&mut self, capacities: Vec<u64>) -> Result<TransactionView, String> {
         self.dao.check_db_ready()?;
-        let target_capacity = capacity + self.transact_args.tx_fee;
-        let cells = self.collect_sighash_cells(target_capacity)?;
-        let raw_transaction = self.build(cells).deposit(capacity)?;
+        let change_lock = self.funding_lock();
+        let deposit_lock = self
+            .transact_args
+            .deposit_lock
+            .clone()
+            .unwrap_or_else(|| change_lock.clone());
+        let occupied = CellOutput::new_builder()
+            .lock(deposit_lock.clone())
+            .type_(Some(dao_type_script(&self.dao.genesis_info)?).pack())
+            .build()
+            .occupied_capacity(Capacity::bytes(DAO_OUTPUT_DATA_LEN).unwrap())
+            .map_err(|err| err.to_string())?
+            .as_u64();
+        if let Some(capacity) = capacities.iter().find(|capacity| **capacity < occupied) {
+            return Err(format!(
+                "Deposit cell capacity {} is less than the occupied capacity of a DAO cell: {}",
+                HumanCapacity(*capacity),
+                HumanCapacity(occupied)
+            ));
+        }
+        let capacity: u64 = capacities.iter().sum();
+        let raw_transaction = self.build_with_fee(|this, tx_fee| {
+            let cells = this.collect_sighash_cells(capacity + tx_fee)?;
+            this.build(cells, tx_fee, change_lock.clone())
+                .deposit(&capacities, deposit_lock.clone())
+        })?;
         self.sign(raw_transaction)
     }
 
     pub fn prepare(&mut self, out_points: Vec<OutPoint>) -> Result<TransactionView, String> {
         self.dao.check_db_ready()?;
-        let tx_fee = self.transact_args.tx_fee;
-        let lock_hash = self.lock_hash();
-        let cells = {
-            let mut to_pay_fee = self.collect_sighash_cells(tx_fee)?;
-            let mut to_prepare = {
-                let deposit_cells = self.dao.query_deposit_cells(lock_hash)?;
-                take_by_out_points(deposit_cells, &out_points)?
-            };
-            to_prepare.append(&mut to_pay_fee);
-            to_prepare
+        let lock_query = self.owner_lock_query();
+        let to_prepare = {
+            let deposit_cells = self.dao.query_deposit_cells(lock_query)?;
+            take_by_out_points(deposit_cells, &out_points)?
         };
-        let raw_transaction = self.build(cells).prepare(self.dao.rpc_client())?;
+        let raw_transaction = self.build_prepare(to_prepare)?;
         self.sign(raw_transaction)
     }
 
+    /// Prepare every deposited cell of the owner (at most `max_cells`), split
+    /// into transactions under `MAX_TX_SIZE`
+    pub fn prepare_all(
+        &mut self,
+        max_cells: Option<usize>,
+    ) -> Result<Vec<TransactionView>, String> {
+        self.dao.check_db_ready()?;
+        let lock_query = self.owner_lock_query();
+        let mut to_prepare = self.dao.query_deposit_cells(lock_query)?;
+        if let Some(max_cells) = max_cells {
+            to_prepare.truncate(max_cells);
+        }
+        if to_prepare.is_empty() {
+            return Err("No deposited cell to prepare".to_string());
+        }
+        let raw_transactions = self.build_split(to_prepare, Self::build_prepare)?;
+        raw_transactions
+            .into_iter()
+            .map(|transaction| self.sign(transaction))
+            .collect()
+    }
+
+    fn build_prepare(&mut self, to_prepare: Vec<LiveCellInfo>) -> Result<TransactionView, String> {
+        let change_lock = self.funding_lock();
+        self.build_with_fee(|this, tx_fee| {
+            let mut cells = to_prepare.clone();
+            cells.append(&mut this.collect_sighash_cells(tx_fee)?);
+            this.build(cells, tx_fee, change_lock.clone())
+                .prepare(this.dao.rpc_client())
+        })
+    }
+
     pub fn withdraw(&mut self, out_points: Vec<OutPoint>) -> Result<TransactionView, String> {
         self.dao.check_db_ready()?;
-        let lock_hash = self.lock_hash();
+        let lock_query = self.owner_lock_query();
         let cells = {
-            let prepare_cells = self.dao.query_prepare_cells(lock_hash)?;
+            let prepare_cells = self.dao.query_prepare_cells(lock_query)?;
             take_by_out_points(prepare_cells, &out_points)?
         };
-        let raw_transaction = self.build(cells).withdraw(self.dao.rpc_client())?;
+        let raw_transaction = self.build_withdraw(cells)?;
         self.sign(raw_transaction)
     }
 
+    /// Withdraw every prepared cell of the owner which is unlocked at the tip
+    /// (at most `max_cells`), split into transactions under `MAX_TX_SIZE`
+    pub fn withdraw_all(
+        &mut self,
+        max_cells: Option<usize>,
+    ) -> Result<Vec<TransactionView>, String> {
+        self.dao.check_db_ready()?;
+        let lock_query = self.owner_lock_query();
+        let prepare_cells = self.dao.query_prepare_cells(lock_query)?;
+        let tip_epoch = {
+            let tip_header: HeaderView = self.dao.rpc_client().get_tip_header()?.into();
+            tip_header.epoch()
+        };
+        let mut cells = Vec::new();
+        for cell in prepare_cells {
+            let unlock_point = dao_unlock_point(self.dao.rpc_client(), &cell.out_point())?
+                .ok_or_else(|| "invalid out_point, not a prepared cell".to_string())?;
+            if epoch_reached(tip_epoch, unlock_point) {
+                cells.push(cell);
+            }
+        }
+        if let Some(max_cells) = max_cells {
+            cells.truncate(max_cells);
+        }
+        if cells.is_empty() {
+            return Err("No prepared cell is withdrawable yet".to_string());
+        }
+        let raw_transactions = self.build_split(cells, Self::build_withdraw)?;
+        raw_transactions
+            .into_iter()
+            .map(|transaction| self.sign(transaction))
+            .collect()
+    }
+
+    fn build_withdraw(&mut self, cells: Vec<LiveCellInfo>) -> Result<TransactionView, String> {
+        let withdraw_lock = self
+            .transact_args
+            .withdraw_lock
+            .clone()
+            .unwrap_or_else(|| self.funding_lock());
+        self.build_with_fee(|this, tx_fee| {
+            this.build(cells.clone(), tx_fee, withdraw_lock.clone())
+                .withdraw(this.dao.rpc_client())
+        })
+    }
+
+    /// Build the unsigned transactions of the DAO cells by `build_fn`, the
+    /// cells are split into as few transactions as possible under
+    /// `MAX_TX_SIZE`.
+    fn build_split<F>(
+        &mut self,
+        cells: Vec<LiveCellInfo>,
+        mut build_fn: F,
+    ) -> Result<Vec<TransactionView>, String>
+    where
+        F: FnMut(&mut Self, Vec<LiveCellInfo>) -> Result<TransactionView, String>,
+    {
+        split_by_size(
+            self,
+            cells,
+            MAX_TX_SIZE,
+            |builder, cells| {
+                let transaction = build_fn(builder, cells)?;
+                let size =
+                    tx_size_in_block(&builder.install_placeholder_witness(
+                        builder.install_lock_dep(transaction.clone()),
+                    )?);
+                Ok((transaction, size))
+            },
+            // The transactions are not committed yet, the fee cells of one
+            // can't pay another
+            |builder, transaction| {
+                for input in transaction.inputs().into_iter() {
+                    builder
+                        .transact_args
+                        .coin_control
+                        .exclude(input.previous_output());
+                }
+            },
+        )
+    }
+
+    /// Build the unsigned transaction by `build_fn` with a fee, when paying by
+    /// fee rate, the fee is re-calculated from the transaction size until it
+    /// converges.
+    fn build_with_fee<F>(&mut self, mut build_fn: F) -> Result<TransactionView, String>
+    where
+        F: FnMut(&mut Self, u64) -> Result<TransactionView, String>,
+    {
+        let fee_rate = match self.transact_args.fee_policy {
+            FeePolicy::Fixed(tx_fee) => return build_fn(self, tx_fee),
+            FeePolicy::Rate(fee_rate) => fee_rate,
+        };
+        let mut tx_fee = 0;
+        for _ in 0..MAX_FEE_ITERATIONS {
+            let transaction = build_fn(self, tx_fee)?;
+            let placeholder =
+                self.install_placeholder_witness(self.install_lock_dep(transaction.clone()))?;
+            let required_fee = calculate_fee(fee_rate, tx_size_in_block(&placeholder));
+            if required_fee <= tx_fee {
+                return Ok(transaction);
+            }
+            tx_fee = required_fee;
+        }
+        Err(format!(
+            "Transaction fee not converged by fee rate {} shannons/KB",
+            fee_rate
+        ))
+    }
+
     fn collect_sighash_cells(&mut self, target_capacity: u64) -> Result<Vec<LiveCellInfo>, String> {
         let mut enough = false;
         let mut take_capacity = 0;
         let max_mature_number = get_max_mature_number(self.dao.rpc_client())?;
-        let terminator = |_, cell: &LiveCellInfo| {
+        let coin_control = &self.transact_args.coin_control;
+        let mut terminator = |_, cell: &LiveCellInfo| {
             if !(cell.type_hashes.is_none() && cell.data_bytes == 0)
                 && is_mature(cell, max_mature_number)
             {
                 return (false, false);
             }
+            if !coin_control.allows(&cell.out_point()) {
+                return (false, false);
+            }
 
             take_capacity += cell.capacity;
             if take_capacity == target_capacity
@@ -273,181 +606,336 @@ impl<'a, 'b> WithTransactArgs<'a, 'b> {
         };
 
         let cells: Vec<LiveCellInfo> = {
-            let lock_hash = self.lock_hash();
+            let lock_query = self.lock_query();
             self.dao
-                .with_db(|db, _| db.get_live_cells_by_lock(lock_hash, None, terminator))?
+                .index_backend()?
+                .get_live_cells(&lock_query, None, &mut terminator)?
         };
 
         if !enough {
-            return Err(format!(
-                "Capacity not enough: {} => {}",
-                Address::new(
-                    self.transact_args.network_type,
-                    self.address_payload.clone()
-                ),
-                take_capacity,
+            let from = Address::new(
+                self.transact_args.network_type,
+                self.funding_payload().clone(),
+            )
+            .to_string();
+            let lock_query = self.lock_query();
+            let candidates =
+                self.dao
+                    .index_backend()?
+                    .get_live_cells(&lock_query, None, &mut |_, cell| {
+                        (
+                            false,
+                            cell.type_hashes.is_none()
+                                && cell.data_bytes == 0
+                                && is_mature(cell, max_mature_number),
+                        )
+                    })?;
+            return Err(self.transact_args.coin_control.not_enough_error(
+                &from,
+                target_capacity,
+                &cells,
+                &candidates,
             ));
         }
         Ok(cells)
     }
 
-    fn build(&self, cells: Vec<LiveCellInfo>) -> DAOBuilder {
-        let tx_fee = self.transact_args.tx_fee;
-        DAOBuilder::new(self.dao.genesis_info.clone(), tx_fee, cells)
+    fn build(&self, cells: Vec<LiveCellInfo>, tx_fee: u64, change_lock: Script) -> DAOBuilder {
+        DAOBuilder::new(
+            self.dao.genesis_info.clone(),
+            tx_fee,
+            self.transact_args.change_cells,
+            cells,
+            change_lock,
+        )
+    }
+
+    /// Group the inputs by the signing keys, return the index of the key and
+    /// the indexes of its inputs. Every input must be locked by one of the
+    /// keys.
+    fn input_groups(
+        &mut self,
+        transaction: &TransactionView,
+    ) -> Result<Vec<(usize, Vec<usize>)>, String> {
+        let locks = self
+            .keys
+            .iter()
+            .map(|key| self.sighash_lock(&key.address_payload))
+            .collect::<Vec<_>>();
+        let mut groups: Vec<(usize, Vec<usize>)> = Vec::new();
+        for (input_idx, input) in transaction.inputs().into_iter().enumerate() {
+            let ((output, _), _) =
+                get_live_cell(self.dao.rpc_client, input.previous_output(), false)?;
+            let key_idx = locks
+                .iter()
+                .position(|lock| lock.as_slice() == output.lock().as_slice())
+                .ok_or_else(|| {
+                    format!(
+                        "Input #{} is not locked by the funding or owner key: {}",
+                        input_idx,
+                        output.lock()
+                    )
+                })?;
+            match groups.iter_mut().find(|(idx, _)| *idx == key_idx) {
+                Some((_, idxs)) => idxs.push(input_idx),
+                None => groups.push((key_idx, vec![input_idx])),
+            }
+        }
+        Ok(groups)
+    }
+
+    // The first witness of every input group with a signature size lock, only
+    // for size estimation
+    fn install_placeholder_witness(
+        &mut self,
+        transaction: TransactionView,
+    ) -> Result<TransactionView, String> {
+        let mut witnesses = transaction.witnesses().into_iter().collect::<Vec<_>>();
+        if let Some(multisig) = self.multisig() {
+            // All the inputs are locked by the multisig lock
+            let lock = witness_lock_placeholder(&MULTISIG_TYPE_HASH, Some(&multisig.config), false)
+                .ok_or_else(|| "No witness placeholder of the multisig lock".to_string())?;
+            witnesses[0] = with_witness_lock(&witnesses[0], &lock)?;
+        } else {
+            let lock = witness_lock_placeholder(&SIGHASH_TYPE_HASH, None, false)
+                .ok_or_else(|| "No witness placeholder of the sighash lock".to_string())?;
+            for (_, idxs) in self.input_groups(&transaction)? {
+                witnesses[idxs[0]] = with_witness_lock(&witnesses[idxs[0]], &lock)?;
+            }
+        }
+        Ok(transaction
+            .as_advanced_builder()
+            .set_witnesses(witnesses)
+            .build())
     }
 
+    // The multisig transaction is not signed here, the signatures are
+    // collected by `dao sign`
     fn sign(&mut self, transaction: TransactionView) -> Result<TransactionView, String> {
-        // 1. Install sighash lock script
-        let transaction = self.install_sighash_lock(transaction);
+        // 1. Install sighash (or multisig) cell dep
+        let transaction = self.install_lock_dep(transaction);
+
+        // 2. Check by the signing policy manifest
+        let network_type = self.transact_args.network_type;
+        let dao = &mut *self.dao;
+        let rpc_client = &mut *dao.rpc_client;
+        let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
+            get_live_cell(rpc_client, out_point, with_data).map(|(output, _)| output)
+        };
+        dao.signing_policy
+            .check_transaction(&transaction, network_type, &mut get_live_cell_fn)?;
 
-        // 2. Install signed sighash witnesses
+        if self.multisig().is_some() {
+            return Ok(transaction);
+        }
+
+        // 3. Install signed sighash witnesses of every input group
         let transaction = self.install_sighash_witness(transaction)?;
 
         Ok(transaction)
     }
 
-    fn install_sighash_lock(&self, transaction: TransactionView) -> TransactionView {
-        let sighash_args = self.sighash_args();
-        let genesis_info = &self.dao.genesis_info;
-        let sighash_dep = genesis_info.sighash_dep();
-        let sighash_type_hash = genesis_info.sighash_type_hash();
-        let lock_script = Script::new_builder()
-            .hash_type(ScriptHashType::Type.into())
-            .code_hash(sighash_type_hash.clone())
-            .args(Bytes::from(sighash_args.as_bytes()).pack())
-            .build();
-        let outputs = transaction
-            .outputs()
-            .into_iter()
-            .map(|output: CellOutput| output.as_builder().lock(lock_script.clone()).build())
-            .collect::<Vec<_>>();
-        transaction
-            .as_advanced_builder()
-            .set_outputs(outputs)
-            .cell_dep(sighash_dep)
-            .build()
+    fn install_lock_dep(&self, transaction: TransactionView) -> TransactionView {
+        let lock_dep = if self.multisig().is_some() {
+            self.dao.genesis_info.multisig_dep()
+        } else {
+            self.dao.genesis_info.sighash_dep()
+        };
+        transaction.as_advanced_builder().cell_dep(lock_dep).build()
     }
 
     fn install_sighash_witness(
         &mut self,
         transaction: TransactionView,
     ) -> Result<TransactionView, String> {
-        for output in transaction.outputs() {
-            assert_eq!(output.lock().hash_type(), ScriptHashType::Type.into());
-            assert_eq!(output.lock().args().len(), 20);
-            assert_eq!(output.lock().code_hash(), SIGHASH_TYPE_HASH.pack());
-        }
         for witness in transaction.witnesses() {
-            if let Ok(w) = WitnessArgs::from_slice(witness.as_slice()) {
+            if let Ok(w) = WitnessArgs::from_slice(&witness.raw_data()) {
                 assert!(w.lock().is_none());
             }
         }
 
-        let mut witnesses = transaction
-            .witnesses()
-            .into_iter()
-            .map(|w| w.unpack())
-            .collect::<Vec<Bytes>>();
-        let init_witness = {
-            let init_witness = if witnesses[0].is_empty() {
-                WitnessArgs::default()
+        let groups = self.input_groups(&transaction)?;
+        let unsigned_witnesses = transaction.witnesses().into_iter().collect::<Vec<_>>();
+        let mut witnesses = unsigned_witnesses.clone();
+        for (key_idx, idxs) in groups {
+            let key = &self.keys[key_idx];
+            let mut single_signer = {
+                let account = H160::from_slice(key.address_payload.args().as_ref()).unwrap();
+                let mut signer: BoxedSignerFn =
+                    Box::new(KeyAdapter(get_privkey_signer(key.key_cap.clone())?));
+                let accounts = vec![account].into_iter().collect::<HashSet<H160>>();
+                signer
+                    .new_signature_builder(&accounts)?
+                    .expect("signer missed")
+            };
+
+            let signature = if key.is_ledger {
+                let annotated_tx =
+                    annotated_transaction(self.dao.rpc_client, &transaction, &unsigned_witnesses)?;
+                single_signer.append(annotated_tx.as_slice());
+                single_signer.finalize()?
             } else {
-                WitnessArgs::from_slice(&witnesses[0]).map_err(|err| err.to_string())?
+                build_signature(
+                    &transaction.hash(),
+                    &idxs,
+                    &unsigned_witnesses,
+                    None,
+                    single_signer,
+                )?
             };
-            init_witness
-                .as_builder()
-                .lock(Some(Bytes::from(&[0u8; 65][..])).pack())
-                .build()
-        };
+            witnesses[idxs[0]] = with_witness_lock(
+                &unsigned_witnesses[idxs[0]],
+                &serialize_signature_bytes(&signature),
+            )?;
+        }
 
-        let mut single_signer = {
-            let account = self.sighash_args();
-            let mut signer: BoxedSignerFn =
-                Box::new(KeyAdapter(get_privkey_signer(self.key_cap.clone())?));
-            let accounts = vec![account].into_iter().collect::<HashSet<H160>>();
-            signer
-                .new_signature_builder(&accounts)?
-                .expect("signer missed")
-        };
+        Ok(transaction
+            .as_advanced_builder()
+            .set_witnesses(witnesses)
+            .build())
+    }
+}
 
-        let is_ledger = match self.transact_args.account {
-            Either::Left(_) => false,
-            Either::Right(AccountId::SoftwareMasterKey(_)) => false,
-            Either::Right(AccountId::LedgerId(_)) => true,
+fn load_sighash_key(
+    dao: &mut DAOSubCommand,
+    account: &Either<PrivkeyWrapper, AccountId>,
+    path: &DerivationPath,
+    password_prompt: Option<&str>,
+) -> Result<SighashKey, String> {
+    if let Either::Right(account_id) = account {
+        let is_ledger = match account_id {
+            AccountId::SoftwareMasterKey(_) => false,
+            AccountId::LedgerId(_) => true,
         };
-
-        let signature = if is_ledger {
-            let input_transactions: Vec<(Transaction, CellInput)> = {
-                let mut txs = Vec::new();
-                for (_idx, input) in transaction.inputs().into_iter().enumerate() {
-                    let ((_cell_output, cell_transaction), _) =
-                        get_live_cell(self.dao.rpc_client, input.previous_output(), false)?;
-                    txs.push((cell_transaction, input));
-                }
-                txs
-            };
-
-            let input_count_bytes = input_transactions.len().to_le_bytes();
-            let input_count = packed::Uint32::new_builder()
-                .nth0(input_count_bytes[0].into())
-                .nth1(input_count_bytes[1].into())
-                .nth2(input_count_bytes[2].into())
-                .nth3(input_count_bytes[3].into())
-                .build();
-
-            let mut inputs = Vec::new();
-
-            for (input_transaction, input) in input_transactions.into_iter() {
-                inputs.push(
-                    packed::AnnotatedCellInput::new_builder()
-                        .input(input)
-                        .source(packed::Transaction::from(input_transaction.clone()).raw())
-                        .build(),
-                );
+        dao.signing_policy.check_path(path, is_ledger)?;
+    }
+    let key = match account {
+        Either::Left(privkey) => {
+            let pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, privkey);
+            SighashKey {
+                address_payload: AddressPayload::from_pubkey(&pubkey),
+                key_cap: Box::new(KeyAdapter(privkey.clone())),
+                is_ledger: false,
             }
-            let raw_tx = packed::AnnotatedRawTransaction::new_builder()
-                .version(transaction.version().pack())
-                .cell_deps(transaction.cell_deps())
-                .header_deps(transaction.header_deps())
-                .inputs(
-                    packed::AnnotatedCellInputVec::new_builder()
-                        .set(inputs)
-                        .build(),
+        }
+        Either::Right(AccountId::SoftwareMasterKey(hash160)) => {
+            let password = read_password(false, password_prompt)?;
+            SighashKey {
+                address_payload: AddressPayload::from_pubkey_hash(hash160.clone()),
+                key_cap: KeyAdapter(
+                    dao.key_store
+                        .get_key(hash160, password.as_bytes())
+                        .map_err(|e| e.to_string())?,
                 )
-                .outputs(transaction.outputs())
-                .outputs_data(transaction.outputs_data())
-                .build();
-            single_signer.append(
-                packed::AnnotatedTransaction::new_builder()
-                    .input_count(input_count)
-                    .raw(raw_tx)
-                    .witnesses(witnesses.clone().pack())
-                    .build()
-                    .as_slice(),
-            );
-            single_signer.finalize()?
-        } else {
-            single_signer.append(&transaction.hash().raw_data());
-            single_signer.append(&(init_witness.as_bytes().len() as u64).to_le_bytes());
-            single_signer.append(&init_witness.as_bytes());
-            for other_witness in witnesses.iter().skip(1) {
-                single_signer.append(&(other_witness.len() as u64).to_le_bytes());
-                single_signer.append(&other_witness);
+                .extended_privkey(path.as_ref())?,
+                is_ledger: false,
             }
-            single_signer.finalize()?
-        };
+        }
+        Either::Right(AccountId::LedgerId(ledger_id)) => {
+            let master = dao
+                .ledger_key_store
+                .borrow_account(ledger_id)
+                .map_err(|e| e.to_string())?
+                .clone();
+            let derived_priv = master
+                .extended_privkey(path.as_ref())
+                .map_err(|e| e.to_string())?;
+            let derived_pub = master
+                .extended_pubkey(path.as_ref())
+                .map_err(|e| e.to_string())?;
+            SighashKey {
+                address_payload: AddressPayload::from_pubkey(&derived_pub.public_key),
+                key_cap: Box::new(KeyAdapter(derived_priv)),
+                is_ledger: true,
+            }
+        }
+    };
+    assert_eq!(key.address_payload.code_hash(), SIGHASH_TYPE_HASH.pack());
+    Ok(key)
+}
 
-        witnesses[0] = init_witness
-            .as_builder()
-            .lock(Some(serialize_signature_bytes(&signature)).pack())
-            .build()
-            .as_bytes();
+// The sighash address of the account without unlocking the key, the key
+// store account is addressed by its lock arg as in `load_sighash_key`
+fn sighash_address_payload(
+    ledger_key_store: &mut LedgerKeyStore,
+    account: &Either<PrivkeyWrapper, AccountId>,
+    path: &DerivationPath,
+) -> Result<AddressPayload, String> {
+    match account {
+        Either::Left(privkey) => Ok(AddressPayload::from_pubkey(
+            &secp256k1::PublicKey::from_secret_key(&SECP256K1, privkey),
+        )),
+        Either::Right(AccountId::SoftwareMasterKey(hash160)) => {
+            Ok(AddressPayload::from_pubkey_hash(hash160.clone()))
+        }
+        Either::Right(AccountId::LedgerId(ledger_id)) => {
+            let derived_pub = ledger_key_store
+                .borrow_account(ledger_id)
+                .map_err(|e| e.to_string())?
+                .extended_pubkey(path.as_ref())
+                .map_err(|e| e.to_string())?;
+            Ok(AddressPayload::from_pubkey(&derived_pub.public_key))
+        }
+    }
+}
 
-        Ok(transaction
-            .as_advanced_builder()
-            .set_witnesses(witnesses.into_iter().map(|w| w.pack()).collect::<Vec<_>>())
-            .build())
+// Set the lock field of the witness (empty witness as the default `WitnessArgs`)
+fn with_witness_lock(witness: &packed::Bytes, lock: &[u8]) -> Result<packed::Bytes, String> {
+    let witness_args = if witness.raw_data().is_empty() {
+        WitnessArgs::default()
+    } else {
+        WitnessArgs::from_slice(&witness.raw_data()).map_err(|err| err.to_string())?
+    };
+    Ok(witness_args
+        .as_builder()
+        .lock(Some(Bytes::from(lock.to_vec())).pack())
+        .build()
+        .as_bytes()
+        .pack())
+}
+
+// The transaction with the source transactions of inputs, signed by the ledger
+fn annotated_transaction(
+    rpc_client: &mut HttpRpcClient,
+    transaction: &TransactionView,
+    witnesses: &[packed::Bytes],
+) -> Result<packed::AnnotatedTransaction, String> {
+    let mut inputs = Vec::new();
+    for input in transaction.inputs().into_iter() {
+        let ((_cell_output, cell_transaction), _) =
+            get_live_cell(rpc_client, input.previous_output(), false)?;
+        inputs.push(
+            packed::AnnotatedCellInput::new_builder()
+                .input(input)
+                .source(packed::Transaction::from(cell_transaction).raw())
+                .build(),
+        );
     }
+
+    let input_count_bytes = inputs.len().to_le_bytes();
+    let input_count = packed::Uint32::new_builder()
+        .nth0(input_count_bytes[0].into())
+        .nth1(input_count_bytes[1].into())
+        .nth2(input_count_bytes[2].into())
+        .nth3(input_count_bytes[3].into())
+        .build();
+    let raw_tx = packed::AnnotatedRawTransaction::new_builder()
+        .version(transaction.version().pack())
+        .cell_deps(transaction.cell_deps())
+        .header_deps(transaction.header_deps())
+        .inputs(
+            packed::AnnotatedCellInputVec::new_builder()
+                .set(inputs)
+                .build(),
+        )
+        .outputs(transaction.outputs())
+        .outputs_data(transaction.outputs_data())
+        .build();
+    Ok(packed::AnnotatedTransaction::new_builder()
+        .input_count(input_count)
+        .raw(raw_tx)
+        .witnesses(witnesses.to_vec().pack())
+        .build())
 }
 
 fn take_by_out_points(
@@ -491,3 +979,114 @@ fn get_cell_data(
         .ok_or_else(|| format!("cell is not found: {:?}", dao_cell.out_point()))?;
     Ok(cell_info.data.unwrap().content)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_sdk::{wallet::ScryptType, MultisigConfig};
+
+    #[test]
+    fn test_add_multisig_signatures() {
+        let privkeys = (1..4u8)
+            .map(|i| PrivkeyWrapper(secp256k1::SecretKey::from_slice(&[i; 32]).unwrap()))
+            .collect::<Vec<_>>();
+        let payloads = privkeys
+            .iter()
+            .map(|privkey| {
+                AddressPayload::from_pubkey(&secp256k1::PublicKey::from_secret_key(
+                    &SECP256K1, privkey,
+                ))
+            })
+            .collect::<Vec<_>>();
+        let config = MultisigConfig::new_with(payloads.clone(), 0, 2).unwrap();
+        let lock = Script::new_builder()
+            .code_hash(MULTISIG_TYPE_HASH.pack())
+            .hash_type(ScriptHashType::Type.into())
+            .args(Bytes::from(config.hash160().as_bytes().to_vec()).pack())
+            .build();
+        let out_point = OutPoint::new(Default::default(), 0);
+        let mut get_live_cell = |_: OutPoint,
+                                 _: bool|
+         -> Result<(CellOutput, Transaction), String> {
+            let output = CellOutput::new_builder().lock(lock.clone()).build();
+            let source_tx = ckb_jsonrpc_types::Transaction::from(packed::Transaction::default());
+            Ok((output, Transaction::from(source_tx)))
+        };
+        let transaction = TransactionView::new_advanced_builder()
+            .input(packed::CellInput::new(out_point, 0))
+            .output(CellOutput::default())
+            .output_data(Bytes::new().pack())
+            .build();
+        let mut helper = TxHelper::new(transaction);
+        helper.add_multisig_config(config.clone());
+        let sign = |helper: &mut TxHelper, idx: usize, get_live_cell: &mut _| {
+            let signer: BoxedSignerFn = Box::new(KeyAdapter(
+                get_privkey_signer(privkeys[idx].clone()).unwrap(),
+            ));
+            let lock_arg = H160::from_slice(payloads[idx].args().as_ref()).unwrap();
+            add_multisig_signatures(helper, signer, &lock_arg, get_live_cell)
+        };
+
+        let (count, threshold, transaction) = sign(&mut helper, 0, &mut get_live_cell).unwrap();
+        assert_eq!((count, threshold), (1, 2));
+        assert!(transaction.is_none());
+        // Signed again by the same member
+        let (count, _, transaction) = sign(&mut helper, 0, &mut get_live_cell).unwrap();
+        assert_eq!(count, 1);
+        assert!(transaction.is_none());
+        let (count, _, transaction) = sign(&mut helper, 2, &mut get_live_cell).unwrap();
+        assert_eq!(count, 2);
+
+        // The signed witness lock has the size of the placeholder the fee is
+        // calculated by
+        let transaction = transaction.unwrap();
+        let witness =
+            WitnessArgs::from_slice(&transaction.witnesses().get(0).unwrap().raw_data()).unwrap();
+        let placeholder = witness_lock_placeholder(&MULTISIG_TYPE_HASH, Some(&config), false);
+        assert_eq!(
+            witness.lock().to_opt().map(|lock| lock.raw_data().len()),
+            placeholder.map(|lock| lock.len())
+        );
+
+        // Not a member
+        let other = PrivkeyWrapper(secp256k1::SecretKey::from_slice(&[9; 32]).unwrap());
+        let other_lock_arg = H160::from_slice(
+            AddressPayload::from_pubkey(&secp256k1::PublicKey::from_secret_key(&SECP256K1, &other))
+                .args()
+                .as_ref(),
+        )
+        .unwrap();
+        let signer: BoxedSignerFn = Box::new(KeyAdapter(get_privkey_signer(other).unwrap()));
+        assert!(
+            add_multisig_signatures(&mut helper, signer, &other_lock_arg, &mut get_live_cell)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_sighash_address_payload() {
+        let dir = std::env::temp_dir().join(format!("ckb-cli-dao-ledger-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut ledger_key_store = LedgerKeyStore::from_dir(dir.clone(), ScryptType::default())
+            .map_err(|err| err.to_string())
+            .unwrap();
+        let path = DerivationPath::empty();
+
+        let privkey = PrivkeyWrapper(secp256k1::SecretKey::from_slice(&[1; 32]).unwrap());
+        let payload = AddressPayload::from_pubkey(&secp256k1::PublicKey::from_secret_key(
+            &SECP256K1, &privkey,
+        ));
+        assert_eq!(
+            sighash_address_payload(&mut ledger_key_store, &Either::Left(privkey), &path).unwrap(),
+            payload
+        );
+        // The key store account is not unlocked (not even in the key store)
+        let lock_arg = H160::from_slice(payload.args().as_ref()).unwrap();
+        let account = Either::Right(AccountId::SoftwareMasterKey(lock_arg));
+        assert_eq!(
+            sighash_address_payload(&mut ledger_key_store, &account, &path).unwrap(),
+            payload
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
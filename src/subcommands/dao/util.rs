@@ -1,18 +1,26 @@
 use crate::utils::{
+    broadcast::SendTargets,
+    on_failure::{self, OnFailure},
     other::check_lack_of_capacity,
     printer::{OutputFormat, Printable},
+    tx_record::save_tx_memo,
+    tx_status::WaitForCommitted,
 };
 use ckb_dao_utils::extract_dao_data;
 use ckb_index::LiveCellInfo;
-use ckb_sdk::HttpRpcClient;
+use ckb_sdk::{constants::ONE_CKB, HttpRpcClient, HumanCapacity};
 use ckb_types::core::{Capacity, TransactionView};
 use ckb_types::packed::CellOutput;
 use ckb_types::{
-    core::{EpochNumber, EpochNumberWithFraction, HeaderView},
+    core::{EpochNumber, EpochNumberWithFraction, HeaderView, ScriptHashType},
     packed,
     prelude::*,
+    H256,
 };
 
+// The deposit cells of one transaction, far below the block size limit
+pub(crate) const MAX_DEPOSIT_CELLS: usize = 1000;
+
 pub(crate) fn calculate_dao_maximum_withdraw(
     rpc_client: &mut HttpRpcClient,
     prepare_cell: &LiveCellInfo,
@@ -76,6 +84,245 @@ pub(crate) fn calculate_dao_maximum_withdraw(
     ))
 }
 
+/// The compensation (interest) accrued by a NervosDAO cell, a deposited cell
+/// accrues until the tip block, a prepared cell accrues until the block it was
+/// prepared in.
+pub(crate) struct DaoCompensation {
+    pub(crate) deposit_block_number: u64,
+    pub(crate) end_block_number: u64,
+    pub(crate) capacity: u64,
+    pub(crate) maximum_withdraw: u64,
+    pub(crate) prepared: bool,
+    // The epoch since which the cell can be withdrawn, for a deposited cell it
+    // is the earliest one when prepared in the tip block
+    pub(crate) unlock_point: EpochNumberWithFraction,
+}
+
+impl DaoCompensation {
+    pub(crate) fn compensation(&self) -> u64 {
+        self.maximum_withdraw.saturating_sub(self.capacity)
+    }
+}
+
+pub(crate) fn calculate_dao_compensation(
+    rpc_client: &mut HttpRpcClient,
+    out_point: &packed::OutPoint,
+    dao_type_hash: &packed::Byte32,
+    tip_header: &HeaderView,
+) -> Result<DaoCompensation, String> {
+    let (tx, block_hash) = get_committed_transaction(rpc_client, out_point.tx_hash().unpack())?;
+    let index: u32 = out_point.index().unpack();
+    let (output, output_data) = tx
+        .output_with_data(index as usize)
+        .ok_or_else(|| "invalid out_point, the cell is not found".to_string())?;
+    let is_dao_cell = output
+        .type_()
+        .to_opt()
+        .map(|script| {
+            &script.code_hash() == dao_type_hash
+                && script.hash_type() == ScriptHashType::Type.into()
+        })
+        .unwrap_or(false);
+    if !is_dao_cell || output_data.len() != 8 {
+        return Err("invalid out_point, not a NervosDAO cell".to_string());
+    }
+    let header: HeaderView = rpc_client
+        .get_header(block_hash)?
+        .ok_or_else(|| "failed to get the header of out_point".to_string())?
+        .into();
+
+    let prepared = output_data.iter().any(|byte| *byte != 0);
+    let (deposit_header, end_header, deposit_output, deposit_output_data) = if !prepared {
+        (header, tip_header.clone(), output, output_data)
+    } else {
+        let deposit_out_point = tx
+            .inputs()
+            .get(index as usize)
+            .ok_or_else(|| "invalid prepare tx".to_string())?
+            .previous_output();
+        let (deposit_tx, deposit_block_hash) =
+            get_committed_transaction(rpc_client, deposit_out_point.tx_hash().unpack())?;
+        let (deposit_output, deposit_output_data) = deposit_tx
+            .output_with_data(deposit_out_point.index().unpack())
+            .ok_or_else(|| "invalid deposit out_point, the cell is not found".to_string())?;
+        let deposit_header: HeaderView = rpc_client
+            .get_header(deposit_block_hash)?
+            .ok_or_else(|| "failed to get deposit_header".to_string())?
+            .into();
+        (deposit_header, header, deposit_output, deposit_output_data)
+    };
+
+    // NOTE: It is safe to use `unwrap` for the data we fetch from ckb node.
+    let occupied_capacity = deposit_output
+        .occupied_capacity(Capacity::bytes(deposit_output_data.len()).unwrap())
+        .unwrap();
+    let capacity: Capacity = deposit_output.capacity().unpack();
+    Ok(DaoCompensation {
+        deposit_block_number: deposit_header.number(),
+        end_block_number: end_header.number(),
+        capacity: capacity.as_u64(),
+        maximum_withdraw: calculate_dao_maximum_withdraw4(
+            &deposit_header,
+            &end_header,
+            &deposit_output,
+            occupied_capacity.as_u64(),
+        ),
+        prepared,
+        unlock_point: minimal_unlock_point(&deposit_header, &end_header),
+    })
+}
+
+/// The epoch since which a prepared NervosDAO cell can be withdrawn, `None`
+/// for a deposited cell (it must be prepared first).
+pub(crate) fn dao_unlock_point(
+    rpc_client: &mut HttpRpcClient,
+    out_point: &packed::OutPoint,
+) -> Result<Option<EpochNumberWithFraction>, String> {
+    let (tx, block_hash) = get_committed_transaction(rpc_client, out_point.tx_hash().unpack())?;
+    let index: u32 = out_point.index().unpack();
+    let (output, output_data) = tx
+        .output_with_data(index as usize)
+        .ok_or_else(|| "invalid out_point, the cell is not found".to_string())?;
+    if output.type_().to_opt().is_none() || output_data.len() != 8 {
+        return Err("invalid out_point, not a NervosDAO cell".to_string());
+    }
+    if output_data.iter().all(|byte| *byte == 0) {
+        return Ok(None);
+    }
+    let deposit_out_point = tx
+        .inputs()
+        .get(index as usize)
+        .ok_or_else(|| "invalid prepare tx".to_string())?
+        .previous_output();
+    let (_, deposit_block_hash) =
+        get_committed_transaction(rpc_client, deposit_out_point.tx_hash().unpack())?;
+    let deposit_header: HeaderView = rpc_client
+        .get_header(deposit_block_hash)?
+        .ok_or_else(|| "failed to get deposit_header".to_string())?
+        .into();
+    let prepare_header: HeaderView = rpc_client
+        .get_header(block_hash)?
+        .ok_or_else(|| "failed to get prepare_header".to_string())?
+        .into();
+    Ok(Some(minimal_unlock_point(&deposit_header, &prepare_header)))
+}
+
+/// Whether the epoch `current` has reached `point`
+pub(crate) fn epoch_reached(
+    current: EpochNumberWithFraction,
+    point: EpochNumberWithFraction,
+) -> bool {
+    current.number() > point.number()
+        || (current.number() == point.number()
+            && current.index() * point.length() >= point.index() * current.length())
+}
+
+// The recent blocks sampled for the median block time (the same count as the
+// median time of the consensus)
+const BLOCK_TIME_SAMPLE_BLOCKS: u64 = 37;
+
+/// Estimates when a NervosDAO cell is unlocked, the future blocks are assumed
+/// to be produced in the median block time of the recent blocks, and the
+/// future epochs to be as long as the tip one.
+pub(crate) struct UnlockEstimator {
+    tip_header: HeaderView,
+    tip_epoch_start: u64,
+    tip_epoch_length: u64,
+    // Unit: millisecond
+    pub(crate) block_time: u64,
+}
+
+impl UnlockEstimator {
+    pub(crate) fn new(
+        rpc_client: &mut HttpRpcClient,
+        tip_header: HeaderView,
+    ) -> Result<UnlockEstimator, String> {
+        let tip_number = tip_header.number();
+        let mut timestamps = Vec::new();
+        for number in tip_number.saturating_sub(BLOCK_TIME_SAMPLE_BLOCKS)..tip_number {
+            timestamps.push(get_header_by_number(rpc_client, number)?.timestamp());
+        }
+        timestamps.push(tip_header.timestamp());
+        let tip_epoch = rpc_client
+            .get_epoch_by_number(tip_header.epoch().number())?
+            .ok_or_else(|| format!("Epoch not found: {}", tip_header.epoch().number()))?;
+        Ok(UnlockEstimator {
+            tip_epoch_start: tip_epoch.start_number,
+            tip_epoch_length: tip_epoch.length,
+            block_time: median_block_interval(&timestamps),
+            tip_header,
+        })
+    }
+
+    pub(crate) fn is_unlocked(&self, unlock_point: EpochNumberWithFraction) -> bool {
+        epoch_reached(self.tip_header.epoch(), unlock_point)
+    }
+
+    /// The timestamp (in milliseconds) of the first block since
+    /// `unlock_point`, estimated when it is not produced yet
+    pub(crate) fn unlock_time(
+        &self,
+        rpc_client: &mut HttpRpcClient,
+        unlock_point: EpochNumberWithFraction,
+    ) -> Result<u64, String> {
+        let tip_epoch = self.tip_header.epoch();
+        let (epoch_start, epoch_length) = if unlock_point.number() <= tip_epoch.number() {
+            let epoch = rpc_client
+                .get_epoch_by_number(unlock_point.number())?
+                .ok_or_else(|| format!("Epoch not found: {}", unlock_point.number()))?;
+            (epoch.start_number, epoch.length)
+        } else {
+            let epochs = unlock_point.number() - tip_epoch.number();
+            (
+                self.tip_epoch_start + self.tip_epoch_length * epochs,
+                self.tip_epoch_length,
+            )
+        };
+        // The first block of the epoch whose fraction is not less than the
+        // fraction of the unlock point
+        let length = unlock_point.length().max(1);
+        let number = epoch_start + (unlock_point.index() * epoch_length + length - 1) / length;
+        let tip_number = self.tip_header.number();
+        if number <= tip_number {
+            Ok(get_header_by_number(rpc_client, number)?.timestamp())
+        } else {
+            Ok(self.tip_header.timestamp() + (number - tip_number) * self.block_time)
+        }
+    }
+}
+
+/// The median of the intervals between the consecutive timestamps
+fn median_block_interval(timestamps: &[u64]) -> u64 {
+    let mut intervals = timestamps
+        .windows(2)
+        .map(|pair| pair[1].saturating_sub(pair[0]))
+        .collect::<Vec<_>>();
+    intervals.sort();
+    intervals.get(intervals.len() / 2).cloned().unwrap_or(0)
+}
+
+fn get_header_by_number(rpc_client: &mut HttpRpcClient, number: u64) -> Result<HeaderView, String> {
+    rpc_client
+        .get_header_by_number(number)?
+        .map(Into::into)
+        .ok_or_else(|| format!("Block not found: {}", number))
+}
+
+fn get_committed_transaction(
+    rpc_client: &mut HttpRpcClient,
+    tx_hash: H256,
+) -> Result<(TransactionView, H256), String> {
+    let tx_status = rpc_client
+        .get_transaction(tx_hash.clone())?
+        .ok_or_else(|| format!("transaction is not found: {:#x}", tx_hash))?;
+    let block_hash = tx_status
+        .tx_status
+        .block_hash
+        .ok_or_else(|| format!("transaction is not committed: {:#x}", tx_hash))?;
+    let tx: packed::Transaction = tx_status.transaction.inner.into();
+    Ok((tx.into_view(), block_hash))
+}
+
 pub(crate) fn calculate_dao_maximum_withdraw4(
     deposit_header: &HeaderView,
     prepare_header: &HeaderView,
@@ -91,9 +338,147 @@ pub(crate) fn calculate_dao_maximum_withdraw4(
     occupied_capacity + withdraw_counted_capacity as u64
 }
 
+/// The capacity a prepared NervosDAO cell gives to the outputs of the withdraw
+/// transaction (the maximum withdraw), by the deposit and prepare headers in
+/// the header deps of the transaction
+pub(crate) fn withdraw_input_capacity(
+    output: &CellOutput,
+    data: &[u8],
+    prepare_block_hash: &packed::Byte32,
+    header_deps: &[HeaderView],
+) -> Result<u64, String> {
+    if data.len() != 8 {
+        return Err("invalid NervosDAO cell data".to_string());
+    }
+    let mut number_bytes = [0u8; 8];
+    number_bytes.copy_from_slice(data);
+    let deposit_number = u64::from_le_bytes(number_bytes);
+    let deposit_header = header_deps
+        .iter()
+        .find(|header| header.number() == deposit_number)
+        .ok_or_else(|| {
+            format!(
+                "the deposit block {} is not in the header deps",
+                deposit_number
+            )
+        })?;
+    let prepare_header = header_deps
+        .iter()
+        .find(|header| &header.hash() == prepare_block_hash)
+        .ok_or_else(|| {
+            format!(
+                "the prepare block {:#x} is not in the header deps",
+                prepare_block_hash
+            )
+        })?;
+    let occupied_capacity = output
+        .occupied_capacity(Capacity::bytes(data.len()).unwrap())
+        .map_err(|err| err.to_string())?;
+    Ok(calculate_dao_maximum_withdraw4(
+        deposit_header,
+        prepare_header,
+        output,
+        occupied_capacity.as_u64(),
+    ))
+}
+
+/// How a deposit is divided into DAO cells (`--split-count` / `--cell-capacity`)
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum DepositSplit {
+    Count(usize),
+    CellCapacity(u64),
+}
+
+/// The capacities of the DAO cells of a deposit. By count, every cell is
+/// rounded down to whole CKB and the last one takes the remainder; by cell
+/// capacity, the last one takes the remainder less than a cell.
+pub(crate) fn split_deposit(
+    capacity: u64,
+    split: Option<DepositSplit>,
+) -> Result<Vec<u64>, String> {
+    let (count, cell_capacity) = match split {
+        None => return Ok(vec![capacity]),
+        Some(DepositSplit::Count(0)) | Some(DepositSplit::CellCapacity(0)) => {
+            return Err("The split count and the cell capacity must be greater than 0".to_string())
+        }
+        Some(DepositSplit::Count(count)) => {
+            let count = count as u64;
+            (count, capacity / count / ONE_CKB * ONE_CKB)
+        }
+        Some(DepositSplit::CellCapacity(cell_capacity)) => {
+            (capacity / cell_capacity, cell_capacity)
+        }
+    };
+    if count == 0 || cell_capacity == 0 {
+        return Err(format!(
+            "Deposit capacity {} is too small to split",
+            HumanCapacity(capacity)
+        ));
+    }
+    if count > MAX_DEPOSIT_CELLS as u64 {
+        return Err(format!(
+            "Too many deposit cells: {}, at most {}",
+            count, MAX_DEPOSIT_CELLS
+        ));
+    }
+    let mut capacities = vec![cell_capacity; count as usize - 1];
+    capacities.push(capacity - cell_capacity * (count - 1));
+    Ok(capacities)
+}
+
+/// Split `cells` in order into as few transactions as possible not larger
+/// than `max_size`. `build_fn` builds the transaction of some cells with its
+/// size, the cells are shrunk by the size ratio until it fits. `accept_fn` is
+/// called with each transaction before the next one is built.
+pub(crate) fn split_by_size<S, C, T, B, A>(
+    state: &mut S,
+    mut cells: Vec<C>,
+    max_size: usize,
+    mut build_fn: B,
+    mut accept_fn: A,
+) -> Result<Vec<T>, String>
+where
+    C: Clone,
+    B: FnMut(&mut S, Vec<C>) -> Result<(T, usize), String>,
+    A: FnMut(&mut S, &T),
+{
+    let mut transactions = Vec::new();
+    while !cells.is_empty() {
+        let mut count = cells.len();
+        let transaction = loop {
+            let (transaction, size) = build_fn(state, cells[..count].to_vec())?;
+            if size <= max_size {
+                break transaction;
+            }
+            if count == 1 {
+                return Err(format!(
+                    "The transaction of one cell is too large: {} bytes",
+                    size
+                ));
+            }
+            // At least one cell less
+            count = (count * max_size / size).min(count - 1).max(1);
+        };
+        accept_fn(state, &transaction);
+        transactions.push(transaction);
+        cells.drain(..count);
+    }
+    Ok(transactions)
+}
+
+/// How to send the DAO transactions, see `TransactArgs`
+#[derive(Clone)]
+pub(crate) struct SendArgs {
+    pub(crate) memo: Option<String>,
+    pub(crate) wait_for_committed: Option<WaitForCommitted>,
+    pub(crate) on_failure: Option<OnFailure>,
+    pub(crate) targets: SendTargets,
+}
+
 pub(crate) fn send_transaction(
     rpc_client: &mut HttpRpcClient,
     transaction: TransactionView,
+    send_args: SendArgs,
     format: OutputFormat,
     color: bool,
     debug: bool,
@@ -107,7 +492,78 @@ pub(crate) fn send_transaction(
         );
     }
 
-    let resp = rpc_client.send_transaction(transaction.data())?;
+    let resp = on_failure::send_transaction(
+        rpc_client,
+        &transaction,
+        send_args.on_failure.as_ref(),
+        &send_args.targets,
+    )?;
+    save_tx_memo(&resp, send_args.memo)?;
+    if let Some(wait) = send_args.wait_for_committed {
+        let resp = wait.wait_response(rpc_client, &resp)?;
+        return Ok(resp.render(format, color));
+    }
+    Ok(resp.render(format, color))
+}
+
+/// Send the transactions split by `--all` one by one, then wait for all of
+/// them with `--wait-for-committed`
+pub(crate) fn send_transactions(
+    rpc_client: &mut HttpRpcClient,
+    transactions: Vec<TransactionView>,
+    send_args: SendArgs,
+    format: OutputFormat,
+    color: bool,
+    debug: bool,
+) -> Result<String, String> {
+    let count = transactions.len();
+    let mut tx_hashes: Vec<H256> = Vec::with_capacity(count);
+    for (index, transaction) in transactions.into_iter().enumerate() {
+        check_lack_of_capacity(&transaction)?;
+        if debug {
+            let transaction_view: ckb_jsonrpc_types::TransactionView = transaction.clone().into();
+            println!(
+                "[Send Transaction {}/{}]:\n{}",
+                index + 1,
+                count,
+                transaction_view.render(format, color)
+            );
+        }
+        let tx_hash = on_failure::send_transaction(
+            rpc_client,
+            &transaction,
+            send_args.on_failure.as_ref(),
+            &send_args.targets,
+        )
+        .map_err(|err| {
+            let sent = tx_hashes
+                .iter()
+                .map(|tx_hash| format!("{:#x}", tx_hash))
+                .collect::<Vec<_>>();
+            format!(
+                "Send transaction {}/{} failed: {}, sent: [{}]",
+                index + 1,
+                count,
+                err,
+                sent.join(", ")
+            )
+        })?;
+        let memo = send_args
+            .memo
+            .as_ref()
+            .map(|memo| format!("{} ({}/{})", memo, index + 1, count));
+        save_tx_memo(&tx_hash, memo)?;
+        tx_hashes.push(tx_hash);
+    }
+    let resp = if let Some(wait) = send_args.wait_for_committed {
+        let responses = tx_hashes
+            .iter()
+            .map(|tx_hash| wait.wait_response(rpc_client, tx_hash))
+            .collect::<Result<Vec<_>, String>>()?;
+        serde_json::json!({ "transactions": responses })
+    } else {
+        serde_json::json!({ "transactions": tx_hashes })
+    };
     Ok(resp.render(format, color))
 }
 
@@ -139,6 +595,7 @@ pub(crate) fn minimal_unlock_point(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::coin_control::CoinControl;
     use ckb_dao_utils::pack_dao_data;
     use ckb_types::{
         bytes::Bytes,
@@ -188,6 +645,109 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_split_deposit() {
+        let capacity = 1000 * ONE_CKB + 5;
+        assert_eq!(split_deposit(capacity, None), Ok(vec![capacity]));
+        assert_eq!(
+            split_deposit(capacity, Some(DepositSplit::Count(3))),
+            Ok(vec![333 * ONE_CKB, 333 * ONE_CKB, 334 * ONE_CKB + 5])
+        );
+        assert_eq!(
+            split_deposit(capacity, Some(DepositSplit::CellCapacity(300 * ONE_CKB))),
+            Ok(vec![300 * ONE_CKB, 300 * ONE_CKB, 400 * ONE_CKB + 5])
+        );
+        assert!(split_deposit(capacity, Some(DepositSplit::CellCapacity(capacity + 1))).is_err());
+        assert!(split_deposit(capacity, Some(DepositSplit::Count(2000))).is_err());
+    }
+
+    #[test]
+    fn test_split_by_size() {
+        // 50 bytes and 100 bytes per cell
+        let build = |_: &mut (), cells: Vec<u32>| -> Result<(Vec<u32>, usize), String> {
+            let size = 50 + 100 * cells.len();
+            Ok((cells, size))
+        };
+        let split = |count: u32, max_size: usize| {
+            split_by_size(&mut (), (0..count).collect(), max_size, build, |_, _| {})
+                .map(|txs| txs.iter().map(Vec::len).collect::<Vec<_>>())
+        };
+        assert_eq!(split(9, 1000), Ok(vec![9]));
+        assert_eq!(split(25, 1000), Ok(vec![9, 9, 7]));
+        assert_eq!(split(3, 160), Ok(vec![1, 1, 1]));
+        assert!(split(3, 100).is_err());
+        // In order, every cell once
+        let txs = split_by_size(&mut (), (0..25).collect(), 1000, build, |_, _| {}).unwrap();
+        assert_eq!(txs.concat(), (0..25).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_split_by_size_exclude_fee_cells() {
+        let out_point = |index: u32| packed::OutPoint::new(H256::default().pack(), index);
+        let fee_cells: Vec<_> = (100..103).map(out_point).collect();
+        // Each transaction is paid by the first fee cell allowed
+        let build = |coin_control: &mut CoinControl,
+                     cells: Vec<packed::OutPoint>|
+         -> Result<(Vec<packed::OutPoint>, usize), String> {
+            let fee_cell = fee_cells
+                .iter()
+                .find(|fee_cell| coin_control.allows(fee_cell))
+                .cloned()
+                .ok_or_else(|| "No fee cell".to_string())?;
+            let size = 100 * (cells.len() + 1);
+            let mut inputs = cells;
+            inputs.push(fee_cell);
+            Ok((inputs, size))
+        };
+        let exclude = |coin_control: &mut CoinControl, inputs: &Vec<packed::OutPoint>| {
+            for input in inputs {
+                coin_control.exclude(input.clone());
+            }
+        };
+        let cells: Vec<_> = (0..6).map(out_point).collect();
+
+        let mut coin_control = CoinControl::default();
+        let txs = split_by_size(&mut coin_control, cells.clone(), 300, build, exclude).unwrap();
+        assert_eq!(txs.len(), 3);
+        let paid_by: Vec<_> = txs.iter().map(|inputs| inputs[2].clone()).collect();
+        assert_eq!(paid_by, fee_cells);
+        assert!(fee_cells
+            .iter()
+            .all(|fee_cell| !coin_control.allows(fee_cell)));
+
+        // Not enough fee cells for the fourth transaction
+        let mut coin_control = CoinControl::default();
+        assert!(split_by_size(&mut coin_control, cells, 200, build, exclude).is_err());
+    }
+
+    #[test]
+    fn test_epoch_reached() {
+        let point = EpochNumberWithFraction::new(185, 5, 1000);
+        assert!(!epoch_reached(
+            EpochNumberWithFraction::new(184, 999, 1000),
+            point
+        ));
+        assert!(!epoch_reached(
+            EpochNumberWithFraction::new(185, 4, 1000),
+            point
+        ));
+        assert!(epoch_reached(
+            EpochNumberWithFraction::new(185, 1, 200),
+            point
+        ));
+        assert!(epoch_reached(
+            EpochNumberWithFraction::new(186, 0, 1000),
+            point
+        ));
+    }
+
+    #[test]
+    fn test_median_block_interval() {
+        assert_eq!(median_block_interval(&[0, 8000, 15000, 30000]), 8000);
+        assert_eq!(median_block_interval(&[1000, 9000]), 8000);
+        assert_eq!(median_block_interval(&[1000]), 0);
+    }
+
     #[test]
     fn check_withdraw_calculation() {
         let data = Bytes::from(vec![1; 10]);
@@ -221,4 +781,54 @@ mod tests {
         );
         assert_eq!(result, 100_000_000_009_999);
     }
+
+    #[test]
+    fn test_withdraw_input_capacity() {
+        let header = |number: u64, ar: u64| {
+            HeaderBuilder::default()
+                .number(number.pack())
+                .dao(pack_dao_data(
+                    ar,
+                    Default::default(),
+                    Default::default(),
+                    Default::default(),
+                ))
+                .build()
+        };
+        let deposit_header = header(100, 10_000_000_000_123_456);
+        let prepare_header = header(200, 10_000_000_001_123_456);
+        let output = CellOutput::new_builder()
+            .capacity(capacity_bytes!(1000000).pack())
+            .build();
+        let data = 100u64.to_le_bytes();
+        let header_deps = vec![prepare_header.clone(), deposit_header.clone()];
+
+        let expected = calculate_dao_maximum_withdraw4(
+            &deposit_header,
+            &prepare_header,
+            &output,
+            output
+                .occupied_capacity(Capacity::bytes(8).unwrap())
+                .unwrap()
+                .as_u64(),
+        );
+        assert!(expected > capacity_bytes!(1000000).as_u64());
+        assert_eq!(
+            withdraw_input_capacity(&output, &data, &prepare_header.hash(), &header_deps),
+            Ok(expected)
+        );
+        // The deposit or prepare header is missing, or the data is invalid
+        assert!(
+            withdraw_input_capacity(&output, &data, &prepare_header.hash(), &header_deps[..1])
+                .is_err()
+        );
+        assert!(
+            withdraw_input_capacity(&output, &data, &prepare_header.hash(), &header_deps[1..])
+                .is_err()
+        );
+        assert!(
+            withdraw_input_capacity(&output, &data[..4], &prepare_header.hash(), &header_deps)
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,425 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ckb_hash::blake2b_256;
+use ckb_index::{IndexDatabase, LiveCellInfo};
+use ckb_sdk::{
+    is_type_id, rpc::Transaction, type_id_args, type_id_script, wallet::KeyStore, Address,
+    AddressPayload, CollectedCell, GenesisInfo, HttpRpcClient, HumanCapacity, TransactionBuilder,
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::Capacity,
+    packed::{CellInput, CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use either::Either;
+use serde_derive::{Deserialize, Serialize};
+
+use super::account::AccountId;
+use super::sudt::sender_payload;
+use super::CliSubCommand;
+use crate::utils::{
+    arg,
+    arg_parser::{ArgParser, FilePathParser, FromStrParser, PrivkeyWrapper},
+    broadcast::SendTargets,
+    index::{with_chain_index_db, IndexController},
+    on_failure::{self, OnFailure},
+    other::{
+        get_account_signer, get_fee_policy, get_genesis_info, get_live_cell,
+        get_live_cell_with_cache, get_max_mature_number, get_network_type, is_mature,
+        privkey_or_from_account,
+    },
+    output::write_file_atomic,
+    policy::SigningPolicy,
+    printer::{OutputFormat, Printable},
+    tx_record::save_tx_memo,
+};
+
+// The deployed cell is always the first output
+const CODE_OUTPUT_INDEX: u32 = 0;
+
+/// The deployed cells recorded by name, the scripts referencing them read the
+/// code hash and the cell dep from here.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeployManifest {
+    pub cells: BTreeMap<String, DeployedCell>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeployedCell {
+    pub tx_hash: H256,
+    pub index: u32,
+    /// The code hash by `hash_type: data`, changed by every upgrade
+    pub data_hash: H256,
+    /// The code hash by `hash_type: type`, only for the cells deployed with
+    /// Type ID, it is kept across upgrades
+    pub type_id: Option<H256>,
+    /// The args of the Type ID script
+    pub type_id_args: Option<H256>,
+}
+
+impl DeployedCell {
+    pub fn out_point(&self) -> OutPoint {
+        OutPoint::new(self.tx_hash.pack(), self.index)
+    }
+}
+
+impl DeployManifest {
+    /// Load the manifest, an empty one when the file not exists
+    pub fn load(path: &Path) -> Result<DeployManifest, String> {
+        if !path.exists() {
+            return Ok(DeployManifest::default());
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Read {} failed: {}", path.display(), err))?;
+        serde_json::from_str(&content)
+            .map_err(|err| format!("Invalid deployment manifest {}: {}", path.display(), err))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        write_file_atomic(path, content.as_bytes())
+    }
+}
+
+pub struct DeploySubCommand<'a> {
+    rpc_client: &'a mut HttpRpcClient,
+    key_store: &'a mut KeyStore,
+    genesis_info: Option<GenesisInfo>,
+    index_dir: PathBuf,
+    index_controller: IndexController,
+    signing_policy: SigningPolicy,
+    send_targets: SendTargets,
+    on_failure: Option<OnFailure>,
+}
+
+impl<'a> DeploySubCommand<'a> {
+    pub fn new(
+        rpc_client: &'a mut HttpRpcClient,
+        key_store: &'a mut KeyStore,
+        genesis_info: Option<GenesisInfo>,
+        index_dir: PathBuf,
+        index_controller: IndexController,
+        signing_policy: SigningPolicy,
+        send_targets: SendTargets,
+    ) -> DeploySubCommand<'a> {
+        DeploySubCommand {
+            rpc_client,
+            key_store,
+            genesis_info,
+            index_dir,
+            index_controller,
+            signing_policy,
+            send_targets,
+            on_failure: None,
+        }
+    }
+
+    fn with_db<F, T>(&mut self, func: F) -> Result<T, String>
+    where
+        F: FnOnce(IndexDatabase) -> T,
+    {
+        let network_type = get_network_type(self.rpc_client)?;
+        let genesis_info = get_genesis_info(&self.genesis_info, self.rpc_client)?;
+        with_chain_index_db(
+            &self.index_dir,
+            &self.index_controller,
+            network_type,
+            genesis_info,
+            func,
+        )
+    }
+
+    pub fn subcommand() -> App<'static, 'static> {
+        let arg_privkey_path = arg::privkey_path().required_unless(arg::from_account().b.name);
+        let arg_from_account = arg::from_account()
+            .required_unless(arg::privkey_path().b.name)
+            .conflicts_with(arg::privkey_path().b.name);
+        let arg_binary_path = Arg::with_name("binary-path")
+            .long("binary-path")
+            .takes_value(true)
+            .required(true)
+            .validator(|input| FilePathParser::new(true).validate(input))
+            .help("The script binary stored as the cell data");
+        let arg_name = Arg::with_name("name")
+            .long("name")
+            .takes_value(true)
+            .required(true)
+            .help("The name of the deployed cell in the manifest");
+        let arg_manifest = Arg::with_name("manifest")
+            .long("manifest")
+            .takes_value(true)
+            .required(true)
+            .validator(|input| FilePathParser::new(false).validate(input))
+            .help("The deployment manifest file (JSON), the deployed cells are recorded by name");
+        let arg_tx_fee = arg::tx_fee().required_unless(arg::fee_rate().b.name);
+        let arg_fee_rate = arg::fee_rate().conflicts_with(arg::tx_fee().b.name);
+
+        SubCommand::with_name("deploy")
+            .about("Deploy script binaries into cells (optionally upgradable by Type ID) and record them in a manifest (with local index)")
+            .subcommands(vec![
+                SubCommand::with_name("new")
+                    .about("Deploy a binary into a new cell locked by the signer")
+                    .arg(arg_privkey_path.clone())
+                    .arg(arg_from_account.clone())
+                    .arg(arg_binary_path.clone())
+                    .arg(arg_name.clone())
+                    .arg(arg_manifest.clone())
+                    .arg(
+                        Arg::with_name("type-id")
+                            .long("type-id")
+                            .help("Add the Type ID type script, the cell can be upgraded by `deploy upgrade` and referenced by the type hash"),
+                    )
+                    .arg(arg_tx_fee.clone())
+                    .arg(arg_fee_rate.clone())
+                    .arg(arg::allow_high_fee())
+                    .arg(arg::on_failure())
+                    .arg(arg::change_cells())
+                    .arg(arg::memo()),
+                SubCommand::with_name("upgrade")
+                    .about("Replace the binary of a Type ID cell in the manifest, the old cell is spent and the type hash is kept")
+                    .arg(arg_privkey_path)
+                    .arg(arg_from_account)
+                    .arg(arg_binary_path)
+                    .arg(arg_name)
+                    .arg(arg_manifest)
+                    .arg(arg_tx_fee)
+                    .arg(arg_fee_rate)
+                    .arg(arg::allow_high_fee())
+                    .arg(arg::on_failure())
+                    .arg(arg::change_cells())
+                    .arg(arg::memo()),
+            ])
+    }
+
+    // The mature live cells of `lock` without type script and data
+    fn plain_cells(&mut self, lock: &Script) -> Result<Vec<LiveCellInfo>, String> {
+        let max_mature_number = get_max_mature_number(self.rpc_client)?;
+        let lock_hash = lock.calc_script_hash();
+        self.with_db(|db| {
+            db.get_live_cells_by_lock(lock_hash, None, |_, info| {
+                (
+                    false,
+                    info.type_hashes.is_none()
+                        && info.data_bytes == 0
+                        && is_mature(info, max_mature_number),
+                )
+            })
+        })
+    }
+
+    // Build the transaction with `fixed_inputs` as the first inputs, the plain
+    // cells of sender pay for the rest, then send it.
+    fn build_and_send(
+        &mut self,
+        m: &ArgMatches,
+        from_account: Either<PrivkeyWrapper, AccountId>,
+        sender: &AddressPayload,
+        mut builder: TransactionBuilder,
+        fixed_inputs: Vec<CollectedCell>,
+        plain_cells: Vec<LiveCellInfo>,
+    ) -> Result<H256, String> {
+        let fee_policy = get_fee_policy(m)?;
+        let change_cells: usize =
+            FromStrParser::<usize>::default().from_matches(m, "change-cells")?;
+        let memo = m.value_of("memo").map(ToOwned::to_owned);
+        builder
+            .change_address(sender.clone())
+            .change_cells(change_cells)
+            .fee_policy(fee_policy);
+
+        let network_type = get_network_type(self.rpc_client)?;
+        let rendered_sender = Address::new(network_type, sender.clone()).to_string();
+        let fixed_capacity: u64 = fixed_inputs.iter().map(|cell| cell.capacity).sum();
+        let mut collector = move |required: u64| {
+            let mut cells = fixed_inputs.clone();
+            let mut from_capacity = fixed_capacity;
+            for info in &plain_cells {
+                if from_capacity >= required {
+                    break;
+                }
+                if cells.iter().any(|cell| cell.out_point == info.out_point()) {
+                    continue;
+                }
+                from_capacity += info.capacity;
+                cells.push(CollectedCell::new(info.out_point(), info.capacity));
+            }
+            if required > from_capacity {
+                return Err(format!(
+                    "Capacity(mature) not enough: {} => {}",
+                    rendered_sender, from_capacity,
+                ));
+            }
+            Ok(cells)
+        };
+
+        let genesis_info = get_genesis_info(&self.genesis_info, self.rpc_client)?;
+        let rpc_client = &mut *self.rpc_client;
+        let mut live_cell_cache: HashMap<(OutPoint, bool), ((CellOutput, Transaction), Bytes)> =
+            Default::default();
+        let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
+            get_live_cell_with_cache(&mut live_cell_cache, rpc_client, out_point, with_data)
+                .map(|(output, _)| output)
+        };
+        let signer = get_account_signer(self.key_store, from_account)?;
+        let tx = self.signing_policy.build_transaction(
+            &builder,
+            &mut collector,
+            &mut get_live_cell_fn,
+            &genesis_info,
+            network_type,
+            signer,
+        )?;
+        let tx_hash = on_failure::send_transaction(
+            self.rpc_client,
+            &tx,
+            self.on_failure.as_ref(),
+            &self.send_targets,
+        )
+        .map_err(|err| format!("Send transaction error: {}", err))?;
+        save_tx_memo(&tx_hash, memo)?;
+        Ok(tx_hash)
+    }
+}
+
+// The code cell with the minimal capacity
+fn code_output(
+    lock: Script,
+    type_script: Option<Script>,
+    data: &Bytes,
+) -> Result<CellOutput, String> {
+    let output = CellOutput::new_builder()
+        .lock(lock)
+        .type_(type_script.pack())
+        .build();
+    let capacity = output
+        .occupied_capacity(Capacity::bytes(data.len()).map_err(|err| err.to_string())?)
+        .map_err(|err| err.to_string())?;
+    Ok(output.as_builder().capacity(capacity.pack()).build())
+}
+
+fn read_binary(m: &ArgMatches) -> Result<Bytes, String> {
+    let path: PathBuf = FilePathParser::new(true).from_matches(m, "binary-path")?;
+    let data = fs::read(&path).map_err(|err| format!("Read {} failed: {}", path.display(), err))?;
+    if data.is_empty() {
+        return Err(format!("The binary {} is empty", path.display()));
+    }
+    Ok(Bytes::from(data))
+}
+
+impl<'a> CliSubCommand for DeploySubCommand<'a> {
+    fn process(
+        &mut self,
+        matches: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        _debug: bool,
+    ) -> Result<String, String> {
+        let (action, m) = match matches.subcommand() {
+            (action @ "new", Some(m)) | (action @ "upgrade", Some(m)) => (action, m),
+            _ => return Err(matches.usage().to_owned()),
+        };
+        self.signing_policy.allow_high_fee = m.is_present("allow-high-fee");
+        self.on_failure = OnFailure::from_matches(m)?;
+
+        let from_account = privkey_or_from_account(m)?;
+        let sender = sender_payload(&from_account)?;
+        let sender_lock = Script::from(&sender);
+        let name = m.value_of("name").unwrap().to_string();
+        let manifest_path: PathBuf = FilePathParser::new(false).from_matches(m, "manifest")?;
+        let mut manifest = DeployManifest::load(&manifest_path)?;
+        let data = read_binary(m)?;
+        let mut plain_cells = self.plain_cells(&sender_lock)?;
+
+        let (fixed_inputs, type_script) = if action == "new" {
+            if manifest.cells.contains_key(&name) {
+                return Err(format!(
+                    "{} is already deployed in {}, replace it by `deploy upgrade`",
+                    name,
+                    manifest_path.display()
+                ));
+            }
+            if m.is_present("type-id") {
+                // The Type ID args is decided by the first input
+                if plain_cells.is_empty() {
+                    return Err("No mature plain cell to create the Type ID".to_string());
+                }
+                let first = plain_cells.remove(0);
+                let first_input = CellInput::new(first.out_point(), 0);
+                let type_script =
+                    type_id_script(type_id_args(&first_input, u64::from(CODE_OUTPUT_INDEX)));
+                let fixed_inputs = vec![CollectedCell::new(first.out_point(), first.capacity)];
+                (fixed_inputs, Some(type_script))
+            } else {
+                (Vec::new(), None)
+            }
+        } else {
+            let deployed = manifest.cells.get(&name).ok_or_else(|| {
+                format!("{} is not deployed in {}", name, manifest_path.display())
+            })?;
+            let type_id_args = deployed.type_id_args.clone().ok_or_else(|| {
+                format!(
+                    "{} is not deployed with --type-id, it can not be upgraded",
+                    name
+                )
+            })?;
+            let out_point = deployed.out_point();
+            let ((output, _), _) = get_live_cell(self.rpc_client, out_point.clone(), false)?;
+            let type_script = type_id_script(Bytes::from(type_id_args.as_bytes().to_vec()));
+            let type_matched = output
+                .type_()
+                .to_opt()
+                .filter(is_type_id)
+                .map(|script| script.calc_script_hash() == type_script.calc_script_hash())
+                .unwrap_or(false);
+            if !type_matched {
+                return Err(format!(
+                    "The Type ID of cell {} does not match the manifest",
+                    out_point
+                ));
+            }
+            if output.lock().calc_script_hash() != sender_lock.calc_script_hash() {
+                return Err(format!("The cell {} is not owned by the signer", out_point));
+            }
+            let capacity: u64 = output.capacity().unpack();
+            (
+                vec![CollectedCell::new(out_point, capacity)],
+                Some(type_script),
+            )
+        };
+
+        let output = code_output(sender_lock, type_script.clone(), &data)?;
+        let capacity: u64 = output.capacity().unpack();
+        let mut builder = TransactionBuilder::default();
+        builder.output(output, data.clone());
+        let tx_hash =
+            self.build_and_send(m, from_account, &sender, builder, fixed_inputs, plain_cells)?;
+
+        let deployed = DeployedCell {
+            tx_hash,
+            index: CODE_OUTPUT_INDEX,
+            data_hash: H256::from(blake2b_256(&data)),
+            type_id: type_script
+                .as_ref()
+                .map(|script| script.calc_script_hash().unpack()),
+            type_id_args: type_script
+                .as_ref()
+                .map(|script| H256::from_slice(&script.args().raw_data()).unwrap()),
+        };
+        manifest.cells.insert(name.clone(), deployed.clone());
+        manifest.save(&manifest_path)?;
+        let resp = serde_json::json!({
+            "name": name,
+            "tx_hash": deployed.tx_hash,
+            "index": deployed.index,
+            "data_hash": deployed.data_hash,
+            "type_id": deployed.type_id,
+            "capacity": format!("{:#}", HumanCapacity::from(capacity)),
+        });
+        Ok(resp.render(format, color))
+    }
+}
@@ -0,0 +1,341 @@
+use std::fs;
+use std::path::PathBuf;
+
+use ckb_index::{with_index_db, IndexDatabase};
+use ckb_sdk::{GenesisInfo, HttpRpcClient};
+use ckb_types::{
+    core::{BlockView, HeaderView},
+    prelude::*,
+    H256,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use serde_derive::{Deserialize, Serialize};
+
+use super::CliSubCommand;
+use crate::utils::{
+    arg_parser::{ArgParser, FromStrParser},
+    index::check_index_chain,
+    interrupt::{check_interrupted, interruptible},
+    other::get_network_type,
+    output::write_file_atomic,
+    printer::{OutputFormat, Printable},
+};
+
+/// The last block persisted by `index rebuild`
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Checkpoint {
+    number: u64,
+    hash: H256,
+}
+
+pub struct IndexSubCommand<'a> {
+    rpc_client: &'a mut HttpRpcClient,
+    index_dir: PathBuf,
+    record_txs: bool,
+}
+
+impl<'a> IndexSubCommand<'a> {
+    pub fn new(
+        rpc_client: &'a mut HttpRpcClient,
+        index_dir: PathBuf,
+        record_txs: bool,
+    ) -> IndexSubCommand<'a> {
+        IndexSubCommand {
+            rpc_client,
+            index_dir,
+            record_txs,
+        }
+    }
+
+    pub fn subcommand() -> App<'static, 'static> {
+        SubCommand::with_name("index")
+            .about("Index database maintenance")
+            .subcommands(vec![SubCommand::with_name("rebuild")
+                .about("Rebuild the index database from genesis, progress is checkpointed and can be resumed")
+                .arg(
+                    Arg::with_name("resume")
+                        .long("resume")
+                        .help("Resume from the last checkpoint instead of restarting from genesis, the blocks forked out of main chain are rolled back"),
+                )
+                .arg(
+                    Arg::with_name("checkpoint-interval")
+                        .long("checkpoint-interval")
+                        .takes_value(true)
+                        .default_value("1000")
+                        .validator(|input| FromStrParser::<u64>::new().validate(input))
+                        .help("Record a checkpoint every N blocks"),
+                )])
+    }
+
+    fn rebuild(&mut self, resume: bool, interval: u64) -> Result<Checkpoint, String> {
+        let genesis_block: BlockView = self
+            .rpc_client
+            .get_block_by_number(0)?
+            .ok_or_else(|| "Can not get genesis block".to_string())?
+            .into();
+        let network_type = get_network_type(self.rpc_client)?;
+        let genesis_info = GenesisInfo::from_block(&genesis_block)?;
+        let genesis_hash: H256 = genesis_info.header().hash().unpack();
+
+        let mut db_dir = self.index_dir.clone();
+        db_dir.push(format!("{:#x}", genesis_hash));
+        let mut checkpoint_path = self.index_dir.clone();
+        checkpoint_path.push(format!("{:#x}.checkpoint", genesis_hash));
+
+        if resume {
+            check_index_chain(&self.index_dir, &genesis_hash)?;
+            let checkpoint = load_checkpoint(&checkpoint_path)?.ok_or_else(|| {
+                "No checkpoint found, please rebuild without --resume".to_string()
+            })?;
+            let rpc_client = &mut *self.rpc_client;
+            let resume_from = with_index_db(&self.index_dir, genesis_hash.clone(), |backend, cf| {
+                let mut db = IndexDatabase::from_db(
+                    backend,
+                    cf,
+                    network_type,
+                    genesis_info.clone(),
+                    false,
+                )?;
+                // Blocks after the checkpoint may be already written
+                let last = match db.last_header() {
+                    Some(header) if header.number() >= checkpoint.number => {
+                        (header.number(), header.hash().unpack())
+                    }
+                    _ => {
+                        return Err(format!(
+                            "Index database does not match checkpoint {} ({:#x}), please rebuild without --resume",
+                            checkpoint.number, checkpoint.hash
+                        )
+                        .into());
+                    }
+                };
+                // The blocks forked out of the main chain since the checkpoint
+                // are rolled back
+                let resume_from = rewind_to_main_chain(
+                    last,
+                    |number| {
+                        rpc_client.get_header_by_number(number).map(|header_opt| {
+                            header_opt.map(|header| HeaderView::from(header).hash().unpack())
+                        })
+                    },
+                    || {
+                        db.rollback_last_block().map_err(|err| err.to_string())?;
+                        let header = db.last_header().ok_or_else(|| {
+                            "The index database is empty after rollback".to_string()
+                        })?;
+                        Ok((header.number(), header.hash().unpack()))
+                    },
+                )?;
+                db.verify_consistency()?;
+                Ok(resume_from)
+            })
+            .map_err(|err| err.to_string())?;
+            if resume_from.0 < checkpoint.number {
+                eprintln!(
+                    "Checkpoint block {} is not in main chain, rolled back to block {}",
+                    checkpoint.number, resume_from.0
+                );
+                let (number, hash) = resume_from.clone();
+                save_checkpoint(&checkpoint_path, &Checkpoint { number, hash })?;
+            }
+            eprintln!("Resume from block: {}", resume_from.0);
+        } else {
+            if db_dir.exists() {
+                fs::remove_dir_all(&db_dir).map_err(|err| err.to_string())?;
+            }
+            if checkpoint_path.exists() {
+                fs::remove_file(&checkpoint_path).map_err(|err| err.to_string())?;
+            }
+        }
+
+        // Ctrl-C stops the rebuild after the current block, the checkpoint is
+        // saved before exiting
+        let _guard = interruptible();
+        let resume_hint = "resume by: ckb-cli index rebuild --resume";
+        loop {
+            let tip_header: HeaderView = self.rpc_client.get_tip_header()?.into();
+            let rpc_client = &mut *self.rpc_client;
+            let record_txs = self.record_txs;
+            // Reopen database for every segment (see the NOTE of `IndexDatabase`)
+            let checkpoint = with_index_db(&self.index_dir, genesis_hash.clone(), |backend, cf| {
+                let mut db = IndexDatabase::from_db(
+                    backend,
+                    cf,
+                    network_type,
+                    genesis_info.clone(),
+                    record_txs,
+                )?;
+                if db.last_number().is_none() {
+                    db.apply_next_block(genesis_block.clone())?;
+                }
+                db.update_tip(tip_header.clone());
+                let segment_end = db.last_number().unwrap() + interval;
+                while db.last_number().unwrap() < tip_header.number()
+                    && db.last_number().unwrap() < segment_end
+                    && check_interrupted(resume_hint).is_ok()
+                {
+                    let next_block = rpc_client
+                        .get_block_by_number(db.next_number().unwrap())?
+                        .ok_or_else(|| "Block not found, chain may be forking".to_string())?;
+                    db.apply_next_block(next_block.into())?;
+                }
+                db.flush();
+                let last_header = db.last_header().unwrap();
+                Ok(Checkpoint {
+                    number: last_header.number(),
+                    hash: last_header.hash().unpack(),
+                })
+            })
+            .map_err(|err| err.to_string())?;
+            save_checkpoint(&checkpoint_path, &checkpoint)?;
+            eprintln!(
+                "Checkpoint: block {} / {}",
+                checkpoint.number,
+                tip_header.number()
+            );
+            check_interrupted(resume_hint)?;
+            if checkpoint.number >= tip_header.number() {
+                break;
+            }
+        }
+
+        eprintln!("Verifying index database...");
+        with_index_db(&self.index_dir, genesis_hash, |backend, cf| {
+            let db = IndexDatabase::from_db(backend, cf, network_type, genesis_info, false)?;
+            db.verify_consistency()?;
+            let last_header = db.last_header().unwrap();
+            Ok(Checkpoint {
+                number: last_header.number(),
+                hash: last_header.hash().unpack(),
+            })
+        })
+        .map_err(|err| err.to_string())
+    }
+}
+
+impl<'a> CliSubCommand for IndexSubCommand<'a> {
+    fn process(
+        &mut self,
+        matches: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        _debug: bool,
+    ) -> Result<String, String> {
+        match matches.subcommand() {
+            ("rebuild", Some(m)) => {
+                let resume = m.is_present("resume");
+                let interval: u64 =
+                    FromStrParser::<u64>::new().from_matches(m, "checkpoint-interval")?;
+                if interval == 0 {
+                    return Err("checkpoint-interval must be greater than 0".to_string());
+                }
+                let checkpoint = self.rebuild(resume, interval)?;
+                let resp = serde_json::json!({
+                    "last_number": checkpoint.number,
+                    "last_hash": checkpoint.hash,
+                    "consistent": true,
+                });
+                Ok(resp.render(format, color))
+            }
+            _ => Err(matches.usage().to_owned()),
+        }
+    }
+}
+
+// Roll back the last block `(number, hash)` until it is in the main chain,
+// return the block to resume from
+fn rewind_to_main_chain<F, G>(
+    mut last: (u64, H256),
+    mut canonical_hash: F,
+    mut rollback: G,
+) -> Result<(u64, H256), String>
+where
+    F: FnMut(u64) -> Result<Option<H256>, String>,
+    G: FnMut() -> Result<(u64, H256), String>,
+{
+    while canonical_hash(last.0)?.as_ref() != Some(&last.1) {
+        if last.0 == 0 {
+            return Err("The genesis block of index database is not in main chain".to_string());
+        }
+        last = rollback()?;
+    }
+    Ok(last)
+}
+
+fn load_checkpoint(path: &PathBuf) -> Result<Option<Checkpoint>, String> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = fs::File::open(path).map_err(|err| err.to_string())?;
+    serde_json::from_reader(file).map(Some).map_err(|err| {
+        format!(
+            "Parse checkpoint {} failed: {}",
+            path.to_string_lossy(),
+            err
+        )
+    })
+}
+
+fn save_checkpoint(path: &PathBuf, checkpoint: &Checkpoint) -> Result<(), String> {
+    let content = serde_json::to_string(checkpoint).map_err(|err| err.to_string())?;
+    write_file_atomic(path, content.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewind_to_main_chain() {
+        let hash = |n: u8| H256::from([n; 32]);
+        // Main chain: 0..=4, the database forked at block 2: 0, 1, 12, 13
+        let main_chain = vec![hash(0), hash(1), hash(2), hash(3), hash(4)];
+        let mut db_chain = vec![hash(0), hash(1), hash(12), hash(13)];
+        let canonical_hash = |number: u64| Ok(main_chain.get(number as usize).cloned());
+
+        let last = (3, hash(13));
+        let resume_from = rewind_to_main_chain(last, canonical_hash, || {
+            db_chain.pop();
+            let number = db_chain.len() as u64 - 1;
+            Ok((number, db_chain[number as usize].clone()))
+        })
+        .unwrap();
+        assert_eq!(resume_from, (1, hash(1)));
+        assert_eq!(db_chain.len(), 2);
+
+        // Nothing is rolled back in main chain
+        let resume_from = rewind_to_main_chain((4, hash(4)), canonical_hash, || {
+            panic!("rolled back in main chain")
+        })
+        .unwrap();
+        assert_eq!(resume_from, (4, hash(4)));
+
+        // A rollback too deep, or a genesis of another chain
+        assert!(rewind_to_main_chain((3, hash(13)), canonical_hash, || Err(
+            "block 2 is too old to roll back".to_string()
+        ))
+        .is_err());
+        assert!(rewind_to_main_chain((0, hash(10)), canonical_hash, || unreachable!()).is_err());
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let dir = std::env::temp_dir().join(format!("ckb-cli-checkpoint-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("0x01.checkpoint");
+        assert!(load_checkpoint(&path).unwrap().is_none());
+
+        let checkpoint = Checkpoint {
+            number: 1000,
+            hash: H256::from([1u8; 32]),
+        };
+        save_checkpoint(&path, &checkpoint).unwrap();
+        let loaded = load_checkpoint(&path).unwrap().unwrap();
+        assert_eq!(loaded.number, 1000);
+        assert_eq!(loaded.hash, checkpoint.hash);
+
+        fs::write(&path, "{").unwrap();
+        assert!(load_checkpoint(&path).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,5 +1,5 @@
 use std::fs;
-use std::io::{Read, Write};
+use std::io::Read;
 use std::path::PathBuf;
 
 use ckb_sdk::{
@@ -12,7 +12,7 @@ use ckb_types::{
         capacity_bytes, Capacity, HeaderBuilder, HeaderView, ScriptHashType, TransactionBuilder,
     },
     h256,
-    packed::{CellDep, CellInput, CellOutput, OutPoint, Script},
+    packed::{CellDep, CellInput, CellOutput, OutPoint, Script, Transaction},
     prelude::*,
     H160, H256,
 };
@@ -21,8 +21,11 @@ use clap::{App, Arg, ArgMatches, SubCommand};
 use super::CliSubCommand;
 use crate::utils::{
     arg,
-    arg_parser::{ArgParser, FilePathParser, FixedHashParser},
-    other::{get_genesis_info, get_singer},
+    arg_parser::{ArgParser, FilePathParser, FixedHashParser, FromStrParser},
+    debugger::{run_debugger, ScriptGroupSpec},
+    other::{get_genesis_info, get_network_type, get_singer},
+    output::write_file_atomic,
+    policy::SigningPolicy,
     printer::{OutputFormat, Printable},
 };
 
@@ -30,6 +33,7 @@ pub struct MockTxSubCommand<'a> {
     rpc_client: &'a mut HttpRpcClient,
     key_store: &'a mut KeyStore,
     genesis_info: Option<GenesisInfo>,
+    signing_policy: SigningPolicy,
 }
 
 impl<'a> MockTxSubCommand<'a> {
@@ -37,11 +41,13 @@ impl<'a> MockTxSubCommand<'a> {
         rpc_client: &'a mut HttpRpcClient,
         key_store: &'a mut KeyStore,
         genesis_info: Option<GenesisInfo>,
+        signing_policy: SigningPolicy,
     ) -> MockTxSubCommand<'a> {
         MockTxSubCommand {
             rpc_client,
             key_store,
             genesis_info,
+            signing_policy,
         }
     }
 
@@ -57,6 +63,11 @@ impl<'a> MockTxSubCommand<'a> {
             .takes_value(true)
             .validator(|input| FilePathParser::new(false).validate(input))
             .help("Completed mock transaction data file (format: json)");
+        let arg_debug_script = Arg::with_name("debug-script")
+            .long("debug-script")
+            .takes_value(true)
+            .validator(|input| FromStrParser::<ScriptGroupSpec>::new().validate(input))
+            .help("When verify failed, run the script group by ckb-debugger (format: <input|output>.<index>.<lock|type>, e.g. input.0.lock)");
         SubCommand::with_name(name)
             .about("Handle mock transactions (verify/send)")
             .subcommands(vec![
@@ -74,10 +85,12 @@ impl<'a> MockTxSubCommand<'a> {
                     ),
                 SubCommand::with_name("verify")
                     .about("Verify a mock transaction in local")
-                    .arg(arg_tx_file.clone()),
+                    .arg(arg_tx_file.clone())
+                    .arg(arg_debug_script.clone()),
                 SubCommand::with_name("send")
                     .about("Complete then send a transaction")
-                    .arg(arg_tx_file.clone()),
+                    .arg(arg_tx_file.clone())
+                    .arg(arg_debug_script),
             ])
     }
 }
@@ -91,6 +104,7 @@ impl<'a> CliSubCommand for MockTxSubCommand<'a> {
         _debug: bool,
     ) -> Result<String, String> {
         let genesis_info = get_genesis_info(&self.genesis_info, self.rpc_client)?;
+        let network = get_network_type(self.rpc_client)?;
 
         let mut complete_tx = |m: &ArgMatches,
                                complete: bool,
@@ -115,17 +129,49 @@ impl<'a> CliSubCommand for MockTxSubCommand<'a> {
             let cycle = {
                 let mut helper = MockTransactionHelper::new(&mut mock_tx);
                 if complete {
-                    helper.complete_tx(None, &genesis_info, &signer, |out_point| {
+                    helper.add_change_output(None, |out_point| loader.get_live_cell(out_point))?;
+                    helper.fill_deps(&genesis_info, |out_point| loader.get_live_cell(out_point))?;
+                    // Check by the signing policy before signing, as `tx sign-inputs`
+                    let tx = helper.mock_tx.core_transaction();
+                    let mock_inputs = &helper.mock_tx.mock_info.inputs;
+                    let mut get_live_cell = |out_point: OutPoint, _with_data: bool| {
+                        let output_opt = match mock_inputs
+                            .iter()
+                            .find(|mock_input| mock_input.input.previous_output() == out_point)
+                        {
+                            Some(mock_input) => Some(mock_input.output.clone()),
+                            None => loader
+                                .get_live_cell(out_point.clone())?
+                                .map(|(output, _)| output),
+                        };
+                        output_opt
+                            .map(|output| (output, Transaction::default()))
+                            .ok_or_else(|| format!("Input cell not found: {}", out_point))
+                    };
+                    self.signing_policy
+                        .check_transaction(&tx, network, &mut get_live_cell)?;
+                    helper.fill_witnesses(&genesis_info, &signer, |out_point| {
                         loader.get_live_cell(out_point)
                     })?;
                 }
                 if verify {
-                    helper.verify(u64::max_value(), loader)?
+                    helper.verify(u64::max_value(), loader)
                 } else {
-                    0
+                    Ok(0)
                 }
             };
-            Ok((mock_tx, cycle))
+            match cycle {
+                Ok(cycle) => Ok((mock_tx, cycle)),
+                Err(err) => {
+                    let group_opt: Option<ScriptGroupSpec> =
+                        FromStrParser::new().from_matches_opt(m, "debug-script", false)?;
+                    if let Some(group) = group_opt {
+                        eprintln!("{}", err);
+                        run_debugger(&mock_tx, &group)?;
+                    }
+                    Err(err)
+                }
+            }
         };
 
         let output_tx = |m: &ArgMatches, mock_tx: &MockTransaction| -> Result<(), String> {
@@ -135,10 +181,7 @@ impl<'a> CliSubCommand for MockTxSubCommand<'a> {
             let output_content =
                 ReprMockTransaction::from(mock_tx.clone()).render(OutputFormat::Json, output_color);
             if let Some(output) = output_opt {
-                let mut out_file = fs::File::create(output).map_err(|err| err.to_string())?;
-                out_file
-                    .write_all(output_content.as_bytes())
-                    .map_err(|err| err.to_string())?;
+                write_file_atomic(&output, output_content.as_bytes())?;
             } else {
                 println!("{}", output_content);
             }
@@ -235,10 +278,16 @@ impl<'a> CliSubCommand for MockTxSubCommand<'a> {
     }
 }
 
-struct Loader<'a> {
+pub(crate) struct Loader<'a> {
     rpc_client: &'a mut HttpRpcClient,
 }
 
+impl<'a> Loader<'a> {
+    pub(crate) fn new(rpc_client: &'a mut HttpRpcClient) -> Loader<'a> {
+        Loader { rpc_client }
+    }
+}
+
 impl<'a> MockResourceLoader for Loader<'a> {
     fn get_header(&mut self, hash: H256) -> Result<Option<HeaderView>, String> {
         self.rpc_client
@@ -1,8 +1,12 @@
 pub mod account;
 pub mod dao;
+pub mod deploy;
+pub mod index;
 pub mod mock_tx;
 pub mod molecule;
+pub mod node;
 pub mod rpc;
+pub mod sudt;
 #[cfg(unix)]
 pub mod tui;
 pub mod tx;
@@ -14,9 +18,13 @@ pub use self::tui::TuiSubCommand;
 
 pub use account::AccountSubCommand;
 pub use dao::DAOSubCommand;
+pub use deploy::DeploySubCommand;
+pub use index::IndexSubCommand;
 pub use mock_tx::MockTxSubCommand;
 pub use molecule::MoleculeSubCommand;
+pub use node::NodeSubCommand;
 pub use rpc::RpcSubCommand;
+pub use sudt::SudtSubCommand;
 pub use tx::TxSubCommand;
 pub use util::UtilSubCommand;
 pub use wallet::{start_index_thread, WalletSubCommand};
@@ -7,6 +7,7 @@ use ckb_hash::blake2b_256;
 use ckb_jsonrpc_types::{self as json_types, JsonBytes};
 use ckb_types::{bytes::Bytes, packed, prelude::*, H256};
 use clap::{App, Arg, ArgMatches, SubCommand};
+use faster_hex::hex_string;
 use serde_derive::{Deserialize, Serialize};
 
 use super::CliSubCommand;
@@ -15,6 +16,7 @@ use crate::utils::{
     printer::{OutputFormat, Printable},
 };
 
+#[derive(Default)]
 pub struct MoleculeSubCommand {}
 
 impl MoleculeSubCommand {
@@ -46,6 +48,17 @@ impl MoleculeSubCommand {
             .default_value("binary")
             .possible_values(&["binary", "hash"])
             .help("Serialize output type");
+        let arg_types = Arg::with_name("types")
+            .long("types")
+            .takes_value(true)
+            .required(true)
+            .help("Comma separated primitive types: u8, u16, u32, u64, u128, byte20, byte32, bytes (molecule Bytes), raw (hex without length header)");
+        let arg_values = Arg::with_name("values")
+            .long("values")
+            .takes_value(true)
+            .required(true)
+            .allow_hyphen_values(true)
+            .help("Comma separated values match the types (integer in decimal, binary in hex)");
 
         SubCommand::with_name(name)
             .about("Molecule encode/decode utilities")
@@ -58,6 +71,11 @@ impl MoleculeSubCommand {
                     .about("Encode molecule type from json to binary")
                     .arg(arg_type.clone())
                     .arg(arg_json_path.clone())
+                    .arg(arg_serialize_output_type.clone()),
+                SubCommand::with_name("encode-args")
+                    .about("Encode script args/cell data from primitive types (concatenated, without schema)")
+                    .arg(arg_types)
+                    .arg(arg_values)
                     .arg(arg_serialize_output_type),
                 SubCommand::with_name("default")
                     .about("Print default json structure of certain molecule type")
@@ -84,76 +102,7 @@ impl CliSubCommand for MoleculeSubCommand {
             ("decode", Some(m)) => {
                 let type_name = m.value_of("type").unwrap();
                 let binary: Vec<u8> = HexParser.from_matches(m, "binary-hex")?;
-                match type_name {
-                    "Uint32" => packed::Uint32::from_slice(&binary)
-                        .map(|s| Unpack::<u32>::unpack(&s).to_string())
-                        .map_err(|err| format!("Invalid data, error: {}", err)),
-                    "Uint64" => packed::Uint64::from_slice(&binary)
-                        .map(|s| Unpack::<u64>::unpack(&s).to_string())
-                        .map_err(|err| format!("Invalid data, error: {}", err)),
-                    "Uint128" => packed::Uint128::from_slice(&binary)
-                        .map(|s| Unpack::<u128>::unpack(&s).to_string())
-                        .map_err(|err| format!("Invalid data, error: {}", err)),
-                    "Bytes" => decode_simple::<packed::Bytes>(&binary),
-                    "BytesVec" => decode_simple::<packed::BytesVec>(&binary),
-                    "Byte32Vec" => decode_simple::<packed::Byte32Vec>(&binary),
-
-                    "UncleBlockVec" => decode_simple::<packed::UncleBlockVec>(&binary),
-                    "TransactionVec" => decode_simple::<packed::TransactionVec>(&binary),
-                    "ProposalShortIdVec" => decode_simple::<packed::ProposalShortIdVec>(&binary),
-                    "CellDepVec" => decode_simple::<packed::CellDepVec>(&binary),
-                    "CellInputVec" => decode_simple::<packed::CellInputVec>(&binary),
-                    "CellOutputVec" => decode_simple::<packed::CellOutputVec>(&binary),
-                    "Script" => {
-                        decode_to_json::<packed::Script, json_types::Script>(&binary, format, color)
-                    }
-                    "OutPoint" => decode_to_json::<packed::OutPoint, json_types::OutPoint>(
-                        &binary, format, color,
-                    ),
-                    "CellInput" => decode_to_json::<packed::CellInput, json_types::CellInput>(
-                        &binary, format, color,
-                    ),
-                    "CellOutput" => decode_to_json::<packed::CellOutput, json_types::CellOutput>(
-                        &binary, format, color,
-                    ),
-                    "CellDep" => decode_to_json::<packed::CellDep, json_types::CellDep>(
-                        &binary, format, color,
-                    ),
-                    "RawTransaction" => decode_to_json::<packed::RawTransaction, RawTransaction>(
-                        &binary, format, color,
-                    ),
-                    "Transaction" => {
-                        decode_to_json::<packed::Transaction, json_types::Transaction>(
-                            &binary, format, color,
-                        )
-                    }
-                    "RawHeader" => {
-                        decode_to_json::<packed::RawHeader, RawHeader>(&binary, format, color)
-                    }
-                    "Header" => {
-                        decode_to_json::<packed::Header, json_types::Header>(&binary, format, color)
-                    }
-                    "UncleBlock" => {
-                        decode_to_json::<packed::WitnessArgs, WitnessArgs>(&binary, format, color)
-                    }
-                    "Block" => {
-                        decode_to_json::<packed::WitnessArgs, WitnessArgs>(&binary, format, color)
-                    }
-                    "CellbaseWitness" => {
-                        decode_to_json::<packed::CellbaseWitness, CellbaseWitness>(
-                            &binary, format, color,
-                        )
-                    }
-                    "WitnessArgs" => {
-                        decode_to_json::<packed::WitnessArgs, WitnessArgs>(&binary, format, color)
-                    }
-                    // In extensions.mol
-                    "OutPointVec" => {
-                        decode_to_json::<packed::OutPointVec, OutPoints>(&binary, format, color)
-                    }
-
-                    _ => Err(format!("Unsupported molecule type name: {}", type_name)),
-                }
+                decode_molecule(type_name, &binary, format, color)
             }
             ("encode", Some(m)) => {
                 let type_name = m.value_of("type").unwrap();
@@ -161,64 +110,28 @@ impl CliSubCommand for MoleculeSubCommand {
                 let json_path: PathBuf = FilePathParser::new(true).from_matches(m, "json-path")?;
                 let content = fs::read_to_string(json_path).map_err(|err| err.to_string())?;
 
-                let binary_result = match type_name {
-                    "Script" => {
-                        encode_from_json::<packed::Script, json_types::Script>(content.as_str())
-                    }
-                    "OutPoint" => {
-                        encode_from_json::<packed::OutPoint, json_types::OutPoint>(content.as_str())
-                    }
-                    "CellInput" => encode_from_json::<packed::CellInput, json_types::CellInput>(
-                        content.as_str(),
-                    ),
-                    "CellOutput" => encode_from_json::<packed::CellOutput, json_types::CellOutput>(
-                        content.as_str(),
-                    ),
-                    "CellDep" => {
-                        encode_from_json::<packed::CellDep, json_types::CellDep>(content.as_str())
-                    }
-                    "RawTransaction" => {
-                        encode_from_json::<packed::RawTransaction, RawTransaction>(content.as_str())
-                    }
-                    "Transaction" => {
-                        encode_from_json::<packed::Transaction, json_types::Transaction>(
-                            content.as_str(),
-                        )
-                    }
-                    "RawHeader" => {
-                        encode_from_json::<packed::RawHeader, RawHeader>(content.as_str())
-                    }
-                    "Header" => {
-                        encode_from_json::<packed::Header, json_types::Header>(content.as_str())
-                    }
-                    "UncleBlock" => {
-                        encode_from_json::<packed::WitnessArgs, WitnessArgs>(content.as_str())
-                    }
-                    "Block" => {
-                        encode_from_json::<packed::WitnessArgs, WitnessArgs>(content.as_str())
-                    }
-                    "CellbaseWitness" => {
-                        encode_from_json::<packed::CellbaseWitness, CellbaseWitness>(
-                            content.as_str(),
-                        )
-                    }
-                    "WitnessArgs" => {
-                        encode_from_json::<packed::WitnessArgs, WitnessArgs>(content.as_str())
-                    }
-                    // In extensions.mol
-                    "OutPointVec" => {
-                        encode_from_json::<packed::OutPointVec, OutPoints>(content.as_str())
-                    }
-                    _ => Err(format!("Unsupported molecule type name: {}", type_name)),
-                };
-
-                let binary = binary_result?;
-                let output = match output_type {
-                    "binary" => format!("0x{}", hex_string(&binary).unwrap()),
-                    "hash" => format!("0x{}", hex_string(&blake2b_256(&binary)).unwrap()),
-                    _ => panic!("Invalid output type"),
-                };
-                Ok(output)
+                let binary = encode_molecule(type_name, &content)?;
+                render_binary(&binary, output_type)
+            }
+            ("encode-args", Some(m)) => {
+                let types = m.value_of("types").unwrap().split(',').collect::<Vec<_>>();
+                let values = m.value_of("values").unwrap().split(',').collect::<Vec<_>>();
+                let output_type = m.value_of("output-type").unwrap();
+                if types.len() != values.len() {
+                    return Err(format!(
+                        "The number of types ({}) and values ({}) not match",
+                        types.len(),
+                        values.len()
+                    ));
+                }
+                let mut binary = Vec::new();
+                for (type_name, value) in types.iter().zip(values.iter()) {
+                    let data = encode_primitive(type_name.trim(), value.trim()).map_err(|err| {
+                        format!("Encode {} as {} failed: {}", value, type_name, err)
+                    })?;
+                    binary.extend_from_slice(&data);
+                }
+                render_binary(&binary, output_type)
             }
             ("default", Some(m)) => {
                 let type_name = m.value_of("type").unwrap();
@@ -276,6 +189,139 @@ impl CliSubCommand for MoleculeSubCommand {
     }
 }
 
+/// Decode the molecule binary of `type_name` (the `molecule decode` command),
+/// the binary is untrusted: an invalid one is an error, never a panic.
+pub fn decode_molecule(
+    type_name: &str,
+    binary: &[u8],
+    format: OutputFormat,
+    color: bool,
+) -> Result<String, String> {
+    match type_name {
+        "Uint32" => packed::Uint32::from_slice(&binary)
+            .map(|s| Unpack::<u32>::unpack(&s).to_string())
+            .map_err(|err| format!("Invalid data, error: {}", err)),
+        "Uint64" => packed::Uint64::from_slice(&binary)
+            .map(|s| Unpack::<u64>::unpack(&s).to_string())
+            .map_err(|err| format!("Invalid data, error: {}", err)),
+        "Uint128" => packed::Uint128::from_slice(&binary)
+            .map(|s| Unpack::<u128>::unpack(&s).to_string())
+            .map_err(|err| format!("Invalid data, error: {}", err)),
+        "Bytes" => decode_simple::<packed::Bytes>(&binary),
+        "BytesVec" => decode_simple::<packed::BytesVec>(&binary),
+        "Byte32Vec" => decode_simple::<packed::Byte32Vec>(&binary),
+
+        "UncleBlockVec" => decode_simple::<packed::UncleBlockVec>(&binary),
+        "TransactionVec" => decode_simple::<packed::TransactionVec>(&binary),
+        "ProposalShortIdVec" => decode_simple::<packed::ProposalShortIdVec>(&binary),
+        "CellDepVec" => decode_simple::<packed::CellDepVec>(&binary),
+        "CellInputVec" => decode_simple::<packed::CellInputVec>(&binary),
+        "CellOutputVec" => decode_simple::<packed::CellOutputVec>(&binary),
+        "Script" => decode_to_json::<packed::Script, json_types::Script>(&binary, format, color),
+        "OutPoint" => {
+            decode_to_json::<packed::OutPoint, json_types::OutPoint>(&binary, format, color)
+        }
+        "CellInput" => {
+            decode_to_json::<packed::CellInput, json_types::CellInput>(&binary, format, color)
+        }
+        "CellOutput" => {
+            decode_to_json::<packed::CellOutput, json_types::CellOutput>(&binary, format, color)
+        }
+        "CellDep" => decode_to_json::<packed::CellDep, json_types::CellDep>(&binary, format, color),
+        "RawTransaction" => {
+            decode_to_json::<packed::RawTransaction, RawTransaction>(&binary, format, color)
+        }
+        "Transaction" => {
+            decode_to_json::<packed::Transaction, json_types::Transaction>(&binary, format, color)
+        }
+        "RawHeader" => decode_to_json::<packed::RawHeader, RawHeader>(&binary, format, color),
+        "Header" => decode_to_json::<packed::Header, json_types::Header>(&binary, format, color),
+        "UncleBlock" => decode_to_json::<packed::WitnessArgs, WitnessArgs>(&binary, format, color),
+        "Block" => decode_to_json::<packed::WitnessArgs, WitnessArgs>(&binary, format, color),
+        "CellbaseWitness" => {
+            decode_to_json::<packed::CellbaseWitness, CellbaseWitness>(&binary, format, color)
+        }
+        "WitnessArgs" => decode_to_json::<packed::WitnessArgs, WitnessArgs>(&binary, format, color),
+        // In extensions.mol
+        "OutPointVec" => decode_to_json::<packed::OutPointVec, OutPoints>(&binary, format, color),
+
+        _ => Err(format!("Unsupported molecule type name: {}", type_name)),
+    }
+}
+
+/// Encode the json of `type_name` to molecule binary (the `molecule encode`
+/// command)
+pub fn encode_molecule(type_name: &str, content: &str) -> Result<Bytes, String> {
+    match type_name {
+        "Script" => encode_from_json::<packed::Script, json_types::Script>(content),
+        "OutPoint" => encode_from_json::<packed::OutPoint, json_types::OutPoint>(content),
+        "CellInput" => encode_from_json::<packed::CellInput, json_types::CellInput>(content),
+        "CellOutput" => encode_from_json::<packed::CellOutput, json_types::CellOutput>(content),
+        "CellDep" => encode_from_json::<packed::CellDep, json_types::CellDep>(content),
+        "RawTransaction" => encode_from_json::<packed::RawTransaction, RawTransaction>(content),
+        "Transaction" => encode_from_json::<packed::Transaction, json_types::Transaction>(content),
+        "RawHeader" => encode_from_json::<packed::RawHeader, RawHeader>(content),
+        "Header" => encode_from_json::<packed::Header, json_types::Header>(content),
+        "UncleBlock" => encode_from_json::<packed::WitnessArgs, WitnessArgs>(content),
+        "Block" => encode_from_json::<packed::WitnessArgs, WitnessArgs>(content),
+        "CellbaseWitness" => encode_from_json::<packed::CellbaseWitness, CellbaseWitness>(content),
+        "WitnessArgs" => encode_from_json::<packed::WitnessArgs, WitnessArgs>(content),
+        // In extensions.mol
+        "OutPointVec" => encode_from_json::<packed::OutPointVec, OutPoints>(content),
+        _ => Err(format!("Unsupported molecule type name: {}", type_name)),
+    }
+}
+
+fn render_binary(binary: &[u8], output_type: &str) -> Result<String, String> {
+    let binary = match output_type {
+        "binary" => binary.to_vec(),
+        "hash" => blake2b_256(binary).to_vec(),
+        _ => return Err(format!("Invalid output type: {}", output_type)),
+    };
+    hex_string(&binary)
+        .map(|hex| format!("0x{}", hex))
+        .map_err(|err| err.to_string())
+}
+
+pub fn encode_primitive(type_name: &str, value: &str) -> Result<Bytes, String> {
+    let parse_fixed = |len: usize| -> Result<Bytes, String> {
+        let data = HexParser.parse(value)?;
+        if data.len() != len {
+            return Err(format!("expected {} bytes, got {}", len, data.len()));
+        }
+        Ok(Bytes::from(data))
+    };
+    match type_name {
+        "u8" => value
+            .parse::<u8>()
+            .map(|v| Bytes::from(vec![v]))
+            .map_err(|err| err.to_string()),
+        "u16" => value
+            .parse::<u16>()
+            .map(|v| Bytes::from(&v.to_le_bytes()[..]))
+            .map_err(|err| err.to_string()),
+        "u32" => value
+            .parse::<u32>()
+            .map(|v| v.pack().as_bytes())
+            .map_err(|err| err.to_string()),
+        "u64" => value
+            .parse::<u64>()
+            .map(|v| v.pack().as_bytes())
+            .map_err(|err| err.to_string()),
+        "u128" => value
+            .parse::<u128>()
+            .map(|v| v.pack().as_bytes())
+            .map_err(|err| err.to_string()),
+        "byte20" => parse_fixed(20),
+        "byte32" => parse_fixed(32),
+        "bytes" => HexParser
+            .parse(value)
+            .map(|data| Bytes::from(data).pack().as_bytes()),
+        "raw" => HexParser.parse(value).map(Bytes::from),
+        _ => Err(format!("unsupported type: {}", type_name)),
+    }
+}
+
 fn decode_simple<T: Entity + Display>(binary: &[u8]) -> Result<String, String> {
     T::from_slice(binary)
         .map(|s| s.to_string())
@@ -284,15 +330,84 @@ fn decode_simple<T: Entity + Display>(binary: &[u8]) -> Result<String, String> {
 
 fn decode_to_json<T, J>(binary: &[u8], format: OutputFormat, color: bool) -> Result<String, String>
 where
-    T: Entity + Into<J>,
+    T: Entity + CheckJson + Into<J>,
     J: serde::Serialize,
 {
-    let json: J = T::from_slice(&binary)
-        .map(Into::into)
-        .map_err(|err| err.to_string())?;
+    let entity = T::from_slice(&binary).map_err(|err| err.to_string())?;
+    entity.check_json()?;
+    let json: J = entity.into();
     Ok(json.render(format, color))
 }
 
+// The conversions of ckb-jsonrpc-types panic on a `hash_type` or `dep_type`
+// byte they do not know (eg. `data1` of CKB2021), the bytes are checked
+// before converting
+trait CheckJson {
+    fn check_json(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl CheckJson for packed::Script {
+    fn check_json(&self) -> Result<(), String> {
+        match self.hash_type().as_slice()[0] {
+            0x00 | 0x01 => Ok(()),
+            0x02 => Err("Unsupported hash_type: data1 (0x02)".to_string()),
+            value => Err(format!("Invalid hash_type: {:#04x}", value)),
+        }
+    }
+}
+
+impl CheckJson for packed::CellOutput {
+    fn check_json(&self) -> Result<(), String> {
+        self.lock().check_json()?;
+        if let Some(type_) = self.type_().to_opt() {
+            type_.check_json()?;
+        }
+        Ok(())
+    }
+}
+
+impl CheckJson for packed::CellDep {
+    fn check_json(&self) -> Result<(), String> {
+        match self.dep_type().as_slice()[0] {
+            0x00 | 0x01 => Ok(()),
+            value => Err(format!("Invalid dep_type: {:#04x}", value)),
+        }
+    }
+}
+
+impl CheckJson for packed::RawTransaction {
+    fn check_json(&self) -> Result<(), String> {
+        for cell_dep in self.cell_deps().into_iter() {
+            cell_dep.check_json()?;
+        }
+        for output in self.outputs().into_iter() {
+            output.check_json()?;
+        }
+        Ok(())
+    }
+}
+
+impl CheckJson for packed::Transaction {
+    fn check_json(&self) -> Result<(), String> {
+        self.raw().check_json()
+    }
+}
+
+impl CheckJson for packed::CellbaseWitness {
+    fn check_json(&self) -> Result<(), String> {
+        self.lock().check_json()
+    }
+}
+
+impl CheckJson for packed::OutPoint {}
+impl CheckJson for packed::CellInput {}
+impl CheckJson for packed::RawHeader {}
+impl CheckJson for packed::Header {}
+impl CheckJson for packed::WitnessArgs {}
+impl CheckJson for packed::OutPointVec {}
+
 fn encode_from_json<'a, T, J>(content: &'a str) -> Result<Bytes, String>
 where
     T: Entity + From<J>,
@@ -485,3 +600,38 @@ impl From<OutPoints> for packed::OutPointVec {
         json.items.into_iter().map(packed::OutPoint::from).pack()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_unknown_hash_type() {
+        let script = |hash_type: u8| {
+            packed::Script::new_builder()
+                .hash_type(packed::Byte::new(hash_type))
+                .build()
+        };
+        let decode = |type_name: &str, binary: &[u8]| {
+            decode_molecule(type_name, binary, OutputFormat::Json, false)
+        };
+        assert!(decode("Script", script(1).as_slice()).is_ok());
+        for hash_type in &[2, 0xff] {
+            assert!(decode("Script", script(*hash_type).as_slice()).is_err());
+            let output = packed::CellOutput::new_builder()
+                .lock(script(*hash_type))
+                .build();
+            assert!(decode("CellOutput", output.as_slice()).is_err());
+            let tx = packed::Transaction::default();
+            let raw = tx.raw().as_builder().outputs(vec![output].pack()).build();
+            let tx = tx.as_builder().raw(raw).build();
+            assert!(decode("Transaction", tx.as_slice()).is_err());
+        }
+        let cell_dep = packed::CellDep::new_builder()
+            .dep_type(packed::Byte::new(2))
+            .build();
+        assert!(decode("CellDep", cell_dep.as_slice()).is_err());
+        assert!(render_binary(&[1, 2], "base64").is_err());
+        assert_eq!(render_binary(&[1, 2], "binary").unwrap(), "0x0102");
+    }
+}
@@ -0,0 +1,253 @@
+use std::time::Duration;
+
+use chrono::{TimeZone, Utc};
+use ckb_sdk::HttpRpcClient;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use ipnetwork::IpNetwork;
+use serde_json::Value;
+
+use super::CliSubCommand;
+use crate::utils::{
+    arg_parser::{ArgParser, DurationParser, FromStrParser},
+    other::parse_hex_u64,
+    printer::{render_table, OutputFormat, Printable},
+};
+
+pub struct NodeSubCommand<'a> {
+    rpc_client: &'a mut HttpRpcClient,
+}
+
+impl<'a> NodeSubCommand<'a> {
+    pub fn new(rpc_client: &'a mut HttpRpcClient) -> NodeSubCommand<'a> {
+        NodeSubCommand { rpc_client }
+    }
+
+    pub fn subcommand() -> App<'static, 'static> {
+        let arg_table = Arg::with_name("table")
+            .long("table")
+            .help("Render as a plain text table");
+        let arg_address = Arg::with_name("address")
+            .long("address")
+            .takes_value(true)
+            .required(true)
+            .validator(|input| FromStrParser::<IpNetwork>::new().validate(input))
+            .help("The IP/Subnet with an optional netmask (default is /32 = single IP)");
+        SubCommand::with_name("node")
+            .about("Peers, banned addresses and sync state of the connected node")
+            .subcommands(vec![
+                SubCommand::with_name("peers")
+                    .about("List connected peers")
+                    .arg(arg_table.clone()),
+                SubCommand::with_name("banned")
+                    .about("List banned IPs/Subnets")
+                    .arg(arg_table.clone()),
+                SubCommand::with_name("ban")
+                    .about("Ban an IP/Subnet")
+                    .arg(arg_address.clone())
+                    .arg(
+                        Arg::with_name("ban-time")
+                            .long("ban-time")
+                            .takes_value(true)
+                            .default_value("24h")
+                            .validator(|input| DurationParser.validate(input))
+                            .help("How long the IP/Subnet is banned"),
+                    )
+                    .arg(
+                        Arg::with_name("reason")
+                            .long("reason")
+                            .takes_value(true)
+                            .help("Ban reason"),
+                    ),
+                SubCommand::with_name("unban")
+                    .about("Remove an IP/Subnet from the banned list")
+                    .arg(arg_address),
+                SubCommand::with_name("sync-state")
+                    .about("Show the block synchronization state of the node")
+                    .arg(arg_table),
+            ])
+    }
+}
+
+impl<'a> CliSubCommand for NodeSubCommand<'a> {
+    fn process(
+        &mut self,
+        matches: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        _debug: bool,
+    ) -> Result<String, String> {
+        // Untyped calls, the fields are changed between node versions
+        match matches.subcommand() {
+            ("peers", Some(m)) => {
+                let peers = self.rpc_client.call("get_peers", Vec::new())?;
+                let rows = peers
+                    .as_array()
+                    .map(|peers| peers.iter().map(peer_row).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                Ok(render_rows(
+                    rows,
+                    &[
+                        "node_id",
+                        "direction",
+                        "address",
+                        "version",
+                        "connected",
+                        "best_known_block",
+                    ],
+                    m.is_present("table"),
+                    format,
+                    color,
+                ))
+            }
+            ("banned", Some(m)) => {
+                let banned = self.rpc_client.call("get_banned_addresses", Vec::new())?;
+                let rows = banned
+                    .as_array()
+                    .map(|banned| {
+                        banned
+                            .iter()
+                            .map(|item| {
+                                serde_json::json!({
+                                    "address": item["address"],
+                                    "ban_until": parse_hex_u64(&item["ban_until"]).map(rfc3339),
+                                    "reason": item["ban_reason"],
+                                    "created_at": parse_hex_u64(&item["created_at"]).map(rfc3339),
+                                })
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+                Ok(render_rows(
+                    rows,
+                    &["address", "ban_until", "reason", "created_at"],
+                    m.is_present("table"),
+                    format,
+                    color,
+                ))
+            }
+            ("ban", Some(m)) => {
+                let address: IpNetwork =
+                    FromStrParser::<IpNetwork>::new().from_matches(m, "address")?;
+                let ban_time: Duration = DurationParser.from_matches(m, "ban-time")?;
+                self.rpc_client.set_ban(
+                    address.to_string(),
+                    "insert".to_string(),
+                    Some(ban_time.as_millis() as u64),
+                    Some(false),
+                    m.value_of("reason").map(ToOwned::to_owned),
+                )?;
+                Ok(String::from("ok"))
+            }
+            ("unban", Some(m)) => {
+                let address: IpNetwork =
+                    FromStrParser::<IpNetwork>::new().from_matches(m, "address")?;
+                self.rpc_client.set_ban(
+                    address.to_string(),
+                    "delete".to_string(),
+                    None,
+                    None,
+                    None,
+                )?;
+                Ok(String::from("ok"))
+            }
+            ("sync-state", Some(m)) => {
+                let state = self
+                    .rpc_client
+                    .call("sync_state", Vec::new())
+                    .map_err(|err| {
+                        format!("{} (sync_state RPC requires node v0.39 or later)", err)
+                    })?;
+                let state = match state {
+                    Value::Object(fields) => fields
+                        .into_iter()
+                        .map(|(key, value)| {
+                            let value = parse_hex_u64(&value).map(Value::from).unwrap_or(value);
+                            (key, value)
+                        })
+                        .collect::<serde_json::Map<_, _>>(),
+                    _ => return Err(format!("Invalid sync state: {}", state)),
+                };
+                if m.is_present("table") {
+                    let rows = state
+                        .iter()
+                        .map(|(key, value)| vec![key.clone(), cell_text(value)])
+                        .collect::<Vec<_>>();
+                    Ok(render_table(&["FIELD", "VALUE"], &rows))
+                } else {
+                    Ok(Value::Object(state).render(format, color))
+                }
+            }
+            _ => Err(matches.usage().to_owned()),
+        }
+    }
+}
+
+fn peer_row(peer: &Value) -> Value {
+    let direction = match peer["is_outbound"].as_bool() {
+        Some(true) => Some("outbound"),
+        Some(false) => Some("inbound"),
+        None => None,
+    };
+    serde_json::json!({
+        "node_id": peer["node_id"],
+        "direction": direction,
+        "address": peer["addresses"][0]["address"],
+        "version": peer["version"],
+        "connected": parse_hex_u64(&peer["connected_duration"]).map(human_duration),
+        "best_known_block": parse_hex_u64(&peer["sync_state"]["best_known_header_number"]),
+    })
+}
+
+fn render_rows(
+    rows: Vec<Value>,
+    columns: &[&str],
+    table: bool,
+    format: OutputFormat,
+    color: bool,
+) -> String {
+    if !table {
+        return rows.render(format, color);
+    }
+    let headers = columns
+        .iter()
+        .map(|column| column.to_uppercase())
+        .collect::<Vec<_>>();
+    let cells = rows
+        .iter()
+        .map(|row| {
+            columns
+                .iter()
+                .map(|column| cell_text(&row[column]))
+                .collect()
+        })
+        .collect::<Vec<_>>();
+    render_table(
+        &headers.iter().map(String::as_str).collect::<Vec<_>>(),
+        &cells,
+    )
+}
+
+fn cell_text(value: &Value) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn rfc3339(timestamp: u64) -> String {
+    Utc.timestamp_millis(timestamp as i64).to_rfc3339()
+}
+
+// Duration in milliseconds, eg: "2d 3h", "5m 12s"
+fn human_duration(millis: u64) -> String {
+    let seconds = millis / 1000;
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m {}s", minutes, seconds % 60)
+    }
+}
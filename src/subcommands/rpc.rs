@@ -4,7 +4,8 @@ use ckb_jsonrpc_types::{
 use ckb_sdk::{
     rpc::{
         BannedAddr, BlockReward, BlockView, CellOutputWithOutPoint, CellTransaction, EpochView,
-        HeaderView, LiveCell, Node, RawHttpRpcClient, TransactionWithStatus,
+        HeaderView, LiveCell, Node, RawHttpRpcClient, Subscription, TransactionWithStatus,
+        SUBSCRIPTION_TOPICS,
     },
     HttpRpcClient,
 };
@@ -14,6 +15,7 @@ use ipnetwork::IpNetwork;
 use multiaddr::Multiaddr;
 use serde_derive::{Deserialize, Serialize};
 use std::fs;
+use std::io::{self, Write};
 use std::path::PathBuf;
 use std::time::Duration;
 
@@ -245,6 +247,34 @@ impl<'a> RpcSubCommand<'a> {
                          .help("Transaction content (json format, see rpc send_transaction)")
                     )
                     .about("Broadcast transaction without verify"),
+                SubCommand::with_name("batch")
+                    .arg(
+                        Arg::with_name("file")
+                            .long("file")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FilePathParser::new(true).validate(input))
+                            .help("JSON array of requests, eg: [{\"method\": \"get_header_by_number\", \"params\": [\"0x1\"]}, ...]")
+                    )
+                    .about("Send the requests in one JSON-RPC batch (a single HTTP round trip), output the responses in the same order"),
+                // [`Subscription`]
+                SubCommand::with_name("subscribe")
+                    .arg(
+                        Arg::with_name("topic")
+                            .long("topic")
+                            .takes_value(true)
+                            .required(true)
+                            .possible_values(&SUBSCRIPTION_TOPICS)
+                            .help("The subscription topic")
+                    )
+                    .arg(
+                        Arg::with_name("ws-url")
+                            .long("ws-url")
+                            .takes_value(true)
+                            .default_value("ws://127.0.0.1:28114")
+                            .help("The WebSocket RPC url of node (`ws_listen_address` in ckb.toml)")
+                    )
+                    .about("Subscribe to a topic and print the events as JSON lines until interrupted"),
             ])
     }
 }
@@ -265,12 +295,11 @@ impl<'a> CliSubCommand for RpcSubCommand<'a> {
                 let hash: H256 = FixedHashParser::<H256>::default().from_matches(m, "hash")?;
 
                 if is_raw_data {
+                    // Untyped, keep the fields unknown by the decoders (eg:
+                    // extension of CKB2021)
                     let resp = self
-                        .raw_rpc_client
-                        .get_block(hash)
-                        .call()
-                        .map(RawOptionBlockView)
-                        .map_err(|err| err.to_string())?;
+                        .rpc_client
+                        .call("get_block", vec![serde_json::json!(hash)])?;
                     Ok(resp.render(format, color))
                 } else {
                     let resp = self.rpc_client.get_block(hash).map(OptionBlockView)?;
@@ -282,12 +311,10 @@ impl<'a> CliSubCommand for RpcSubCommand<'a> {
                 let number: u64 = FromStrParser::<u64>::default().from_matches(m, "number")?;
 
                 if is_raw_data {
-                    let resp = self
-                        .raw_rpc_client
-                        .get_block_by_number(BlockNumber::from(number))
-                        .call()
-                        .map(RawOptionBlockView)
-                        .map_err(|err| err.to_string())?;
+                    let resp = self.rpc_client.call(
+                        "get_block_by_number",
+                        vec![serde_json::json!(BlockNumber::from(number))],
+                    )?;
                     Ok(resp.render(format, color))
                 } else {
                     let resp = self
@@ -480,11 +507,8 @@ impl<'a> CliSubCommand for RpcSubCommand<'a> {
 
                 if is_raw_data {
                     let resp = self
-                        .raw_rpc_client
-                        .get_transaction(hash)
-                        .call()
-                        .map(RawOptionTransactionWithStatus)
-                        .map_err(|err| err.to_string())?;
+                        .rpc_client
+                        .call("get_transaction", vec![serde_json::json!(hash)])?;
                     Ok(resp.render(format, color))
                 } else {
                     let resp = self
@@ -699,6 +723,44 @@ impl<'a> CliSubCommand for RpcSubCommand<'a> {
                 let resp = self.rpc_client.broadcast_transaction(tx.into())?;
                 Ok(resp.render(format, color))
             }
+            ("batch", Some(m)) => {
+                let path: PathBuf = FilePathParser::new(true).from_matches(m, "file")?;
+                let content = fs::read_to_string(path).map_err(|err| err.to_string())?;
+                let requests: Vec<serde_json::Value> = serde_json::from_str(&content)
+                    .map_err(|err| format!("Invalid requests file: {}", err))?;
+                let methods = requests
+                    .iter()
+                    .map(|request| request["method"].clone())
+                    .collect::<Vec<_>>();
+                let responses = self.rpc_client.batch(requests)?;
+                let resp = methods
+                    .into_iter()
+                    .zip(responses)
+                    .map(|(method, response)| {
+                        let mut item = serde_json::json!({ "method": method });
+                        if let Some(error) = response.get("error") {
+                            item["error"] = error.clone();
+                        } else {
+                            item["result"] = response["result"].clone();
+                        }
+                        item
+                    })
+                    .collect::<Vec<_>>();
+                Ok(resp.render(format, color))
+            }
+            // [`Subscription`]
+            ("subscribe", Some(m)) => {
+                let topic = m.value_of("topic").unwrap();
+                let ws_url = m.value_of("ws-url").unwrap();
+                let mut subscription = Subscription::subscribe(ws_url, topic)?;
+                let stdout = io::stdout();
+                while let Some(event) = subscription.next_event()? {
+                    let mut handle = stdout.lock();
+                    writeln!(handle, "{}", event).map_err(|err| err.to_string())?;
+                    handle.flush().map_err(|err| err.to_string())?;
+                }
+                Err("Subscription closed by node".to_string())
+            }
             _ => Err(matches.usage().to_owned()),
         }
     }
@@ -743,15 +805,9 @@ pub struct CellTransactions(pub Vec<CellTransaction>);
 #[derive(Serialize, Deserialize)]
 pub struct RawNodes(pub Vec<rpc_types::Node>);
 
-#[derive(Serialize, Deserialize)]
-pub struct RawOptionTransactionWithStatus(pub Option<rpc_types::TransactionWithStatus>);
-
 #[derive(Serialize, Deserialize)]
 pub struct RawCellOutputWithOutPoints(pub Vec<rpc_types::CellOutputWithOutPoint>);
 
-#[derive(Serialize, Deserialize)]
-pub struct RawOptionBlockView(pub Option<rpc_types::BlockView>);
-
 #[derive(Serialize, Deserialize)]
 pub struct RawOptionHeaderView(pub Option<rpc_types::HeaderView>);
 
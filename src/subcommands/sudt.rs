@@ -0,0 +1,500 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use ckb_index::{IndexDatabase, LiveCellInfo};
+use ckb_sdk::{
+    rpc::Transaction, wallet::KeyStore, Address, AddressPayload, CollectedCell, GenesisInfo,
+    HttpRpcClient, HumanCapacity, TransactionBuilder, SECP256K1,
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, DepType, ScriptHashType, TransactionView},
+    packed::{CellDep, CellOutput, OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use either::Either;
+
+use super::account::AccountId;
+use super::CliSubCommand;
+use crate::utils::{
+    alias::resolve_address,
+    arg,
+    arg_parser::{
+        AddressParser, ArgParser, FixedHashParser, FromStrParser, OutPointParser, PrivkeyWrapper,
+    },
+    broadcast::SendTargets,
+    index::{with_chain_index_db, IndexController},
+    on_failure::{self, OnFailure},
+    other::{
+        get_account_signer, get_fee_policy, get_genesis_info, get_live_cell,
+        get_live_cell_with_cache, get_max_mature_number, get_network_type, is_mature,
+        privkey_or_from_account,
+    },
+    policy::SigningPolicy,
+    printer::{OutputFormat, Printable},
+    tx_record::save_tx_memo,
+};
+
+// The amount of sUDT is stored in the first 16 bytes of cell data (u128 little endian)
+pub(crate) const UDT_AMOUNT_SIZE: usize = 16;
+
+pub struct SudtSubCommand<'a> {
+    rpc_client: &'a mut HttpRpcClient,
+    key_store: &'a mut KeyStore,
+    genesis_info: Option<GenesisInfo>,
+    index_dir: PathBuf,
+    index_controller: IndexController,
+    signing_policy: SigningPolicy,
+    send_targets: SendTargets,
+    on_failure: Option<OnFailure>,
+}
+
+impl<'a> SudtSubCommand<'a> {
+    pub fn new(
+        rpc_client: &'a mut HttpRpcClient,
+        key_store: &'a mut KeyStore,
+        genesis_info: Option<GenesisInfo>,
+        index_dir: PathBuf,
+        index_controller: IndexController,
+        signing_policy: SigningPolicy,
+        send_targets: SendTargets,
+    ) -> SudtSubCommand<'a> {
+        SudtSubCommand {
+            rpc_client,
+            key_store,
+            genesis_info,
+            index_dir,
+            index_controller,
+            signing_policy,
+            send_targets,
+            on_failure: None,
+        }
+    }
+
+    fn with_db<F, T>(&mut self, func: F) -> Result<T, String>
+    where
+        F: FnOnce(IndexDatabase) -> T,
+    {
+        let network_type = get_network_type(self.rpc_client)?;
+        let genesis_info = get_genesis_info(&self.genesis_info, self.rpc_client)?;
+        with_chain_index_db(
+            &self.index_dir,
+            &self.index_controller,
+            network_type,
+            genesis_info,
+            func,
+        )
+    }
+
+    pub fn subcommand() -> App<'static, 'static> {
+        let arg_udt_code_hash = arg::udt_code_hash();
+        let arg_udt_cell_dep = arg::udt_cell_dep();
+        let arg_owner = arg::udt_owner();
+        let arg_amount = Arg::with_name("amount")
+            .long("amount")
+            .takes_value(true)
+            .required(true)
+            .validator(|input| FromStrParser::<u128>::default().validate(input))
+            .help("The amount of token (u128, the decimals is not considered)");
+        let arg_privkey_path = arg::privkey_path().required_unless(arg::from_account().b.name);
+        let arg_from_account = arg::from_account()
+            .required_unless(arg::privkey_path().b.name)
+            .conflicts_with(arg::privkey_path().b.name);
+        let arg_tx_fee = arg::tx_fee().required_unless(arg::fee_rate().b.name);
+        let arg_fee_rate = arg::fee_rate().conflicts_with(arg::tx_fee().b.name);
+        let arg_change_cells = arg::change_cells();
+
+        SubCommand::with_name("sudt")
+            .about("Issue / transfer / burn / query balance of Simple UDT (with local index)")
+            .subcommands(vec![
+                SubCommand::with_name("issue")
+                    .about("Issue token to an address, the signer is the owner of the token")
+                    .arg(arg_privkey_path.clone())
+                    .arg(arg_from_account.clone())
+                    .arg(arg::to_address().required(true))
+                    .arg(arg_amount.clone())
+                    .arg(arg_udt_code_hash.clone())
+                    .arg(arg_udt_cell_dep.clone())
+                    .arg(arg_tx_fee.clone())
+                    .arg(arg_fee_rate.clone())
+                    .arg(arg::allow_high_fee())
+                    .arg(arg::on_failure())
+                    .arg(arg_change_cells.clone())
+                    .arg(arg::memo()),
+                SubCommand::with_name("transfer")
+                    .about("Transfer token to an address, the token change goes back to the sender")
+                    .arg(arg_privkey_path.clone())
+                    .arg(arg_from_account.clone())
+                    .arg(arg_owner.clone())
+                    .arg(arg::to_address().required(true))
+                    .arg(arg_amount.clone())
+                    .arg(arg_udt_code_hash.clone())
+                    .arg(arg_udt_cell_dep.clone())
+                    .arg(arg_tx_fee.clone())
+                    .arg(arg_fee_rate.clone())
+                    .arg(arg::allow_high_fee())
+                    .arg(arg::on_failure())
+                    .arg(arg_change_cells.clone())
+                    .arg(arg::memo()),
+                SubCommand::with_name("burn")
+                    .about(
+                        "Burn token of the sender, the released capacity goes back to the sender",
+                    )
+                    .arg(arg_privkey_path)
+                    .arg(arg_from_account)
+                    .arg(arg_owner.clone())
+                    .arg(arg_amount)
+                    .arg(arg_udt_code_hash.clone())
+                    .arg(arg_udt_cell_dep)
+                    .arg(arg_tx_fee)
+                    .arg(arg_fee_rate)
+                    .arg(arg::allow_high_fee())
+                    .arg(arg::on_failure())
+                    .arg(arg_change_cells)
+                    .arg(arg::memo()),
+                SubCommand::with_name("balance")
+                    .about("Get token balance of an address")
+                    .arg(arg::address().required(true))
+                    .arg(arg_owner)
+                    .arg(arg_udt_code_hash),
+            ])
+    }
+
+    // Collect live sUDT cells of `lock_script` (the amounts are read from cell
+    // data by RPC), stop when the total amount reaches `required`
+    fn collect_udt_cells(
+        &mut self,
+        type_script: &Script,
+        lock_script: &Script,
+        required: Option<u128>,
+    ) -> Result<Vec<(LiveCellInfo, u128)>, String> {
+        let lock_hash: H256 = lock_script.calc_script_hash().unpack();
+        let type_hash = type_script.calc_script_hash();
+        let infos = self.with_db(|db| {
+            db.get_live_cells_by_type(type_hash, None, |_, info| {
+                (
+                    false,
+                    info.lock_hash == lock_hash && info.data_bytes >= UDT_AMOUNT_SIZE as u64,
+                )
+            })
+        })?;
+        let mut cells = Vec::new();
+        let mut total: u128 = 0;
+        for info in infos {
+            if required.map(|value| total >= value).unwrap_or(false) {
+                break;
+            }
+            let (_, data) = get_live_cell(self.rpc_client, info.out_point(), true)?;
+            let amount = parse_udt_amount(&data)?;
+            total = total
+                .checked_add(amount)
+                .ok_or_else(|| "Token amount overflow".to_string())?;
+            cells.push((info, amount));
+        }
+        Ok(cells)
+    }
+
+    fn build_and_send(
+        &mut self,
+        m: &ArgMatches,
+        from_account: Either<PrivkeyWrapper, AccountId>,
+        sender: &AddressPayload,
+        mut builder: TransactionBuilder,
+        udt_inputs: Vec<CollectedCell>,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let cell_dep_out_point: OutPoint = OutPointParser.from_matches(m, "udt-cell-dep")?;
+        let fee_policy = get_fee_policy(m)?;
+        let change_cells: usize =
+            FromStrParser::<usize>::default().from_matches(m, "change-cells")?;
+        let memo = m.value_of("memo").map(ToOwned::to_owned);
+        builder
+            .cell_dep(
+                CellDep::new_builder()
+                    .out_point(cell_dep_out_point)
+                    .dep_type(DepType::Code.into())
+                    .build(),
+            )
+            .change_address(sender.clone())
+            .change_cells(change_cells)
+            .fee_policy(fee_policy);
+
+        let max_mature_number = get_max_mature_number(self.rpc_client)?;
+        let lock_hash = Script::from(sender).calc_script_hash();
+        let plain_cells = self.with_db(|db| {
+            db.get_live_cells_by_lock(lock_hash, None, |_, info| {
+                (
+                    false,
+                    info.type_hashes.is_none()
+                        && info.data_bytes == 0
+                        && is_mature(info, max_mature_number),
+                )
+            })
+        })?;
+        let network_type = get_network_type(self.rpc_client)?;
+        let rendered_sender = Address::new(network_type, sender.clone()).to_string();
+        // The sUDT inputs are always included, the plain cells of sender pay
+        // for the rest of outputs capacity and fee
+        let udt_capacity: u64 = udt_inputs.iter().map(|cell| cell.capacity).sum();
+        let mut collector = move |required: u64| {
+            let mut cells = udt_inputs.clone();
+            let mut from_capacity = udt_capacity;
+            for info in &plain_cells {
+                if from_capacity >= required {
+                    break;
+                }
+                from_capacity += info.capacity;
+                cells.push(CollectedCell::new(info.out_point(), info.capacity));
+            }
+            if required > from_capacity {
+                return Err(format!(
+                    "Capacity(mature) not enough: {} => {}",
+                    rendered_sender, from_capacity,
+                ));
+            }
+            Ok(cells)
+        };
+
+        let genesis_info = get_genesis_info(&self.genesis_info, self.rpc_client)?;
+        let rpc_client = &mut *self.rpc_client;
+        let mut live_cell_cache: HashMap<(OutPoint, bool), ((CellOutput, Transaction), Bytes)> =
+            Default::default();
+        let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
+            get_live_cell_with_cache(&mut live_cell_cache, rpc_client, out_point, with_data)
+                .map(|(output, _)| output)
+        };
+        let signer = get_account_signer(self.key_store, from_account)?;
+        let tx = self.signing_policy.build_transaction(
+            &builder,
+            &mut collector,
+            &mut get_live_cell_fn,
+            &genesis_info,
+            network_type,
+            signer,
+        )?;
+        self.send_transaction(tx, memo, format, color, debug)
+    }
+
+    fn send_transaction(
+        &mut self,
+        transaction: TransactionView,
+        memo: Option<String>,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let transaction_view: ckb_jsonrpc_types::TransactionView = transaction.clone().into();
+        if debug {
+            println!(
+                "[Send Transaction]:\n{}",
+                transaction_view.render(format, color)
+            );
+        }
+
+        let resp = on_failure::send_transaction(
+            self.rpc_client,
+            &transaction,
+            self.on_failure.as_ref(),
+            &self.send_targets,
+        )
+        .map_err(|err| format!("Send transaction error: {}", err))?;
+        save_tx_memo(&resp, memo)?;
+        Ok(resp.render(format, color))
+    }
+}
+
+impl<'a> CliSubCommand for SudtSubCommand<'a> {
+    fn process(
+        &mut self,
+        matches: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        if let (_, Some(m)) = matches.subcommand() {
+            self.signing_policy.allow_high_fee = m.is_present("allow-high-fee");
+            self.on_failure = OnFailure::from_matches(m)?;
+        }
+        match matches.subcommand() {
+            ("issue", Some(m)) => {
+                let from_account = privkey_or_from_account(m)?;
+                let sender = sender_payload(&from_account)?;
+                let to_address: Address =
+                    resolve_address(m.value_of("to-address").unwrap(), network_type)?;
+                let amount = amount_from_matches(m)?;
+                let type_script = udt_type_script(m, &Script::from(&sender))?;
+
+                let mut builder = TransactionBuilder::default();
+                let output = udt_output(Script::from(to_address.payload()), type_script)?;
+                builder.output(output, udt_data(amount));
+                self.build_and_send(
+                    m,
+                    from_account,
+                    &sender,
+                    builder,
+                    Vec::new(),
+                    format,
+                    color,
+                    debug,
+                )
+            }
+            ("transfer", Some(m)) | ("burn", Some(m)) => {
+                let from_account = privkey_or_from_account(m)?;
+                let sender = sender_payload(&from_account)?;
+                let owner: Address = AddressParser::default()
+                    .set_network(network_type)
+                    .from_matches(m, "owner")?;
+                let amount = amount_from_matches(m)?;
+                let type_script = udt_type_script(m, &Script::from(owner.payload()))?;
+                let sender_lock = Script::from(&sender);
+
+                let cells = self.collect_udt_cells(&type_script, &sender_lock, Some(amount))?;
+                let total: u128 = cells.iter().map(|(_, amount)| amount).sum();
+                if total < amount {
+                    return Err(format!(
+                        "Token amount not enough: required {}, found {}",
+                        amount, total
+                    ));
+                }
+
+                // Amount conservation: inputs = outputs (+ burned)
+                let mut builder = TransactionBuilder::default();
+                if matches.subcommand_name() == Some("transfer") {
+                    let to_address: Address =
+                        resolve_address(m.value_of("to-address").unwrap(), network_type)?;
+                    let output =
+                        udt_output(Script::from(to_address.payload()), type_script.clone())?;
+                    builder.output(output, udt_data(amount));
+                }
+                let rest = total - amount;
+                if rest > 0 {
+                    let output = udt_output(sender_lock, type_script)?;
+                    builder.output(output, udt_data(rest));
+                }
+                let udt_inputs = cells
+                    .iter()
+                    .map(|(info, _)| CollectedCell::new(info.out_point(), info.capacity))
+                    .collect();
+                self.build_and_send(
+                    m,
+                    from_account,
+                    &sender,
+                    builder,
+                    udt_inputs,
+                    format,
+                    color,
+                    debug,
+                )
+            }
+            ("balance", Some(m)) => {
+                let address: Address = AddressParser::default()
+                    .set_network(network_type)
+                    .from_matches(m, "address")?;
+                let owner: Address = AddressParser::default()
+                    .set_network(network_type)
+                    .from_matches(m, "owner")?;
+                let type_script = udt_type_script(m, &Script::from(owner.payload()))?;
+                let cells =
+                    self.collect_udt_cells(&type_script, &Script::from(address.payload()), None)?;
+                let amount: u128 = cells.iter().map(|(_, amount)| amount).sum();
+                let capacity: u64 = cells.iter().map(|(info, _)| info.capacity).sum();
+                let type_hash: H256 = type_script.calc_script_hash().unpack();
+                let resp = serde_json::json!({
+                    "type_hash": type_hash,
+                    // u128 may not fit in a JSON number
+                    "amount": amount.to_string(),
+                    "cells": cells.len(),
+                    "capacity": format!("{:#}", HumanCapacity::from(capacity)),
+                });
+                Ok(resp.render(format, color))
+            }
+            _ => Err(matches.usage().to_owned()),
+        }
+    }
+}
+
+// The address of signer, the token change and capacity change go back to it
+pub(crate) fn sender_payload(
+    from_account: &Either<PrivkeyWrapper, AccountId>,
+) -> Result<AddressPayload, String> {
+    match from_account {
+        Either::Left(privkey) => {
+            let pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, privkey);
+            Ok(AddressPayload::from_pubkey(&pubkey))
+        }
+        Either::Right(AccountId::SoftwareMasterKey(lock_arg)) => {
+            Ok(AddressPayload::from_pubkey_hash(lock_arg.clone()))
+        }
+        Either::Right(AccountId::LedgerId(_)) => {
+            Err("Hardware wallet is not supported by sudt subcommand yet".to_string())
+        }
+    }
+}
+
+pub(crate) fn amount_from_matches(m: &ArgMatches) -> Result<u128, String> {
+    let amount: u128 = FromStrParser::<u128>::default().from_matches(m, "amount")?;
+    if amount == 0 {
+        return Err("Token amount can not be zero".to_string());
+    }
+    Ok(amount)
+}
+
+// sUDT type script: args is the lock hash of the owner
+pub(crate) fn udt_type_script(m: &ArgMatches, owner_lock: &Script) -> Result<Script, String> {
+    let code_hash: H256 = FixedHashParser::<H256>::default().from_matches(m, "udt-code-hash")?;
+    Ok(Script::new_builder()
+        .code_hash(code_hash.pack())
+        .hash_type(ScriptHashType::Type.into())
+        .args(Bytes::from(owner_lock.calc_script_hash().as_slice().to_vec()).pack())
+        .build())
+}
+
+// The output holding the token, with the minimal capacity
+pub(crate) fn udt_output(lock: Script, type_script: Script) -> Result<CellOutput, String> {
+    let output = CellOutput::new_builder()
+        .lock(lock)
+        .type_(Some(type_script).pack())
+        .build();
+    let capacity = output
+        .occupied_capacity(Capacity::bytes(UDT_AMOUNT_SIZE).map_err(|err| err.to_string())?)
+        .map_err(|err| err.to_string())?;
+    Ok(output.as_builder().capacity(capacity.pack()).build())
+}
+
+pub(crate) fn udt_data(amount: u128) -> Bytes {
+    Bytes::from(amount.to_le_bytes().to_vec())
+}
+
+pub(crate) fn parse_udt_amount(data: &[u8]) -> Result<u128, String> {
+    if data.len() < UDT_AMOUNT_SIZE {
+        return Err(format!(
+            "Invalid sUDT cell data length: {}, expected at least {}",
+            data.len(),
+            UDT_AMOUNT_SIZE
+        ));
+    }
+    let mut amount_bytes = [0u8; UDT_AMOUNT_SIZE];
+    amount_bytes.copy_from_slice(&data[0..UDT_AMOUNT_SIZE]);
+    Ok(u128::from_le_bytes(amount_bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_udt_amount() {
+        let amount = u128::max_value() - 1;
+        assert_eq!(parse_udt_amount(&udt_data(amount)).unwrap(), amount);
+        // Extra data after the amount is allowed
+        let mut data = udt_data(100).to_vec();
+        data.extend_from_slice(&[1, 2, 3]);
+        assert_eq!(parse_udt_amount(&data).unwrap(), 100);
+        assert!(parse_udt_amount(&[0u8; 15]).is_err());
+    }
+}
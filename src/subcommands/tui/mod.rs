@@ -28,7 +28,7 @@ use ckb_types::{
 };
 
 use crate::utils::{
-    index::{IndexController, IndexRequest},
+    index::{check_index_chain, IndexController, IndexRequest},
     other::get_network_type,
 };
 use state::{start_rpc_thread, State, SummaryInfo};
@@ -508,10 +508,13 @@ fn render_top_capacity<B: Backend>(
         .split(ctx.rect);
     let lines = if index.state().read().is_processing() {
         let genesis_hash: H256 = genesis_info.header().hash().unpack();
-        let capacity_list_result = with_index_db(index_dir, genesis_hash, |backend, cf| {
-            let db =
-                IndexDatabase::from_db(backend, cf, network_type, genesis_info.clone(), false)?;
-            Ok(db.get_top_n(50))
+        let capacity_list_result = check_index_chain(&index_dir, &genesis_hash).and_then(|_| {
+            with_index_db(index_dir, genesis_hash, |backend, cf| {
+                let db =
+                    IndexDatabase::from_db(backend, cf, network_type, genesis_info.clone(), false)?;
+                Ok(db.get_top_n(50))
+            })
+            .map_err(|err| err.to_string())
         });
         match capacity_list_result {
             Ok(capacity_list) => capacity_list
@@ -539,7 +542,7 @@ fn render_top_capacity<B: Backend>(
                     ]
                 })
                 .collect::<Vec<_>>(),
-            Err(err) => vec![Text::raw(format!("Open db error: {}", err.to_string()))],
+            Err(err) => vec![Text::raw(format!("Open db error: {}", err))],
         }
     } else {
         vec![Text::raw(index.state().read().to_string())]
@@ -2,45 +2,70 @@ use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
 
+use ckb_index::LiveCellInfo;
 use ckb_jsonrpc_types as json_types;
 use ckb_jsonrpc_types::JsonBytes;
 use ckb_ledger::LedgerKeyStore;
 use ckb_sdk::{
-    constants::{MULTISIG_TYPE_HASH, SECP_SIGNATURE_SIZE},
+    calculate_fee, child_pays_for_parent_fee,
+    constants::{
+        MIN_SECP_CELL_CAPACITY, MULTISIG_TYPE_HASH, SECP_SIGNATURE_SIZE, SIGHASH_TYPE_HASH,
+    },
+    estimate_tx_size,
     rpc::Transaction,
+    serialize_signature, tx_size_in_block,
     wallet::{AbstractKeyStore, DerivationPath, KeyStore},
-    Address, AddressPayload, BoxedSignerFn, CodeHashIndex, GenesisInfo, HttpRpcClient,
-    HumanCapacity, MultisigConfig, NetworkType, TxHelper,
+    Address, AddressPayload, BoxedSignerFn, CodeHashIndex, CollectedCell, FeePolicy, GenesisInfo,
+    HttpRpcClient, HumanCapacity, MockTransactionHelper, MultisigConfig, NetworkType,
+    OmniLockConfig, TransactionBuilder, TxHelper,
 };
 use ckb_types::{
     bytes::Bytes,
-    core::Capacity,
-    packed::{self, CellOutput, OutPoint, Script},
+    core::{Capacity, DepType, ScriptHashType, TransactionView},
+    packed::{self, CellDep, CellOutput, OutPoint, Script, WitnessArgs},
     prelude::*,
     H160, H256,
 };
 use clap::{App, Arg, ArgMatches, SubCommand};
+use either::Either;
 use faster_hex::hex_string;
 use serde_derive::{Deserialize, Serialize};
 
-use super::{account::AccountId, CliSubCommand};
+use super::{
+    account::AccountId, dao::withdraw_input_capacity, mock_tx::Loader, sudt::sender_payload,
+    CliSubCommand,
+};
 use crate::utils::{
     arg,
     arg_parser::{
         AddressParser, ArgParser, CapacityParser, DerivationPathParser, FilePathParser,
-        FixedHashParser, FromAccountParser, FromStrParser, HexParser, PrivkeyPathParser,
-        PrivkeyWrapper,
+        FixedHashParser, FromAccountParser, FromStrParser, HexParser, OutPointParser, PathParser,
+        PrivkeyPathParser, PrivkeyWrapper,
     },
+    broadcast::SendTargets,
+    coin_control::display_out_point,
+    crypt::{content_hash, decrypt, encrypt, parse_gpg_fingerprint, Recipient},
+    index::IndexController,
+    index_backend::{index_backend_from_matches, CellQuery},
     key_adapter::KeyAdapter,
+    on_failure::{self, OnFailure},
     other::{
-        check_capacity, get_genesis_info, get_keystore_signer, get_live_cell,
-        get_live_cell_with_cache, get_master_key_signer_raw, get_network_type, get_privkey_signer,
-        get_to_data, read_password, serialize_signature, serialize_signature_bytes,
+        check_capacity, get_account_signer, get_fee_policy, get_genesis_info, get_keystore_signer,
+        get_live_cell, get_live_cell_with_cache, get_master_key_signer_raw, get_max_mature_number,
+        get_network_type, get_privkey_signer, get_to_data, is_mature, privkey_or_from_account,
+        read_password, remote_signer_from_matches, serialize_signature_bytes,
     },
-    printer::{OutputFormat, Printable},
+    output::write_file_atomic,
+    overlay::ChainOverlay,
+    policy::SigningPolicy,
+    printer::{render_list, OutputFormat, Printable},
+    tx_proof::{TransactionProof, TxProofFile},
+    tx_record::{load_memos, save_tx_memo, TxRecordStore},
+    tx_status::{tx_progress, ProposalWindow, TxProgress, WaitForCommitted},
 };
 
 pub struct TxSubCommand<'a> {
@@ -48,6 +73,10 @@ pub struct TxSubCommand<'a> {
     key_store: &'a mut KeyStore,
     ledger_key_store: &'a mut LedgerKeyStore,
     genesis_info: Option<GenesisInfo>,
+    index_dir: PathBuf,
+    index_controller: IndexController,
+    signing_policy: SigningPolicy,
+    send_targets: SendTargets,
 }
 
 impl<'a> TxSubCommand<'a> {
@@ -56,13 +85,137 @@ impl<'a> TxSubCommand<'a> {
         key_store: &'a mut KeyStore,
         ledger_key_store: &'a mut LedgerKeyStore,
         genesis_info: Option<GenesisInfo>,
+        index_dir: PathBuf,
+        index_controller: IndexController,
+        signing_policy: SigningPolicy,
+        send_targets: SendTargets,
     ) -> TxSubCommand<'a> {
         TxSubCommand {
             rpc_client,
             key_store,
             ledger_key_store,
             genesis_info,
+            index_dir,
+            index_controller,
+            signing_policy,
+            send_targets,
+        }
+    }
+
+    // Connect to the node only without --offline
+    fn verify_proof(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let input: PathBuf = FilePathParser::new(true).from_matches(m, "input")?;
+        let content = fs::read(&input).map_err(|err| err.to_string())?;
+        let proof_file: TxProofFile = serde_json::from_slice(&content)
+            .map_err(|err| format!("Invalid proof file: {}", err))?;
+        let (tx_hash, header) = proof_file.verify()?;
+        let block_hash: H256 = header.hash().unpack();
+        let mut resp = serde_json::json!({
+            "tx_hash": tx_hash,
+            "block_hash": block_hash,
+            "block_number": header.number(),
+            "timestamp": header.timestamp(),
+            "valid": true,
+        });
+        if !m.is_present("offline") {
+            let main_chain_hash = self.rpc_client.get_block_hash(header.number())?;
+            if main_chain_hash.as_ref() != Some(&block_hash) {
+                return Err(format!(
+                    "Block {:#x} is not in the main chain of the node",
+                    block_hash
+                ));
+            }
+            let tip = self.rpc_client.get_tip_block_number()?;
+            resp["confirmations"] = serde_json::json!(tip.saturating_sub(header.number()) + 1);
+        }
+        Ok(resp.render(format, color))
+    }
+
+    // Select the spendable cells of the address by the index backend until
+    // the inputs cover the outputs and the fee, the rest is either zero or
+    // enough for a change cell (added by `tx complete`).
+    fn add_inputs_from_address(
+        &mut self,
+        m: &ArgMatches,
+        tx_file: &PathBuf,
+        network: NetworkType,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let from_address: Address = AddressParser::default()
+            .set_network(network)
+            .from_matches(m, "from-address")?;
+        let tx_fee: u64 = CapacityParser.from_matches(m, "tx-fee")?;
+        let from_payload = from_address.payload();
+        if from_payload.code_hash() != SIGHASH_TYPE_HASH.pack()
+            && from_payload.code_hash() != MULTISIG_TYPE_HASH.pack()
+        {
+            return Err(format!(
+                "Only the inputs of sighash/multisig address can be selected: {}",
+                from_address
+            ));
+        }
+        let genesis_info = get_genesis_info(&self.genesis_info, self.rpc_client)?;
+        let max_mature_number = get_max_mature_number(self.rpc_client)?;
+        let mut index_backend = index_backend_from_matches(
+            m,
+            self.index_dir.clone(),
+            genesis_info.clone(),
+            network,
+            Arc::clone(self.index_controller.state()),
+        )?;
+        let mut helper = read_tx_file(tx_file)?;
+        check_unsigned(&helper)?;
+        let rpc_client = &mut *self.rpc_client;
+        let mut get_live_cell = |out_point, with_data| {
+            get_live_cell(rpc_client, out_point, with_data).map(|(output, _)| output)
+        };
+        let (input_total, output_total) = capacity_totals(&helper, &mut get_live_cell)?;
+        if input_total >= output_total + tx_fee {
+            return Err("The inputs already cover the outputs and the fee".to_string());
+        }
+        let required = output_total + tx_fee - input_total;
+        let inputs: HashSet<OutPoint> = helper
+            .transaction()
+            .inputs()
+            .into_iter()
+            .map(|input| input.previous_output())
+            .collect();
+
+        let cells = index_backend.get_live_cells(
+            &CellQuery::Lock(Script::from(from_payload)),
+            None,
+            &mut input_selector(required, max_mature_number, &inputs),
+        )?;
+        let collected: u64 = cells.iter().map(|info| info.capacity).sum();
+        if !covers_required(collected, required) {
+            return Err(format!(
+                "Capacity(mature) not enough: {} => {}, required: {} (or {} with a change cell)",
+                from_address,
+                HumanCapacity(collected),
+                HumanCapacity(required),
+                HumanCapacity(required + MIN_SECP_CELL_CAPACITY),
+            ));
+        }
+        for info in &cells {
+            helper.add_input(info.out_point(), None, &mut get_live_cell, &genesis_info)?;
         }
+        capacity_totals(&helper, &mut get_live_cell)?;
+        write_tx_file(tx_file, helper, network)?;
+        let resp = serde_json::json!({
+            "inputs": cells
+                .iter()
+                .map(|info| display_out_point(&info.out_point()))
+                .collect::<Vec<_>>(),
+            "capacity": format!("{:#}", HumanCapacity(collected)),
+            "change": format!("{:#}", HumanCapacity(collected - required)),
+        });
+        Ok(resp.render(format, color))
     }
 
     pub fn subcommand(name: &'static str) -> App<'static, 'static> {
@@ -72,6 +225,11 @@ impl<'a> TxSubCommand<'a> {
             .validator(|input| FilePathParser::new(false).validate(input))
             .required(true)
             .help("Multisig transaction data file (format: json)");
+        let arg_change_path = Arg::with_name("change-path")
+            .long("change-path")
+            .takes_value(true)
+            .validator(|input| FromStrParser::<DerivationPath>::new().validate(input))
+            .help("The derivation path of the change output, shown as change by ledger (default: no change, the outputs of the signing address are shown as change)");
         let arg_sighash_address = Arg::with_name("sighash-address")
             .long("sighash-address")
             .takes_value(true)
@@ -91,6 +249,12 @@ impl<'a> TxSubCommand<'a> {
             .default_value("1")
             .validator(|input| FromStrParser::<u8>::default().validate(input))
             .help("Multisig threshold");
+        let arg_offline_file = Arg::with_name("offline-file")
+            .long("offline-file")
+            .takes_value(true)
+            .validator(|input| FilePathParser::new(false).validate(input))
+            .required(true)
+            .help("Offline signing file (format: json)");
         let arg_since_absolute_epoch = Arg::with_name("since-absolute-epoch")
             .long("since-absolute-epoch")
             .takes_value(true)
@@ -98,11 +262,27 @@ impl<'a> TxSubCommand<'a> {
             .help("Since absolute epoch number");
 
         SubCommand::with_name(name)
-            .about("Handle common sighash/multisig transaction")
+            .about("Handle common sighash/multisig/omnilock transaction")
             .subcommands(vec![
                 SubCommand::with_name("init")
-                    .about("Init a common (sighash/multisig) transaction")
-                    .arg(arg_tx_file.clone()),
+                    .about("Init a common (sighash/multisig/omnilock) transaction")
+                    .arg(arg_tx_file.clone())
+                    .arg(
+                        Arg::with_name("omnilock-type-hash")
+                            .long("omnilock-type-hash")
+                            .takes_value(true)
+                            .requires("omnilock-dep")
+                            .validator(|input| FixedHashParser::<H256>::default().validate(input))
+                            .help("The type hash of the deployed omnilock script, inputs with omnilock (secp256k1 auth) can be added then"),
+                    )
+                    .arg(
+                        Arg::with_name("omnilock-dep")
+                            .long("omnilock-dep")
+                            .takes_value(true)
+                            .requires("omnilock-type-hash")
+                            .validator(|input| OutPointParser.validate(input))
+                            .help("The out-point of the omnilock script cell (format: {tx-hash}-{index})"),
+                    ),
                 SubCommand::with_name("add-multisig-config")
                     .about("Add multisig config")
                     .arg(arg_sighash_address.clone())
@@ -121,13 +301,14 @@ impl<'a> TxSubCommand<'a> {
                     )
                     .arg(arg_tx_file.clone()),
                 SubCommand::with_name("add-input")
-                    .about("Add cell input (with secp/multisig lock)")
+                    .about("Add cell input (with secp/multisig lock) by out-point, or select the inputs of an address to cover the outputs and the fee")
                     .arg(
                         Arg::with_name("tx-hash")
                             .long("tx-hash")
                             .takes_value(true)
                             .validator(|input| FixedHashParser::<H256>::default().validate(input))
-                            .required(true)
+                            .required_unless_one(&["out-point", "from-address"])
+                            .requires("index")
                             .help("Transaction hash"),
                     )
                     .arg(
@@ -135,13 +316,46 @@ impl<'a> TxSubCommand<'a> {
                             .long("index")
                             .takes_value(true)
                             .validator(|input| FromStrParser::<u32>::default().validate(input))
-                            .required(true)
+                            .requires("tx-hash")
                             .help("Transaction output index"),
                     )
+                    .arg(
+                        arg::out_point()
+                            .conflicts_with_all(&["tx-hash", "from-address"])
+                    )
+                    .arg(
+                        Arg::with_name("from-address")
+                            .long("from-address")
+                            .takes_value(true)
+                            .conflicts_with_all(&["tx-hash", "since-absolute-epoch"])
+                            .requires("tx-fee")
+                            .validator(|input| AddressParser::default().validate(input))
+                            .help("Select the live cells of this sighash/multisig address until the inputs cover the outputs and --tx-fee (leaving no change or enough for a change cell)"),
+                    )
+                    .arg(
+                        arg::tx_fee()
+                            .requires("from-address")
+                            .help("The transaction fee reserved by --from-address (unit: CKB, format: 0.0001)"),
+                    )
+                    .arg(arg::index_backend())
+                    .arg(arg::indexer_url())
+                    .arg(arg::light_client_url())
                     .arg(arg_since_absolute_epoch.clone())
                     .arg(arg_tx_file.clone()),
                 SubCommand::with_name("add-output")
                     .about("Add cell output")
+                    .arg(
+                        Arg::with_name("to-address")
+                            .long("to-address")
+                            .conflicts_with_all(&[
+                                "to-sighash-address",
+                                "to-short-multisig-address",
+                                "to-long-multisig-address",
+                            ])
+                            .takes_value(true)
+                            .validator(|input| AddressParser::default().validate(input))
+                            .help("To any address (e.g. a full address of a custom lock)"),
+                    )
                     .arg(
                         Arg::with_name("to-sighash-address")
                             .long("to-sighash-address")
@@ -176,6 +390,56 @@ impl<'a> TxSubCommand<'a> {
                     .arg(arg::to_data())
                     .arg(arg::to_data_path())
                     .arg(arg_tx_file.clone()),
+                SubCommand::with_name("add-cell-dep")
+                    .about("Add a cell dep required by the type scripts (or custom lock scripts) of the transaction")
+                    .arg(arg::out_point().required(true))
+                    .arg(
+                        Arg::with_name("dep-type")
+                            .long("dep-type")
+                            .takes_value(true)
+                            .possible_values(&["code", "dep_group"])
+                            .default_value("code")
+                            .help("The dep type of the cell dep"),
+                    )
+                    .arg(arg_tx_file.clone()),
+                SubCommand::with_name("add-witness")
+                    .about("Set the input_type / output_type of the witness of an input (the lock is filled by signing)")
+                    .arg(
+                        Arg::with_name("input-index")
+                            .long("input-index")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FromStrParser::<usize>::default().validate(input))
+                            .help("The index of the input (from 0)"),
+                    )
+                    .arg(
+                        Arg::with_name("input-type")
+                            .long("input-type")
+                            .takes_value(true)
+                            .required_unless("output-type")
+                            .validator(|input| HexParser.validate(input))
+                            .help("The input_type field of the witness (hex)"),
+                    )
+                    .arg(
+                        Arg::with_name("output-type")
+                            .long("output-type")
+                            .takes_value(true)
+                            .validator(|input| HexParser.validate(input))
+                            .help("The output_type field of the witness (hex)"),
+                    )
+                    .arg(arg_tx_file.clone()),
+                SubCommand::with_name("complete")
+                    .about("Check the transaction, then add the change output of the inputs left after the outputs and the fee, it's ready for sign-inputs then")
+                    .arg(
+                        Arg::with_name("change-address")
+                            .long("change-address")
+                            .takes_value(true)
+                            .validator(|input| AddressParser::default().validate(input))
+                            .help("The address of the change output, default: the lock of the first input"),
+                    )
+                    .arg(arg::tx_fee().required_unless(arg::fee_rate().b.name))
+                    .arg(arg::fee_rate().conflicts_with(arg::tx_fee().b.name))
+                    .arg(arg_tx_file.clone()),
                 SubCommand::with_name("add-signature")
                     .about("Add signature")
                     .arg(
@@ -208,14 +472,16 @@ impl<'a> TxSubCommand<'a> {
                     .arg(arg_tx_file.clone()),
                 SubCommand::with_name("sign-inputs")
                     .about("Sign all sighash/multisig inputs in this transaction")
-                    .arg(arg::privkey_path().required_unless(arg::from_account().b.name))
-                    .arg(arg::from_account().required_unless(arg::privkey_path().b.name))
+                    .arg(arg::privkey_path().required_unless_one(&["from-account", "signer-url"]))
+                    .arg(arg::from_account().required_unless_one(&["privkey-path", "signer-url"]))
+                    .arg(arg::signer_url().conflicts_with_all(&["privkey-path", "from-account"]))
                     .arg(arg_tx_file.clone())
                     .arg(
                         Arg::with_name("add-signatures")
                             .long("add-signatures")
                             .help("Sign and add signatures"),
                     )
+                    .arg(arg::allow_high_fee())
                     .arg(
                         Arg::with_name("path")
                             .long("path")
@@ -225,7 +491,8 @@ impl<'a> TxSubCommand<'a> {
                                 FromStrParser::<DerivationPath>::new().validate(input)
                             })
                             .help("The address path"),
-                    ),
+                    )
+                    .arg(arg_change_path.clone()),
                 SubCommand::with_name("send")
                     .about("Send multisig transaction")
                     .arg(arg_tx_file.clone())
@@ -236,7 +503,210 @@ impl<'a> TxSubCommand<'a> {
                             .default_value("1.0")
                             .validator(|input| CapacityParser.validate(input))
                             .help("Max transaction fee (unit: CKB)"),
+                    )
+                    .arg(arg::memo())
+                    .arg(arg::on_failure())
+                    .args(&arg::wait_for_committed_args()),
+                SubCommand::with_name("simulate")
+                    .about("Validate transactions against the chain state plus the not yet sent transactions in --pending-set (e.g. a child spending the outputs of its parent), nothing is sent")
+                    .arg(
+                        arg_tx_file
+                            .clone()
+                            .multiple(true)
+                            .number_of_values(1)
+                            .validator(|input| FilePathParser::new(true).validate(input))
+                            .help("The transaction file (or a JSON transaction) to validate, can be given multiple times, the later ones can spend the outputs of the former ones"),
+                    )
+                    .arg(
+                        Arg::with_name("pending-set")
+                            .long("pending-set")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .validator(|input| PathParser::new(true).validate(input))
+                            .help("A not yet sent transaction file, or a directory of them (*.json, applied by file name order), applied to the chain state before the --tx-file, can be given multiple times"),
+                    ),
+                SubCommand::with_name("cpfp")
+                    .about("Accelerate a stuck low fee transaction (child pays for parent): spend an output of it by a child transaction paying the fee of both")
+                    .arg(
+                        Arg::with_name("parent")
+                            .long("parent")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FixedHashParser::<H256>::default().validate(input))
+                            .help("The hash of the parent transaction"),
+                    )
+                    .arg(
+                        Arg::with_name("output-index")
+                            .long("output-index")
+                            .takes_value(true)
+                            .validator(|input| FromStrParser::<u32>::default().validate(input))
+                            .help("The output of the parent to spend (default: the first output owned by the signer), it must be a plain capacity cell"),
+                    )
+                    .arg(
+                        arg::fee_rate()
+                            .required(true)
+                            .help("The fee rate of the parent and child together (unit: shannons/KB)"),
+                    )
+                    .arg(
+                        Arg::with_name("parent-tx-file")
+                            .long("parent-tx-file")
+                            .takes_value(true)
+                            .validator(|input| FilePathParser::new(true).validate(input))
+                            .help("The signed parent transaction (transaction file or JSON transaction), sent before the child when the node does not know the parent"),
+                    )
+                    .arg(arg::privkey_path().required_unless(arg::from_account().b.name))
+                    .arg(arg::from_account().required_unless(arg::privkey_path().b.name))
+                    .arg(arg::allow_high_fee())
+                    .arg(arg::memo())
+                    .arg(arg::on_failure()),
+                SubCommand::with_name("memo")
+                    .about("Show memos of sent transactions (local tx records)")
+                    .arg(
+                        Arg::with_name("tx-hash")
+                            .long("tx-hash")
+                            .takes_value(true)
+                            .validator(|input| FixedHashParser::<H256>::default().validate(input))
+                            .help("Transaction hash (show all records if not given)"),
+                    ),
+                SubCommand::with_name("status")
+                    .about("Show the status of a transaction (pending/proposed/committed) and estimate the blocks until it is committed by the proposal window")
+                    .arg(
+                        Arg::with_name("tx-hash")
+                            .long("tx-hash")
+                            .takes_value(true)
+                            .required_unless("schema")
+                            .validator(|input| FixedHashParser::<H256>::default().validate(input))
+                            .help("Transaction hash"),
+                    )
+                    .arg(
+                        Arg::with_name("watch")
+                            .long("watch")
+                            .help("Keep polling and print the status changes until the transaction is committed (and confirmed by --confirmations blocks)"),
+                    )
+                    .arg(arg::confirmations().requires("watch"))
+                    .arg(arg::wait_timeout().requires("watch")),
+                SubCommand::with_name("proof")
+                    .about("Export a committed transaction with its block header and merkle proof, the recipient checks it by `tx verify-proof` without the block")
+                    .arg(
+                        Arg::with_name("tx-hash")
+                            .long("tx-hash")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FixedHashParser::<H256>::default().validate(input))
+                            .help("Transaction hash"),
+                    )
+                    .arg(
+                        Arg::with_name("output")
+                            .long("output")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FilePathParser::new(false).validate(input))
+                            .help("The proof file (format: json)"),
+                    ),
+                SubCommand::with_name("verify-proof")
+                    .about("Verify a proof file exported by `tx proof`: the transaction is committed in the block of the header, and (unless --offline) the block is in the main chain of the node")
+                    .arg(
+                        Arg::with_name("input")
+                            .long("input")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FilePathParser::new(true).validate(input))
+                            .help("The proof file"),
+                    )
+                    .arg(
+                        Arg::with_name("offline")
+                            .long("offline")
+                            .help("Only check the proof against the header in the file (the header itself is not checked)"),
+                    ),
+                SubCommand::with_name("build")
+                    .about("Prepare an offline signing file: attach the input cells, source transactions and signing digests to the transaction")
+                    .arg(arg_tx_file.clone())
+                    .arg(arg_offline_file.clone().help("The offline signing file to write")),
+                SubCommand::with_name("sign")
+                    .about("Sign the offline signing file without connecting to a node (keystore or ledger), the signatures are added to the file")
+                    .arg(arg_offline_file.clone().validator(|input| FilePathParser::new(true).validate(input)))
+                    .arg(arg::privkey_path().required_unless(arg::from_account().b.name))
+                    .arg(arg::from_account().required_unless(arg::privkey_path().b.name))
+                    .arg(
+                        Arg::with_name("path")
+                            .long("path")
+                            .takes_value(true)
+                            .validator(|input| {
+                                FromStrParser::<DerivationPath>::new().validate(input)
+                            })
+                            .help("The address path (required by ledger account)"),
+                    )
+                    .arg(arg_change_path.clone())
+                    .arg(arg::allow_high_fee()),
+                SubCommand::with_name("combine")
+                    .about("Merge the signatures of signed offline files into a transaction file, then send it by `tx send`")
+                    .arg(
+                        arg_offline_file
+                            .clone()
+                            .multiple(true)
+                            .number_of_values(1)
+                            .validator(|input| FilePathParser::new(true).validate(input))
+                            .help("The signed offline file, can be given multiple times"),
+                    )
+                    .arg(arg_tx_file.clone()),
+                SubCommand::with_name("export")
+                    .about("Export the transaction file encrypted to the offline signer (by age or gpg)")
+                    .arg(arg_tx_file.clone())
+                    .arg(
+                        Arg::with_name("encrypt-to")
+                            .long("encrypt-to")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FromStrParser::<Recipient>::new().validate(input))
+                            .help("Age recipient (age1...) or gpg key (the gpg message is also signed, age file is authenticated by the printed content hash)"),
+                    )
+                    .arg(
+                        Arg::with_name("sign-key")
+                            .long("sign-key")
+                            .takes_value(true)
+                            .help("The gpg key to sign the message (default key if not given)"),
+                    )
+                    .arg(
+                        Arg::with_name("output")
+                            .long("output")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FilePathParser::new(false).validate(input))
+                            .help("The encrypted file"),
                     ),
+                SubCommand::with_name("import")
+                    .about("Decrypt an exported transaction file (the gpg signature is verified against the sender's key)")
+                    .arg(
+                        Arg::with_name("input")
+                            .long("input")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FilePathParser::new(true).validate(input))
+                            .help("The encrypted file"),
+                    )
+                    .arg(
+                        Arg::with_name("identity")
+                            .long("identity")
+                            .takes_value(true)
+                            .validator(|input| FilePathParser::new(true).validate(input))
+                            .help("The age identity file (required by age file)"),
+                    )
+                    .arg(
+                        Arg::with_name("content-hash")
+                            .long("content-hash")
+                            .takes_value(true)
+                            .validator(|input| FixedHashParser::<H256>::default().validate(input))
+                            .help("The content hash printed by `tx export` (required by age file, which is not signed)"),
+                    )
+                    .arg(
+                        Arg::with_name("sender-fingerprint")
+                            .long("sender-fingerprint")
+                            .takes_value(true)
+                            .validator(|input| parse_gpg_fingerprint(&input).map(|_| ()))
+                            .help("The full fingerprint of the sender's gpg key (required by gpg message), the message must be signed by this key or its subkey"),
+                    )
+                    .arg(arg_tx_file.clone()),
                 SubCommand::with_name("build-multisig-address")
                     .about(
                         "Build multisig address with multisig config and since(optional) argument",
@@ -257,13 +727,30 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
         color: bool,
         debug: bool,
     ) -> Result<String, String> {
+        if let (_, Some(m)) = matches.subcommand() {
+            self.signing_policy.allow_high_fee = m.is_present("allow-high-fee");
+        }
+        // Offline commands, never connect to the node
+        match matches.subcommand() {
+            ("sign", Some(m)) => return self.sign_offline(m, format, color),
+            ("combine", Some(m)) => return combine_offline(m, format, color),
+            ("verify-proof", Some(m)) => return self.verify_proof(m, format, color),
+            _ => {}
+        }
         let network = get_network_type(self.rpc_client)?;
 
         match matches.subcommand() {
             ("init", Some(m)) => {
                 let tx_file_opt: Option<PathBuf> =
                     FilePathParser::new(false).from_matches_opt(m, "tx-file", false)?;
-                let helper = TxHelper::default();
+                let omnilock_type_hash: Option<H256> = FixedHashParser::<H256>::default()
+                    .from_matches_opt(m, "omnilock-type-hash", false)?;
+                let omnilock_dep: Option<OutPoint> =
+                    OutPointParser.from_matches_opt(m, "omnilock-dep", false)?;
+                let mut helper = TxHelper::default();
+                if let (Some(type_hash), Some(out_point)) = (omnilock_type_hash, omnilock_dep) {
+                    helper.set_omnilock(Some(OmniLockConfig::new(type_hash, out_point)));
+                }
                 let repr = ReprTxHelper::new(helper, network);
 
                 if let Some(tx_file) = tx_file_opt {
@@ -293,27 +780,38 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
             }
             ("add-input", Some(m)) => {
                 let tx_file: PathBuf = FilePathParser::new(true).from_matches(m, "tx-file")?;
-                let tx_hash: H256 =
-                    FixedHashParser::<H256>::default().from_matches(m, "tx-hash")?;
-                let index: u32 = FromStrParser::<u32>::default().from_matches(m, "index")?;
+                if m.is_present("from-address") {
+                    return self.add_inputs_from_address(m, &tx_file, network, format, color);
+                }
+                let out_point = match OutPointParser.from_matches_opt(m, "out-point", false)? {
+                    Some(out_point) => out_point,
+                    None => {
+                        let tx_hash: H256 =
+                            FixedHashParser::<H256>::default().from_matches(m, "tx-hash")?;
+                        let index: u32 =
+                            FromStrParser::<u32>::default().from_matches(m, "index")?;
+                        OutPoint::new_builder()
+                            .tx_hash(tx_hash.pack())
+                            .index(index.pack())
+                            .build()
+                    }
+                };
                 let since_absolute_epoch_opt: Option<u64> = FromStrParser::<u64>::default()
                     .from_matches_opt(m, "since-absolute-epoch", false)?;
 
                 let genesis_info = get_genesis_info(&self.genesis_info, self.rpc_client)?;
-                let out_point = OutPoint::new_builder()
-                    .tx_hash(tx_hash.pack())
-                    .index(index.pack())
-                    .build();
                 let mut get_live_cell = |out_point, with_data| {
                     get_live_cell(self.rpc_client, out_point, with_data).map(|(output, _)| output)
                 };
                 modify_tx_file(&tx_file, network, |helper| {
+                    check_unsigned(helper)?;
                     helper.add_input(
                         out_point,
                         since_absolute_epoch_opt,
                         &mut get_live_cell,
                         &genesis_info,
-                    )
+                    )?;
+                    capacity_totals(helper, &mut get_live_cell).map(|_| ())
                 })?;
 
                 Ok(String::from("ok"))
@@ -328,6 +826,9 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                 let to_long_multisig_address_opt: Option<Address> = AddressParser::default()
                     .set_full_type(MULTISIG_TYPE_HASH)
                     .from_matches_opt(m, "to-long-multisig-address", false)?;
+                let to_address_opt: Option<Address> = AddressParser::default()
+                    .set_network(network)
+                    .from_matches_opt(m, "to-address", false)?;
 
                 let to_data = get_to_data(m)?;
                 check_capacity(capacity, to_data.len())?;
@@ -340,7 +841,8 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                         ));
                     }
                 }
-                let lock_script = to_sighash_address_opt
+                let lock_script = to_address_opt
+                    .or_else(|| to_sighash_address_opt)
                     .or_else(|| to_short_multisig_address_opt)
                     .or_else(|| to_long_multisig_address_opt)
                     .map(|address| Script::from(address.payload()))
@@ -349,14 +851,80 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                     .capacity(Capacity::shannons(capacity).pack())
                     .lock(lock_script)
                     .build();
+                let occupied = output
+                    .occupied_capacity(
+                        Capacity::bytes(to_data.len()).map_err(|err| err.to_string())?,
+                    )
+                    .map_err(|err| err.to_string())?
+                    .as_u64();
+                if capacity < occupied {
+                    return Err(format!(
+                        "Capacity {} is less than the occupied capacity of the output: {}",
+                        HumanCapacity(capacity),
+                        HumanCapacity(occupied)
+                    ));
+                }
 
                 modify_tx_file(&tx_file, network, |helper| {
+                    check_unsigned(helper)?;
                     helper.add_output(output, to_data);
                     Ok(())
                 })?;
 
                 Ok(String::from("ok"))
             }
+            ("add-cell-dep", Some(m)) => {
+                let tx_file: PathBuf = FilePathParser::new(true).from_matches(m, "tx-file")?;
+                let out_point: OutPoint = OutPointParser.from_matches(m, "out-point")?;
+                let dep_type = match m.value_of("dep-type") {
+                    Some("dep_group") => DepType::DepGroup,
+                    _ => DepType::Code,
+                };
+                // The dep cell must be live when the transaction is sent
+                get_live_cell(self.rpc_client, out_point.clone(), false)?;
+                let cell_dep = CellDep::new_builder()
+                    .out_point(out_point)
+                    .dep_type(dep_type.into())
+                    .build();
+                modify_tx_file(&tx_file, network, |helper| {
+                    check_unsigned(helper)?;
+                    helper.add_cell_dep(cell_dep);
+                    Ok(())
+                })?;
+                Ok(String::from("ok"))
+            }
+            ("add-witness", Some(m)) => {
+                let tx_file: PathBuf = FilePathParser::new(true).from_matches(m, "tx-file")?;
+                let input_index: usize =
+                    FromStrParser::<usize>::default().from_matches(m, "input-index")?;
+                let input_type: Option<Bytes> =
+                    HexParser.from_matches_opt(m, "input-type", false)?;
+                let output_type: Option<Bytes> =
+                    HexParser.from_matches_opt(m, "output-type", false)?;
+                modify_tx_file(&tx_file, network, |helper| {
+                    set_witness_types(helper, input_index, input_type, output_type)
+                })?;
+                Ok(String::from("ok"))
+            }
+            ("complete", Some(m)) => {
+                let tx_file: PathBuf = FilePathParser::new(true).from_matches(m, "tx-file")?;
+                let change_address_opt: Option<Address> = AddressParser::default()
+                    .set_network(network)
+                    .from_matches_opt(m, "change-address", false)?;
+                let fee_policy = get_fee_policy(m)?;
+                let mut get_live_cell = |out_point, with_data| {
+                    get_live_cell(self.rpc_client, out_point, with_data).map(|(output, _)| output)
+                };
+                let resp = modify_tx_file(&tx_file, network, |helper| {
+                    complete_tx(
+                        helper,
+                        change_address_opt.map(|address| Script::from(address.payload())),
+                        fee_policy,
+                        &mut get_live_cell,
+                    )
+                })?;
+                Ok(resp.render(format, color))
+            }
             ("add-signature", Some(m)) => {
                 let tx_file: PathBuf = FilePathParser::new(true).from_matches(m, "tx-file")?;
                 let lock_arg: Bytes = HexParser.from_matches(m, "lock-arg")?;
@@ -428,6 +996,7 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                     print_cell_info(
                         prefix,
                         network,
+                        helper.omnilock(),
                         output.lock(),
                         capacity,
                         data.len(),
@@ -444,6 +1013,7 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                     print_cell_info(
                         "output",
                         network,
+                        helper.omnilock(),
                         output.lock(),
                         capacity,
                         data_len,
@@ -480,14 +1050,20 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                 // TODO: should only be required on ledger accounts
                 let path: DerivationPath = DerivationPathParser.from_matches(m, "path")?;
 
-                let my_path = path.clone();
+                let change_path: DerivationPath = DerivationPathParser
+                    .from_matches_opt(m, "change-path", false)?
+                    .unwrap_or_else(DerivationPath::empty);
 
-                let is_ledger = match account_opt.clone().unwrap() {
-                    AccountId::SoftwareMasterKey(_) => false,
-                    AccountId::LedgerId(_) => true,
+                let is_ledger = match account_opt {
+                    Some(AccountId::LedgerId(_)) => true,
+                    _ => false,
                 };
+                self.signing_policy.check_path(&path, is_ledger)?;
 
-                let signer: BoxedSignerFn = if let Some(privkey) = privkey_opt {
+                let remote_signer_opt = remote_signer_from_matches(m)?;
+                let signer: BoxedSignerFn = if let Some(remote_signer) = remote_signer_opt {
+                    Box::new(KeyAdapter(get_privkey_signer(remote_signer)?))
+                } else if let Some(privkey) = privkey_opt {
                     Box::new(KeyAdapter(get_privkey_signer(privkey)?))
                 } else {
                     match account_opt.unwrap() {
@@ -517,9 +1093,15 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                         .map(|(output, _)| output)
                 };
 
+                let signing_policy = &self.signing_policy;
                 let signatures = modify_tx_file(&tx_file, network, |helper| {
+                    signing_policy.check_transaction(
+                        helper.transaction(),
+                        network,
+                        &mut get_live_cell,
+                    )?;
                     let signatures =
-                        helper.sign_inputs(signer, &mut get_live_cell, is_ledger, &my_path)?;
+                        helper.sign_inputs(signer, &mut get_live_cell, is_ledger, &change_path)?;
                     if m.is_present("add-signatures") {
                         for (ref lock_arg, ref signature) in &signatures {
                             helper.add_signature(
@@ -578,12 +1160,236 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
                 if debug {
                     println!("[send transaction]:\n{}", rpc_tx.render(format, color));
                 }
-                let resp = self
+                let on_failure = OnFailure::from_matches(m)?;
+                let resp = on_failure::send_transaction(
+                    self.rpc_client,
+                    &tx,
+                    on_failure.as_ref(),
+                    &self.send_targets,
+                )
+                .map_err(|err| format!("Send transaction error: {}", err))?;
+                save_tx_memo(&resp, m.value_of("memo").map(ToOwned::to_owned))?;
+                if let Some(wait) = WaitForCommitted::from_matches(m, "wait-for-committed")? {
+                    let resp = wait.wait_response(self.rpc_client, &resp)?;
+                    return Ok(resp.render(format, color));
+                }
+                Ok(resp.render(format, color))
+            }
+            ("simulate", Some(m)) => self.simulate(m, format, color),
+            ("cpfp", Some(m)) => self.cpfp(m, format, color),
+            ("memo", Some(m)) => {
+                let tx_hash_opt: Option<H256> =
+                    FixedHashParser::<H256>::default().from_matches_opt(m, "tx-hash", false)?;
+                let store = TxRecordStore::load()?;
+                let records = store
+                    .records()
+                    .iter()
+                    .filter(|record| {
+                        tx_hash_opt
+                            .as_ref()
+                            .map(|tx_hash| &record.tx_hash == tx_hash)
+                            .unwrap_or(true)
+                    })
+                    .map(|record| serde_json::to_value(record).unwrap())
+                    .collect::<Vec<_>>();
+                Ok(records.render(format, color))
+            }
+            ("status", Some(m)) => {
+                let tx_hash: H256 =
+                    FixedHashParser::<H256>::default().from_matches(m, "tx-hash")?;
+                if let Some(wait) = WaitForCommitted::from_matches(m, "watch")? {
+                    wait.wait(self.rpc_client, &tx_hash)?;
+                }
+                let window = ProposalWindow::from_node(self.rpc_client);
+                let (progress, tip) = tx_progress(self.rpc_client, &tx_hash, window)?;
+                let mut resp = serde_json::json!({
+                    "tx_hash": tx_hash,
+                    "status": progress.status(),
+                    "tip_block_number": tip,
+                    "proposal_window": {
+                        "closest": window.closest,
+                        "farthest": window.farthest,
+                    },
+                });
+                match progress {
+                    TxProgress::Proposed {
+                        proposed_at: Some(number),
+                    } => {
+                        resp["proposed_at"] = serde_json::json!(number);
+                    }
+                    TxProgress::Committed {
+                        block_number,
+                        block_hash,
+                    } => {
+                        resp["block_number"] = serde_json::json!(block_number);
+                        resp["block_hash"] = serde_json::json!(block_hash);
+                        resp["confirmations"] =
+                            serde_json::json!(tip.saturating_sub(block_number) + 1);
+                    }
+                    _ => {}
+                }
+                if let Some(memo) = load_memos().remove(&tx_hash) {
+                    resp["memo"] = serde_json::json!(memo);
+                }
+                if let Some((min, max)) = progress.blocks_to_commit(window, tip) {
+                    if min > 0 {
+                        resp["estimated_blocks_to_commit"] =
+                            serde_json::json!({ "min": min, "max": max });
+                    }
+                }
+                Ok(resp.render(format, color))
+            }
+            ("proof", Some(m)) => {
+                let tx_hash: H256 =
+                    FixedHashParser::<H256>::default().from_matches(m, "tx-hash")?;
+                let output: PathBuf = FilePathParser::new(false).from_matches(m, "output")?;
+
+                let tx_with_status: Option<json_types::TransactionWithStatus> = self
+                    .rpc_client
+                    .call("get_transaction", vec![serde_json::json!(tx_hash)])
+                    .and_then(|value| {
+                        serde_json::from_value(value).map_err(|err| err.to_string())
+                    })?;
+                let tx_with_status = tx_with_status
+                    .ok_or_else(|| format!("Transaction {:#x} not found", tx_hash))?;
+                let block_hash = match tx_with_status.tx_status {
+                    json_types::TxStatus {
+                        status: json_types::Status::Committed,
+                        block_hash: Some(block_hash),
+                    } => block_hash,
+                    _ => return Err(format!("Transaction {:#x} is not committed yet", tx_hash)),
+                };
+                let proof: TransactionProof = self
                     .rpc_client
-                    .send_transaction(tx.data())
-                    .map_err(|err| format!("Send transaction error: {}", err))?;
+                    .call(
+                        "get_transaction_proof",
+                        vec![serde_json::json!([tx_hash]), serde_json::json!(block_hash)],
+                    )
+                    .and_then(|value| serde_json::from_value(value).map_err(|err| err.to_string()))
+                    .map_err(|err| {
+                        format!(
+                            "get_transaction_proof failed (the node may not support it): {}",
+                            err
+                        )
+                    })?;
+                let header: Option<json_types::HeaderView> = self
+                    .rpc_client
+                    .call("get_header", vec![serde_json::json!(block_hash)])
+                    .and_then(|value| {
+                        serde_json::from_value(value).map_err(|err| err.to_string())
+                    })?;
+                let header = header.ok_or_else(|| format!("Block {:#x} not found", block_hash))?;
+
+                let proof_file = TxProofFile {
+                    transaction: tx_with_status.transaction.inner,
+                    header: header.inner,
+                    proof,
+                };
+                // Never hand out a proof which does not verify
+                let (_, header) = proof_file.verify()?;
+                let content =
+                    serde_json::to_string_pretty(&proof_file).map_err(|err| err.to_string())?;
+                write_file_atomic(&output, content.as_bytes())?;
+                let resp = serde_json::json!({
+                    "tx_hash": tx_hash,
+                    "block_hash": block_hash,
+                    "block_number": header.number(),
+                    "output": output.to_string_lossy(),
+                });
+                Ok(resp.render(format, color))
+            }
+            ("build", Some(m)) => {
+                let tx_file: PathBuf = FilePathParser::new(true).from_matches(m, "tx-file")?;
+                let offline_file: PathBuf =
+                    FilePathParser::new(false).from_matches(m, "offline-file")?;
+
+                let mut live_cell_cache: HashMap<
+                    (OutPoint, bool),
+                    ((CellOutput, Transaction), Bytes),
+                > = Default::default();
+                let mut get_live_cell = |out_point: OutPoint, with_data: bool| {
+                    get_live_cell_with_cache(
+                        &mut live_cell_cache,
+                        self.rpc_client,
+                        out_point,
+                        with_data,
+                    )
+                    .map(|(output, _)| output)
+                };
+
+                let file = fs::File::open(tx_file).map_err(|err| err.to_string())?;
+                let repr: ReprTxHelper =
+                    serde_json::from_reader(&file).map_err(|err| err.to_string())?;
+                let helper = TxHelper::try_from(repr)?;
+                helper.check_tx(&mut get_live_cell)?;
+
+                let mut input_cells = Vec::new();
+                for input in helper.transaction().inputs().into_iter() {
+                    let out_point = input.previous_output();
+                    let (output, source_tx) = get_live_cell(out_point.clone(), false)?;
+                    input_cells.push(ReprInputCell {
+                        out_point: out_point.into(),
+                        output: output.into(),
+                        source_tx,
+                    });
+                }
+                let signing_digests = helper
+                    .signing_digests(&mut get_live_cell)?
+                    .into_iter()
+                    .map(|(lock_arg, digest)| (JsonBytes::from_bytes(lock_arg), digest))
+                    .collect::<HashMap<_, _>>();
+                let tx_hash: H256 = helper.transaction().hash().unpack();
+                let repr = ReprOfflineTx {
+                    network,
+                    tx_helper: ReprTxHelper::new(helper, network),
+                    input_cells,
+                    signing_digests: signing_digests.clone(),
+                };
+                write_offline_file(&offline_file, &repr)?;
+                let resp = serde_json::json!({
+                    "tx_hash": tx_hash,
+                    "signing_digests": signing_digests,
+                });
+                Ok(resp.render(format, color))
+            }
+            ("export", Some(m)) => {
+                let tx_file: PathBuf = FilePathParser::new(true).from_matches(m, "tx-file")?;
+                let recipient: Recipient = FromStrParser::new().from_matches(m, "encrypt-to")?;
+                let output: PathBuf = FilePathParser::new(false).from_matches(m, "output")?;
+
+                let content = fs::read(&tx_file).map_err(|err| err.to_string())?;
+                // Make sure it is a valid transaction file
+                let repr: ReprTxHelper =
+                    serde_json::from_slice(&content).map_err(|err| err.to_string())?;
+                TxHelper::try_from(repr)?;
+                let encrypted = encrypt(&content, &recipient, m.value_of("sign-key"))?;
+                write_file_atomic(&output, &encrypted)?;
+                let resp = serde_json::json!({
+                    "content_hash": content_hash(&content),
+                });
                 Ok(resp.render(format, color))
             }
+            ("import", Some(m)) => {
+                let input: PathBuf = FilePathParser::new(true).from_matches(m, "input")?;
+                let identity_opt: Option<PathBuf> =
+                    FilePathParser::new(true).from_matches_opt(m, "identity", false)?;
+                let content_hash_opt: Option<H256> = FixedHashParser::<H256>::default()
+                    .from_matches_opt(m, "content-hash", false)?;
+                let tx_file: PathBuf = FilePathParser::new(false).from_matches(m, "tx-file")?;
+
+                let content = fs::read(&input).map_err(|err| err.to_string())?;
+                let decrypted = decrypt(
+                    &content,
+                    identity_opt.as_ref(),
+                    content_hash_opt.as_ref(),
+                    m.value_of("sender-fingerprint"),
+                )?;
+                let repr: ReprTxHelper = serde_json::from_slice(&decrypted)
+                    .map_err(|err| format!("Invalid transaction file: {}", err))?;
+                TxHelper::try_from(repr)?;
+                write_file_atomic(&tx_file, &decrypted)?;
+                Ok(String::from("ok"))
+            }
             ("build-multisig-address", Some(m)) => {
                 let sighash_addresses: Vec<Address> = AddressParser::default()
                     .set_network(network)
@@ -615,16 +1421,533 @@ impl<'a> CliSubCommand for TxSubCommand<'a> {
     }
 }
 
+impl<'a> TxSubCommand<'a> {
+    fn sign_offline(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let offline_file: PathBuf = FilePathParser::new(true).from_matches(m, "offline-file")?;
+        let privkey_opt: Option<PrivkeyWrapper> =
+            PrivkeyPathParser.from_matches_opt(m, "privkey-path", false)?;
+        let account_opt: Option<AccountId> =
+            FromAccountParser::default().from_matches_opt(m, "from-account", false)?;
+        let path_opt: Option<DerivationPath> =
+            DerivationPathParser.from_matches_opt(m, "path", false)?;
+        let change_path: DerivationPath = DerivationPathParser
+            .from_matches_opt(m, "change-path", false)?
+            .unwrap_or_else(DerivationPath::empty);
+
+        let mut repr = read_offline_file(&offline_file)?;
+        let network = repr.network;
+        let mut helper = TxHelper::try_from(repr.tx_helper.clone())?;
+        let live_cells = repr.live_cells()?;
+        let mut get_live_cell = |out_point: OutPoint, _with_data: bool| {
+            live_cells
+                .get(&out_point)
+                .cloned()
+                .ok_or_else(|| format!("Input cell not found in offline file: {}", out_point))
+        };
+        // The transaction and the attached input cells must match the digests
+        // reviewed on the online machine
+        let digests = helper
+            .signing_digests(&mut get_live_cell)?
+            .into_iter()
+            .map(|(lock_arg, digest)| (JsonBytes::from_bytes(lock_arg), digest))
+            .collect::<HashMap<_, _>>();
+        if digests != repr.signing_digests {
+            return Err("Signing digests mismatch, the offline file may be tampered".to_string());
+        }
+
+        let is_ledger = match account_opt {
+            Some(AccountId::LedgerId(_)) => true,
+            _ => false,
+        };
+        let path = match path_opt {
+            Some(path) => path,
+            None if is_ledger => {
+                return Err("The path is required by ledger account".to_string());
+            }
+            None => DerivationPath::empty(),
+        };
+        self.signing_policy.check_path(&path, is_ledger)?;
+        let signer: BoxedSignerFn = if let Some(privkey) = privkey_opt {
+            Box::new(KeyAdapter(get_privkey_signer(privkey)?))
+        } else {
+            match account_opt.unwrap() {
+                AccountId::SoftwareMasterKey(hash160) => {
+                    let password = read_password(false, None)?;
+                    let key_store = self.key_store.clone();
+                    Box::new(KeyAdapter(get_keystore_signer(
+                        key_store, hash160, password,
+                    )?))
+                }
+                AccountId::LedgerId(ref ledger_id) => {
+                    let key = self
+                        .ledger_key_store
+                        .borrow_account(&ledger_id)
+                        .map_err(|e| e.to_string())?
+                        .clone();
+                    Box::new(KeyAdapter(get_master_key_signer_raw(key, path.clone())?))
+                }
+            }
+        };
+
+        self.signing_policy
+            .check_transaction(helper.transaction(), network, &mut get_live_cell)?;
+        let signatures = helper.sign_inputs(signer, &mut get_live_cell, is_ledger, &change_path)?;
+        if signatures.is_empty() {
+            return Err("No input can be signed by the given key".to_string());
+        }
+        for (lock_arg, signature) in &signatures {
+            helper.add_signature(lock_arg.clone(), serialize_signature_bytes(signature))?;
+        }
+        repr.tx_helper = ReprTxHelper::new(helper, network);
+        write_offline_file(&offline_file, &repr)?;
+
+        let resp = signatures
+            .into_iter()
+            .map(|(ref lock_arg, ref signature)| {
+                serde_json::json!({
+                    "lock-arg": format!("0x{}", hex_string(lock_arg).unwrap()),
+                    "digest": digests.get(&JsonBytes::from_bytes(lock_arg.clone())),
+                    "signature": format!("0x{}", hex_string(&serialize_signature(signature)).unwrap()),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(resp.render(format, color))
+    }
+
+    fn simulate(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let pending_set: Vec<PathBuf> = PathParser::new(true).from_matches_vec(m, "pending-set")?;
+        let mut files = Vec::new();
+        for path in pending_set {
+            for file in pending_set_files(&path)? {
+                files.push(("pending", file));
+            }
+        }
+        let tx_files: Vec<PathBuf> = FilePathParser::new(true).from_matches_vec(m, "tx-file")?;
+        files.extend(tx_files.into_iter().map(|file| ("simulate", file)));
+
+        let mut overlay = ChainOverlay::new();
+        let mut live_cell_cache = HashMap::default();
+        let mut ok = true;
+        let mut results = Vec::new();
+        for (kind, file) in files {
+            let mut result = self
+                .simulate_tx(&mut overlay, &mut live_cell_cache, &file)
+                .unwrap_or_else(|err| {
+                    ok = false;
+                    serde_json::json!({ "error": err })
+                });
+            result["kind"] = serde_json::json!(kind);
+            result["file"] = serde_json::json!(file.to_string_lossy());
+            results.push(result);
+        }
+        let resp = serde_json::json!({
+            "ok": ok,
+            "transactions": results,
+        });
+        Ok(render_list(&resp, "transactions", format, color))
+    }
+
+    /// Validate the transaction against the chain state with the overlay, then
+    /// apply it to the overlay. A failed transaction is not applied, so the
+    /// transactions spending its outputs fail too.
+    fn simulate_tx(
+        &mut self,
+        overlay: &mut ChainOverlay,
+        live_cell_cache: &mut HashMap<(OutPoint, bool), ((CellOutput, Transaction), Bytes)>,
+        file: &Path,
+    ) -> Result<serde_json::Value, String> {
+        let genesis_info = get_genesis_info(&self.genesis_info, self.rpc_client)?;
+        let rpc_client = &mut *self.rpc_client;
+        let tx = {
+            let overlay = &*overlay;
+            let mut get_live_cell = |out_point: OutPoint, with_data: bool| {
+                if let Some(tx_hash) = overlay.consumed_by(&out_point) {
+                    return Err(format!(
+                        "The cell {} is already consumed by pending transaction {:#x}",
+                        out_point, tx_hash
+                    ));
+                }
+                if let Some((output, tx)) = overlay.pending_cell(&out_point) {
+                    let source_tx = Transaction::from(json_types::Transaction::from(tx.data()));
+                    return Ok((output, source_tx));
+                }
+                get_live_cell_with_cache(live_cell_cache, rpc_client, out_point, with_data)
+                    .map(|(output, _)| output)
+            };
+            read_tx_or_json_file(file, &mut get_live_cell)?
+        };
+        overlay.check_conflicts(&tx)?;
+
+        let mut mock_tx =
+            on_failure::mock_transaction(&mut overlay.loader(Loader::new(rpc_client)), &tx)?;
+        // A prepared NervosDAO input gives its capacity with the compensation
+        let mut input_total: u64 = 0;
+        for input in &mock_tx.mock_info.inputs {
+            let is_dao_withdraw = input
+                .output
+                .type_()
+                .to_opt()
+                .map(|script| {
+                    &script.code_hash() == genesis_info.dao_type_hash()
+                        && script.hash_type() == ScriptHashType::Type.into()
+                })
+                .unwrap_or(false)
+                && input.data.iter().any(|byte| *byte != 0);
+            let capacity = if is_dao_withdraw {
+                let out_point = input.input.previous_output();
+                let prepare_block_hash = rpc_client
+                    .get_transaction(out_point.tx_hash().unpack())?
+                    .and_then(|tx_with_status| tx_with_status.tx_status.block_hash)
+                    .ok_or_else(|| {
+                        format!(
+                            "The prepare transaction of NervosDAO input {} is not committed",
+                            out_point
+                        )
+                    })?;
+                withdraw_input_capacity(
+                    &input.output,
+                    &input.data,
+                    &prepare_block_hash.pack(),
+                    &mock_tx.mock_info.header_deps,
+                )
+                .map_err(|err| format!("NervosDAO input {}: {}", out_point, err))?
+            } else {
+                input.output.capacity().unpack()
+            };
+            input_total = input_total
+                .checked_add(capacity)
+                .ok_or_else(|| "The inputs capacity overflow".to_string())?;
+        }
+        let output_total: u64 = tx
+            .outputs()
+            .into_iter()
+            .map(|output| -> u64 { output.capacity().unpack() })
+            .sum();
+        if output_total > input_total {
+            return Err(format!(
+                "The outputs capacity {:#} is more than the inputs capacity {:#}",
+                HumanCapacity(output_total),
+                HumanCapacity(input_total)
+            ));
+        }
+        let cycles = MockTransactionHelper::new(&mut mock_tx)
+            .verify(u64::max_value(), overlay.loader(Loader::new(rpc_client)))?;
+        overlay.apply(&tx)?;
+
+        let tx_hash: H256 = tx.hash().unpack();
+        Ok(serde_json::json!({
+            "tx_hash": tx_hash,
+            "cycles": cycles,
+            "fee": format!("{:#}", HumanCapacity(input_total - output_total)),
+        }))
+    }
+
+    fn cpfp(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let parent_hash: H256 = FixedHashParser::<H256>::default().from_matches(m, "parent")?;
+        let output_index_opt: Option<u32> =
+            FromStrParser::<u32>::default().from_matches_opt(m, "output-index", false)?;
+        let fee_rate: u64 = FromStrParser::<u64>::default().from_matches(m, "fee-rate")?;
+        let parent_file_opt: Option<PathBuf> =
+            FilePathParser::new(true).from_matches_opt(m, "parent-tx-file", false)?;
+        let on_failure = OnFailure::from_matches(m)?;
+        let memo = m.value_of("memo").map(ToOwned::to_owned);
+        let from_account = privkey_or_from_account(m)?;
+        if let Either::Right(AccountId::LedgerId(_)) = from_account {
+            return Err("Hardware wallet is not supported by tx cpfp yet".to_string());
+        }
+        let sender = sender_payload(&from_account)?;
+        let sender_lock = Script::from(&sender);
+        let network = get_network_type(self.rpc_client)?;
+        let genesis_info = get_genesis_info(&self.genesis_info, self.rpc_client)?;
+
+        // The parent is sent before the child when the node does not know it
+        let (parent, send_parent) = match self.rpc_client.get_transaction(parent_hash.clone())? {
+            Some(tx_with_status) => {
+                if let json_types::Status::Committed = tx_with_status.tx_status.status {
+                    return Err(format!(
+                        "The parent transaction {:#x} is already committed",
+                        parent_hash
+                    ));
+                }
+                let tx = packed::Transaction::from(tx_with_status.transaction.inner);
+                (tx.into_view(), false)
+            }
+            None => {
+                let path = parent_file_opt.ok_or_else(|| {
+                    format!(
+                        "The parent transaction {:#x} is not found by the node, give it by --parent-tx-file",
+                        parent_hash
+                    )
+                })?;
+                let rpc_client = &mut *self.rpc_client;
+                let tx = read_tx_or_json_file(&path, &mut |out_point, with_data| {
+                    get_live_cell(rpc_client, out_point, with_data).map(|(output, _)| output)
+                })?;
+                if tx.hash() != parent_hash.pack() {
+                    return Err(format!(
+                        "The transaction in {} is not the parent {:#x}",
+                        path.display(),
+                        parent_hash
+                    ));
+                }
+                (tx, true)
+            }
+        };
+
+        let mut parent_input_total: u64 = 0;
+        for input in parent.inputs().into_iter() {
+            let out_point = input.previous_output();
+            let source_tx = self
+                .rpc_client
+                .get_transaction(out_point.tx_hash().unpack())?
+                .ok_or_else(|| format!("The input {} of the parent is not found", out_point))?;
+            let index: u32 = out_point.index().unpack();
+            let output = source_tx
+                .transaction
+                .inner
+                .outputs
+                .get(index as usize)
+                .cloned()
+                .ok_or_else(|| format!("The input {} of the parent is not found", out_point))?;
+            let capacity: u64 = CellOutput::from(output).capacity().unpack();
+            parent_input_total += capacity;
+        }
+        let parent_output_total: u64 = parent
+            .outputs()
+            .into_iter()
+            .map(|output| -> u64 { output.capacity().unpack() })
+            .sum();
+        let parent_fee = parent_input_total
+            .checked_sub(parent_output_total)
+            .ok_or_else(|| "The parent outputs capacity is more than its inputs".to_string())?;
+
+        let sender_lock_hash = sender_lock.calc_script_hash();
+        let output_index = match output_index_opt {
+            Some(index) => index,
+            None => parent
+                .outputs()
+                .into_iter()
+                .position(|output| output.lock().calc_script_hash() == sender_lock_hash)
+                .ok_or_else(|| {
+                    "No output of the parent is owned by the signer, give it by --output-index"
+                        .to_string()
+                })? as u32,
+        };
+        let (output, data) = parent
+            .output_with_data(output_index as usize)
+            .ok_or_else(|| format!("The parent has no output {}", output_index))?;
+        if output.lock().calc_script_hash() != sender_lock_hash {
+            return Err(format!(
+                "The output {} of the parent is not owned by the signer",
+                output_index
+            ));
+        }
+        if output.type_().to_opt().is_some() || !data.is_empty() {
+            return Err(format!(
+                "The output {} of the parent is not a plain capacity cell",
+                output_index
+            ));
+        }
+        let parent_out_point = OutPoint::new(parent.hash(), output_index);
+        let capacity: u64 = output.capacity().unpack();
+
+        let parent_cell = (
+            output,
+            Transaction::from(json_types::Transaction::from(parent.data())),
+        );
+        let rpc_client = &mut *self.rpc_client;
+        let mut live_cell_cache: HashMap<(OutPoint, bool), ((CellOutput, Transaction), Bytes)> =
+            Default::default();
+        let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
+            if out_point == parent_out_point {
+                return Ok(parent_cell.clone());
+            }
+            get_live_cell_with_cache(&mut live_cell_cache, rpc_client, out_point, with_data)
+                .map(|(output, _)| output)
+        };
+        let collected = vec![CollectedCell::new(parent_out_point.clone(), capacity)];
+        let mut collector =
+            |_required: u64| -> Result<Vec<CollectedCell>, String> { Ok(collected.clone()) };
+
+        // The whole output goes back to the signer, except the fee
+        let mut builder = TransactionBuilder::default();
+        builder
+            .change_address(sender)
+            .fee_policy(FeePolicy::Fixed(0));
+        let unsigned =
+            builder.build_unsigned(&mut collector, &mut get_live_cell_fn, &genesis_info)?;
+        let parent_size = tx_size_in_block(&parent);
+        let child_size = estimate_tx_size(&unsigned, &mut get_live_cell_fn)?;
+        let fee = child_pays_for_parent_fee(fee_rate, parent_size, parent_fee, child_size);
+        builder.fee_policy(FeePolicy::Fixed(fee));
+
+        let signer = get_account_signer(self.key_store, from_account)?;
+        let tx = self.signing_policy.build_transaction(
+            &builder,
+            &mut collector,
+            &mut get_live_cell_fn,
+            &genesis_info,
+            network,
+            signer,
+        )?;
+
+        if send_parent {
+            on_failure::send_transaction(
+                self.rpc_client,
+                &parent,
+                on_failure.as_ref(),
+                &self.send_targets,
+            )
+            .map_err(|err| format!("Send parent transaction error: {}", err))?;
+        }
+        let tx_hash = on_failure::send_transaction(
+            self.rpc_client,
+            &tx,
+            on_failure.as_ref(),
+            &self.send_targets,
+        )
+        .map_err(|err| format!("Send transaction error: {}", err))?;
+        save_tx_memo(&tx_hash, memo)?;
+
+        let package_size = parent_size + tx_size_in_block(&tx);
+        let resp = serde_json::json!({
+            "parent_tx_hash": parent_hash,
+            "parent_sent": send_parent,
+            "parent_fee": format!("{:#}", HumanCapacity(parent_fee)),
+            "tx_hash": tx_hash,
+            "fee": format!("{:#}", HumanCapacity(fee)),
+            "package_fee_rate": (parent_fee + fee) * 1000 / package_size as u64,
+        });
+        Ok(resp.render(format, color))
+    }
+}
+
+/// The transaction files of a pending set path, a directory gives its `*.json`
+/// files in file name order.
+fn pending_set_files(path: &Path) -> Result<Vec<PathBuf>, String> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let mut files = fs::read_dir(path)
+        .map_err(|err| format!("Read {} failed: {}", path.display(), err))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.to_string())?;
+    files.retain(|file| file.is_file() && file.extension().map(|ext| ext == "json") == Some(true));
+    files.sort();
+    Ok(files)
+}
+
+/// Read a transaction file of the `tx` subcommand (the witnesses are built
+/// from the signatures) or a JSON transaction (as the `send_transaction` RPC
+/// param).
+fn read_simulate_tx<F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>>(
+    file: &Path,
+    get_live_cell: &mut F,
+) -> Result<TransactionView, String> {
+    let content = fs::read_to_string(file).map_err(|err| err.to_string())?;
+    if let Ok(repr) = serde_json::from_str::<ReprTxHelper>(&content) {
+        return TxHelper::try_from(repr)?.build_tx(get_live_cell);
+    }
+    let tx: json_types::Transaction = serde_json::from_str(&content)
+        .map_err(|err| format!("Invalid transaction file {}: {}", file.display(), err))?;
+    Ok(packed::Transaction::from(tx).into_view())
+}
+
+fn combine_offline(m: &ArgMatches, format: OutputFormat, color: bool) -> Result<String, String> {
+    let offline_files: Vec<PathBuf> =
+        FilePathParser::new(true).from_matches_vec(m, "offline-file")?;
+    let tx_file: PathBuf = FilePathParser::new(false).from_matches(m, "tx-file")?;
+
+    let mut combined: Option<(TxHelper, NetworkType)> = None;
+    for offline_file in offline_files {
+        let repr = read_offline_file(&offline_file)?;
+        let network = repr.network;
+        let helper = TxHelper::try_from(repr.tx_helper)?;
+        if let Some((ref mut combined_helper, _)) = combined {
+            if combined_helper.transaction().hash() != helper.transaction().hash() {
+                return Err(format!(
+                    "Transaction of {} is different from the others",
+                    offline_file.to_string_lossy()
+                ));
+            }
+            for (lock_arg, signatures) in helper.signatures() {
+                for signature in signatures {
+                    combined_helper.add_signature(lock_arg.clone(), signature.clone())?;
+                }
+            }
+        } else {
+            combined = Some((helper, network));
+        }
+    }
+    let (helper, network) = combined.ok_or_else(|| "No offline file given".to_string())?;
+    let tx_hash: H256 = helper.transaction().hash().unpack();
+    let signatures = helper
+        .signatures()
+        .iter()
+        .map(|(lock_arg, signatures)| {
+            (
+                format!("0x{}", hex_string(lock_arg).unwrap()),
+                signatures.len(),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    write_tx_file(&tx_file, helper, network)?;
+    let resp = serde_json::json!({
+        "tx_hash": tx_hash,
+        "signatures": signatures,
+    });
+    Ok(resp.render(format, color))
+}
+
+fn read_offline_file(path: &PathBuf) -> Result<ReprOfflineTx, String> {
+    let file = fs::File::open(path).map_err(|err| err.to_string())?;
+    serde_json::from_reader(&file)
+        .map_err(|err| format!("Invalid offline file {}: {}", path.to_string_lossy(), err))
+}
+
+fn write_offline_file(path: &PathBuf, repr: &ReprOfflineTx) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(repr).map_err(|err| err.to_string())?;
+    write_file_atomic(path, content.as_bytes())
+}
+
 fn print_cell_info(
     prefix: &str,
     network: NetworkType,
+    omnilock: Option<&OmniLockConfig>,
     lock: packed::Script,
     capacity: u64,
     data_len: usize,
     type_script_empty: bool,
 ) {
-    let address_payload = AddressPayload::from(lock);
-    let lock_kind = if address_payload.code_hash() == MULTISIG_TYPE_HASH.pack() {
+    let is_omnilock = omnilock
+        .map(|config| config.is_omnilock(&lock))
+        .unwrap_or(false);
+    let address_payload = match AddressPayload::try_from(lock) {
+        Ok(payload) => payload,
+        Err(err) => {
+            println!("[{}] invalid lock: {}", prefix, err);
+            return;
+        }
+    };
+    let lock_kind = if is_omnilock {
+        "omnilock"
+    } else if address_payload.code_hash() == MULTISIG_TYPE_HASH.pack() {
         if address_payload.args().len() == 20 {
             "multisig without since"
         } else {
@@ -651,18 +1974,213 @@ fn modify_tx_file<T, F: FnOnce(&mut TxHelper) -> Result<T, String>>(
     network: NetworkType,
     func: F,
 ) -> Result<T, String> {
+    let mut helper = read_tx_file(path)?;
+    let result = func(&mut helper)?;
+    write_tx_file(path, helper, network)?;
+    Ok(result)
+}
+
+/// Check the transaction built by `tx add-input` / `add-output`, then add the
+/// change output of the capacity left after the outputs and the fee
+fn complete_tx<F>(
+    helper: &mut TxHelper,
+    change_lock_opt: Option<Script>,
+    fee_policy: FeePolicy,
+    get_live_cell: &mut F,
+) -> Result<serde_json::Value, String>
+where
+    F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+{
+    check_unsigned(helper)?;
+    let first_input = helper
+        .transaction()
+        .inputs()
+        .get(0)
+        .ok_or_else(|| "No input in the transaction".to_string())?;
+    let change_lock = match change_lock_opt {
+        Some(lock) => lock,
+        None => get_live_cell(first_input.previous_output(), false)?
+            .0
+            .lock(),
+    };
+    let (input_total, output_total) = capacity_totals(helper, &mut *get_live_cell)?;
+    let change_output = CellOutput::new_builder().lock(change_lock).build();
+    let change_occupied = change_output
+        .occupied_capacity(Capacity::zero())
+        .map_err(|err| err.to_string())?
+        .as_u64();
+    let tx_fee = match fee_policy {
+        FeePolicy::Fixed(tx_fee) => tx_fee,
+        FeePolicy::Rate(fee_rate) => {
+            // Sized with the change output, it's a little more when no change
+            let mut with_change = helper.clone();
+            with_change.add_output(change_output.clone(), Bytes::new());
+            calculate_fee(
+                fee_rate,
+                estimate_tx_size(&with_change, &mut *get_live_cell)?,
+            )
+        }
+    };
+    if input_total < output_total + tx_fee {
+        return Err(format!(
+            "Capacity of inputs not enough: {}, required: {} (outputs) + {} (fee), add more inputs by `tx add-input`",
+            HumanCapacity(input_total),
+            HumanCapacity(output_total),
+            HumanCapacity(tx_fee),
+        ));
+    }
+    let change = input_total - output_total - tx_fee;
+    if change > 0 {
+        if change < change_occupied {
+            return Err(format!(
+                "The change {} is less than the capacity of a change cell {}, add more inputs or pay it as the fee",
+                HumanCapacity(change),
+                HumanCapacity(change_occupied),
+            ));
+        }
+        let change_output = change_output
+            .as_builder()
+            .capacity(Capacity::shannons(change).pack())
+            .build();
+        helper.add_output(change_output, Bytes::new());
+    }
+    Ok(serde_json::json!({
+        "input_total": format!("{:#}", HumanCapacity(input_total)),
+        "output_total": format!("{:#}", HumanCapacity(output_total + change)),
+        "change": format!("{:#}", HumanCapacity(change)),
+        "tx_fee": format!("{:#}", HumanCapacity(tx_fee)),
+    }))
+}
+
+/// Set the input_type / output_type of the witness of an input, the lock of
+/// the witness is filled by signing
+fn set_witness_types(
+    helper: &mut TxHelper,
+    input_index: usize,
+    input_type: Option<Bytes>,
+    output_type: Option<Bytes>,
+) -> Result<(), String> {
+    check_unsigned(helper)?;
+    let inputs_len = helper.transaction().inputs().len();
+    if input_index >= inputs_len {
+        return Err(format!(
+            "Input index {} out of range, the transaction has {} inputs",
+            input_index, inputs_len
+        ));
+    }
+    let mut witnesses = helper.init_witnesses();
+    let witness_data = witnesses[input_index].raw_data();
+    let witness_args = if witness_data.is_empty() {
+        WitnessArgs::default()
+    } else {
+        WitnessArgs::from_slice(&witness_data)
+            .map_err(|err| format!("Invalid witness of input {}: {}", input_index, err))?
+    };
+    let mut builder = witness_args.as_builder();
+    if let Some(data) = input_type {
+        builder = builder.input_type(Some(data).pack());
+    }
+    if let Some(data) = output_type {
+        builder = builder.output_type(Some(data).pack());
+    }
+    witnesses[input_index] = builder.build().as_bytes().pack();
+
+    let transaction = helper
+        .transaction()
+        .as_advanced_builder()
+        .set_witnesses(witnesses)
+        .build();
+    let mut new_helper = TxHelper::new(transaction);
+    for config in helper.multisig_configs().values() {
+        new_helper.add_multisig_config(config.clone());
+    }
+    new_helper.set_omnilock(helper.omnilock().cloned());
+    new_helper.set_cheque(helper.cheque().cloned());
+    *helper = new_helper;
+    Ok(())
+}
+
+/// The capacity of the inputs and the outputs, the inputs must be live and
+/// not duplicated (their locks are checked by `TxHelper::add_input`)
+fn capacity_totals<F>(helper: &TxHelper, get_live_cell: &mut F) -> Result<(u64, u64), String>
+where
+    F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+{
+    let transaction = helper.transaction();
+    let mut out_points = HashSet::new();
+    let mut input_total = 0;
+    for input in transaction.inputs().into_iter() {
+        let out_point = input.previous_output();
+        if !out_points.insert(out_point.clone()) {
+            return Err(format!(
+                "Duplicated input: {}",
+                display_out_point(&out_point)
+            ));
+        }
+        let (output, _) = get_live_cell(out_point, false)?;
+        let capacity: u64 = output.capacity().unpack();
+        input_total += capacity;
+    }
+    let mut output_total = 0;
+    for output in transaction.outputs().into_iter() {
+        let capacity: u64 = output.capacity().unpack();
+        output_total += capacity;
+    }
+    Ok((input_total, output_total))
+}
+
+// The cells selected by `tx add-input --from-address`: the plain mature cells
+// not in the inputs yet, until the capacity covers `required` (see
+// `covers_required`)
+fn input_selector<'a>(
+    required: u64,
+    max_mature_number: u64,
+    inputs: &'a HashSet<OutPoint>,
+) -> impl FnMut(usize, &LiveCellInfo) -> (bool, bool) + 'a {
+    let mut collected = 0;
+    move |_: usize, info: &LiveCellInfo| {
+        if info.type_hashes.is_none()
+            && info.data_bytes == 0
+            && is_mature(info, max_mature_number)
+            && !inputs.contains(&info.out_point())
+        {
+            collected += info.capacity;
+            (covers_required(collected, required), true)
+        } else {
+            (false, false)
+        }
+    }
+}
+
+// The rest is either zero or enough for a change cell
+fn covers_required(capacity: u64, required: u64) -> bool {
+    capacity == required || capacity >= required + MIN_SECP_CELL_CAPACITY
+}
+
+// The signatures are invalidated by changing the transaction
+fn check_unsigned(helper: &TxHelper) -> Result<(), String> {
+    if helper.signatures().is_empty() {
+        Ok(())
+    } else {
+        Err("The transaction is signed, clear the signatures first by `tx clear-field --field signatures`".to_string())
+    }
+}
+
+/// Load the transaction saved by `write_tx_file`
+pub fn read_tx_file(path: &PathBuf) -> Result<TxHelper, String> {
     let file = fs::File::open(path).map_err(|err| err.to_string())?;
     let repr: ReprTxHelper = serde_json::from_reader(&file).map_err(|err| err.to_string())?;
-    let mut helper = TxHelper::try_from(repr)?;
-
-    let result = func(&mut helper)?;
+    TxHelper::try_from(repr)
+}
 
+/// Save the transaction to a file which can be handled by `tx` subcommand
+/// (e.g. collect more signatures then send it)
+pub fn write_tx_file(path: &PathBuf, helper: TxHelper, network: NetworkType) -> Result<(), String> {
     let repr = ReprTxHelper::new(helper, network);
     let mut file = fs::File::create(path).map_err(|err| err.to_string())?;
     let content = serde_json::to_string_pretty(&repr).map_err(|err| err.to_string())?;
     file.write_all(content.as_bytes())
-        .map_err(|err| err.to_string())?;
-    Ok(result)
+        .map_err(|err| err.to_string())
 }
 
 #[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Debug)]
@@ -671,6 +2189,15 @@ struct ReprTxHelper {
     transaction: json_types::Transaction,
     multisig_configs: HashMap<H160, ReprMultisigConfig>,
     signatures: HashMap<JsonBytes, Vec<JsonBytes>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    omnilock: Option<ReprOmniLockConfig>,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+struct ReprOmniLockConfig {
+    type_hash: H256,
+    cell_dep: json_types::OutPoint,
 }
 
 impl ReprTxHelper {
@@ -701,6 +2228,10 @@ impl ReprTxHelper {
                     )
                 })
                 .collect(),
+            omnilock: tx.omnilock().map(|config| ReprOmniLockConfig {
+                type_hash: config.type_hash().clone(),
+                cell_dep: config.cell_dep().out_point().into(),
+            }),
         }
     }
 }
@@ -726,6 +2257,10 @@ impl TryFrom<ReprTxHelper> for TxHelper {
             .collect();
 
         let mut tx_helper = TxHelper::new(transaction);
+        tx_helper.set_omnilock(
+            repr.omnilock
+                .map(|config| OmniLockConfig::new(config.type_hash, config.cell_dep.into())),
+        );
         for cfg in multisig_configs {
             tx_helper.add_multisig_config(cfg);
         }
@@ -738,6 +2273,56 @@ impl TryFrom<ReprTxHelper> for TxHelper {
     }
 }
 
+/// Everything required to sign the transaction without a node (air-gapped
+/// signing), created by `tx build`
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+struct ReprOfflineTx {
+    network: NetworkType,
+    tx_helper: ReprTxHelper,
+    input_cells: Vec<ReprInputCell>,
+    // lock_arg => blake2b hash of the message to sign
+    signing_digests: HashMap<JsonBytes, H256>,
+}
+
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+#[serde(deny_unknown_fields)]
+struct ReprInputCell {
+    out_point: json_types::OutPoint,
+    output: json_types::CellOutput,
+    // Required by ledger signing
+    source_tx: Transaction,
+}
+
+impl ReprOfflineTx {
+    // The attached source transaction must hash to the out point and hold the
+    // attached output, otherwise a forged input cell would be signed
+    fn live_cells(&self) -> Result<HashMap<OutPoint, (CellOutput, Transaction)>, String> {
+        let mut live_cells = HashMap::default();
+        for cell in &self.input_cells {
+            let out_point: OutPoint = cell.out_point.clone().into();
+            let source_tx = packed::Transaction::from(cell.source_tx.clone()).into_view();
+            if source_tx.hash() != out_point.tx_hash() {
+                return Err(format!(
+                    "The source transaction of input cell {} has hash {:#x}, the offline file may be tampered",
+                    out_point,
+                    source_tx.hash()
+                ));
+            }
+            let output: CellOutput = cell.output.clone().into();
+            let index: u32 = out_point.index().unpack();
+            if source_tx.output(index as usize).as_ref() != Some(&output) {
+                return Err(format!(
+                    "The input cell {} mismatches its source transaction, the offline file may be tampered",
+                    out_point
+                ));
+            }
+            live_cells.insert(out_point, (output, cell.source_tx.clone()));
+        }
+        Ok(live_cells)
+    }
+}
+
 #[derive(Clone, Default, Serialize, Deserialize, PartialEq, Eq, Debug)]
 #[serde(deny_unknown_fields)]
 struct ReprMultisigConfig {
@@ -774,3 +2359,260 @@ impl TryFrom<ReprMultisigConfig> for MultisigConfig {
         MultisigConfig::new_with(sighash_addresses, repr.require_first_n, repr.threshold)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_index::CellIndex;
+    use ckb_sdk::constants::ONE_CKB;
+    use ckb_types::{core::TransactionBuilder as CoreTransactionBuilder, packed::CellInput};
+
+    fn sighash_lock(byte: u8) -> Script {
+        Script::from(&AddressPayload::from_pubkey_hash(H160::from([byte; 20])))
+    }
+
+    fn cell(capacity: u64, lock_byte: u8) -> CellOutput {
+        CellOutput::new_builder()
+            .capacity(Capacity::shannons(capacity).pack())
+            .lock(sighash_lock(lock_byte))
+            .build()
+    }
+
+    // The inputs of 150 and 100 CKB locked by `sighash_lock(1)`
+    fn live_cells() -> (Vec<OutPoint>, HashMap<OutPoint, CellOutput>) {
+        let out_points = vec![
+            OutPoint::new(Default::default(), 0),
+            OutPoint::new(Default::default(), 1),
+        ];
+        let cells = vec![
+            (out_points[0].clone(), cell(150 * ONE_CKB, 1)),
+            (out_points[1].clone(), cell(100 * ONE_CKB, 1)),
+        ];
+        (out_points, cells.into_iter().collect())
+    }
+
+    fn new_helper(inputs: &[OutPoint], output_capacity: u64) -> TxHelper {
+        let transaction = CoreTransactionBuilder::default()
+            .inputs(
+                inputs
+                    .iter()
+                    .map(|out_point| CellInput::new(out_point.clone(), 0)),
+            )
+            .output(cell(output_capacity, 2))
+            .output_data(Bytes::new().pack())
+            .build();
+        TxHelper::new(transaction)
+    }
+
+    #[test]
+    fn test_complete_tx() {
+        let (out_points, cells) = live_cells();
+        let mut get_live_cell = |out_point: OutPoint, _with_data: bool| {
+            cells
+                .get(&out_point)
+                .cloned()
+                .map(|output| {
+                    let source_tx = json_types::Transaction::from(packed::Transaction::default());
+                    (output, Transaction::from(source_tx))
+                })
+                .ok_or_else(|| "Cell not found".to_string())
+        };
+
+        // The change goes back to the lock of the first input
+        let mut helper = new_helper(&out_points, 100 * ONE_CKB);
+        complete_tx(
+            &mut helper,
+            None,
+            FeePolicy::Fixed(1000),
+            &mut get_live_cell,
+        )
+        .unwrap();
+        let outputs = helper.transaction().outputs();
+        assert_eq!(outputs.len(), 2);
+        let change = outputs.get(1).unwrap();
+        let change_capacity: u64 = change.capacity().unpack();
+        assert_eq!(change.lock(), sighash_lock(1));
+        assert_eq!(change_capacity, 150 * ONE_CKB - 1000);
+
+        // The fee by rate is sized with the change output
+        let mut helper = new_helper(&out_points, 100 * ONE_CKB);
+        complete_tx(&mut helper, None, FeePolicy::Rate(1000), &mut get_live_cell).unwrap();
+        let (input_total, output_total) = capacity_totals(&helper, &mut get_live_cell).unwrap();
+        let tx_size = estimate_tx_size(&helper, &mut get_live_cell).unwrap();
+        assert_eq!(input_total - output_total, calculate_fee(1000, tx_size));
+
+        // No change output when the inputs are used up
+        let mut helper = new_helper(&out_points[1..], 100 * ONE_CKB);
+        complete_tx(&mut helper, None, FeePolicy::Fixed(0), &mut get_live_cell).unwrap();
+        assert_eq!(helper.transaction().outputs().len(), 1);
+
+        // The change is less than a change cell, or the inputs are not enough
+        let mut helper = new_helper(&out_points[..1], 100 * ONE_CKB);
+        assert!(complete_tx(
+            &mut helper,
+            None,
+            FeePolicy::Fixed(1000),
+            &mut get_live_cell
+        )
+        .is_err());
+        let mut helper = new_helper(&out_points[1..], 100 * ONE_CKB);
+        assert!(complete_tx(
+            &mut helper,
+            None,
+            FeePolicy::Fixed(1000),
+            &mut get_live_cell
+        )
+        .is_err());
+
+        // A signed transaction is not changed
+        let mut helper = new_helper(&out_points, 100 * ONE_CKB);
+        helper
+            .add_signature(Bytes::from(vec![1u8; 20]), Bytes::from(vec![0u8; 65]))
+            .unwrap();
+        assert!(complete_tx(
+            &mut helper,
+            None,
+            FeePolicy::Fixed(1000),
+            &mut get_live_cell
+        )
+        .is_err());
+        assert_eq!(helper.transaction().outputs().len(), 1);
+    }
+
+    #[test]
+    fn test_capacity_totals() {
+        let (out_points, cells) = live_cells();
+        let mut get_live_cell = |out_point: OutPoint, _with_data: bool| {
+            cells
+                .get(&out_point)
+                .cloned()
+                .map(|output| {
+                    let source_tx = json_types::Transaction::from(packed::Transaction::default());
+                    (output, Transaction::from(source_tx))
+                })
+                .ok_or_else(|| "Cell not found".to_string())
+        };
+        let helper = new_helper(&out_points, 100 * ONE_CKB);
+        assert_eq!(
+            capacity_totals(&helper, &mut get_live_cell),
+            Ok((250 * ONE_CKB, 100 * ONE_CKB))
+        );
+        let duplicated = vec![out_points[0].clone(), out_points[0].clone()];
+        let helper = new_helper(&duplicated, 100 * ONE_CKB);
+        assert!(capacity_totals(&helper, &mut get_live_cell).is_err());
+        let unknown = vec![OutPoint::new(Default::default(), 2)];
+        let helper = new_helper(&unknown, 100 * ONE_CKB);
+        assert!(capacity_totals(&helper, &mut get_live_cell).is_err());
+    }
+
+    #[test]
+    fn test_set_witness_types() {
+        let (out_points, _) = live_cells();
+        let mut helper = new_helper(&out_points, 100 * ONE_CKB);
+        let members = (1..3u8)
+            .map(|i| AddressPayload::from_pubkey_hash(H160::from([i; 20])))
+            .collect::<Vec<_>>();
+        helper.add_multisig_config(MultisigConfig::new_with(members, 0, 1).unwrap());
+
+        // The input type and the output type are merged into the witness
+        set_witness_types(&mut helper, 1, Some(Bytes::from(&b"in"[..])), None).unwrap();
+        set_witness_types(&mut helper, 1, None, Some(Bytes::from(&b"out"[..]))).unwrap();
+        let witnesses = helper.transaction().witnesses();
+        assert_eq!(witnesses.len(), 2);
+        assert!(witnesses.get(0).unwrap().raw_data().is_empty());
+        let witness_args = WitnessArgs::from_slice(&witnesses.get(1).unwrap().raw_data()).unwrap();
+        assert!(witness_args.lock().to_opt().is_none());
+        assert_eq!(
+            witness_args
+                .input_type()
+                .to_opt()
+                .map(|data| data.raw_data()),
+            Some(Bytes::from(&b"in"[..]))
+        );
+        assert_eq!(
+            witness_args
+                .output_type()
+                .to_opt()
+                .map(|data| data.raw_data()),
+            Some(Bytes::from(&b"out"[..]))
+        );
+        assert_eq!(helper.multisig_configs().len(), 1);
+
+        assert!(set_witness_types(&mut helper, 2, None, None).is_err());
+        helper
+            .add_signature(Bytes::from(vec![1u8; 20]), Bytes::from(vec![0u8; 65]))
+            .unwrap();
+        assert!(set_witness_types(&mut helper, 0, None, None).is_err());
+    }
+
+    #[test]
+    fn test_input_selector() {
+        let info = |output_index: u32, capacity: u64| LiveCellInfo {
+            tx_hash: H256::default(),
+            tx_index: output_index,
+            data_bytes: 0,
+            lock_hash: H256::default(),
+            type_hashes: None,
+            capacity,
+            number: 10,
+            index: CellIndex {
+                tx_index: 1,
+                output_index,
+            },
+        };
+        let inputs: HashSet<OutPoint> = vec![info(0, 0).out_point()].into_iter().collect();
+        let mut select = input_selector(100 * ONE_CKB, 100, &inputs);
+
+        // Already an input, with data, or an immature cellbase cell
+        assert_eq!(select(0, &info(0, 100 * ONE_CKB)), (false, false));
+        let mut with_data = info(1, 100 * ONE_CKB);
+        with_data.data_bytes = 1;
+        assert_eq!(select(0, &with_data), (false, false));
+        let mut immature = info(1, 100 * ONE_CKB);
+        immature.index.tx_index = 0;
+        immature.number = 200;
+        assert_eq!(select(0, &immature), (false, false));
+
+        // 20 CKB left is not enough for a change cell, 70 CKB is
+        assert_eq!(select(0, &info(1, 120 * ONE_CKB)), (false, true));
+        assert_eq!(select(1, &info(2, 50 * ONE_CKB)), (true, true));
+
+        assert!(covers_required(100, 100));
+        assert!(!covers_required(101, 100));
+    }
+
+    #[test]
+    fn test_offline_live_cells() {
+        let output = CellOutput::new_builder()
+            .capacity(Capacity::shannons(100).pack())
+            .build();
+        let source_tx = CoreTransactionBuilder::default()
+            .output(output.clone())
+            .output_data(Bytes::new().pack())
+            .build();
+        let out_point = OutPoint::new(source_tx.hash(), 0);
+        let mut repr = ReprOfflineTx {
+            network: NetworkType::Testnet,
+            tx_helper: ReprTxHelper::new(TxHelper::default(), NetworkType::Testnet),
+            input_cells: vec![ReprInputCell {
+                out_point: out_point.clone().into(),
+                output: output.clone().into(),
+                source_tx: Transaction::from(json_types::Transaction::from(source_tx.data())),
+            }],
+            signing_digests: HashMap::default(),
+        };
+        assert_eq!(repr.live_cells().unwrap()[&out_point].0, output);
+
+        // A forged output
+        repr.input_cells[0].output = output
+            .as_builder()
+            .capacity(Capacity::shannons(200).pack())
+            .build()
+            .into();
+        assert!(repr.live_cells().is_err());
+        // A source transaction not of the out point
+        repr.input_cells[0].output = output.into();
+        repr.input_cells[0].out_point = OutPoint::new(Default::default(), 0).into();
+        assert!(repr.live_cells().is_err());
+    }
+}
@@ -1,11 +1,19 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
 use chrono::prelude::*;
 use ckb_crypto::secp::SECP256K1;
 use ckb_hash::blake2b_256;
+use ckb_jsonrpc_types as rpc_types;
+use ckb_ledger::LedgerKeyStore;
 use ckb_sdk::{
     constants::{MULTISIG_TYPE_HASH, SIGHASH_TYPE_HASH},
     rpc::ChainInfo,
-    wallet::KeyStore,
-    Address, AddressPayload, CodeHashIndex, HttpRpcClient, NetworkType, OldAddress,
+    serialize_signature,
+    wallet::{AbstractKeyStore, AbstractMasterPrivKey, AbstractPrivKey, KeyStore},
+    Address, AddressHashType, AddressPayload, CodeHashIndex, HttpRpcClient, NetworkType,
+    OldAddress,
 };
 use ckb_types::{
     bytes::Bytes,
@@ -22,13 +30,16 @@ use secp256k1::recovery::{RecoverableSignature, RecoveryId};
 
 use super::CliSubCommand;
 use crate::utils::{
+    alert::{alert_expiry, AlertConfig},
+    alias::{is_alias_name, AliasResolver},
     arg,
     arg_parser::{
-        AddressParser, AddressPayloadOption, ArgParser, FixedHashParser, FromStrParser, HexParser,
-        PrivkeyPathParser, PrivkeyWrapper, PubkeyHexParser,
+        AddressParser, AddressPayloadOption, ArgParser, FilePathParser, FixedHashParser,
+        FromStrParser, HexParser, PrivkeyPathParser, PrivkeyWrapper, PubkeyHexParser,
     },
-    other::{get_address, read_password, serialize_signature},
-    printer::{OutputFormat, Printable},
+    other::{get_address, parse_hex_u64, read_password},
+    printer::{render_list, OutputFormat, Printable},
+    sign_vectors::{run_vector, software_vectors, SOFTWARE_VECTORS},
 };
 
 const FLAG_SINCE_EPOCH_NUMBER: u64 =
@@ -39,16 +50,22 @@ const BLOCK_PERIOD: u64 = 8 * 1000; // 8 seconds
 pub struct UtilSubCommand<'a> {
     rpc_client: &'a mut HttpRpcClient,
     key_store: &'a mut KeyStore,
+    ledger_key_store: &'a mut LedgerKeyStore,
+    alert_config: AlertConfig,
 }
 
 impl<'a> UtilSubCommand<'a> {
     pub fn new(
         rpc_client: &'a mut HttpRpcClient,
         key_store: &'a mut KeyStore,
+        ledger_key_store: &'a mut LedgerKeyStore,
+        alert_config: AlertConfig,
     ) -> UtilSubCommand<'a> {
         UtilSubCommand {
             rpc_client,
             key_store,
+            ledger_key_store,
+            alert_config,
         }
     }
 
@@ -224,10 +241,126 @@ impl<'a> UtilSubCommand<'a> {
                             .validator(|input| DateTime::parse_from_rfc3339(&input).map(|_| ()).map_err(|err| err.to_string()))
                             .help("The locktime in RFC3339 format. Example: 2014-11-28T21:00:00+00:00")
                     ),
+                SubCommand::with_name("verify-alert")
+                    .about("Verify the signatures of an alert by the alert public keys (default is the alert keys of CKB, override by `alert_signature` in config file)")
+                    .arg(
+                        Arg::with_name("alert-file")
+                            .long("alert-file")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FilePathParser::new(true).validate(input))
+                            .help("The signed alert (json format, same as the parameter of `send_alert` RPC)")
+                    ),
+                SubCommand::with_name("block-assembler")
+                    .about("Print the [block_assembler] section of ckb.toml for mining rewards to an account (single signature lock)")
+                    .arg(
+                        Arg::with_name("account")
+                            .long("account")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| {
+                                FixedHashParser::<H160>::default()
+                                    .validate(input.clone())
+                                    .or_else(|_| AddressParser::default().validate(input))
+                            })
+                            .help("Lock argument (account) or the address of the mining rewards"),
+                    )
+                    .arg(
+                        Arg::with_name("message")
+                            .long("message")
+                            .takes_value(true)
+                            .default_value("0x")
+                            .validator(|input| HexParser.validate(input))
+                            .help("The message in cellbase witness (hex format)"),
+                    ),
+                SubCommand::with_name("hardfork-info")
+                    .about("Show the activation epochs of CKB2021 hardfork features from the node (`get_consensus` RPC)"),
+                SubCommand::with_name("attest-binary")
+                    .about("Print the blake2b hash of this binary, the commit it is built from and the hash of the Cargo.lock it is built with, for audit records")
+                    .arg(
+                        Arg::with_name("expected")
+                            .long("expected")
+                            .takes_value(true)
+                            .validator(|input| {
+                                FixedHashParser::<H256>::default()
+                                    .validate(input.clone())
+                                    .or_else(|_| FilePathParser::new(true).validate(input))
+                            })
+                            .help("Verify the attestation by the expected binary hash, or by a previous attestation (json file, every field of it must match)"),
+                    ),
+                SubCommand::with_name("sign-test-vectors")
+                    .about("Check the derivation, digest, signature and recovery of the signers against test vectors: the software signer by known answers, and the attached ledger device (the hashes are confirmed on the device)")
+                    .arg(
+                        Arg::with_name("skip-ledger")
+                            .long("skip-ledger")
+                            .help("Do not run the vectors by the attached ledger device"),
+                    ),
+                SubCommand::with_name("query-address")
+                    .about("Resolve an alias (eg: alice.bit) to the address by the local aliases or the resolver of ~/.ckb-cli/address-aliases.json, without sending anything")
+                    .arg(
+                        Arg::with_name("name")
+                            .long("name")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| {
+                                if is_alias_name(&input) {
+                                    Ok(())
+                                } else {
+                                    Err(format!("Invalid alias name: {}", input))
+                                }
+                            })
+                            .help("The alias name"),
+                    ),
         ])
     }
 }
 
+impl<'a> UtilSubCommand<'a> {
+    // The vectors by every attached ledger device, only the signature and the
+    // recovery can be checked since the device key is unknown
+    fn ledger_vectors(&mut self) -> Result<Vec<serde_json::Value>, String> {
+        let ledger_ids = self
+            .ledger_key_store
+            .list_accounts()
+            .map_err(|err| err.to_string())?
+            .collect::<Vec<_>>();
+        let mut results = Vec::new();
+        for ledger_id in ledger_ids {
+            let master = self
+                .ledger_key_store
+                .borrow_account(&ledger_id)
+                .map_err(|err| err.to_string())?
+                .clone();
+            let signer = format!("ledger {:#x} ({})", ledger_id.0, master.app_info());
+            for vector in SOFTWARE_VECTORS {
+                eprintln!(
+                    "Please confirm the hash on the ledger device ({})",
+                    vector.path
+                );
+                results.push(run_vector(
+                    &signer,
+                    vector.path,
+                    vector.message,
+                    None,
+                    |path| {
+                        master
+                            .extended_privkey(path.as_ref())
+                            .and_then(|cap| cap.public_key())
+                            .map_err(|err| err.to_string())
+                    },
+                    |path, digest| {
+                        master
+                            .extended_privkey(path.as_ref())
+                            .and_then(|cap| cap.sign_hash(digest))
+                            .map_err(|err| err.to_string())
+                    },
+                ));
+            }
+        }
+        Ok(results)
+    }
+}
+
 impl<'a> CliSubCommand for UtilSubCommand<'a> {
     fn process(
         &mut self,
@@ -237,6 +370,18 @@ impl<'a> CliSubCommand for UtilSubCommand<'a> {
         debug: bool,
     ) -> Result<String, String> {
         match matches.subcommand() {
+            ("sign-test-vectors", Some(m)) => {
+                let mut results = software_vectors();
+                if !m.is_present("skip-ledger") {
+                    results.extend(self.ledger_vectors()?);
+                }
+                let ok = results.iter().all(|result| result["ok"] == true);
+                let resp = serde_json::json!({
+                    "ok": ok,
+                    "vectors": results,
+                });
+                Ok(render_list(&resp, "vectors", format, color))
+            }
             ("key-info", Some(m)) => {
                 let privkey_opt: Option<PrivkeyWrapper> =
                     PrivkeyPathParser.from_matches_opt(m, "privkey-path", false)?;
@@ -277,6 +422,10 @@ message = "0x"
                         "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_string(),
                         "testnet": Address::new(NetworkType::Testnet, address_payload.clone()).to_string(),
                     },
+                    "full_address": {
+                        "mainnet": Address::new(NetworkType::Mainnet, address_payload.clone()).to_full_string(),
+                        "testnet": Address::new(NetworkType::Testnet, address_payload.clone()).to_full_string(),
+                    },
                     // NOTE: remove this later (after all testnet race reward received)
                     "old-testnet-address": old_address.display_with_prefix(NetworkType::Testnet),
                     "lock_arg": format!("{:#x}", lock_arg),
@@ -533,11 +682,214 @@ message = "0x"
                 });
                 Ok(resp.render(format, color))
             }
+            ("verify-alert", Some(m)) => {
+                let path: PathBuf = FilePathParser::new(true).from_matches(m, "alert-file")?;
+                let content = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+                let alert: rpc_types::Alert =
+                    serde_json::from_str(&content).map_err(|err| err.to_string())?;
+                let alert_packed: packed::Alert = alert.clone().into();
+                self.alert_config.verify(&alert_packed)?;
+                let alert_hash: H256 = alert_packed.calc_alert_hash().unpack();
+                let resp = serde_json::json!({
+                    "alert_hash": alert_hash,
+                    "id": alert.id,
+                    "cancel": alert.cancel,
+                    "priority": alert.priority,
+                    "expires": alert_expiry(alert.notice_until.value()),
+                    "message": alert.message,
+                    "verified": true,
+                });
+                Ok(resp.render(format, color))
+            }
+            ("attest-binary", Some(m)) => {
+                let mut attestation = binary_attestation()?;
+                if let Some(expected) = m.value_of("expected") {
+                    let expected = match FixedHashParser::<H256>::default().parse(expected) {
+                        Ok(hash) => serde_json::json!({ "binary_blake2b": hash }),
+                        Err(_) => {
+                            let content =
+                                fs::read_to_string(expected).map_err(|err| err.to_string())?;
+                            serde_json::from_str(&content).map_err(|err| {
+                                format!("Invalid attestation file {}: {}", expected, err)
+                            })?
+                        }
+                    };
+                    let mismatches = attestation_mismatches(&expected, &attestation)?;
+                    if !mismatches.is_empty() {
+                        return Err(format!("Attestation mismatch:\n{}", mismatches.join("\n")));
+                    }
+                    attestation["verified"] = serde_json::json!(true);
+                }
+                Ok(attestation.render(format, color))
+            }
+            ("block-assembler", Some(m)) => {
+                let lock_arg: H160 =
+                    match FixedHashParser::<H160>::default().from_matches(m, "account") {
+                        Ok(lock_arg) => lock_arg,
+                        Err(_) => {
+                            let address: Address =
+                                AddressParser::default().from_matches(m, "account")?;
+                            let payload = address.payload();
+                            // Miners can only unlock the rewards by the standard
+                            // single signature lock (the node does not check it)
+                            if payload.code_hash() != SIGHASH_TYPE_HASH.pack()
+                                || payload.hash_type() != AddressHashType::Type
+                            {
+                                return Err(format!(
+                                    "Address {} is not a single signature (sighash) address",
+                                    address
+                                ));
+                            }
+                            H160::from_slice(&payload.args()).map_err(|err| err.to_string())?
+                        }
+                    };
+                let message: Vec<u8> = HexParser.from_matches(m, "message")?;
+                if !self.key_store.has_account(&lock_arg) {
+                    eprintln!(
+                        "WARNING: account {:#x} is not in the local key store, please make sure you own it",
+                        lock_arg
+                    );
+                }
+                Ok(format!(
+                    "[block_assembler]\ncode_hash = \"{:#x}\"\nargs = \"{:#x}\"\nhash_type = \"type\"\nmessage = \"0x{}\"",
+                    SIGHASH_TYPE_HASH,
+                    lock_arg,
+                    hex_string(&message).unwrap(),
+                ))
+            }
+            ("query-address", Some(m)) => {
+                let name = m.value_of("name").unwrap();
+                let resolved = AliasResolver::load()?.resolve(name)?;
+                let address: Address = AddressParser::default().parse(&resolved)?;
+                let lock_hash: H256 = packed::Script::from(address.payload())
+                    .calc_script_hash()
+                    .unpack();
+                let resp = serde_json::json!({
+                    "name": name,
+                    "address": address.to_string(),
+                    "lock_hash": format!("{:#x}", lock_hash),
+                });
+                Ok(resp.render(format, color))
+            }
+            ("hardfork-info", _) => {
+                // Use untyped calls, the typed decoders do not know the new
+                // data format (block extension, `data1` hash type)
+                let consensus =
+                    self.rpc_client
+                        .call("get_consensus", Vec::new())
+                        .map_err(|err| {
+                            format!(
+                                "{}\n(the node may be older than v0.100 and not support hardfork)",
+                                err
+                            )
+                        })?;
+                let tip_header = self.rpc_client.call("get_tip_header", Vec::new())?;
+                let tip_epoch = parse_hex_u64(&tip_header["inner"]["epoch"])
+                    .or_else(|| parse_hex_u64(&tip_header["epoch"]))
+                    .map(|value| EpochNumberWithFraction::from_full_value(value).number())
+                    .ok_or_else(|| format!("Invalid tip header: {}", tip_header))?;
+                let features = consensus["hardfork_features"]
+                    .as_array()
+                    .ok_or_else(|| "Missing hardfork_features in consensus".to_string())?
+                    .iter()
+                    .map(|feature| {
+                        let epoch_opt = parse_hex_u64(&feature["epoch_number"]);
+                        let status = match epoch_opt {
+                            Some(epoch) if epoch <= tip_epoch => "active".to_string(),
+                            Some(epoch) => format!("pending (in {} epochs)", epoch - tip_epoch),
+                            None => "not scheduled".to_string(),
+                        };
+                        let rfc = feature["rfc"].as_str().unwrap_or_default();
+                        let mut item = serde_json::json!({
+                            "rfc": rfc,
+                            "epoch_number": epoch_opt,
+                            "status": status,
+                        });
+                        if let Some(note) = hardfork_feature_note(rfc) {
+                            item["note"] = serde_json::json!(note);
+                        }
+                        item
+                    })
+                    .collect::<Vec<_>>();
+                let resp = serde_json::json!({
+                    "chain": consensus["id"],
+                    "tip_epoch": tip_epoch,
+                    "hardfork_features": features,
+                });
+                Ok(resp.render(format, color))
+            }
             _ => Err(matches.usage().to_owned()),
         }
     }
 }
 
+// The features change the format of data decoded by ckb-cli
+fn hardfork_feature_note(rfc: &str) -> Option<&'static str> {
+    match rfc {
+        "0031" => Some("Blocks may have an extension field"),
+        "0032" => Some("Scripts may use hash_type data1 (CKB-VM version 1)"),
+        _ => None,
+    }
+}
+
+fn binary_attestation() -> Result<serde_json::Value, String> {
+    let binary_path = env::current_exe().map_err(|err| err.to_string())?;
+    let binary = fs::read(&binary_path)
+        .map_err(|err| format!("Read binary {} failed: {}", binary_path.display(), err))?;
+    let version = crate::get_version();
+    Ok(serde_json::json!({
+        "version": version.short(),
+        "commit": version.commit_describe,
+        "commit_date": version.commit_date,
+        "binary_path": binary_path.display().to_string(),
+        "binary_blake2b": format!("0x{}", hex_string(&blake2b_256(&binary)).unwrap()),
+        "cargo_lock_blake2b": cargo_lock_blake2b(),
+    }))
+}
+
+// The hash of the Cargo.lock this binary is built with (see build.rs), `null`
+// when it was missing at build time
+fn cargo_lock_blake2b() -> serde_json::Value {
+    match env!("CARGO_LOCK_BLAKE2B") {
+        "" => serde_json::Value::Null,
+        hash => serde_json::Value::from(hash),
+    }
+}
+
+// The fields of `expected` which differ from `attestation`, the binary path
+// may differ between hosts. The expected binary hash is required, otherwise
+// any binary would be verified.
+fn attestation_mismatches(
+    expected: &serde_json::Value,
+    attestation: &serde_json::Value,
+) -> Result<Vec<String>, String> {
+    let expected = expected
+        .as_object()
+        .ok_or_else(|| "The expected attestation is not a json object".to_string())?;
+    if !expected
+        .get("binary_blake2b")
+        .map(serde_json::Value::is_string)
+        .unwrap_or(false)
+    {
+        return Err("The expected attestation has no binary_blake2b".to_string());
+    }
+    Ok(expected
+        .iter()
+        .filter(|(key, _)| key.as_str() != "binary_path" && key.as_str() != "verified")
+        .filter(|(key, value)| attestation.get(key.as_str()) != Some(value))
+        .map(|(key, value)| {
+            format!(
+                "{}: expected {}, got {}",
+                key,
+                value,
+                attestation
+                    .get(key.as_str())
+                    .unwrap_or(&serde_json::Value::Null)
+            )
+        })
+        .collect())
+}
+
 fn sign_message(
     from_privkey_opt: Option<&PrivkeyWrapper>,
     from_account_opt: Option<(&KeyStore, &H160)>,
@@ -630,4 +982,53 @@ mod test {
         );
         assert_eq!(epoch, EpochNumberWithFraction::new(3, 300, EPOCH_LENGTH))
     }
+
+    #[test]
+    fn test_attestation_mismatches() {
+        let attestation = serde_json::json!({
+            "commit": "v0.27.1-abc",
+            "binary_path": "/usr/bin/ckb-cli",
+            "binary_blake2b": "0x01",
+        });
+        let expected = serde_json::json!({
+            "commit": "v0.27.1-abc",
+            "binary_path": "/opt/ckb-cli",
+            "binary_blake2b": "0x01",
+            "verified": true,
+        });
+        assert!(attestation_mismatches(&expected, &attestation)
+            .unwrap()
+            .is_empty());
+        // Without the binary hash nothing is verified
+        assert!(attestation_mismatches(&serde_json::json!({}), &attestation).is_err());
+        assert!(attestation_mismatches(
+            &serde_json::json!({ "commit": "v0.27.1-abc" }),
+            &attestation
+        )
+        .is_err());
+        let expected = serde_json::json!({
+            "binary_blake2b": "0x02",
+            "commit": "v0.27.0-def",
+            "cargo_lock_blake2b": "0x03",
+        });
+        assert_eq!(
+            attestation_mismatches(&expected, &attestation)
+                .unwrap()
+                .len(),
+            3
+        );
+        assert!(attestation_mismatches(&serde_json::json!("0x01"), &attestation).is_err());
+    }
+
+    #[test]
+    fn test_cargo_lock_blake2b() {
+        match cargo_lock_blake2b() {
+            serde_json::Value::Null => {}
+            serde_json::Value::String(hash) => {
+                assert!(hash.starts_with("0x"));
+                assert_eq!(hash.len(), 66);
+            }
+            value => panic!("Invalid cargo_lock_blake2b: {}", value),
+        }
+    }
 }
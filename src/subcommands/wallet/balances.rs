@@ -0,0 +1,290 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+
+use ckb_index::LiveCellInfo;
+use ckb_sdk::{constants::DAO_TYPE_HASH, Address, AddressPayload, HumanCapacity};
+use ckb_types::{packed::Script, H160};
+use clap::{Arg, ArgMatches};
+
+use super::WalletSubCommand;
+use crate::utils::{
+    alias::resolve_address,
+    arg_parser::{ArgParser, FilePathParser, FromStrParser},
+    index_backend::{CellQuery, IndexBackend, IndexBackendConfig},
+    other::{get_max_mature_number, get_network_type, is_mature},
+    printer::{render_csv, OutputFormat, Printable},
+};
+
+/// The capacity of an address, the immature capacity is also counted in
+/// `free` (the same as the single address output).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Balance {
+    pub total: u64,
+    pub free: u64,
+    pub dao: u64,
+    pub immature: u64,
+}
+
+impl Balance {
+    pub fn add_cell(&mut self, info: &LiveCellInfo, max_mature_number: u64) {
+        if !is_mature(info, max_mature_number) {
+            self.immature += info.capacity;
+        }
+        if info
+            .type_hashes
+            .as_ref()
+            .filter(|(code_hash, _)| code_hash == &DAO_TYPE_HASH)
+            .is_some()
+        {
+            self.dao += info.capacity;
+        } else {
+            self.free += info.capacity;
+        }
+        self.total += info.capacity;
+    }
+
+    pub fn merge(&mut self, other: &Balance) {
+        self.total += other.total;
+        self.free += other.free;
+        self.dao += other.dao;
+        self.immature += other.immature;
+    }
+
+    fn fields(&self) -> Vec<String> {
+        [self.total, self.free, self.dao, self.immature]
+            .iter()
+            .map(|capacity| format!("{:#}", HumanCapacity::from(*capacity)))
+            .collect()
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let fields = self.fields();
+        serde_json::json!({
+            "total": fields[0],
+            "free": fields[1],
+            "dao": fields[2],
+            "immature": fields[3],
+        })
+    }
+}
+
+/// The `(line number, address)` of an addresses file, one address (or alias)
+/// per line, empty lines and `#` comments are skipped.
+pub fn address_lines(content: &str) -> Vec<(usize, &str)> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(idx, line)| (idx + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+fn address_balance(
+    backend: &mut dyn IndexBackend,
+    address: &Address,
+    max_mature_number: u64,
+) -> Result<Balance, String> {
+    let query = CellQuery::Lock(Script::from(address.payload()));
+    let mut balance = Balance::default();
+    for info in backend.get_live_cells(&query, None, &mut |_, _| (false, true))? {
+        balance.add_cell(&info, max_mature_number);
+    }
+    Ok(balance)
+}
+
+/// Query the balances by `concurrency` threads, each thread builds its own
+/// index backend. The results are in the order of `addresses`.
+pub fn query_balances(
+    config: &IndexBackendConfig,
+    addresses: &[Address],
+    concurrency: usize,
+    max_mature_number: u64,
+) -> Vec<Result<Balance, String>> {
+    let (job_sender, job_receiver) = crossbeam_channel::unbounded();
+    let (result_sender, result_receiver) = crossbeam_channel::unbounded();
+    for job in addresses.iter().cloned().enumerate() {
+        job_sender.send(job).expect("send query job");
+    }
+    drop(job_sender);
+
+    let handles = (0..concurrency.min(addresses.len()))
+        .map(|_| {
+            let config = config.clone();
+            let job_receiver = job_receiver.clone();
+            let result_sender = result_sender.clone();
+            thread::spawn(move || {
+                let mut backend = config.build();
+                for (idx, address) in job_receiver.iter() {
+                    let result = match backend {
+                        Ok(ref mut backend) => {
+                            address_balance(backend.as_mut(), &address, max_mature_number)
+                        }
+                        Err(ref err) => Err(err.clone()),
+                    };
+                    if result_sender.send((idx, result)).is_err() {
+                        break;
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(result_sender);
+
+    let mut balances = vec![None; addresses.len()];
+    for (idx, result) in result_receiver.iter() {
+        balances[idx] = Some(result);
+    }
+    for handle in handles {
+        let _ = handle.join();
+    }
+    balances
+        .into_iter()
+        .map(|result| result.unwrap_or_else(|| Err("The query thread panicked".to_string())))
+        .collect()
+}
+
+impl<'a> WalletSubCommand<'a> {
+    /// `get-capacity` of the addresses in `--addresses-file` or all the
+    /// keystore accounts, one row per address plus the totals.
+    pub(crate) fn get_capacities(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        let addresses = if let Some(path) = m.value_of("addresses-file") {
+            let path: PathBuf = FilePathParser::new(true).parse(path)?;
+            let content = fs::read_to_string(&path)
+                .map_err(|err| format!("Read {} failed: {}", path.display(), err))?;
+            address_lines(&content)
+                .into_iter()
+                .map(|(line_number, address)| {
+                    resolve_address(address, network_type)
+                        .map_err(|err| format!("Invalid address at line {}: {}", line_number, err))
+                })
+                .collect::<Result<Vec<_>, String>>()?
+        } else {
+            let mut lock_args = self
+                .key_store
+                .get_accounts()
+                .keys()
+                .cloned()
+                .collect::<Vec<H160>>();
+            lock_args.sort();
+            lock_args
+                .into_iter()
+                .map(|lock_arg| {
+                    Address::new(network_type, AddressPayload::from_pubkey_hash(lock_arg))
+                })
+                .collect()
+        };
+        let concurrency: usize =
+            FromStrParser::<usize>::default().from_matches(m, "concurrency")?;
+        if concurrency == 0 {
+            return Err("--concurrency must be greater than 0".to_string());
+        }
+
+        let genesis_info = self.genesis_info()?;
+        let config = IndexBackendConfig::from_matches(
+            m,
+            self.index_dir.clone(),
+            genesis_info,
+            network_type,
+            Arc::clone(self.index_controller.state()),
+        )?;
+        // The local index database is locked by each opening
+        let concurrency = if config.is_remote() { concurrency } else { 1 };
+        let max_mature_number = get_max_mature_number(self.rpc_client)?;
+        let results = query_balances(&config, &addresses, concurrency, max_mature_number);
+
+        let mut totals = Balance::default();
+        let mut rows = Vec::new();
+        let mut csv_rows = Vec::new();
+        for (address, result) in addresses.iter().zip(results) {
+            let address = address.to_string();
+            match result {
+                Ok(balance) => {
+                    totals.merge(&balance);
+                    let mut row = balance.to_json();
+                    row["address"] = serde_json::json!(address);
+                    rows.push(row);
+                    let mut csv_row = vec![address];
+                    csv_row.extend(balance.fields());
+                    csv_row.push(String::new());
+                    csv_rows.push(csv_row);
+                }
+                Err(err) => {
+                    rows.push(serde_json::json!({"address": address, "error": err}));
+                    let mut csv_row = vec![address];
+                    csv_row.extend(vec![String::new(); 4]);
+                    csv_row.push(err);
+                    csv_rows.push(csv_row);
+                }
+            }
+        }
+
+        if format == OutputFormat::Csv {
+            let mut total_row = vec!["total".to_string()];
+            total_row.extend(totals.fields());
+            total_row.push(String::new());
+            csv_rows.push(total_row);
+            let headers = ["address", "total", "free", "dao", "immature", "error"];
+            return Ok(render_csv(&headers, &csv_rows));
+        }
+        let mut resp = totals.to_json();
+        resp["addresses"] = serde_json::json!(rows);
+        Ok(resp.render(format, color))
+    }
+}
+
+/// The arguments of querying many addresses by `get-capacity`
+pub fn args() -> Vec<Arg<'static, 'static>> {
+    let single_args = &[
+        "lock-hash",
+        "address",
+        "pubkey",
+        "lock-arg",
+        "derived",
+        "read-through",
+        "at-block",
+    ];
+    vec![
+        Arg::with_name("addresses-file")
+            .long("addresses-file")
+            .takes_value(true)
+            .validator(|input| FilePathParser::new(true).validate(input))
+            .conflicts_with_all(single_args)
+            .help("Query the addresses in this file (one address or alias per line, `#` comments are skipped), output one row per address and the totals"),
+        Arg::with_name("all-accounts")
+            .long("all-accounts")
+            .conflicts_with_all(single_args)
+            .conflicts_with("addresses-file")
+            .help("Query all the keystore accounts (without derived addresses), output one row per account and the totals"),
+        Arg::with_name("concurrency")
+            .long("concurrency")
+            .takes_value(true)
+            .default_value("8")
+            .validator(|input| FromStrParser::<usize>::default().validate(input))
+            .help("The number of addresses queried at the same time by --addresses-file or --all-accounts (indexer backend only)"),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_address_lines() {
+        let content = "# treasury\nckt1qyqt8xaupvm8837nv3gtc9x0ekkj64vud3jqfwyw5v\n\n  alice  \n";
+        assert_eq!(
+            address_lines(content),
+            vec![
+                (2, "ckt1qyqt8xaupvm8837nv3gtc9x0ekkj64vud3jqfwyw5v"),
+                (4, "alice")
+            ]
+        );
+    }
+}
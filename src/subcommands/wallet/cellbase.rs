@@ -0,0 +1,57 @@
+use std::collections::BTreeMap;
+
+use chrono::{TimeZone, Utc};
+use ckb_types::core::EpochNumberWithFraction;
+
+/// The first block (number) a cellbase cell created at `cell_epoch` is mature,
+/// `target_start` and `target_length` describe the epoch the cell matures in
+/// (`cell_epoch.number() + 4`).
+pub fn mature_block_number(
+    cell_epoch: EpochNumberWithFraction,
+    target_start: u64,
+    target_length: u64,
+) -> u64 {
+    // The first block of target epoch whose fraction is not less than the
+    // fraction of cell's epoch
+    let length = cell_epoch.length().max(1);
+    target_start + (cell_epoch.index() * target_length + length - 1) / length
+}
+
+/// Sum the capacity and count the cells by the day (UTC) they are mature,
+/// `cells` are `(mature timestamp in milliseconds, capacity)`.
+pub fn group_by_day(cells: &[(u64, u64)]) -> BTreeMap<String, (u64, usize)> {
+    let mut days: BTreeMap<String, (u64, usize)> = BTreeMap::new();
+    for (timestamp, capacity) in cells {
+        let day = Utc
+            .timestamp_millis(*timestamp as i64)
+            .format("%Y-%m-%d")
+            .to_string();
+        let entry = days.entry(day).or_insert((0, 0));
+        entry.0 += capacity;
+        entry.1 += 1;
+    }
+    days
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mature_block_number() {
+        let epoch = EpochNumberWithFraction::new(10, 0, 1000);
+        assert_eq!(mature_block_number(epoch, 50000, 1200), 50000);
+        let epoch = EpochNumberWithFraction::new(10, 500, 1000);
+        assert_eq!(mature_block_number(epoch, 50000, 1200), 50600);
+        let epoch = EpochNumberWithFraction::new(10, 1, 3);
+        assert_eq!(mature_block_number(epoch, 50000, 1000), 50334);
+    }
+
+    #[test]
+    fn test_group_by_day() {
+        let day = 24 * 3600 * 1000;
+        let days = group_by_day(&[(day, 100), (day + 1000, 200), (day * 2, 300)]);
+        assert_eq!(days["1970-01-02"], (300, 2));
+        assert_eq!(days["1970-01-03"], (300, 1));
+    }
+}
@@ -0,0 +1,594 @@
+use std::collections::HashMap;
+
+use ckb_index::LiveCellInfo;
+use ckb_sdk::{
+    cheque_lock_hash_prefix, cheque_withdraw_since, parse_cheque_args, rpc::Transaction, Address,
+    AddressPayload, ChequeConfig, CollectedCell, HttpRpcClient, HumanCapacity, NetworkType,
+    TransactionBuilder, CHEQUE_WITHDRAW_EPOCHS,
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::{Capacity, DepType, EpochNumberWithFraction, TransactionView},
+    packed::{Byte32, CellDep, CellOutput, OutPoint, Script},
+    prelude::*,
+    H160, H256,
+};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use either::Either;
+
+use super::super::account::AccountId;
+use super::super::sudt::{
+    amount_from_matches, parse_udt_amount, sender_payload, udt_data, udt_output, udt_type_script,
+    UDT_AMOUNT_SIZE,
+};
+use super::WalletSubCommand;
+use crate::utils::{
+    alias::resolve_address,
+    arg,
+    arg_parser::{
+        AddressParser, ArgParser, FixedHashParser, FromStrParser, OutPointParser, PrivkeyWrapper,
+    },
+    other::{
+        get_account_signer, get_fee_policy, get_live_cell, get_live_cell_with_cache,
+        get_max_mature_number, get_network_type, is_mature, privkey_or_from_account,
+    },
+    printer::{OutputFormat, Printable},
+};
+
+/// The role of my account in a cheque cell
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChequeRole {
+    /// Can claim the token at any time
+    Receiver,
+    /// Can withdraw the token after `CHEQUE_WITHDRAW_EPOCHS` epochs
+    Sender,
+}
+
+impl ChequeRole {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChequeRole::Receiver => "receiver",
+            ChequeRole::Sender => "sender",
+        }
+    }
+}
+
+/// An outstanding cheque cell of my account
+struct ChequeCell {
+    info: LiveCellInfo,
+    role: ChequeRole,
+    // The lock hash prefix of the other side
+    counterparty: H160,
+    amount: u128,
+    // The transaction sent the cheque
+    transaction: Transaction,
+}
+
+/// The cheque input has relative since of `CHEQUE_WITHDRAW_EPOCHS` epochs
+/// (the fraction part is 0), compare with the fraction of both epochs.
+pub fn is_withdrawable(
+    cell_epoch: EpochNumberWithFraction,
+    tip_epoch: EpochNumberWithFraction,
+) -> bool {
+    let target = cell_epoch.number() + CHEQUE_WITHDRAW_EPOCHS;
+    if tip_epoch.number() != target {
+        return tip_epoch.number() > target;
+    }
+    // index / length, the length of genesis epoch fraction may be 0
+    let cell_length = cell_epoch.length().max(1);
+    let tip_length = tip_epoch.length().max(1);
+    tip_epoch.index() * cell_length >= cell_epoch.index() * tip_length
+}
+
+pub fn subcommands() -> Vec<App<'static, 'static>> {
+    let arg_cheque_type_hash = Arg::with_name("cheque-type-hash")
+        .long("cheque-type-hash")
+        .takes_value(true)
+        .required(true)
+        .validator(|input| FixedHashParser::<H256>::default().validate(input))
+        .help("The type hash of cheque lock script (it is not deployed in the genesis block)");
+    let arg_cheque_dep = Arg::with_name("cheque-dep")
+        .long("cheque-dep")
+        .takes_value(true)
+        .required(true)
+        .validator(|input| OutPointParser.validate(input))
+        .help("The out point of cheque lock script cell (format: {tx_hash}-{index}, dep_type is code)");
+    let arg_privkey_path = arg::privkey_path().required_unless(arg::from_account().b.name);
+    let arg_from_account = arg::from_account()
+        .required_unless(arg::privkey_path().b.name)
+        .conflicts_with(arg::privkey_path().b.name);
+    let arg_tx_fee = arg::tx_fee().required_unless(arg::fee_rate().b.name);
+    let arg_fee_rate = arg::fee_rate().conflicts_with(arg::tx_fee().b.name);
+
+    vec![
+        SubCommand::with_name("send-cheque")
+            .about("Send sUDT to an address by a cheque cell, the receiver claims it by `claim-cheque`, the sender can withdraw it after 6 epochs if not claimed")
+            .arg(arg_privkey_path.clone())
+            .arg(arg_from_account.clone())
+            .arg(arg::to_address().required(true))
+            .arg(
+                Arg::with_name("amount")
+                    .long("amount")
+                    .takes_value(true)
+                    .required(true)
+                    .validator(|input| FromStrParser::<u128>::default().validate(input))
+                    .help("The amount of token (u128, the decimals is not considered)"),
+            )
+            .arg(arg::udt_owner())
+            .arg(arg::udt_code_hash())
+            .arg(arg::udt_cell_dep())
+            .arg(arg_cheque_type_hash.clone())
+            .arg(arg_cheque_dep.clone())
+            .arg(arg_tx_fee.clone())
+            .arg(arg_fee_rate.clone())
+            .arg(arg::allow_high_fee())
+            .arg(arg::on_failure())
+            .arg(arg::memo()),
+        SubCommand::with_name("claim-cheque")
+            .about("Claim all the cheques of a token sent to the account, the capacity of cheque cells goes back to the senders")
+            .arg(arg_privkey_path.clone())
+            .arg(arg_from_account.clone())
+            .arg(arg::udt_owner())
+            .arg(arg::udt_code_hash())
+            .arg(arg::udt_cell_dep())
+            .arg(arg_cheque_type_hash.clone())
+            .arg(arg_cheque_dep.clone())
+            .arg(arg_tx_fee.clone())
+            .arg(arg_fee_rate.clone())
+            .arg(arg::allow_high_fee())
+            .arg(arg::on_failure())
+            .arg(arg::memo()),
+        SubCommand::with_name("withdraw-cheque")
+            .about("Withdraw the unclaimed cheques of a token sent by the account (6 epochs after sent)")
+            .arg(arg_privkey_path)
+            .arg(arg_from_account)
+            .arg(arg::udt_owner())
+            .arg(arg::udt_code_hash())
+            .arg(arg::udt_cell_dep())
+            .arg(arg_cheque_type_hash.clone())
+            .arg(arg_cheque_dep)
+            .arg(arg_tx_fee)
+            .arg(arg_fee_rate)
+            .arg(arg::allow_high_fee())
+            .arg(arg::on_failure())
+            .arg(arg::memo()),
+        SubCommand::with_name("cheque-cells")
+            .about("List the outstanding cheques of a token sent to or sent by an address (with local index)")
+            .arg(arg::address().required(true))
+            .arg(arg::udt_owner())
+            .arg(arg::udt_code_hash())
+            .arg(arg_cheque_type_hash),
+    ]
+}
+
+impl<'a> WalletSubCommand<'a> {
+    pub fn send_cheque(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        let from_account = privkey_or_from_account(m)?;
+        let sender = sender_payload(&from_account)?;
+        let receiver: Address = resolve_address(m.value_of("to-address").unwrap(), network_type)?;
+        let amount = amount_from_matches(m)?;
+        let type_script = udt_type_script_from_matches(m, network_type)?;
+        let config = cheque_config(m)?;
+        let sender_lock = Script::from(&sender);
+
+        let cells = self.collect_udt_cells(&type_script, &sender_lock, amount)?;
+        let total: u128 = cells.iter().map(|(_, amount)| amount).sum();
+        if total < amount {
+            return Err(format!(
+                "Token amount not enough: required {}, found {}",
+                amount, total
+            ));
+        }
+
+        let mut builder = TransactionBuilder::default();
+        let cheque_lock = config.lock_script(
+            &Script::from(receiver.payload()).calc_script_hash(),
+            &sender_lock.calc_script_hash(),
+        );
+        builder.output(
+            udt_output(cheque_lock, type_script.clone())?,
+            udt_data(amount),
+        );
+        let rest = total - amount;
+        if rest > 0 {
+            builder.output(udt_output(sender_lock, type_script)?, udt_data(rest));
+        }
+        let udt_inputs = cells
+            .iter()
+            .map(|(info, _)| CollectedCell::new(info.out_point(), info.capacity))
+            .collect();
+        let tx = self.build_cheque_tx(m, from_account, &sender, builder, udt_inputs, false)?;
+        let memo = m.value_of("memo").map(ToOwned::to_owned);
+        self.send_transaction(tx, memo, format, color, debug)
+    }
+
+    pub fn claim_cheque(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        let from_account = privkey_or_from_account(m)?;
+        let receiver = sender_payload(&from_account)?;
+        let type_script = udt_type_script_from_matches(m, network_type)?;
+        let config = cheque_config(m)?;
+        let receiver_lock = Script::from(&receiver);
+
+        let cheques = self
+            .find_cheque_cells(&config, &type_script, &receiver_lock.calc_script_hash())?
+            .into_iter()
+            .filter(|cheque| cheque.role == ChequeRole::Receiver)
+            .collect::<Vec<_>>();
+        if cheques.is_empty() {
+            return Err("No cheque to claim".to_string());
+        }
+
+        let mut builder = TransactionBuilder::default();
+        builder.cheque(config);
+        let mut total: u128 = 0;
+        for cheque in &cheques {
+            // The capacity of cheque cell goes back to the sender
+            let sender_lock =
+                resolve_cheque_sender(self.rpc_client, &cheque.transaction, &cheque.counterparty)?;
+            let output = CellOutput::new_builder()
+                .capacity(Capacity::shannons(cheque.info.capacity).pack())
+                .lock(sender_lock)
+                .build();
+            builder.output(output, Bytes::new());
+            total = total
+                .checked_add(cheque.amount)
+                .ok_or_else(|| "Token amount overflow".to_string())?;
+        }
+        builder.output(udt_output(receiver_lock, type_script)?, udt_data(total));
+        let cheque_inputs = cheques
+            .iter()
+            .map(|cheque| CollectedCell::new(cheque.info.out_point(), cheque.info.capacity))
+            .collect();
+        let tx = self.build_cheque_tx(m, from_account, &receiver, builder, cheque_inputs, true)?;
+        let memo = m.value_of("memo").map(ToOwned::to_owned);
+        self.send_transaction(tx, memo, format, color, debug)
+    }
+
+    pub fn withdraw_cheque(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        let from_account = privkey_or_from_account(m)?;
+        let sender = sender_payload(&from_account)?;
+        let type_script = udt_type_script_from_matches(m, network_type)?;
+        let config = cheque_config(m)?;
+        let sender_lock = Script::from(&sender);
+
+        let sent = self
+            .find_cheque_cells(&config, &type_script, &sender_lock.calc_script_hash())?
+            .into_iter()
+            .filter(|cheque| cheque.role == ChequeRole::Sender)
+            .collect::<Vec<_>>();
+        let tip_epoch = self.tip_epoch()?;
+        let mut cheques = Vec::new();
+        for cheque in sent {
+            if is_withdrawable(self.cell_epoch(&cheque.info)?, tip_epoch) {
+                cheques.push(cheque);
+            }
+        }
+        if cheques.is_empty() {
+            return Err(format!(
+                "No cheque to withdraw, the cheque can be withdrawn {} epochs after sent",
+                CHEQUE_WITHDRAW_EPOCHS
+            ));
+        }
+
+        let mut builder = TransactionBuilder::default();
+        builder.cheque(config);
+        let mut total: u128 = 0;
+        for cheque in &cheques {
+            builder.input_since(cheque.info.out_point(), cheque_withdraw_since());
+            total = total
+                .checked_add(cheque.amount)
+                .ok_or_else(|| "Token amount overflow".to_string())?;
+        }
+        // The rest capacity of cheque cells goes to the change
+        builder.output(udt_output(sender_lock, type_script)?, udt_data(total));
+        let cheque_inputs = cheques
+            .iter()
+            .map(|cheque| CollectedCell::new(cheque.info.out_point(), cheque.info.capacity))
+            .collect();
+        let tx = self.build_cheque_tx(m, from_account, &sender, builder, cheque_inputs, true)?;
+        let memo = m.value_of("memo").map(ToOwned::to_owned);
+        self.send_transaction(tx, memo, format, color, debug)
+    }
+
+    pub fn cheque_cells(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        let address: Address = AddressParser::default()
+            .set_network(network_type)
+            .from_matches(m, "address")?;
+        let type_script = udt_type_script_from_matches(m, network_type)?;
+        // Only the type hash is required to identify the cheque cells
+        let type_hash: H256 =
+            FixedHashParser::<H256>::default().from_matches(m, "cheque-type-hash")?;
+        let config = ChequeConfig::new(type_hash, OutPoint::default());
+
+        let lock_hash = Script::from(address.payload()).calc_script_hash();
+        let cheques = self.find_cheque_cells(&config, &type_script, &lock_hash)?;
+        let tip_epoch = self.tip_epoch()?;
+        let mut resp = Vec::with_capacity(cheques.len());
+        for cheque in &cheques {
+            let cell_epoch = self.cell_epoch(&cheque.info)?;
+            let withdraw_epoch = cell_epoch.number() + CHEQUE_WITHDRAW_EPOCHS;
+            resp.push(serde_json::json!({
+                "out_point": format!("{:#x}-{}", cheque.info.tx_hash, cheque.info.tx_index),
+                "role": cheque.role.as_str(),
+                "counterparty_lock_hash_prefix": cheque.counterparty,
+                // u128 may not fit in a JSON number
+                "amount": cheque.amount.to_string(),
+                "capacity": format!("{:#}", HumanCapacity::from(cheque.info.capacity)),
+                "block_number": cheque.info.number,
+                "withdraw_epoch": withdraw_epoch,
+                "withdrawable": is_withdrawable(cell_epoch, tip_epoch),
+            }));
+        }
+        Ok(serde_json::json!({ "cheques": resp }).render(format, color))
+    }
+
+    // Collect live sUDT cells of `lock_script`, stop when the total amount
+    // reaches `required`
+    fn collect_udt_cells(
+        &mut self,
+        type_script: &Script,
+        lock_script: &Script,
+        required: u128,
+    ) -> Result<Vec<(LiveCellInfo, u128)>, String> {
+        let lock_hash: H256 = lock_script.calc_script_hash().unpack();
+        let type_hash = type_script.calc_script_hash();
+        let infos = self.with_db(|db| {
+            db.get_live_cells_by_type(type_hash, None, |_, info| {
+                (
+                    false,
+                    info.lock_hash == lock_hash && info.data_bytes >= UDT_AMOUNT_SIZE as u64,
+                )
+            })
+        })?;
+        let mut cells = Vec::new();
+        let mut total: u128 = 0;
+        for info in infos {
+            if total >= required {
+                break;
+            }
+            let (_, data) = get_live_cell(self.rpc_client, info.out_point(), true)?;
+            let amount = parse_udt_amount(&data)?;
+            total = total
+                .checked_add(amount)
+                .ok_or_else(|| "Token amount overflow".to_string())?;
+            cells.push((info, amount));
+        }
+        Ok(cells)
+    }
+
+    // The cheque cells of a token are found from the index by the sUDT type
+    // script, then the cheque lock args are matched with the lock hash
+    fn find_cheque_cells(
+        &mut self,
+        config: &ChequeConfig,
+        type_script: &Script,
+        lock_hash: &Byte32,
+    ) -> Result<Vec<ChequeCell>, String> {
+        let my_prefix = cheque_lock_hash_prefix(lock_hash);
+        let type_hash = type_script.calc_script_hash();
+        let matched = self.with_db(|db| {
+            db.get_live_cells_by_type(type_hash, None, |_, info| {
+                (false, info.data_bytes >= UDT_AMOUNT_SIZE as u64)
+            })
+            .into_iter()
+            .filter_map(|info| {
+                let lock = db.get_lock_script_by_hash(info.lock_hash.pack())?;
+                if !config.is_cheque(&lock) {
+                    return None;
+                }
+                let (receiver, sender) = parse_cheque_args(&lock.args().raw_data()).ok()?;
+                if receiver == my_prefix {
+                    Some((info, ChequeRole::Receiver, sender))
+                } else if sender == my_prefix {
+                    Some((info, ChequeRole::Sender, receiver))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>()
+        })?;
+
+        let mut cheques = Vec::with_capacity(matched.len());
+        for (info, role, counterparty) in matched {
+            let ((_, transaction), data) = get_live_cell(self.rpc_client, info.out_point(), true)?;
+            let amount = parse_udt_amount(&data)?;
+            cheques.push(ChequeCell {
+                info,
+                role,
+                counterparty,
+                amount,
+                transaction,
+            });
+        }
+        Ok(cheques)
+    }
+
+    fn tip_epoch(&mut self) -> Result<EpochNumberWithFraction, String> {
+        let tip = self.rpc_client.get_tip_header()?;
+        Ok(EpochNumberWithFraction::from_full_value(tip.inner.epoch.0))
+    }
+
+    fn cell_epoch(&mut self, info: &LiveCellInfo) -> Result<EpochNumberWithFraction, String> {
+        let header = self
+            .rpc_client
+            .get_header_by_number(info.number)?
+            .ok_or_else(|| format!("Block not found: {}", info.number))?;
+        Ok(EpochNumberWithFraction::from_full_value(
+            header.inner.epoch.0,
+        ))
+    }
+
+    // The `inputs` are always included, the plain cells of the signer pay for
+    // the rest of outputs capacity and fee. The cheque cells are unlocked by an
+    // input of the receiver (claim) or the sender (withdraw), so at least one
+    // plain cell is included when `signer_input_required`.
+    fn build_cheque_tx(
+        &mut self,
+        m: &ArgMatches,
+        from_account: Either<PrivkeyWrapper, AccountId>,
+        signer_payload: &AddressPayload,
+        mut builder: TransactionBuilder,
+        inputs: Vec<CollectedCell>,
+        signer_input_required: bool,
+    ) -> Result<TransactionView, String> {
+        let cell_dep_out_point: OutPoint = OutPointParser.from_matches(m, "udt-cell-dep")?;
+        let fee_policy = get_fee_policy(m)?;
+        builder
+            .cell_dep(
+                CellDep::new_builder()
+                    .out_point(cell_dep_out_point)
+                    .dep_type(DepType::Code.into())
+                    .build(),
+            )
+            .change_address(signer_payload.clone())
+            .fee_policy(fee_policy);
+
+        let max_mature_number = get_max_mature_number(self.rpc_client)?;
+        let lock_hash = Script::from(signer_payload).calc_script_hash();
+        let plain_cells = self.with_db(|db| {
+            db.get_live_cells_by_lock(lock_hash, None, |_, info| {
+                (
+                    false,
+                    info.type_hashes.is_none()
+                        && info.data_bytes == 0
+                        && is_mature(info, max_mature_number),
+                )
+            })
+        })?;
+        let network_type = get_network_type(self.rpc_client)?;
+        let rendered_signer = Address::new(network_type, signer_payload.clone()).to_string();
+        let inputs_capacity: u64 = inputs.iter().map(|cell| cell.capacity).sum();
+        let mut collector = move |required: u64| {
+            let mut cells = inputs.clone();
+            let mut from_capacity = inputs_capacity;
+            for (idx, info) in plain_cells.iter().enumerate() {
+                if from_capacity >= required && (idx > 0 || !signer_input_required) {
+                    break;
+                }
+                from_capacity += info.capacity;
+                cells.push(CollectedCell::new(info.out_point(), info.capacity));
+            }
+            if required > from_capacity || (signer_input_required && cells.len() == inputs.len()) {
+                return Err(format!(
+                    "Capacity(mature) not enough: {} => {}",
+                    rendered_signer, from_capacity,
+                ));
+            }
+            Ok(cells)
+        };
+
+        let genesis_info = self.genesis_info()?;
+        let rpc_client = &mut *self.rpc_client;
+        let mut live_cell_cache: HashMap<(OutPoint, bool), ((CellOutput, Transaction), Bytes)> =
+            Default::default();
+        let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
+            get_live_cell_with_cache(&mut live_cell_cache, rpc_client, out_point, with_data)
+                .map(|(output, _)| output)
+        };
+        let signer = get_account_signer(self.key_store, from_account)?;
+        self.signing_policy.build_transaction(
+            &builder,
+            &mut collector,
+            &mut get_live_cell_fn,
+            &genesis_info,
+            network_type,
+            signer,
+        )
+    }
+}
+
+fn cheque_config(m: &ArgMatches) -> Result<ChequeConfig, String> {
+    let type_hash: H256 = FixedHashParser::<H256>::default().from_matches(m, "cheque-type-hash")?;
+    let out_point: OutPoint = OutPointParser.from_matches(m, "cheque-dep")?;
+    Ok(ChequeConfig::new(type_hash, out_point))
+}
+
+fn udt_type_script_from_matches(
+    m: &ArgMatches,
+    network_type: NetworkType,
+) -> Result<Script, String> {
+    let owner: Address = AddressParser::default()
+        .set_network(network_type)
+        .from_matches(m, "owner")?;
+    udt_type_script(m, &Script::from(owner.payload()))
+}
+
+// The cheque args only keep the lock hash prefix of the sender, find the full
+// lock script from the transaction sent the cheque (its outputs or inputs are
+// owned by the sender)
+fn resolve_cheque_sender(
+    rpc_client: &mut HttpRpcClient,
+    transaction: &Transaction,
+    sender_prefix: &H160,
+) -> Result<Script, String> {
+    let is_sender =
+        |lock: &Script| &cheque_lock_hash_prefix(&lock.calc_script_hash()) == sender_prefix;
+    for output in &transaction.outputs {
+        let lock = Script::from(output.lock.clone());
+        if is_sender(&lock) {
+            return Ok(lock);
+        }
+    }
+    for input in &transaction.inputs {
+        let prev = &input.previous_output;
+        if let Some(prev_tx) = rpc_client.get_transaction(prev.tx_hash.clone())? {
+            if let Some(output) = prev_tx
+                .transaction
+                .inner
+                .outputs
+                .get(prev.index.value() as usize)
+            {
+                let lock = Script::from(output.lock.clone());
+                if is_sender(&lock) {
+                    return Ok(lock);
+                }
+            }
+        }
+    }
+    Err(format!(
+        "Sender lock script not found, lock hash prefix: {:#x}",
+        sender_prefix
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_withdrawable() {
+        let epoch = |number, index, length| EpochNumberWithFraction::new(number, index, length);
+        assert!(!is_withdrawable(epoch(10, 0, 1000), epoch(15, 999, 1000)));
+        assert!(is_withdrawable(epoch(10, 0, 1000), epoch(16, 0, 1000)));
+        assert!(!is_withdrawable(epoch(10, 500, 1000), epoch(16, 499, 1000)));
+        assert!(is_withdrawable(epoch(10, 500, 1000), epoch(16, 900, 1800)));
+        assert!(is_withdrawable(epoch(10, 500, 1000), epoch(17, 0, 1000)));
+    }
+}
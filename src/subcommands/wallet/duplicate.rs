@@ -0,0 +1,115 @@
+use std::collections::{HashMap, HashSet};
+
+use ckb_index::TxInfo;
+use ckb_sdk::{Address, HttpRpcClient};
+use ckb_types::{packed::Script, prelude::*, H256};
+
+use crate::utils::tx_record::load_memos;
+
+/// A committed transaction paid the same capacity to the same address
+pub struct DuplicatePayment {
+    pub tx_hash: H256,
+    pub block_number: u64,
+    pub address: Address,
+    pub capacity: u64,
+    // The memo in local tracking store
+    pub memo: Option<String>,
+}
+
+/// Find the recipients (address, capacity) already paid by the transactions
+/// of the sender (from the index history). An unreadable local tracking
+/// store only loses the memos.
+pub fn find_duplicate_payments(
+    rpc_client: &mut HttpRpcClient,
+    txs: Vec<TxInfo>,
+    recipients: &[(Address, u64)],
+) -> Result<Vec<DuplicatePayment>, String> {
+    let memos = load_memos();
+    let mut checked = HashSet::new();
+    let mut duplicates = Vec::new();
+    for info in txs {
+        if !checked.insert(info.tx_hash.clone()) {
+            continue;
+        }
+        let tx = rpc_client
+            .get_transaction(info.tx_hash.clone())?
+            .ok_or_else(|| format!("Transaction not found: {:#x}", info.tx_hash))?;
+        let outputs = tx
+            .transaction
+            .inner
+            .outputs
+            .into_iter()
+            .map(|output| (Script::from(output.lock), output.capacity.0))
+            .collect::<Vec<_>>();
+        duplicates.extend(match_payments(
+            &info.tx_hash,
+            info.block_number,
+            &outputs,
+            recipients,
+            &memos,
+        ));
+    }
+    Ok(duplicates)
+}
+
+// The recipients paid by the outputs (lock, capacity) of a transaction
+fn match_payments(
+    tx_hash: &H256,
+    block_number: u64,
+    outputs: &[(Script, u64)],
+    recipients: &[(Address, u64)],
+    memos: &HashMap<H256, String>,
+) -> Vec<DuplicatePayment> {
+    let mut duplicates = Vec::new();
+    for (lock, output_capacity) in outputs {
+        for (address, capacity) in recipients {
+            let recipient_lock = Script::from(address.payload());
+            if lock.as_slice() == recipient_lock.as_slice() && output_capacity == capacity {
+                duplicates.push(DuplicatePayment {
+                    tx_hash: tx_hash.clone(),
+                    block_number,
+                    address: address.clone(),
+                    capacity: *capacity,
+                    memo: memos.get(tx_hash).cloned(),
+                });
+            }
+        }
+    }
+    duplicates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_sdk::{AddressPayload, NetworkType};
+    use ckb_types::H160;
+
+    #[test]
+    fn test_match_payments() {
+        let address = |byte: u8| {
+            let payload = AddressPayload::from_pubkey_hash(H160::from([byte; 20]));
+            Address::new(NetworkType::Testnet, payload)
+        };
+        let lock = |byte: u8| Script::from(address(byte).payload());
+        let tx_hash = H256::from([1u8; 32]);
+        let recipients = vec![(address(1), 100), (address(2), 200)];
+        let mut memos = HashMap::new();
+        memos.insert(tx_hash.clone(), "rent of May".to_string());
+
+        // Same address and capacity, the change output and another capacity
+        // are not duplicates
+        let outputs = vec![(lock(1), 100), (lock(2), 300), (lock(3), 200)];
+        let duplicates = match_payments(&tx_hash, 10, &outputs, &recipients, &memos);
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].address, address(1));
+        assert_eq!(duplicates[0].capacity, 100);
+        assert_eq!(duplicates[0].block_number, 10);
+        assert_eq!(duplicates[0].memo, Some("rent of May".to_string()));
+
+        // Without the local records there is no memo
+        let outputs = vec![(lock(1), 100), (lock(2), 200)];
+        let duplicates = match_payments(&tx_hash, 10, &outputs, &recipients, &HashMap::new());
+        assert_eq!(duplicates.len(), 2);
+        assert!(duplicates.iter().all(|duplicate| duplicate.memo.is_none()));
+    }
+}
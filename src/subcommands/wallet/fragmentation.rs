@@ -0,0 +1,243 @@
+use ckb_sdk::MAX_FEE_ITERATIONS;
+
+// Too many inputs make the consolidation transaction too large, the rest can
+// be consolidated by running it again
+const MAX_CONSOLIDATE_INPUTS: usize = 256;
+
+/// The distribution of spendable (mature, without type script and data) cells
+pub struct CellStats {
+    pub count: usize,
+    pub total: u64,
+    pub smallest: u64,
+    pub median: u64,
+    pub largest: u64,
+    pub dust_count: usize,
+    pub dust_capacity: u64,
+}
+
+impl CellStats {
+    pub fn new(capacities: &[u64], dust_threshold: u64) -> CellStats {
+        let mut sorted = capacities.to_vec();
+        sorted.sort();
+        let dust = sorted
+            .iter()
+            .filter(|capacity| **capacity < dust_threshold)
+            .collect::<Vec<_>>();
+        CellStats {
+            count: sorted.len(),
+            total: sorted.iter().sum(),
+            smallest: sorted.first().cloned().unwrap_or(0),
+            median: sorted.get(sorted.len() / 2).cloned().unwrap_or(0),
+            largest: sorted.last().cloned().unwrap_or(0),
+            dust_count: dust.len(),
+            dust_capacity: dust.into_iter().sum(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Advice {
+    /// Merge the dust cells (indexes of input capacities) into one cell
+    Consolidate {
+        inputs: Vec<usize>,
+    },
+    /// Split all the cells into `outputs` cells for parallel spending
+    Split {
+        inputs: Vec<usize>,
+        outputs: usize,
+    },
+    Healthy,
+}
+
+impl Advice {
+    pub fn action(&self) -> &'static str {
+        match self {
+            Advice::Consolidate { .. } => "consolidate",
+            Advice::Split { .. } => "split",
+            Advice::Healthy => "none",
+        }
+    }
+
+    pub fn inputs(&self) -> &[usize] {
+        match self {
+            Advice::Consolidate { inputs } | Advice::Split { inputs, .. } => inputs,
+            Advice::Healthy => &[],
+        }
+    }
+
+    pub fn outputs(&self) -> usize {
+        match self {
+            Advice::Consolidate { .. } => 1,
+            Advice::Split { outputs, .. } => *outputs,
+            Advice::Healthy => 0,
+        }
+    }
+}
+
+/// Too many dust cells (smaller than `dust_threshold`) make transactions large
+/// and expensive, so consolidate them first. Too few usable cells (not smaller
+/// than `dust_threshold`) block spending in parallel, so split them into
+/// `target_cells` cells when the balance is enough (every new cell is at least
+/// twice of `dust_threshold`, so it is still usable after paying some fee).
+pub fn advise(capacities: &[u64], dust_threshold: u64, target_cells: usize) -> Advice {
+    let stats = CellStats::new(capacities, dust_threshold);
+    if stats.dust_count >= 2 {
+        let inputs = (0..capacities.len())
+            .filter(|idx| capacities[*idx] < dust_threshold)
+            .take(MAX_CONSOLIDATE_INPUTS)
+            .collect();
+        return Advice::Consolidate { inputs };
+    }
+    let usable_count = stats.count - stats.dust_count;
+    let splittable = stats.total / (dust_threshold.max(1) * 2);
+    if usable_count < target_cells && splittable as usize > usable_count {
+        let outputs = target_cells.min(splittable as usize);
+        let inputs = (0..capacities.len()).take(MAX_CONSOLIDATE_INPUTS).collect();
+        return Advice::Split { inputs, outputs };
+    }
+    Advice::Healthy
+}
+
+/// Distribute the cells (indexes of input capacities) into chunks of at most
+/// `max_inputs` inputs. The larger cells are dealt out first, so every chunk
+/// can pay its own fee.
+pub fn sweep_chunks(capacities: &[u64], max_inputs: usize) -> Vec<Vec<usize>> {
+    if capacities.is_empty() {
+        return Vec::new();
+    }
+    let chunks = (capacities.len() + max_inputs - 1) / max_inputs;
+    let mut indexes = (0..capacities.len()).collect::<Vec<_>>();
+    indexes.sort_by(|a, b| capacities[*b].cmp(&capacities[*a]));
+    let mut result = vec![Vec::new(); chunks];
+    for (idx, input) in indexes.into_iter().enumerate() {
+        result[idx % chunks].push(input);
+    }
+    result
+}
+
+/// The number of inputs of the next consolidation transaction (merging the
+/// smallest cells into one) when there are `cells` cells, `None` when done.
+/// Without `until_cells` it is one transaction of at most `max_inputs` inputs,
+/// otherwise the transactions go on until there are less than `until_cells`
+/// cells, the last one takes just enough inputs.
+pub fn next_consolidation(
+    cells: usize,
+    max_inputs: usize,
+    until_cells: Option<usize>,
+    done_txs: usize,
+) -> Option<usize> {
+    let inputs = match until_cells {
+        None if done_txs > 0 => return None,
+        None => cells.min(max_inputs),
+        Some(until_cells) if cells < until_cells => return None,
+        // Merging k cells reduces the count by k - 1
+        Some(until_cells) => (cells + 2 - until_cells).min(max_inputs).min(cells),
+    };
+    if inputs < 2 {
+        None
+    } else {
+        Some(inputs)
+    }
+}
+
+/// The extra capacity to collect for a payment whose inputs are first merged
+/// by consolidation transactions, so the merged cells still pay the payment
+/// after the fees. `fees(reserve)` is the total fee of the consolidation
+/// transactions and the payment when `reserve` more is collected (`None` when
+/// no consolidation is needed), more inputs may cost more fee so it goes on
+/// until the reserve covers the fees.
+pub fn consolidation_fee_reserve<F>(mut fees: F) -> Result<u64, String>
+where
+    F: FnMut(u64) -> Result<Option<u64>, String>,
+{
+    let mut reserve = 0;
+    for _ in 0..MAX_FEE_ITERATIONS {
+        match fees(reserve)? {
+            Some(fee) if fee > reserve => reserve = fee,
+            _ => return Ok(reserve),
+        }
+    }
+    Err("The fee of the consolidation transactions not converged".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_advise() {
+        assert_eq!(advise(&[], 200, 4), Advice::Healthy);
+        assert_eq!(
+            advise(&[100, 1000, 150, 300], 200, 4),
+            Advice::Consolidate { inputs: vec![0, 2] }
+        );
+        assert_eq!(
+            advise(&[2000], 200, 4),
+            Advice::Split {
+                inputs: vec![0],
+                outputs: 4
+            }
+        );
+        assert_eq!(
+            advise(&[1300, 100], 200, 4),
+            Advice::Split {
+                inputs: vec![0, 1],
+                outputs: 3
+            }
+        );
+        assert_eq!(advise(&[300, 300, 300, 300], 200, 4), Advice::Healthy);
+        assert_eq!(advise(&[700], 200, 4), Advice::Healthy);
+    }
+
+    #[test]
+    fn test_sweep_chunks() {
+        assert!(sweep_chunks(&[], 2).is_empty());
+        assert_eq!(sweep_chunks(&[3, 1], 2), vec![vec![0, 1]]);
+        assert_eq!(
+            sweep_chunks(&[1, 5, 3, 4, 2], 2),
+            vec![vec![1, 4], vec![3, 0], vec![2]]
+        );
+    }
+
+    #[test]
+    fn test_next_consolidation() {
+        assert_eq!(next_consolidation(1000, 256, None, 0), Some(256));
+        assert_eq!(next_consolidation(745, 256, None, 1), None);
+        assert_eq!(next_consolidation(10, 256, None, 0), Some(10));
+        assert_eq!(next_consolidation(1, 256, None, 0), None);
+
+        // 1000 => 745 => 490 => 235 => 10 (the last one takes 226 inputs)
+        let mut cells = 1000;
+        let mut txs = 0;
+        while let Some(inputs) = next_consolidation(cells, 256, Some(11), txs) {
+            cells = cells - inputs + 1;
+            txs += 1;
+        }
+        assert_eq!((cells, txs), (10, 4));
+        assert_eq!(next_consolidation(10, 256, Some(11), 0), None);
+        assert_eq!(next_consolidation(11, 256, Some(11), 0), Some(2));
+        assert_eq!(next_consolidation(3, 2, Some(2), 0), Some(2));
+    }
+
+    #[test]
+    fn test_consolidation_fee_reserve() {
+        assert_eq!(consolidation_fee_reserve(|_| Ok(None)), Ok(0));
+        // Every 100 shannons collected more needs one more input of 10 shannons
+        let mut calls = Vec::new();
+        let reserve = consolidation_fee_reserve(|reserve| {
+            calls.push(reserve);
+            Ok(Some(1000 + reserve / 100 * 10))
+        });
+        assert_eq!(reserve, Ok(1110));
+        assert_eq!(calls, vec![0, 1000, 1100, 1110]);
+        // The last call is made with the returned reserve
+        let mut last = None;
+        let reserve = consolidation_fee_reserve(|reserve| {
+            last = Some(reserve);
+            Ok(Some(500))
+        });
+        assert_eq!((reserve, last), (Ok(500), Some(500)));
+        assert!(consolidation_fee_reserve(|reserve| Ok(Some(reserve + 1))).is_err());
+        assert!(consolidation_fee_reserve(|_| Err("not enough".to_string())).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use std::collections::HashMap;
+
+use ckb_index::TxInfo;
+use ckb_sdk::{
+    constants::DAO_TYPE_HASH,
+    rpc::{CellOutput, TransactionPoint},
+    HttpRpcClient,
+};
+use ckb_types::{
+    core::HeaderView,
+    packed::{self, Byte32},
+    prelude::*,
+    H256,
+};
+
+use crate::utils::arg_parser::{ArgParser, FixedHashParser, FromStrParser};
+
+const PAGE_SIZE: u64 = 50;
+
+/// A cell owned by the lock at the target block: created at or before the
+/// target block and not consumed at or before it
+pub struct HistoricalCell {
+    pub created_by: TransactionPoint,
+    pub cell_output: CellOutput,
+}
+
+impl HistoricalCell {
+    pub fn capacity(&self) -> u64 {
+        self.cell_output.capacity.0
+    }
+
+    pub fn is_dao(&self) -> bool {
+        self.cell_output
+            .type_
+            .as_ref()
+            .filter(|script| script.code_hash == DAO_TYPE_HASH)
+            .is_some()
+    }
+}
+
+/// Resolve `--at-block` (block number or block hash) to a main chain block
+pub fn resolve_block(rpc_client: &mut HttpRpcClient, input: &str) -> Result<(u64, H256), String> {
+    let header_opt = if input.starts_with("0x") {
+        let block_hash: H256 = FixedHashParser::<H256>::default().parse(input)?;
+        rpc_client.get_header(block_hash)?
+    } else {
+        let number: u64 = FromStrParser::<u64>::new().parse(input)?;
+        rpc_client.get_header_by_number(number)?
+    };
+    let header: HeaderView = header_opt
+        .ok_or_else(|| format!("Block not found: {}", input))?
+        .into();
+    let block_hash: H256 = header.hash().unpack();
+    // An uncle or forked block is not a valid point of history
+    if rpc_client.get_block_hash(header.number())? != Some(block_hash.clone()) {
+        return Err(format!("Block {:#x} is not in main chain", block_hash));
+    }
+    Ok((header.number(), block_hash))
+}
+
+/// Replay the cell history of `lock_hash` (from the indexer RPC of the node) to
+/// find the cells owned by it at block `number`.
+///
+/// NOTE: The history before the lock hash is indexed by the node (see
+/// `rpc index_lock_hash --index-from`) is not available.
+pub fn cells_at_block(
+    rpc_client: &mut HttpRpcClient,
+    lock_hash: &H256,
+    number: u64,
+) -> Result<Vec<HistoricalCell>, String> {
+    let mut points = Vec::new();
+    let mut page = 0;
+    loop {
+        let txs = rpc_client
+            .get_transactions_by_lock_hash(lock_hash.clone(), page, PAGE_SIZE, None)
+            .map_err(|err| format!("Indexer RPC error (is Indexer module enabled?): {}", err))?;
+        let is_last_page = (txs.len() as u64) < PAGE_SIZE;
+        points.extend(
+            txs.into_iter()
+                .filter(|tx| {
+                    tx.created_by.block_number <= number
+                        && tx
+                            .consumed_by
+                            .as_ref()
+                            .map(|point| point.block_number > number)
+                            .unwrap_or(true)
+                })
+                .map(|tx| tx.created_by),
+        );
+        if is_last_page {
+            break;
+        }
+        page += 1;
+    }
+
+    let mut outputs_cache: HashMap<H256, Vec<CellOutput>> = HashMap::default();
+    let mut cells = Vec::with_capacity(points.len());
+    for created_by in points {
+        if !outputs_cache.contains_key(&created_by.tx_hash) {
+            let tx = rpc_client
+                .get_transaction(created_by.tx_hash.clone())?
+                .ok_or_else(|| format!("Transaction not found: {:#x}", created_by.tx_hash))?;
+            outputs_cache.insert(created_by.tx_hash.clone(), tx.transaction.inner.outputs);
+        }
+        let cell_output = outputs_cache[&created_by.tx_hash]
+            .get(created_by.index as usize)
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "Output not found: {:#x}-{}",
+                    created_by.tx_hash, created_by.index
+                )
+            })?;
+        cells.push(HistoricalCell {
+            created_by,
+            cell_output,
+        });
+    }
+    Ok(cells)
+}
+
+/// A transaction touching the lock, `received` and `spent` are the capacity
+/// of its outputs and inputs owned by the lock.
+pub struct TxRecord {
+    pub tx_hash: H256,
+    pub block_number: u64,
+    pub timestamp: u64,
+    pub received: u64,
+    pub spent: u64,
+}
+
+impl TxRecord {
+    pub fn direction(&self) -> &'static str {
+        if self.received >= self.spent {
+            "in"
+        } else {
+            "out"
+        }
+    }
+
+    pub fn capacity_delta(&self) -> i128 {
+        i128::from(self.received) - i128::from(self.spent)
+    }
+}
+
+/// Resolve the capacity changes of `lock_hash` in the transactions recorded by
+/// local index (the index only stores the out points of inputs and outputs).
+pub fn tx_records(
+    rpc_client: &mut HttpRpcClient,
+    lock_hash: &H256,
+    txs: Vec<TxInfo>,
+) -> Result<Vec<TxRecord>, String> {
+    let lock_hash: Byte32 = lock_hash.pack();
+    let mut outputs_cache: HashMap<H256, Vec<CellOutput>> = HashMap::default();
+    let mut get_outputs = |rpc_client: &mut HttpRpcClient, tx_hash: H256| {
+        if !outputs_cache.contains_key(&tx_hash) {
+            let tx = rpc_client
+                .get_transaction(tx_hash.clone())?
+                .ok_or_else(|| format!("Transaction not found: {:#x}", tx_hash))?;
+            outputs_cache.insert(tx_hash.clone(), tx.transaction.inner.outputs);
+        }
+        Ok::<_, String>(outputs_cache[&tx_hash].clone())
+    };
+    let capacity_of = |outputs: &[CellOutput], index: usize| {
+        outputs
+            .get(index)
+            .filter(|output| {
+                packed::Script::from(output.lock.clone()).calc_script_hash() == lock_hash
+            })
+            .map(|output| output.capacity.0)
+            .unwrap_or(0)
+    };
+
+    let mut records = Vec::with_capacity(txs.len());
+    for info in txs {
+        let mut spent = 0;
+        for out_point in info.inputs() {
+            let outputs = get_outputs(&mut *rpc_client, out_point.tx_hash().unpack())?;
+            let index: u32 = out_point.index().unpack();
+            spent += capacity_of(&outputs, index as usize);
+        }
+        let outputs = get_outputs(&mut *rpc_client, info.tx_hash.clone())?;
+        let received = (0..outputs.len())
+            .map(|index| capacity_of(&outputs, index))
+            .sum();
+        records.push(TxRecord {
+            tx_hash: info.tx_hash,
+            block_number: info.block_number,
+            timestamp: info.block_timestamp,
+            received,
+            spent,
+        });
+    }
+    Ok(records)
+}
@@ -1,3 +1,4 @@
+use std::collections::VecDeque;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -5,8 +6,10 @@ use std::thread;
 use std::time::{Duration, Instant};
 
 use ckb_index::{with_index_db, IndexDatabase};
-use ckb_sdk::GenesisInfo;
-use ckb_sdk::HttpRpcClient;
+use ckb_sdk::{
+    rpc::{BlockView as RpcBlockView, RpcFuture},
+    GenesisInfo, HttpRpcClient,
+};
 use ckb_types::{
     core::{service::Request, BlockView},
     prelude::*,
@@ -14,14 +17,19 @@ use ckb_types::{
 };
 use ckb_util::RwLock;
 use crossbeam_channel::Receiver;
+use futures::Future;
 
-use crate::utils::index::{IndexController, IndexRequest, IndexResponse, IndexThreadState};
+use crate::utils::index::{
+    chain_mismatch_error, IndexController, IndexRequest, IndexResponse, IndexThreadState,
+};
 use crate::utils::other::get_network_type;
 
 pub fn start_index_thread(
     url: &str,
     index_dir: PathBuf,
     state: Arc<RwLock<IndexThreadState>>,
+    fetch_concurrency: usize,
+    record_txs: bool,
 ) -> IndexController {
     let (sender, receiver) = crossbeam_channel::bounded::<Request<IndexRequest, IndexResponse>>(1);
     let shutdown = Arc::new(AtomicBool::new(false));
@@ -52,6 +60,8 @@ pub fn start_index_thread(
                     &index_dir,
                     &state,
                     &shutdown_clone,
+                    fetch_concurrency,
+                    record_txs,
                 ) {
                     Ok(true) => {
                         state.write().stop();
@@ -72,7 +82,7 @@ pub fn start_index_thread(
         })
         .expect("Spawn index thread failed");
 
-    IndexController::new(state_clone, sender, shutdown)
+    IndexController::new(state_clone, sender, shutdown, record_txs)
 }
 
 fn process(
@@ -81,14 +91,18 @@ fn process(
     index_dir: &PathBuf,
     state: &Arc<RwLock<IndexThreadState>>,
     shutdown: &Arc<AtomicBool>,
+    fetch_concurrency: usize,
+    record_txs: bool,
 ) -> Result<bool, String> {
     if let Some(exit) = try_recv(&receiver, rpc_client) {
         return Ok(exit);
     }
 
     state.write().start_init();
-    let genesis_block: BlockView = rpc_client
-        .get_block_by_number(0)?
+    let async_client = rpc_client.async_client().clone();
+    let genesis_block: BlockView = async_client
+        .get_block_by_number(0)
+        .wait()?
         .expect("Can not get genesis block?")
         .into();
     let network_type = get_network_type(rpc_client)?;
@@ -101,20 +115,28 @@ fn process(
     loop {
         if next_get_tip <= Instant::now() {
             next_get_tip = Instant::now() + Duration::from_secs(1);
-            tip_header = rpc_client.get_tip_header()?.into();
+            tip_header = async_client.get_tip_header().wait()?.into();
             log::debug!("Update to tip {}", tip_header.number());
         }
 
         if tip_header.number() >= next_number {
             let exit_opt = with_index_db(index_dir, genesis_hash.clone(), |backend, cf| {
-                let mut db =
-                    IndexDatabase::from_db(backend, cf, network_type, genesis_info.clone(), false)
-                        .unwrap();
+                let mut db = IndexDatabase::from_db(
+                    backend,
+                    cf,
+                    network_type,
+                    genesis_info.clone(),
+                    record_txs,
+                )?;
                 if db.last_number().is_none() {
                     db.apply_next_block(genesis_block.clone())
                         .expect("Apply genesis block failed");
                 }
                 db.update_tip(tip_header.clone());
+                // Up to `fetch_concurrency` block requests are in flight while
+                // the blocks are applied in order, so the round trips overlap
+                // each other and the db writes
+                let mut pending: VecDeque<(u64, RpcFuture<Option<RpcBlockView>>)> = VecDeque::new();
                 while tip_header.number() > db.last_number().unwrap() {
                     if shutdown.load(Ordering::Relaxed) {
                         return Ok(Some(true));
@@ -122,9 +144,30 @@ fn process(
                     if let Some(exit) = try_recv(&receiver, rpc_client) {
                         return Ok(Some(exit));
                     }
-                    if let Some(next_block) =
-                        rpc_client.get_block_by_number(db.next_number().unwrap())?
+                    // Url updated, restart from the genesis block of the new node
+                    if rpc_client.url() != async_client.url() {
+                        return Ok(Some(false));
+                    }
+                    let number = db.next_number().unwrap();
+                    if pending.front().map(|(pending_number, _)| *pending_number) != Some(number) {
+                        pending.clear();
+                    }
+                    let mut request_number = pending
+                        .back()
+                        .map(|(pending_number, _)| pending_number + 1)
+                        .unwrap_or(number);
+                    while pending.len() < fetch_concurrency && request_number <= tip_header.number()
                     {
+                        pending.push_back((
+                            request_number,
+                            async_client.get_block_by_number(request_number),
+                        ));
+                        request_number += 1;
+                    }
+                    let (_, block_future) = pending
+                        .pop_front()
+                        .expect("The next block is always requested");
+                    if let Some(next_block) = block_future.wait()? {
                         db.apply_next_block(next_block.into())
                             .expect("Add block failed");
                         state
@@ -141,7 +184,7 @@ fn process(
                     .processing(db.last_header().cloned(), tip_header.number());
                 Ok(None)
             })
-            .map_err(|err| err.to_string())?;
+            .map_err(|err| chain_mismatch_error(&err).unwrap_or_else(|| err.to_string()))?;
             if let Some(exit) = exit_opt {
                 return Ok(exit);
             }
@@ -153,6 +196,9 @@ fn process(
         if let Some(exit) = try_recv(&receiver, rpc_client) {
             return Ok(exit);
         }
+        if rpc_client.url() != async_client.url() {
+            return Ok(false);
+        }
         thread::sleep(Duration::from_millis(100));
     }
 }
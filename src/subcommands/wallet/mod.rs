@@ -1,46 +1,94 @@
+mod balances;
+mod cellbase;
+mod cheque;
+mod duplicate;
+mod fragmentation;
+mod history;
 mod index;
+mod payout;
+mod read_through;
+mod recipients;
+mod scan;
 
 use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
 
+use cellbase::{group_by_day, mature_block_number};
+use chrono::{TimeZone, Utc};
+use duplicate::find_duplicate_payments;
 use either::Either;
+use fragmentation::{
+    advise, consolidation_fee_reserve, next_consolidation, sweep_chunks, Advice, CellStats,
+};
+use history::{cells_at_block, resolve_block, tx_records};
 pub use index::start_index_thread;
+use read_through::read_through;
+use recipients::{parse_recipient, read_recipients_file, split_recipient};
+use scan::scan_chain;
 
 use super::account::AccountId;
+use super::tx::write_tx_file;
 use ckb_types::{
     bytes::Bytes,
-    core::{BlockView, Capacity, ScriptHashType, TransactionView},
-    packed::{Byte32, CellOutput, OutPoint, Script},
+    core::{BlockView, EpochNumberWithFraction, TransactionView},
+    packed::{CellOutput, OutPoint, Script},
     prelude::*,
     H160, H256,
 };
-use clap::{App, ArgMatches, SubCommand};
+use clap::{App, Arg, ArgMatches, SubCommand};
 
 use super::CliSubCommand;
 use crate::utils::{
+    alias::resolve_address,
     arg,
-    arg_parser::{AddressParser, ArgParser, CapacityParser, FixedHashParser, FromStrParser},
-    index::IndexController,
+    arg_parser::{
+        AddressParser, ArgParser, CapacityParser, FilePathParser, FixedHashParser,
+        FromAccountParser, FromStrParser,
+    },
+    broadcast::SendTargets,
+    coin_control::CoinControl,
+    index::{with_chain_index_db, IndexController},
+    index_backend::{
+        index_backend_from_matches, light_client_url, CellQuery, IndexBackend, LightClientBackend,
+    },
+    multisig::MultisigConfigStore,
+    on_failure::{self, OnFailure},
     other::{
-        check_capacity, get_address, get_live_cell_with_cache, get_master_key_signer_raw,
-        get_max_mature_number, get_network_type, get_privkey_signer, get_to_data, is_mature,
+        check_capacity, confirm, get_account_signer, get_address, get_fee_policy,
+        get_input_cell_with_cache, get_master_key_signer_raw, get_max_mature_number,
+        get_network_type, get_privkey_signer, get_to_data, is_mature,
         make_address_payload_and_master_key_cap, privkey_or_from_account, read_password,
-        serialize_signature_bytes,
+        remote_signer_from_matches, serialize_signature_bytes,
     },
-    printer::{OutputFormat, Printable},
+    policy::SigningPolicy,
+    printer::{render_list, OutputFormat, Printable},
+    tx_record::{load_memos, save_tx_memo},
+    tx_status::WaitForCommitted,
 };
-use ckb_index::{with_index_db, IndexDatabase, LiveCellInfo};
+use ckb_index::{IndexDatabase, LiveCellInfo, LiveCellQuery};
 use ckb_ledger::LedgerKeyStore;
 use ckb_sdk::{
+    calculate_fee,
     constants::{
-        DAO_TYPE_HASH, MIN_SECP_CELL_CAPACITY, MULTISIG_TYPE_HASH, ONE_CKB, SIGHASH_TYPE_HASH,
+        CELLBASE_MATURITY, DAO_TYPE_HASH, MIN_SECP_CELL_CAPACITY, MULTISIG_TYPE_HASH, ONE_CKB,
+        SIGHASH_TYPE_HASH,
     },
+    estimate_tx_size,
     rpc::Transaction,
-    wallet::{AbstractMasterPrivKey, AbstractPrivKey, DerivationPath, KeyStore},
-    Address, AddressPayload, GenesisInfo, HttpRpcClient, HumanCapacity, MultisigConfig,
-    NetworkType, SignerClosureHelper, SignerFnTrait, Since, SinceType, TxHelper,
+    wallet::{
+        AbstractMasterPrivKey, AbstractPrivKey, ChildNumber, DerivationPath, ExtendedPubKey,
+        KeyChain, KeyStore,
+    },
+    Address, AddressHashType, AddressPayload, CollectedCell, FeePolicy, GenesisInfo, HttpRpcClient,
+    HumanCapacity, MultisigConfig, NetworkType, SignerClosureHelper, SignerFnTrait, Since,
+    SinceType, TransactionBuilder, SECP256K1,
 };
 
+// The number of recent blocks to calculate the average block time
+const CELLBASE_REPORT_SAMPLE_BLOCKS: u64 = 1000;
+
 pub struct WalletSubCommand<'a> {
     rpc_client: &'a mut HttpRpcClient,
     key_store: &'a mut KeyStore,
@@ -48,6 +96,14 @@ pub struct WalletSubCommand<'a> {
     genesis_info: Option<GenesisInfo>,
     index_dir: PathBuf,
     index_controller: IndexController,
+    signing_policy: SigningPolicy,
+    send_targets: SendTargets,
+    wait_for_committed: Option<WaitForCommitted>,
+    on_failure: Option<OnFailure>,
+    coin_control: CoinControl,
+    light_client: Option<LightClientBackend>,
+    // When set the built transactions are kept here instead of being sent
+    staged_txs: Option<Vec<TransactionView>>,
 }
 
 impl<'a> WalletSubCommand<'a> {
@@ -58,6 +114,8 @@ impl<'a> WalletSubCommand<'a> {
         genesis_info: Option<GenesisInfo>,
         index_dir: PathBuf,
         index_controller: IndexController,
+        signing_policy: SigningPolicy,
+        send_targets: SendTargets,
     ) -> WalletSubCommand<'a> {
         WalletSubCommand {
             rpc_client,
@@ -66,6 +124,13 @@ impl<'a> WalletSubCommand<'a> {
             genesis_info,
             index_dir,
             index_controller,
+            signing_policy,
+            send_targets,
+            wait_for_committed: None,
+            on_failure: None,
+            coin_control: CoinControl::default(),
+            light_client: None,
+            staged_txs: None,
         }
     }
 
@@ -87,17 +152,25 @@ impl<'a> WalletSubCommand<'a> {
     {
         let network_type = get_network_type(self.rpc_client)?;
         let genesis_info = self.genesis_info()?;
-        let genesis_hash: H256 = genesis_info.header().hash().unpack();
-        with_index_db(&self.index_dir, genesis_hash, |backend, cf| {
-            let db = IndexDatabase::from_db(backend, cf, network_type, genesis_info, false)?;
-            Ok(func(db))
-        })
-        .map_err(|_err| {
-            format!(
-                "Index database may not ready, sync process: {}",
-                self.index_controller.state().read().to_string()
-            )
-        })
+        with_chain_index_db(
+            &self.index_dir,
+            &self.index_controller,
+            network_type,
+            genesis_info,
+            func,
+        )
+    }
+
+    fn index_backend(&mut self, m: &ArgMatches) -> Result<Box<dyn IndexBackend>, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        let genesis_info = self.genesis_info()?;
+        index_backend_from_matches(
+            m,
+            self.index_dir.clone(),
+            genesis_info,
+            network_type,
+            Arc::clone(self.index_controller.state()),
+        )
     }
 
     pub fn subcommand() -> App<'static, 'static> {
@@ -106,21 +179,116 @@ impl<'a> WalletSubCommand<'a> {
             .subcommands(vec![
                 SubCommand::with_name("transfer")
                     .about("Transfer capacity to an address (can have data)")
-                    .arg(arg::privkey_path().required_unless(arg::from_account().b.name))
+                    .arg(arg::privkey_path().required_unless_one(&["from-account", "signer-url"]))
                     .arg(
                         arg::from_account()
-                            .required_unless(arg::privkey_path().b.name)
+                            .required_unless_one(&["privkey-path", "signer-url"])
                             .conflicts_with(arg::privkey_path().b.name),
                     )
+                    .arg(arg::signer_url().conflicts_with_all(&[
+                        "privkey-path",
+                        "from-account",
+                        "derive-change-address",
+                    ]))
                     .arg(arg::from_locked_address())
+                    .arg(arg::to_address().required_unless_one(&["to", "to-file"]))
+                    .arg(arg::to_data().requires(arg::to_address().b.name))
+                    .arg(arg::to_data_path().requires(arg::to_address().b.name))
+                    .arg(
+                        arg::capacity()
+                            .required_unless_one(&["to", "to-file"])
+                            .requires(arg::to_address().b.name),
+                    )
+                    .arg(
+                        Arg::with_name("to")
+                            .long("to")
+                            .takes_value(true)
+                            .multiple(true)
+                            .number_of_values(1)
+                            .conflicts_with(arg::to_address().b.name)
+                            .validator(|input| {
+                                let (_, capacity) = split_recipient(&input)?;
+                                CapacityParser.validate(capacity.to_string())
+                            })
+                            .help("Recipient as <address>:<capacity> (unit: CKB), can be given multiple times to pay several outputs in one transaction"),
+                    )
+                    .arg(
+                        Arg::with_name("to-file")
+                            .long("to-file")
+                            .takes_value(true)
+                            .conflicts_with(arg::to_address().b.name)
+                            .validator(|input| FilePathParser::new(true).validate(input))
+                            .help("CSV file of recipients, one `address,capacity` (unit: CKB) per line"),
+                    )
+                    .arg(arg::tx_fee().required_unless(arg::fee_rate().b.name))
+                    .arg(arg::fee_rate().conflicts_with(arg::tx_fee().b.name))
+                    .arg(arg::allow_high_fee())
+                    .arg(arg::on_failure())
+                    .args(&arg::wait_for_committed_args())
+                    .arg(arg::change_cells())
+                    .arg(arg::max_inputs().help(
+                        "The max number of inputs of each transaction, more inputs are first merged by consolidation transactions",
+                    ))
+                    .arg(arg::use_out_point())
+                    .arg(arg::exclude_out_point())
+                    .arg(arg::tip())
+                    .arg(arg::tip_address())
+                    .arg(arg::derive_receiving_address_length())
+                    .arg(arg::derive_change_address_length())
+                    .arg(arg::derive_change_address().conflicts_with(arg::privkey_path().b.name))
+                    .arg(arg::memo())
+                    .arg(
+                        Arg::with_name("duplicate-window")
+                            .long("duplicate-window")
+                            .takes_value(true)
+                            .default_value("1000")
+                            .validator(|input| FromStrParser::<u64>::default().validate(input))
+                            .help("Warn and ask for confirmation when the same capacity was paid to the same address within this many recent blocks (by local index history, needs --index-tx-history, 0 to disable)"),
+                    )
+                    .arg(
+                        Arg::with_name("allow-duplicate")
+                            .long("allow-duplicate")
+                            .help("Skip the duplicate payment detection"),
+                    )
+                    .arg(arg::index_backend())
+                    .arg(arg::indexer_url())
+                    .arg(arg::light_client_url()),
+                SubCommand::with_name("transfer-multisig")
+                    .about("Transfer capacity from a multisig address (created by `account multisig-create`)")
+                    .arg(
+                        Arg::with_name("from-multisig-address")
+                            .long("from-multisig-address")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| AddressParser::default().validate(input))
+                            .help("The multisig address (short or long with since), the change also goes back to it"),
+                    )
+                    .arg(
+                        arg::from_account()
+                            .multiple(true)
+                            .number_of_values(1)
+                            .help("Sign by this keystore account (a member of the multisig), can be given multiple times"),
+                    )
                     .arg(arg::to_address().required(true))
                     .arg(arg::to_data())
                     .arg(arg::to_data_path())
                     .arg(arg::capacity().required(true))
-                    .arg(arg::tx_fee().required(true))
-                    .arg(arg::derive_receiving_address_length())
-                    .arg(arg::derive_change_address_length())
-                    .arg(arg::derive_change_address().conflicts_with(arg::privkey_path().b.name)),
+                    .arg(arg::tx_fee().required_unless(arg::fee_rate().b.name))
+                    .arg(arg::fee_rate().conflicts_with(arg::tx_fee().b.name))
+                    .arg(arg::allow_high_fee())
+                    .arg(arg::on_failure())
+                    .arg(arg::change_cells())
+                    .arg(
+                        Arg::with_name("tx-file")
+                            .long("tx-file")
+                            .takes_value(true)
+                            .validator(|input| FilePathParser::new(false).validate(input))
+                            .help("Save the (partially) signed transaction to this file instead of sending it, more signatures can be added by `tx sign-inputs` and `tx add-signature`, then send it by `tx send`"),
+                    )
+                    .arg(arg::memo())
+                    .arg(arg::index_backend())
+                    .arg(arg::indexer_url())
+                    .arg(arg::light_client_url()),
                 SubCommand::with_name("get-capacity")
                     .about("Get capacity by lock script hash or address or lock arg or pubkey")
                     .arg(arg::lock_hash())
@@ -129,7 +297,13 @@ impl<'a> WalletSubCommand<'a> {
                     .arg(arg::lock_arg())
                     .arg(arg::derive_receiving_address_length())
                     .arg(arg::derive_change_address_length())
-                    .arg(arg::derived().conflicts_with(arg::lock_hash().b.name)),
+                    .arg(arg::derived().conflicts_with(arg::lock_hash().b.name))
+                    .arg(arg::read_through())
+                    .arg(arg::at_block())
+                    .args(&balances::args())
+                    .arg(arg::index_backend())
+                    .arg(arg::indexer_url())
+                    .arg(arg::light_client_url()),
                 SubCommand::with_name("get-live-cells")
                     .about("Get live cells by lock/type/code hash")
                     .arg(arg::lock_hash())
@@ -138,13 +312,165 @@ impl<'a> WalletSubCommand<'a> {
                     .arg(arg::address())
                     .arg(arg::live_cells_limit())
                     .arg(arg::from_block_number())
-                    .arg(arg::to_block_number()),
+                    .arg(arg::to_block_number())
+                    .arg(arg::read_through())
+                    .arg(arg::at_block())
+                    .arg(arg::index_backend())
+                    .arg(arg::indexer_url())
+                    .arg(arg::light_client_url()),
+                SubCommand::with_name("analyze-fragmentation")
+                    .about("Analyze the live cells distribution of an address and recommend (optionally execute) a consolidation or splitting transaction")
+                    .arg(arg::address())
+                    .arg(arg::privkey_path().conflicts_with(arg::address().b.name))
+                    .arg(
+                        arg::from_account()
+                            .conflicts_with(arg::privkey_path().b.name)
+                            .conflicts_with(arg::address().b.name),
+                    )
+                    .arg(
+                        Arg::with_name("dust-threshold")
+                            .long("dust-threshold")
+                            .takes_value(true)
+                            .default_value("200")
+                            .validator(|input| CapacityParser.validate(input))
+                            .help("Cells smaller than this capacity are dust (unit: CKB)"),
+                    )
+                    .arg(
+                        Arg::with_name("target-cells")
+                            .long("target-cells")
+                            .takes_value(true)
+                            .default_value("4")
+                            .validator(|input| {
+                                let cells = FromStrParser::<usize>::default().parse(&input)?;
+                                if cells == 0 || cells > arg::MAX_CHANGE_CELLS {
+                                    return Err(format!(
+                                        "target-cells must be in range [1, {}]",
+                                        arg::MAX_CHANGE_CELLS
+                                    ));
+                                }
+                                Ok(())
+                            })
+                            .help("The expected number of usable cells for spending in parallel"),
+                    )
+                    .arg(arg::fee_rate().default_value("1000"))
+                    .arg(arg::allow_high_fee())
+                    .arg(arg::on_failure())
+                    .arg(
+                        Arg::with_name("execute")
+                            .long("execute")
+                            .help("Sign and send the recommended transaction (requires --privkey-path or --from-account)"),
+                    )
+                    .arg(arg::index_backend())
+                    .arg(arg::indexer_url())
+                    .arg(arg::light_client_url()),
+                SubCommand::with_name("scan")
+                    .about("Discover the used receiving and change addresses derived from an account (BIP-44) until --gap-limit consecutive unused ones, report their balances and the next unused index of each chain")
+                    .arg(
+                        Arg::with_name("account")
+                            .long("account")
+                            .takes_value(true)
+                            .required(true)
+                            .validator(|input| FixedHashParser::<H160>::default().validate(input))
+                            .help("The lock argument of the keystore account (the password is required)"),
+                    )
+                    .arg(
+                        Arg::with_name("gap-limit")
+                            .long("gap-limit")
+                            .takes_value(true)
+                            .default_value("20")
+                            .validator(|input| FromStrParser::<u32>::default().validate(input))
+                            .help("Stop scanning a chain after this many consecutive unused addresses"),
+                    ),
+                SubCommand::with_name("cellbase-report")
+                    .about("List immature cellbase (mining reward) cells with the projected mature block and date, and the capacity becomes spendable per day")
+                    .arg(arg::address())
+                    .arg(arg::pubkey())
+                    .arg(arg::lock_arg())
+                    .arg(arg::index_backend())
+                    .arg(arg::indexer_url())
+                    .arg(arg::light_client_url()),
+                SubCommand::with_name("sweep")
+                    .about("Send all the mature live cells (without type script and data) of an account to an address, split into several transactions when there are too many cells")
+                    .arg(arg::privkey_path().required_unless(arg::from_account().b.name))
+                    .arg(
+                        arg::from_account()
+                            .required_unless(arg::privkey_path().b.name)
+                            .conflicts_with(arg::privkey_path().b.name),
+                    )
+                    .arg(arg::to_address().required(true))
+                    .arg(arg::max_inputs())
+                    .arg(arg::fee_rate().default_value("1000"))
+                    .arg(arg::allow_high_fee())
+                    .arg(arg::on_failure())
+                    .arg(arg::index_backend())
+                    .arg(arg::indexer_url())
+                    .arg(arg::light_client_url()),
+                SubCommand::with_name("consolidate")
+                    .about("Merge the smallest mature live cells (without type script and data) of an address into one cell, optionally repeated until there are less than --until-cells cells")
+                    .arg(arg::address())
+                    .arg(arg::privkey_path().required_unless(arg::from_account().b.name))
+                    .arg(
+                        arg::from_account()
+                            .required_unless(arg::privkey_path().b.name)
+                            .conflicts_with(arg::privkey_path().b.name),
+                    )
+                    .arg(arg::max_inputs())
+                    .arg(
+                        Arg::with_name("until-cells")
+                            .long("until-cells")
+                            .takes_value(true)
+                            .validator(|input| {
+                                let cells = FromStrParser::<usize>::default().parse(&input)?;
+                                if cells < 2 {
+                                    return Err("until-cells must be at least 2".to_string());
+                                }
+                                Ok(())
+                            })
+                            .help("Keep consolidating (the later transactions spend the merged cells of the earlier ones) until the cell count drops below this threshold"),
+                    )
+                    .arg(arg::fee_rate().default_value("1000"))
+                    .arg(arg::allow_high_fee())
+                    .arg(arg::on_failure())
+                    .arg(arg::index_backend())
+                    .arg(arg::indexer_url())
+                    .arg(arg::light_client_url()),
+                SubCommand::with_name("payout")
+                    .about("Pay the recipients of a manifest in batches")
+                    .subcommands(vec![SubCommand::with_name("run")
+                        .about("Run the payout pipeline of a manifest (TOML): build and sign each batch, send it, wait for the confirmations, finally reconcile the committed transactions with the recipients. The progress is saved to the state file, run it again to resume")
+                        .arg(
+                            Arg::with_name("manifest")
+                                .long("manifest")
+                                .takes_value(true)
+                                .required(true)
+                                .validator(|input| FilePathParser::new(true).validate(input))
+                                .help("The payout manifest file"),
+                        )]),
+                SubCommand::with_name("tx-history")
+                    .about("List transactions touching an address (newest first) from local index, needs --index-tx-history, the blocks indexed without it are not recorded (run `index rebuild` to include them)")
+                    .arg(arg::address().required_unless("schema"))
+                    .arg(
+                        Arg::with_name("limit")
+                            .long("limit")
+                            .takes_value(true)
+                            .validator(|input| FromStrParser::<usize>::default().validate(input))
+                            .default_value("20")
+                            .help("Get transactions <= limit"),
+                    )
+                    .arg(
+                        Arg::with_name("before")
+                            .long("before")
+                            .takes_value(true)
+                            .validator(|input| FromStrParser::<u64>::default().validate(input))
+                            .help("Only the transactions in blocks before this block number (the next page is before the block number of the last transaction, the transactions of a block are never split between pages)"),
+                    ),
                 // Move to index subcommand
                 SubCommand::with_name("db-metrics").about("Show index database metrics"),
                 SubCommand::with_name("top-capacity")
                     .about("Show top n capacity owned by lock script hash")
                     .arg(arg::top_n()),
             ])
+            .subcommands(cheque::subcommands())
     }
     pub fn transfer(
         &mut self,
@@ -153,14 +479,27 @@ impl<'a> WalletSubCommand<'a> {
         color: bool,
         debug: bool,
     ) -> Result<String, String> {
-        let from_account = privkey_or_from_account(m)?;
+        let remote_signer_opt = remote_signer_from_matches(m)?;
+        let from_account_opt = if remote_signer_opt.is_none() {
+            Some(privkey_or_from_account(m)?)
+        } else {
+            None
+        };
 
-        let (from_address_payload_opt, master_key_cap_opt) =
-            make_address_payload_and_master_key_cap(
-                &from_account,
+        let (from_address_payload_opt, master_key_cap_opt) = match from_account_opt {
+            Some(ref from_account) => make_address_payload_and_master_key_cap(
+                from_account,
                 self.key_store,
                 self.ledger_key_store,
-            )?;
+            )?,
+            None => {
+                let remote_signer = remote_signer_opt.as_ref().expect("checked above");
+                (
+                    Some(AddressPayload::from_pubkey(&remote_signer.public_key()?)),
+                    None,
+                )
+            }
+        };
         let from_address_info_opt: Option<(AddressPayload, H160)> =
             from_address_payload_opt.map(|payload| {
                 let hash160 = H160::from_slice(payload.args().as_ref()).unwrap();
@@ -169,12 +508,38 @@ impl<'a> WalletSubCommand<'a> {
 
         let network_type = get_network_type(self.rpc_client)?;
 
-        let to_address: Address = AddressParser::default()
+        let recipients = if let Some(to_address) = m.value_of("to-address") {
+            let to_address: Address = resolve_address(to_address, network_type)?;
+            let to_capacity: u64 = CapacityParser.from_matches(m, "capacity")?;
+            vec![(to_address, to_capacity, get_to_data(m)?)]
+        } else {
+            let mut recipients = Vec::new();
+            for input in m.values_of("to").into_iter().flatten() {
+                let (address, capacity) = parse_recipient(input, network_type)?;
+                recipients.push((address, capacity, Bytes::default()));
+            }
+            let to_file_opt: Option<PathBuf> =
+                FilePathParser::new(true).from_matches_opt(m, "to-file", false)?;
+            if let Some(to_file) = to_file_opt {
+                for (address, capacity) in read_recipients_file(&to_file, network_type)? {
+                    recipients.push((address, capacity, Bytes::default()));
+                }
+            }
+            if recipients.is_empty() {
+                return Err("No recipient given".to_string());
+            }
+            recipients
+        };
+        let fee_policy = get_fee_policy(m)?;
+        let change_cells: usize =
+            FromStrParser::<usize>::default().from_matches(m, "change-cells")?;
+        let max_inputs: usize = FromStrParser::<usize>::default().from_matches(m, "max-inputs")?;
+        let tip: u64 = CapacityParser
+            .from_matches_opt(m, "tip", false)?
+            .unwrap_or(0);
+        let tip_address_opt: Option<Address> = AddressParser::default()
             .set_network(network_type)
-            .from_matches(m, "to-address")?;
-
-        let to_capacity: u64 = CapacityParser.from_matches(m, "capacity")?;
-        let tx_fee: u64 = CapacityParser.from_matches(m, "tx-fee")?;
+            .from_matches_opt(m, "tip-address", false)?;
 
         let from_locked_address_opt: Option<Address> = AddressParser::default()
             .set_network(network_type)
@@ -201,13 +566,13 @@ impl<'a> WalletSubCommand<'a> {
             }
         }
 
-        // The lock hashes for search live cells
-        let mut lock_hashes = Vec::new();
+        // The lock scripts for search live cells
+        let mut lock_scripts = Vec::new();
         let mut path_map: HashMap<H160, DerivationPath> = Default::default();
 
         if let Some((ref underived_payload, ref underived_hash)) = from_address_info_opt {
             // Remember underived keypair's script hash
-            lock_hashes.push(Script::from(underived_payload).calc_script_hash());
+            lock_scripts.push(Script::from(underived_payload));
             // Remember underived pub key hash
             path_map.insert(underived_hash.clone(), DerivationPath::empty());
         }
@@ -244,7 +609,7 @@ impl<'a> WalletSubCommand<'a> {
                 for (path, hash160) in key_set.external.iter().chain(key_set.change.iter()) {
                     path_map.insert(hash160.clone(), path.clone());
                     let payload = AddressPayload::from_pubkey_hash(hash160.clone());
-                    lock_hashes.push(Script::from(&payload).calc_script_hash());
+                    lock_scripts.push(Script::from(&payload));
                 }
                 (
                     last_change_address.payload().clone(),
@@ -262,10 +627,7 @@ impl<'a> WalletSubCommand<'a> {
 
         let multisig_config_opt =
             if let Some(from_locked_address) = from_locked_address_opt.as_ref() {
-                lock_hashes.insert(
-                    0,
-                    Script::from(from_locked_address.payload()).calc_script_hash(),
-                );
+                lock_scripts.insert(0, Script::from(from_locked_address.payload()));
                 let mut lock_args = path_map.keys();
                 Some(loop {
                     let lock_arg =
@@ -289,9 +651,9 @@ impl<'a> WalletSubCommand<'a> {
                 None
             };
 
-        let to_data = get_to_data(m)?;
+        let memo = m.value_of("memo").map(ToOwned::to_owned);
 
-        let is_ledger = if let Either::Right(account) = from_account.clone() {
+        let is_ledger = if let Some(Either::Right(account)) = from_account_opt.clone() {
             match account {
                 AccountId::SoftwareMasterKey(_) => false,
                 AccountId::LedgerId(_) => true,
@@ -300,22 +662,63 @@ impl<'a> WalletSubCommand<'a> {
             false
         };
 
+        for path in path_map.values() {
+            self.signing_policy.check_path(path, is_ledger)?;
+        }
+
+        let duplicate_window: u64 =
+            FromStrParser::<u64>::default().from_matches(m, "duplicate-window")?;
+        if duplicate_window > 0
+            && !m.is_present("allow-duplicate")
+            && self.index_controller.record_txs()
+        {
+            self.check_duplicate_payments(&lock_scripts, &recipients, duplicate_window)?;
+        }
+
+        let index_backend = self.index_backend(m)?;
         let payload_opt = from_address_info_opt.map(|(x, _y)| x);
-        if let Either::Left(from_privkey) = from_account {
+        if let Some(remote_signer) = remote_signer_opt {
+            let signer = get_privkey_signer(remote_signer)?;
+            self.transfer_impl(
+                network_type,
+                payload_opt,
+                change_address_payload,
+                recipients,
+                fee_policy,
+                change_cells,
+                max_inputs,
+                tip,
+                tip_address_opt.clone(),
+                lock_scripts,
+                index_backend,
+                signer,
+                false,
+                change_path,
+                multisig_config_opt,
+                memo,
+                format,
+                color,
+                debug,
+            )
+        } else if let Some(Either::Left(from_privkey)) = from_account_opt {
             let signer = get_privkey_signer(from_privkey)?;
             self.transfer_impl(
                 network_type,
                 payload_opt,
                 change_address_payload,
-                to_address,
-                to_capacity,
-                to_data,
-                tx_fee,
-                lock_hashes,
+                recipients,
+                fee_policy,
+                change_cells,
+                max_inputs,
+                tip,
+                tip_address_opt.clone(),
+                lock_scripts,
+                index_backend,
                 signer,
                 false,
                 change_path,
                 multisig_config_opt,
+                memo,
                 format,
                 color,
                 debug,
@@ -326,15 +729,19 @@ impl<'a> WalletSubCommand<'a> {
                 network_type,
                 payload_opt,
                 change_address_payload,
-                to_address,
-                to_capacity,
-                to_data,
-                tx_fee,
-                lock_hashes,
+                recipients,
+                fee_policy,
+                change_cells,
+                max_inputs,
+                tip,
+                tip_address_opt.clone(),
+                lock_scripts,
+                index_backend,
                 signer,
                 is_ledger,
                 change_path,
                 multisig_config_opt,
+                memo,
                 format,
                 color,
                 debug,
@@ -344,145 +751,1204 @@ impl<'a> WalletSubCommand<'a> {
         }
     }
 
+    /// Catch accidental double submissions: the same capacity paid to the same
+    /// address by the sender within the last `window` blocks requires
+    /// confirmation.
+    fn check_duplicate_payments(
+        &mut self,
+        lock_scripts: &[Script],
+        recipients: &[(Address, u64, Bytes)],
+        window: u64,
+    ) -> Result<(), String> {
+        let from_number = self
+            .rpc_client
+            .get_tip_block_number()?
+            .saturating_sub(window);
+        let lock_hashes = lock_scripts
+            .iter()
+            .map(|script| script.calc_script_hash())
+            .collect::<Vec<_>>();
+        let txs = match self
+            .with_db(|db| {
+                lock_hashes
+                    .into_iter()
+                    .map(|lock_hash| {
+                        db.get_txs_by_lock(lock_hash, Some(from_number), usize::max_value())
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|txs| txs.into_iter().flatten().collect::<Vec<_>>())
+                    .map_err(|err| err.to_string())
+            })
+            .and_then(|result| result)
+        {
+            Ok(txs) => txs,
+            Err(err) => {
+                eprintln!("[WARNING] Skip duplicate payment detection: {}", err);
+                return Ok(());
+            }
+        };
+        let recipients = recipients
+            .iter()
+            .map(|(address, capacity, _)| (address.clone(), *capacity))
+            .collect::<Vec<_>>();
+        let duplicates = find_duplicate_payments(self.rpc_client, txs, &recipients)?;
+        if duplicates.is_empty() {
+            return Ok(());
+        }
+        for duplicate in &duplicates {
+            eprintln!(
+                "[WARNING] {:#} was already paid to {} by transaction {:#x} (block {}){}",
+                HumanCapacity::from(duplicate.capacity),
+                duplicate.address,
+                duplicate.tx_hash,
+                duplicate.block_number,
+                duplicate
+                    .memo
+                    .as_ref()
+                    .map(|memo| format!(", memo: {}", memo))
+                    .unwrap_or_default()
+            );
+        }
+        if confirm("Send the transfer again")? {
+            Ok(())
+        } else {
+            Err(
+                "Duplicate payment not confirmed, pass --allow-duplicate to skip the detection"
+                    .to_string(),
+            )
+        }
+    }
+
+    pub fn transfer_multisig(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        let from_address: Address = AddressParser::default()
+            .set_network(network_type)
+            .from_matches(m, "from-multisig-address")?;
+        let from_payload = from_address.payload().clone();
+        let from_args = from_payload.args();
+        if from_payload.code_hash() != MULTISIG_TYPE_HASH.pack()
+            || (from_args.len() != 20 && from_args.len() != 28)
+        {
+            return Err(format!("Invalid multisig address: {}", from_address));
+        }
+        let multisig_hash160 = H160::from_slice(&from_args[0..20]).unwrap();
+        let multisig_config = MultisigConfigStore::load()?
+            .get(&multisig_hash160)?
+            .ok_or_else(|| {
+                format!(
+                    "Multisig config of {} not found, please create it by `account multisig-create`",
+                    from_address
+                )
+            })?;
+
+        let accounts: Vec<AccountId> =
+            FromAccountParser::default().from_matches_vec(m, "from-account")?;
+        let to_address: Address = resolve_address(m.value_of("to-address").unwrap(), network_type)?;
+        let to_capacity: u64 = CapacityParser.from_matches(m, "capacity")?;
+        let fee_policy = get_fee_policy(m)?;
+        let change_cells: usize =
+            FromStrParser::<usize>::default().from_matches(m, "change-cells")?;
+        let to_data = get_to_data(m)?;
+        let tx_file_opt: Option<PathBuf> =
+            FilePathParser::new(false).from_matches_opt(m, "tx-file", false)?;
+        let memo = m.value_of("memo").map(ToOwned::to_owned);
+
+        check_capacity(to_capacity, to_data.len())?;
+        if let FeePolicy::Fixed(tx_fee) = fee_policy {
+            if tx_fee > ONE_CKB {
+                return Err("Transaction fee can not be more than 1.0 CKB".to_string());
+            }
+        }
+
+        // Unlock all the keys first
+        let member_lock_args = multisig_config.sighash_lock_args();
+        let mut keys = Vec::new();
+        for account in accounts {
+            let lock_arg = match account {
+                AccountId::SoftwareMasterKey(lock_arg) => lock_arg,
+                AccountId::LedgerId(_) => {
+                    return Err("Only keystore account can sign multisig transaction".to_string());
+                }
+            };
+            if !member_lock_args.contains(&lock_arg) {
+                return Err(format!(
+                    "Account {:#x} is not a member of the multisig",
+                    lock_arg
+                ));
+            }
+            let prompt = format!("Password of {:#x}", lock_arg);
+            let password = read_password(false, Some(&prompt))?;
+            let key = self
+                .key_store
+                .get_key(&lock_arg, password.as_bytes())
+                .map_err(|err| err.to_string())?
+                .clone();
+            keys.push((lock_arg, key));
+        }
+
+        let max_mature_number = get_max_mature_number(self.rpc_client)?;
+        let genesis_info = self.genesis_info()?;
+        let mut index_backend = self.index_backend(m)?;
+        // For check index database is ready
+        index_backend.last_number()?;
+        let mut collector = index_cell_collector(
+            index_backend,
+            max_mature_number,
+            vec![Script::from(&from_payload)],
+            from_address.to_string(),
+            self.coin_control.clone(),
+        );
+
+        let mut builder = TransactionBuilder::default();
+        builder
+            .output_to(to_address.payload(), to_capacity, to_data)
+            .change_address(from_payload.clone())
+            .change_cells(change_cells)
+            .fee_policy(fee_policy)
+            .multisig_config(multisig_config.clone());
+
+        let mut live_cell_cache: HashMap<(OutPoint, bool), ((CellOutput, Transaction), Bytes)> =
+            Default::default();
+        let rpc_client = &mut *self.rpc_client;
+        let light_client = &mut self.light_client;
+        let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
+            get_input_cell_with_cache(
+                &mut live_cell_cache,
+                rpc_client,
+                light_client.as_mut(),
+                out_point,
+                with_data,
+            )
+            .map(|(output, _)| output)
+        };
+        let mut helper =
+            builder.build_unsigned(&mut collector, &mut get_live_cell_fn, &genesis_info)?;
+        self.signing_policy.check_transaction(
+            &helper.transaction(),
+            network_type,
+            &mut get_live_cell_fn,
+        )?;
+        for (lock_arg, key) in keys {
+            let mut path_map = HashMap::default();
+            path_map.insert(lock_arg, DerivationPath::empty());
+            let signer = get_keystore_signer(key, path_map);
+            for (multisig_lock_arg, signature) in helper.sign_inputs(
+                signer,
+                &mut get_live_cell_fn,
+                false,
+                &DerivationPath::empty(),
+            )? {
+                helper.add_signature(multisig_lock_arg, serialize_signature_bytes(&signature))?;
+            }
+        }
+
+        let signatures_count = helper
+            .signatures()
+            .get(&Bytes::from(from_args.as_ref()))
+            .map(HashSet::len)
+            .unwrap_or(0);
+        let threshold = usize::from(multisig_config.threshold());
+        if let Some(tx_file) = tx_file_opt {
+            write_tx_file(&tx_file, helper, network_type)?;
+            let resp = serde_json::json!({
+                "tx_file": tx_file.to_string_lossy(),
+                "signatures": signatures_count,
+                "threshold": threshold,
+            });
+            return Ok(resp.render(format, color));
+        }
+        if signatures_count < threshold {
+            return Err(format!(
+                "Not enough signatures ({}/{}), please give --tx-file to save the transaction and collect more signatures",
+                signatures_count, threshold
+            ));
+        }
+        let tx = helper.build_tx(&mut get_live_cell_fn)?;
+        self.send_transaction(tx, memo, format, color, debug)
+    }
+
     fn transfer_impl(
         &mut self,
         network_type: NetworkType,
         from_address_payload_opt: Option<AddressPayload>,
         change_address_payload: AddressPayload,
-        to_address: Address,
-        to_capacity: u64,
-        to_data: Bytes,
-        tx_fee: u64,
-        lock_hashes: Vec<Byte32>,
+        recipients: Vec<(Address, u64, Bytes)>,
+        fee_policy: FeePolicy,
+        change_cells: usize,
+        max_inputs: usize,
+        tip: u64,
+        tip_address_opt: Option<Address>,
+        lock_scripts: Vec<Script>,
+        mut index_backend: Box<dyn IndexBackend>,
         signer: impl SignerFnTrait,
         is_ledger: bool,
         change_path: &DerivationPath,
         multisig_config_opt: Option<MultisigConfig>,
+        memo: Option<String>,
         format: OutputFormat,
         color: bool,
         debug: bool,
     ) -> Result<String, String> {
         let from_address = from_address_payload_opt.map(|x| Address::new(network_type, x.clone()));
 
-        let to_address_hash_type = to_address.payload().hash_type();
-        let to_address_code_hash: H256 = to_address.payload().code_hash().unpack();
-        let to_address_args_len = to_address.payload().args().len();
-        if !(to_address_hash_type == ScriptHashType::Type
-            && to_address_code_hash == SIGHASH_TYPE_HASH
-            && to_address_args_len == 20)
-            && !(to_address_hash_type == ScriptHashType::Type
-                && to_address_code_hash == MULTISIG_TYPE_HASH
-                && (to_address_args_len == 20 || to_address_args_len == 28))
-        {
-            return Err(format!("Invalid to-address: {}", to_address));
+        for (to_address, to_capacity, to_data) in &recipients {
+            let to_address_hash_type = to_address.payload().hash_type();
+            let to_address_code_hash: H256 = to_address.payload().code_hash().unpack();
+            let to_address_args_len = to_address.payload().args().len();
+            if !(to_address_hash_type == AddressHashType::Type
+                && to_address_code_hash == SIGHASH_TYPE_HASH
+                && to_address_args_len == 20)
+                && !(to_address_hash_type == AddressHashType::Type
+                    && to_address_code_hash == MULTISIG_TYPE_HASH
+                    && (to_address_args_len == 20 || to_address_args_len == 28))
+            {
+                return Err(format!("Invalid to-address: {}", to_address));
+            }
+            check_capacity(*to_capacity, to_data.len())
+                .map_err(|err| format!("Invalid capacity to {}: {}", to_address, err))?;
+        }
+        if tip_address_opt.is_some() {
+            check_capacity(tip, 0)?;
+        }
+        if let FeePolicy::Fixed(tx_fee) = fee_policy {
+            if tx_fee > ONE_CKB {
+                return Err("Transaction fee can not be more than 1.0 CKB".to_string());
+            }
         }
-
-        check_capacity(to_capacity, to_data.len())?;
 
         let max_mature_number = get_max_mature_number(self.rpc_client)?;
-        let mut from_capacity = 0;
-        let mut infos: Vec<LiveCellInfo> = Default::default();
-        let mut terminator = |_, info: &LiveCellInfo| {
-            if from_capacity >= to_capacity + tx_fee {
-                (true, false)
-            } else if info.type_hashes.is_none()
-                && info.data_bytes == 0
-                && is_mature(info, max_mature_number)
-            {
-                from_capacity += info.capacity;
-                infos.push(info.clone());
-                (from_capacity >= to_capacity + tx_fee, false)
+        let genesis_info = self.genesis_info()?;
+
+        // For check index database is ready
+        index_backend.last_number()?;
+        let rendered_from_address = match from_address {
+            Some(ref x) => format!("{}", x),
+            None => "<hardware wallet>".to_string(),
+        };
+        let mut collector = index_cell_collector(
+            index_backend,
+            max_mature_number,
+            lock_scripts,
+            rendered_from_address,
+            self.coin_control.clone(),
+        );
+
+        // Probe the inputs needed (with room for the fee and the change cell),
+        // too many are first merged into a few cells by consolidation
+        // transactions which the payment spends. The merged cells are less than
+        // the probed ones by the fees, so more is probed to cover them.
+        let tx_fee = match fee_policy {
+            FeePolicy::Fixed(tx_fee) => tx_fee,
+            FeePolicy::Rate(_) => 0,
+        };
+        let required = recipients
+            .iter()
+            .try_fold(0u64, |sum, (_, capacity, _)| sum.checked_add(*capacity))
+            .and_then(|value| value.checked_add(tip))
+            .and_then(|value| value.checked_add(tx_fee))
+            .and_then(|value| value.checked_add(MIN_SECP_CELL_CAPACITY))
+            .ok_or_else(|| "Capacity overflow: the recipients, tip and fee".to_string())?;
+
+        let change_lock = Script::from(&change_address_payload);
+        let mut builder = TransactionBuilder::default();
+        for (to_address, to_capacity, to_data) in recipients {
+            builder.output_to(to_address.payload(), to_capacity, to_data);
+        }
+        builder
+            .change_address(change_address_payload.clone())
+            .change_cells(change_cells)
+            .fee_policy(fee_policy);
+        if let Some(ref tip_address) = tip_address_opt {
+            builder.output_to(tip_address.payload(), tip, Bytes::default());
+        } else {
+            builder.tip(tip);
+        }
+        if tip > 0 {
+            let tip_target = match tip_address_opt {
+                Some(ref address) => format!("output to {}", address),
+                None => "extra fee".to_string(),
+            };
+            eprintln!("Tip: {} CKB ({})", HumanCapacity::from(tip), tip_target);
+        }
+        if let Some(multisig_config) = multisig_config_opt.clone() {
+            builder.multisig_config(multisig_config);
+        }
+        if is_ledger {
+            builder.ledger(change_path.clone());
+        }
+
+        let mut consolidation_txs = Vec::new();
+        let mut consolidation_fees = Vec::new();
+        consolidation_fee_reserve(|reserve| {
+            consolidation_txs.clear();
+            consolidation_fees.clear();
+            let required = required
+                .checked_add(reserve)
+                .ok_or_else(|| "Capacity overflow".to_string())?;
+            let cells = match collector(required) {
+                Ok(cells) => cells,
+                // The payment reports the capacity not enough
+                Err(_) if reserve == 0 => return Ok(None),
+                Err(err) => return Err(err),
+            };
+            if cells.len() <= max_inputs {
+                return Ok(None);
+            }
+            if is_ledger || multisig_config_opt.is_some() {
+                return Err(format!(
+                    "The transfer needs {} inputs, more than --max-inputs {}, please merge the cells by `wallet sweep` first",
+                    cells.len(),
+                    max_inputs
+                ));
+            }
+            let capacities = cells.iter().map(|cell| cell.capacity).collect::<Vec<_>>();
+            let chunks = sweep_chunks(&capacities, max_inputs);
+            if chunks.len() > max_inputs {
+                return Err(format!(
+                    "The transfer needs {} inputs, too many to merge with --max-inputs {}, please merge the cells by `wallet sweep` first",
+                    cells.len(),
+                    max_inputs
+                ));
+            }
+            let fee_rate = match fee_policy {
+                FeePolicy::Rate(fee_rate) => fee_rate,
+                FeePolicy::Fixed(_) => {
+                    return Err(format!(
+                        "The transfer needs {} inputs, more than --max-inputs {}, please give --fee-rate instead of --tx-fee to pay the consolidation transactions",
+                        cells.len(),
+                        max_inputs
+                    ));
+                }
+            };
+            let txs = self.build_sweep_txs(
+                &cells,
+                &chunks,
+                &change_address_payload,
+                fee_rate,
+                network_type,
+                dyn_clone::clone(&signer),
+            )?;
+            for (tx, chunk) in txs.iter().zip(chunks.iter()) {
+                let inputs_capacity: u64 = chunk.iter().map(|idx| cells[*idx].capacity).sum();
+                let capacity: u64 = tx.outputs().get(0).unwrap().capacity().unpack();
+                consolidation_fees.push(inputs_capacity - capacity);
+            }
+            let merged_cells = txs.iter().map(merged_cell).collect::<Vec<_>>();
+            let payment_fee =
+                estimate_payment_fee(&builder, &merged_cells, &genesis_info, fee_rate)?;
+            consolidation_txs = txs;
+            Ok(Some(consolidation_fees.iter().sum::<u64>() + payment_fee))
+        })?;
+        let consolidated_cells = consolidation_txs
+            .iter()
+            .map(|tx| {
+                let capacity: u64 = tx.outputs().get(0).unwrap().capacity().unpack();
+                CollectedCell::new(OutPoint::new(tx.hash(), 0), capacity)
+            })
+            .collect::<Vec<_>>();
+        let mut collector: Box<dyn FnMut(u64) -> Result<Vec<CollectedCell>, String>> =
+            if consolidated_cells.is_empty() {
+                Box::new(collector)
             } else {
-                (false, false)
+                let cells = consolidated_cells.clone();
+                Box::new(move |_required: u64| Ok(cells.clone()))
+            };
+
+        let mut live_cell_cache: HashMap<(OutPoint, bool), ((CellOutput, Transaction), Bytes)> =
+            Default::default();
+        // The consolidated cells are not on chain yet
+        for (out_point, cell) in consolidation_txs.iter().map(merged_cell) {
+            for with_data in &[false, true] {
+                live_cell_cache.insert(
+                    (out_point.clone(), *with_data),
+                    (cell.clone(), Bytes::new()),
+                );
+            }
+        }
+        let rpc_client = &mut *self.rpc_client;
+        let light_client = &mut self.light_client;
+        let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
+            get_input_cell_with_cache(
+                &mut live_cell_cache,
+                rpc_client,
+                light_client.as_mut(),
+                out_point,
+                with_data,
+            )
+            .map(|(output, _)| output)
+        };
+        let tx = self.signing_policy.build_transaction(
+            &builder,
+            &mut collector,
+            &mut get_live_cell_fn,
+            &genesis_info,
+            network_type,
+            signer,
+        )?;
+        if consolidation_txs.is_empty() {
+            return self.send_transaction(tx, memo, format, color, debug);
+        }
+
+        let inputs_count: usize = consolidation_txs.iter().map(|tx| tx.inputs().len()).sum();
+        eprintln!(
+            "The transfer needs {} inputs, more than --max-inputs {}, it is split into:",
+            inputs_count, max_inputs
+        );
+        for (tx_idx, (consolidation_tx, fee)) in consolidation_txs
+            .iter()
+            .zip(consolidation_fees.iter())
+            .enumerate()
+        {
+            eprintln!(
+                "  consolidate {}/{}: {} inputs, fee {} CKB",
+                tx_idx + 1,
+                consolidation_txs.len(),
+                consolidation_tx.inputs().len(),
+                HumanCapacity::from(*fee)
+            );
+        }
+        let consolidated_capacity: u64 = consolidated_cells.iter().map(|cell| cell.capacity).sum();
+        let mut outputs_capacity: u64 = 0;
+        let mut sent_capacity: u64 = 0;
+        for output in tx.outputs().into_iter() {
+            let capacity: u64 = output.capacity().unpack();
+            outputs_capacity += capacity;
+            if output.lock() != change_lock {
+                sent_capacity += capacity;
+            }
+        }
+        let payment_fee = consolidated_capacity - outputs_capacity;
+        let total_fee = consolidation_fees.iter().sum::<u64>() + payment_fee;
+        eprintln!(
+            "  payment: {} inputs, fee {} CKB",
+            tx.inputs().len(),
+            HumanCapacity::from(payment_fee)
+        );
+        eprintln!("Total fee: {} CKB", HumanCapacity::from(total_fee));
+        let transferred = if sent_capacity > 0 {
+            sent_capacity
+        } else {
+            outputs_capacity
+        };
+        self.signing_policy
+            .check_total_fee(total_fee, transferred)?;
+
+        for (tx_idx, consolidation_tx) in consolidation_txs.iter().enumerate() {
+            if let Some(staged_txs) = self.staged_txs.as_mut() {
+                staged_txs.push(consolidation_tx.clone());
+                continue;
+            }
+            // Sent by an interrupted run of the same transfer, which builds
+            // the same transactions from the same cells
+            let tx_hash: H256 = consolidation_tx.hash().unpack();
+            if self.rpc_client.get_transaction(tx_hash.clone())?.is_some() {
+                eprintln!(
+                    "consolidate {}/{} {:#x} is already sent, skipped",
+                    tx_idx + 1,
+                    consolidation_txs.len(),
+                    tx_hash
+                );
+                continue;
+            }
+            if debug {
+                let tx_view: ckb_jsonrpc_types::TransactionView = consolidation_tx.clone().into();
+                println!("[Send Transaction]:\n{}", tx_view.render(format, color));
+            }
+            let tx_hash = on_failure::send_transaction(
+                self.rpc_client,
+                consolidation_tx,
+                self.on_failure.as_ref(),
+                &self.send_targets,
+            )
+            .map_err(|err| {
+                format!(
+                    "Send consolidation transaction {}/{} error: {}, run the same transfer again to resume (the sent transactions are skipped)",
+                    tx_idx + 1,
+                    consolidation_txs.len(),
+                    err
+                )
+            })?;
+            let memo = format!("consolidate {}/{}", tx_idx + 1, consolidation_txs.len());
+            save_tx_memo(&tx_hash, Some(memo))?;
+        }
+        self.send_transaction(tx, memo, format, color, debug)
+    }
+
+    pub fn analyze_fragmentation(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        let from_account_opt = if m.is_present("privkey-path") || m.is_present("from-account") {
+            Some(privkey_or_from_account(m)?)
+        } else {
+            None
+        };
+        let address_payload = match from_account_opt {
+            Some(Either::Left(ref privkey)) => {
+                let pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, privkey);
+                AddressPayload::from_pubkey(&pubkey)
+            }
+            Some(Either::Right(AccountId::SoftwareMasterKey(ref lock_arg))) => {
+                AddressPayload::from_pubkey_hash(lock_arg.clone())
             }
+            Some(Either::Right(AccountId::LedgerId(_))) => {
+                return Err("Hardware wallet is not supported by analyze-fragmentation".to_string());
+            }
+            None => get_address(Some(network_type), m)?,
         };
+        let execute = m.is_present("execute");
+        if execute && from_account_opt.is_none() {
+            return Err("--execute requires --privkey-path or --from-account".to_string());
+        }
+        let dust_threshold: u64 = CapacityParser.from_matches(m, "dust-threshold")?;
+        let target_cells: usize =
+            FromStrParser::<usize>::default().from_matches(m, "target-cells")?;
+        let fee_rate: u64 = FromStrParser::<u64>::default().from_matches(m, "fee-rate")?;
+
+        let max_mature_number = get_max_mature_number(self.rpc_client)?;
+        let lock_script = Script::from(&address_payload);
+        let mut index_backend = self.index_backend(m)?;
+        let infos =
+            index_backend.get_live_cells(&CellQuery::Lock(lock_script), None, &mut |_, _| {
+                (false, true)
+            })?;
+        let (spendable, others): (Vec<LiveCellInfo>, Vec<LiveCellInfo>) =
+            infos.into_iter().partition(|info| {
+                info.type_hashes.is_none()
+                    && info.data_bytes == 0
+                    && is_mature(info, max_mature_number)
+            });
+        let capacities = spendable
+            .iter()
+            .map(|info| info.capacity)
+            .collect::<Vec<_>>();
+        let stats = CellStats::new(&capacities, dust_threshold);
+        let advice = advise(&capacities, dust_threshold, target_cells);
+        let address = Address::new(network_type, address_payload.clone());
+        let mut resp = serde_json::json!({
+            "address": address.to_string(),
+            "cells": stats.count,
+            "total": format!("{:#}", HumanCapacity::from(stats.total)),
+            "smallest": format!("{:#}", HumanCapacity::from(stats.smallest)),
+            "median": format!("{:#}", HumanCapacity::from(stats.median)),
+            "largest": format!("{:#}", HumanCapacity::from(stats.largest)),
+            "dust_cells": stats.dust_count,
+            "dust_capacity": format!("{:#}", HumanCapacity::from(stats.dust_capacity)),
+            // Immature cellbase cells or cells with type script/data
+            "excluded_cells": others.len(),
+            "recommendation": {
+                "action": advice.action(),
+            },
+        });
+        if advice == Advice::Healthy {
+            return Ok(resp.render(format, color));
+        }
 
+        let inputs = advice
+            .inputs()
+            .iter()
+            .map(|idx| CollectedCell::new(spendable[*idx].out_point(), spendable[*idx].capacity))
+            .collect::<Vec<_>>();
+        let inputs_capacity: u64 = inputs.iter().map(|cell| cell.capacity).sum();
+        let mut collector =
+            |_required: u64| -> Result<Vec<CollectedCell>, String> { Ok(inputs.clone()) };
+        let mut builder = TransactionBuilder::default();
+        builder
+            .change_address(address_payload)
+            .change_cells(advice.outputs())
+            .fee_policy(FeePolicy::Rate(fee_rate));
         let genesis_info = self.genesis_info()?;
-        let genesis_hash = genesis_info.header().hash();
-        let genesis_info_clone = genesis_info.clone();
+        let mut live_cell_cache: HashMap<(OutPoint, bool), ((CellOutput, Transaction), Bytes)> =
+            Default::default();
+        let rpc_client = &mut *self.rpc_client;
+        let light_client = &mut self.light_client;
+        let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
+            get_input_cell_with_cache(
+                &mut live_cell_cache,
+                rpc_client,
+                light_client.as_mut(),
+                out_point,
+                with_data,
+            )
+            .map(|(output, _)| output)
+        };
+        let helper =
+            builder.build_unsigned(&mut collector, &mut get_live_cell_fn, &genesis_info)?;
+        let outputs = helper.transaction().outputs();
+        let outputs_capacity: u64 = outputs
+            .into_iter()
+            .map(|output| {
+                let capacity: u64 = output.capacity().unpack();
+                capacity
+            })
+            .sum();
+        resp["recommendation"] = serde_json::json!({
+            "action": advice.action(),
+            "inputs": inputs.len(),
+            "outputs": helper.transaction().outputs().len(),
+            "projected_fee": format!("{:#}", HumanCapacity::from(inputs_capacity - outputs_capacity)),
+        });
+        if !execute {
+            return Ok(resp.render(format, color));
+        }
 
-        // For check index database is ready
-        self.with_db(|_| ())?;
-        let index_dir = self.index_dir.clone();
-
-        if let Err(err) = with_index_db(&index_dir, genesis_hash.unpack(), |backend, cf| {
-            IndexDatabase::from_db(backend, cf, network_type, genesis_info_clone, false)
-                .map(|db| {
-                    for lock_hash in lock_hashes {
-                        db.get_live_cells_by_lock(lock_hash, None, &mut terminator);
-                    }
-                })
-                .map_err(Into::into)
-        }) {
+        let signer = get_account_signer(self.key_store, from_account_opt.unwrap())?;
+        let tx = self.signing_policy.build_transaction(
+            &builder,
+            &mut collector,
+            &mut get_live_cell_fn,
+            &genesis_info,
+            network_type,
+            signer,
+        )?;
+        if debug {
+            let tx_view: ckb_jsonrpc_types::TransactionView = tx.clone().into();
+            println!("[Send Transaction]:\n{}", tx_view.render(format, color));
+        }
+        let tx_hash = on_failure::send_transaction(
+            self.rpc_client,
+            &tx,
+            self.on_failure.as_ref(),
+            &self.send_targets,
+        )
+        .map_err(|err| format!("Send transaction error: {}", err))?;
+        let memo = format!("{} {} cells", advice.action(), inputs.len());
+        save_tx_memo(&tx_hash, Some(memo))?;
+        resp["tx_hash"] = serde_json::json!(tx_hash);
+        Ok(resp.render(format, color))
+    }
+
+    pub fn scan(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let lock_arg: H160 = FixedHashParser::<H160>::default().from_matches(m, "account")?;
+        let gap_limit: u32 = FromStrParser::<u32>::default().from_matches(m, "gap-limit")?;
+        if gap_limit == 0 {
+            return Err("--gap-limit must be greater than 0".to_string());
+        }
+        if !self.key_store.has_account(&lock_arg) {
             return Err(format!(
-                "Index database may not ready, sync process: {}, error: {}",
-                self.index_controller.state().read().to_string(),
-                err.to_string(),
+                "Derived addresses are only scanned for keystore accounts, {:#x} is not found",
+                lock_arg
             ));
         }
+        let network_type = get_network_type(self.rpc_client)?;
+        let account_path = DerivationPath::from_str("m/44'/309'/0'").unwrap();
+        let password = read_password(false, None)?;
+        // Derived from the account extended public key, the key is decrypted once
+        let account_xpub = self
+            .key_store
+            .extended_pubkey_with_password(&lock_arg, account_path.as_ref(), password.as_bytes())
+            .map_err(|err| err.to_string())?;
 
-        if tx_fee > ONE_CKB {
-            return Err("Transaction fee can not be more than 1.0 CKB".to_string());
-        }
-        if to_capacity + tx_fee > from_capacity {
-            let rendered_from_address = match from_address {
-                Some(ref x) => format!("{}", x),
-                None => "<hardware wallet>".to_string(),
+        let resp = self.with_db(|db| {
+            let mut total_capacity = 0;
+            let mut scan = |chain: KeyChain| {
+                let derive = |index: u32| -> ExtendedPubKey {
+                    let path = [
+                        ChildNumber::Normal {
+                            index: chain as u32,
+                        },
+                        ChildNumber::Normal { index },
+                    ];
+                    account_xpub
+                        .derive_pub(&SECP256K1, &path[..])
+                        .expect("Derive public key failed")
+                };
+                let mut db_error = None;
+                let (scanned, next_unused) = scan_chain(gap_limit, |index| {
+                    let payload = AddressPayload::from_pubkey(&derive(index).public_key);
+                    let lock_hash = Script::from(&payload).calc_script_hash();
+                    let capacity = db.get_capacity(lock_hash.clone()).unwrap_or(0);
+                    let has_txs = match db.get_txs_by_lock(lock_hash, None, 1) {
+                        Ok(txs) => !txs.is_empty(),
+                        Err(err) => {
+                            db_error.get_or_insert(err.to_string());
+                            false
+                        }
+                    };
+                    if capacity > 0 || has_txs {
+                        Some((payload, capacity))
+                    } else {
+                        None
+                    }
+                });
+                if let Some(err) = db_error {
+                    return Err(err);
+                }
+                let addresses = scanned
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, usage)| {
+                        let used = usage.is_some();
+                        let (payload, capacity) = usage.unwrap_or_else(|| {
+                            let payload =
+                                AddressPayload::from_pubkey(&derive(index as u32).public_key);
+                            (payload, 0)
+                        });
+                        total_capacity += capacity;
+                        serde_json::json!({
+                            "index": index,
+                            "path": format!("{}/{}/{}", account_path, chain as u32, index),
+                            "address": Address::new(network_type, payload).to_string(),
+                            "used": used,
+                            "capacity": format!("{:#}", HumanCapacity::from(capacity)),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                Ok(serde_json::json!({
+                    "next_unused_index": next_unused,
+                    "addresses": addresses,
+                }))
+            };
+            let receiving = scan(KeyChain::External)?;
+            let change = scan(KeyChain::Change)?;
+            Ok(serde_json::json!({
+                "account": format!("{:#x}", lock_arg),
+                "gap_limit": gap_limit,
+                "receiving": receiving,
+                "change": change,
+                "total_capacity": format!("{:#}", HumanCapacity::from(total_capacity)),
+            }))
+        })??;
+        Ok(resp.render(format, color))
+    }
+
+    pub fn cellbase_report(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        let address_payload = get_address(Some(network_type), m)?;
+        let max_mature_number = get_max_mature_number(self.rpc_client)?;
+        let mut index_backend = self.index_backend(m)?;
+        let mut infos = index_backend.get_live_cells(
+            &CellQuery::Lock(Script::from(&address_payload)),
+            None,
+            &mut |_, info| (false, !is_mature(info, max_mature_number)),
+        )?;
+        infos.sort_by_key(|info| info.number);
+
+        let tip = self.rpc_client.get_tip_header()?;
+        let tip_number = tip.inner.number;
+        let tip_epoch = EpochNumberWithFraction::from_full_value(tip.inner.epoch.0);
+        let tip_epoch_view = self
+            .rpc_client
+            .get_epoch_by_number(tip_epoch.number())?
+            .ok_or_else(|| format!("Epoch not found: {}", tip_epoch.number()))?;
+        // Average block time of recent blocks, used to project the dates
+        let sample_number = tip_number.saturating_sub(CELLBASE_REPORT_SAMPLE_BLOCKS);
+        let sample = self
+            .rpc_client
+            .get_header_by_number(sample_number)?
+            .ok_or_else(|| format!("Block not found: {}", sample_number))?;
+        let block_time = (tip.inner.timestamp.0 - sample.inner.timestamp.0)
+            / (tip_number - sample_number).max(1);
+
+        let mut cells = Vec::with_capacity(infos.len());
+        let mut mature_at = Vec::with_capacity(infos.len());
+        for info in &infos {
+            let header = self
+                .rpc_client
+                .get_header_by_number(info.number)?
+                .ok_or_else(|| format!("Block not found: {}", info.number))?;
+            let cell_epoch = EpochNumberWithFraction::from_full_value(header.inner.epoch.0);
+            let target_epoch = cell_epoch.number() + CELLBASE_MATURITY.number();
+            let (target_start, target_length) = if target_epoch <= tip_epoch.number() {
+                let epoch = self
+                    .rpc_client
+                    .get_epoch_by_number(target_epoch)?
+                    .ok_or_else(|| format!("Epoch not found: {}", target_epoch))?;
+                (epoch.start_number, epoch.length)
+            } else {
+                // Assume the future epochs have the same length as current one
+                let epochs = target_epoch - tip_epoch.number();
+                (
+                    tip_epoch_view.start_number + tip_epoch_view.length * epochs,
+                    tip_epoch_view.length,
+                )
             };
+            let mature_number = mature_block_number(cell_epoch, target_start, target_length);
+            let timestamp =
+                tip.inner.timestamp.0 + mature_number.saturating_sub(tip_number) * block_time;
+            mature_at.push((timestamp, info.capacity));
+            cells.push(serde_json::json!({
+                "out_point": {
+                    "tx_hash": info.tx_hash,
+                    "index": info.tx_index,
+                },
+                "block_number": info.number,
+                "capacity": format!("{:#}", HumanCapacity::from(info.capacity)),
+                "mature_at_block": mature_number,
+                "projected_date": Utc.timestamp_millis(timestamp as i64).to_rfc3339(),
+            }));
+        }
+        let daily = group_by_day(&mature_at)
+            .into_iter()
+            .map(|(day, (capacity, count))| {
+                serde_json::json!({
+                    "date": day,
+                    "cells": count,
+                    "capacity": format!("{:#}", HumanCapacity::from(capacity)),
+                })
+            })
+            .collect::<Vec<_>>();
+        let total: u64 = infos.iter().map(|info| info.capacity).sum();
+        let resp = serde_json::json!({
+            "address": Address::new(network_type, address_payload).to_string(),
+            "tip_block_number": tip_number,
+            "average_block_time": format!("{:.2}s", block_time as f64 / 1000.0),
+            "total_immature": format!("{:#}", HumanCapacity::from(total)),
+            "immature_cells": cells,
+            "daily": daily,
+        });
+        Ok(resp.render(format, color))
+    }
+
+    pub fn sweep(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        let from_account = privkey_or_from_account(m)?;
+        let from_payload = match from_account {
+            Either::Left(ref privkey) => {
+                let pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, privkey);
+                AddressPayload::from_pubkey(&pubkey)
+            }
+            Either::Right(AccountId::SoftwareMasterKey(ref lock_arg)) => {
+                AddressPayload::from_pubkey_hash(lock_arg.clone())
+            }
+            Either::Right(AccountId::LedgerId(_)) => {
+                return Err("Hardware wallet is not supported by sweep".to_string());
+            }
+        };
+        let to_address: Address = resolve_address(m.value_of("to-address").unwrap(), network_type)?;
+        let max_inputs: usize = FromStrParser::<usize>::default().from_matches(m, "max-inputs")?;
+        let fee_rate: u64 = FromStrParser::<u64>::default().from_matches(m, "fee-rate")?;
+
+        let max_mature_number = get_max_mature_number(self.rpc_client)?;
+        let mut index_backend = self.index_backend(m)?;
+        let infos = index_backend.get_live_cells(
+            &CellQuery::Lock(Script::from(&from_payload)),
+            None,
+            &mut |_, info| {
+                let spendable = info.type_hashes.is_none()
+                    && info.data_bytes == 0
+                    && is_mature(info, max_mature_number);
+                (false, spendable)
+            },
+        )?;
+        if infos.is_empty() {
+            return Err("No mature live cell to sweep".to_string());
+        }
+        let cells = infos
+            .iter()
+            .map(|info| CollectedCell::new(info.out_point(), info.capacity))
+            .collect::<Vec<_>>();
+        let capacities = infos.iter().map(|info| info.capacity).collect::<Vec<_>>();
+        let chunks = sweep_chunks(&capacities, max_inputs);
+
+        let to_payload = to_address.payload();
+        let signer = get_account_signer(self.key_store, from_account)?;
+        let txs =
+            self.build_sweep_txs(&cells, &chunks, to_payload, fee_rate, network_type, signer)?;
+
+        let mut sent_txs = Vec::with_capacity(txs.len());
+        let mut total_sent = 0;
+        for (tx_idx, tx) in txs.iter().enumerate() {
+            if debug {
+                let tx_view: ckb_jsonrpc_types::TransactionView = tx.clone().into();
+                println!("[Send Transaction]:\n{}", tx_view.render(format, color));
+            }
+            let sent: u64 = tx
+                .outputs()
+                .into_iter()
+                .map(|output| {
+                    let capacity: u64 = output.capacity().unpack();
+                    capacity
+                })
+                .sum();
+            let tx_hash = on_failure::send_transaction(
+                self.rpc_client,
+                tx,
+                self.on_failure.as_ref(),
+                &self.send_targets,
+            )
+            .map_err(|err| format!("Send transaction {} error: {}", tx_idx, err))?;
+            let memo = format!("sweep {}/{}", tx_idx + 1, txs.len());
+            save_tx_memo(&tx_hash, Some(memo))?;
+            total_sent += sent;
+            sent_txs.push(serde_json::json!({
+                "tx_hash": tx_hash,
+                "inputs": tx.inputs().len(),
+                "capacity": format!("{:#}", HumanCapacity::from(sent)),
+            }));
+        }
+        let resp = serde_json::json!({
+            "from": Address::new(network_type, from_payload).to_string(),
+            "to": to_address.to_string(),
+            "total_cells": infos.len(),
+            "total_sent": format!("{:#}", HumanCapacity::from(total_sent)),
+            "transactions": sent_txs,
+        });
+        Ok(resp.render(format, color))
+    }
+
+    pub fn consolidate(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let network_type = get_network_type(self.rpc_client)?;
+        let from_account = privkey_or_from_account(m)?;
+        let from_payload = match from_account {
+            Either::Left(ref privkey) => {
+                let pubkey = secp256k1::PublicKey::from_secret_key(&SECP256K1, privkey);
+                AddressPayload::from_pubkey(&pubkey)
+            }
+            Either::Right(AccountId::SoftwareMasterKey(ref lock_arg)) => {
+                AddressPayload::from_pubkey_hash(lock_arg.clone())
+            }
+            Either::Right(AccountId::LedgerId(_)) => {
+                return Err("Hardware wallet is not supported by consolidate".to_string());
+            }
+        };
+        if m.is_present("address") && get_address(Some(network_type), m)? != from_payload {
+            return Err("The address is not the address of the key".to_string());
+        }
+        let max_inputs: usize = FromStrParser::<usize>::default().from_matches(m, "max-inputs")?;
+        let until_cells: Option<usize> =
+            FromStrParser::<usize>::default().from_matches_opt(m, "until-cells", false)?;
+        let fee_rate: u64 = FromStrParser::<u64>::default().from_matches(m, "fee-rate")?;
+
+        let max_mature_number = get_max_mature_number(self.rpc_client)?;
+        let mut index_backend = self.index_backend(m)?;
+        let infos = index_backend.get_live_cells(
+            &CellQuery::Lock(Script::from(&from_payload)),
+            None,
+            &mut |_, info| {
+                let spendable = info.type_hashes.is_none()
+                    && info.data_bytes == 0
+                    && is_mature(info, max_mature_number);
+                (false, spendable)
+            },
+        )?;
+        let cells = infos
+            .iter()
+            .map(|info| CollectedCell::new(info.out_point(), info.capacity))
+            .collect::<Vec<_>>();
+        if next_consolidation(cells.len(), max_inputs, until_cells, 0).is_none() {
             return Err(format!(
-                "Capacity(mature) not enough: {} => {}",
-                rendered_from_address, from_capacity,
+                "Nothing to consolidate, {} mature live cells",
+                cells.len()
             ));
         }
 
-        let rest_capacity = from_capacity - to_capacity - tx_fee;
-        if rest_capacity < MIN_SECP_CELL_CAPACITY && tx_fee + rest_capacity > ONE_CKB {
-            return Err("Transaction fee can not be more than 1.0 CKB, please change to-capacity value to adjust".to_string());
+        let signer = get_account_signer(self.key_store, from_account)?;
+        let (txs, fees) = self.build_consolidate_txs(
+            cells.clone(),
+            max_inputs,
+            until_cells,
+            &from_payload,
+            fee_rate,
+            network_type,
+            signer,
+        )?;
+
+        let mut sent_txs = Vec::with_capacity(txs.len());
+        let mut cells_after = cells.len();
+        for (tx_idx, (tx, fee)) in txs.iter().zip(fees.iter()).enumerate() {
+            if debug {
+                let tx_view: ckb_jsonrpc_types::TransactionView = tx.clone().into();
+                println!("[Send Transaction]:\n{}", tx_view.render(format, color));
+            }
+            let tx_hash = on_failure::send_transaction(
+                self.rpc_client,
+                tx,
+                self.on_failure.as_ref(),
+                &self.send_targets,
+            )
+            .map_err(|err| format!("Send transaction {} error: {}", tx_idx, err))?;
+            let memo = format!("consolidate {}/{}", tx_idx + 1, txs.len());
+            save_tx_memo(&tx_hash, Some(memo))?;
+            cells_after = cells_after + 1 - tx.inputs().len();
+            let capacity: u64 = tx.outputs().get(0).unwrap().capacity().unpack();
+            sent_txs.push(serde_json::json!({
+                "tx_hash": tx_hash,
+                "inputs": tx.inputs().len(),
+                "capacity": format!("{:#}", HumanCapacity::from(capacity)),
+                "fee": format!("{:#}", HumanCapacity::from(*fee)),
+            }));
         }
+        let resp = serde_json::json!({
+            "address": Address::new(network_type, from_payload).to_string(),
+            "cells_before": cells.len(),
+            "cells_after": cells_after,
+            "total_fee": format!("{:#}", HumanCapacity::from(fees.iter().sum::<u64>())),
+            "transactions": sent_txs,
+        });
+        Ok(resp.render(format, color))
+    }
 
-        let mut helper = TxHelper::default();
-        if let Some(multisig_config) = multisig_config_opt {
-            helper.add_multisig_config(multisig_config)
+    // Merge the smallest cells one transaction after another, the merged cell
+    // goes back to the pool so a later transaction may spend it (before it is
+    // committed). Return the transactions and their fees.
+    fn build_consolidate_txs<S: SignerFnTrait>(
+        &mut self,
+        mut pool: Vec<CollectedCell>,
+        max_inputs: usize,
+        until_cells: Option<usize>,
+        to_payload: &AddressPayload,
+        fee_rate: u64,
+        network_type: NetworkType,
+        signer: S,
+    ) -> Result<(Vec<TransactionView>, Vec<u64>), String> {
+        let genesis_info = self.genesis_info()?;
+        let mut live_cell_cache: HashMap<(OutPoint, bool), ((CellOutput, Transaction), Bytes)> =
+            Default::default();
+        let mut txs = Vec::new();
+        let mut fees = Vec::new();
+        while let Some(inputs_count) =
+            next_consolidation(pool.len(), max_inputs, until_cells, txs.len())
+        {
+            pool.sort_by_key(|cell| cell.capacity);
+            let inputs = pool.drain(..inputs_count).collect::<Vec<_>>();
+            let inputs_capacity: u64 = inputs.iter().map(|cell| cell.capacity).sum();
+            let mut collector =
+                |_required: u64| -> Result<Vec<CollectedCell>, String> { Ok(inputs.clone()) };
+            let mut builder = TransactionBuilder::default();
+            builder
+                .change_address(to_payload.clone())
+                .fee_policy(FeePolicy::Rate(fee_rate));
+            let rpc_client = &mut *self.rpc_client;
+            let light_client = &mut self.light_client;
+            let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
+                get_input_cell_with_cache(
+                    &mut live_cell_cache,
+                    rpc_client,
+                    light_client.as_mut(),
+                    out_point,
+                    with_data,
+                )
+                .map(|(output, _)| output)
+            };
+            let tx = self
+                .signing_policy
+                .build_transaction(
+                    &builder,
+                    &mut collector,
+                    &mut get_live_cell_fn,
+                    &genesis_info,
+                    network_type,
+                    dyn_clone::clone(&signer),
+                )
+                .map_err(|err| format!("Build transaction {} failed: {}", txs.len(), err))?;
+
+            // The merged cell is not on chain yet
+            let output = tx.outputs().get(0).unwrap();
+            let capacity: u64 = output.capacity().unpack();
+            let inner = Transaction::from(ckb_jsonrpc_types::Transaction::from(tx.data()));
+            for with_data in &[false, true] {
+                live_cell_cache.insert(
+                    (OutPoint::new(tx.hash(), 0), *with_data),
+                    ((output.clone(), inner.clone()), Bytes::new()),
+                );
+            }
+            pool.push(CollectedCell::new(OutPoint::new(tx.hash(), 0), capacity));
+            fees.push(inputs_capacity - capacity);
+            txs.push(tx);
         }
+        Ok((txs, fees))
+    }
 
+    // Build (and sign) all the transactions before sending any of them
+    fn build_sweep_txs<S: SignerFnTrait>(
+        &mut self,
+        cells: &[CollectedCell],
+        chunks: &[Vec<usize>],
+        to_payload: &AddressPayload,
+        fee_rate: u64,
+        network_type: NetworkType,
+        signer: S,
+    ) -> Result<Vec<TransactionView>, String> {
+        let genesis_info = self.genesis_info()?;
         let mut live_cell_cache: HashMap<(OutPoint, bool), ((CellOutput, Transaction), Bytes)> =
             Default::default();
+        let rpc_client = &mut *self.rpc_client;
+        let light_client = &mut self.light_client;
         let mut get_live_cell_fn = |out_point: OutPoint, with_data: bool| {
-            get_live_cell_with_cache(&mut live_cell_cache, self.rpc_client, out_point, with_data)
-                .map(|(output, _)| output)
+            get_input_cell_with_cache(
+                &mut live_cell_cache,
+                rpc_client,
+                light_client.as_mut(),
+                out_point,
+                with_data,
+            )
+            .map(|(output, _)| output)
         };
-        for info in &infos {
-            helper.add_input(info.out_point(), None, &mut get_live_cell_fn, &genesis_info)?;
-        }
-        let to_output = CellOutput::new_builder()
-            .capacity(Capacity::shannons(to_capacity).pack())
-            .lock(to_address.payload().into())
-            .build();
-        helper.add_output(to_output, to_data);
-        if rest_capacity >= MIN_SECP_CELL_CAPACITY {
-            let change_output = CellOutput::new_builder()
-                .capacity(Capacity::shannons(rest_capacity).pack())
-                .lock((&change_address_payload).into())
-                .build();
-            helper.add_output(change_output, Bytes::default());
-        }
-
-        for (ref lock_arg, ref signature) in
-            helper.sign_inputs(signer, &mut get_live_cell_fn, is_ledger, change_path)?
-        {
-            helper.add_signature(lock_arg.clone(), serialize_signature_bytes(signature))?;
+        let mut txs = Vec::with_capacity(chunks.len());
+        for (chunk_idx, chunk) in chunks.iter().enumerate() {
+            let inputs = chunk
+                .iter()
+                .map(|idx| cells[*idx].clone())
+                .collect::<Vec<_>>();
+            let mut collector =
+                |_required: u64| -> Result<Vec<CollectedCell>, String> { Ok(inputs.clone()) };
+            let mut builder = TransactionBuilder::default();
+            // No outputs, the whole balance (minus fee) goes to the change cell
+            builder
+                .change_address(to_payload.clone())
+                .fee_policy(FeePolicy::Rate(fee_rate));
+            let tx = self
+                .signing_policy
+                .build_transaction(
+                    &builder,
+                    &mut collector,
+                    &mut get_live_cell_fn,
+                    &genesis_info,
+                    network_type,
+                    dyn_clone::clone(&signer),
+                )
+                .map_err(|err| format!("Build transaction {} failed: {}", chunk_idx, err))?;
+            txs.push(tx);
         }
-        let tx = helper.build_tx(&mut get_live_cell_fn)?;
-        self.send_transaction(tx, format, color, debug)
+        Ok(txs)
     }
 
     fn send_transaction(
         &mut self,
         transaction: TransactionView,
+        memo: Option<String>,
         format: OutputFormat,
         color: bool,
         debug: bool,
     ) -> Result<String, String> {
+        if let Some(staged_txs) = self.staged_txs.as_mut() {
+            let tx_hash: H256 = transaction.hash().unpack();
+            staged_txs.push(transaction);
+            return Ok(tx_hash.render(format, color));
+        }
         let transaction_view: ckb_jsonrpc_types::TransactionView = transaction.clone().into();
         if debug {
             println!(
@@ -491,10 +1957,18 @@ impl<'a> WalletSubCommand<'a> {
             );
         }
 
-        let resp = self
-            .rpc_client
-            .send_transaction(transaction.data())
-            .map_err(|err| format!("Send transaction error: {}", err))?;
+        let resp = on_failure::send_transaction(
+            self.rpc_client,
+            &transaction,
+            self.on_failure.as_ref(),
+            &self.send_targets,
+        )
+        .map_err(|err| format!("Send transaction error: {}", err))?;
+        save_tx_memo(&resp, memo)?;
+        if let Some(wait) = self.wait_for_committed {
+            let resp = wait.wait_response(self.rpc_client, &resp)?;
+            return Ok(resp.render(format, color));
+        }
         Ok(resp.render(format, color))
     }
 }
@@ -507,13 +1981,45 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
         color: bool,
         debug: bool,
     ) -> Result<String, String> {
+        if let (_, Some(m)) = matches.subcommand() {
+            self.signing_policy.allow_high_fee = m.is_present("allow-high-fee");
+            self.wait_for_committed = WaitForCommitted::from_matches(m, "wait-for-committed")?;
+            self.on_failure = OnFailure::from_matches(m)?;
+            // The input cells are resolved by and the transactions are sent to
+            // the light client, not the node
+            self.send_targets.light_client_url = light_client_url(m);
+            self.light_client = match self.send_targets.light_client_url {
+                Some(ref url) => Some(LightClientBackend::new(url)?),
+                None => None,
+            };
+            self.coin_control = CoinControl::from_matches(m)?;
+        }
         match matches.subcommand() {
             ("transfer", Some(m)) => self.transfer(m, format, color, debug),
+            ("transfer-multisig", Some(m)) => self.transfer_multisig(m, format, color, debug),
+            ("scan", Some(m)) => self.scan(m, format, color),
+            ("cellbase-report", Some(m)) => self.cellbase_report(m, format, color),
+            ("sweep", Some(m)) => self.sweep(m, format, color, debug),
+            ("consolidate", Some(m)) => self.consolidate(m, format, color, debug),
+            ("payout", Some(m)) => match m.subcommand() {
+                ("run", Some(m)) => self.payout_run(m, format, color, debug),
+                _ => Err(m.usage().to_owned()),
+            },
+            ("send-cheque", Some(m)) => self.send_cheque(m, format, color, debug),
+            ("claim-cheque", Some(m)) => self.claim_cheque(m, format, color, debug),
+            ("withdraw-cheque", Some(m)) => self.withdraw_cheque(m, format, color, debug),
+            ("cheque-cells", Some(m)) => self.cheque_cells(m, format, color),
+            ("analyze-fragmentation", Some(m)) => {
+                self.analyze_fragmentation(m, format, color, debug)
+            }
             ("get-capacity", Some(m)) => {
+                if m.is_present("addresses-file") || m.is_present("all-accounts") {
+                    return self.get_capacities(m, format, color);
+                }
                 let lock_hash_opt: Option<H256> =
                     FixedHashParser::<H256>::default().from_matches_opt(m, "lock-hash", false)?;
-                let lock_hashes = if let Some(lock_hash) = lock_hash_opt {
-                    vec![lock_hash.pack()]
+                let queries = if let Some(lock_hash) = lock_hash_opt {
+                    vec![CellQuery::Hash(LiveCellQuery::Lock(lock_hash.pack()))]
                 } else {
                     let network_type = get_network_type(self.rpc_client)?;
 
@@ -530,7 +2036,7 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
                     } else {
                         get_address(Some(network_type), m)?
                     };
-                    let mut lock_hashes = vec![Script::from(&address_payload).calc_script_hash()];
+                    let mut queries = vec![CellQuery::Lock(Script::from(&address_payload))];
                     if m.is_present("derived") {
                         let password = read_password(false, None)?;
                         let lock_arg = H160::from_slice(address_payload.args().as_ref()).unwrap();
@@ -547,46 +2053,123 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
                             .map_err(|err| err.to_string())?;
                         for (_, hash160) in key_set.external.iter().chain(key_set.change.iter()) {
                             let payload = AddressPayload::from_pubkey_hash(hash160.clone());
-                            lock_hashes.push(Script::from(&payload).calc_script_hash());
+                            queries.push(CellQuery::Lock(Script::from(&payload)));
                         }
                     }
-                    lock_hashes
+                    queries
                 };
 
-                let max_mature_number = get_max_mature_number(self.rpc_client)?;
-                let (total_capacity, immature_capacity, free_capacity, dao_capacity) = self
-                    .with_db(|db| {
-                        let mut total_capacity = 0;
-                        let mut free_capacity = 0;
-                        let mut dao_capacity = 0;
-                        let mut immature_capacity = 0;
-                        let mut terminator = |_idx: usize, info: &LiveCellInfo| {
-                            if !is_mature(info, max_mature_number) {
-                                immature_capacity += info.capacity;
-                            }
-                            if info
-                                .type_hashes
-                                .as_ref()
-                                .filter(|(code_hash, _)| code_hash == &DAO_TYPE_HASH)
-                                .is_some()
-                            {
-                                dao_capacity += info.capacity;
+                if let Some(at_block) = m.value_of("at-block") {
+                    let (number, block_hash) = resolve_block(self.rpc_client, at_block)?;
+                    let mut total_capacity = 0;
+                    let mut free_capacity = 0;
+                    let mut dao_capacity = 0;
+                    for query in &queries {
+                        let lock_hash: H256 = query.lock_hash().unwrap().unpack();
+                        for cell in cells_at_block(self.rpc_client, &lock_hash, number)? {
+                            if cell.is_dao() {
+                                dao_capacity += cell.capacity();
                             } else {
-                                free_capacity += info.capacity;
+                                free_capacity += cell.capacity();
                             }
-                            total_capacity += info.capacity;
-                            (false, false)
-                        };
-                        for lock_hash in lock_hashes {
-                            let _ = db.get_live_cells_by_lock(lock_hash, None, &mut terminator);
+                            total_capacity += cell.capacity();
                         }
-                        (
-                            total_capacity,
-                            immature_capacity,
-                            free_capacity,
-                            dao_capacity,
-                        )
-                    })?;
+                    }
+                    let mut resp = serde_json::json!({
+                        "block_number": number,
+                        "block_hash": block_hash,
+                        "total": format!("{:#}", HumanCapacity::from(total_capacity)),
+                    });
+                    if dao_capacity > 0 {
+                        resp["dao"] =
+                            serde_json::json!(format!("{:#}", HumanCapacity::from(dao_capacity)));
+                        resp["free"] =
+                            serde_json::json!(format!("{:#}", HumanCapacity::from(free_capacity)));
+                    }
+                    return Ok(resp.render(format, color));
+                }
+
+                let read_through_enabled = m.is_present("read-through");
+                let mut index_backend = self.index_backend(m)?;
+                let mut infos = Vec::new();
+                for query in &queries {
+                    infos.extend(
+                        index_backend.get_live_cells(query, None, &mut |_, _| (false, true))?,
+                    );
+                }
+                let local_number = index_backend.last_number()?.unwrap_or(0);
+                let tip_number = if read_through_enabled {
+                    self.rpc_client.get_tip_block_number()?
+                } else {
+                    local_number
+                };
+                let mut read_through_results = Vec::new();
+                if local_number < tip_number {
+                    for query in &queries {
+                        let lock_hash: H256 = query.lock_hash().unwrap().unpack();
+                        let out_points = infos
+                            .iter()
+                            .filter(|info| info.lock_hash == lock_hash)
+                            .map(LiveCellInfo::out_point)
+                            .collect::<Vec<_>>();
+                        read_through_results.push(read_through(
+                            self.rpc_client,
+                            &lock_hash,
+                            local_number,
+                            &out_points,
+                        )?);
+                    }
+                }
+
+                let max_mature_number = get_max_mature_number(self.rpc_client)?;
+                let mut total_capacity = 0;
+                let mut free_capacity = 0;
+                let mut dao_capacity = 0;
+                let mut immature_capacity = 0;
+                let mut spent_capacity = 0;
+                for info in &infos {
+                    if read_through_results
+                        .iter()
+                        .any(|result| result.spent.contains(&info.out_point()))
+                    {
+                        spent_capacity += info.capacity;
+                        continue;
+                    }
+                    if !is_mature(info, max_mature_number) {
+                        immature_capacity += info.capacity;
+                    }
+                    if info
+                        .type_hashes
+                        .as_ref()
+                        .filter(|(code_hash, _)| code_hash == &DAO_TYPE_HASH)
+                        .is_some()
+                    {
+                        dao_capacity += info.capacity;
+                    } else {
+                        free_capacity += info.capacity;
+                    }
+                    total_capacity += info.capacity;
+                }
+                let mut received_capacity = 0;
+                for cell in read_through_results
+                    .iter()
+                    .flat_map(|result| result.new_cells.iter())
+                {
+                    let capacity = cell.cell_output.capacity.0;
+                    if cell
+                        .cell_output
+                        .type_
+                        .as_ref()
+                        .filter(|script| script.code_hash == DAO_TYPE_HASH)
+                        .is_some()
+                    {
+                        dao_capacity += capacity;
+                    } else {
+                        free_capacity += capacity;
+                    }
+                    total_capacity += capacity;
+                    received_capacity += capacity;
+                }
 
                 let mut resp = serde_json::json!({
                     "total": format!("{:#}", HumanCapacity::from(total_capacity))
@@ -601,6 +2184,15 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
                     resp["free"] =
                         serde_json::json!(format!("{:#}", HumanCapacity::from(free_capacity)));
                 }
+                if !read_through_results.is_empty() {
+                    // Maturity of provisional cells is not checked
+                    resp["provisional"] = serde_json::json!({
+                        "from_block": local_number + 1,
+                        "to_block": tip_number,
+                        "received": format!("{:#}", HumanCapacity::from(received_capacity)),
+                        "spent": format!("{:#}", HumanCapacity::from(spent_capacity)),
+                    });
+                }
                 Ok(resp.render(format, color))
             }
             ("get-live-cells", Some(m)) => {
@@ -617,84 +2209,168 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
                     FromStrParser::<u64>::default().from_matches_opt(m, "to", false)?;
 
                 let network_type = get_network_type(self.rpc_client)?;
-                let lock_hash_opt = if lock_hash_opt.is_none() {
-                    let address_opt: Option<Address> = AddressParser::default()
-                        .set_network_opt(Some(network_type))
-                        .from_matches_opt(m, "address", false)?;
-                    address_opt
-                        .map(|address| Script::from(address.payload()).calc_script_hash().unpack())
+                let address_opt: Option<Address> = AddressParser::default()
+                    .set_network_opt(Some(network_type))
+                    .from_matches_opt(m, "address", false)?;
+                let query = if let Some(lock_hash) = lock_hash_opt {
+                    CellQuery::Hash(LiveCellQuery::Lock(lock_hash.pack()))
+                } else if let Some(address) = address_opt {
+                    CellQuery::Lock(Script::from(address.payload()))
+                } else if let Some(type_hash) = type_hash_opt {
+                    CellQuery::Hash(LiveCellQuery::Type(type_hash.pack()))
+                } else if let Some(code_hash) = code_hash_opt {
+                    CellQuery::Hash(LiveCellQuery::Code(code_hash.pack()))
                 } else {
-                    lock_hash_opt
-                };
-
-                if lock_hash_opt.is_none() && type_hash_opt.is_none() && code_hash_opt.is_none() {
                     return Err(
                         "lock-hash or type-hash or code-hash or address is required".to_owned()
                     );
-                }
+                };
 
                 let to_number = to_number_opt.unwrap_or(std::u64::MAX);
-                let (infos, total_count, total_capacity, current_count, current_capacity) = self
-                    .with_db(|db| {
-                        let mut total_count: u32 = 0;
-                        let mut total_capacity: u64 = 0;
-                        let mut current_count: u32 = 0;
-                        let mut current_capacity: u64 = 0;
-                        let terminator = |idx, info: &LiveCellInfo| {
-                            let stop = idx >= limit || info.number > to_number;
-                            let push_info = !stop;
-                            total_count += 1;
-                            total_capacity += info.capacity;
-                            if push_info {
-                                current_count += 1;
-                                current_capacity += info.capacity;
-                            }
-                            (false, push_info)
-                        };
-                        let infos = if let Some(lock_hash) = lock_hash_opt {
-                            db.get_live_cells_by_lock(
-                                lock_hash.clone().pack(),
-                                from_number_opt,
-                                terminator,
-                            )
-                        } else if let Some(type_hash) = type_hash_opt {
-                            db.get_live_cells_by_type(
-                                type_hash.clone().pack(),
-                                from_number_opt,
-                                terminator,
-                            )
-                        } else {
-                            db.get_live_cells_by_code(
-                                code_hash_opt.clone().unwrap().pack(),
-                                from_number_opt,
-                                terminator,
-                            )
-                        };
-                        (
-                            infos,
-                            total_count,
-                            total_capacity,
-                            current_count,
-                            current_capacity,
-                        )
-                    })?;
+                if let Some(at_block) = m.value_of("at-block") {
+                    let lock_hash: H256 = query
+                        .lock_hash()
+                        .ok_or_else(|| {
+                            "--at-block only works with lock-hash or address".to_owned()
+                        })?
+                        .unpack();
+                    let (number, block_hash) = resolve_block(self.rpc_client, at_block)?;
+                    let from_number = from_number_opt.unwrap_or(0);
+                    let cells = cells_at_block(self.rpc_client, &lock_hash, number)?
+                        .into_iter()
+                        .filter(|cell| {
+                            cell.created_by.block_number >= from_number
+                                && cell.created_by.block_number <= to_number
+                        })
+                        .collect::<Vec<_>>();
+                    let total_capacity: u64 = cells.iter().map(|cell| cell.capacity()).sum();
+                    let live_cells = cells
+                        .iter()
+                        .take(limit)
+                        .map(|cell| {
+                            serde_json::json!({
+                                "tx_hash": cell.created_by.tx_hash,
+                                "tx_index": cell.created_by.index,
+                                "number": cell.created_by.block_number,
+                                "capacity": format!("{:#}", HumanCapacity::from(cell.capacity())),
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    let current_capacity: u64 =
+                        cells.iter().take(limit).map(|cell| cell.capacity()).sum();
+                    let resp = serde_json::json!({
+                        "block_number": number,
+                        "block_hash": block_hash,
+                        "total_capacity": format!("{:#}", HumanCapacity::from(total_capacity)),
+                        "current_capacity": format!("{:#}", HumanCapacity::from(current_capacity)),
+                        "total_count": cells.len(),
+                        "current_count": live_cells.len(),
+                        "live_cells": live_cells,
+                    });
+                    return Ok(render_list(&resp, "live_cells", format, color));
+                }
+
+                let read_through_enabled = m.is_present("read-through");
+                let read_through_lock_hash_opt = query.lock_hash();
+                if read_through_enabled && read_through_lock_hash_opt.is_none() {
+                    return Err("--read-through only works with lock-hash or address".to_owned());
+                }
+
+                let mut index_backend = self.index_backend(m)?;
+                let mut total_count: u32 = 0;
+                let mut total_capacity: u64 = 0;
+                let mut current_count: u32 = 0;
+                let mut current_capacity: u64 = 0;
+                let mut terminator = |idx, info: &LiveCellInfo| {
+                    let stop = idx >= limit || info.number > to_number;
+                    let push_info = !stop;
+                    total_count += 1;
+                    total_capacity += info.capacity;
+                    if push_info {
+                        current_count += 1;
+                        current_capacity += info.capacity;
+                    }
+                    (false, push_info)
+                };
+                let infos =
+                    index_backend.get_live_cells(&query, from_number_opt, &mut terminator)?;
+
+                let mut read_through_result = None;
+                if read_through_enabled {
+                    let lock_hash: H256 = read_through_lock_hash_opt.unwrap().unpack();
+                    let local_out_points = index_backend
+                        .get_live_cells(&query, None, &mut |_, _| (false, true))?
+                        .iter()
+                        .map(LiveCellInfo::out_point)
+                        .collect::<Vec<_>>();
+                    let local_number = index_backend.last_number()?.unwrap_or(0);
+                    let tip_number = self.rpc_client.get_tip_block_number()?;
+                    if local_number < tip_number {
+                        let result = read_through(
+                            self.rpc_client,
+                            &lock_hash,
+                            local_number,
+                            &local_out_points,
+                        )?;
+                        read_through_result = Some((result, tip_number));
+                    }
+                }
+
                 let max_mature_number = get_max_mature_number(self.rpc_client)?;
-                let resp = serde_json::json!({
-                    "live_cells": infos.into_iter().map(|info| {
+                let mut live_cells = infos
+                    .into_iter()
+                    .map(|info| {
                         let mut value = serde_json::to_value(&info).unwrap();
                         let mature = serde_json::Value::Bool(is_mature(&info, max_mature_number));
-                        let capacity_string = serde_json::Value::String(format!("{:#}", HumanCapacity::from(info.capacity)));
+                        let capacity_string = serde_json::Value::String(format!(
+                            "{:#}",
+                            HumanCapacity::from(info.capacity)
+                        ));
                         let map = value.as_object_mut().unwrap();
                         map.insert("capacity".to_string(), capacity_string);
                         map.insert("mature".to_string(), mature);
+                        if let Some((result, _)) = read_through_result.as_ref() {
+                            if result.spent.contains(&info.out_point()) {
+                                map.insert(
+                                    "provisional_spent".to_string(),
+                                    serde_json::Value::Bool(true),
+                                );
+                            }
+                        }
                         value
-                    }).collect::<Vec<_>>(),
+                    })
+                    .collect::<Vec<_>>();
+                let mut resp = serde_json::json!({
                     "total_capacity": format!("{:#}", HumanCapacity::from(total_capacity)),
                     "current_capacity": format!("{:#}", HumanCapacity::from(current_capacity)),
                     "total_count": total_count,
                     "current_count": current_count,
                 });
-                Ok(resp.render(format, color))
+                if let Some((result, tip_number)) = read_through_result {
+                    for cell in result
+                        .new_cells
+                        .iter()
+                        .filter(|cell| cell.created_by.block_number <= to_number)
+                        .take(limit.saturating_sub(live_cells.len()))
+                    {
+                        live_cells.push(serde_json::json!({
+                            "tx_hash": cell.created_by.tx_hash,
+                            "tx_index": cell.created_by.index,
+                            "number": cell.created_by.block_number,
+                            "capacity": format!("{:#}", HumanCapacity::from(cell.cell_output.capacity.0)),
+                            "provisional": true,
+                        }));
+                    }
+                    resp["provisional"] = serde_json::json!({
+                        "from_block": result.local_number + 1,
+                        "to_block": tip_number,
+                        "received_count": result.new_cells.len(),
+                        "received": format!("{:#}", HumanCapacity::from(result.new_capacity())),
+                        "spent_count": result.spent.len(),
+                    });
+                }
+                resp["live_cells"] = serde_json::Value::Array(live_cells);
+                Ok(render_list(&resp, "live_cells", format, color))
             }
             ("top-capacity", Some(m)) => {
                 let n: usize = m
@@ -716,6 +2392,59 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
                 })?;
                 Ok(resp.render(format, color))
             }
+            ("tx-history", Some(m)) => {
+                let network_type = get_network_type(self.rpc_client)?;
+                let address: Address = AddressParser::default()
+                    .set_network(network_type)
+                    .from_matches(m, "address")?;
+                let limit: usize = FromStrParser::<usize>::default().from_matches(m, "limit")?;
+                let before_opt: Option<u64> =
+                    FromStrParser::<u64>::default().from_matches_opt(m, "before", false)?;
+                if !self.index_controller.record_txs() {
+                    return Err("The transactions of each lock are not recorded, please start ckb-cli with --index-tx-history (and run `index rebuild` to include the blocks indexed before)".to_string());
+                }
+                let lock_hash: H256 = Script::from(address.payload()).calc_script_hash().unpack();
+                let (history_from, txs) = self.with_db(|db| {
+                    (
+                        db.tx_history_from(),
+                        db.get_recent_txs_by_lock(lock_hash.pack(), before_opt, limit),
+                    )
+                })?;
+                match history_from {
+                    None => {
+                        return Err("The transactions of each lock are not recorded for the last indexed block, please run `index rebuild` with --index-tx-history".to_string());
+                    }
+                    Some(number) if number > 0 => {
+                        eprintln!(
+                            "[WARNING] The transactions before block {} are not recorded (indexed without --index-tx-history), run `index rebuild` to include them",
+                            number
+                        );
+                    }
+                    Some(_) => {}
+                }
+                let txs = txs.map_err(|err| err.to_string())?;
+                let records = tx_records(self.rpc_client, &lock_hash, txs)?;
+                let memos = load_memos();
+                let resp = records
+                    .iter()
+                    .map(|record| {
+                        let delta = record.capacity_delta();
+                        let sign = if delta < 0 { "-" } else { "+" };
+                        let mut item = serde_json::json!({
+                            "tx_hash": format!("{:#x}", record.tx_hash),
+                            "block_number": record.block_number,
+                            "timestamp": Utc.timestamp_millis(record.timestamp as i64).to_rfc3339(),
+                            "direction": record.direction(),
+                            "capacity_delta": format!("{}{:#}", sign, HumanCapacity::from(delta.abs() as u64)),
+                        });
+                        if let Some(memo) = memos.get(&record.tx_hash) {
+                            item["memo"] = serde_json::json!(memo);
+                        }
+                        item
+                    })
+                    .collect::<Vec<_>>();
+                Ok(resp.render(format, color))
+            }
             ("db-metrics", _) => {
                 let metrcis = self.with_db(|db| db.get_metrics(None))?;
                 let resp = serde_json::to_value(metrcis).map_err(|err| err.to_string())?;
@@ -726,6 +2455,99 @@ impl<'a> CliSubCommand for WalletSubCommand<'a> {
     }
 }
 
+// Collect mature live cells (without type script and data) of `lock_scripts`
+// from index backend
+// The cell merged by a consolidation transaction (its only output)
+fn merged_cell(tx: &TransactionView) -> (OutPoint, (CellOutput, Transaction)) {
+    let output = tx.outputs().get(0).unwrap();
+    let inner = Transaction::from(ckb_jsonrpc_types::Transaction::from(tx.data()));
+    (OutPoint::new(tx.hash(), 0), (output, inner))
+}
+
+// The fee of the payment spending the merged cells, by the size of it
+// (unsigned, with the witness placeholders)
+fn estimate_payment_fee(
+    builder: &TransactionBuilder,
+    merged_cells: &[(OutPoint, (CellOutput, Transaction))],
+    genesis_info: &GenesisInfo,
+    fee_rate: u64,
+) -> Result<u64, String> {
+    let cells = merged_cells
+        .iter()
+        .map(|(out_point, (output, _))| {
+            CollectedCell::new(out_point.clone(), output.capacity().unpack())
+        })
+        .collect::<Vec<_>>();
+    let mut collector =
+        |_required: u64| -> Result<Vec<CollectedCell>, String> { Ok(cells.clone()) };
+    let mut get_live_cell = |out_point: OutPoint, _with_data: bool| {
+        merged_cells
+            .iter()
+            .find(|(merged, _)| merged == &out_point)
+            .map(|(_, cell)| cell.clone())
+            .ok_or_else(|| "The input is not a merged cell".to_string())
+    };
+    let mut builder = builder.clone();
+    builder.fee_policy(FeePolicy::Fixed(0));
+    let helper = builder.build_unsigned(&mut collector, &mut get_live_cell, genesis_info)?;
+    let tx_size = estimate_tx_size(&helper, &mut get_live_cell)?;
+    Ok(calculate_fee(fee_rate, tx_size))
+}
+
+fn index_cell_collector(
+    mut index_backend: Box<dyn IndexBackend>,
+    max_mature_number: u64,
+    lock_scripts: Vec<Script>,
+    rendered_from_address: String,
+    coin_control: CoinControl,
+) -> impl FnMut(u64) -> Result<Vec<CollectedCell>, String> {
+    move |required: u64| {
+        let is_spendable = |info: &LiveCellInfo| {
+            info.type_hashes.is_none() && info.data_bytes == 0 && is_mature(info, max_mature_number)
+        };
+        let mut from_capacity = 0;
+        let mut infos: Vec<LiveCellInfo> = Default::default();
+        let mut terminator = |_, info: &LiveCellInfo| {
+            if from_capacity >= required {
+                (true, false)
+            } else if is_spendable(info) && coin_control.allows(&info.out_point()) {
+                from_capacity += info.capacity;
+                infos.push(info.clone());
+                (from_capacity >= required, false)
+            } else {
+                (false, false)
+            }
+        };
+        for lock_script in &lock_scripts {
+            index_backend.get_live_cells(
+                &CellQuery::Lock(lock_script.clone()),
+                None,
+                &mut terminator,
+            )?;
+        }
+        if required > from_capacity {
+            let mut candidates = Vec::new();
+            for lock_script in &lock_scripts {
+                candidates.extend(index_backend.get_live_cells(
+                    &CellQuery::Lock(lock_script.clone()),
+                    None,
+                    &mut |_, info| (false, is_spendable(info)),
+                )?);
+            }
+            return Err(coin_control.not_enough_error(
+                &rendered_from_address,
+                required,
+                &infos,
+                &candidates,
+            ));
+        }
+        Ok(infos
+            .iter()
+            .map(|info| CollectedCell::new(info.out_point(), info.capacity))
+            .collect())
+    }
+}
+
 fn get_keystore_signer<K>(key: K, path_map: HashMap<H160, DerivationPath>) -> impl SignerFnTrait
 where
     K: AbstractMasterPrivKey + Clone,
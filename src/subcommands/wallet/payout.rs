@@ -0,0 +1,629 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ckb_hash::blake2b_256;
+use ckb_jsonrpc_types as json_types;
+use ckb_sdk::{Address, HttpRpcClient, HumanCapacity};
+use ckb_types::{core::TransactionView, packed, prelude::*, H256};
+use clap::ArgMatches;
+use serde_derive::{Deserialize, Serialize};
+
+use super::recipients::read_recipients_file;
+use super::WalletSubCommand;
+use crate::utils::{
+    arg_parser::{ArgParser, FilePathParser},
+    interrupt::{check_interrupted, interruptible},
+    on_failure,
+    other::{get_network_type, sync_to_tip},
+    output::write_file_atomic,
+    printer::{OutputFormat, Printable},
+    tx_record::save_tx_memo,
+    tx_status::WaitForCommitted,
+};
+
+fn default_fee_rate() -> u64 {
+    1000
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+fn default_max_inputs() -> usize {
+    256
+}
+
+fn default_confirmations() -> u64 {
+    1
+}
+
+/// The payout manifest (TOML), the relative paths are relative to the
+/// manifest file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PayoutManifest {
+    /// Keystore lock-arg or ledger account id
+    pub from_account: Option<String>,
+    pub privkey_path: Option<PathBuf>,
+    /// Required by a ledger account
+    pub derive_change_address: Option<String>,
+    /// CSV file of `address,capacity` (unit: CKB)
+    pub recipients: PathBuf,
+    /// Shannons per KB
+    #[serde(default = "default_fee_rate")]
+    pub fee_rate: u64,
+    /// The max number of recipients paid by one transaction
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    #[serde(default = "default_max_inputs")]
+    pub max_inputs: usize,
+    /// Wait for each batch to be confirmed by this many blocks before the next
+    #[serde(default = "default_confirmations")]
+    pub confirmations: u64,
+    pub memo: Option<String>,
+    pub index_backend: Option<String>,
+    pub indexer_url: Option<String>,
+    pub light_client_url: Option<String>,
+    /// Default: `<manifest>.state.json`
+    pub state_file: Option<PathBuf>,
+}
+
+impl PayoutManifest {
+    pub fn load(path: &Path) -> Result<PayoutManifest, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Read {} failed: {}", path.display(), err))?;
+        let mut manifest: PayoutManifest = toml::from_str(&content)
+            .map_err(|err| format!("Invalid manifest {}: {}", path.display(), err))?;
+        if manifest.from_account.is_some() == manifest.privkey_path.is_some() {
+            return Err(
+                "The manifest needs exactly one of from_account and privkey_path".to_string(),
+            );
+        }
+        if manifest.batch_size == 0 || manifest.max_inputs == 0 {
+            return Err("batch_size and max_inputs must be greater than 0".to_string());
+        }
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        manifest.recipients = base_dir.join(&manifest.recipients);
+        manifest.privkey_path = manifest.privkey_path.map(|path| base_dir.join(path));
+        let mut default_state_file = path.as_os_str().to_owned();
+        default_state_file.push(".state.json");
+        manifest.state_file = Some(
+            manifest
+                .state_file
+                .map(|path| base_dir.join(path))
+                .unwrap_or_else(|| PathBuf::from(default_state_file)),
+        );
+        Ok(manifest)
+    }
+
+    // The arguments of `wallet transfer` paying one batch
+    fn transfer_args(&self, recipients: &[(Address, u64)]) -> Vec<String> {
+        let mut args = vec!["wallet".to_string(), "transfer".to_string()];
+        if let Some(ref from_account) = self.from_account {
+            args.extend(vec!["--from-account".to_string(), from_account.clone()]);
+        }
+        if let Some(ref privkey_path) = self.privkey_path {
+            args.extend(vec![
+                "--privkey-path".to_string(),
+                privkey_path.to_string_lossy().into_owned(),
+            ]);
+        }
+        if let Some(ref address) = self.derive_change_address {
+            args.extend(vec!["--derive-change-address".to_string(), address.clone()]);
+        }
+        for (name, value) in &[
+            ("--index-backend", &self.index_backend),
+            ("--indexer-url", &self.indexer_url),
+            ("--light-client-url", &self.light_client_url),
+        ] {
+            if let Some(value) = value {
+                args.extend(vec![name.to_string(), value.clone()]);
+            }
+        }
+        args.extend(vec![
+            "--fee-rate".to_string(),
+            self.fee_rate.to_string(),
+            "--max-inputs".to_string(),
+            self.max_inputs.to_string(),
+            // The batches are already checked against each other
+            "--allow-duplicate".to_string(),
+        ]);
+        for (address, capacity) in recipients {
+            args.push("--to".to_string());
+            args.push(format!("{}:{}", address, HumanCapacity::from(*capacity)));
+        }
+        args
+    }
+}
+
+/// The ranges of the recipients paid by each batch
+pub fn plan_batches(recipients: usize, batch_size: usize) -> Vec<(usize, usize)> {
+    (0..recipients)
+        .step_by(batch_size)
+        .map(|start| (start, recipients.min(start + batch_size)))
+        .collect()
+}
+
+/// The next step of a signed batch when resumed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignedStep {
+    /// Send the transactions the node does not have yet
+    Send,
+    /// The inputs are spent elsewhere (eg: by another transfer) before any
+    /// transaction of the batch is sent, build the batch again
+    Rebuild,
+}
+
+/// `sent` tells which transactions of the batch the node has, `inputs_live`
+/// whether the cells the others spend from outside the batch are still live
+pub fn signed_step(sent: &[bool], inputs_live: bool) -> Result<SignedStep, String> {
+    if inputs_live || sent.iter().all(|sent| *sent) {
+        Ok(SignedStep::Send)
+    } else if sent.iter().any(|sent| *sent) {
+        Err("Part of the batch is sent but the inputs of the rest are spent elsewhere, please check the transactions of the state file".to_string())
+    } else {
+        Ok(SignedStep::Rebuild)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    /// Not built yet
+    Pending,
+    /// Built and signed, maybe sent
+    Signed,
+    Sent,
+    Committed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchState {
+    pub start: usize,
+    pub end: usize,
+    pub status: BatchStatus,
+    /// The transactions in sending order, the payment is the last one (the
+    /// others consolidate the inputs)
+    pub transactions: Vec<json_types::Transaction>,
+    pub block_number: Option<u64>,
+}
+
+impl BatchState {
+    fn transaction_views(&self) -> Vec<TransactionView> {
+        self.transactions
+            .iter()
+            .map(|tx| packed::Transaction::from(tx.clone()).into_view())
+            .collect()
+    }
+
+    // Back to pending, the signed transactions can not be sent any more
+    fn reset(&mut self) {
+        self.status = BatchStatus::Pending;
+        self.transactions.clear();
+        self.block_number = None;
+    }
+}
+
+/// The progress of a payout, saved after every step, so a rerun resumes it
+/// without building (and paying) a batch twice: a signed batch is sent again
+/// as is.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayoutState {
+    /// Resuming with changed recipients is refused
+    pub recipients_hash: H256,
+    pub batches: Vec<BatchState>,
+}
+
+impl PayoutState {
+    fn load_or_new(
+        path: &Path,
+        recipients_hash: H256,
+        batches: Vec<(usize, usize)>,
+    ) -> Result<PayoutState, String> {
+        if path.exists() {
+            let content = fs::read(path).map_err(|err| err.to_string())?;
+            let state: PayoutState = serde_json::from_slice(&content)
+                .map_err(|err| format!("Invalid state file {}: {}", path.display(), err))?;
+            if state.recipients_hash != recipients_hash {
+                return Err(format!(
+                    "The recipients changed since the payout started, remove the state file {} (after checking its transactions) to start a new payout",
+                    path.display()
+                ));
+            }
+            return Ok(state);
+        }
+        Ok(PayoutState {
+            recipients_hash,
+            batches: batches
+                .into_iter()
+                .map(|(start, end)| BatchState {
+                    start,
+                    end,
+                    status: BatchStatus::Pending,
+                    transactions: Vec::new(),
+                    block_number: None,
+                })
+                .collect(),
+        })
+    }
+
+    fn save(&self, path: &Path) -> Result<(), String> {
+        // Replaced atomically, an interrupted save leaves the old state
+        let content = serde_json::to_string_pretty(self).map_err(|err| err.to_string())?;
+        write_file_atomic(path, content.as_bytes())
+            .map_err(|err| format!("Save payout state error: {}", err))
+    }
+}
+
+// The fee of a committed transaction: the input cells are read from their
+// transactions
+fn transaction_fee(rpc_client: &mut HttpRpcClient, tx: &TransactionView) -> Result<u64, String> {
+    let mut inputs_capacity = 0;
+    for input in tx.inputs() {
+        let out_point = input.previous_output();
+        let tx_hash: H256 = out_point.tx_hash().unpack();
+        let index: u32 = out_point.index().unpack();
+        let prev_tx = rpc_client
+            .get_transaction(tx_hash.clone())?
+            .ok_or_else(|| format!("Transaction {:#x} not found", tx_hash))?;
+        let output = packed::Transaction::from(prev_tx.transaction.inner)
+            .raw()
+            .outputs()
+            .get(index as usize)
+            .ok_or_else(|| format!("Invalid input {:#x}-{}", tx_hash, index))?;
+        let capacity: u64 = output.capacity().unpack();
+        inputs_capacity += capacity;
+    }
+    let outputs_capacity: u64 = tx
+        .outputs()
+        .into_iter()
+        .map(|output| {
+            let capacity: u64 = output.capacity().unpack();
+            capacity
+        })
+        .sum();
+    Ok(inputs_capacity - outputs_capacity)
+}
+
+impl<'a> WalletSubCommand<'a> {
+    /// Pay the recipients of the manifest batch by batch: build and sign a
+    /// batch (saved to the state file), send it, wait for the confirmations
+    /// and the index, then the next one. Finally check every recipient is
+    /// paid by the committed transactions.
+    pub fn payout_run(
+        &mut self,
+        m: &ArgMatches,
+        format: OutputFormat,
+        color: bool,
+        debug: bool,
+    ) -> Result<String, String> {
+        let manifest_path: PathBuf = FilePathParser::new(true).from_matches(m, "manifest")?;
+        let manifest = PayoutManifest::load(&manifest_path)?;
+        let state_path = manifest.state_file.clone().expect("set by load");
+        let network_type = get_network_type(self.rpc_client)?;
+        let recipients = read_recipients_file(&manifest.recipients, network_type)?;
+        if recipients.is_empty() {
+            return Err("No recipient in the manifest".to_string());
+        }
+        let recipients_content = fs::read(&manifest.recipients).map_err(|err| err.to_string())?;
+        let recipients_hash = H256::from(blake2b_256(&recipients_content));
+        let mut state = PayoutState::load_or_new(
+            &state_path,
+            recipients_hash,
+            plan_batches(recipients.len(), manifest.batch_size),
+        )?;
+        state.save(&state_path)?;
+
+        let total: u64 = recipients.iter().map(|(_, capacity)| capacity).sum();
+        let done = state
+            .batches
+            .iter()
+            .filter(|batch| batch.status == BatchStatus::Committed)
+            .count();
+        eprintln!(
+            "Payout: {} recipients, {} CKB, {} batches ({} done), fee rate: {} shannons/KB, state: {}",
+            recipients.len(),
+            HumanCapacity::from(total),
+            state.batches.len(),
+            done,
+            manifest.fee_rate,
+            state_path.display()
+        );
+
+        let wait = WaitForCommitted {
+            confirmations: manifest.confirmations.max(1),
+            timeout: None,
+        };
+        let is_local_index = manifest
+            .index_backend
+            .as_ref()
+            .map(|backend| backend == "local")
+            .unwrap_or(true);
+        let batches_count = state.batches.len();
+        let memo_prefix = manifest
+            .memo
+            .clone()
+            .unwrap_or_else(|| "payout".to_string());
+        // Ctrl-C stops after the current step, the state file has the signed
+        // and sent batches
+        let _guard = interruptible();
+        let resume_hint = format!(
+            "resume by: ckb-cli wallet payout run --manifest {}",
+            manifest_path.display()
+        );
+        for batch_idx in 0..batches_count {
+            let (start, end) = (state.batches[batch_idx].start, state.batches[batch_idx].end);
+            let batch_name = format!("batch {}/{}", batch_idx + 1, batches_count);
+            let mut rebuilt = false;
+            loop {
+                check_interrupted(&resume_hint)?;
+                match state.batches[batch_idx].status {
+                    BatchStatus::Pending => {
+                        let args = manifest.transfer_args(&recipients[start..end]);
+                        let matches = Self::subcommand()
+                            .get_matches_from_safe(args)
+                            .map_err(|err| format!("Invalid manifest: {}", err.message))?;
+                        let (_, transfer_m) = matches.subcommand();
+                        self.wait_for_committed = None;
+                        self.staged_txs = Some(Vec::new());
+                        let result =
+                            self.transfer(transfer_m.expect("transfer"), format, color, debug);
+                        let staged = self.staged_txs.take().unwrap_or_default();
+                        result.map_err(|err| format!("Build {} failed: {}", batch_name, err))?;
+                        let batch = &mut state.batches[batch_idx];
+                        batch.transactions = staged
+                            .into_iter()
+                            .map(|tx| json_types::Transaction::from(tx.data()))
+                            .collect();
+                        batch.status = BatchStatus::Signed;
+                        state.save(&state_path)?;
+                    }
+                    BatchStatus::Signed => {
+                        let txs = state.batches[batch_idx].transaction_views();
+                        let (sent, inputs_live) = self.batch_progress(&txs)?;
+                        if signed_step(&sent, inputs_live)
+                            .map_err(|err| format!("{}: {}", batch_name, err))?
+                            == SignedStep::Rebuild
+                        {
+                            if rebuilt {
+                                return Err(format!(
+                                    "The inputs of {} are spent elsewhere again, {}",
+                                    batch_name, resume_hint
+                                ));
+                            }
+                            eprintln!(
+                                "The inputs of {} are spent elsewhere before it is sent, build it again",
+                                batch_name
+                            );
+                            state.batches[batch_idx].reset();
+                            state.save(&state_path)?;
+                            rebuilt = true;
+                            continue;
+                        }
+                        for (tx_idx, tx) in txs.iter().enumerate() {
+                            // Sent before the interruption
+                            if sent[tx_idx] {
+                                continue;
+                            }
+                            let tx_hash = on_failure::send_transaction(
+                                self.rpc_client,
+                                tx,
+                                self.on_failure.as_ref(),
+                                &self.send_targets,
+                            )
+                            .map_err(|err| {
+                                format!("Send {} transaction {} error: {}", batch_name, tx_idx, err)
+                            })?;
+                            let memo = if tx_idx + 1 == txs.len() {
+                                format!("{} {}", memo_prefix, batch_name)
+                            } else {
+                                format!("{} {} consolidate {}", memo_prefix, batch_name, tx_idx + 1)
+                            };
+                            save_tx_memo(&tx_hash, Some(memo))?;
+                        }
+                        state.batches[batch_idx].status = BatchStatus::Sent;
+                        state.save(&state_path)?;
+                        let payment_hash: H256 = txs.last().expect("payment").hash().unpack();
+                        eprintln!("{} sent: {:#x}", batch_name, payment_hash);
+                    }
+                    BatchStatus::Sent => {
+                        let txs = state.batches[batch_idx].transaction_views();
+                        let payment_hash: H256 = txs.last().expect("payment").hash().unpack();
+                        // Dropped by the node (eg: an input is spent by another
+                        // transaction), resumed as a signed batch
+                        if self
+                            .rpc_client
+                            .get_transaction(payment_hash.clone())?
+                            .is_none()
+                        {
+                            eprintln!(
+                                "{} {:#x} is not found on the node, check it again",
+                                batch_name, payment_hash
+                            );
+                            state.batches[batch_idx].status = BatchStatus::Signed;
+                            state.save(&state_path)?;
+                            continue;
+                        }
+                        let (block_number, _) = wait.wait(self.rpc_client, &payment_hash)?;
+                        state.batches[batch_idx].status = BatchStatus::Committed;
+                        state.batches[batch_idx].block_number = Some(block_number);
+                        state.save(&state_path)?;
+                        // The next batch selects the cells from the index
+                        if is_local_index && batch_idx + 1 < batches_count {
+                            sync_to_tip(&self.index_controller)?;
+                        }
+                    }
+                    BatchStatus::Committed => break,
+                }
+            }
+        }
+
+        self.payout_report(&state, &recipients, format, color)
+    }
+
+    // Which transactions of the batch the node has, and whether the cells the
+    // others spend from outside the batch are still live
+    fn batch_progress(&mut self, txs: &[TransactionView]) -> Result<(Vec<bool>, bool), String> {
+        let tx_hashes = txs.iter().map(|tx| tx.hash()).collect::<Vec<_>>();
+        let mut sent = Vec::with_capacity(txs.len());
+        let mut inputs_live = true;
+        for tx in txs {
+            let tx_hash: H256 = tx.hash().unpack();
+            let is_sent = self.rpc_client.get_transaction(tx_hash)?.is_some();
+            sent.push(is_sent);
+            if is_sent {
+                continue;
+            }
+            for input in tx.inputs() {
+                let out_point = input.previous_output();
+                if tx_hashes.contains(&out_point.tx_hash()) {
+                    continue;
+                }
+                if self.rpc_client.get_live_cell(out_point, false)?.status != "live" {
+                    inputs_live = false;
+                }
+            }
+        }
+        Ok((sent, inputs_live))
+    }
+
+    // Reconcile the committed payments with the recipients
+    fn payout_report(
+        &mut self,
+        state: &PayoutState,
+        recipients: &[(Address, u64)],
+        format: OutputFormat,
+        color: bool,
+    ) -> Result<String, String> {
+        let mut batches = Vec::new();
+        let mut unpaid = Vec::new();
+        let mut total_fee = 0;
+        for (batch_idx, batch) in state.batches.iter().enumerate() {
+            let txs = batch.transaction_views();
+            let mut batch_fee = 0;
+            for tx in &txs {
+                batch_fee += transaction_fee(self.rpc_client, tx)?;
+            }
+            total_fee += batch_fee;
+            let payment = txs.last().expect("payment");
+            let mut outputs = payment
+                .outputs()
+                .into_iter()
+                .map(|output| {
+                    let capacity: u64 = output.capacity().unpack();
+                    (output.lock(), capacity)
+                })
+                .collect::<Vec<_>>();
+            for (address, capacity) in &recipients[batch.start..batch.end] {
+                let lock = packed::Script::from(address.payload());
+                match outputs.iter().position(|(output_lock, output_capacity)| {
+                    output_lock.as_slice() == lock.as_slice() && output_capacity == capacity
+                }) {
+                    Some(pos) => {
+                        outputs.remove(pos);
+                    }
+                    None => unpaid.push(serde_json::json!({
+                        "address": address.to_string(),
+                        "capacity": format!("{:#}", HumanCapacity::from(*capacity)),
+                        "batch": batch_idx + 1,
+                    })),
+                }
+            }
+            let amount: u64 = recipients[batch.start..batch.end]
+                .iter()
+                .map(|(_, capacity)| capacity)
+                .sum();
+            let payment_hash: H256 = payment.hash().unpack();
+            batches.push(serde_json::json!({
+                "batch": batch_idx + 1,
+                "recipients": batch.end - batch.start,
+                "amount": format!("{:#}", HumanCapacity::from(amount)),
+                "tx_hash": payment_hash,
+                "consolidation_txs": txs.len() - 1,
+                "block_number": batch.block_number,
+                "fee": format!("{:#}", HumanCapacity::from(batch_fee)),
+            }));
+        }
+        let total: u64 = recipients.iter().map(|(_, capacity)| capacity).sum();
+        let resp = serde_json::json!({
+            "recipients": recipients.len(),
+            "total_paid": format!("{:#}", HumanCapacity::from(total)),
+            "total_fee": format!("{:#}", HumanCapacity::from(total_fee)),
+            "batches": batches,
+            "unpaid": unpaid,
+        });
+        Ok(resp.render(format, color))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_batches() {
+        assert!(plan_batches(0, 100).is_empty());
+        assert_eq!(plan_batches(3, 100), vec![(0, 3)]);
+        assert_eq!(
+            plan_batches(250, 100),
+            vec![(0, 100), (100, 200), (200, 250)]
+        );
+    }
+
+    #[test]
+    fn test_signed_step() {
+        // Not sent yet, or sent before the interruption
+        assert_eq!(signed_step(&[false, false], true), Ok(SignedStep::Send));
+        assert_eq!(signed_step(&[true, false], true), Ok(SignedStep::Send));
+        assert_eq!(signed_step(&[true, true], false), Ok(SignedStep::Send));
+        // The inputs are spent elsewhere
+        assert_eq!(signed_step(&[false, false], false), Ok(SignedStep::Rebuild));
+        assert!(signed_step(&[true, false], false).is_err());
+    }
+
+    #[test]
+    fn test_resume_state() {
+        let dir = std::env::temp_dir().join(format!("ckb-cli-payout-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("payout.toml.state.json");
+        let hash = H256::from([1u8; 32]);
+
+        let mut state = PayoutState::load_or_new(&path, hash.clone(), plan_batches(5, 2)).unwrap();
+        assert_eq!(state.batches.len(), 3);
+        assert!(state
+            .batches
+            .iter()
+            .all(|batch| batch.status == BatchStatus::Pending));
+        let tx = json_types::Transaction::from(packed::Transaction::default());
+        state.batches[0].status = BatchStatus::Committed;
+        state.batches[0].block_number = Some(10);
+        state.batches[1].status = BatchStatus::Signed;
+        state.batches[1].transactions = vec![tx];
+        state.save(&path).unwrap();
+
+        // Resumed from the saved progress, not the new plan
+        let mut state = PayoutState::load_or_new(&path, hash, plan_batches(5, 5)).unwrap();
+        let statuses = state
+            .batches
+            .iter()
+            .map(|batch| batch.status)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            statuses,
+            vec![
+                BatchStatus::Committed,
+                BatchStatus::Signed,
+                BatchStatus::Pending
+            ]
+        );
+        assert_eq!(state.batches[0].block_number, Some(10));
+        assert_eq!(state.batches[1].transaction_views().len(), 1);
+        // Rebuilt
+        state.batches[1].reset();
+        assert_eq!(state.batches[1].status, BatchStatus::Pending);
+        assert!(state.batches[1].transactions.is_empty());
+        // The recipients changed
+        assert!(PayoutState::load_or_new(&path, H256::from([2u8; 32]), Vec::new()).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashSet;
+
+use ckb_sdk::{rpc::LiveCell, HttpRpcClient};
+use ckb_types::{packed::OutPoint, prelude::*, H256};
+
+const PAGE_SIZE: u64 = 50;
+
+/// Live cells changed after the local index tip, they come from the indexer
+/// RPC of the node and are provisional (not yet verified by local index).
+pub struct ReadThrough {
+    // The block number of local index tip
+    pub local_number: u64,
+    // Live cells created after local index tip
+    pub new_cells: Vec<LiveCell>,
+    // Cells in local index which already spent after local index tip
+    pub spent: HashSet<OutPoint>,
+}
+
+impl ReadThrough {
+    pub fn new_capacity(&self) -> u64 {
+        self.new_cells
+            .iter()
+            .map(|cell| cell.cell_output.capacity.0)
+            .sum()
+    }
+}
+
+/// Fetch all live cells of `lock_hash` by the indexer RPC and diff them with
+/// the live cells (`local_out_points`) in local index
+pub fn read_through(
+    rpc_client: &mut HttpRpcClient,
+    lock_hash: &H256,
+    local_number: u64,
+    local_out_points: &[OutPoint],
+) -> Result<ReadThrough, String> {
+    let mut node_cells = Vec::new();
+    let mut page = 0;
+    loop {
+        let cells = rpc_client
+            .get_live_cells_by_lock_hash(lock_hash.clone(), page, PAGE_SIZE, None)
+            .map_err(|err| format!("Indexer RPC error (is Indexer module enabled?): {}", err))?;
+        let is_last_page = (cells.len() as u64) < PAGE_SIZE;
+        node_cells.extend(cells);
+        if is_last_page {
+            break;
+        }
+        page += 1;
+    }
+    if node_cells.is_empty() && !local_out_points.is_empty() {
+        return Err(format!(
+            "Lock hash {:#x} may not be indexed by node, please call `rpc index_lock_hash` first",
+            lock_hash
+        ));
+    }
+
+    let node_out_points = node_cells
+        .iter()
+        .map(|cell| OutPoint::new(cell.created_by.tx_hash.pack(), cell.created_by.index as u32))
+        .collect::<HashSet<_>>();
+    let spent = local_out_points
+        .iter()
+        .filter(|out_point| !node_out_points.contains(out_point))
+        .cloned()
+        .collect();
+    let new_cells = node_cells
+        .into_iter()
+        .filter(|cell| cell.created_by.block_number > local_number)
+        .collect();
+    Ok(ReadThrough {
+        local_number,
+        new_cells,
+        spent,
+    })
+}
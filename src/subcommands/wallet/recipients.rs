@@ -0,0 +1,96 @@
+use std::fs;
+use std::path::Path;
+
+use ckb_sdk::{Address, NetworkType};
+
+use crate::utils::{
+    alias::resolve_address,
+    arg_parser::{ArgParser, CapacityParser},
+};
+
+/// Split `<address>:<capacity>` (the address can be an alias)
+pub fn split_recipient(input: &str) -> Result<(&str, &str), String> {
+    let pos = input.rfind(':').ok_or_else(|| {
+        format!(
+            "Invalid recipient (expected <address>:<capacity>): {}",
+            input
+        )
+    })?;
+    Ok((input[..pos].trim(), input[pos + 1..].trim()))
+}
+
+pub fn parse_recipient(input: &str, network: NetworkType) -> Result<(Address, u64), String> {
+    let (address, capacity) = split_recipient(input)?;
+    Ok((
+        resolve_address(address, network)?,
+        CapacityParser.parse(capacity)?,
+    ))
+}
+
+/// The `(line number, address, capacity)` of recipients CSV, empty lines, `#`
+/// comments and the header line (`address,capacity`) are skipped.
+pub fn recipient_lines(content: &str) -> Result<Vec<(usize, &str, &str)>, String> {
+    let mut lines = Vec::new();
+    for (idx, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+        if fields.len() != 2 {
+            return Err(format!(
+                "Invalid recipient at line {} (expected address,capacity): {}",
+                idx + 1,
+                line
+            ));
+        }
+        if lines.is_empty() && fields[0].eq_ignore_ascii_case("address") {
+            continue;
+        }
+        lines.push((idx + 1, fields[0], fields[1]));
+    }
+    Ok(lines)
+}
+
+pub fn read_recipients_file(
+    path: &Path,
+    network: NetworkType,
+) -> Result<Vec<(Address, u64)>, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| format!("Read {} failed: {}", path.display(), err))?;
+    recipient_lines(&content)?
+        .into_iter()
+        .map(|(line_number, address, capacity)| {
+            let address = resolve_address(address, network)
+                .map_err(|err| format!("Invalid address at line {}: {}", line_number, err))?;
+            let capacity = CapacityParser
+                .parse(capacity)
+                .map_err(|err| format!("Invalid capacity at line {}: {}", line_number, err))?;
+            Ok((address, capacity))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_recipient() {
+        assert_eq!(
+            split_recipient("ckt1qyq:100.5").unwrap(),
+            ("ckt1qyq", "100.5")
+        );
+        assert!(split_recipient("ckt1qyq").is_err());
+    }
+
+    #[test]
+    fn test_recipient_lines() {
+        let content = "address,capacity\n# payroll\n\nckt1a, 100\nckt1b,61.5\n";
+        assert_eq!(
+            recipient_lines(content).unwrap(),
+            vec![(4, "ckt1a", "100"), (5, "ckt1b", "61.5")]
+        );
+        assert!(recipient_lines("ckt1a,100,1").is_err());
+    }
+}
@@ -0,0 +1,59 @@
+/// Stop scanning a chain at this index even if the gap limit is not reached
+pub const MAX_SCAN_INDEX: u32 = 100_000;
+
+/// Scan the addresses of a chain (receiving or change) from index 0 until
+/// `gap_limit` consecutive addresses are unused (BIP-44 address gap limit).
+/// `usage` returns the usage of the address at an index, `None` if unused.
+/// Return the usage of the addresses before the next unused index (the unused
+/// ones between the used are `None`) and the next unused index.
+pub fn scan_chain<T, F>(gap_limit: u32, mut usage: F) -> (Vec<Option<T>>, u32)
+where
+    F: FnMut(u32) -> Option<T>,
+{
+    let mut scanned = Vec::new();
+    let mut next_unused = 0;
+    let mut index = 0;
+    while index < next_unused + gap_limit && index < MAX_SCAN_INDEX {
+        let value = usage(index);
+        if value.is_some() {
+            next_unused = index + 1;
+        }
+        scanned.push(value);
+        index += 1;
+    }
+    scanned.truncate(next_unused as usize);
+    (scanned, next_unused)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_chain() {
+        let used = [0, 1, 5, 24];
+        let mut scanned_count = 0;
+        let (scanned, next_unused) = scan_chain(20, |index| {
+            scanned_count += 1;
+            if used.contains(&index) {
+                Some(index)
+            } else {
+                None
+            }
+        });
+        assert_eq!(next_unused, 25);
+        assert_eq!(scanned_count, 45);
+        assert_eq!(scanned.len(), 25);
+        assert_eq!(scanned[5], Some(5));
+        assert_eq!(scanned[6], None);
+
+        // Not any address used
+        let (scanned, next_unused) = scan_chain(20, |_| None::<()>);
+        assert_eq!(next_unused, 0);
+        assert!(scanned.is_empty());
+
+        // The gap between 0 and 25 is larger than the limit
+        let (_, next_unused) = scan_chain(20, |index| if index == 25 { Some(()) } else { None });
+        assert_eq!(next_unused, 0);
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use chrono::{TimeZone, Utc};
+use ckb_app_config::NetworkAlertConfig;
+use ckb_crypto::secp::{Message, Pubkey, Signature};
+use ckb_jsonrpc_types::JsonBytes;
+use ckb_types::{packed, prelude::*};
+use faster_hex::hex_string;
+use serde_derive::{Deserialize, Serialize};
+
+/// The public keys used to verify alert signatures, default is the alert keys
+/// of CKB. Override it in config file (`~/.ckb-cli/config`) for devnets:
+///
+/// ```json
+/// "alert_signature": {
+///     "signatures_threshold": 1,
+///     "public_keys": ["0x03..."]
+/// }
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AlertConfig {
+    pub signatures_threshold: usize,
+    pub public_keys: Vec<JsonBytes>,
+}
+
+impl Default for AlertConfig {
+    fn default() -> AlertConfig {
+        let config = NetworkAlertConfig::default();
+        AlertConfig {
+            signatures_threshold: config.signatures_threshold,
+            public_keys: config.public_keys,
+        }
+    }
+}
+
+impl AlertConfig {
+    pub fn from_config(configs: &serde_json::Value) -> Result<AlertConfig, String> {
+        let config = match configs.get("alert_signature") {
+            Some(value) => serde_json::from_value::<AlertConfig>(value.clone())
+                .map_err(|err| format!("Invalid alert_signature config: {}", err))?,
+            None => return Ok(AlertConfig::default()),
+        };
+        config.pubkeys()?;
+        if config.signatures_threshold == 0
+            || config.signatures_threshold > config.public_keys.len()
+        {
+            return Err(format!(
+                "Invalid alert_signature config: signatures_threshold {} with {} public keys",
+                config.signatures_threshold,
+                config.public_keys.len()
+            ));
+        }
+        Ok(config)
+    }
+
+    fn pubkeys(&self) -> Result<Vec<Pubkey>, String> {
+        self.public_keys
+            .iter()
+            .map(|key| {
+                Pubkey::from_slice(key.as_bytes()).map_err(|err| {
+                    format!(
+                        "Invalid alert public key 0x{}: {}",
+                        hex_string(key.as_bytes()).unwrap(),
+                        err
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Check the alert is signed by at least `signatures_threshold` of the
+    /// public keys
+    pub fn verify(&self, alert: &packed::Alert) -> Result<(), String> {
+        let pubkeys = self.pubkeys()?;
+        let message = Message::from_slice(alert.calc_alert_hash().as_slice())
+            .map_err(|err| err.to_string())?;
+        let signers = alert
+            .signatures()
+            .into_iter()
+            .filter_map(|signature| Signature::from_slice(&signature.raw_data()).ok())
+            .filter_map(|signature| signature.recover(&message).ok())
+            .filter_map(|pubkey| pubkeys.iter().position(|key| key == &pubkey))
+            .collect::<HashSet<_>>();
+        if signers.len() < self.signatures_threshold {
+            return Err(format!(
+                "Alert signatures not enough: valid {}, required {}",
+                signers.len(),
+                self.signatures_threshold
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Human readable expiry of alert (`notice_until` is in milliseconds)
+pub fn alert_expiry(notice_until: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_millis() as u64;
+    let until = Utc.timestamp_millis(notice_until as i64).to_rfc3339();
+    if notice_until < now {
+        format!("{} (expired)", until)
+    } else {
+        let hours = (notice_until - now) / 1000 / 3600;
+        if hours >= 24 {
+            format!("{} (in {} days)", until, hours / 24)
+        } else {
+            format!("{} (in {} hours)", until, hours)
+        }
+    }
+}
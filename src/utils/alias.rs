@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::fs;
+
+use ckb_sdk::{rpc::new_http_transport, Address, AddressPayload, NetworkType};
+use jsonrpc_client_core::{expand_params, jsonrpc_client};
+use jsonrpc_client_http::HttpHandle;
+use serde_derive::{Deserialize, Serialize};
+
+use super::arg_parser::{AddressParser, ArgParser};
+use super::other::{confirm, get_ckb_cli_dir};
+
+const ALIASES_FILE: &str = "address-aliases.json";
+
+jsonrpc_client!(pub struct RawAliasClient {
+    pub fn resolve_alias(&mut self, name: String) -> RpcRequest<Option<String>>;
+});
+
+/// Config of alias resolver: `~/.ckb-cli/address-aliases.json`
+///
+/// ```json
+/// {
+///     "resolver_url": "http://127.0.0.1:8228",
+///     "aliases": { "alice.bit": "ckt1qyq..." }
+/// }
+/// ```
+///
+/// Local aliases are searched first, then the `resolve_alias` JSON-RPC method
+/// of `resolver_url` is called.
+#[derive(Serialize, Deserialize, Default, Clone, Debug)]
+pub struct AliasResolver {
+    #[serde(default)]
+    resolver_url: Option<String>,
+    #[serde(default)]
+    aliases: HashMap<String, String>,
+}
+
+impl AliasResolver {
+    pub fn load() -> Result<AliasResolver, String> {
+        let mut path = get_ckb_cli_dir();
+        path.push(ALIASES_FILE);
+        if !path.exists() {
+            return Ok(AliasResolver::default());
+        }
+        let file = fs::File::open(&path).map_err(|err| err.to_string())?;
+        serde_json::from_reader(file)
+            .map_err(|err| format!("Parse {} failed: {}", path.to_string_lossy(), err))
+    }
+
+    pub fn resolve(&self, name: &str) -> Result<String, String> {
+        if let Some(address) = self.aliases.get(name) {
+            return Ok(address.clone());
+        }
+        if let Some(url) = self.resolver_url.as_ref() {
+            let transport = new_http_transport()?;
+            let handle: HttpHandle = transport.handle(url).map_err(|err| err.to_string())?;
+            return RawAliasClient::new(handle)
+                .resolve_alias(name.to_string())
+                .call()
+                .map_err(|err| format!("Resolve alias {} error: {}", name, err))?
+                .ok_or_else(|| format!("Alias not found by resolver: {}", name));
+        }
+        Err(format!(
+            "Alias not found: {} (no resolver_url in {})",
+            name, ALIASES_FILE
+        ))
+    }
+
+    /// The local aliases of the address (of any network), sorted by name
+    pub fn local_aliases_of(&self, payload: &AddressPayload) -> Vec<String> {
+        let mut names = self
+            .aliases
+            .iter()
+            .filter(|(_, address)| {
+                AddressParser::default()
+                    .parse(address)
+                    .map(|address| address.payload() == payload)
+                    .unwrap_or(false)
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        names
+    }
+}
+
+/// Human names which may be resolved to an address (e.g. `alice.bit`)
+pub fn is_alias_name(input: &str) -> bool {
+    !input.is_empty()
+        && !input.starts_with("ckb1")
+        && !input.starts_with("ckt1")
+        && input
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '_')
+}
+
+/// Parse the input as an address, or resolve it as an alias. The resolved
+/// address must be confirmed by user.
+pub fn resolve_address(input: &str, network: NetworkType) -> Result<Address, String> {
+    let mut parser = AddressParser::default();
+    parser.set_network(network);
+    if !is_alias_name(input) {
+        return parser.parse(input);
+    }
+    let resolved = AliasResolver::load()?.resolve(input)?;
+    let address = parser.parse(&resolved)?;
+    eprintln!("Resolved alias {} => {}", input, address);
+    if confirm("Confirm the address")? {
+        Ok(address)
+    } else {
+        Err("Resolved address not confirmed".to_string())
+    }
+}
@@ -1,12 +1,16 @@
+use crate::utils::alias::is_alias_name;
 use crate::utils::arg_parser::{
     AccountIdParser, AddressParser, ArgParser, CapacityParser, FilePathParser, FixedHashParser,
     FromAccountParser, FromStrParser, HexParser, OutPointParser, PrivkeyPathParser,
-    PubkeyHexParser,
+    PubkeyHexParser, UrlParser,
 };
 use ckb_sdk::wallet::DerivationPath;
 use ckb_types::{H160, H256};
 use clap::Arg;
 
+// Too many change cells make the transaction (and the fee) unreasonably large
+pub const MAX_CHANGE_CELLS: usize = 32;
+
 pub fn privkey_path<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("privkey-path")
         .long("privkey-path")
@@ -81,6 +85,54 @@ pub fn derived<'a, 'b>() -> Arg<'a, 'b> {
         .help("Search derived address space (search 10000 addresses(change/receiving) max, required keystore password, see: BIP-44)")
 }
 
+pub fn read_through<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("read-through")
+        .long("read-through")
+        .help("When local index is behind the node, merge the recent cells from node's indexer RPC (marked as provisional)")
+}
+
+pub fn at_block<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("at-block")
+        .long("at-block")
+        .takes_value(true)
+        .validator(|input| {
+            if input.starts_with("0x") {
+                FixedHashParser::<H256>::default().validate(input)
+            } else {
+                FromStrParser::<u64>::default().validate(input)
+            }
+        })
+        .conflicts_with("read-through")
+        .help("Query as of a past block (number or hash), the history is replayed from node's indexer RPC")
+}
+
+pub fn index_backend<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("index-backend")
+        .long("index-backend")
+        .takes_value(true)
+        .possible_values(&["local", "indexer", "light-client"])
+        .default_value("local")
+        .help("Where to search live cells: local index database, a remote ckb-indexer (given by --indexer-url) or a CKB light client (experimental, given by --light-client-url)")
+}
+
+pub fn indexer_url<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("indexer-url")
+        .long("indexer-url")
+        .takes_value(true)
+        .required_if("index-backend", "indexer")
+        .validator(|input| UrlParser.validate(input))
+        .help("The JSON-RPC url of ckb-indexer (e.g. http://127.0.0.1:8116)")
+}
+
+pub fn light_client_url<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("light-client-url")
+        .long("light-client-url")
+        .takes_value(true)
+        .required_if("index-backend", "light-client")
+        .validator(|input| UrlParser.validate(input))
+        .help("The JSON-RPC url of CKB light client (e.g. http://127.0.0.1:9000), the transactions are sent to it instead of the --url node")
+}
+
 pub fn lock_arg<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("lock-arg")
         .long("lock-arg")
@@ -101,6 +153,8 @@ pub fn account_id<'a, 'b>() -> Arg<'a, 'b> {
             "- software key lock argument: blake2b(pubkey)[0..20]\n",
             "\n",
             "- hardware wallet: opaque identifie\nr",
+            "\n",
+            "- hardware wallet label given by `account ledger-label`\n",
         ))
 }
 
@@ -118,9 +172,19 @@ pub fn from_account<'a, 'b>() -> Arg<'a, 'b> {
             " - hardware wallet: opaque identifier\n",
             "\n",
             " - sighash address for software key\n",
+            "\n",
+            " - hardware wallet label given by `account ledger-label`\n",
         ))
 }
 
+pub fn signer_url<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("signer-url")
+        .long("signer-url")
+        .takes_value(true)
+        .validator(|input| UrlParser.validate(input))
+        .help("Sign by a remote signing service (JSON-RPC over https), the auth token is read from the CKB_CLI_SIGNER_TOKEN environment variable")
+}
+
 pub fn from_locked_address<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("from-locked-address")
         .long("from-locked-address")
@@ -133,8 +197,14 @@ pub fn to_address<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("to-address")
         .long("to-address")
         .takes_value(true)
-        .validator(|input| AddressParser::default().validate(input))
-        .help("Target address")
+        .validator(|input| {
+            if is_alias_name(&input) {
+                Ok(())
+            } else {
+                AddressParser::default().validate(input)
+            }
+        })
+        .help("Target address, or an alias resolved by ~/.ckb-cli/address-aliases.json")
 }
 
 pub fn to_data<'a, 'b>() -> Arg<'a, 'b> {
@@ -169,6 +239,135 @@ pub fn tx_fee<'a, 'b>() -> Arg<'a, 'b> {
         .help("The transaction fee capacity (unit: CKB, format: 0.0001)")
 }
 
+pub fn fee_rate<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("fee-rate")
+        .long("fee-rate")
+        .takes_value(true)
+        .validator(|input| FromStrParser::<u64>::new().validate(input))
+        .help("Calculate the transaction fee by transaction size (unit: shannons/KB)")
+}
+
+pub fn allow_high_fee<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("allow-high-fee")
+        .long("allow-high-fee")
+        .help("Sign even when the transaction fee exceeds the sanity caps (`max_fee` / `max_fee_percent` of config, default: 1 CKB / 1% of transferred capacity)")
+}
+
+pub fn wait_for_committed<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("wait-for-committed")
+        .long("wait-for-committed")
+        .help("Wait until the transaction is committed (and confirmed by --confirmations blocks) after sending, then print the block number and hash")
+}
+
+pub fn confirmations<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("confirmations")
+        .long("confirmations")
+        .takes_value(true)
+        .validator(|input| {
+            FromStrParser::<u64>::default()
+                .parse(&input)
+                .and_then(|value| {
+                    if value == 0 {
+                        Err("confirmations must be at least 1".to_string())
+                    } else {
+                        Ok(())
+                    }
+                })
+        })
+        .help("The number of blocks (including the committing block) to wait for [default: 1]")
+}
+
+pub fn wait_timeout<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("timeout")
+        .long("timeout")
+        .takes_value(true)
+        .validator(|input| FromStrParser::<u64>::default().validate(input))
+        .help("Give up waiting after this many seconds [default: wait forever]")
+}
+
+/// `--wait-for-committed [--confirmations N] [--timeout secs]` of the commands
+/// sending a transaction
+pub fn wait_for_committed_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        wait_for_committed(),
+        confirmations().requires(wait_for_committed().b.name),
+        wait_timeout().requires(wait_for_committed().b.name),
+    ]
+}
+
+pub fn on_failure<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("on-failure")
+        .long("on-failure")
+        .takes_value(true)
+        .number_of_values(2)
+        .value_names(&["action", "dir"])
+        .help("When the scripts fail to verify on the node, save the transaction with its resolved cells and headers as a mock transaction file in <dir> for replaying by `mock-tx verify` (action: dump-mock-tx)")
+}
+
+pub fn tip<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("tip")
+        .long("tip")
+        .takes_value(true)
+        .validator(|input| CapacityParser.validate(input))
+        .help("Extra fee paid on top of the transaction fee (unit: CKB, format: 0.0001)")
+}
+
+pub fn tip_address<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("tip-address")
+        .long("tip-address")
+        .takes_value(true)
+        .requires("tip")
+        .validator(|input| AddressParser::default().validate(input))
+        .help("Send the tip to this (miner donation) address as an output instead of paying it as fee")
+}
+
+pub fn change_cells<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("change-cells")
+        .long("change-cells")
+        .takes_value(true)
+        .default_value("1")
+        .validator(|input| {
+            let cells = FromStrParser::<usize>::default().parse(&input)?;
+            if cells == 0 || cells > MAX_CHANGE_CELLS {
+                return Err(format!(
+                    "change-cells must be in range [1, {}]",
+                    MAX_CHANGE_CELLS
+                ));
+            }
+            Ok(())
+        })
+        .help("Split the change into at most N cells of whole CKB (each can hold a secp256k1 cell), so following transactions can spend them in parallel")
+}
+
+pub fn udt_code_hash<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("udt-code-hash")
+        .long("udt-code-hash")
+        .takes_value(true)
+        .required(true)
+        .validator(|input| FixedHashParser::<H256>::default().validate(input))
+        .help("The code hash of sUDT type script (hash_type is type)")
+}
+
+pub fn udt_cell_dep<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("udt-cell-dep")
+        .long("udt-cell-dep")
+        .takes_value(true)
+        .required(true)
+        .validator(|input| OutPointParser.validate(input))
+        .help("The out point of sUDT script cell (format: {tx_hash}-{index}, dep_type is code)")
+}
+
+pub fn udt_owner<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("owner")
+        .long("owner")
+        .takes_value(true)
+        .required(true)
+        .validator(|input| AddressParser::default().validate(input))
+        .help(
+            "The address of the token issuer, the args of sUDT type script is the lock hash of it",
+        )
+}
+
 pub fn type_hash<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("type-hash")
         .long("type-hash")
@@ -227,3 +426,46 @@ pub fn out_point<'a, 'b>() -> Arg<'a, 'b> {
         .validator(|input| { OutPointParser.validate(input) })
         .help("out-point to specify a cell. Example: 0xd56ed5d4e8984701714de9744a533413f79604b3b91461e2265614829d2005d1-1")
 }
+
+pub fn max_inputs<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("max-inputs")
+        .long("max-inputs")
+        .takes_value(true)
+        .default_value("256")
+        .validator(|input| {
+            let max_inputs = FromStrParser::<usize>::default().parse(&input)?;
+            if max_inputs == 0 {
+                return Err("max-inputs must be greater than 0".to_string());
+            }
+            Ok(())
+        })
+        .help("The max number of inputs of each transaction")
+}
+
+pub fn use_out_point<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("use-out-point")
+        .long("use-out-point")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .validator(|input| { OutPointParser.validate(input) })
+        .help("Spend only the cells given by this option (can be given multiple times) to pay the capacity and fee, format: {tx-hash}-{index} or {tx-hash}:{index}")
+}
+
+pub fn exclude_out_point<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("exclude-out-point")
+        .long("exclude-out-point")
+        .takes_value(true)
+        .multiple(true)
+        .number_of_values(1)
+        .conflicts_with("use-out-point")
+        .validator(|input| { OutPointParser.validate(input) })
+        .help("Never spend the cell (can be given multiple times), format: {tx-hash}-{index} or {tx-hash}:{index}")
+}
+
+pub fn memo<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("memo")
+        .long("memo")
+        .takes_value(true)
+        .help("Memo of the transaction, only saved in local tx records (never on chain)")
+}
@@ -4,6 +4,7 @@ use std::io::Read;
 use std::marker::PhantomData;
 use std::path::PathBuf;
 use std::str::FromStr;
+use std::sync::Once;
 use std::time::Duration;
 
 use clap::ArgMatches;
@@ -17,6 +18,7 @@ use ckb_sdk::{
 };
 use ckb_types::{packed::OutPoint, prelude::*, H160, H256};
 
+use super::ledger_labels::resolve_ledger_label;
 pub use super::privkey_wrapper::PrivkeyWrapper;
 use crate::subcommands::account::AccountId;
 
@@ -306,6 +308,12 @@ pub struct PathParser {
     should_exists: bool,
 }
 
+impl PathParser {
+    pub fn new(should_exists: bool) -> PathParser {
+        PathParser { should_exists }
+    }
+}
+
 impl ArgParser for PathParser {
     type Value = PathBuf;
     type Error = String;
@@ -352,11 +360,13 @@ pub struct DirPathParser {
     path_parser: PathParser,
 }
 
-// impl DirPathParser {
-//     pub fn new(should_exists: bool) -> DirPathParser {
-//         DirPathParser { path_parser: PathParser { should_exists } }
-//     }
-// }
+impl DirPathParser {
+    pub fn new(should_exists: bool) -> DirPathParser {
+        DirPathParser {
+            path_parser: PathParser { should_exists },
+        }
+    }
+}
 
 impl ArgParser for DirPathParser {
     type Value = PathBuf;
@@ -521,6 +531,8 @@ impl Default for AddressParser {
     }
 }
 
+static DEPRECATED_ADDRESS_WARNING: Once = Once::new();
+
 impl ArgParser for AddressParser {
     type Value = Address;
     type Error = String;
@@ -542,7 +554,15 @@ impl ArgParser for AddressParser {
             Ok(())
         }
 
-        if let Ok(address) = Address::from_str(input) {
+        if let Ok((address, ty)) = Address::from_str_with_type(input) {
+            if ty != AddressType::Full {
+                DEPRECATED_ADDRESS_WARNING.call_once(|| {
+                    eprintln!(
+                        "[warning] The short and full (data/type) addresses are deprecated since CKB2021, use the full address instead: {}",
+                        address.to_full_string()
+                    );
+                });
+            }
             if let Some(network) = self.network {
                 if address.network().to_prefix() != network.to_prefix() {
                     return Err(format!(
@@ -637,10 +657,11 @@ impl ArgParser for OutPointParser {
     type Error = String;
 
     fn parse(&self, input: &str) -> Result<OutPoint, String> {
-        let parts = input.split('-').collect::<Vec<_>>();
+        let separator = if input.contains(':') { ':' } else { '-' };
+        let parts = input.split(separator).collect::<Vec<_>>();
         if parts.len() != 2 {
             return Err(format!(
-                "Invalid OutPoint: {}, format: {{tx-hash}}-{{index}}",
+                "Invalid OutPoint: {}, format: {{tx-hash}}-{{index}} or {{tx-hash}}:{{index}}",
                 input
             ));
         }
@@ -706,7 +727,7 @@ pub struct AccountIdParser(EitherParser<FixedHashParser<H160>, FixedHashParser<H
 
 impl AccountIdParser {
     fn render_error((left_error, right_error): (String, String)) -> String {
-        format!("Not a valid account id of any type: not a valid software key because of {}, not a valid ledger key because of {}", left_error, right_error)
+        format!("Not a valid account id of any type: not a valid software key because of {}, not a valid ledger key because of {}, not a ledger label", left_error, right_error)
     }
 }
 
@@ -724,10 +745,11 @@ impl ArgParser for AccountIdParser {
     type Error = String;
 
     fn parse(&self, input: &str) -> Result<AccountId, String> {
-        self.0
-            .parse(input)
-            .map(From::from)
-            .map_err(Self::render_error)
+        self.0.parse(input).map(From::from).or_else(|err| {
+            resolve_ledger_label(input)
+                .map(AccountId::LedgerId)
+                .ok_or_else(|| Self::render_error(err))
+        })
     }
 }
 
@@ -772,6 +794,7 @@ impl FromAccountParser {
                 " - not a valid software key lock arg because of {}\n",
                 " - not a valid ledger key because of {}\n",
                 " - not a address (for software key) because of {} or {}\n",
+                " - not a ledger label (see `account ledger-label`)\n",
             ),
             lock_arg_error, wallet_error, address_default_error, address_sighash_error,
         )
@@ -786,7 +809,11 @@ impl ArgParser for FromAccountParser {
         Self::mk_parser()
             .parse(input)
             .map(From::from)
-            .map_err(Self::render_error)
+            .or_else(|err| {
+                resolve_ledger_label(input)
+                    .map(AccountId::LedgerId)
+                    .ok_or_else(|| Self::render_error(err))
+            })
     }
 }
 
@@ -0,0 +1,165 @@
+use std::thread::{self, JoinHandle};
+
+use ckb_sdk::{rpc::RpcError, HttpRpcClient};
+use ckb_types::{core::TransactionView, prelude::*, H256};
+
+use super::arg_parser::{ArgParser, UrlParser};
+
+/// The JSON-RPC error code of the node rejecting a transaction already in its
+/// pool (`PoolRejectedDuplicatedTransaction`)
+pub const POOL_REJECTED_DUPLICATED_TRANSACTION: i64 = -1107;
+
+/// Where the sent transactions go
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SendTargets {
+    /// The light client of `--index-backend light-client`, the transactions
+    /// are sent to it instead of the `--url` node
+    pub light_client_url: Option<String>,
+    /// The other RPC urls the transactions are also submitted to, set by
+    /// `--broadcast-all-nodes` from the `broadcast_urls` config
+    pub broadcast_urls: Vec<String>,
+}
+
+/// The urls of the `broadcast_urls` config (a list of RPC urls)
+pub fn broadcast_urls_from_config(configs: &serde_json::Value) -> Result<Vec<String>, String> {
+    let values = match configs.get("broadcast_urls") {
+        Some(serde_json::Value::Array(values)) => values,
+        Some(_) => return Err("Invalid broadcast_urls config: expected a list of urls".to_string()),
+        None => return Ok(Vec::new()),
+    };
+    values
+        .iter()
+        .map(|value| {
+            let url = value
+                .as_str()
+                .ok_or_else(|| format!("Invalid broadcast_urls config: {} is not a url", value))?;
+            UrlParser
+                .validate(url)
+                .map_err(|err| format!("Invalid broadcast_urls config: {}: {}", url, err))?;
+            Ok(url.to_string())
+        })
+        .collect()
+}
+
+/// The node rejects a transaction it already has, that is not a failure of
+/// broadcasting since the nodes relay the transactions to each other.
+pub fn is_duplicated_error(err: &RpcError) -> bool {
+    err.code == Some(POOL_REJECTED_DUPLICATED_TRANSACTION)
+}
+
+/// Sending a transaction to the other nodes in parallel
+pub struct Broadcast {
+    tx_hash: H256,
+    handles: Vec<(String, JoinHandle<Result<H256, RpcError>>)>,
+}
+
+impl Broadcast {
+    /// Start sending the transaction to each of `urls` by its own thread
+    pub fn start(tx: &TransactionView, urls: Vec<String>) -> Broadcast {
+        let handles = urls
+            .into_iter()
+            .map(|url| {
+                let tx = tx.data();
+                let client_url = url.clone();
+                let handle = thread::spawn(move || {
+                    HttpRpcClient::try_new(client_url)
+                        .map_err(|message| RpcError {
+                            code: None,
+                            message,
+                        })
+                        .and_then(|mut client| client.send_transaction_with_code(tx))
+                });
+                (url, handle)
+            })
+            .collect();
+        Broadcast {
+            tx_hash: tx.hash().unpack(),
+            handles,
+        }
+    }
+
+    /// Merge the result of the primary node with the others: the transaction
+    /// is sent when any node accepted it (or already had it), the failures of
+    /// the other nodes are only warned.
+    pub fn finish(self, primary: Result<H256, RpcError>) -> Result<H256, String> {
+        if self.handles.is_empty() {
+            return primary.map_err(|err| err.message);
+        }
+        let tx_hash = self.tx_hash;
+        let tolerate = |result: Result<H256, RpcError>| match result {
+            Err(ref err) if is_duplicated_error(err) => Ok(tx_hash.clone()),
+            result => result,
+        };
+        let primary = tolerate(primary);
+        let mut accepted = primary.is_ok();
+        for (url, handle) in self.handles {
+            let result = handle.join().unwrap_or_else(|_| {
+                Err(RpcError {
+                    code: None,
+                    message: "the sending thread panicked".to_string(),
+                })
+            });
+            match tolerate(result) {
+                Ok(_) => accepted = true,
+                Err(err) => eprintln!("[WARNING] Send transaction to {} failed: {}", url, err),
+            }
+        }
+        match primary {
+            Err(err) if accepted => {
+                eprintln!(
+                    "[WARNING] Send transaction to the primary node failed: {}",
+                    err
+                );
+                Ok(tx_hash)
+            }
+            result => result.map_err(|err| err.message),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_broadcast_urls_from_config() {
+        let configs = serde_json::json!({
+            "broadcast_urls": ["http://10.0.0.2:8114", "http://10.0.0.3:8114"],
+        });
+        assert_eq!(
+            broadcast_urls_from_config(&configs),
+            Ok(vec![
+                "http://10.0.0.2:8114".to_string(),
+                "http://10.0.0.3:8114".to_string()
+            ])
+        );
+        assert_eq!(
+            broadcast_urls_from_config(&serde_json::json!({})),
+            Ok(Vec::new())
+        );
+        assert!(broadcast_urls_from_config(&serde_json::json!({"broadcast_urls": "x"})).is_err());
+        assert!(
+            broadcast_urls_from_config(&serde_json::json!({"broadcast_urls": ["no url"]})).is_err()
+        );
+    }
+
+    #[test]
+    fn test_is_duplicated_error() {
+        let duplicated = RpcError {
+            code: Some(POOL_REJECTED_DUPLICATED_TRANSACTION),
+            message: "PoolRejectedDuplicatedTransaction: Transaction(Byte32(0x..)) already exist in transaction_pool".to_string(),
+        };
+        assert!(is_duplicated_error(&duplicated));
+        // Only the error code counts, not the message
+        let rejected = RpcError {
+            code: Some(-302),
+            message: "TransactionFailedToVerify: Duplicated inputs".to_string(),
+        };
+        assert!(!is_duplicated_error(&rejected));
+        let unreachable = RpcError {
+            code: None,
+            message: "already exist".to_string(),
+        };
+        assert!(!is_duplicated_error(&unreachable));
+    }
+}
@@ -0,0 +1,261 @@
+use std::collections::HashSet;
+
+use ckb_index::LiveCellInfo;
+use ckb_sdk::HumanCapacity;
+use ckb_types::{packed::OutPoint, prelude::*, H256};
+use clap::ArgMatches;
+
+use super::arg_parser::{ArgParser, OutPointParser};
+
+// The candidates listed when the selected cells are not enough
+const MAX_LISTED_CANDIDATES: usize = 20;
+
+/// Manual selection of the cells spent for capacity: only the cells of
+/// `--use-out-point` are spent when given, the cells of `--exclude-out-point`
+/// are never spent.
+#[derive(Debug, Clone, Default)]
+pub struct CoinControl {
+    use_out_points: HashSet<OutPoint>,
+    exclude_out_points: HashSet<OutPoint>,
+}
+
+impl CoinControl {
+    pub fn from_matches(m: &ArgMatches) -> Result<CoinControl, String> {
+        let use_out_points: Vec<OutPoint> = OutPointParser.from_matches_vec(m, "use-out-point")?;
+        let exclude_out_points: Vec<OutPoint> =
+            OutPointParser.from_matches_vec(m, "exclude-out-point")?;
+        let coin_control = CoinControl {
+            use_out_points: use_out_points.iter().cloned().collect(),
+            exclude_out_points: exclude_out_points.iter().cloned().collect(),
+        };
+        if coin_control.use_out_points.len() != use_out_points.len()
+            || coin_control.exclude_out_points.len() != exclude_out_points.len()
+        {
+            return Err("Duplicated out-points".to_string());
+        }
+        Ok(coin_control)
+    }
+
+    pub fn is_manual(&self) -> bool {
+        !self.use_out_points.is_empty()
+    }
+
+    /// Never spend the cell, e.g. it's spent by a transaction built but not
+    /// committed yet
+    pub fn exclude(&mut self, out_point: OutPoint) {
+        self.exclude_out_points.insert(out_point);
+    }
+
+    /// Whether the cell may be spent
+    pub fn allows(&self, out_point: &OutPoint) -> bool {
+        if self.exclude_out_points.contains(out_point) {
+            false
+        } else if self.is_manual() {
+            self.use_out_points.contains(out_point)
+        } else {
+            true
+        }
+    }
+
+    /// The error of not enough capacity, with the spendable cells not selected
+    /// (by capacity, largest first) to choose from. `selected` are the cells
+    /// taken, `candidates` are all the spendable cells.
+    pub fn not_enough_error(
+        &self,
+        from: &str,
+        required: u64,
+        selected: &[LiveCellInfo],
+        candidates: &[LiveCellInfo],
+    ) -> String {
+        let selected_capacity: u64 = selected.iter().map(|info| info.capacity).sum();
+        let mut message = format!(
+            "Capacity(mature) not enough: {} => {}, required: {}",
+            from,
+            HumanCapacity::from(selected_capacity),
+            HumanCapacity::from(required),
+        );
+        if self.is_manual() {
+            let missing = self
+                .use_out_points
+                .iter()
+                .filter(|out_point| !selected.iter().any(|info| &info.out_point() == *out_point))
+                .map(display_out_point)
+                .collect::<Vec<_>>();
+            if !missing.is_empty() {
+                message.push_str(&format!(
+                    "\nNot spendable cells (spent, immature, or with type script or data): {}",
+                    missing.join(", ")
+                ));
+            }
+        }
+        let mut others = candidates
+            .iter()
+            .filter(|info| {
+                let out_point = info.out_point();
+                !self.exclude_out_points.contains(&out_point)
+                    && !selected
+                        .iter()
+                        .any(|selected| selected.out_point() == out_point)
+            })
+            .collect::<Vec<_>>();
+        others.sort_by(|a, b| b.capacity.cmp(&a.capacity));
+        if !others.is_empty() {
+            message.push_str("\nOther spendable cells (--use-out-point):");
+            for info in others.iter().take(MAX_LISTED_CANDIDATES) {
+                message.push_str(&format!(
+                    "\n  {} {}",
+                    display_out_point(&info.out_point()),
+                    HumanCapacity::from(info.capacity)
+                ));
+            }
+            if others.len() > MAX_LISTED_CANDIDATES {
+                message.push_str(&format!(
+                    "\n  ... {} more",
+                    others.len() - MAX_LISTED_CANDIDATES
+                ));
+            }
+        }
+        message
+    }
+}
+
+/// The out-point in the format of the arguments: `<tx-hash>-<index>`
+pub fn display_out_point(out_point: &OutPoint) -> String {
+    let tx_hash: H256 = out_point.tx_hash().unpack();
+    let index: u32 = out_point.index().unpack();
+    format!("{:#x}-{}", tx_hash, index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::arg;
+    use ckb_index::CellIndex;
+    use ckb_sdk::constants::ONE_CKB;
+    use clap::App;
+
+    fn out_point(index: u32) -> OutPoint {
+        OutPoint::new(H256::default().pack(), index)
+    }
+
+    fn cell(index: u32, capacity: u64) -> LiveCellInfo {
+        LiveCellInfo {
+            tx_hash: H256::default(),
+            tx_index: index,
+            data_bytes: 0,
+            lock_hash: H256::default(),
+            type_hashes: None,
+            capacity,
+            number: 10,
+            index: CellIndex {
+                tx_index: 1,
+                output_index: index,
+            },
+        }
+    }
+
+    fn from_args(args: &[&str]) -> Result<CoinControl, String> {
+        let matches = App::new("test")
+            .arg(arg::use_out_point())
+            .arg(arg::exclude_out_point())
+            .get_matches_from_safe(args)
+            .map_err(|err| err.message)?;
+        CoinControl::from_matches(&matches)
+    }
+
+    #[test]
+    fn test_from_matches() {
+        let hash = format!("{:#x}", H256::default());
+        let use_0 = format!("{}-0", hash);
+        let use_1 = format!("{}:1", hash);
+
+        let coin_control = from_args(&["test"]).unwrap();
+        assert!(!coin_control.is_manual());
+        assert!(coin_control.allows(&out_point(0)));
+
+        let coin_control =
+            from_args(&["test", "--use-out-point", &use_0, "--use-out-point", &use_1]).unwrap();
+        assert!(coin_control.is_manual());
+        assert!(coin_control.allows(&out_point(1)));
+        assert!(!coin_control.allows(&out_point(2)));
+
+        let coin_control = from_args(&["test", "--exclude-out-point", &use_0]).unwrap();
+        assert!(!coin_control.is_manual());
+        assert!(!coin_control.allows(&out_point(0)));
+        assert!(coin_control.allows(&out_point(1)));
+
+        assert_eq!(
+            from_args(&["test", "--use-out-point", &use_0, "--use-out-point", &use_0]).unwrap_err(),
+            "Duplicated out-points"
+        );
+        // Both options are not allowed together
+        assert!(from_args(&[
+            "test",
+            "--use-out-point",
+            &use_0,
+            "--exclude-out-point",
+            &use_1
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_allows() {
+        let mut coin_control = CoinControl {
+            use_out_points: vec![out_point(0), out_point(1)].into_iter().collect(),
+            exclude_out_points: HashSet::new(),
+        };
+        assert!(coin_control.allows(&out_point(0)));
+        assert!(!coin_control.allows(&out_point(2)));
+        // Excluded over manually selected, e.g. spent by a transaction not
+        // committed yet
+        coin_control.exclude(out_point(0));
+        assert!(!coin_control.allows(&out_point(0)));
+        assert!(coin_control.allows(&out_point(1)));
+        assert!(coin_control.is_manual());
+    }
+
+    #[test]
+    fn test_not_enough_error() {
+        let candidates: Vec<_> = (0..25)
+            .map(|index| cell(index, u64::from(index + 1) * ONE_CKB))
+            .collect();
+        let mut coin_control = CoinControl::default();
+        coin_control.exclude(out_point(24));
+        let message =
+            coin_control.not_enough_error("ckt1qyq", 100 * ONE_CKB, &candidates[..1], &candidates);
+        let lines: Vec<_> = message.lines().collect();
+        assert_eq!(
+            lines[0],
+            "Capacity(mature) not enough: ckt1qyq => 1.0, required: 100.0"
+        );
+        assert_eq!(lines[1], "Other spendable cells (--use-out-point):");
+        // The largest first, neither excluded nor selected
+        assert_eq!(
+            lines[2],
+            format!("  {} 24.0", display_out_point(&out_point(23)))
+        );
+        assert_eq!(lines.len(), 2 + MAX_LISTED_CANDIDATES + 1);
+        assert_eq!(lines[lines.len() - 1], "  ... 3 more");
+
+        // The manually selected cells not spendable are listed
+        let coin_control = CoinControl {
+            use_out_points: vec![out_point(0), out_point(30)].into_iter().collect(),
+            exclude_out_points: HashSet::new(),
+        };
+        let message = coin_control.not_enough_error(
+            "ckt1qyq",
+            100 * ONE_CKB,
+            &candidates[..1],
+            &candidates[..1],
+        );
+        assert_eq!(
+            message.lines().nth(1).unwrap(),
+            format!(
+                "Not spendable cells (spent, immature, or with type script or data): {}",
+                display_out_point(&out_point(30))
+            )
+        );
+        assert_eq!(message.lines().count(), 2);
+    }
+}
@@ -5,12 +5,15 @@ use std::path::PathBuf;
 use std::sync::Arc;
 
 use ansi_term::Colour::Yellow;
-use ckb_sdk::NetworkType;
+use ckb_sdk::{HumanCapacity, NetworkType};
 use ckb_util::RwLock;
 use regex::{Captures, Regex};
 
 use crate::utils::{
+    alert::AlertConfig,
+    broadcast::SendTargets,
     index::IndexThreadState,
+    policy::SigningPolicy,
     printer::{OutputFormat, Printable},
 };
 
@@ -25,6 +28,9 @@ pub struct GlobalConfig {
     path: PathBuf,
     completion_style: bool,
     edit_style: bool,
+    signing_policy: SigningPolicy,
+    alert_config: AlertConfig,
+    send_targets: SendTargets,
     env_variable: HashMap<String, serde_json::Value>,
     index_state: Arc<RwLock<IndexThreadState>>,
 }
@@ -40,6 +46,9 @@ impl GlobalConfig {
             path: env::current_dir().unwrap(),
             completion_style: true,
             edit_style: true,
+            signing_policy: SigningPolicy::default(),
+            alert_config: AlertConfig::default(),
+            send_targets: SendTargets::default(),
             env_variable: HashMap::new(),
             index_state,
         }
@@ -90,23 +99,39 @@ impl GlobalConfig {
         self.env_variable.extend(vars);
     }
 
+    /// Substitute `${key}` by the variable, or the environment variable when
+    /// the variable is not set
     pub fn replace_cmd(&self, regex: &Regex, line: &str) -> String {
         regex
             .replace_all(line, |caps: &Captures| match caps.name("key") {
-                Some(key) => self
-                    .get(Some(key.as_str()))
-                    .map(|value| match value {
-                        serde_json::Value::String(s) => s.to_owned(),
-                        serde_json::Value::Number(n) => n.to_string(),
-                        _ => String::new(),
-                    })
-                    .next()
-                    .unwrap_or_default(),
+                Some(key) => self.lookup_var(key.as_str()).unwrap_or_default(),
                 None => String::new(),
             })
             .into_owned()
     }
 
+    /// The `${key}` of the line set neither as a variable nor as an
+    /// environment variable
+    pub fn undefined_vars(&self, regex: &Regex, line: &str) -> Vec<String> {
+        regex
+            .captures_iter(line)
+            .filter_map(|caps| caps.name("key"))
+            .map(|key| key.as_str().to_string())
+            .filter(|key| self.lookup_var(key).is_none())
+            .collect()
+    }
+
+    fn lookup_var(&self, key: &str) -> Option<String> {
+        self.get(Some(key))
+            .map(|value| match value {
+                serde_json::Value::String(s) => s.to_owned(),
+                serde_json::Value::Number(n) => n.to_string(),
+                _ => String::new(),
+            })
+            .next()
+            .or_else(|| env::var(key).ok())
+    }
+
     pub fn set_url(&mut self, value: String) {
         if value.starts_with("http://") || value.starts_with("https://") {
             self.url = Some(value);
@@ -145,6 +170,10 @@ impl GlobalConfig {
         self.edit_style = !self.edit_style;
     }
 
+    pub fn switch_hardened_only(&mut self) {
+        self.signing_policy.hardened_only = !self.signing_policy.hardened_only;
+    }
+
     pub fn set_color(&mut self, value: bool) {
         self.color = value;
     }
@@ -165,6 +194,18 @@ impl GlobalConfig {
         self.edit_style = value;
     }
 
+    pub fn set_signing_policy(&mut self, value: SigningPolicy) {
+        self.signing_policy = value;
+    }
+
+    pub fn set_alert_config(&mut self, value: AlertConfig) {
+        self.alert_config = value;
+    }
+
+    pub fn set_send_targets(&mut self, value: SendTargets) {
+        self.send_targets = value;
+    }
+
     pub fn color(&self) -> bool {
         self.color
     }
@@ -185,37 +226,58 @@ impl GlobalConfig {
         self.edit_style
     }
 
-    pub fn print(&self) {
-        let path = self.path.to_string_lossy();
-        let color = self.color.to_string();
-        let debug = self.debug.to_string();
-        let output_format = self.output_format.to_string();
+    pub fn signing_policy(&self) -> SigningPolicy {
+        self.signing_policy.clone()
+    }
+
+    pub fn alert_config(&self) -> &AlertConfig {
+        &self.alert_config
+    }
+
+    pub fn send_targets(&self) -> SendTargets {
+        self.send_targets.clone()
+    }
+
+    /// The displayed config entries: (name, value)
+    pub fn entries(&self) -> Vec<(&'static str, String)> {
         let completion_style = if self.completion_style {
             "List"
         } else {
             "Circular"
         };
         let edit_style = if self.edit_style { "Emacs" } else { "Vi" };
-        let index_state = self.index_state.read().to_string();
-        let version = crate::get_version();
-        let version_long = version.long();
+        let max_fee = format!(
+            "{:#} or {}% of transferred capacity",
+            HumanCapacity::from(self.signing_policy.max_fee),
+            self.signing_policy.max_fee_basis_points as f64 / 100.0
+        );
         let network_string = self
             .network()
             .map(|value| format!("{:?}", value))
             .unwrap_or_else(|| "unknown".to_string());
-        let url_string = format!("{} (network: {})", self.get_url(), network_string);
-        let values = [
-            ("ckb-cli version", version_long.as_str()),
-            ("url", url_string.as_str()),
-            ("pwd", path.deref()),
-            ("color", color.as_str()),
-            ("debug", debug.as_str()),
-            ("output format", output_format.as_str()),
-            ("completion style", completion_style),
-            ("edit style", edit_style),
-            ("index db state", index_state.as_str()),
-        ];
+        vec![
+            ("ckb-cli version", crate::get_version().long()),
+            (
+                "url",
+                format!("{} (network: {})", self.get_url(), network_string),
+            ),
+            ("pwd", self.path.to_string_lossy().into_owned()),
+            ("color", self.color.to_string()),
+            ("debug", self.debug.to_string()),
+            ("output format", self.output_format.to_string()),
+            ("completion style", completion_style.to_string()),
+            ("edit style", edit_style.to_string()),
+            (
+                "hardened only",
+                self.signing_policy.hardened_only.to_string(),
+            ),
+            ("max fee", max_fee),
+            ("index db state", self.index_state.read().to_string()),
+        ]
+    }
 
+    pub fn print(&self) {
+        let values = self.entries();
         let max_width = values
             .iter()
             .map(|(name, _)| name.len())
@@ -225,9 +287,9 @@ impl GlobalConfig {
             .iter()
             .map(|(name, value)| {
                 let value = if self.color {
-                    Yellow.paint(*value).to_string()
+                    Yellow.paint(value.as_str()).to_string()
                 } else {
-                    (*value).to_string()
+                    value.clone()
                 };
                 format!("[ {:>width$} ]: {}", name, value, width = max_width)
             })
@@ -268,3 +330,91 @@ impl<'a> ::std::iter::Iterator for KV<'a> {
         }
     }
 }
+
+/// Select a field of a json value by a jq-like path, eg: `.transaction.hash`,
+/// `.inputs[0].capacity` (or `.inputs.0.capacity`), `.` is the whole value
+pub fn select_field(
+    value: &serde_json::Value,
+    selector: &str,
+) -> Result<serde_json::Value, String> {
+    if !selector.starts_with('.') {
+        return Err(format!(
+            "Invalid field selector {}: expected to start with `.`",
+            selector
+        ));
+    }
+    let path = selector.replace('[', ".").replace(']', "");
+    path.split('.')
+        .filter(|part| !part.is_empty())
+        .try_fold(value, |value, part| {
+            let field = match (value, part.parse::<usize>()) {
+                (serde_json::Value::Array(items), Ok(index)) => items.get(index),
+                _ => value.get(part),
+            };
+            field.ok_or_else(|| format!("No field {} in the output", selector))
+        })
+        .map(Clone::clone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_field() {
+        let value = serde_json::json!({
+            "transaction": {"hash": "0x01", "inputs": [{"capacity": 1}, {"capacity": 2}]},
+        });
+        assert_eq!(select_field(&value, ".").unwrap(), value);
+        assert_eq!(
+            select_field(&value, ".transaction.hash").unwrap(),
+            serde_json::json!("0x01")
+        );
+        assert_eq!(
+            select_field(&value, ".transaction.inputs[1].capacity").unwrap(),
+            serde_json::json!(2)
+        );
+        assert_eq!(
+            select_field(&value, ".transaction.inputs.0").unwrap(),
+            serde_json::json!({"capacity": 1})
+        );
+        assert!(select_field(&value, ".transaction.inputs[2]").is_err());
+        assert!(select_field(&value, ".transaction.fee").is_err());
+        assert!(select_field(&value, "transaction").is_err());
+    }
+
+    #[test]
+    fn test_replace_cmd() {
+        let regex = Regex::new(crate::interactive::ENV_PATTERN).unwrap();
+        let mut config =
+            GlobalConfig::new(None, Arc::new(RwLock::new(IndexThreadState::WaitToStart)));
+        config.set("amount".to_string(), serde_json::json!("100"));
+        config.set("fee".to_string(), serde_json::json!(1));
+        let env_key = format!("CKB_CLI_TEST_VAR_{}", std::process::id());
+        env::set_var(&env_key, "ckt1qyq");
+        // A variable, a number and the environment variable fallback
+        let line = format!(
+            "wallet transfer --to-address ${{{}}} --capacity ${{amount}} --tx-fee ${{ fee }}",
+            env_key
+        );
+        assert_eq!(
+            config.replace_cmd(&regex, &line),
+            "wallet transfer --to-address ckt1qyq --capacity 100 --tx-fee 1"
+        );
+        assert!(config.undefined_vars(&regex, &line).is_empty());
+        // A variable overrides the environment variable
+        config.set(env_key.clone(), serde_json::json!("ckt1qyz"));
+        assert_eq!(
+            config.replace_cmd(&regex, &format!("${{{}}}", env_key)),
+            "ckt1qyz"
+        );
+        env::remove_var(&env_key);
+
+        let line = "wallet transfer --to-address ${to} --capacity ${amount}";
+        assert_eq!(config.undefined_vars(&regex, line), vec!["to".to_string()]);
+        assert_eq!(
+            config.replace_cmd(&regex, line),
+            "wallet transfer --to-address  --capacity 100"
+        );
+    }
+}
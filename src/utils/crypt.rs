@@ -0,0 +1,286 @@
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::thread;
+
+use ckb_hash::blake2b_256;
+use ckb_types::H256;
+
+const AGE_BIN: &str = "age";
+const GPG_BIN: &str = "gpg";
+const AGE_ARMOR_HEADER: &str = "-----BEGIN AGE ENCRYPTED FILE-----";
+const PGP_ARMOR_HEADER: &str = "-----BEGIN PGP MESSAGE-----";
+const GPG_STATUS_PREFIX: &str = "[GNUPG:] ";
+
+/// The recipient of an encrypted file: an age public key (`age1...`) or a gpg
+/// key (key id, fingerprint or email)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Recipient {
+    Age(String),
+    Gpg(String),
+}
+
+impl FromStr for Recipient {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Recipient, String> {
+        if input.is_empty() {
+            Err("Empty recipient".to_string())
+        } else if input.starts_with("age1") {
+            Ok(Recipient::Age(input.to_string()))
+        } else {
+            Ok(Recipient::Gpg(input.to_string()))
+        }
+    }
+}
+
+/// Encrypt `content` to `recipient`, the output is ASCII armored. The gpg
+/// message is also signed by the default key (or `sign_key`) of local user.
+pub fn encrypt(
+    content: &[u8],
+    recipient: &Recipient,
+    sign_key: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    match recipient {
+        Recipient::Age(key) => {
+            let mut cmd = Command::new(AGE_BIN);
+            cmd.args(&["--encrypt", "--armor", "--recipient", key]);
+            run_filter(AGE_BIN, cmd, content).map(|(output, _)| output)
+        }
+        Recipient::Gpg(key) => {
+            let mut cmd = Command::new(GPG_BIN);
+            cmd.args(&["--armor", "--encrypt", "--sign", "--recipient", key]);
+            if let Some(sign_key) = sign_key {
+                cmd.args(&["--local-user", sign_key]);
+            }
+            run_filter(GPG_BIN, cmd, content).map(|(output, _)| output)
+        }
+    }
+}
+
+/// The blake2b-256 hash of the plain content, given to the importer of an
+/// unsigned (age) file to authenticate it
+pub fn content_hash(content: &[u8]) -> H256 {
+    H256::from(blake2b_256(content))
+}
+
+/// Decrypt an ASCII armored age/gpg file. A gpg message must carry a good
+/// signature by the key of `sender_fingerprint` (a good signature of any key
+/// in the keyring is not enough), an age file must be decrypted by `identity`
+/// file and match `expected_hash` (anyone knowing the recipient can encrypt an
+/// age file).
+pub fn decrypt(
+    content: &[u8],
+    identity: Option<&PathBuf>,
+    expected_hash: Option<&H256>,
+    sender_fingerprint: Option<&str>,
+) -> Result<Vec<u8>, String> {
+    let text = String::from_utf8_lossy(content);
+    let text = text.trim_start();
+    let output = if text.starts_with(AGE_ARMOR_HEADER) {
+        let identity = identity
+            .ok_or_else(|| "The identity file is required to decrypt age file".to_string())?;
+        let expected_hash = expected_hash.ok_or_else(|| {
+            "The age file is not signed, the content hash given by the exporter is required"
+                .to_string()
+        })?;
+        let mut cmd = Command::new(AGE_BIN);
+        cmd.arg("--decrypt").arg("--identity").arg(identity);
+        let (output, _) = run_filter(AGE_BIN, cmd, content)?;
+        check_content_hash(&output, expected_hash)?;
+        output
+    } else if text.starts_with(PGP_ARMOR_HEADER) {
+        let sender_fingerprint = sender_fingerprint.ok_or_else(|| {
+            "The fingerprint of the sender's gpg key is required to verify the signature"
+                .to_string()
+        })?;
+        let mut cmd = Command::new(GPG_BIN);
+        cmd.args(&["--decrypt", "--status-fd", "2"]);
+        let (output, status) = run_filter(GPG_BIN, cmd, content)?;
+        check_gpg_signer(&status, sender_fingerprint)?;
+        if let Some(expected_hash) = expected_hash {
+            check_content_hash(&output, expected_hash)?;
+        }
+        output
+    } else {
+        return Err(
+            "Unknown encrypted file format, expected ASCII armored age or gpg file".to_string(),
+        );
+    };
+    Ok(output)
+}
+
+/// Normalize a full gpg key fingerprint (40 or 64 hex characters, spaces are
+/// allowed), a short key id is refused since it can be forged
+pub fn parse_gpg_fingerprint(input: &str) -> Result<String, String> {
+    let fingerprint = input
+        .trim_start_matches("0x")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase();
+    if !(fingerprint.len() == 40 || fingerprint.len() == 64)
+        || !fingerprint.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return Err(format!(
+            "Invalid gpg fingerprint {}, expected the full fingerprint (40 or 64 hex characters)",
+            input
+        ));
+    }
+    Ok(fingerprint)
+}
+
+// The `--status-fd` output of gpg must have a good signature (GOODSIG) and a
+// valid signature (VALIDSIG) of the expected key, VALIDSIG gives the
+// fingerprint of the signing (sub)key and of its primary key
+fn check_gpg_signer(status: &str, expected_fingerprint: &str) -> Result<(), String> {
+    let expected_fingerprint = parse_gpg_fingerprint(expected_fingerprint)?;
+    let mut good = false;
+    let mut signers = Vec::new();
+    for line in status.lines() {
+        if !line.starts_with(GPG_STATUS_PREFIX) {
+            continue;
+        }
+        let fields = line[GPG_STATUS_PREFIX.len()..]
+            .split_whitespace()
+            .collect::<Vec<_>>();
+        match fields.first() {
+            Some(&"GOODSIG") => good = true,
+            Some(&"VALIDSIG") => {
+                // VALIDSIG <fpr> <date> <timestamp> <expire> <version>
+                //   <reserved> <pubkey-algo> <hash-algo> <class> [<primary-fpr>]
+                signers.extend(fields.get(1).map(|fpr| fpr.to_ascii_uppercase()));
+                signers.extend(fields.get(10).map(|fpr| fpr.to_ascii_uppercase()));
+            }
+            _ => {}
+        }
+    }
+    if !good || signers.is_empty() {
+        return Err("The gpg message has no good signature".to_string());
+    }
+    if !signers.contains(&expected_fingerprint) {
+        return Err(format!(
+            "The gpg message is signed by {}, expected {}",
+            signers[0], expected_fingerprint
+        ));
+    }
+    Ok(())
+}
+
+fn check_content_hash(content: &[u8], expected_hash: &H256) -> Result<(), String> {
+    let actual_hash = content_hash(content);
+    if &actual_hash != expected_hash {
+        return Err(format!(
+            "The content hash of the decrypted file is {:#x}, expected {:#x}",
+            actual_hash, expected_hash
+        ));
+    }
+    Ok(())
+}
+
+// Pipe `input` through the command, return stdout and stderr
+fn run_filter(bin: &str, mut cmd: Command, input: &[u8]) -> Result<(Vec<u8>, String), String> {
+    let mut child = cmd
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            if err.kind() == ErrorKind::NotFound {
+                format!("{} not found in PATH", bin)
+            } else {
+                format!("Run {} error: {}", bin, err)
+            }
+        })?;
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    // Write in another thread, otherwise the child may block on a full stdout
+    let writer = thread::spawn(move || stdin.write_all(&input));
+    let output = child.wait_with_output().map_err(|err| err.to_string())?;
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    writer
+        .join()
+        .map_err(|_| format!("Write to {} panicked", bin))?
+        .map_err(|err| format!("Write to {} error: {}", bin, err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} exited with {}: {}",
+            bin,
+            output.status,
+            stderr.trim()
+        ));
+    }
+    Ok((output.stdout, stderr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_recipient() {
+        assert_eq!(
+            Recipient::from_str("age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p"),
+            Ok(Recipient::Age(
+                "age1ql3z7hjy54pw3hyww5ayyfg7zqgvc7w3j2elw8zmrj2kg5sfn9aqmcac8p".to_string()
+            ))
+        );
+        assert_eq!(
+            Recipient::from_str("alice@example.com"),
+            Ok(Recipient::Gpg("alice@example.com".to_string()))
+        );
+        assert!(Recipient::from_str("").is_err());
+    }
+
+    #[test]
+    fn test_check_content_hash() {
+        let content = br#"{"transaction": {}}"#;
+        let hash = content_hash(content);
+        assert_eq!(hash, H256::from(blake2b_256(content)));
+        assert!(check_content_hash(content, &hash).is_ok());
+        assert!(check_content_hash(br#"{"transaction": {"version": "0x1"}}"#, &hash).is_err());
+        // An age file is not decrypted without the content hash
+        let age_file = format!(
+            "{}\nYWdl\n-----END AGE ENCRYPTED FILE-----\n",
+            AGE_ARMOR_HEADER
+        );
+        let identity = PathBuf::from("identity.txt");
+        let err = decrypt(age_file.as_bytes(), Some(&identity), None, None).unwrap_err();
+        assert!(err.contains("content hash"), "{}", err);
+    }
+
+    #[test]
+    fn test_check_gpg_signer() {
+        let sender = "D8A1 64A2 93CA 8A91 9A3C  9F6E 5F12 C4B8 E3A9 7F20";
+        let subkey = "0B1E5C3D7A2F9E8D6C4B3A291807F6E5D4C3B2A1";
+        let status = |fpr: &str| {
+            format!(
+                "[GNUPG:] NEWSIG\n[GNUPG:] GOODSIG 5F12C4B8E3A97F20 Alice <alice@example.com>\n\
+                 [GNUPG:] VALIDSIG {} 2021-01-01 1609459200 0 4 0 1 10 00 D8A164A293CA8A919A3C9F6E5F12C4B8E3A97F20\n",
+                fpr
+            )
+        };
+        // Signed by the primary key or its subkey
+        assert!(
+            check_gpg_signer(&status("D8A164A293CA8A919A3C9F6E5F12C4B8E3A97F20"), sender).is_ok()
+        );
+        assert!(check_gpg_signer(&status(subkey), sender).is_ok());
+        assert!(check_gpg_signer(&status(subkey), subkey).is_ok());
+        // A good signature of another key in the keyring
+        let other = "[GNUPG:] GOODSIG 1111111111111111 Mallory\n\
+                     [GNUPG:] VALIDSIG 1111111111111111111111111111111111111111 2021-01-01 1609459200 0 4 0 1 10 00 1111111111111111111111111111111111111111\n";
+        let err = check_gpg_signer(other, sender).unwrap_err();
+        assert!(err.contains("expected"), "{}", err);
+        // Expired key: VALIDSIG without GOODSIG
+        let expired = status(subkey).replace("GOODSIG", "EXPKEYSIG");
+        assert!(check_gpg_signer(&expired, sender).is_err());
+        assert!(check_gpg_signer("", sender).is_err());
+        // The short key id is refused
+        assert!(check_gpg_signer(&status(subkey), "5F12C4B8E3A97F20").is_err());
+        assert_eq!(
+            parse_gpg_fingerprint("0xd8a164a293ca8a919a3c9f6e5f12c4b8e3a97f20").unwrap(),
+            "D8A164A293CA8A919A3C9F6E5F12C4B8E3A97F20"
+        );
+    }
+}
@@ -0,0 +1,126 @@
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use std::str::FromStr;
+
+use ckb_sdk::{MockTransaction, ReprMockTransaction};
+use ckb_types::{prelude::*, H256};
+
+use super::printer::{OutputFormat, Printable};
+
+const DEBUGGER_BIN: &str = "ckb-debugger";
+
+/// The script group to debug, format: `<input|output>.<index>.<lock|type>`
+/// (e.g. `input.0.lock`, `output.1.type`)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ScriptGroupSpec {
+    pub cell_type: String,
+    pub cell_index: usize,
+    pub script_type: String,
+}
+
+impl FromStr for ScriptGroupSpec {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<ScriptGroupSpec, String> {
+        let parts = input.split('.').collect::<Vec<_>>();
+        let err = || {
+            format!(
+                "Invalid script group: {}, expected format: <input|output>.<index>.<lock|type>",
+                input
+            )
+        };
+        if parts.len() != 3 {
+            return Err(err());
+        }
+        let cell_type = parts[0].to_string();
+        let cell_index = parts[1].parse::<usize>().map_err(|_| err())?;
+        let script_type = parts[2].to_string();
+        if (cell_type != "input" && cell_type != "output")
+            || (script_type != "lock" && script_type != "type")
+        {
+            return Err(err());
+        }
+        if cell_type == "output" && script_type == "lock" {
+            return Err(format!("Output lock script is not executed: {}", input));
+        }
+        Ok(ScriptGroupSpec {
+            cell_type,
+            cell_index,
+            script_type,
+        })
+    }
+}
+
+impl fmt::Display for ScriptGroupSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}",
+            self.cell_type, self.cell_index, self.script_type
+        )
+    }
+}
+
+/// Export the mock transaction to a temporary file and run the failing script
+/// group by `ckb-debugger` (ckb-standalone-debugger), its output is streamed to
+/// the terminal.
+pub fn run_debugger(mock_tx: &MockTransaction, group: &ScriptGroupSpec) -> Result<(), String> {
+    let tx_hash: H256 = mock_tx.core_transaction().hash().unpack();
+    let mut tx_file: PathBuf = env::temp_dir();
+    tx_file.push(format!("ckb-cli-mock-tx-{:x}.json", tx_hash));
+    let content = ReprMockTransaction::from(mock_tx.clone()).render(OutputFormat::Json, false);
+    let mut file = fs::File::create(&tx_file).map_err(|err| err.to_string())?;
+    file.write_all(content.as_bytes())
+        .map_err(|err| err.to_string())?;
+    eprintln!(
+        "Mock transaction exported to {}, debugging script group {}",
+        tx_file.to_string_lossy(),
+        group
+    );
+
+    let status = Command::new(DEBUGGER_BIN)
+        .arg("--tx-file")
+        .arg(&tx_file)
+        .arg("--cell-type")
+        .arg(&group.cell_type)
+        .arg("--cell-index")
+        .arg(group.cell_index.to_string())
+        .arg("--script-group-type")
+        .arg(&group.script_type)
+        .status()
+        .map_err(|err| {
+            if err.kind() == ErrorKind::NotFound {
+                format!(
+                    "{} not found in PATH, install it from https://github.com/nervosnetwork/ckb-standalone-debugger",
+                    DEBUGGER_BIN
+                )
+            } else {
+                format!("Run {} error: {}", DEBUGGER_BIN, err)
+            }
+        })?;
+    eprintln!("{} exited with {}", DEBUGGER_BIN, status);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_script_group() {
+        let group = ScriptGroupSpec::from_str("input.2.lock").unwrap();
+        assert_eq!(group.cell_type, "input");
+        assert_eq!(group.cell_index, 2);
+        assert_eq!(group.script_type, "lock");
+        assert_eq!(group.to_string(), "input.2.lock");
+        assert!(ScriptGroupSpec::from_str("output.0.type").is_ok());
+        assert!(ScriptGroupSpec::from_str("output.0.lock").is_err());
+        assert!(ScriptGroupSpec::from_str("input.x.lock").is_err());
+        assert!(ScriptGroupSpec::from_str("dep.0.type").is_err());
+        assert!(ScriptGroupSpec::from_str("input.0").is_err());
+    }
+}
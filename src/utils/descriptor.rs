@@ -0,0 +1,292 @@
+use std::fmt;
+use std::str::FromStr;
+
+use ckb_hash::blake2b_256;
+use ckb_sdk::{
+    wallet::{ChainCode, ChildNumber, DerivationPath, ExtendedPubKey, Fingerprint, KeyChain},
+    AddressPayload, MultisigConfig, SECP256K1,
+};
+use ckb_types::H160;
+use faster_hex::{hex_decode, hex_string};
+
+use super::arg_parser::{ArgParser, FixedHashParser, FromStrParser};
+
+const CHECKSUM_LEN: usize = 4;
+
+/// A textual definition of an account, it can be shared with other tools and
+/// the addresses are reconstructed from it deterministically (analogous to
+/// Bitcoin output descriptors). An optional `#<checksum>` suffix is the first
+/// 4 bytes of blake2b(body).
+///
+///   - `sighash(0x<lock-arg>)`: a single secp256k1 key
+///   - `sighash([<account-path>]0x<pubkey><chain-code>)`: a BIP-44 account,
+///     receiving addresses are derived by `<account-path>/0/i` and change
+///     addresses by `<account-path>/1/i`
+///   - `multisig(<require-first-n>,<threshold>,0x<lock-arg>,...[,since=<epoch>])`:
+///     a secp256k1-multisig lock
+#[derive(Clone)]
+pub enum Descriptor {
+    Sighash(H160),
+    SighashAccount {
+        path: DerivationPath,
+        extended_pubkey: ExtendedPubKey,
+    },
+    Multisig {
+        config: MultisigConfig,
+        since_absolute_epoch: Option<u64>,
+    },
+}
+
+impl Descriptor {
+    pub fn lock_type(&self) -> &'static str {
+        match self {
+            Descriptor::Sighash(_) | Descriptor::SighashAccount { .. } => "sighash",
+            Descriptor::Multisig { .. } => "multisig",
+        }
+    }
+
+    /// The address of a single key or multisig descriptor, `None` for an
+    /// account descriptor
+    pub fn address_payload(&self) -> Option<AddressPayload> {
+        match self {
+            Descriptor::Sighash(lock_arg) => {
+                Some(AddressPayload::from_pubkey_hash(lock_arg.clone()))
+            }
+            Descriptor::SighashAccount { .. } => None,
+            Descriptor::Multisig {
+                config,
+                since_absolute_epoch,
+            } => Some(config.to_address_payload(*since_absolute_epoch)),
+        }
+    }
+
+    /// Derive `length` addresses of `chain` from index `start` (only for account
+    /// descriptor)
+    pub fn derive(
+        &self,
+        chain: KeyChain,
+        start: u32,
+        length: u32,
+    ) -> Result<Vec<(DerivationPath, AddressPayload)>, String> {
+        let (path, extended_pubkey) = match self {
+            Descriptor::SighashAccount {
+                path,
+                extended_pubkey,
+            } => (path, extended_pubkey),
+            _ => return Err("Only account descriptor can derive addresses".to_string()),
+        };
+        (start..start + length)
+            .map(|index| {
+                let child_path = [
+                    ChildNumber::from_normal_idx(chain as u32).map_err(|err| err.to_string())?,
+                    ChildNumber::from_normal_idx(index).map_err(|err| err.to_string())?,
+                ];
+                let child = extended_pubkey
+                    .derive_pub(&SECP256K1, &child_path)
+                    .map_err(|err| err.to_string())?;
+                let child_path = DerivationPath::from_str(&format!(
+                    "{}/{}/{}",
+                    path, child_path[0], child_path[1]
+                ))
+                .map_err(|err| err.to_string())?;
+                Ok((child_path, AddressPayload::from_pubkey(&child.public_key)))
+            })
+            .collect()
+    }
+
+    fn body(&self) -> String {
+        match self {
+            Descriptor::Sighash(lock_arg) => format!("sighash({:#x})", lock_arg),
+            Descriptor::SighashAccount {
+                path,
+                extended_pubkey,
+            } => format!(
+                "sighash([{}]0x{}{})",
+                path,
+                hex_string(&extended_pubkey.public_key.serialize()[..]).unwrap(),
+                hex_string(&extended_pubkey.chain_code[..]).unwrap(),
+            ),
+            Descriptor::Multisig {
+                config,
+                since_absolute_epoch,
+            } => {
+                let mut items = vec![
+                    config.require_first_n().to_string(),
+                    config.threshold().to_string(),
+                ];
+                items.extend(
+                    config.sighash_addresses().iter().map(|payload| {
+                        format!("0x{}", hex_string(payload.args().as_ref()).unwrap())
+                    }),
+                );
+                if let Some(epoch) = since_absolute_epoch {
+                    items.push(format!("since={}", epoch));
+                }
+                format!("multisig({})", items.join(","))
+            }
+        }
+    }
+}
+
+fn checksum(body: &str) -> String {
+    hex_string(&blake2b_256(body.as_bytes())[0..CHECKSUM_LEN]).unwrap()
+}
+
+impl fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let body = self.body();
+        write!(f, "{}#{}", body, checksum(&body))
+    }
+}
+
+impl FromStr for Descriptor {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Descriptor, String> {
+        let input = input.trim();
+        let body = match input.rfind('#') {
+            Some(pos) => {
+                let (body, expected) = (&input[..pos], &input[pos + 1..]);
+                let actual = checksum(body);
+                if actual != expected {
+                    return Err(format!(
+                        "Invalid descriptor checksum: expected {}, got {}",
+                        actual, expected
+                    ));
+                }
+                body
+            }
+            None => input,
+        };
+        if !body.ends_with(')') {
+            return Err(format!("Invalid descriptor: {}", body));
+        }
+        if body.starts_with("sighash(") {
+            parse_sighash(&body["sighash(".len()..body.len() - 1])
+        } else if body.starts_with("multisig(") {
+            parse_multisig(&body["multisig(".len()..body.len() - 1])
+        } else {
+            Err(format!(
+                "Invalid descriptor, expected sighash(..) or multisig(..): {}",
+                body
+            ))
+        }
+    }
+}
+
+fn parse_sighash(inner: &str) -> Result<Descriptor, String> {
+    if !inner.starts_with('[') {
+        return FixedHashParser::<H160>::default()
+            .parse(inner)
+            .map(Descriptor::Sighash);
+    }
+    let end = inner
+        .find(']')
+        .ok_or_else(|| format!("Invalid account key, missing ']': {}", inner))?;
+    let path = DerivationPath::from_str(&inner[1..end])
+        .map_err(|err| format!("Invalid account path {}: {}", &inner[1..end], err))?;
+    let key_hex = &inner[end + 1..];
+    if !key_hex.starts_with("0x") || key_hex.len() != 2 + (33 + 32) * 2 {
+        return Err(format!(
+            "Invalid account key, expected 0x<pubkey(33 bytes)><chain-code(32 bytes)>: {}",
+            key_hex
+        ));
+    }
+    let mut key_bytes = [0u8; 33 + 32];
+    hex_decode(key_hex[2..].as_bytes(), &mut key_bytes).map_err(|err| err.to_string())?;
+    let public_key = secp256k1::PublicKey::from_slice(&key_bytes[0..33])
+        .map_err(|err| format!("Invalid public key: {}", err))?;
+    let child_number = path
+        .as_ref()
+        .last()
+        .cloned()
+        .unwrap_or(ChildNumber::Normal { index: 0 });
+    // The parent fingerprint is unknown and not required by derivation
+    let extended_pubkey = ExtendedPubKey {
+        depth: path.as_ref().len() as u8,
+        parent_fingerprint: Fingerprint::from(&[0u8; 4][..]),
+        child_number,
+        public_key,
+        chain_code: ChainCode::from(&key_bytes[33..]),
+    };
+    Ok(Descriptor::SighashAccount {
+        path,
+        extended_pubkey,
+    })
+}
+
+fn parse_multisig(inner: &str) -> Result<Descriptor, String> {
+    let mut items = inner.split(',').map(str::trim).collect::<Vec<_>>();
+    let since_absolute_epoch = match items.last() {
+        Some(item) if item.starts_with("since=") => {
+            let epoch = FromStrParser::<u64>::default().parse(&item["since=".len()..])?;
+            items.pop();
+            Some(epoch)
+        }
+        _ => None,
+    };
+    if items.len() < 3 {
+        return Err(format!(
+            "Invalid multisig, expected <require-first-n>,<threshold>,<lock-arg>,...: {}",
+            inner
+        ));
+    }
+    let require_first_n = FromStrParser::<u8>::default().parse(items[0])?;
+    let threshold = FromStrParser::<u8>::default().parse(items[1])?;
+    let sighash_addresses = items[2..]
+        .iter()
+        .map(|item| {
+            FixedHashParser::<H160>::default()
+                .parse(item)
+                .map(AddressPayload::from_pubkey_hash)
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let config = MultisigConfig::new_with(sighash_addresses, require_first_n, threshold)?;
+    Ok(Descriptor::Multisig {
+        config,
+        since_absolute_epoch,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptor_roundtrip() {
+        let lock_arg = "0xc8328aabcd9b9e8e64fbc566c4385c3bdeb219d7";
+        let lock_arg2 = "0x2d7a8eaba7e1a2b6a3a4bcb2ea6ebcfc3bb3bb02";
+        let pubkey = secp256k1::PublicKey::from_secret_key(
+            &SECP256K1,
+            &secp256k1::SecretKey::from_slice(&[1u8; 32]).unwrap(),
+        );
+        let inputs = vec![
+            format!("sighash({})", lock_arg),
+            format!(
+                "sighash([m/44'/309'/0']0x{}{})",
+                hex_string(&pubkey.serialize()[..]).unwrap(),
+                hex_string(&[2u8; 32]).unwrap()
+            ),
+            format!("multisig(0,1,{},{})", lock_arg, lock_arg2),
+            format!("multisig(1,2,{},{},since=100)", lock_arg, lock_arg2),
+        ];
+        for input in inputs {
+            let descriptor = Descriptor::from_str(&input).unwrap();
+            let output = descriptor.to_string();
+            assert!(output.starts_with(&format!("{}#", input)));
+            let parsed = Descriptor::from_str(&output).unwrap();
+            assert_eq!(parsed.to_string(), output);
+        }
+    }
+
+    #[test]
+    fn test_descriptor_invalid() {
+        let input = "sighash(0xc8328aabcd9b9e8e64fbc566c4385c3bdeb219d7)#00000000";
+        assert!(Descriptor::from_str(input).is_err());
+        assert!(Descriptor::from_str("pkh(0xc8328aabcd9b9e8e64fbc566c4385c3bdeb219d7)").is_err());
+        assert!(Descriptor::from_str(
+            "multisig(0,3,0xc8328aabcd9b9e8e64fbc566c4385c3bdeb219d7,0x2d7a8eaba7e1a2b6a3a4bcb2ea6ebcfc3bb3bb02)"
+        )
+        .is_err());
+    }
+}
@@ -1,9 +1,14 @@
 use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
 
+use ckb_index::{with_index_db, Error as IndexDbError, IndexDatabase, IndexError};
+use ckb_sdk::{GenesisInfo, NetworkType};
 use ckb_types::{
     core::{service::Request, HeaderView},
     prelude::*,
@@ -13,6 +18,100 @@ use ckb_util::RwLock;
 use crossbeam_channel::Sender;
 use serde_derive::{Deserialize, Serialize};
 
+const SWITCH_NODE_HINT: &str =
+    "please switch to the node of the intended network by `--url` (or `API_URL`, or `config --url` in interactive mode), or index this chain by `index rebuild` (or `--wait-for-sync`)";
+
+/// The genesis hashes of the chains having an index database in `index_dir`
+/// (the databases are kept in the directories named by the genesis hash)
+fn indexed_chains(index_dir: &Path) -> Vec<H256> {
+    let entries = match fs::read_dir(index_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut chains = entries
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with("0x") {
+                return None;
+            }
+            H256::from_str(&name[2..]).ok()
+        })
+        .collect::<Vec<_>>();
+    chains.sort();
+    chains
+}
+
+/// Check the chain of the connected node (by its genesis hash) has an index
+/// database when there are the databases of other chains, a node of another
+/// network would otherwise read an index which is never synced (or empty).
+pub fn check_index_chain(index_dir: &Path, genesis_hash: &H256) -> Result<(), String> {
+    let chains = indexed_chains(index_dir);
+    if chains.is_empty() || chains.contains(genesis_hash) {
+        return Ok(());
+    }
+    Err(format!(
+        "The node is on another chain than the index database (node genesis: {:#x}, index genesis: {}), {}",
+        genesis_hash,
+        chains
+            .iter()
+            .map(|hash| format!("{:#x}", hash))
+            .collect::<Vec<_>>()
+            .join(", "),
+        SWITCH_NODE_HINT
+    ))
+}
+
+/// The error of opening the index database when it belongs to another
+/// network or chain than the node
+pub fn chain_mismatch_error(err: &IndexDbError) -> Option<String> {
+    match err {
+        IndexDbError::Index(IndexError::InvalidNetworkType(detail)) => Some(format!(
+            "The index database is of another network type than the node ({}), {}",
+            detail, SWITCH_NODE_HINT
+        )),
+        IndexDbError::Index(IndexError::InvalidGenesis(detail)) => Some(format!(
+            "The index database belongs to another chain than the node (index genesis: {}), {}",
+            detail, SWITCH_NODE_HINT
+        )),
+        _ => None,
+    }
+}
+
+/// The error of `with_index_db` shown to the user, any error but a chain
+/// mismatch means the index database is not ready
+pub fn index_db_error(err: &IndexDbError, state: &IndexThreadState) -> String {
+    chain_mismatch_error(err).unwrap_or_else(|| {
+        format!(
+            "Index database may not ready, sync process: {}",
+            state.to_string()
+        )
+    })
+}
+
+/// Run `func` on the index database of the chain of `genesis_info`, after
+/// checking the chain has one (see `check_index_chain`)
+pub fn with_chain_index_db<F, T>(
+    index_dir: &Path,
+    index_controller: &IndexController,
+    network_type: NetworkType,
+    genesis_info: GenesisInfo,
+    func: F,
+) -> Result<T, String>
+where
+    F: FnOnce(IndexDatabase) -> T,
+{
+    let genesis_hash: H256 = genesis_info.header().hash().unpack();
+    check_index_chain(index_dir, &genesis_hash)?;
+    let record_txs = index_controller.record_txs();
+    with_index_db(index_dir, genesis_hash, |backend, cf| {
+        let db = IndexDatabase::from_db(backend, cf, network_type, genesis_info, record_txs)?;
+        Ok(func(db))
+    })
+    .map_err(|err| index_db_error(&err, &index_controller.state().read()))
+}
+
 pub enum IndexRequest {
     Kick,
     UpdateUrl(String),
@@ -149,6 +248,7 @@ pub struct IndexController {
     state: Arc<RwLock<IndexThreadState>>,
     sender: Sender<Request<IndexRequest, IndexResponse>>,
     shutdown: Arc<AtomicBool>,
+    record_txs: bool,
 }
 
 impl Clone for IndexController {
@@ -157,6 +257,7 @@ impl Clone for IndexController {
             state: Arc::clone(&self.state),
             shutdown: Arc::clone(&self.shutdown),
             sender: self.sender.clone(),
+            record_txs: self.record_txs,
         }
     }
 }
@@ -166,13 +267,19 @@ impl IndexController {
         state: Arc<RwLock<IndexThreadState>>,
         sender: Sender<Request<IndexRequest, IndexResponse>>,
         shutdown: Arc<AtomicBool>,
+        record_txs: bool,
     ) -> IndexController {
         IndexController {
             state,
             sender,
             shutdown,
+            record_txs,
         }
     }
+    /// The transactions of each lock are recorded (`--index-tx-history`)
+    pub fn record_txs(&self) -> bool {
+        self.record_txs
+    }
     pub fn state(&self) -> &Arc<RwLock<IndexThreadState>> {
         &self.state
     }
@@ -198,3 +305,31 @@ impl IndexController {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_index_chain() {
+        let index_dir =
+            std::env::temp_dir().join(format!("ckb-cli-index-chain-{}", std::process::id()));
+        let mainnet = H256([1u8; 32]);
+        let testnet = H256([2u8; 32]);
+        // No index database yet
+        assert_eq!(check_index_chain(&index_dir, &testnet), Ok(()));
+
+        fs::create_dir_all(index_dir.join(format!("{:#x}", mainnet))).unwrap();
+        fs::write(index_dir.join("config.json"), b"{}").unwrap();
+        assert_eq!(indexed_chains(&index_dir), vec![mainnet.clone()]);
+        assert_eq!(check_index_chain(&index_dir, &mainnet), Ok(()));
+        let err = check_index_chain(&index_dir, &testnet).unwrap_err();
+        assert!(err.contains(&format!("{:#x}", mainnet)));
+        assert!(err.contains("--url"));
+
+        // Both chains are indexed
+        fs::create_dir_all(index_dir.join(format!("{:#x}", testnet))).unwrap();
+        assert_eq!(check_index_chain(&index_dir, &testnet), Ok(()));
+        fs::remove_dir_all(&index_dir).unwrap();
+    }
+}
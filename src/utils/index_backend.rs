@@ -0,0 +1,880 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use ckb_index::{with_index_db, CellIndex, IndexDatabase, LiveCellInfo, LiveCellQuery};
+use ckb_jsonrpc_types::{
+    BlockNumber, CellOutput, HeaderView, JsonBytes, OutPoint, Script, Transaction, TransactionView,
+    Uint32, Uint64,
+};
+use ckb_sdk::{
+    rpc::{new_http_transport, JournaledHandle},
+    GenesisInfo, NetworkType,
+};
+use ckb_types::{bytes::Bytes, packed, prelude::*, H256};
+use ckb_util::RwLock;
+use clap::ArgMatches;
+use jsonrpc_client_core::{expand_params, jsonrpc_client};
+use serde_derive::{Deserialize, Serialize};
+
+use super::index::{check_index_chain, index_db_error, IndexThreadState};
+
+const INDEXER_PAGE_SIZE: u32 = 100;
+
+/// What the live cells are searched by
+#[derive(Debug, Clone)]
+pub enum CellQuery {
+    // Lock script, supported by all backends
+    Lock(packed::Script),
+    // Lock/type/code hash, only supported by local index database
+    Hash(LiveCellQuery),
+}
+
+impl CellQuery {
+    pub fn lock_hash(&self) -> Option<packed::Byte32> {
+        match self {
+            CellQuery::Lock(lock) => Some(lock.calc_script_hash()),
+            CellQuery::Hash(LiveCellQuery::Lock(lock_hash)) => Some(lock_hash.clone()),
+            CellQuery::Hash(_) => None,
+        }
+    }
+}
+
+/// Where the live cells of `wallet`/`dao` subcommands come from
+pub trait IndexBackend {
+    /// The number of the last indexed block
+    fn last_number(&mut self) -> Result<Option<u64>, String>;
+
+    /// Live cells ordered by block number, the `terminator` works the same as
+    /// in `IndexDatabase::get_live_cells_by_lock`: return (stop, push_info)
+    fn get_live_cells(
+        &mut self,
+        query: &CellQuery,
+        from_number: Option<u64>,
+        terminator: &mut dyn FnMut(usize, &LiveCellInfo) -> (bool, bool),
+    ) -> Result<Vec<LiveCellInfo>, String>;
+}
+
+/// Select the index backend by `--index-backend`, `--indexer-url` and
+/// `--light-client-url`
+pub fn index_backend_from_matches(
+    m: &ArgMatches,
+    index_dir: PathBuf,
+    genesis_info: GenesisInfo,
+    network_type: NetworkType,
+    index_state: Arc<RwLock<IndexThreadState>>,
+) -> Result<Box<dyn IndexBackend>, String> {
+    IndexBackendConfig::from_matches(m, index_dir, genesis_info, network_type, index_state)?.build()
+}
+
+/// The `--light-client-url` when `--index-backend light-client` is given
+pub fn light_client_url(m: &ArgMatches) -> Option<String> {
+    match m.value_of("index-backend") {
+        Some("light-client") => m.value_of("light-client-url").map(ToOwned::to_owned),
+        _ => None,
+    }
+}
+
+/// The selected index backend, it can be sent to other threads to build a
+/// backend in each of them.
+#[derive(Clone)]
+pub struct IndexBackendConfig {
+    indexer_url: Option<String>,
+    light_client_url: Option<String>,
+    index_dir: PathBuf,
+    genesis_info: GenesisInfo,
+    network_type: NetworkType,
+    index_state: Arc<RwLock<IndexThreadState>>,
+}
+
+impl IndexBackendConfig {
+    pub fn from_matches(
+        m: &ArgMatches,
+        index_dir: PathBuf,
+        genesis_info: GenesisInfo,
+        network_type: NetworkType,
+        index_state: Arc<RwLock<IndexThreadState>>,
+    ) -> Result<IndexBackendConfig, String> {
+        let indexer_url = match m.value_of("index-backend") {
+            Some("indexer") => Some(
+                m.value_of("indexer-url")
+                    .ok_or_else(|| "--indexer-url is required by indexer backend".to_string())?
+                    .to_string(),
+            ),
+            _ => None,
+        };
+        let light_client_url = match m.value_of("index-backend") {
+            Some("light-client") => {
+                let url = m.value_of("light-client-url").ok_or_else(|| {
+                    "--light-client-url is required by light-client backend".to_string()
+                })?;
+                Some(url.to_string())
+            }
+            _ => None,
+        };
+        Ok(IndexBackendConfig {
+            indexer_url,
+            light_client_url,
+            index_dir,
+            genesis_info,
+            network_type,
+            index_state,
+        })
+    }
+
+    /// Whether the cells are searched by RPC (not locking the local index)
+    pub fn is_remote(&self) -> bool {
+        self.indexer_url.is_some() || self.light_client_url.is_some()
+    }
+
+    pub fn build(&self) -> Result<Box<dyn IndexBackend>, String> {
+        if let Some(ref url) = self.light_client_url {
+            return Ok(Box::new(LightClientBackend::new(url)?));
+        }
+        match self.indexer_url {
+            Some(ref url) => Ok(Box::new(IndexerBackend::new(url)?)),
+            None => Ok(Box::new(LocalIndexBackend {
+                index_dir: self.index_dir.clone(),
+                genesis_info: self.genesis_info.clone(),
+                network_type: self.network_type,
+                index_state: Arc::clone(&self.index_state),
+            })),
+        }
+    }
+}
+
+/// The local index database maintained by the index thread
+pub struct LocalIndexBackend {
+    index_dir: PathBuf,
+    genesis_info: GenesisInfo,
+    network_type: NetworkType,
+    index_state: Arc<RwLock<IndexThreadState>>,
+}
+
+impl LocalIndexBackend {
+    fn with_db<F, T>(&self, func: F) -> Result<T, String>
+    where
+        F: FnOnce(IndexDatabase) -> T,
+    {
+        let genesis_hash: H256 = self.genesis_info.header().hash().unpack();
+        check_index_chain(&self.index_dir, &genesis_hash)?;
+        with_index_db(&self.index_dir, genesis_hash, |backend, cf| {
+            let db = IndexDatabase::from_db(
+                backend,
+                cf,
+                self.network_type,
+                self.genesis_info.clone(),
+                false,
+            )?;
+            Ok(func(db))
+        })
+        .map_err(|err| index_db_error(&err, &self.index_state.read()))
+    }
+}
+
+impl IndexBackend for LocalIndexBackend {
+    fn last_number(&mut self) -> Result<Option<u64>, String> {
+        self.with_db(|db| db.last_number())
+    }
+
+    fn get_live_cells(
+        &mut self,
+        query: &CellQuery,
+        from_number: Option<u64>,
+        terminator: &mut dyn FnMut(usize, &LiveCellInfo) -> (bool, bool),
+    ) -> Result<Vec<LiveCellInfo>, String> {
+        self.with_db(|db| match query {
+            CellQuery::Lock(lock) => {
+                db.get_live_cells_by_lock(lock.calc_script_hash(), from_number, terminator)
+            }
+            CellQuery::Hash(LiveCellQuery::Lock(hash)) => {
+                db.get_live_cells_by_lock(hash.clone(), from_number, terminator)
+            }
+            CellQuery::Hash(LiveCellQuery::Type(hash)) => {
+                db.get_live_cells_by_type(hash.clone(), from_number, terminator)
+            }
+            CellQuery::Hash(LiveCellQuery::Code(hash)) => {
+                db.get_live_cells_by_code(hash.clone(), from_number, terminator)
+            }
+        })
+    }
+}
+
+jsonrpc_client!(pub struct RawIndexerClient {
+    pub fn get_tip(&mut self) -> RpcRequest<Option<IndexerTip>>;
+    pub fn get_cells(
+        &mut self,
+        search_key: SearchKey,
+        order: String,
+        limit: Uint32,
+        after_cursor: Option<JsonBytes>
+    ) -> RpcRequest<IndexerCells>;
+});
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchKey {
+    script: Script,
+    script_type: String,
+    filter: Option<SearchKeyFilter>,
+}
+
+impl SearchKey {
+    fn lock(lock: packed::Script, from_number: Option<u64>) -> SearchKey {
+        SearchKey {
+            script: lock.into(),
+            script_type: "lock".to_string(),
+            filter: from_number.map(|number| SearchKeyFilter {
+                block_range: [BlockNumber::from(number), BlockNumber::from(std::u64::MAX)],
+            }),
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct SearchKeyFilter {
+    // [start, end)
+    block_range: [BlockNumber; 2],
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct IndexerTip {
+    block_number: BlockNumber,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct IndexerCells {
+    objects: Vec<IndexerCell>,
+    last_cursor: JsonBytes,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub struct IndexerCell {
+    output: CellOutput,
+    output_data: JsonBytes,
+    out_point: OutPoint,
+    block_number: BlockNumber,
+    tx_index: Uint32,
+}
+
+impl From<IndexerCell> for LiveCellInfo {
+    fn from(cell: IndexerCell) -> LiveCellInfo {
+        let output: packed::CellOutput = cell.output.into();
+        let type_hashes = output.type_().to_opt().map(|type_script| {
+            (
+                type_script.code_hash().unpack(),
+                type_script.calc_script_hash().unpack(),
+            )
+        });
+        let output_index = cell.out_point.index.value();
+        LiveCellInfo {
+            tx_hash: cell.out_point.tx_hash,
+            tx_index: output_index,
+            data_bytes: cell.output_data.as_bytes().len() as u64,
+            lock_hash: output.lock().calc_script_hash().unpack(),
+            type_hashes,
+            capacity: output.capacity().unpack(),
+            number: cell.block_number.value(),
+            index: CellIndex {
+                tx_index: cell.tx_index.value(),
+                output_index,
+            },
+        }
+    }
+}
+
+/// A remote ckb-indexer (https://github.com/nervosnetwork/ckb-indexer), it
+/// follows the chain tip and handles reorgs by itself. The cells can only be
+/// searched by lock script (not lock hash).
+pub struct IndexerBackend {
+    url: String,
+    client: RawIndexerClient<JournaledHandle>,
+}
+
+impl IndexerBackend {
+    pub fn new(url: &str) -> Result<IndexerBackend, String> {
+        let transport = new_http_transport()?;
+        let handle = transport.handle(url).map_err(|err| err.to_string())?;
+        Ok(IndexerBackend {
+            url: url.to_string(),
+            client: RawIndexerClient::new(JournaledHandle::new(url, handle)),
+        })
+    }
+}
+
+impl IndexBackend for IndexerBackend {
+    fn last_number(&mut self) -> Result<Option<u64>, String> {
+        self.client
+            .get_tip()
+            .call()
+            .map(|tip_opt| tip_opt.map(|tip| tip.block_number.value()))
+            .map_err(|err| format!("ckb-indexer RPC error ({}): {}", self.url, err))
+    }
+
+    fn get_live_cells(
+        &mut self,
+        query: &CellQuery,
+        from_number: Option<u64>,
+        terminator: &mut dyn FnMut(usize, &LiveCellInfo) -> (bool, bool),
+    ) -> Result<Vec<LiveCellInfo>, String> {
+        let lock = match query {
+            CellQuery::Lock(lock) => lock.clone(),
+            CellQuery::Hash(_) => {
+                return Err(
+                    "Search by lock/type/code hash is not supported by ckb-indexer, please search by address"
+                        .to_string(),
+                );
+            }
+        };
+        let search_key = SearchKey::lock(lock, from_number);
+        let client = &mut self.client;
+        let url = &self.url;
+        get_cells_by_pages(
+            |cursor| {
+                client
+                    .get_cells(
+                        search_key.clone(),
+                        "asc".to_string(),
+                        Uint32::from(INDEXER_PAGE_SIZE),
+                        cursor,
+                    )
+                    .call()
+                    .map_err(|err| format!("ckb-indexer RPC error ({}): {}", url, err))
+            },
+            terminator,
+        )
+    }
+}
+
+// The `get_cells` RPC of ckb-indexer and light client, page by page
+fn get_cells_by_pages<F>(
+    mut get_page: F,
+    terminator: &mut dyn FnMut(usize, &LiveCellInfo) -> (bool, bool),
+) -> Result<Vec<LiveCellInfo>, String>
+where
+    F: FnMut(Option<JsonBytes>) -> Result<IndexerCells, String>,
+{
+    let mut infos = Vec::new();
+    let mut idx = 0;
+    let mut cursor = None;
+    loop {
+        let cells = get_page(cursor.take())?;
+        let is_last_page = cells.objects.len() < INDEXER_PAGE_SIZE as usize;
+        for cell in cells.objects {
+            let info = LiveCellInfo::from(cell);
+            let (stop, push_info) = terminator(idx, &info);
+            if push_info {
+                infos.push(info);
+            }
+            if stop {
+                return Ok(infos);
+            }
+            idx += 1;
+        }
+        if is_last_page {
+            return Ok(infos);
+        }
+        cursor = Some(cells.last_cursor);
+    }
+}
+
+jsonrpc_client!(pub struct RawLightClient {
+    pub fn get_tip_header(&mut self) -> RpcRequest<HeaderView>;
+    pub fn get_scripts(&mut self) -> RpcRequest<Vec<ScriptStatus>>;
+    pub fn set_scripts(&mut self, scripts: Vec<ScriptStatus>) -> RpcRequest<()>;
+    pub fn get_cells(
+        &mut self,
+        search_key: SearchKey,
+        order: String,
+        limit: Uint32,
+        after_cursor: Option<JsonBytes>
+    ) -> RpcRequest<IndexerCells>;
+    pub fn get_transaction(&mut self, tx_hash: H256) -> RpcRequest<LightClientTransaction>;
+});
+
+/// A transaction of the registered scripts fetched by the light client
+#[derive(Deserialize, Clone, Debug)]
+pub struct LightClientTransaction {
+    transaction: Option<TransactionView>,
+}
+
+impl LightClientTransaction {
+    // The output and data of the cell at `index`, with the transaction
+    fn cell(self, index: usize) -> Option<((packed::CellOutput, Transaction), Bytes)> {
+        let json_tx = self.transaction?.inner;
+        let tx = packed::Transaction::from(json_tx.clone());
+        let output = tx.raw().outputs().get(index)?;
+        let data = tx.raw().outputs_data().get(index)?.raw_data();
+        Some(((output, json_tx), data))
+    }
+}
+
+/// A script the light client syncs the cells and transactions of, from
+/// `block_number` on
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ScriptStatus {
+    script: Script,
+    script_type: String,
+    block_number: Uint64,
+}
+
+/// A CKB light client (https://github.com/nervosnetwork/ckb-light-client),
+/// it verifies the chain by itself and only fetches the blocks matching the
+/// registered scripts, so neither a local index nor a trusted indexer is
+/// needed. The lock script of a query is registered (syncing from genesis)
+/// the first time, the cells are available after the client caught up with
+/// the tip.
+pub struct LightClientBackend {
+    url: String,
+    client: RawLightClient<JournaledHandle>,
+}
+
+impl LightClientBackend {
+    pub fn new(url: &str) -> Result<LightClientBackend, String> {
+        let transport = new_http_transport()?;
+        let handle = transport.handle(url).map_err(|err| err.to_string())?;
+        Ok(LightClientBackend {
+            url: url.to_string(),
+            client: RawLightClient::new(JournaledHandle::new(url, handle)),
+        })
+    }
+
+    fn rpc_error(&self, err: impl ToString) -> String {
+        format!("Light client RPC error ({}): {}", self.url, err.to_string())
+    }
+
+    /// The output and data of a cell (with the transaction containing it)
+    /// from the transactions the light client fetched, it has no
+    /// `get_live_cell`: the cell is one of the live cells it searched.
+    pub fn get_cell(
+        &mut self,
+        out_point: packed::OutPoint,
+    ) -> Result<((packed::CellOutput, Transaction), Bytes), String> {
+        let index: u32 = out_point.index().unpack();
+        self.client
+            .get_transaction(out_point.tx_hash().unpack())
+            .call()
+            .map_err(|err| self.rpc_error(err))?
+            .cell(index as usize)
+            .ok_or_else(|| format!("Cell not found by the light client: {}", out_point))
+    }
+
+    // Register the lock script if not yet, error when its cells are not
+    // synced to the tip
+    fn ensure_synced(&mut self, lock: &packed::Script) -> Result<(), String> {
+        let tip_number = match self.last_number()? {
+            Some(number) => number,
+            None => return Ok(()),
+        };
+        let mut scripts = self
+            .client
+            .get_scripts()
+            .call()
+            .map_err(|err| self.rpc_error(err))?;
+        let script = Script::from(lock.clone());
+        let synced_number = scripts
+            .iter()
+            .find(|status| status.script_type == "lock" && status.script == script)
+            .map(|status| status.block_number.value());
+        match synced_number {
+            Some(number) if number >= tip_number => Ok(()),
+            Some(number) => Err(format!(
+                "The light client is syncing the cells of the lock script: block {}/{}, please try again later",
+                number, tip_number
+            )),
+            None => {
+                scripts.push(ScriptStatus {
+                    script,
+                    script_type: "lock".to_string(),
+                    block_number: Uint64::from(0),
+                });
+                self.client
+                    .set_scripts(scripts)
+                    .call()
+                    .map_err(|err| self.rpc_error(err))?;
+                Err(format!(
+                    "The lock script is registered to the light client (syncing from genesis, tip: {}), please try again later",
+                    tip_number
+                ))
+            }
+        }
+    }
+}
+
+impl IndexBackend for LightClientBackend {
+    fn last_number(&mut self) -> Result<Option<u64>, String> {
+        self.client
+            .get_tip_header()
+            .call()
+            .map(|header| Some(header.inner.number.value()))
+            .map_err(|err| self.rpc_error(err))
+    }
+
+    fn get_live_cells(
+        &mut self,
+        query: &CellQuery,
+        from_number: Option<u64>,
+        terminator: &mut dyn FnMut(usize, &LiveCellInfo) -> (bool, bool),
+    ) -> Result<Vec<LiveCellInfo>, String> {
+        let lock = match query {
+            CellQuery::Lock(lock) => lock.clone(),
+            CellQuery::Hash(_) => {
+                return Err(
+                    "Search by lock/type/code hash is not supported by light client, please search by address"
+                        .to_string(),
+                );
+            }
+        };
+        self.ensure_synced(&lock)?;
+        let search_key = SearchKey::lock(lock, from_number);
+        let client = &mut self.client;
+        let url = &self.url;
+        get_cells_by_pages(
+            |cursor| {
+                client
+                    .get_cells(
+                        search_key.clone(),
+                        "asc".to_string(),
+                        Uint32::from(INDEXER_PAGE_SIZE),
+                        cursor,
+                    )
+                    .call()
+                    .map_err(|err| format!("Light client RPC error ({}): {}", url, err))
+            },
+            terminator,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_types::core::{Capacity, HeaderBuilder, TransactionBuilder};
+    use serde_json::{json, Value};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::Mutex;
+    use std::thread;
+
+    // A JSON-RPC service on localhost answering by `handler(method, params)`,
+    // the requests are recorded
+    fn serve<F>(handler: F) -> (String, Arc<Mutex<Vec<Value>>>)
+    where
+        F: Fn(&str, &Value) -> Value + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let requests_clone = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut reader = BufReader::new(stream.unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end().to_lowercase();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if line.starts_with("content-length:") {
+                        content_length = line["content-length:".len()..].trim().parse().unwrap();
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let result = handler(request["method"].as_str().unwrap(), &request["params"]);
+                requests_clone.lock().unwrap().push(request.clone());
+                let response = json!({
+                    "jsonrpc": "2.0",
+                    "id": request["id"],
+                    "result": result,
+                })
+                .to_string();
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+        });
+        (url, requests)
+    }
+
+    // The params of the requests of `method`
+    fn requested(requests: &Arc<Mutex<Vec<Value>>>, method: &str) -> Vec<Value> {
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request["method"] == method)
+            .map(|request| request["params"].clone())
+            .collect()
+    }
+
+    // The `get_cells` result of the cells numbered from 0 to `total`, the
+    // cursor is the page number
+    fn cells_result(total: u64, cursor: &Value) -> Value {
+        let page_size = u64::from(INDEXER_PAGE_SIZE);
+        let page = cursor
+            .as_str()
+            .map(|cursor| u64::from_str_radix(&cursor[2..], 16).unwrap())
+            .unwrap_or(0);
+        let start = page * page_size;
+        let end = std::cmp::min(start + page_size, total);
+        let objects = (start..end)
+            .map(|number| {
+                json!({
+                    "output": CellOutput::from(
+                        packed::CellOutput::new_builder()
+                            .capacity(Capacity::shannons(number).pack())
+                            .build()
+                    ),
+                    "output_data": "0x",
+                    "out_point": OutPoint::from(packed::OutPoint::new(Default::default(), 0)),
+                    "block_number": BlockNumber::from(number),
+                    "tx_index": Uint32::from(0),
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "objects": objects,
+            "last_cursor": JsonBytes::from_vec(vec![page as u8 + 1]),
+        })
+    }
+
+    fn indexer_cell(number: u64) -> IndexerCell {
+        IndexerCell {
+            output: packed::CellOutput::new_builder()
+                .capacity(Capacity::shannons(number).pack())
+                .build()
+                .into(),
+            output_data: JsonBytes::from_vec(Vec::new()),
+            out_point: packed::OutPoint::new(Default::default(), 0).into(),
+            block_number: BlockNumber::from(number),
+            tx_index: Uint32::from(0),
+        }
+    }
+
+    // The cells numbered from 0 to `total`, the cursor is the page number
+    fn get_page(total: u64, cursor: Option<JsonBytes>) -> Result<IndexerCells, String> {
+        let page_size = u64::from(INDEXER_PAGE_SIZE);
+        let page = cursor.map(|cursor| cursor.as_bytes()[0]).unwrap_or(0);
+        let start = u64::from(page) * page_size;
+        let end = std::cmp::min(start + page_size, total);
+        Ok(IndexerCells {
+            objects: (start..end).map(indexer_cell).collect(),
+            last_cursor: JsonBytes::from_vec(vec![page + 1]),
+        })
+    }
+
+    #[test]
+    fn test_get_cells_by_pages() {
+        let total = u64::from(INDEXER_PAGE_SIZE) * 2 + 1;
+        let mut pages = 0;
+        let infos = get_cells_by_pages(
+            |cursor| {
+                pages += 1;
+                get_page(total, cursor)
+            },
+            &mut |_, _| (false, true),
+        )
+        .unwrap();
+        assert_eq!(infos.len() as u64, total);
+        assert_eq!(infos.last().unwrap().number, total - 1);
+        assert_eq!(pages, 3);
+
+        // Stopped by the terminator before the last page
+        let mut pages = 0;
+        let infos = get_cells_by_pages(
+            |cursor| {
+                pages += 1;
+                get_page(total, cursor)
+            },
+            &mut |idx, _| (idx == 150, idx % 2 == 0),
+        )
+        .unwrap();
+        assert_eq!(infos.len(), 76);
+        assert_eq!(infos.last().unwrap().number, 150);
+        assert_eq!(pages, 2);
+    }
+
+    #[test]
+    fn test_search_key_filter() {
+        let lock = packed::Script::default();
+        let search_key = serde_json::to_value(SearchKey::lock(lock.clone(), None)).unwrap();
+        assert_eq!(search_key["script"], json!(Script::from(lock.clone())));
+        assert_eq!(search_key["script_type"], "lock");
+        assert_eq!(search_key["filter"], Value::Null);
+
+        // From the block on, the end of the range is excluded
+        let search_key = serde_json::to_value(SearchKey::lock(lock, Some(0x20))).unwrap();
+        assert_eq!(
+            search_key["filter"],
+            json!({ "block_range": ["0x20", "0xffffffffffffffff"] })
+        );
+    }
+
+    #[test]
+    fn test_indexer_backend() {
+        let total = u64::from(INDEXER_PAGE_SIZE) * 2 + 1;
+        let (url, requests) = serve(move |method, params| match method {
+            "get_tip" => json!({ "block_hash": H256::default(), "block_number": "0x10" }),
+            "get_cells" => cells_result(total, &params[3]),
+            method => panic!("unexpected method: {}", method),
+        });
+        let mut backend = IndexerBackend::new(&url).unwrap();
+        assert_eq!(backend.last_number().unwrap(), Some(0x10));
+
+        let lock = packed::Script::new_builder()
+            .args(Bytes::from(vec![1]).pack())
+            .build();
+        let infos = backend
+            .get_live_cells(&CellQuery::Lock(lock.clone()), Some(5), &mut |_, _| {
+                (false, true)
+            })
+            .unwrap();
+        assert_eq!(infos.len() as u64, total);
+        assert_eq!(infos.last().unwrap().number, total - 1);
+        let params = requested(&requests, "get_cells");
+        assert_eq!(
+            params
+                .iter()
+                .map(|params| params[3].clone())
+                .collect::<Vec<_>>(),
+            vec![Value::Null, json!("0x01"), json!("0x02")]
+        );
+        for params in &params {
+            assert_eq!(params[0]["script"], json!(Script::from(lock.clone())));
+            assert_eq!(
+                params[0]["filter"]["block_range"],
+                json!(["0x5", "0xffffffffffffffff"])
+            );
+            assert_eq!(params[1], "asc");
+            assert_eq!(params[2], json!(Uint32::from(INDEXER_PAGE_SIZE)));
+        }
+
+        // Stopped by the terminator, the next page is not requested
+        let infos = backend
+            .get_live_cells(&CellQuery::Lock(lock.clone()), None, &mut |idx, _| {
+                (idx == 10, true)
+            })
+            .unwrap();
+        assert_eq!(infos.len(), 11);
+        let params = requested(&requests, "get_cells");
+        assert_eq!(params.len(), 4);
+        assert_eq!(params[3][0]["filter"], Value::Null);
+
+        let query = CellQuery::Hash(LiveCellQuery::Lock(lock.calc_script_hash()));
+        assert!(backend
+            .get_live_cells(&query, None, &mut |_, _| (false, true))
+            .is_err());
+    }
+
+    #[test]
+    fn test_light_client_ensure_synced() {
+        let lock = packed::Script::new_builder()
+            .args(Bytes::from(vec![2]).pack())
+            .build();
+        let script = Script::from(lock.clone());
+        let tip_header = HeaderView::from(HeaderBuilder::default().number(100u64.pack()).build());
+        // The block number the cells of the lock are synced to, `None` when
+        // the lock is not registered
+        let synced_number = Arc::new(Mutex::new(None));
+        let synced_number_clone = Arc::clone(&synced_number);
+        let (url, requests) = serve(move |method, params| match method {
+            "get_tip_header" => json!(tip_header),
+            "get_scripts" => match *synced_number_clone.lock().unwrap() {
+                Some(number) => json!([{
+                    "script": script,
+                    "script_type": "lock",
+                    "block_number": Uint64::from(number),
+                }]),
+                None => json!([]),
+            },
+            "set_scripts" => Value::Null,
+            "get_cells" => cells_result(3, &params[3]),
+            method => panic!("unexpected method: {}", method),
+        });
+        let mut backend = LightClientBackend::new(&url).unwrap();
+        let get_live_cells = |backend: &mut LightClientBackend| {
+            backend.get_live_cells(&CellQuery::Lock(lock.clone()), None, &mut |_, _| {
+                (false, true)
+            })
+        };
+
+        // Registered from genesis the first time
+        let err = get_live_cells(&mut backend).unwrap_err();
+        assert!(err.contains("registered"), "{}", err);
+        assert_eq!(
+            requested(&requests, "set_scripts"),
+            vec![json!([[{
+                "script": Script::from(lock.clone()),
+                "script_type": "lock",
+                "block_number": "0x0",
+            }]])]
+        );
+
+        *synced_number.lock().unwrap() = Some(50);
+        let err = get_live_cells(&mut backend).unwrap_err();
+        assert!(err.contains("block 50/100"), "{}", err);
+        assert!(requested(&requests, "get_cells").is_empty());
+
+        *synced_number.lock().unwrap() = Some(100);
+        assert_eq!(get_live_cells(&mut backend).unwrap().len(), 3);
+        assert_eq!(requested(&requests, "set_scripts").len(), 1);
+    }
+
+    #[test]
+    fn test_light_client_transaction_cell() {
+        let output = packed::CellOutput::new_builder()
+            .capacity(Capacity::shannons(100).pack())
+            .build();
+        let tx = TransactionBuilder::default()
+            .output(output.clone())
+            .output_data(Bytes::from(vec![1, 2]).pack())
+            .build();
+        let fetched = LightClientTransaction {
+            transaction: Some(tx.clone().into()),
+        };
+        let ((cell_output, cell_tx), data) = fetched.clone().cell(0).unwrap();
+        assert_eq!(cell_output.as_slice(), output.as_slice());
+        assert_eq!(
+            packed::Transaction::from(cell_tx).as_slice(),
+            tx.data().as_slice()
+        );
+        assert_eq!(data, Bytes::from(vec![1, 2]));
+        assert!(fetched.cell(1).is_none());
+        // Not fetched by the light client (not of the registered scripts)
+        assert!(LightClientTransaction { transaction: None }
+            .cell(0)
+            .is_none());
+    }
+
+    #[test]
+    fn test_light_client_url() {
+        let app = clap::App::new("test")
+            .arg(crate::utils::arg::index_backend())
+            .arg(crate::utils::arg::indexer_url())
+            .arg(crate::utils::arg::light_client_url());
+        let m = app.clone().get_matches_from(vec![
+            "test",
+            "--light-client-url",
+            "http://127.0.0.1:9000",
+        ]);
+        assert_eq!(light_client_url(&m), None);
+        let m = app.get_matches_from(vec![
+            "test",
+            "--index-backend",
+            "light-client",
+            "--light-client-url",
+            "http://127.0.0.1:9000",
+        ]);
+        assert_eq!(
+            light_client_url(&m),
+            Some("http://127.0.0.1:9000".to_string())
+        );
+    }
+}
@@ -0,0 +1,61 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use super::index::IndexController;
+
+// Exit code of a process killed by SIGINT
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static INTERRUPTIBLE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Handle Ctrl-C: a long operation marked by `interruptible()` (e.g. waiting
+/// for the index sync or a transaction) stops at its next check and tells how
+/// to resume. Otherwise (or on a second Ctrl-C) the index thread is stopped
+/// between two blocks, so the index database stays consistent and the sync
+/// resumes from the last applied block on the next start, then the process
+/// exits.
+pub fn set_interrupt_handler(index_controller: IndexController) -> Result<(), String> {
+    ctrlc::set_handler(move || {
+        let waiting = INTERRUPTIBLE_COUNT.load(Ordering::SeqCst) > 0;
+        if waiting && !INTERRUPTED.swap(true, Ordering::SeqCst) {
+            eprintln!("Interrupted, stopping after the current step (press Ctrl-C again to quit)");
+            return;
+        }
+        let state = index_controller.state().read().to_string();
+        index_controller.shutdown();
+        eprintln!(
+            "Interrupted, index database: {} (the sync resumes from there on the next start)",
+            state
+        );
+        process::exit(INTERRUPTED_EXIT_CODE);
+    })
+    .map_err(|err| format!("Set Ctrl-C handler failed: {}", err))
+}
+
+/// Marks a long operation which checks `check_interrupted()`, until dropped
+pub struct InterruptibleGuard {
+    _private: (),
+}
+
+impl Drop for InterruptibleGuard {
+    fn drop(&mut self) {
+        if INTERRUPTIBLE_COUNT.fetch_sub(1, Ordering::SeqCst) == 1 {
+            INTERRUPTED.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+pub fn interruptible() -> InterruptibleGuard {
+    INTERRUPTIBLE_COUNT.fetch_add(1, Ordering::SeqCst);
+    InterruptibleGuard { _private: () }
+}
+
+/// Error with `resume_hint` when Ctrl-C is pressed
+pub fn check_interrupted(resume_hint: &str) -> Result<(), String> {
+    if INTERRUPTED.load(Ordering::SeqCst) {
+        Err(format!("Interrupted, {}", resume_hint))
+    } else {
+        Ok(())
+    }
+}
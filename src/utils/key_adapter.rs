@@ -103,6 +103,10 @@ where
         self.0.append(message_fragment)
     }
 
+    fn sign_digest(&mut self) -> bool {
+        self.0.sign_digest()
+    }
+
     fn finalize(self: Box<Self>) -> Result<RecoverableSignature, Self::Err> {
         // cannot do something safe and easy like reallocating because might be DST
         let inner: Box<T> = unsafe { Box::from_raw(&mut (*Box::into_raw(self)).0 as *mut _) };
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use ckb_ledger::LedgerId;
+use ckb_sdk::wallet::DerivationPath;
+use ckb_types::H256;
+use serde_derive::{Deserialize, Serialize};
+
+use super::other::get_ckb_cli_dir;
+use super::output::write_file_atomic;
+
+const LEDGER_LABELS_FILE: &str = "ledger-labels.json";
+
+/// The account path used when no derivation root is given
+pub const DEFAULT_DERIVATION_ROOT: &str = "m/44'/309'/0'";
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LedgerLabel {
+    pub label: String,
+    /// RFC 3339 time the label was first given
+    pub created_at: String,
+    pub derivation_root: String,
+}
+
+/// Names of the ledger devices given by `account ledger-label`, keyed by the
+/// wallet id: `~/.ckb-cli/ledger-labels.json`
+pub struct LedgerLabelStore {
+    path: PathBuf,
+    labels: HashMap<H256, LedgerLabel>,
+}
+
+/// A label can't be taken for a wallet id, a lock arg or an address
+pub fn check_label(label: &str) -> Result<(), String> {
+    if label.is_empty() || label.chars().any(char::is_whitespace) {
+        return Err(format!(
+            "Invalid ledger label {:?}: must be non-empty without spaces",
+            label
+        ));
+    }
+    if label.starts_with("0x") || label.starts_with("ckb1") || label.starts_with("ckt1") {
+        return Err(format!(
+            "Invalid ledger label {:?}: must not start with 0x, ckb1 or ckt1",
+            label
+        ));
+    }
+    Ok(())
+}
+
+impl LedgerLabelStore {
+    pub fn load() -> Result<LedgerLabelStore, String> {
+        let mut path = get_ckb_cli_dir();
+        fs::create_dir_all(&path).map_err(|err| err.to_string())?;
+        path.push(LEDGER_LABELS_FILE);
+        let labels = if path.exists() {
+            let file = fs::File::open(&path).map_err(|err| err.to_string())?;
+            serde_json::from_reader(file)
+                .map_err(|err| format!("Parse {} failed: {}", path.to_string_lossy(), err))?
+        } else {
+            HashMap::new()
+        };
+        Ok(LedgerLabelStore { path, labels })
+    }
+
+    pub fn get(&self, id: &LedgerId) -> Option<&LedgerLabel> {
+        self.labels.get(&id.0)
+    }
+
+    /// The wallet id of a label
+    pub fn resolve(&self, label: &str) -> Option<LedgerId> {
+        self.labels
+            .iter()
+            .find(|(_, value)| value.label == label)
+            .map(|(id, _)| LedgerId(id.clone()))
+    }
+
+    /// Name the device (or rename it, the creation time is kept)
+    pub fn set(
+        &mut self,
+        id: &LedgerId,
+        label: &str,
+        derivation_root: Option<&DerivationPath>,
+    ) -> Result<LedgerLabel, String> {
+        check_label(label)?;
+        if let Some(other) = self.resolve(label).filter(|other| other != id) {
+            return Err(format!(
+                "Ledger label {} is already used by {:#x}",
+                label, other.0
+            ));
+        }
+        let value = match self.labels.get(&id.0) {
+            Some(old) => LedgerLabel {
+                label: label.to_string(),
+                created_at: old.created_at.clone(),
+                derivation_root: derivation_root
+                    .map(ToString::to_string)
+                    .unwrap_or_else(|| old.derivation_root.clone()),
+            },
+            None => LedgerLabel {
+                label: label.to_string(),
+                created_at: chrono::Utc::now().to_rfc3339(),
+                derivation_root: derivation_root
+                    .map(ToString::to_string)
+                    .unwrap_or_else(|| DEFAULT_DERIVATION_ROOT.to_string()),
+            },
+        };
+        self.labels.insert(id.0.clone(), value.clone());
+        self.save()?;
+        Ok(value)
+    }
+
+    /// The derivation root of the device, the default account path when not
+    /// labeled
+    pub fn derivation_root(&self, id: &LedgerId) -> DerivationPath {
+        self.get(id)
+            .and_then(|value| DerivationPath::from_str(&value.derivation_root).ok())
+            .unwrap_or_else(|| DerivationPath::from_str(DEFAULT_DERIVATION_ROOT).unwrap())
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(&self.labels).map_err(|err| err.to_string())?;
+        write_file_atomic(&self.path, content.as_bytes())
+            .map_err(|err| format!("Save ledger labels error: {}", err))
+    }
+}
+
+/// The ledger account of a label, for the account arguments
+pub fn resolve_ledger_label(label: &str) -> Option<LedgerId> {
+    if check_label(label).is_err() {
+        return None;
+    }
+    LedgerLabelStore::load().ok()?.resolve(label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_label() {
+        assert!(check_label("cold-storage").is_ok());
+        assert!(check_label("").is_err());
+        assert!(check_label("my ledger").is_err());
+        assert!(check_label("0x1234").is_err());
+        assert!(check_label("ckb1qyq").is_err());
+    }
+}
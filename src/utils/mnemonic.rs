@@ -0,0 +1,244 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bip39::{Language, Mnemonic, MnemonicType, Seed};
+use ckb_sdk::wallet::{Crypto, MasterPrivKey, ScryptType};
+use ckb_types::H160;
+
+use super::other::get_ckb_cli_dir;
+use super::output::write_file_atomic;
+
+const MNEMONICS_DIR: &str = "mnemonics";
+
+/// Generate a new English BIP-39 mnemonic of 12 or 24 words
+pub fn new_mnemonic(words: usize) -> Result<Mnemonic, String> {
+    let mnemonic_type = match words {
+        12 => MnemonicType::Words12,
+        24 => MnemonicType::Words24,
+        _ => return Err(format!("Unsupported mnemonic words count: {}", words)),
+    };
+    Ok(Mnemonic::new(mnemonic_type, Language::English))
+}
+
+/// Parse an English BIP-39 mnemonic (12 or 24 words), the checksum is verified
+pub fn parse_mnemonic(phrase: &str) -> Result<Mnemonic, String> {
+    let phrase = phrase.split_whitespace().collect::<Vec<_>>().join(" ");
+    let words = phrase.split(' ').count();
+    if words != 12 && words != 24 {
+        return Err(format!(
+            "Invalid mnemonic: expected 12 or 24 words, got {}",
+            words
+        ));
+    }
+    Mnemonic::from_phrase(&phrase, Language::English)
+        .map_err(|err| format!("Invalid mnemonic: {}", err))
+}
+
+/// The master key of mnemonic (with optional passphrase, empty string means no
+/// passphrase), compatible with Neuron
+pub fn mnemonic_master_key(mnemonic: &Mnemonic, passphrase: &str) -> Result<MasterPrivKey, String> {
+    let seed = Seed::new(mnemonic, passphrase);
+    MasterPrivKey::from_seed(seed.as_bytes()).map_err(|err| err.to_string())
+}
+
+/// The mnemonic of keystore account, encrypted by the password of the
+/// account: `~/.ckb-cli/mnemonics/<lock-arg>.json`. The passphrase is never
+/// saved.
+pub fn save_mnemonic(lock_arg: &H160, phrase: &str, password: &[u8]) -> Result<(), String> {
+    write_mnemonic(
+        &mnemonic_path(&mnemonics_dir()?, lock_arg),
+        lock_arg,
+        phrase,
+        password,
+    )
+}
+
+/// Decrypt the mnemonic of keystore account, return `None` if the account is
+/// not created/imported from a mnemonic
+pub fn load_mnemonic(lock_arg: &H160, password: &[u8]) -> Result<Option<String>, String> {
+    load_mnemonic_in(&mnemonics_dir()?, lock_arg, password)
+}
+
+/// Re-encrypt the mnemonic (if any) by the new password together with the key
+/// file (by `change_key`). The new mnemonic is saved aside before the key file
+/// is changed and replaces the old one after, a change interrupted in between
+/// is completed by `load_mnemonic` with the new password.
+pub fn change_mnemonic_password<F, T>(
+    lock_arg: &H160,
+    password: &[u8],
+    new_password: &[u8],
+    change_key: F,
+) -> Result<T, String>
+where
+    F: FnOnce() -> Result<T, String>,
+{
+    change_mnemonic_password_in(
+        &mnemonics_dir()?,
+        lock_arg,
+        password,
+        new_password,
+        change_key,
+    )
+}
+
+fn load_mnemonic_in(
+    dir: &Path,
+    lock_arg: &H160,
+    password: &[u8],
+) -> Result<Option<String>, String> {
+    let path = mnemonic_path(dir, lock_arg);
+    let staged_path = staged_mnemonic_path(dir, lock_arg);
+    if staged_path.exists() {
+        if let Ok(phrase) = read_mnemonic(&staged_path, password) {
+            // The key file is changed but the mnemonic is not replaced yet
+            fs::rename(&staged_path, &path).map_err(|err| err.to_string())?;
+            return Ok(Some(phrase));
+        }
+    }
+    if !path.exists() {
+        return Ok(None);
+    }
+    read_mnemonic(&path, password).map(Some)
+}
+
+fn change_mnemonic_password_in<F, T>(
+    dir: &Path,
+    lock_arg: &H160,
+    password: &[u8],
+    new_password: &[u8],
+    change_key: F,
+) -> Result<T, String>
+where
+    F: FnOnce() -> Result<T, String>,
+{
+    let phrase = match load_mnemonic_in(dir, lock_arg, password)? {
+        Some(phrase) => phrase,
+        None => return change_key(),
+    };
+    let staged_path = staged_mnemonic_path(dir, lock_arg);
+    write_mnemonic(&staged_path, lock_arg, &phrase, new_password)?;
+    match change_key() {
+        Ok(value) => {
+            fs::rename(&staged_path, mnemonic_path(dir, lock_arg))
+                .map_err(|err| format!("Replace mnemonic error: {}", err))?;
+            Ok(value)
+        }
+        Err(err) => {
+            let _ = fs::remove_file(&staged_path);
+            Err(err)
+        }
+    }
+}
+
+fn write_mnemonic(
+    path: &Path,
+    lock_arg: &H160,
+    phrase: &str,
+    password: &[u8],
+) -> Result<(), String> {
+    let crypto = Crypto::encrypt_key_scrypt(phrase.as_bytes(), password, ScryptType::default());
+    let content = serde_json::json!({
+        "lock_arg": format!("{:#x}", lock_arg),
+        "crypto": crypto.to_json(),
+    });
+    write_file_atomic(path, content.to_string().as_bytes())
+        .map_err(|err| format!("Save mnemonic error: {}", err))
+}
+
+fn read_mnemonic(path: &Path, password: &[u8]) -> Result<String, String> {
+    let content = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let data: serde_json::Value = serde_json::from_str(&content)
+        .map_err(|err| format!("Parse {} failed: {}", path.to_string_lossy(), err))?;
+    let crypto = Crypto::from_json(&data["crypto"]).map_err(|err| err.to_string())?;
+    let phrase = crypto.decrypt(password).map_err(|err| err.to_string())?;
+    String::from_utf8(phrase).map_err(|err| err.to_string())
+}
+
+fn mnemonics_dir() -> Result<PathBuf, String> {
+    let mut path = get_ckb_cli_dir();
+    path.push(MNEMONICS_DIR);
+    fs::create_dir_all(&path).map_err(|err| err.to_string())?;
+    Ok(path)
+}
+
+fn mnemonic_path(dir: &Path, lock_arg: &H160) -> PathBuf {
+    dir.join(format!("{:x}.json", lock_arg))
+}
+
+// The mnemonic encrypted by the new password during a password change
+fn staged_mnemonic_path(dir: &Path, lock_arg: &H160) -> PathBuf {
+    dir.join(format!("{:x}.json.new", lock_arg))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mnemonic() {
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        assert!(parse_mnemonic(phrase).is_ok());
+        assert!(parse_mnemonic(&format!("  {}\n", phrase.replace(' ', "  "))).is_ok());
+        // Bad checksum
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
+        assert!(parse_mnemonic(phrase).is_err());
+        assert!(parse_mnemonic("abandon about").is_err());
+    }
+
+    #[test]
+    fn test_change_mnemonic_password() {
+        let dir = std::env::temp_dir().join(format!("ckb-cli-mnemonics-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let lock_arg = H160::from([1u8; 20]);
+        let phrase = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        write_mnemonic(&mnemonic_path(&dir, &lock_arg), &lock_arg, phrase, b"old").unwrap();
+
+        // The key file is not changed, neither is the mnemonic
+        let result = change_mnemonic_password_in(&dir, &lock_arg, b"old", b"new", || {
+            Err::<(), _>("wrong password".to_string())
+        });
+        assert!(result.is_err());
+        assert!(!staged_mnemonic_path(&dir, &lock_arg).exists());
+        assert_eq!(
+            load_mnemonic_in(&dir, &lock_arg, b"old")
+                .unwrap()
+                .as_deref(),
+            Some(phrase)
+        );
+
+        change_mnemonic_password_in(&dir, &lock_arg, b"old", b"new", || Ok(())).unwrap();
+        assert!(load_mnemonic_in(&dir, &lock_arg, b"old").is_err());
+        assert_eq!(
+            load_mnemonic_in(&dir, &lock_arg, b"new")
+                .unwrap()
+                .as_deref(),
+            Some(phrase)
+        );
+
+        // Interrupted after the key file is changed
+        write_mnemonic(
+            &staged_mnemonic_path(&dir, &lock_arg),
+            &lock_arg,
+            phrase,
+            b"newer",
+        )
+        .unwrap();
+        assert_eq!(
+            load_mnemonic_in(&dir, &lock_arg, b"newer")
+                .unwrap()
+                .as_deref(),
+            Some(phrase)
+        );
+        assert!(!staged_mnemonic_path(&dir, &lock_arg).exists());
+        assert!(load_mnemonic_in(&dir, &lock_arg, b"new").is_err());
+
+        // Not created from a mnemonic
+        let other = H160::from([2u8; 20]);
+        assert_eq!(load_mnemonic_in(&dir, &other, b"old"), Ok(None));
+        assert_eq!(
+            change_mnemonic_password_in(&dir, &other, b"old", b"new", || Ok(1)),
+            Ok(1)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
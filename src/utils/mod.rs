@@ -1,13 +1,37 @@
+pub mod alert;
+pub mod alias;
 pub mod arg;
 pub mod arg_parser;
+pub mod broadcast;
+pub mod coin_control;
 pub mod completer;
 pub mod config;
+pub mod crypt;
+pub mod debugger;
+pub mod descriptor;
 pub mod index;
+pub mod index_backend;
+pub mod interrupt;
 pub mod json_color;
 pub mod key_adapter;
+pub mod ledger_labels;
+pub mod mnemonic;
+pub mod multisig;
+pub mod notify;
+pub mod on_failure;
 pub mod other;
+pub mod output;
+pub mod overlay;
+pub mod policy;
 pub mod printer;
 pub mod privkey_wrapper;
+pub mod remote_config;
+pub mod schema;
+pub mod sign_vectors;
+pub mod tor;
+pub mod tx_proof;
+pub mod tx_record;
+pub mod tx_status;
 
 #[allow(clippy::cast_lossless)]
 pub mod yaml_ser;
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use ckb_sdk::{AddressPayload, MultisigConfig};
+use ckb_types::H160;
+use serde_derive::{Deserialize, Serialize};
+
+use super::other::get_ckb_cli_dir;
+
+const MULTISIG_CONFIGS_FILE: &str = "multisig-configs.json";
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct ReprStoredConfig {
+    sighash_lock_args: Vec<H160>,
+    require_first_n: u8,
+    threshold: u8,
+}
+
+impl From<&MultisigConfig> for ReprStoredConfig {
+    fn from(config: &MultisigConfig) -> ReprStoredConfig {
+        ReprStoredConfig {
+            sighash_lock_args: config
+                .sighash_addresses()
+                .iter()
+                .map(|payload| H160::from_slice(payload.args().as_ref()).unwrap())
+                .collect(),
+            require_first_n: config.require_first_n(),
+            threshold: config.threshold(),
+        }
+    }
+}
+
+/// Multisig configs created by `account multisig-create`, keyed by the
+/// multisig script hash160: `~/.ckb-cli/multisig-configs.json`
+pub struct MultisigConfigStore {
+    path: PathBuf,
+    configs: HashMap<H160, ReprStoredConfig>,
+}
+
+impl MultisigConfigStore {
+    pub fn load() -> Result<MultisigConfigStore, String> {
+        let mut path = get_ckb_cli_dir();
+        fs::create_dir_all(&path).map_err(|err| err.to_string())?;
+        path.push(MULTISIG_CONFIGS_FILE);
+        let configs = if path.exists() {
+            let file = fs::File::open(&path).map_err(|err| err.to_string())?;
+            serde_json::from_reader(file)
+                .map_err(|err| format!("Parse {} failed: {}", path.to_string_lossy(), err))?
+        } else {
+            HashMap::new()
+        };
+        Ok(MultisigConfigStore { path, configs })
+    }
+
+    pub fn get(&self, hash160: &H160) -> Result<Option<MultisigConfig>, String> {
+        self.configs
+            .get(hash160)
+            .map(|repr| {
+                let sighash_addresses = repr
+                    .sighash_lock_args
+                    .iter()
+                    .map(|lock_arg| AddressPayload::from_pubkey_hash(lock_arg.clone()))
+                    .collect();
+                MultisigConfig::new_with(sighash_addresses, repr.require_first_n, repr.threshold)
+            })
+            .transpose()
+    }
+
+    /// All the configs, ordered by the multisig script hash160
+    pub fn list(&self) -> Result<Vec<MultisigConfig>, String> {
+        let mut hash160s = self.configs.keys().collect::<Vec<_>>();
+        hash160s.sort();
+        hash160s
+            .into_iter()
+            .filter_map(|hash160| self.get(hash160).transpose())
+            .collect()
+    }
+
+    pub fn insert(&mut self, config: &MultisigConfig) -> Result<(), String> {
+        self.configs
+            .insert(config.hash160(), ReprStoredConfig::from(config));
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let content = serde_json::to_string_pretty(&self.configs).map_err(|err| err.to_string())?;
+        let mut file = fs::File::create(&self.path).map_err(|err| err.to_string())?;
+        file.write_all(content.as_bytes())
+            .map_err(|err| format!("Save multisig configs error: {:?}", err))
+    }
+}
@@ -0,0 +1,494 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use ckb_sdk::{
+    rpc::new_http_transport, Address, GenesisInfo, HttpRpcClient, HumanCapacity, NetworkType,
+};
+use ckb_types::{
+    core::HeaderView,
+    packed::{OutPoint, Script},
+    prelude::*,
+    H256,
+};
+use futures::Future;
+use jsonrpc_client_core::Transport;
+use serde_derive::Deserialize;
+
+use super::arg_parser::{AddressParser, ArgParser, CapacityParser, OutPointParser};
+use super::index::{with_chain_index_db, IndexController};
+use super::other::{get_genesis_info, get_network_type};
+use super::output::write_file_atomic;
+use crate::subcommands::dao::{dao_unlock_point, epoch_reached};
+
+/// A condition watched by the server mode
+#[derive(Clone, Debug)]
+pub enum Condition {
+    /// The capacity of the address (from the index database) is below the
+    /// threshold
+    BalanceBelow { address: Address, capacity: u64 },
+    /// The prepared NervosDAO cell reached its unlock epoch and is still live,
+    /// a deposited cell is rejected (it is never withdrawable before prepared)
+    DaoWithdrawable(OutPoint),
+}
+
+impl Condition {
+    fn kind(&self) -> &'static str {
+        match self {
+            Condition::BalanceBelow { .. } => "balance_below",
+            Condition::DaoWithdrawable(_) => "dao_withdrawable",
+        }
+    }
+}
+
+/// Where a notification is delivered
+#[derive(Clone, Debug, PartialEq)]
+pub enum Hook {
+    /// POST the notification (json) to the url
+    Webhook(String),
+    /// Run the shell command, the notification (json) is written to stdin
+    Command(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Rule {
+    pub name: String,
+    pub condition: Condition,
+    pub hooks: Vec<Hook>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawHook {
+    webhook: Option<String>,
+    command: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawBalanceBelow {
+    address: String,
+    capacity: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRule {
+    name: String,
+    balance_below: Option<RawBalanceBelow>,
+    dao_withdrawable: Option<String>,
+    hooks: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawRules {
+    hooks: HashMap<String, RawHook>,
+    rules: Vec<RawRule>,
+}
+
+/// Load the rules file of `server --notify-rules`:
+///
+/// ```json
+/// {
+///     "hooks": {
+///         "ops": {"webhook": "http://127.0.0.1:9000/ckb"},
+///         "log": {"command": "logger -t ckb-cli"}
+///     },
+///     "rules": [
+///         {
+///             "name": "treasury",
+///             "balance_below": {"address": "ckb1...", "capacity": "10000"},
+///             "hooks": ["ops", "log"]
+///         },
+///         {"name": "dao", "dao_withdrawable": "0x<tx-hash>-0", "hooks": ["ops"]}
+///     ]
+/// }
+/// ```
+pub fn load_rules(path: &PathBuf) -> Result<Vec<Rule>, String> {
+    let file = fs::File::open(path).map_err(|err| err.to_string())?;
+    let raw: RawRules = serde_json::from_reader(file)
+        .map_err(|err| format!("Parse {} failed: {}", path.to_string_lossy(), err))?;
+    parse_rules(raw)
+}
+
+fn parse_rules(raw: RawRules) -> Result<Vec<Rule>, String> {
+    let mut hooks = HashMap::new();
+    for (name, hook) in raw.hooks {
+        let hook = match (hook.webhook, hook.command) {
+            (Some(url), None) => Hook::Webhook(url),
+            (None, Some(command)) => Hook::Command(command),
+            _ => {
+                return Err(format!(
+                    "Hook {} must have exactly one of webhook and command",
+                    name
+                ))
+            }
+        };
+        hooks.insert(name, hook);
+    }
+    let mut names = HashSet::new();
+    if let Some(rule) = raw.rules.iter().find(|rule| !names.insert(&rule.name)) {
+        return Err(format!("Duplicated rule name: {}", rule.name));
+    }
+    raw.rules
+        .into_iter()
+        .map(|rule| {
+            let condition = match (rule.balance_below, rule.dao_withdrawable) {
+                (Some(balance_below), None) => Condition::BalanceBelow {
+                    address: AddressParser::default().parse(&balance_below.address)?,
+                    capacity: CapacityParser.parse(&balance_below.capacity)?.0,
+                },
+                (None, Some(out_point)) => {
+                    Condition::DaoWithdrawable(OutPointParser.parse(&out_point)?)
+                }
+                _ => {
+                    return Err(format!(
+                        "Rule {} must have exactly one of balance_below and dao_withdrawable",
+                        rule.name
+                    ))
+                }
+            };
+            if rule.hooks.is_empty() {
+                return Err(format!("Rule {} has no hooks", rule.name));
+            }
+            let rule_hooks = rule
+                .hooks
+                .iter()
+                .map(|name| {
+                    hooks
+                        .get(name)
+                        .cloned()
+                        .ok_or_else(|| format!("Rule {} uses unknown hook {}", rule.name, name))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(Rule {
+                name: rule.name,
+                condition,
+                hooks: rule_hooks,
+            })
+        })
+        .collect()
+}
+
+/// Check the rules periodically, a notification is sent when the condition
+/// of a rule becomes true (again after it was false), not on every check.
+/// The triggered rules are saved to `state_path` on every change, so the
+/// server stopped at any time (e.g. by Ctrl-C) does not notify them again
+/// after restarting.
+pub struct Watcher {
+    rules: Vec<Rule>,
+    interval: Duration,
+    state_path: PathBuf,
+    index_dir: PathBuf,
+    index_controller: IndexController,
+    rpc_client: HttpRpcClient,
+    genesis_info: Option<GenesisInfo>,
+    network_type: Option<NetworkType>,
+}
+
+impl Watcher {
+    pub fn new(
+        rules: Vec<Rule>,
+        interval: Duration,
+        state_path: PathBuf,
+        url: &str,
+        index_dir: PathBuf,
+        index_controller: IndexController,
+    ) -> Watcher {
+        Watcher {
+            rules,
+            interval,
+            state_path,
+            index_dir,
+            index_controller,
+            rpc_client: HttpRpcClient::new(url.to_string()),
+            genesis_info: None,
+            network_type: None,
+        }
+    }
+
+    pub fn rules_count(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Reject the `dao_withdrawable` rules of a deposited cell. A cell not
+    /// found yet (e.g. the prepare transaction is pending) is checked again
+    /// by the watcher.
+    pub fn check_rules(&mut self) -> Result<(), String> {
+        for rule in &self.rules {
+            if let Condition::DaoWithdrawable(out_point) = &rule.condition {
+                match dao_unlock_point(&mut self.rpc_client, out_point) {
+                    Ok(Some(_)) => {}
+                    Ok(None) => {
+                        return Err(format!("Rule {}: {}", rule.name, not_prepared(out_point)))
+                    }
+                    Err(err) => eprintln!(
+                        "[WARNING] Check rule {} failed: {}, it is checked again by the watcher",
+                        rule.name, err
+                    ),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn start(mut self) -> thread::JoinHandle<()> {
+        thread::Builder::new()
+            .name("notify".to_string())
+            .spawn(move || {
+                let mut triggered = load_triggered(&self.state_path).unwrap_or_else(|err| {
+                    log::warn!("Ignore the notify state: {}", err);
+                    HashSet::new()
+                });
+                loop {
+                    let last_triggered = triggered.clone();
+                    for rule in self.rules.clone() {
+                        match self.evaluate(&rule.condition) {
+                            Ok(Some(mut notification)) => {
+                                if triggered.insert(rule.name.clone()) {
+                                    notification["rule"] = serde_json::json!(rule.name);
+                                    notification["condition"] =
+                                        serde_json::json!(rule.condition.kind());
+                                    notify(&rule, &notification);
+                                }
+                            }
+                            Ok(None) => {
+                                triggered.remove(&rule.name);
+                            }
+                            Err(err) => log::warn!("Check rule {} failed: {}", rule.name, err),
+                        }
+                    }
+                    if triggered != last_triggered {
+                        if let Err(err) = save_triggered(&self.state_path, &triggered) {
+                            log::warn!("Save the notify state failed: {}", err);
+                        }
+                    }
+                    thread::sleep(self.interval);
+                }
+            })
+            .expect("Spawn notify thread failed")
+    }
+
+    // The notification when the condition holds
+    fn evaluate(&mut self, condition: &Condition) -> Result<Option<serde_json::Value>, String> {
+        let tip_header: HeaderView = self.rpc_client.get_tip_header()?.into();
+        match condition {
+            Condition::BalanceBelow { address, capacity } => {
+                if !self.index_controller.state().read().is_synced() {
+                    return Err("index database is not synced yet".to_string());
+                }
+                let balance = self.get_capacity(address)?;
+                if balance >= *capacity {
+                    return Ok(None);
+                }
+                Ok(Some(serde_json::json!({
+                    "message": format!(
+                        "Capacity of {} is {:#}, below {:#}",
+                        address,
+                        HumanCapacity::from(balance),
+                        HumanCapacity::from(*capacity)
+                    ),
+                    "tip_block_number": tip_header.number(),
+                    "address": address.to_string(),
+                    "capacity": format!("{:#}", HumanCapacity::from(balance)),
+                    "threshold": format!("{:#}", HumanCapacity::from(*capacity)),
+                })))
+            }
+            Condition::DaoWithdrawable(out_point) => {
+                let unlock_point = dao_unlock_point(&mut self.rpc_client, out_point)?
+                    .ok_or_else(|| not_prepared(out_point))?;
+                if !epoch_reached(tip_header.epoch(), unlock_point) {
+                    return Ok(None);
+                }
+                let status = self
+                    .rpc_client
+                    .get_live_cell(out_point.clone(), false)?
+                    .status;
+                if status != "live" {
+                    return Ok(None);
+                }
+                let tx_hash: H256 = out_point.tx_hash().unpack();
+                let index: u32 = out_point.index().unpack();
+                let out_point = format!("{:#x}-{}", tx_hash, index);
+                Ok(Some(serde_json::json!({
+                    "message": format!(
+                        "NervosDAO cell {} is withdrawable since epoch {}",
+                        out_point, unlock_point
+                    ),
+                    "tip_block_number": tip_header.number(),
+                    "out_point": out_point,
+                    "unlock_epoch": unlock_point.to_string(),
+                })))
+            }
+        }
+    }
+
+    fn get_capacity(&mut self, address: &Address) -> Result<u64, String> {
+        let genesis_info = get_genesis_info(&self.genesis_info, &mut self.rpc_client)?;
+        self.genesis_info = Some(genesis_info.clone());
+        let network_type = match self.network_type {
+            Some(network_type) => network_type,
+            None => get_network_type(&mut self.rpc_client)?,
+        };
+        self.network_type = Some(network_type);
+
+        let lock_hash = Script::from(address.payload()).calc_script_hash();
+        with_chain_index_db(
+            &self.index_dir,
+            &self.index_controller,
+            network_type,
+            genesis_info,
+            |db| db.get_capacity(lock_hash).unwrap_or(0),
+        )
+    }
+}
+
+fn not_prepared(out_point: &OutPoint) -> String {
+    let tx_hash: H256 = out_point.tx_hash().unpack();
+    let index: u32 = out_point.index().unpack();
+    format!(
+        "NervosDAO cell {:#x}-{} is deposited, not prepared, please watch the cell of the prepare transaction",
+        tx_hash, index
+    )
+}
+
+// The names of the triggered rules, empty without the state file
+fn load_triggered(path: &Path) -> Result<HashSet<String>, String> {
+    if !path.exists() {
+        return Ok(HashSet::new());
+    }
+    let content = fs::read(path).map_err(|err| err.to_string())?;
+    serde_json::from_slice(&content)
+        .map_err(|err| format!("Parse {} failed: {}", path.display(), err))
+}
+
+fn save_triggered(path: &Path, triggered: &HashSet<String>) -> Result<(), String> {
+    let mut names = triggered.iter().collect::<Vec<_>>();
+    names.sort();
+    let content = serde_json::to_vec_pretty(&names).map_err(|err| err.to_string())?;
+    write_file_atomic(path, &content)
+}
+
+fn notify(rule: &Rule, notification: &serde_json::Value) {
+    log::info!("Rule {} triggered: {}", rule.name, notification["message"]);
+    for hook in &rule.hooks {
+        if let Err(err) = run_hook(hook, notification) {
+            log::warn!("Notify rule {} failed: {}", rule.name, err);
+        }
+    }
+}
+
+fn run_hook(hook: &Hook, notification: &serde_json::Value) -> Result<(), String> {
+    let body = serde_json::to_vec(notification).map_err(|err| err.to_string())?;
+    match hook {
+        Hook::Webhook(url) => {
+            let handle = new_http_transport()?
+                .handle(url)
+                .map_err(|err| err.to_string())?;
+            handle
+                .send(body)
+                .wait()
+                .map(|_| ())
+                .map_err(|err| format!("POST {} failed: {}", url, err))
+        }
+        Hook::Command(command) => {
+            #[cfg(unix)]
+            let mut cmd = {
+                let mut cmd = Command::new("sh");
+                cmd.arg("-c").arg(command);
+                cmd
+            };
+            #[cfg(not(unix))]
+            let mut cmd = {
+                let mut cmd = Command::new("cmd");
+                cmd.arg("/C").arg(command);
+                cmd
+            };
+            let mut child = cmd
+                .env(
+                    "CKB_CLI_NOTIFY_RULE",
+                    notification["rule"].as_str().unwrap_or(""),
+                )
+                .env(
+                    "CKB_CLI_NOTIFY_MESSAGE",
+                    notification["message"].as_str().unwrap_or(""),
+                )
+                .stdin(Stdio::piped())
+                .spawn()
+                .map_err(|err| format!("Run `{}` failed: {}", command, err))?;
+            if let Some(mut stdin) = child.stdin.take() {
+                // The command may not read stdin at all
+                let _ = stdin.write_all(&body);
+            }
+            let status = child.wait().map_err(|err| err.to_string())?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(format!("`{}` exited with {}", command, status))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rules() {
+        let raw: RawRules = serde_json::from_value(serde_json::json!({
+            "hooks": {
+                "ops": {"webhook": "http://127.0.0.1:9000/ckb"},
+                "log": {"command": "logger -t ckb-cli"},
+            },
+            "rules": [
+                {
+                    "name": "treasury",
+                    "balance_below": {
+                        "address": "ckt1qyqt8xaupvm8837nv3gtc9x0ekkj64vud3jqfwyw5v",
+                        "capacity": "10000",
+                    },
+                    "hooks": ["ops", "log"],
+                },
+            ],
+        }))
+        .unwrap();
+        let rules = parse_rules(raw).unwrap();
+        assert_eq!(rules[0].hooks.len(), 2);
+        match rules[0].condition {
+            Condition::BalanceBelow { capacity, .. } => assert_eq!(capacity, 10_000 * 100_000_000),
+            _ => panic!("Unexpected condition"),
+        }
+
+        let raw: RawRules = serde_json::from_value(serde_json::json!({
+            "hooks": {},
+            "rules": [{
+                "name": "dao",
+                "dao_withdrawable": format!("0x{}-0", "00".repeat(32)),
+                "hooks": ["ops"],
+            }],
+        }))
+        .unwrap();
+        assert!(parse_rules(raw).is_err());
+    }
+
+    #[test]
+    fn test_triggered_state() {
+        let path =
+            std::env::temp_dir().join(format!("ckb-cli-notify-{}.state", std::process::id()));
+        assert_eq!(load_triggered(&path), Ok(HashSet::new()));
+        let triggered = vec!["treasury".to_string(), "dao".to_string()]
+            .into_iter()
+            .collect::<HashSet<_>>();
+        save_triggered(&path, &triggered).unwrap();
+        assert_eq!(load_triggered(&path), Ok(triggered));
+        save_triggered(&path, &HashSet::new()).unwrap();
+        assert_eq!(load_triggered(&path), Ok(HashSet::new()));
+        fs::remove_file(&path).unwrap();
+    }
+}
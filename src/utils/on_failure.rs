@@ -0,0 +1,181 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ckb_sdk::{
+    rpc::RpcError, HttpRpcClient, MockCellDep, MockInfo, MockInput, MockResourceLoader,
+    MockTransaction, ReprMockTransaction,
+};
+use ckb_types::{
+    bytes::Bytes,
+    core::{DepType, TransactionView},
+    packed::{CellDep, CellOutput, OutPoint, OutPointVec},
+    prelude::*,
+    H256,
+};
+use clap::ArgMatches;
+
+use super::broadcast::{Broadcast, SendTargets};
+use super::output::write_file_atomic;
+use crate::subcommands::mock_tx::Loader;
+
+pub const DUMP_MOCK_TX: &str = "dump-mock-tx";
+
+/// What to do when the node rejects a transaction because a script failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OnFailure {
+    /// Save the transaction with its resolved inputs, cell deps and header deps
+    /// as a mock transaction file in the directory
+    DumpMockTx(PathBuf),
+}
+
+impl OnFailure {
+    pub fn from_matches(m: &ArgMatches) -> Result<Option<OnFailure>, String> {
+        let values = match m.values_of("on-failure") {
+            Some(values) => values.collect::<Vec<_>>(),
+            None => return Ok(None),
+        };
+        match values.as_slice() {
+            [DUMP_MOCK_TX, dir] => Ok(Some(OnFailure::DumpMockTx(PathBuf::from(dir)))),
+            _ => Err(format!(
+                "Invalid --on-failure: {}, expected: {} <dir>",
+                values.join(" "),
+                DUMP_MOCK_TX
+            )),
+        }
+    }
+}
+
+/// Send the transaction, when the scripts failed to verify on the node either
+/// dump the mock transaction (by `on_failure`) or hint how to do it. It is
+/// sent to the light client instead of the node when `targets` has one, and
+/// also to the `broadcast_urls` of `targets` at the same time.
+pub fn send_transaction(
+    rpc_client: &mut HttpRpcClient,
+    tx: &TransactionView,
+    on_failure: Option<&OnFailure>,
+    targets: &SendTargets,
+) -> Result<H256, String> {
+    let broadcast = Broadcast::start(tx, targets.broadcast_urls.clone());
+    let primary = match targets.light_client_url {
+        Some(ref url) => HttpRpcClient::try_new(url.clone())
+            .map_err(|message| RpcError {
+                code: None,
+                message,
+            })
+            .and_then(|mut client| client.send_transaction_with_code(tx.data())),
+        None => send_to_node(rpc_client, tx, on_failure),
+    };
+    broadcast.finish(primary)
+}
+
+fn send_to_node(
+    rpc_client: &mut HttpRpcClient,
+    tx: &TransactionView,
+    on_failure: Option<&OnFailure>,
+) -> Result<H256, RpcError> {
+    let mut err = match rpc_client.send_transaction_with_code(tx.data()) {
+        Ok(tx_hash) => return Ok(tx_hash),
+        Err(err) => err,
+    };
+    if !is_script_failure(&err) {
+        return Err(err);
+    }
+    err.message = match on_failure {
+        Some(OnFailure::DumpMockTx(dir)) => match dump_mock_tx(rpc_client, tx, dir) {
+            Ok(path) => format!(
+                "{}\nThe mock transaction is saved to {}, replay it by: ckb-cli mock-tx verify --tx-file {}",
+                err,
+                path.display(),
+                path.display()
+            ),
+            Err(dump_err) => format!("{}\nDump the mock transaction failed: {}", err, dump_err),
+        },
+        None => format!(
+            "{}\nRerun with `--on-failure {} <dir>` to save the transaction as a mock transaction for replaying",
+            err, DUMP_MOCK_TX
+        ),
+    };
+    Err(err)
+}
+
+// The JSON-RPC error code of `TransactionFailedToVerify`, the node reports it
+// when the scripts (or the other rules) of the transaction failed to verify
+const TRANSACTION_FAILED_TO_VERIFY: i64 = -302;
+
+fn is_script_failure(err: &RpcError) -> bool {
+    err.code == Some(TRANSACTION_FAILED_TO_VERIFY)
+}
+
+fn dump_mock_tx(
+    rpc_client: &mut HttpRpcClient,
+    tx: &TransactionView,
+    dir: &Path,
+) -> Result<PathBuf, String> {
+    let mock_tx = mock_transaction(&mut Loader::new(rpc_client), tx)?;
+    fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    let tx_hash: H256 = tx.hash().unpack();
+    let path = dir.join(format!("{:x}.json", tx_hash));
+    let content = serde_json::to_string_pretty(&ReprMockTransaction::from(mock_tx))
+        .map_err(|err| err.to_string())?;
+    write_file_atomic(&path, content.as_bytes())?;
+    Ok(path)
+}
+
+/// Resolve the inputs, cell deps (including the members of dep groups) and
+/// header deps of the transaction by the loader (e.g. from the node)
+pub fn mock_transaction<L: MockResourceLoader>(
+    loader: &mut L,
+    tx: &TransactionView,
+) -> Result<MockTransaction, String> {
+    let mut load_cell = |out_point: OutPoint| -> Result<(CellOutput, Bytes), String> {
+        loader
+            .get_live_cell(out_point.clone())?
+            .ok_or_else(|| format!("Cell not found: {}", out_point))
+    };
+
+    let mut inputs = Vec::new();
+    for input in tx.inputs().into_iter() {
+        let (output, data) = load_cell(input.previous_output())?;
+        inputs.push(MockInput {
+            input,
+            output,
+            data,
+        });
+    }
+    let mut cell_deps = Vec::new();
+    for cell_dep in tx.cell_deps().into_iter() {
+        let (output, data) = load_cell(cell_dep.out_point())?;
+        if cell_dep.dep_type() == DepType::DepGroup.into() {
+            let sub_out_points = OutPointVec::from_slice(&data)
+                .map_err(|err| format!("Parse dep group data error: {}", err))?;
+            for sub_out_point in sub_out_points.into_iter() {
+                let (sub_output, sub_data) = load_cell(sub_out_point.clone())?;
+                cell_deps.push(MockCellDep {
+                    cell_dep: CellDep::new_builder().out_point(sub_out_point).build(),
+                    output: sub_output,
+                    data: sub_data,
+                });
+            }
+        }
+        cell_deps.push(MockCellDep {
+            cell_dep,
+            output,
+            data,
+        });
+    }
+    let mut header_deps = Vec::new();
+    for block_hash in tx.header_deps().into_iter() {
+        let header = loader
+            .get_header(block_hash.unpack())?
+            .ok_or_else(|| format!("Header not found: {:x}", block_hash))?;
+        header_deps.push(header);
+    }
+    Ok(MockTransaction {
+        mock_info: MockInfo {
+            inputs,
+            cell_deps,
+            header_deps,
+        },
+        tx: tx.data(),
+    })
+}
@@ -1,7 +1,11 @@
+use std::cell::{Cell, RefCell};
 use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fs;
-use std::io::Read;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
@@ -12,17 +16,18 @@ use rpassword::prompt_password_stdout;
 
 use ckb_hash::blake2b_256;
 use ckb_index::{LiveCellInfo, VERSION};
-use ckb_ledger::LedgerKeyStore;
+use ckb_ledger::{AppVersion, LedgerKeyStore, SignProgress};
 use ckb_sdk::{
     calc_max_mature_number,
     constants::{CELLBASE_MATURITY, MIN_SECP_CELL_CAPACITY, ONE_CKB},
-    rpc::{AlertMessage, Transaction},
+    rpc::Transaction,
+    serialize_signature,
     wallet::{
         AbstractKeyStore, AbstractMasterPrivKey, AbstractPrivKey, DerivationPath,
-        FullyBoxedAbstractMasterPrivkey, KeyStore, ScryptType,
+        FullyBoxedAbstractMasterPrivkey, KeyStore, RemoteSigner, ScryptType,
     },
-    Address, AddressPayload, CodeHashIndex, GenesisInfo, HttpRpcClient, NetworkType,
-    SignerClosureHelper, SignerFnTrait, SECP256K1,
+    Address, AddressPayload, BoxedSignerFn, CodeHashIndex, FeePolicy, GenesisInfo, HttpRpcClient,
+    NetworkType, SignerClosureHelper, SignerFnTrait, SECP256K1,
 };
 use ckb_types::{
     bytes::Bytes,
@@ -33,15 +38,163 @@ use ckb_types::{
 };
 
 use super::arg_parser::{
-    AddressParser, ArgParser, FixedHashParser, FromAccountParser, HexParser, PrivkeyPathParser,
-    PrivkeyWrapper, PubkeyHexParser,
+    AddressParser, ArgParser, CapacityParser, FixedHashParser, FromAccountParser, FromStrParser,
+    HexParser, PrivkeyPathParser, PrivkeyWrapper, PubkeyHexParser,
 };
 use super::index::{IndexController, IndexRequest, IndexThreadState};
+use super::index_backend::LightClientBackend;
+use super::interrupt::{check_interrupted, interruptible};
 use super::key_adapter::KeyAdapter;
+use super::printer::{is_a_tty, is_term_dumb};
 use crate::subcommands::account::AccountId;
 
+/// Where `read_password` gets the password from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PasswordSource {
+    /// Prompt on the terminal
+    Prompt,
+    /// Given by the caller (eg: a request of server mode), `None` means no
+    /// password is given and there is no terminal to prompt on
+    Preset(Option<String>),
+}
+
+thread_local! {
+    static PASSWORD_SOURCE: RefCell<PasswordSource> = RefCell::new(PasswordSource::Prompt);
+    static ASSUME_YES: Cell<bool> = Cell::new(false);
+}
+
+/// Environment variable of the password for automation (see
+/// `password_from_matches`)
+pub const PASSWORD_ENV_NAME: &str = "CKB_CLI_PASSWORD";
+
+/// Replace the password source of current thread
+pub fn set_password_source(source: PasswordSource) {
+    PASSWORD_SOURCE.with(|current| current.replace(source));
+}
+
+/// The password given by `--password-file`, `--password-fd` or
+/// `CKB_CLI_PASSWORD`, it answers the password prompts so the commands can
+/// run without a terminal, except the confirmation of a new password. A
+/// warning is printed since the password may leak (eg. the environment of a
+/// process can be read by the same user).
+pub fn password_from_matches(
+    m: &ArgMatches,
+    env_password: Option<String>,
+) -> Result<Option<String>, String> {
+    let password = if let Some(path) = m.value_of("password-file") {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(path)
+                .map_err(|err| format!("Read password file {} failed: {}", path, err))?
+                .permissions()
+                .mode();
+            if mode & 0o077 != 0 {
+                eprintln!(
+                    "WARNING: password file {} is accessible by other users (mode {:o}), run `chmod 600 {}`",
+                    path,
+                    mode & 0o777,
+                    path
+                );
+            }
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Read password file {} failed: {}", path, err))?;
+        trim_line_ending(content)
+    } else if let Some(fd) = m.value_of("password-fd") {
+        let fd: i32 = FromStrParser::<i32>::default().parse(fd)?;
+        // The fd is closed after reading, stdin/stdout/stderr must stay open
+        if fd < 3 {
+            return Err(format!(
+                "Invalid --password-fd {}: expected 3 or greater (0, 1 and 2 are the standard streams)",
+                fd
+            ));
+        }
+        read_password_fd(fd)?
+    } else {
+        match env_password {
+            Some(password) if !password.is_empty() => {
+                eprintln!(
+                    "WARNING: the password is read from {}, it is visible to the processes of the same user, prefer --password-file or --password-fd",
+                    PASSWORD_ENV_NAME
+                );
+                password
+            }
+            _ => return Ok(None),
+        }
+    };
+    if password.is_empty() {
+        return Err("The given password is empty".to_string());
+    }
+    Ok(Some(password))
+}
+
+#[cfg(unix)]
+fn read_password_fd(fd: i32) -> Result<String, String> {
+    use std::os::unix::io::FromRawFd;
+    // The fd is owned by the file from now on, it is closed after reading
+    let mut file = unsafe { fs::File::from_raw_fd(fd) };
+    let mut content = String::new();
+    file.read_to_string(&mut content)
+        .map_err(|err| format!("Read password from fd {} failed: {}", fd, err))?;
+    Ok(trim_line_ending(content))
+}
+
+#[cfg(not(unix))]
+fn read_password_fd(_fd: i32) -> Result<String, String> {
+    Err("--password-fd is only supported on unix".to_string())
+}
+
+// Only the line ending is removed, a password may start or end with spaces
+fn trim_line_ending(mut content: String) -> String {
+    if content.ends_with('\n') {
+        content.pop();
+        if content.ends_with('\r') {
+            content.pop();
+        }
+    }
+    content
+}
+
+/// Call `func` with the password read from `source`
+pub fn with_password_source<T, F: FnOnce() -> T>(source: PasswordSource, func: F) -> T {
+    let previous = PASSWORD_SOURCE.with(|current| current.replace(source));
+    let result = func();
+    PASSWORD_SOURCE.with(|current| current.replace(previous));
+    result
+}
+
+/// Read a password, `repeat` asks it twice to confirm a new password. The
+/// preset password (see `PasswordSource::Preset`) can not confirm a new
+/// password: a command reading the old and the new password would get the
+/// same one, see `read_confirmed_password`.
 pub fn read_password(repeat: bool, prompt: Option<&str>) -> Result<String, String> {
+    read_password_inner(repeat, false, prompt)
+}
+
+/// Read a password asked twice on a terminal (eg. the password of a new key
+/// by `account new`), the preset password is accepted since it is the only
+/// password the command reads
+pub fn read_confirmed_password(prompt: Option<&str>) -> Result<String, String> {
+    read_password_inner(true, true, prompt)
+}
+
+fn read_password_inner(
+    repeat: bool,
+    allow_preset: bool,
+    prompt: Option<&str>,
+) -> Result<String, String> {
     let prompt = prompt.unwrap_or("Password");
+    if let PasswordSource::Preset(password) = PASSWORD_SOURCE.with(|source| source.borrow().clone())
+    {
+        if repeat && !allow_preset {
+            return Err(format!(
+                "{} can not be given by --password-file, --password-fd or {}, please run the command on a terminal",
+                prompt, PASSWORD_ENV_NAME
+            ));
+        }
+        return password.ok_or_else(|| format!("{} is required", prompt));
+    }
     let pass =
         prompt_password_stdout(format!("{}: ", prompt).as_str()).map_err(|err| err.to_string())?;
     if repeat {
@@ -54,6 +207,98 @@ pub fn read_password(repeat: bool, prompt: Option<&str>) -> Result<String, Strin
     Ok(pass)
 }
 
+/// Answer yes to every confirmation of current thread (`--yes`), the prompts
+/// are still printed
+pub fn set_assume_yes(yes: bool) {
+    ASSUME_YES.with(|current| current.set(yes));
+}
+
+/// Ask the user to confirm on the terminal, never confirmed when there is no
+/// terminal to prompt on (server mode) unless `--yes` is given
+pub fn confirm(prompt: &str) -> Result<bool, String> {
+    if ASSUME_YES.with(Cell::get) {
+        eprintln!("{} [y/N]: y (--yes)", prompt);
+        return Ok(true);
+    }
+    if PASSWORD_SOURCE.with(|source| *source.borrow() != PasswordSource::Prompt) {
+        return Ok(false);
+    }
+    eprint!("{} [y/N]: ", prompt);
+    io::stderr().flush().map_err(|err| err.to_string())?;
+    let mut answer = String::new();
+    io::stdin()
+        .read_line(&mut answer)
+        .map_err(|err| err.to_string())?;
+    Ok(answer.trim().eq_ignore_ascii_case("y"))
+}
+
+/// Environment variable of the selected vault (also set by `--vault <name>`)
+pub const VAULT_ENV_NAME: &str = "CKB_CLI_VAULT";
+
+/// The data directory: `~/.ckb-cli`, or `~/.ckb-cli/vaults/<name>` when a
+/// vault is selected. Keystore, config, index database, address aliases and
+/// tx records are all stored under this directory.
+pub fn get_ckb_cli_dir() -> PathBuf {
+    let mut ckb_cli_dir = dirs::home_dir().unwrap();
+    ckb_cli_dir.push(".ckb-cli");
+    if let Some(vault) = env::var(VAULT_ENV_NAME)
+        .ok()
+        .filter(|name| !name.is_empty())
+    {
+        ckb_cli_dir.push("vaults");
+        ckb_cli_dir.push(vault);
+    }
+    ckb_cli_dir
+}
+
+/// Environment variable of the Speculos emulator APDU address (also set by
+/// `--ledger-emulator <host:port>`)
+pub const LEDGER_EMULATOR_ENV_NAME: &str = "CKB_CLI_LEDGER_EMULATOR";
+
+pub fn parse_ledger_emulator(input: &str) -> Result<SocketAddr, String> {
+    input
+        .to_socket_addrs()
+        .map_err(|err| format!("Invalid ledger emulator address {}: {}", input, err))?
+        .next()
+        .ok_or_else(|| format!("Ledger emulator address {} resolved to nothing", input))
+}
+
+/// The Speculos emulator used instead of ledger devices, if selected
+pub fn ledger_emulator() -> Result<Option<SocketAddr>, String> {
+    env::var(LEDGER_EMULATOR_ENV_NAME)
+        .ok()
+        .filter(|input| !input.is_empty())
+        .map(|input| parse_ledger_emulator(&input))
+        .transpose()
+}
+
+/// Environment variable of the oldest ledger app version allowed to sign
+/// (also set by `--ledger-min-app-version <x.y.z>`)
+pub const LEDGER_MIN_APP_VERSION_ENV_NAME: &str = "CKB_CLI_LEDGER_MIN_APP_VERSION";
+
+/// The oldest ledger app version allowed to sign, if configured
+pub fn ledger_min_app_version() -> Result<Option<AppVersion>, String> {
+    env::var(LEDGER_MIN_APP_VERSION_ENV_NAME)
+        .ok()
+        .filter(|input| !input.is_empty())
+        .map(|input| AppVersion::parse(&input))
+        .transpose()
+}
+
+pub fn check_vault_name(name: &str) -> Result<(), String> {
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "Invalid vault name: {:?}, only [a-zA-Z0-9_-] are allowed",
+            name
+        ));
+    }
+    Ok(())
+}
+
 fn get_some_dir(dir: &str, ckb_cli_dir: &PathBuf) -> Result<PathBuf, String> {
     let mut keystore_dir = ckb_cli_dir.clone();
     keystore_dir.push(dir);
@@ -67,8 +312,41 @@ pub fn get_key_store(ckb_cli_dir: &PathBuf) -> Result<KeyStore, String> {
 }
 
 pub fn get_ledger_key_store(ckb_cli_dir: &PathBuf) -> Result<LedgerKeyStore, String> {
-    let keystore_dir = get_some_dir("ledger-keystore", ckb_cli_dir)?;
-    LedgerKeyStore::from_dir(keystore_dir, ScryptType::default()).map_err(|err| err.to_string())
+    let keystore_dir = get_some_dir("ledger", ckb_cli_dir)?;
+    let mut ledger_key_store = LedgerKeyStore::from_dir(keystore_dir, ScryptType::default())
+        .map_err(|err| err.to_string())?;
+    ledger_key_store.set_emulator(ledger_emulator()?);
+    if let Some(version) = ledger_min_app_version()? {
+        ledger_key_store.set_min_app_version(version);
+    }
+    ledger_key_store.set_sign_progress(Some(Arc::new(print_ledger_sign_progress)));
+    Ok(ledger_key_store)
+}
+
+/// Show the progress of signing by a ledger on stderr, as a status line redrawn
+/// in place on a terminal, otherwise only the confirmation request is printed.
+fn print_ledger_sign_progress(progress: SignProgress) {
+    const BAR_WIDTH: usize = 20;
+    let status_line = is_a_tty(true) && !is_term_dumb();
+    let mut stderr = io::stderr();
+    let _ = match progress {
+        SignProgress::Sending { sent, total } if status_line => {
+            let filled = sent * BAR_WIDTH / total.max(1);
+            write!(
+                stderr,
+                "\r[{}{}] {}",
+                "#".repeat(filled),
+                "-".repeat(BAR_WIDTH - filled),
+                progress
+            )
+        }
+        SignProgress::Sending { .. } => Ok(()),
+        SignProgress::AwaitingConfirmation if status_line => write!(stderr, "\r\x1b[K{}", progress),
+        SignProgress::AwaitingConfirmation => writeln!(stderr, "{}", progress),
+        SignProgress::Finished if status_line => write!(stderr, "\r\x1b[K"),
+        SignProgress::Finished => Ok(()),
+    };
+    let _ = stderr.flush();
 }
 
 pub fn get_all_key_stores(ckb_cli_dir: &PathBuf) -> Result<(KeyStore, LedgerKeyStore), String> {
@@ -112,34 +390,32 @@ pub fn get_singer(
     }
 }
 
+/// Warn about the alerts reported by the node. The node does not report the
+/// alert signatures, so the messages are not shown (they may be forged by the
+/// node), a signed alert is shown by `util verify-alert` once verified.
 pub fn check_alerts(rpc_client: &mut HttpRpcClient) {
     if let Some(alerts) = rpc_client
         .get_blockchain_info()
         .ok()
         .map(|info| info.alerts)
     {
-        for AlertMessage {
-            id,
-            priority,
-            notice_until,
-            message,
-        } in alerts
-        {
-            if notice_until.0
-                >= SystemTime::now()
-                    .duration_since(UNIX_EPOCH)
-                    .expect("Time went backwards")
-                    .as_secs()
-                    * 1000
-            {
-                eprintln!(
-                    "[{}]: id={}, priority={}, message={}",
-                    "alert".yellow().bold(),
-                    id.to_string().blue().bold(),
-                    priority.to_string().blue().bold(),
-                    message.yellow().bold(),
-                )
-            }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs()
+            * 1000;
+        let ids = alerts
+            .iter()
+            .filter(|alert| alert.notice_until.0 >= now)
+            .map(|alert| alert.id.to_string())
+            .collect::<Vec<_>>();
+        if !ids.is_empty() {
+            eprintln!(
+                "[{}]: the node reports {} alert(s) (id: {}), the unverified messages are not shown, verify the signed alert by `util verify-alert`",
+                "alert".yellow().bold(),
+                ids.len(),
+                ids.join(", ").blue().bold(),
+            );
         }
     }
 }
@@ -174,6 +450,28 @@ pub fn get_live_cell_with_cache(
     }
 }
 
+/// `get_live_cell_with_cache` by the light client when it is given (by
+/// `--index-backend light-client`), the node is not asked for the cells
+pub fn get_input_cell_with_cache(
+    cache: &mut HashMap<(OutPoint, bool), ((CellOutput, Transaction), Bytes)>,
+    client: &mut HttpRpcClient,
+    light_client: Option<&mut LightClientBackend>,
+    out_point: OutPoint,
+    with_data: bool,
+) -> Result<((CellOutput, Transaction), Bytes), String> {
+    let light_client = match light_client {
+        Some(light_client) => light_client,
+        None => return get_live_cell_with_cache(cache, client, out_point, with_data),
+    };
+    if let Some(output) = cache.get(&(out_point.clone(), with_data)).cloned() {
+        return Ok(output);
+    }
+    let ((output, tx), data) = light_client.get_cell(out_point.clone())?;
+    let cell = ((output, tx.into()), data);
+    cache.insert((out_point, with_data), cell.clone());
+    Ok(cell)
+}
+
 pub fn get_live_cell(
     client: &mut HttpRpcClient,
     out_point: OutPoint,
@@ -245,6 +543,7 @@ pub fn index_dirname() -> String {
 pub fn sync_to_tip(index_controller: &IndexController) -> Result<(), String> {
     // Kick index thread to start
     Request::call(index_controller.sender(), IndexRequest::Kick);
+    let _guard = interruptible();
     loop {
         let state = IndexThreadState::clone(&index_controller.state().read());
         if state.is_synced() {
@@ -252,6 +551,10 @@ pub fn sync_to_tip(index_controller: &IndexController) -> Result<(), String> {
         } else if state.is_error() {
             return Err(state.get_error().unwrap());
         } else {
+            check_interrupted(&format!(
+                "index database: {} (the sync resumes from there)",
+                state
+            ))?;
             thread::sleep(Duration::from_millis(200));
         }
     }
@@ -314,6 +617,18 @@ pub fn get_to_data(m: &ArgMatches) -> Result<Bytes, String> {
     }
 }
 
+/// Fee policy from `--fee-rate` or `--tx-fee`
+pub fn get_fee_policy(m: &ArgMatches) -> Result<FeePolicy, String> {
+    let fee_rate_opt: Option<u64> =
+        FromStrParser::<u64>::new().from_matches_opt(m, "fee-rate", false)?;
+    match fee_rate_opt {
+        Some(fee_rate) => Ok(FeePolicy::Rate(fee_rate)),
+        None => CapacityParser
+            .from_matches(m, "tx-fee")
+            .map(FeePolicy::Fixed),
+    }
+}
+
 pub fn get_keystore_signer(
     key_store: KeyStore,
     account: H160,
@@ -326,6 +641,28 @@ pub fn get_keystore_signer(
     get_master_key_signer_raw(key, DerivationPath::empty())
 }
 
+/// The signer of `--privkey-path`, or of the software key of `--from-account`
+/// (the password is asked). The hardware wallet accounts are not supported.
+pub fn get_account_signer(
+    key_store: &KeyStore,
+    from_account: Either<PrivkeyWrapper, AccountId>,
+) -> Result<BoxedSignerFn<'static>, String> {
+    match from_account {
+        Either::Left(privkey) => Ok(Box::new(KeyAdapter(get_privkey_signer(privkey)?))),
+        Either::Right(AccountId::SoftwareMasterKey(lock_arg)) => {
+            let password = read_password(false, None)?;
+            Ok(Box::new(KeyAdapter(get_keystore_signer(
+                key_store.clone(),
+                lock_arg,
+                password,
+            )?)))
+        }
+        Either::Right(AccountId::LedgerId(_)) => {
+            Err("Hardware wallet is not supported by this command yet".to_string())
+        }
+    }
+}
+
 pub fn get_master_key_signer_raw<'a, K>(
     key: K,
     path: DerivationPath,
@@ -359,14 +696,6 @@ where
     }))
 }
 
-pub fn serialize_signature(signature: &secp256k1::recovery::RecoverableSignature) -> [u8; 65] {
-    let (recov_id, data) = signature.serialize_compact();
-    let mut signature_bytes = [0u8; 65];
-    signature_bytes[0..64].copy_from_slice(&data[0..64]);
-    signature_bytes[64] = recov_id.to_i32() as u8;
-    signature_bytes
-}
-
 pub fn serialize_signature_bytes(signature: &secp256k1::recovery::RecoverableSignature) -> Bytes {
     Bytes::from(&serialize_signature(signature)[..])
 }
@@ -391,6 +720,18 @@ pub fn privkey_or_from_account(
     })
 }
 
+/// The bearer token sent to the remote signer of `--signer-url`
+pub const SIGNER_TOKEN_ENV_NAME: &str = "CKB_CLI_SIGNER_TOKEN";
+
+pub fn remote_signer_from_matches(m: &ArgMatches) -> Result<Option<RemoteSigner>, String> {
+    let url = match m.value_of("signer-url") {
+        Some(url) => url,
+        None => return Ok(None),
+    };
+    let token = env::var(SIGNER_TOKEN_ENV_NAME).ok();
+    RemoteSigner::connect(url, token.as_ref().map(String::as_str)).map(Some)
+}
+
 pub fn make_address_payload_and_master_key_cap<'a>(
     from_account: &'a Either<PrivkeyWrapper, AccountId>,
     key_store: &'a mut KeyStore,
@@ -433,3 +774,66 @@ pub fn make_address_payload_and_master_key_cap<'a>(
         ),
     })
 }
+
+/// Parse a `0x` prefixed hex number in the JSON-RPC response
+pub fn parse_hex_u64(value: &serde_json::Value) -> Option<u64> {
+    value
+        .as_str()
+        .filter(|hex| hex.starts_with("0x"))
+        .and_then(|hex| u64::from_str_radix(&hex[2..], 16).ok())
+}
+
+/// The witness lock sizes of the `witness_placeholders` config, it maps the
+/// code hash of a lock script to the size of its signed witness lock:
+///
+/// ```json
+/// "witness_placeholders": { "0x<code hash>": 85 }
+/// ```
+pub fn witness_placeholders_from_config(
+    configs: &serde_json::Value,
+) -> Result<Vec<(H256, usize)>, String> {
+    let values = match configs.get("witness_placeholders") {
+        Some(serde_json::Value::Object(values)) => values,
+        Some(_) => {
+            return Err(
+                "Invalid witness_placeholders config: expected a map of code hash to size"
+                    .to_string(),
+            )
+        }
+        None => return Ok(Vec::new()),
+    };
+    values
+        .iter()
+        .map(|(code_hash, size)| {
+            let code_hash: H256 = FixedHashParser::<H256>::default()
+                .parse(code_hash)
+                .map_err(|err| format!("Invalid witness_placeholders config: {}", err))?;
+            let size = size.as_u64().ok_or_else(|| {
+                format!(
+                    "Invalid witness_placeholders config: {} is not a size",
+                    size
+                )
+            })?;
+            Ok((code_hash, size as usize))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_password_preset() {
+        let preset = PasswordSource::Preset(Some("secret".to_string()));
+        with_password_source(preset.clone(), || {
+            assert_eq!(read_password(false, None).unwrap(), "secret");
+            assert!(read_password(true, Some("New password")).is_err());
+            assert_eq!(read_confirmed_password(None).unwrap(), "secret");
+        });
+        with_password_source(PasswordSource::Preset(None), || {
+            assert!(read_password(false, None).is_err());
+            assert!(read_confirmed_password(None).is_err());
+        });
+    }
+}
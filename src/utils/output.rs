@@ -0,0 +1,67 @@
+use std::io::Write;
+use std::path::Path;
+
+use ckb_sdk::wallet;
+use flate2::{write::GzEncoder, Compression};
+
+/// Write the file atomically: the content is written to a temporary file in
+/// the same directory then renamed over `path`, so a crash never leaves a
+/// half written file behind.
+pub fn write_file_atomic(path: &Path, content: &[u8]) -> Result<(), String> {
+    wallet::write_file_atomic(path, content)
+        .map_err(|err| format!("Write {} failed: {}", path.to_string_lossy(), err))
+}
+
+/// Write the output of a command to a file (`--output-to`), gzip compressed
+/// when the file name ends with `.gz`
+pub fn write_output(path: &Path, output: &str) -> Result<(), String> {
+    let mut content = output.to_string();
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    if path.extension().map(|ext| ext == "gz").unwrap_or(false) {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let compressed = encoder
+            .write_all(content.as_bytes())
+            .and_then(|_| encoder.finish())
+            .map_err(|err| format!("Compress output failed: {}", err))?;
+        write_file_atomic(path, &compressed)
+    } else {
+        write_file_atomic(path, content.as_bytes())
+    }
+}
+
+// A hidden file next to the target, the rename is only atomic within the same
+// file system
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+    use std::env;
+    use std::fs;
+    use std::io::Read;
+    use std::process;
+
+    #[test]
+    fn test_write_output() {
+        let dir = env::temp_dir().join(format!("ckb-cli-output-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("cells.json");
+        write_output(&path, "{}").unwrap();
+        write_output(&path, "[]").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[]\n");
+
+        let path = dir.join("cells.csv.gz");
+        write_output(&path, "a,b\n1,2").unwrap();
+        let mut content = String::new();
+        GzDecoder::new(fs::File::open(&path).unwrap())
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "a,b\n1,2\n");
+
+        // No temporary file is left
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
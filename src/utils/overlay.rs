@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+
+use ckb_sdk::MockResourceLoader;
+use ckb_types::{
+    bytes::Bytes,
+    core::{HeaderView, TransactionView},
+    packed::{CellOutput, OutPoint},
+    prelude::*,
+    H256,
+};
+
+/// The changes of not yet sent transactions on top of the chain state: the
+/// outputs they create are live and the cells they consume are dead.
+#[derive(Default)]
+pub struct ChainOverlay {
+    // out point => (output, data, hash of the creating transaction)
+    created: HashMap<OutPoint, (CellOutput, Bytes, H256)>,
+    // out point => hash of the consuming transaction
+    consumed: HashMap<OutPoint, H256>,
+    transactions: HashMap<H256, TransactionView>,
+}
+
+impl ChainOverlay {
+    pub fn new() -> ChainOverlay {
+        ChainOverlay::default()
+    }
+
+    /// The transaction of the out point if it is created by an applied
+    /// transaction and not consumed yet
+    pub fn pending_cell(&self, out_point: &OutPoint) -> Option<(CellOutput, &TransactionView)> {
+        if self.consumed.contains_key(out_point) {
+            return None;
+        }
+        self.created
+            .get(out_point)
+            .map(|(output, _, tx_hash)| (output.clone(), &self.transactions[tx_hash]))
+    }
+
+    /// The hash of the applied transaction which consumed the out point
+    pub fn consumed_by(&self, out_point: &OutPoint) -> Option<&H256> {
+        self.consumed.get(out_point)
+    }
+
+    /// Check the inputs and cell deps are not consumed by the applied
+    /// transactions (a double spend on the chain)
+    pub fn check_conflicts(&self, tx: &TransactionView) -> Result<(), String> {
+        let inputs = tx.inputs().into_iter().map(|input| input.previous_output());
+        let cell_deps = tx.cell_deps().into_iter().map(|dep| dep.out_point());
+        for (kind, out_point) in inputs
+            .map(|out_point| ("input", out_point))
+            .chain(cell_deps.map(|out_point| ("cell dep", out_point)))
+        {
+            if let Some(tx_hash) = self.consumed_by(&out_point) {
+                return Err(format!(
+                    "The {} {} is already consumed by pending transaction {:#x}",
+                    kind, out_point, tx_hash
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Consume the inputs and create the outputs of the transaction
+    pub fn apply(&mut self, tx: &TransactionView) -> Result<(), String> {
+        self.check_conflicts(tx)?;
+        let tx_hash: H256 = tx.hash().unpack();
+        for input in tx.inputs().into_iter() {
+            self.consumed
+                .insert(input.previous_output(), tx_hash.clone());
+        }
+        for (index, (output, data)) in tx.outputs_with_data_iter().enumerate() {
+            let out_point = OutPoint::new(tx.hash(), index as u32);
+            self.created
+                .insert(out_point, (output, data, tx_hash.clone()));
+        }
+        self.transactions.insert(tx_hash, tx.clone());
+        Ok(())
+    }
+
+    /// Load the cells from the overlay first then by `loader`
+    pub fn loader<L: MockResourceLoader>(&self, loader: L) -> OverlayLoader<L> {
+        OverlayLoader {
+            overlay: self,
+            loader,
+        }
+    }
+}
+
+pub struct OverlayLoader<'a, L> {
+    overlay: &'a ChainOverlay,
+    loader: L,
+}
+
+impl<'a, L: MockResourceLoader> MockResourceLoader for OverlayLoader<'a, L> {
+    fn get_header(&mut self, hash: H256) -> Result<Option<HeaderView>, String> {
+        self.loader.get_header(hash)
+    }
+
+    fn get_live_cell(
+        &mut self,
+        out_point: OutPoint,
+    ) -> Result<Option<(CellOutput, Bytes)>, String> {
+        if self.overlay.consumed.contains_key(&out_point) {
+            return Ok(None);
+        }
+        if let Some((output, data, _)) = self.overlay.created.get(&out_point) {
+            return Ok(Some((output.clone(), data.clone())));
+        }
+        self.loader.get_live_cell(out_point)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ckb_types::{
+        core::{capacity_bytes, Capacity, TransactionBuilder},
+        h256,
+        packed::CellInput,
+    };
+
+    struct ChainLoader(HashMap<OutPoint, (CellOutput, Bytes)>);
+
+    impl MockResourceLoader for ChainLoader {
+        fn get_header(&mut self, _hash: H256) -> Result<Option<HeaderView>, String> {
+            Ok(None)
+        }
+        fn get_live_cell(
+            &mut self,
+            out_point: OutPoint,
+        ) -> Result<Option<(CellOutput, Bytes)>, String> {
+            Ok(self.0.get(&out_point).cloned())
+        }
+    }
+
+    fn spend(out_point: OutPoint, capacity: Capacity) -> TransactionView {
+        TransactionBuilder::default()
+            .input(CellInput::new(out_point, 0))
+            .output(CellOutput::new_builder().capacity(capacity.pack()).build())
+            .output_data(Bytes::new().pack())
+            .build()
+    }
+
+    #[test]
+    fn test_parent_and_child() {
+        let chain_out_point = OutPoint::new(h256!("0x1").pack(), 0);
+        let chain_cell = CellOutput::new_builder()
+            .capacity(capacity_bytes!(1000).pack())
+            .build();
+        let mut chain = HashMap::new();
+        chain.insert(chain_out_point.clone(), (chain_cell, Bytes::new()));
+
+        let mut overlay = ChainOverlay::new();
+        let parent = spend(chain_out_point.clone(), capacity_bytes!(999));
+        overlay.apply(&parent).unwrap();
+        let parent_out_point = OutPoint::new(parent.hash(), 0);
+
+        let mut loader = overlay.loader(ChainLoader(chain));
+        assert_eq!(loader.get_live_cell(chain_out_point.clone()), Ok(None));
+        let (output, _) = loader
+            .get_live_cell(parent_out_point.clone())
+            .unwrap()
+            .unwrap();
+        assert_eq!(output.capacity(), capacity_bytes!(999).pack());
+        assert!(overlay.pending_cell(&parent_out_point).is_some());
+
+        // Another spend of the chain cell is a double spend
+        let conflict = spend(chain_out_point, capacity_bytes!(998));
+        assert!(overlay.check_conflicts(&conflict).is_err());
+
+        let child = spend(parent_out_point.clone(), capacity_bytes!(998));
+        overlay.apply(&child).unwrap();
+        assert!(overlay.pending_cell(&parent_out_point).is_none());
+        assert_eq!(
+            overlay.consumed_by(&parent_out_point),
+            Some(&child.hash().unpack())
+        );
+    }
+}
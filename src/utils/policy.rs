@@ -0,0 +1,570 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use chrono::Utc;
+use ckb_sdk::{
+    rpc::Transaction,
+    wallet::{is_hardened_account_path, DerivationPath, ACCOUNT_LEVEL_DEPTH},
+    Address, AddressPayload, CellCollector, GenesisInfo, HumanCapacity, NetworkType, SignerFnTrait,
+    TransactionBuilder,
+};
+use ckb_types::{
+    core::{Capacity, TransactionView},
+    packed::{CellOutput, OutPoint},
+    prelude::*,
+    H256,
+};
+
+use super::arg_parser::{ArgParser, CapacityParser, FixedHashParser};
+
+const MANIFEST_FILENAME: &str = "signing-policy.json";
+const MANIFEST_LOG_FILENAME: &str = "signing-policy.log";
+
+// 1 CKB
+const DEFAULT_MAX_FEE: u64 = 100_000_000;
+// 1%
+const DEFAULT_MAX_FEE_BASIS_POINTS: u64 = 100;
+
+/// Signing policy from config file (`~/.ckb-cli/config`) and the policy
+/// manifest (`~/.ckb-cli/signing-policy.json`)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SigningPolicy {
+    // Refuse non-hardened account level derivation path
+    pub hardened_only: bool,
+    // Unit: shannon, the absolute fee cap
+    pub max_fee: u64,
+    // Unit: 0.01%, the fee cap relative to the transferred capacity
+    pub max_fee_basis_points: u64,
+    // Skip the fee caps, given by `--allow-high-fee` of the command
+    pub allow_high_fee: bool,
+    pub manifest: Option<Arc<PolicyManifest>>,
+}
+
+impl Default for SigningPolicy {
+    fn default() -> SigningPolicy {
+        SigningPolicy {
+            hardened_only: false,
+            max_fee: DEFAULT_MAX_FEE,
+            max_fee_basis_points: DEFAULT_MAX_FEE_BASIS_POINTS,
+            allow_high_fee: false,
+            manifest: None,
+        }
+    }
+}
+
+impl SigningPolicy {
+    pub fn from_config(configs: &serde_json::Value) -> Result<SigningPolicy, String> {
+        let max_fee = match &configs["max_fee"] {
+            serde_json::Value::Null => DEFAULT_MAX_FEE,
+            serde_json::Value::String(capacity) => CapacityParser
+                .parse(capacity)
+                .map_err(|err| format!("Invalid max_fee config: {}", err))?,
+            other => return Err(format!("Invalid max_fee config: {}", other)),
+        };
+        let max_fee_basis_points = match &configs["max_fee_percent"] {
+            serde_json::Value::Null => DEFAULT_MAX_FEE_BASIS_POINTS,
+            value => match value.as_f64() {
+                Some(percent) if percent >= 0.0 && percent <= 100.0 => {
+                    (percent * 100.0).round() as u64
+                }
+                _ => return Err(format!("Invalid max_fee_percent config: {}", value)),
+            },
+        };
+        // The policy manifest given in config (eg: by the remote config)
+        // instead of the manifest file
+        let manifest = match &configs["signing_policy"] {
+            serde_json::Value::Null => None,
+            value => Some(Arc::new(
+                PolicyManifest::from_json(&value.to_string())
+                    .map_err(|err| format!("Invalid signing_policy config: {}", err))?,
+            )),
+        };
+        Ok(SigningPolicy {
+            hardened_only: configs["hardened_only"].as_bool().unwrap_or(false),
+            max_fee,
+            max_fee_basis_points,
+            allow_high_fee: false,
+            manifest,
+        })
+    }
+
+    /// The config values differ from the defaults, saved to config file
+    #[allow(dead_code)]
+    pub fn to_config(&self) -> serde_json::Value {
+        let mut configs = serde_json::json!({ "hardened_only": self.hardened_only });
+        if self.max_fee != DEFAULT_MAX_FEE {
+            configs["max_fee"] = serde_json::json!(HumanCapacity::from(self.max_fee).to_string());
+        }
+        if self.max_fee_basis_points != DEFAULT_MAX_FEE_BASIS_POINTS {
+            configs["max_fee_percent"] =
+                serde_json::json!(self.max_fee_basis_points as f64 / 100.0);
+        }
+        configs
+    }
+
+    /// Load the policy manifest from `ckb_cli_dir` if it exists and it is not
+    /// given in config
+    pub fn load_manifest(&mut self, ckb_cli_dir: &Path) -> Result<(), String> {
+        let path = ckb_cli_dir.join(MANIFEST_FILENAME);
+        if let Some(manifest) = self.manifest.take() {
+            let mut manifest = (*manifest).clone();
+            manifest.log_path = Some(ckb_cli_dir.join(MANIFEST_LOG_FILENAME));
+            self.manifest = Some(Arc::new(manifest));
+        } else if path.exists() {
+            let content = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+            let mut manifest = PolicyManifest::from_json(&content)
+                .map_err(|err| format!("Invalid signing policy {}: {}", path.display(), err))?;
+            manifest.log_path = Some(ckb_cli_dir.join(MANIFEST_LOG_FILENAME));
+            self.manifest = Some(Arc::new(manifest));
+        }
+        Ok(())
+    }
+
+    /// Check the transaction by the policy manifest before pass it to any
+    /// signer, the violations are logged.
+    pub fn check_transaction<F>(
+        &self,
+        tx: &TransactionView,
+        network: NetworkType,
+        get_live_cell: &mut F,
+    ) -> Result<(), String>
+    where
+        F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+    {
+        self.check_fee(tx, get_live_cell)?;
+        let manifest = match self.manifest {
+            Some(ref manifest) => manifest,
+            None => return Ok(()),
+        };
+        let violations = manifest.violations(tx, network, get_live_cell)?;
+        if violations.is_empty() {
+            return Ok(());
+        }
+        let tx_hash: H256 = tx.hash().unpack();
+        if let Err(err) = manifest.log(&tx_hash, &violations) {
+            eprintln!("[WARNING] Write signing policy log failed: {}", err);
+        }
+        Err(format!(
+            "Transaction {:#x} refused by signing policy:\n  {}",
+            tx_hash,
+            violations.join("\n  ")
+        ))
+    }
+
+    /// Sanity check of the fee against `max_fee` and `max_fee_percent` of the
+    /// transferred capacity (the outputs not go back to the locks of inputs,
+    /// or all the outputs when there is none), protects from burning funds by
+    /// bugs or bad fee rate. The DAO compensation of inputs is not counted.
+    fn check_fee<F>(&self, tx: &TransactionView, get_live_cell: &mut F) -> Result<(), String>
+    where
+        F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+    {
+        if self.allow_high_fee {
+            return Ok(());
+        }
+        let mut input_locks = HashSet::new();
+        let mut input_capacity: u64 = 0;
+        for input in tx.inputs().into_iter() {
+            let (output, _) = get_live_cell(input.previous_output(), false)?;
+            let capacity: Capacity = output.capacity().unpack();
+            input_capacity = input_capacity.saturating_add(capacity.as_u64());
+            input_locks.insert(output.lock());
+        }
+        let mut output_capacity: u64 = 0;
+        let mut sent_capacity: u64 = 0;
+        for output in tx.outputs().into_iter() {
+            let capacity: Capacity = output.capacity().unpack();
+            output_capacity = output_capacity.saturating_add(capacity.as_u64());
+            if !input_locks.contains(&output.lock()) {
+                sent_capacity = sent_capacity.saturating_add(capacity.as_u64());
+            }
+        }
+        let fee = input_capacity.saturating_sub(output_capacity);
+        let transferred = if sent_capacity > 0 {
+            sent_capacity
+        } else {
+            output_capacity
+        };
+        self.check_total_fee(fee, transferred)
+    }
+
+    /// The check of `check_transaction` for the fee paid by several
+    /// transactions which transfer `transferred` together (eg: a transfer
+    /// split into consolidation transactions and the payment)
+    pub fn check_total_fee(&self, fee: u64, transferred: u64) -> Result<(), String> {
+        if self.allow_high_fee {
+            return Ok(());
+        }
+        let relative_max_fee =
+            (u128::from(transferred) * u128::from(self.max_fee_basis_points) / 10_000) as u64;
+        let exceeded = if fee > self.max_fee {
+            format!("max_fee {:#}", HumanCapacity::from(self.max_fee))
+        } else if fee > relative_max_fee {
+            format!(
+                "max_fee_percent {}% of transferred capacity {:#}",
+                self.max_fee_basis_points as f64 / 100.0,
+                HumanCapacity::from(transferred)
+            )
+        } else {
+            return Ok(());
+        };
+        Err(format!(
+            "Transaction fee {:#} exceeds {}, refuse to sign. Check the fee arguments, or pass --allow-high-fee if it is intended",
+            HumanCapacity::from(fee),
+            exceeded
+        ))
+    }
+
+    /// `TransactionBuilder::build` with the unsigned transaction checked by
+    /// `check_transaction` before signing
+    pub fn build_transaction<C, F, S>(
+        &self,
+        builder: &TransactionBuilder,
+        collector: &mut C,
+        get_live_cell: &mut F,
+        genesis_info: &GenesisInfo,
+        network: NetworkType,
+        signer: S,
+    ) -> Result<TransactionView, String>
+    where
+        C: CellCollector,
+        F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+        S: SignerFnTrait,
+    {
+        let helper = builder.build_unsigned(collector, get_live_cell, genesis_info)?;
+        self.check_transaction(helper.transaction(), network, get_live_cell)?;
+        builder.sign(helper, get_live_cell, signer)
+    }
+
+    /// Check the derivation path before derive or sign with it. For hardware
+    /// wallet the path is refused, for software keys only a warning is printed.
+    pub fn check_path(&self, path: &DerivationPath, is_hardware: bool) -> Result<(), String> {
+        if !self.hardened_only || is_hardened_account_path(path.as_ref()) {
+            return Ok(());
+        }
+        let message = format!(
+            "Derivation path {} is not hardened at account level (first {} levels)",
+            path, ACCOUNT_LEVEL_DEPTH
+        );
+        if is_hardware {
+            Err(format!("{}, refused by hardened_only policy", message))
+        } else {
+            eprintln!("[WARNING] {}", message);
+            Ok(())
+        }
+    }
+}
+
+/// Operator-writable limits of automated signing, eg:
+///
+/// ```json
+/// {
+///     "max_capacity_per_tx": "10000.0",
+///     "allowed_destination_prefixes": ["ckb1qyqt8xaupvm8837nv3gtc9x0ekkj64vud3jqfwyw5v"],
+///     "allowed_code_hashes": ["0x9bd7e06f3ecf4be0f2fcd2188b23f1b9fcc88e5d4b65a8637b17723bbda3cce8"]
+/// }
+/// ```
+///
+/// The outputs go back to the locks of inputs (change) are not destinations.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PolicyManifest {
+    // Unit: shannon, the capacity sent to destinations
+    pub max_capacity_per_tx: Option<u64>,
+    pub allowed_destination_prefixes: Option<Vec<String>>,
+    // The lock and type scripts of outputs
+    pub allowed_code_hashes: Option<HashSet<H256>>,
+    log_path: Option<PathBuf>,
+}
+
+impl PolicyManifest {
+    pub fn from_json(content: &str) -> Result<PolicyManifest, String> {
+        let value: serde_json::Value =
+            serde_json::from_str(content).map_err(|err| err.to_string())?;
+        let max_capacity_per_tx = match &value["max_capacity_per_tx"] {
+            serde_json::Value::Null => None,
+            serde_json::Value::String(capacity) => Some(CapacityParser.parse(capacity)?),
+            other => return Err(format!("Invalid max_capacity_per_tx: {}", other)),
+        };
+        let allowed_destination_prefixes = string_list(&value, "allowed_destination_prefixes")?;
+        let allowed_code_hashes = string_list(&value, "allowed_code_hashes")?
+            .map(|hashes| {
+                hashes
+                    .iter()
+                    .map(|hash| FixedHashParser::<H256>::default().parse(hash))
+                    .collect::<Result<HashSet<_>, String>>()
+            })
+            .transpose()?;
+        Ok(PolicyManifest {
+            max_capacity_per_tx,
+            allowed_destination_prefixes,
+            allowed_code_hashes,
+            log_path: None,
+        })
+    }
+
+    pub fn violations<F>(
+        &self,
+        tx: &TransactionView,
+        network: NetworkType,
+        get_live_cell: &mut F,
+    ) -> Result<Vec<String>, String>
+    where
+        F: FnMut(OutPoint, bool) -> Result<(CellOutput, Transaction), String>,
+    {
+        let mut input_locks = HashSet::new();
+        for input in tx.inputs().into_iter() {
+            let (output, _) = get_live_cell(input.previous_output(), false)?;
+            input_locks.insert(output.lock());
+        }
+
+        let mut violations = Vec::new();
+        let mut sent_capacity: u64 = 0;
+        for (idx, output) in tx.outputs().into_iter().enumerate() {
+            let scripts = Some(output.lock())
+                .into_iter()
+                .chain(output.type_().to_opt());
+            if let Some(ref allowed) = self.allowed_code_hashes {
+                for script in scripts {
+                    let code_hash: H256 = script.code_hash().unpack();
+                    if !allowed.contains(&code_hash) {
+                        violations.push(format!(
+                            "output #{} uses script code_hash {:#x} not allowed",
+                            idx, code_hash
+                        ));
+                    }
+                }
+            }
+            if input_locks.contains(&output.lock()) {
+                continue;
+            }
+            let capacity: Capacity = output.capacity().unpack();
+            sent_capacity = sent_capacity.saturating_add(capacity.as_u64());
+            if let Some(ref prefixes) = self.allowed_destination_prefixes {
+                let payload = match AddressPayload::try_from(output.lock()) {
+                    Ok(payload) => payload,
+                    Err(err) => {
+                        violations.push(format!("output #{} has an invalid lock: {}", idx, err));
+                        continue;
+                    }
+                };
+                let address_string = Address::new(network, payload).to_string();
+                if !prefixes
+                    .iter()
+                    .any(|prefix| address_string.starts_with(prefix.as_str()))
+                {
+                    violations.push(format!(
+                        "output #{} destination {} is not allowed",
+                        idx, address_string
+                    ));
+                }
+            }
+        }
+        if let Some(max_capacity) = self.max_capacity_per_tx {
+            if sent_capacity > max_capacity {
+                violations.push(format!(
+                    "sent capacity {:#} exceeds max_capacity_per_tx {:#}",
+                    HumanCapacity::from(sent_capacity),
+                    HumanCapacity::from(max_capacity)
+                ));
+            }
+        }
+        Ok(violations)
+    }
+
+    fn log(&self, tx_hash: &H256, violations: &[String]) -> Result<(), String> {
+        let path = match self.log_path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|err| err.to_string())?;
+        for violation in violations {
+            writeln!(
+                file,
+                "{} {:#x} {}",
+                Utc::now().to_rfc3339(),
+                tx_hash,
+                violation
+            )
+            .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+fn string_list(value: &serde_json::Value, key: &str) -> Result<Option<Vec<String>>, String> {
+    match &value[key] {
+        serde_json::Value::Null => Ok(None),
+        serde_json::Value::Array(items) => items
+            .iter()
+            .map(|item| {
+                item.as_str()
+                    .map(ToOwned::to_owned)
+                    .ok_or_else(|| format!("Invalid item of {}: {}", key, item))
+            })
+            .collect::<Result<Vec<_>, String>>()
+            .map(Some),
+        other => Err(format!("Invalid {}: {}", key, other)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_check_path() {
+        let policy = SigningPolicy {
+            hardened_only: true,
+            ..SigningPolicy::default()
+        };
+        let hardened = DerivationPath::from_str("m/44'/309'/0'/0/1").unwrap();
+        let normal = DerivationPath::from_str("m/44'/309'/0/0/1").unwrap();
+        assert!(policy.check_path(&hardened, true).is_ok());
+        assert!(policy.check_path(&DerivationPath::empty(), true).is_ok());
+        assert!(policy.check_path(&normal, true).is_err());
+        assert!(policy.check_path(&normal, false).is_ok());
+        assert!(SigningPolicy::default().check_path(&normal, true).is_ok());
+    }
+
+    #[test]
+    fn test_check_fee() {
+        use ckb_types::{core::TransactionBuilder, packed::CellInput, H160};
+
+        let sender = AddressPayload::from_pubkey_hash(H160::from([1u8; 20]));
+        let receiver = AddressPayload::from_pubkey_hash(H160::from([2u8; 20]));
+        let output = |payload: &AddressPayload, capacity: u64| {
+            CellOutput::new_builder()
+                .capacity(Capacity::shannons(capacity).pack())
+                .lock(payload.into())
+                .build()
+        };
+        let check = |policy: &SigningPolicy, sent: u64, change: u64| {
+            let tx = TransactionBuilder::default()
+                .input(CellInput::new(OutPoint::default(), 0))
+                .output(output(&receiver, sent))
+                .output(output(&sender, change))
+                .build();
+            let mut get_live_cell = |_: OutPoint, _: bool| -> Result<_, String> {
+                Ok((output(&sender, 10_000_000_000_000), Transaction::default()))
+            };
+            policy.check_fee(&tx, &mut get_live_cell).is_ok()
+        };
+
+        let policy = SigningPolicy::default();
+        // 1000 CKB sent, 0.001 CKB fee
+        assert!(check(&policy, 100_000_000_000, 9_899_999_900_000));
+        // 2 CKB fee
+        assert!(!check(&policy, 100_000_000_000, 9_899_800_000_000));
+        // 0.5 CKB fee of 10 CKB sent
+        assert!(!check(&policy, 1_000_000_000, 9_998_950_000_000));
+        // The fee of several transactions
+        assert!(policy.check_total_fee(100_000, 100_000_000_000).is_ok());
+        assert!(policy
+            .check_total_fee(200_000_000, 100_000_000_000)
+            .is_err());
+        let policy = SigningPolicy {
+            allow_high_fee: true,
+            ..SigningPolicy::default()
+        };
+        assert!(check(&policy, 100_000_000_000, 9_899_800_000_000));
+        assert!(policy.check_total_fee(200_000_000, 100_000_000_000).is_ok());
+
+        let configs = serde_json::json!({ "max_fee": "0.5", "max_fee_percent": 2.5 });
+        let policy = SigningPolicy::from_config(&configs).unwrap();
+        assert_eq!(policy.max_fee, 50_000_000);
+        assert_eq!(policy.max_fee_basis_points, 250);
+        assert_eq!(
+            SigningPolicy::from_config(&policy.to_config()).unwrap(),
+            policy
+        );
+        assert!(
+            SigningPolicy::from_config(&serde_json::json!({ "max_fee_percent": 200 })).is_err()
+        );
+    }
+
+    #[test]
+    fn test_manifest_violations() {
+        use ckb_sdk::constants::SIGHASH_TYPE_HASH;
+        use ckb_types::{
+            core::TransactionBuilder,
+            packed::{Byte, CellInput, Script},
+            H160,
+        };
+
+        let sender = AddressPayload::from_pubkey_hash(H160::from([1u8; 20]));
+        let receiver = AddressPayload::from_pubkey_hash(H160::from([2u8; 20]));
+        let receiver_address = Address::new(NetworkType::Testnet, receiver.clone()).to_string();
+        let output = |payload: &AddressPayload, capacity: u64| {
+            CellOutput::new_builder()
+                .capacity(Capacity::shannons(capacity).pack())
+                .lock(payload.into())
+                .build()
+        };
+        let tx = TransactionBuilder::default()
+            .input(CellInput::new(OutPoint::default(), 0))
+            .output(output(&receiver, 300))
+            .output(output(&sender, 700))
+            .build();
+        let check = |manifest: &str| {
+            let mut get_live_cell = |_: OutPoint, _: bool| -> Result<_, String> {
+                Ok((output(&sender, 1000), Transaction::default()))
+            };
+            PolicyManifest::from_json(manifest)
+                .unwrap()
+                .violations(&tx, NetworkType::Testnet, &mut get_live_cell)
+                .unwrap()
+                .len()
+        };
+
+        assert_eq!(check("{}"), 0);
+        let manifest = format!(
+            r#"{{"max_capacity_per_tx": "0.000003", "allowed_destination_prefixes": ["{}"], "allowed_code_hashes": ["{:#x}"]}}"#,
+            receiver_address, SIGHASH_TYPE_HASH
+        );
+        assert_eq!(check(&manifest), 0);
+        assert_eq!(check(r#"{"max_capacity_per_tx": "0.000002"}"#), 1);
+        assert_eq!(
+            check(r#"{"allowed_destination_prefixes": ["ckt1nope"]}"#),
+            1
+        );
+        assert_eq!(
+            check(
+                r#"{"allowed_code_hashes": ["0x0000000000000000000000000000000000000000000000000000000000000000"]}"#
+            ),
+            2
+        );
+        assert!(PolicyManifest::from_json(r#"{"max_capacity_per_tx": 1}"#).is_err());
+
+        // An unknown hash_type of the transaction file is a violation
+        let invalid_lock = Script::from(&receiver)
+            .as_builder()
+            .hash_type(Byte::new(0x07))
+            .build();
+        let tx = tx
+            .as_advanced_builder()
+            .set_outputs(vec![output(&receiver, 300)
+                .as_builder()
+                .lock(invalid_lock)
+                .build()])
+            .build();
+        let mut get_live_cell = |_: OutPoint, _: bool| -> Result<_, String> {
+            Ok((output(&sender, 1000), Transaction::default()))
+        };
+        let manifest =
+            PolicyManifest::from_json(r#"{"allowed_destination_prefixes": ["ckt1"]}"#).unwrap();
+        let violations = manifest
+            .violations(&tx, NetworkType::Testnet, &mut get_live_cell)
+            .unwrap();
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].contains("invalid lock"), "{}", violations[0]);
+    }
+}
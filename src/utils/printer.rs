@@ -1,3 +1,5 @@
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
 use std::env;
 use std::fmt;
 
@@ -7,6 +9,47 @@ use colored::Colorize;
 use crate::utils::json_color::Colorizer;
 use crate::utils::yaml_ser;
 
+thread_local! {
+    static CAPTURE_JSON: Cell<bool> = Cell::new(false);
+    // The last value rendered in json without color and its text
+    static CAPTURED_JSON: RefCell<Option<(serde_json::Value, String)>> = RefCell::new(None);
+}
+
+/// Run `f` and keep the last value it rendered in json without color, the
+/// output of `f` is mapped back to the value by `CapturedJson::into_value`
+/// instead of being parsed.
+pub fn capture_json<T, F>(f: F) -> (T, CapturedJson)
+where
+    F: FnOnce() -> T,
+{
+    let capturing = CAPTURE_JSON.with(|capture| capture.replace(true));
+    let previous = CAPTURED_JSON.with(|captured| captured.borrow_mut().take());
+    let result = f();
+    CAPTURE_JSON.with(|capture| capture.set(capturing));
+    let captured = CAPTURED_JSON.with(|captured| captured.replace(previous));
+    (result, CapturedJson(captured))
+}
+
+pub struct CapturedJson(Option<(serde_json::Value, String)>);
+
+impl CapturedJson {
+    /// The value `output` was rendered from, a json string when `output` is a
+    /// text (eg: a message)
+    pub fn into_value(self, output: String) -> serde_json::Value {
+        match self.0 {
+            Some((value, text)) if text == output => value,
+            _ => serde_json::Value::String(output),
+        }
+    }
+}
+
+fn captured_json(value: serde_json::Value, text: String) -> String {
+    if CAPTURE_JSON.with(Cell::get) {
+        CAPTURED_JSON.with(|captured| *captured.borrow_mut() = Some((value, text.clone())));
+    }
+    text
+}
+
 pub fn is_a_tty(stderr: bool) -> bool {
     let stream = if stderr {
         atty::Stream::Stderr
@@ -24,6 +67,7 @@ pub fn is_term_dumb() -> bool {
 pub enum OutputFormat {
     Yaml,
     Json,
+    Csv,
 }
 
 impl fmt::Display for OutputFormat {
@@ -34,6 +78,7 @@ impl fmt::Display for OutputFormat {
             match self {
                 OutputFormat::Yaml => "yaml",
                 OutputFormat::Json => "json",
+                OutputFormat::Csv => "csv",
             }
         )
     }
@@ -44,6 +89,7 @@ impl OutputFormat {
         match format {
             "yaml" => Ok(OutputFormat::Yaml),
             "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
             _ => Err(format!("Invalid output format: {}", format)),
         }
     }
@@ -106,13 +152,167 @@ where
                 if color {
                     Colorizer::arbitrary().colorize_json_value(&value).unwrap()
                 } else {
-                    serde_json::to_string_pretty(&value).unwrap()
+                    let text = serde_json::to_string_pretty(&value).unwrap();
+                    captured_json(value, text)
                 }
             }
+            OutputFormat::Csv => to_csv(&serde_json::to_value(self).unwrap()),
+        }
+    }
+}
+
+/// Render a response with a list field, in CSV only the rows of the list are
+/// rendered (the summary fields are dropped).
+pub fn render_list(
+    value: &serde_json::Value,
+    key: &str,
+    format: OutputFormat,
+    color: bool,
+) -> String {
+    if format == OutputFormat::Csv {
+        value[key].render(format, color)
+    } else {
+        value.render(format, color)
+    }
+}
+
+/// Render rows as CSV (RFC 4180), the fields are quoted when needed.
+pub fn render_csv(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let render_row = |cells: Vec<&str>| {
+        cells
+            .into_iter()
+            .map(escape_csv_field)
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let mut lines = vec![render_row(headers.to_vec())];
+    for row in rows {
+        lines.push(render_row(row.iter().map(String::as_str).collect()));
+    }
+    lines.join("\n")
+}
+
+fn escape_csv_field(field: &str) -> String {
+    if field.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// An array is a row per item, the columns are the union of (flattened) keys,
+// an object is a row of key and value.
+fn to_csv(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Array(items) => {
+            let rows = items
+                .iter()
+                .map(|item| {
+                    let mut cells = Vec::new();
+                    flatten_csv_value(String::new(), item, &mut cells);
+                    cells
+                })
+                .collect::<Vec<_>>();
+            let headers = rows
+                .iter()
+                .flat_map(|cells| cells.iter().map(|(name, _)| name.clone()))
+                .collect::<BTreeSet<_>>()
+                .into_iter()
+                .collect::<Vec<_>>();
+            let rows = rows
+                .into_iter()
+                .map(|cells| {
+                    headers
+                        .iter()
+                        .map(|header| {
+                            cells
+                                .iter()
+                                .find(|(name, _)| name == header)
+                                .map(|(_, cell)| cell.clone())
+                                .unwrap_or_default()
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+            render_csv(
+                &headers.iter().map(String::as_str).collect::<Vec<_>>(),
+                &rows,
+            )
+        }
+        serde_json::Value::Object(_) => {
+            let mut cells = Vec::new();
+            flatten_csv_value(String::new(), value, &mut cells);
+            let rows = cells
+                .into_iter()
+                .map(|(name, cell)| vec![name, cell])
+                .collect::<Vec<_>>();
+            render_csv(&["key", "value"], &rows)
+        }
+        value => render_csv(&["value"], &[vec![csv_cell(value)]]),
+    }
+}
+
+// Nested objects are flattened as `parent.child` columns
+fn flatten_csv_value(prefix: String, value: &serde_json::Value, cells: &mut Vec<(String, String)>) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map {
+                let name = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_csv_value(name, value, cells);
+            }
+        }
+        value => {
+            let name = if prefix.is_empty() {
+                "value".to_string()
+            } else {
+                prefix
+            };
+            cells.push((name, csv_cell(value)));
         }
     }
 }
 
+fn csv_cell(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(value) => value.clone(),
+        value => value.to_string(),
+    }
+}
+
+/// Render rows as a plain text table, the columns are aligned by the widest
+/// cell (include the header).
+pub fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths = headers
+        .iter()
+        .map(|header| header.len())
+        .collect::<Vec<_>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let render_row = |cells: Vec<&str>| {
+        cells
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    let mut lines = vec![render_row(headers.to_vec())];
+    for row in rows {
+        lines.push(render_row(row.iter().map(String::as_str).collect()));
+    }
+    lines.join("\n")
+}
+
 #[derive(Clone, Debug)]
 pub enum TypedStr<'a> {
     Null(Option<&'a str>),
@@ -158,3 +358,50 @@ impl<'a> TypedStr<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_csv() {
+        let value = serde_json::json!([
+            {"#": 0, "address": {"mainnet": "ckb1a", "testnet": "ckt1a"}, "memo": "rent, \"march\""},
+            {"#": 1, "ledger_id": "0x01"},
+        ]);
+        assert_eq!(
+            value.render(OutputFormat::Csv, false),
+            "#,address.mainnet,address.testnet,ledger_id,memo\n\
+             0,ckb1a,ckt1a,,\"rent, \"\"march\"\"\"\n\
+             1,,,0x01,"
+        );
+        let value = serde_json::json!({"total": 10, "cells": [{"index": 1}]});
+        assert_eq!(
+            render_list(&value, "cells", OutputFormat::Csv, false),
+            "index\n1"
+        );
+        assert_eq!(
+            value.render(OutputFormat::Csv, false),
+            "key,value\ncells,\"[{\"\"index\"\":1}]\"\ntotal,10"
+        );
+    }
+    #[test]
+    fn test_capture_json() {
+        let value = serde_json::json!({"capacity": "100.0", "cells": [1, 2]});
+        let (output, captured) = capture_json(|| value.render(OutputFormat::Json, false));
+        assert_eq!(captured.into_value(output), value);
+
+        // A message printed after the value is rendered is not the value
+        let (output, captured) = capture_json(|| {
+            let _ = value.render(OutputFormat::Json, false);
+            "DONE".to_string()
+        });
+        assert_eq!(captured.into_value(output), serde_json::json!("DONE"));
+
+        // Nothing is kept out of `capture_json`
+        let (output, captured) = capture_json(|| "true".to_string());
+        assert_eq!(captured.into_value(output), serde_json::json!("true"));
+        let _ = value.render(OutputFormat::Json, false);
+        assert!(CAPTURED_JSON.with(|captured| captured.borrow().is_none()));
+    }
+}
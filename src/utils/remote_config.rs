@@ -0,0 +1,313 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use ckb_crypto::secp::{Message, Pubkey, Signature};
+use ckb_hash::blake2b_256;
+use ckb_jsonrpc_types::JsonBytes;
+use ckb_sdk::rpc::http_get;
+use serde_derive::{Deserialize, Serialize};
+
+use super::arg_parser::{ArgParser, HexParser, UrlParser};
+use super::output::write_file_atomic;
+
+const CACHE_FILENAME: &str = "remote-config.json";
+const DEFAULT_REFRESH_SECS: u64 = 3600;
+
+/// The config entries which may be given by the remote config: the node
+/// list, the fee policy, the spending limits (the signing policy manifest)
+/// and the known witness sizes
+pub const REMOTE_CONFIG_KEYS: &[&str] = &[
+    "url",
+    "broadcast_urls",
+    "max_fee",
+    "max_fee_percent",
+    "hardened_only",
+    "signing_policy",
+    "witness_placeholders",
+];
+
+/// Parts of the config loaded from a signed document at an url, the signing
+/// hosts of a fleet are configured centrally by it. In config file
+/// (`~/.ckb-cli/config`):
+///
+/// ```json
+/// "remote_config": {
+///     "url": "https://ops.example.com/ckb-cli.json",
+///     "public_key": "0x03...",
+///     "refresh_secs": 3600
+/// }
+/// ```
+///
+/// The verified document is cached in `~/.ckb-cli/remote-config.json`, it is
+/// fetched again after `refresh_secs` and used when the url is unreachable.
+#[derive(Debug, Clone)]
+pub struct RemoteConfig {
+    pub url: String,
+    pub public_key: Pubkey,
+    pub refresh_secs: u64,
+}
+
+/// The document served at the url. `content` is the JSON object of the
+/// config entries with an increasing `serial`, `signature` is the recoverable
+/// signature of `blake2b_256(content)` (by `util sign-message --recoverable`).
+#[derive(Serialize, Deserialize)]
+struct SignedConfig {
+    content: String,
+    signature: JsonBytes,
+}
+
+type ConfigEntries = serde_json::Map<String, serde_json::Value>;
+
+impl RemoteConfig {
+    pub fn from_config(configs: &serde_json::Value) -> Result<Option<RemoteConfig>, String> {
+        let value = match configs.get("remote_config") {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let url = value["url"]
+            .as_str()
+            .ok_or_else(|| "Invalid remote_config config: missing url".to_string())?;
+        UrlParser
+            .validate(url)
+            .map_err(|err| format!("Invalid remote_config config: {}: {}", url, err))?;
+        let public_key = value["public_key"]
+            .as_str()
+            .ok_or_else(|| "Invalid remote_config config: missing public_key".to_string())?;
+        let public_key = HexParser
+            .parse(public_key)
+            .and_then(|bytes| Pubkey::from_slice(&bytes).map_err(|err| err.to_string()))
+            .map_err(|err| format!("Invalid remote_config public_key: {}", err))?;
+        let refresh_secs = match &value["refresh_secs"] {
+            serde_json::Value::Null => DEFAULT_REFRESH_SECS,
+            other => other
+                .as_u64()
+                .ok_or_else(|| format!("Invalid remote_config refresh_secs: {}", other))?,
+        };
+        Ok(Some(RemoteConfig {
+            url: url.to_string(),
+            public_key,
+            refresh_secs,
+        }))
+    }
+
+    /// The config entries of the cache, or of the url when the cache is
+    /// older than `refresh_secs`
+    pub fn load(&self, ckb_cli_dir: &Path) -> Result<ConfigEntries, String> {
+        self.load_with(ckb_cli_dir, http_get)
+    }
+
+    fn load_with<F>(&self, ckb_cli_dir: &Path, fetch: F) -> Result<ConfigEntries, String>
+    where
+        F: FnOnce(&str) -> Result<Vec<u8>, String>,
+    {
+        let cache_path = ckb_cli_dir.join(CACHE_FILENAME);
+        let cached = if cache_path.exists() {
+            match fs::read(&cache_path)
+                .map_err(|err| err.to_string())
+                .and_then(|document| self.verify(&document))
+            {
+                Ok(cached) => Some(cached),
+                Err(err) => {
+                    eprintln!(
+                        "[WARNING] Ignore the remote config cache {}: {}",
+                        cache_path.display(),
+                        err
+                    );
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let fresh = fs::metadata(&cache_path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .map(|age| age < Duration::from_secs(self.refresh_secs))
+            .unwrap_or(false);
+        if let (true, Some((_, entries))) = (fresh, &cached) {
+            return Ok(entries.clone());
+        }
+
+        // Only an unreachable url falls back to the cache, a document not
+        // verified (e.g. tampered) is an error
+        let fetched = match fetch(&self.url) {
+            Ok(document) => {
+                let config = self
+                    .verify(&document)
+                    .map_err(|err| format!("Remote config {}: {}", self.url, err))?;
+                Ok((document, config))
+            }
+            Err(err) => Err(err),
+        };
+        match (fetched, cached) {
+            (Ok((_, (serial, _))), Some((cached_serial, entries))) if serial < cached_serial => {
+                eprintln!(
+                    "[WARNING] The remote config serial {} is older than the cached {}, use the cache",
+                    serial, cached_serial
+                );
+                Ok(entries)
+            }
+            (Ok((document, (_, entries))), _) => {
+                write_file_atomic(&cache_path, &document)
+                    .map_err(|err| format!("Save remote config cache failed: {}", err))?;
+                Ok(entries)
+            }
+            (Err(err), Some((_, entries))) => {
+                eprintln!(
+                    "[WARNING] Fetch remote config {} failed: {}, use the cache",
+                    self.url, err
+                );
+                Ok(entries)
+            }
+            (Err(err), None) => Err(format!("Fetch remote config {} failed: {}", self.url, err)),
+        }
+    }
+
+    /// Check the signature of the document, return the serial and the config
+    /// entries
+    fn verify(&self, document: &[u8]) -> Result<(u64, ConfigEntries), String> {
+        let signed: SignedConfig = serde_json::from_slice(document)
+            .map_err(|err| format!("Invalid remote config: {}", err))?;
+        let message = Message::from_slice(&blake2b_256(signed.content.as_bytes()))
+            .map_err(|err| err.to_string())?;
+        let pubkey = Signature::from_slice(signed.signature.as_bytes())
+            .and_then(|signature| signature.recover(&message))
+            .map_err(|err| format!("Invalid remote config signature: {}", err))?;
+        if pubkey != self.public_key {
+            return Err("The remote config is not signed by remote_config public_key".to_string());
+        }
+        let mut entries = match serde_json::from_str(&signed.content) {
+            Ok(serde_json::Value::Object(entries)) => entries,
+            _ => return Err("Invalid remote config: content is not a JSON object".to_string()),
+        };
+        let serial = entries
+            .remove("serial")
+            .and_then(|serial| serial.as_u64())
+            .ok_or_else(|| "Invalid remote config: missing serial".to_string())?;
+        if let Some(key) = entries
+            .keys()
+            .find(|key| !REMOTE_CONFIG_KEYS.contains(&key.as_str()))
+        {
+            return Err(format!(
+                "Invalid remote config: {} is not allowed (allowed: {})",
+                key,
+                REMOTE_CONFIG_KEYS.join(", ")
+            ));
+        }
+        Ok((serial, entries))
+    }
+}
+
+/// Override the entries of the config file by the remote config when
+/// `remote_config` is given
+pub fn apply_remote_config(
+    configs: &mut serde_json::Value,
+    ckb_cli_dir: &Path,
+) -> Result<(), String> {
+    if let Some(remote_config) = RemoteConfig::from_config(configs)? {
+        for (key, value) in remote_config.load(ckb_cli_dir)? {
+            configs[key.as_str()] = value;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ckb_crypto::secp::Privkey;
+
+    fn sign(privkey: &Privkey, content: &str) -> Vec<u8> {
+        let message = Message::from_slice(&blake2b_256(content.as_bytes())).unwrap();
+        let signature = privkey.sign_recoverable(&message).unwrap();
+        serde_json::to_vec(&SignedConfig {
+            content: content.to_string(),
+            signature: JsonBytes::from_vec(signature.serialize()),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_verify() {
+        let privkey = Privkey::from_slice(&[1u8; 32]);
+        let remote_config = RemoteConfig {
+            url: "https://ops.example.com/ckb-cli.json".to_string(),
+            public_key: privkey.pubkey().unwrap(),
+            refresh_secs: DEFAULT_REFRESH_SECS,
+        };
+
+        let content = r#"{"serial": 3, "max_fee": "0.5"}"#;
+        let (serial, entries) = remote_config.verify(&sign(&privkey, content)).unwrap();
+        assert_eq!(serial, 3);
+        assert_eq!(entries["max_fee"], serde_json::json!("0.5"));
+
+        // Signed by another key
+        let other = Privkey::from_slice(&[2u8; 32]);
+        assert!(remote_config.verify(&sign(&other, content)).is_err());
+        // Tampered content
+        let mut signed: SignedConfig = serde_json::from_slice(&sign(&privkey, content)).unwrap();
+        signed.content = r#"{"serial": 3, "max_fee": "500"}"#.to_string();
+        assert!(remote_config
+            .verify(&serde_json::to_vec(&signed).unwrap())
+            .is_err());
+        // Without serial, or with an entry not allowed
+        assert!(remote_config
+            .verify(&sign(&privkey, r#"{"max_fee": "0.5"}"#))
+            .is_err());
+        assert!(remote_config
+            .verify(&sign(&privkey, r#"{"serial": 4, "remote_config": {}}"#))
+            .is_err());
+    }
+
+    #[test]
+    fn test_load() {
+        let dir =
+            std::env::temp_dir().join(format!("ckb-cli-remote-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let privkey = Privkey::from_slice(&[1u8; 32]);
+        let remote_config = RemoteConfig {
+            url: "https://ops.example.com/ckb-cli.json".to_string(),
+            public_key: privkey.pubkey().unwrap(),
+            refresh_secs: 0,
+        };
+        let unreachable = |_: &str| Err("connection refused".to_string());
+        let serve = |content: &'static str| {
+            let document = sign(&privkey, content);
+            move |_: &str| -> Result<Vec<u8>, String> { Ok(document) }
+        };
+
+        // No cache and the url is unreachable
+        assert!(remote_config.load_with(&dir, unreachable).is_err());
+        // Fetched and cached
+        let entries = remote_config
+            .load_with(&dir, serve(r#"{"serial": 2, "max_fee": "0.5"}"#))
+            .unwrap();
+        assert_eq!(entries["max_fee"], serde_json::json!("0.5"));
+        assert!(dir.join(CACHE_FILENAME).exists());
+        // The cache is used when the url is unreachable
+        let entries = remote_config.load_with(&dir, unreachable).unwrap();
+        assert_eq!(entries["max_fee"], serde_json::json!("0.5"));
+        // An older serial is ignored
+        let entries = remote_config
+            .load_with(&dir, serve(r#"{"serial": 1, "max_fee": "5"}"#))
+            .unwrap();
+        assert_eq!(entries["max_fee"], serde_json::json!("0.5"));
+        // An invalid signature is an error, not a fallback to the cache
+        let other = Privkey::from_slice(&[2u8; 32]);
+        let forged = sign(&other, r#"{"serial": 3, "max_fee": "5"}"#);
+        assert!(remote_config.load_with(&dir, |_| Ok(forged)).is_err());
+
+        // A fresh cache is used without fetching
+        let remote_config = RemoteConfig {
+            refresh_secs: DEFAULT_REFRESH_SECS,
+            ..remote_config
+        };
+        let entries = remote_config
+            .load_with(&dir, |_| panic!("fetched with a fresh cache"))
+            .unwrap();
+        assert_eq!(entries["max_fee"], serde_json::json!("0.5"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use clap::ArgMatches;
+
+// The JSON Schemas of command outputs, they are published in `schemas/` of the
+// repository and printed by `<command> --schema`.
+const SCHEMAS: &[(&str, &str)] = &[
+    (
+        "account list",
+        include_str!("../../schemas/account-list.json"),
+    ),
+    (
+        "dao export-positions",
+        include_str!("../../schemas/dao-export-positions.json"),
+    ),
+    (
+        "dao query-compensation",
+        include_str!("../../schemas/dao-query-compensation.json"),
+    ),
+    (
+        "dao query-deposited-cells",
+        include_str!("../../schemas/dao-query-deposited-cells.json"),
+    ),
+    (
+        "dao query-prepared-cells",
+        include_str!("../../schemas/dao-query-prepared-cells.json"),
+    ),
+    ("node peers", include_str!("../../schemas/node-peers.json")),
+    ("tx memo", include_str!("../../schemas/tx-memo.json")),
+    ("tx status", include_str!("../../schemas/tx-status.json")),
+    (
+        "util key-info",
+        include_str!("../../schemas/util-key-info.json"),
+    ),
+    (
+        "wallet get-capacity",
+        include_str!("../../schemas/wallet-get-capacity.json"),
+    ),
+    (
+        "wallet get-live-cells",
+        include_str!("../../schemas/wallet-get-live-cells.json"),
+    ),
+    (
+        "wallet tx-history",
+        include_str!("../../schemas/wallet-tx-history.json"),
+    ),
+];
+
+pub fn output_schema(command: &str) -> Option<&'static str> {
+    SCHEMAS
+        .iter()
+        .find(|(name, _)| *name == command)
+        .map(|(_, schema)| *schema)
+}
+
+/// The output schema of the parsed command when `--schema` is given, the
+/// arguments required by the commands having a schema are `required_unless`
+/// it. Return `None` when `--schema` is absent.
+pub fn schema_request(matches: &ArgMatches) -> Option<Result<String, String>> {
+    let mut present = matches.is_present("schema");
+    let mut path = Vec::new();
+    let mut current = matches;
+    while let (name, Some(sub_matches)) = current.subcommand() {
+        path.push(name);
+        present = present || sub_matches.is_present("schema");
+        current = sub_matches;
+    }
+    if !present {
+        return None;
+    }
+    let command = path.join(" ");
+    Some(
+        output_schema(&command)
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| {
+                let commands = SCHEMAS.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+                format!(
+                    "No output schema for `{}`, available: {}",
+                    command,
+                    commands.join(", ")
+                )
+            }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_cli;
+
+    fn request(line: &str) -> Option<Result<String, String>> {
+        let args = std::iter::once("ckb-cli").chain(line.split_whitespace());
+        let matches = build_cli("0.0.0", "0.0.0")
+            .get_matches_from_safe(args)
+            .unwrap();
+        schema_request(&matches)
+    }
+
+    #[test]
+    fn test_schemas() {
+        for (name, schema) in SCHEMAS {
+            let value: serde_json::Value = serde_json::from_str(schema).unwrap();
+            assert_eq!(value["title"], *name);
+        }
+        assert!(request("wallet get-capacity").is_none());
+        assert!(
+            request("--url http://127.0.0.1:8114 wallet get-capacity --schema")
+                .unwrap()
+                .is_ok()
+        );
+        // The required arguments are not needed
+        assert!(request("tx status --schema").unwrap().is_ok());
+        assert!(request("wallet transfer --schema").unwrap().is_err());
+    }
+}
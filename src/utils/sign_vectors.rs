@@ -0,0 +1,166 @@
+use std::str::FromStr;
+
+use ckb_crypto::secp::SECP256K1;
+use ckb_hash::blake2b_256;
+use ckb_sdk::{
+    serialize_signature,
+    wallet::{AbstractMasterPrivKey, DerivationPath, MasterPrivKey},
+};
+use ckb_types::{H160, H256};
+use faster_hex::hex_string;
+use secp256k1::recovery::RecoverableSignature;
+
+/// The seed of the software test vectors (the seed of BIP-32 test vector 1)
+pub const SOFTWARE_SEED: [u8; 16] = [
+    0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f,
+];
+
+/// A signing test vector, the digest is blake2b (ckb-default-hash) of the
+/// message and the signature is RFC 6979 deterministic, so every signer
+/// holding the same key gives the same answers.
+pub struct KnownVector {
+    pub path: &'static str,
+    pub message: &'static str,
+    pub pubkey: &'static str,
+    pub digest: &'static str,
+    pub signature: &'static str,
+}
+
+/// The known answers of `SOFTWARE_SEED`
+pub const SOFTWARE_VECTORS: &[KnownVector] = &[
+    KnownVector {
+        path: "m/44'/309'/0'/0/0",
+        message: "ckb-cli sign test vector m/44'/309'/0'/0/0",
+        pubkey: "0331b3c0225388c5010e3507beb28ecf409c022ef6f358f02b139cbae082f5a2a3",
+        digest: "7e8748862281289d596917b7cbd3eae231d684b2c5e2355839127e1ef7827919",
+        signature: "9795d89b43e291a7dfaa2e4440bc7108d14201df3bad615a14e5c320474039975073cb3ce13049a20994fc9b1135458ace7cdde4124640671505b3f8d8b0cb3900",
+    },
+    KnownVector {
+        path: "m/44'/309'/0'/1/0",
+        message: "ckb-cli sign test vector m/44'/309'/0'/1/0",
+        pubkey: "03f3e2413bc1d2f23d69ec629937e351f1923d1ecaa99aed05d751cb12e6641cde",
+        digest: "05234ec8eb36424404e0e3ecc92aca1d3f25f3aecf03dc8d1787046579ab89a7",
+        signature: "1f0bbfb1a3296793073c6e7822577e69177ca60c17f836ae2f44197978e6a726007b45ee782176cdb721caed4c7f18c0a08ad6232c84929fd174925090f1b19f00",
+    },
+];
+
+fn check(ok: bool) -> &'static str {
+    if ok {
+        "ok"
+    } else {
+        "mismatch"
+    }
+}
+
+/// Run a vector by any signer: derive the public key of the path, sign the
+/// digest, then recover the public key from the signature. The answers are
+/// compared with `expected` when the key is known, otherwise only the
+/// signature and the recovery are checked.
+pub fn run_vector<P, S>(
+    signer: &str,
+    path: &str,
+    message: &str,
+    expected: Option<&KnownVector>,
+    public_key: P,
+    sign_digest: S,
+) -> serde_json::Value
+where
+    P: FnOnce(&DerivationPath) -> Result<secp256k1::PublicKey, String>,
+    S: FnOnce(&DerivationPath, &H256) -> Result<RecoverableSignature, String>,
+{
+    let result = DerivationPath::from_str(path)
+        .map_err(|err| err.to_string())
+        .and_then(|path| {
+            let pubkey = public_key(&path)?;
+            let digest = H256::from(blake2b_256(message.as_bytes()));
+            let signature = sign_digest(&path, &digest)?;
+            Ok((pubkey, digest, signature))
+        });
+    let (pubkey, digest, signature) = match result {
+        Ok(answers) => answers,
+        Err(err) => {
+            return serde_json::json!({
+                "signer": signer,
+                "path": path,
+                "ok": false,
+                "error": err,
+            })
+        }
+    };
+
+    let pubkey_hex = hex_string(&pubkey.serialize()[..]).unwrap();
+    let digest_hex = hex_string(digest.as_bytes()).unwrap();
+    let signature_hex = hex_string(&serialize_signature(&signature)[..]).unwrap();
+    let message = secp256k1::Message::from_slice(digest.as_bytes()).unwrap();
+    let recovered = SECP256K1.recover(&message, &signature).ok();
+    let mut checks = serde_json::json!({
+        "signature": check(
+            SECP256K1
+                .verify(&message, &signature.to_standard(), &pubkey)
+                .is_ok()
+        ),
+        "recovery": check(recovered == Some(pubkey)),
+    });
+    if let Some(expected) = expected {
+        checks["derivation"] = serde_json::json!(check(pubkey_hex == expected.pubkey));
+        checks["digest"] = serde_json::json!(check(digest_hex == expected.digest));
+        checks["known_signature"] = serde_json::json!(check(signature_hex == expected.signature));
+    }
+    let ok = checks
+        .as_object()
+        .unwrap()
+        .values()
+        .all(|value| value == "ok");
+    let lock_arg = H160::from_slice(&blake2b_256(&pubkey.serialize()[..])[0..20]).unwrap();
+    serde_json::json!({
+        "signer": signer,
+        "path": path,
+        "pubkey": format!("0x{}", pubkey_hex),
+        "lock_arg": format!("{:#x}", lock_arg),
+        "digest": format!("0x{}", digest_hex),
+        "signature": format!("0x{}", signature_hex),
+        "checks": checks,
+        "ok": ok,
+    })
+}
+
+/// Run the known answer vectors by the software signer
+pub fn software_vectors() -> Vec<serde_json::Value> {
+    let master = MasterPrivKey::from_seed(&SOFTWARE_SEED).expect("valid seed");
+    SOFTWARE_VECTORS
+        .iter()
+        .map(|vector| {
+            run_vector(
+                "software",
+                vector.path,
+                vector.message,
+                Some(vector),
+                |path| {
+                    master
+                        .extended_pubkey(path.as_ref())
+                        .map(|xpub| xpub.public_key)
+                        .map_err(|err| err.to_string())
+                },
+                |path, digest| {
+                    let privkey = master
+                        .extended_privkey(path.as_ref())
+                        .map_err(|err| err.to_string())?;
+                    let message = secp256k1::Message::from_slice(digest.as_bytes()).unwrap();
+                    Ok(SECP256K1.sign_recoverable(&message, &privkey.private_key))
+                },
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_software_vectors() {
+        for result in software_vectors() {
+            assert_eq!(result["ok"], true, "{}", result);
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::net::{SocketAddr, TcpStream};
+use std::process::{self, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use clap::ArgMatches;
+
+use super::arg_parser::{ArgParser, FromStrParser};
+use super::other::get_ckb_cli_dir;
+
+pub const SOCKS_PROXY_ENV_NAME: &str = "CKB_CLI_SOCKS_PROXY";
+
+// The SOCKS ports of Tor daemon and Tor Browser
+const TOR_SOCKS_PORTS: [u16; 2] = [9050, 9150];
+const TOR_BOOTSTRAP_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// The SOCKS5 proxy selected by `--socks-proxy` (or env `CKB_CLI_SOCKS_PROXY`)
+/// and `--tor`
+pub fn socks_proxy_from_matches(
+    m: &ArgMatches,
+    env_proxy: Option<String>,
+) -> Result<Option<SocketAddr>, String> {
+    if m.is_present("tor") {
+        return connect_tor().map(Some);
+    }
+    match m
+        .value_of("socks-proxy")
+        .map(ToOwned::to_owned)
+        .or(env_proxy)
+    {
+        Some(ref input) if !input.is_empty() => {
+            FromStrParser::<SocketAddr>::new().parse(input).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Use the local running Tor, spawn one if it is not running. The spawned Tor
+/// exits with current process.
+fn connect_tor() -> Result<SocketAddr, String> {
+    for port in &TOR_SOCKS_PORTS {
+        let addr = SocketAddr::from(([127, 0, 0, 1], *port));
+        if TcpStream::connect_timeout(&addr, Duration::from_secs(1)).is_ok() {
+            return Ok(addr);
+        }
+    }
+
+    let port = TOR_SOCKS_PORTS[0];
+    let mut data_dir = get_ckb_cli_dir();
+    data_dir.push("tor");
+    fs::create_dir_all(&data_dir).map_err(|err| err.to_string())?;
+    let mut child = Command::new("tor")
+        .arg("--SocksPort")
+        .arg(port.to_string())
+        .arg("--DataDirectory")
+        .arg(&data_dir)
+        .arg("--__OwningControllerProcess")
+        .arg(process::id().to_string())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|err| format!("Spawn tor failed (is tor installed?): {}", err))?;
+    eprintln!("Waiting for Tor bootstrap ...");
+
+    let stdout = child.stdout.take().expect("piped stdout");
+    let (sender, receiver) = crossbeam_channel::bounded(1);
+    thread::spawn(move || {
+        // Keep draining the output, or tor will be blocked when the pipe is full
+        for line in BufReader::new(stdout).lines() {
+            match line {
+                Ok(ref line) if line.contains("Bootstrapped 100%") => {
+                    let _ = sender.try_send(());
+                }
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+    });
+    if receiver.recv_timeout(TOR_BOOTSTRAP_TIMEOUT).is_err() {
+        let _ = child.kill();
+        return Err(format!(
+            "Tor not bootstrapped in {} seconds",
+            TOR_BOOTSTRAP_TIMEOUT.as_secs()
+        ));
+    }
+    Ok(SocketAddr::from(([127, 0, 0, 1], port)))
+}
@@ -0,0 +1,130 @@
+use ckb_hash::new_blake2b;
+use ckb_jsonrpc_types::{Header, Transaction, Uint32};
+use ckb_types::{core::HeaderView, packed, prelude::*, H256};
+use serde_derive::{Deserialize, Serialize};
+
+/// The result of `get_transaction_proof` RPC (with one transaction)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransactionProof {
+    pub block_hash: H256,
+    pub witnesses_root: H256,
+    pub proof: MerkleProof,
+}
+
+/// The CBMT (complete binary merkle tree) proof of the transaction hashes,
+/// `indices` are the indices in the tree (not in the block)
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub indices: Vec<Uint32>,
+    pub lemmas: Vec<H256>,
+}
+
+/// The file given to the recipient, it proves the transaction is committed in
+/// the block of the header without asking a full node for the block.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TxProofFile {
+    pub transaction: Transaction,
+    pub header: Header,
+    pub proof: TransactionProof,
+}
+
+impl TxProofFile {
+    /// Check the transaction is in the block of the header, return the
+    /// transaction hash and the header.
+    pub fn verify(&self) -> Result<(H256, HeaderView), String> {
+        let tx_view = packed::Transaction::from(self.transaction.clone()).into_view();
+        let tx_hash: H256 = tx_view.hash().unpack();
+        let header = packed::Header::from(self.header.clone()).into_view();
+        let block_hash: H256 = header.hash().unpack();
+        if block_hash != self.proof.block_hash {
+            return Err(format!(
+                "The header hash {:#x} is not the block hash of the proof {:#x}",
+                block_hash, self.proof.block_hash
+            ));
+        }
+        if self.proof.proof.indices.len() != 1 {
+            return Err(format!(
+                "Expect the proof of one transaction, got {}",
+                self.proof.proof.indices.len()
+            ));
+        }
+        let index = self.proof.proof.indices[0].value();
+        let raw_transactions_root = merkle_proof_root(&tx_hash, index, &self.proof.proof.lemmas)
+            .ok_or_else(|| "Invalid merkle proof".to_string())?;
+        // transactions_root = merkle_root([raw_transactions_root, witnesses_root])
+        let transactions_root = merge(&raw_transactions_root, &self.proof.witnesses_root);
+        let expected: H256 = header.transactions_root().unpack();
+        if transactions_root != expected {
+            return Err(format!(
+                "Transaction {:#x} is not in block {:#x} (transactions_root mismatch)",
+                tx_hash, block_hash
+            ));
+        }
+        Ok((tx_hash, header))
+    }
+}
+
+fn merge(left: &H256, right: &H256) -> H256 {
+    let mut hash = [0u8; 32];
+    let mut blake2b = new_blake2b();
+    blake2b.update(left.as_bytes());
+    blake2b.update(right.as_bytes());
+    blake2b.finalize(&mut hash);
+    H256::from(hash)
+}
+
+/// The root of a CBMT by the proof of one leaf at tree index `index`, the
+/// lemmas are the siblings from the leaf up to the root.
+pub fn merkle_proof_root(leaf: &H256, mut index: u32, lemmas: &[H256]) -> Option<H256> {
+    let mut node = leaf.clone();
+    let mut lemmas = lemmas.iter();
+    while index > 0 {
+        let sibling = lemmas.next()?;
+        // The left child has an odd index
+        node = if index & 1 == 1 {
+            merge(&node, sibling)
+        } else {
+            merge(sibling, &node)
+        };
+        index = (index - 1) >> 1;
+    }
+    if lemmas.next().is_some() {
+        return None;
+    }
+    Some(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merkle_proof_root() {
+        let leaves = (0..3u8).map(|i| H256::from([i; 32])).collect::<Vec<_>>();
+        // The tree: [root, merge(l1, l2), l0, l1, l2]
+        let node1 = merge(&leaves[1], &leaves[2]);
+        let root = merge(&node1, &leaves[0]);
+
+        assert_eq!(
+            merkle_proof_root(&leaves[0], 2, &[node1.clone()]),
+            Some(root.clone())
+        );
+        assert_eq!(
+            merkle_proof_root(&leaves[1], 3, &[leaves[2].clone(), leaves[0].clone()]),
+            Some(root.clone())
+        );
+        assert_eq!(
+            merkle_proof_root(&leaves[2], 4, &[leaves[1].clone(), leaves[0].clone()]),
+            Some(root.clone())
+        );
+        assert_ne!(
+            merkle_proof_root(&leaves[1], 4, &[leaves[1].clone(), leaves[0].clone()]),
+            Some(root)
+        );
+        assert_eq!(merkle_proof_root(&leaves[0], 2, &[]), None);
+        assert_eq!(
+            merkle_proof_root(&leaves[0], 0, &[]),
+            Some(leaves[0].clone())
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+use ckb_types::H256;
+use serde_derive::{Deserialize, Serialize};
+
+use super::other::get_ckb_cli_dir;
+use super::output::write_file_atomic;
+
+const TX_RECORDS_FILE: &str = "tx-records.json";
+
+/// Local information of a sent transaction (never on chain)
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct TxRecord {
+    pub tx_hash: H256,
+    pub memo: Option<String>,
+    // Unix timestamp in milliseconds
+    pub sent_at: u64,
+}
+
+/// Local tracking store of sent transactions: `~/.ckb-cli/tx-records.json`
+pub struct TxRecordStore {
+    path: PathBuf,
+    records: Vec<TxRecord>,
+}
+
+impl TxRecordStore {
+    pub fn load() -> Result<TxRecordStore, String> {
+        let mut path = get_ckb_cli_dir();
+        fs::create_dir_all(&path).map_err(|err| err.to_string())?;
+        path.push(TX_RECORDS_FILE);
+        let records = if path.exists() {
+            let file = fs::File::open(&path).map_err(|err| err.to_string())?;
+            serde_json::from_reader(file)
+                .map_err(|err| format!("Parse {} failed: {}", path.to_string_lossy(), err))?
+        } else {
+            Vec::new()
+        };
+        Ok(TxRecordStore { path, records })
+    }
+
+    pub fn records(&self) -> &[TxRecord] {
+        &self.records
+    }
+
+    pub fn insert(&mut self, record: TxRecord) -> Result<(), String> {
+        self.records.retain(|old| old.tx_hash != record.tx_hash);
+        self.records.push(record);
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        // Replaced atomically, an interrupted save leaves the old records
+        let content = serde_json::to_string_pretty(&self.records).map_err(|err| err.to_string())?;
+        write_file_atomic(&self.path, content.as_bytes())
+    }
+}
+
+/// The memos of the local tracking store shown by the outputs of the
+/// transactions, an unreadable store only loses the memos
+pub fn load_memos() -> HashMap<H256, String> {
+    match TxRecordStore::load() {
+        Ok(store) => store
+            .records
+            .into_iter()
+            .filter_map(|record| record.memo.map(|memo| (record.tx_hash, memo)))
+            .collect(),
+        Err(err) => {
+            eprintln!("[WARNING] Skip the memos of local tx records: {}", err);
+            HashMap::default()
+        }
+    }
+}
+
+/// Remember the memo of a sent transaction, do nothing when there is no memo
+pub fn save_tx_memo(tx_hash: &H256, memo: Option<String>) -> Result<(), String> {
+    if memo.is_none() {
+        return Ok(());
+    }
+    let sent_at = chrono::Utc::now().timestamp_millis() as u64;
+    TxRecordStore::load()?.insert(TxRecord {
+        tx_hash: tx_hash.clone(),
+        memo,
+        sent_at,
+    })
+}
@@ -0,0 +1,303 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ckb_jsonrpc_types::Status;
+use ckb_sdk::HttpRpcClient;
+use ckb_types::H256;
+use clap::ArgMatches;
+use faster_hex::hex_string;
+
+use super::arg_parser::{ArgParser, FromStrParser};
+use super::interrupt::{check_interrupted, interruptible};
+use super::other::parse_hex_u64;
+
+// The interval of polling the transaction status, about the block interval
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The proposal window of consensus (`tx_proposal_window`), a transaction
+/// proposed in block `n` can be committed from block `n + closest` to block
+/// `n + farthest`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProposalWindow {
+    pub closest: u64,
+    pub farthest: u64,
+}
+
+impl Default for ProposalWindow {
+    // The proposal window of mainnet and testnet
+    fn default() -> ProposalWindow {
+        ProposalWindow {
+            closest: 2,
+            farthest: 10,
+        }
+    }
+}
+
+impl ProposalWindow {
+    /// Read from `get_consensus` RPC, use the default value when the node does
+    /// not support it.
+    pub fn from_node(rpc_client: &mut HttpRpcClient) -> ProposalWindow {
+        rpc_client
+            .call("get_consensus", Vec::new())
+            .ok()
+            .and_then(|consensus| {
+                let window = &consensus["tx_proposal_window"];
+                Some(ProposalWindow {
+                    closest: parse_hex_u64(&window["closest"])?,
+                    farthest: parse_hex_u64(&window["farthest"])?,
+                })
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TxProgress {
+    /// Not found in the tx-pool or the chain
+    Unknown,
+    /// In the tx-pool, waiting for a proposal
+    Pending,
+    /// Proposed in block `proposed_at` (`None` when the proposal block is
+    /// not found, eg: proposed by an uncle)
+    Proposed {
+        proposed_at: Option<u64>,
+    },
+    Committed {
+        block_number: u64,
+        block_hash: H256,
+    },
+}
+
+impl TxProgress {
+    pub fn status(&self) -> &'static str {
+        match self {
+            TxProgress::Unknown => "unknown",
+            TxProgress::Pending => "pending",
+            TxProgress::Proposed { .. } => "proposed",
+            TxProgress::Committed { .. } => "committed",
+        }
+    }
+
+    /// Estimate the range of blocks after `tip` until the transaction is
+    /// committed. A pending transaction is assumed to be proposed by the next
+    /// block, and so is a proposed one out of the proposal window (it must be
+    /// proposed again).
+    pub fn blocks_to_commit(&self, window: ProposalWindow, tip: u64) -> Option<(u64, u64)> {
+        let next_proposal = (window.closest + 1, window.farthest + 1);
+        match self {
+            TxProgress::Unknown => None,
+            TxProgress::Pending => Some(next_proposal),
+            TxProgress::Proposed {
+                proposed_at: Some(number),
+            } => {
+                if tip >= number + window.farthest {
+                    Some(next_proposal)
+                } else {
+                    let min = (number + window.closest).saturating_sub(tip);
+                    Some((min.max(1), number + window.farthest - tip))
+                }
+            }
+            TxProgress::Proposed { proposed_at: None } => Some((1, window.farthest)),
+            TxProgress::Committed { .. } => Some((0, 0)),
+        }
+    }
+}
+
+/// Query the progress of a transaction and the tip block number, the proposal
+/// block of a proposed transaction is searched in the proposal window.
+pub fn tx_progress(
+    rpc_client: &mut HttpRpcClient,
+    tx_hash: &H256,
+    window: ProposalWindow,
+) -> Result<(TxProgress, u64), String> {
+    let tip = rpc_client.get_tip_block_number()?;
+    let tx_status = match rpc_client.get_transaction(tx_hash.clone())? {
+        Some(tx_with_status) => tx_with_status.tx_status,
+        None => return Ok((TxProgress::Unknown, tip)),
+    };
+    let progress = match tx_status.status {
+        Status::Pending => TxProgress::Pending,
+        Status::Proposed => {
+            let short_id = serde_json::json!(format!(
+                "0x{}",
+                hex_string(&tx_hash.as_bytes()[..10]).unwrap()
+            ));
+            let mut proposed_at = None;
+            for number in (tip.saturating_sub(window.farthest)..=tip).rev() {
+                let block = match rpc_client.get_block_by_number(number)? {
+                    Some(block) => block,
+                    None => continue,
+                };
+                let found = block
+                    .proposals
+                    .iter()
+                    .chain(block.uncles.iter().flat_map(|uncle| uncle.proposals.iter()))
+                    .any(|id| serde_json::json!(id) == short_id);
+                if found {
+                    proposed_at = Some(number);
+                    break;
+                }
+            }
+            TxProgress::Proposed { proposed_at }
+        }
+        Status::Committed => {
+            let block_hash = tx_status
+                .block_hash
+                .ok_or_else(|| "Committed transaction without block hash".to_string())?;
+            let header = rpc_client
+                .get_header(block_hash.clone())?
+                .ok_or_else(|| format!("Block not found: {:#x}", block_hash))?;
+            TxProgress::Committed {
+                block_number: header.inner.number,
+                block_hash,
+            }
+        }
+    };
+    Ok((progress, tip))
+}
+
+/// The options of `--wait-for-committed` (and `tx status --watch`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WaitForCommitted {
+    pub confirmations: u64,
+    pub timeout: Option<Duration>,
+}
+
+impl Default for WaitForCommitted {
+    fn default() -> WaitForCommitted {
+        WaitForCommitted {
+            confirmations: 1,
+            timeout: None,
+        }
+    }
+}
+
+impl WaitForCommitted {
+    /// Read `--confirmations` and `--timeout`, return `None` when the flag
+    /// `flag_name` is absent.
+    pub fn from_matches(
+        m: &ArgMatches,
+        flag_name: &str,
+    ) -> Result<Option<WaitForCommitted>, String> {
+        if !m.is_present(flag_name) {
+            return Ok(None);
+        }
+        let confirmations: Option<u64> =
+            FromStrParser::<u64>::default().from_matches_opt(m, "confirmations", false)?;
+        let timeout: Option<u64> =
+            FromStrParser::<u64>::default().from_matches_opt(m, "timeout", false)?;
+        Ok(Some(WaitForCommitted {
+            confirmations: confirmations.unwrap_or(1),
+            timeout: timeout.map(Duration::from_secs),
+        }))
+    }
+
+    /// Poll the status of the transaction until it is committed and confirmed
+    /// by enough blocks, the status changes are printed to stderr. Return the
+    /// committing block number and hash.
+    pub fn wait(
+        &self,
+        rpc_client: &mut HttpRpcClient,
+        tx_hash: &H256,
+    ) -> Result<(u64, H256), String> {
+        let window = ProposalWindow::from_node(rpc_client);
+        let started = Instant::now();
+        let mut last_message = None;
+        let _guard = interruptible();
+        loop {
+            check_interrupted(&format!(
+                "resume by: tx status --tx-hash {:#x} --watch",
+                tx_hash
+            ))?;
+            let (progress, tip) = tx_progress(rpc_client, tx_hash, window)?;
+            let message = match progress {
+                TxProgress::Unknown if last_message.is_some() => {
+                    return Err(format!(
+                        "Transaction {:#x} is removed from the tx-pool (rejected or replaced)",
+                        tx_hash
+                    ));
+                }
+                TxProgress::Committed {
+                    block_number,
+                    ref block_hash,
+                } => {
+                    let confirmations = tip.saturating_sub(block_number) + 1;
+                    if confirmations >= self.confirmations {
+                        eprintln!(
+                            "Transaction {:#x} committed in block #{} ({:#x}), confirmations: {}",
+                            tx_hash, block_number, block_hash, confirmations
+                        );
+                        return Ok((block_number, block_hash.clone()));
+                    }
+                    format!(
+                        "committed in block #{}, confirmations: {}/{}",
+                        block_number, confirmations, self.confirmations
+                    )
+                }
+                _ => progress.status().to_string(),
+            };
+            if last_message.as_ref() != Some(&message) {
+                eprintln!("Transaction {:#x} is {}", tx_hash, message);
+                last_message = Some(message);
+            }
+
+            let mut interval = POLL_INTERVAL;
+            if let Some(timeout) = self.timeout {
+                let elapsed = started.elapsed();
+                if elapsed >= timeout {
+                    return Err(format!(
+                        "Timeout waiting for transaction {:#x} to be committed, last status: {}",
+                        tx_hash,
+                        progress.status()
+                    ));
+                }
+                interval = interval.min(timeout - elapsed);
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    /// Wait for the sent transaction, the response of the sending commands
+    /// with `--wait-for-committed`
+    pub fn wait_response(
+        &self,
+        rpc_client: &mut HttpRpcClient,
+        tx_hash: &H256,
+    ) -> Result<serde_json::Value, String> {
+        let (block_number, block_hash) = self.wait(rpc_client, tx_hash)?;
+        Ok(serde_json::json!({
+            "tx_hash": tx_hash,
+            "block_number": block_number,
+            "block_hash": block_hash,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocks_to_commit() {
+        let window = ProposalWindow::default();
+        assert_eq!(TxProgress::Unknown.blocks_to_commit(window, 100), None);
+        assert_eq!(
+            TxProgress::Pending.blocks_to_commit(window, 100),
+            Some((3, 11))
+        );
+        let proposed = TxProgress::Proposed {
+            proposed_at: Some(99),
+        };
+        assert_eq!(proposed.blocks_to_commit(window, 100), Some((1, 9)));
+        assert_eq!(proposed.blocks_to_commit(window, 109), Some((3, 11)));
+        let proposed = TxProgress::Proposed {
+            proposed_at: Some(100),
+        };
+        assert_eq!(proposed.blocks_to_commit(window, 100), Some((2, 10)));
+        let committed = TxProgress::Committed {
+            block_number: 90,
+            block_hash: H256::default(),
+        };
+        assert_eq!(committed.blocks_to_commit(window, 100), Some((0, 0)));
+    }
+}